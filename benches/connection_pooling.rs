@@ -0,0 +1,107 @@
+// Throughput of SDK retrieve calls with the connection settings of the enclave api :
+// - "connection-per-call" opens a connection for every call, as clients without pooling do
+// - "http1-keepalive" reuses the pooled HTTP/1.1 connections of the client
+// - "http2" multiplexes all the calls of the client on one HTTP/2 connection
+// TLS is left out, it only widens the gap in favour of reused connections.
+//
+// cargo bench --bench connection_pooling
+
+use std::{net::SocketAddr, time::Duration};
+
+use axum::{
+	http::{header::CONTENT_TYPE, HeaderName},
+	routing::post,
+	Router,
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use futures::future::join_all;
+use hyper::{client::HttpConnector, Body, Client, Request, Server};
+use tokio::runtime::Runtime;
+
+// Concurrent retrieve calls of an SDK batch
+const CALLS: usize = 256;
+
+// Defaults of the enclave connection settings
+const HTTP2_KEEPALIVE_INTERVAL: u64 = 20;
+const HTTP2_KEEPALIVE_TIMEOUT: u64 = 20;
+const HTTP2_MAX_STREAMS: u32 = 256;
+const HTTP1_HEADER_TIMEOUT: u64 = 30;
+
+async fn retrieve_keyshare() -> ([(HeaderName, &'static str); 1], String) {
+	let keyshare = "a".repeat(512);
+	(
+		[(CONTENT_TYPE, "application/json")],
+		format!(
+			r#"{{"status":"SUCCESS","nft_id":1337,"enclave_id":"enclave","keyshare_data":"{keyshare}"}}"#
+		),
+	)
+}
+
+fn start_server(runtime: &Runtime) -> SocketAddr {
+	let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+	listener.set_nonblocking(true).unwrap();
+	let address = listener.local_addr().unwrap();
+
+	let app = Router::new().route("/api/secret-nft/retrieve-keyshare", post(retrieve_keyshare));
+
+	runtime.spawn(async move {
+		Server::from_tcp(listener)
+			.unwrap()
+			.tcp_nodelay(true)
+			.http1_keepalive(true)
+			.http1_header_read_timeout(Duration::from_secs(HTTP1_HEADER_TIMEOUT))
+			.http2_keep_alive_interval(Duration::from_secs(HTTP2_KEEPALIVE_INTERVAL))
+			.http2_keep_alive_timeout(Duration::from_secs(HTTP2_KEEPALIVE_TIMEOUT))
+			.http2_max_concurrent_streams(HTTP2_MAX_STREAMS)
+			.http2_adaptive_window(true)
+			.serve(app.into_make_service())
+			.await
+			.unwrap()
+	});
+
+	address
+}
+
+async fn send_calls(client: &Client<HttpConnector>, address: SocketAddr) {
+	let calls = (0..CALLS).map(|_| {
+		let request = Request::post(format!("http://{address}/api/secret-nft/retrieve-keyshare"))
+			.header(CONTENT_TYPE, "application/json")
+			.body(Body::from(
+				r#"{"requester_address":"5C4h","data":"1337_1000_15","signature":"0x00"}"#,
+			))
+			.unwrap();
+
+		async move {
+			let response = client.request(request).await.unwrap();
+			hyper::body::to_bytes(response.into_body()).await.unwrap()
+		}
+	});
+
+	join_all(calls).await;
+}
+
+fn connection_pooling(criterion: &mut Criterion) {
+	let runtime = Runtime::new().unwrap();
+	let address = start_server(&runtime);
+
+	let clients = [
+		("connection-per-call", Client::builder().pool_max_idle_per_host(0).build_http()),
+		("http1-keepalive", Client::builder().build_http()),
+		("http2", Client::builder().http2_only(true).build_http()),
+	];
+
+	let mut group = criterion.benchmark_group("retrieve-calls");
+	group.throughput(Throughput::Elements(CALLS as u64));
+	group.measurement_time(Duration::from_secs(10));
+
+	for (name, client) in clients.iter() {
+		group.bench_with_input(BenchmarkId::from_parameter(name), client, |bencher, client| {
+			bencher.to_async(&runtime).iter(|| send_calls(client, address))
+		});
+	}
+
+	group.finish();
+}
+
+criterion_group!(benches, connection_pooling);
+criterion_main!(benches);
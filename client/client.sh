@@ -21,7 +21,7 @@ echo "Get NFT key-shares from TEE"
 curl -X POST https://dev-c1n1.ternoa.network:8101/api//secret-nft/retrieve-keyshare -H 'Content-Type: application/json' -d '{  "owner_address": "5CcqaTBwWvbB2MvmeteSDLVujL3oaFHtdf24pPVT3Xf8v7tC",  "data": "494_214299_20",  "signature": "0x22d8812c669c67b98bcba386528fe80e17452d9a1c55871305d61f1c85a1210648860309ce280225dec32eb3b9f82382df30f581328a32f2a7d6f44ae422e48c"}'
 
 echo "Remove NFT key-shares from TEE"
-curl -X POST https://dev-c1n1.ternoa.network:8101/api//secret-nft/remove-keyshare -H 'Content-Type: application/json' -d '{  "owner_address": "5G1AGcU2D8832LcRefKrPm8Zrob63vf6uQSzKGmhyV9DrzFs",  "nft_id": 494}'
+curl -X POST https://dev-c1n1.ternoa.network:8101/api//secret-nft/remove-keyshare -H 'Content-Type: application/json' -d '{  "requester_address": "5G1AGcU2D8832LcRefKrPm8Zrob63vf6uQSzKGmhyV9DrzFs",  "data": "494_214299_20",  "signature": "0x<metric-server signature of data>"}'
 
 ### CAPSULE
 echo "Get CAPSULE views log"
@@ -37,4 +37,4 @@ echo "Get CAPSULE key-shares from TEE"
 curl -X POST https://dev-c1n1.ternoa.network:8101/api//capsule-nft/retrieve-keyshare -H 'Content-Type: application/json' -d '{  "owner_address": "5CcqaTBwWvbB2MvmeteSDLVujL3oaFHtdf24pPVT3Xf8v7tC",  "data": "494_214299_20",  "signature": "0x22d8812c669c67b98bcba386528fe80e17452d9a1c55871305d61f1c85a1210648860309ce280225dec32eb3b9f82382df30f581328a32f2a7d6f44ae422e48c"}'
 
 echo "Remove CAPSULE key-shares from TEE"
-curl -X POST https://dev-c1n1.ternoa.network:8101/api//capsule-nft/remove-keyshare -H 'Content-Type: application/json' -d '{  "owner_address": "5G1AGcU2D8832LcRefKrPm8Zrob63vf6uQSzKGmhyV9DrzFs",  "nft_id": 494}'
+curl -X POST https://dev-c1n1.ternoa.network:8101/api//capsule-nft/remove-keyshare -H 'Content-Type: application/json' -d '{  "requester_address": "5G1AGcU2D8832LcRefKrPm8Zrob63vf6uQSzKGmhyV9DrzFs",  "data": "494_214299_20",  "signature": "0x<metric-server signature of data>"}'
@@ -0,0 +1,15 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// The server is a binary crate, the pure parser is included by path
+#[path = "../../src/chain/parser.rs"]
+mod parser;
+
+fuzz_target!(|data: &str| {
+	if let Ok(fields) = parser::parse_retrieve(data) {
+		let serialized =
+			format!("{}_{}_{}", fields.nft_id, fields.block_number, fields.block_validation);
+		assert_eq!(parser::parse_retrieve(&serialized), Ok(fields));
+	}
+});
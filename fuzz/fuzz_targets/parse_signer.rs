@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// The server is a binary crate, the pure parser is included by path
+#[path = "../../src/chain/parser.rs"]
+mod parser;
+
+fuzz_target!(|signer: &str| {
+	if let Ok(fields) = parser::parse_signer(signer) {
+		// The address is a field of the unwrapped signer
+		assert!(parser::strip_bytes_wrapping(signer).starts_with(fields.address));
+	}
+});
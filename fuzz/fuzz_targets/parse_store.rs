@@ -0,0 +1,21 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// The server is a binary crate, the pure parser is included by path
+#[path = "../../src/chain/parser.rs"]
+mod parser;
+
+fuzz_target!(|data: &str| {
+	if let Ok(fields) = parser::parse_store(data) {
+		// Legacy keyshares can not contain the separator
+		assert!(!fields.keyshare.contains('_'));
+		let serialized = format!(
+			"{}_{}_{}_{}",
+			fields.nft_id, fields.keyshare, fields.block_number, fields.block_validation
+		);
+		assert_eq!(parser::parse_store(&serialized), Ok(fields));
+	}
+
+	let _ = parser::parse_binary_store(data);
+});
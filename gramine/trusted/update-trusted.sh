@@ -1,6 +1,10 @@
 mkdir -p ./etc/ssl/certs/
 cp -f /etc/ssl/certs/ca-certificates.crt ./etc/ssl/certs/
 
+mkdir -p ./etc/sgx/
+cp -f /etc/sgx/Intel_SGX_Provisioning_Certification_RootCA.pem ./etc/sgx/
+curl -fsSL https://certificates.trustedservices.intel.com/Intel_SGX_Attestation_RootCA.pem -o ./etc/sgx/Intel_SGX_Attestation_RootCA.pem
+
 mkdir -p ./arch_libdir/
 cp -f /lib/x86_64-linux-gnu/libcrypto.so.3 ./arch_libdir/
 cp -f /lib/x86_64-linux-gnu/libgcc_s.so.1 ./arch_libdir/
@@ -2,8 +2,8 @@
 BASEDIR="$( cd -- "$( dirname -- "${BASH_SOURCE[0]}" )/.." &> /dev/null && pwd )"
 SCRIPTSPATH="$BASEDIR/scripts/"
 GRAMINEPATH="$BASEDIR/gramine/"
-SEALPATH="$GRAMINEPATH/nft/"
-CERTPATH="$GRAMINEPATH/certificates/"
+SEALPATH="$GRAMINEPATH/storage/nft/"
+CERTPATH="$GRAMINEPATH/storage/certificates/"
 
 # DEFAULT VALUES
 PORT=
@@ -44,7 +44,7 @@ done
 #rm -rf $SEALPATH/*.state
 #rm -rf $SEALPATH/*.key
 #rm -rf $GRAMINEPATH/bin/*
-rm -rf $GRAMINEPATH/certificates/*
+rm -rf $CERTPATH/*
 rm -rf $SEALPATH/*.keyshare
 rm -rf $SEALPATH/*.log
 
@@ -7,12 +7,12 @@ clear_enclave() {
   printf 'Cleaning : "%s"\n' "$1" >&2
   cd "$1"
   #./scripts/clear-server.sh
-  rm gramine/*.log gramine/*.manifest gramine/*.sgx gramine/*.sig gramine/certificates/*
+  rm gramine/*.log gramine/*.manifest gramine/*.sgx gramine/*.sig gramine/storage/certificates/*
   rm ./gramine/bin/*
-  rm ./gramine/nft/*.log
-  rm ./gramine/nft/*.keyshare
-  rm ./gramine/nft/sync.state
-  touch ./gramine/nft/sync.state
+  rm ./gramine/storage/nft/*.log
+  rm ./gramine/storage/nft/*.keyshare
+  rm ./gramine/storage/nft/sync.state
+  touch ./gramine/storage/nft/sync.state
   rm start.log
   cd ..
 }
@@ -3,9 +3,20 @@
 # DEFAULT VALUES
 PORT=${PORT:-8101}
 
+# Seconds to drain in-flight requests and stop the subsystems, before the enclave is killed
+STOP_TIMEOUT=${STOP_TIMEOUT:-70}
+
 stop_enclave() {
     printf 'stop enclave with identifier : "%s"\n' "$1" >&2
-    ps aux | grep "$1" | grep -v grep | awk '{ print $2}' | xargs kill -9
+    PIDS=$(ps aux | grep "$1" | grep -v grep | awk '{ print $2}')
+    [ -z "$PIDS" ] && return
+    kill -TERM $PIDS
+    for _ in $(seq "$STOP_TIMEOUT"); do
+        kill -0 $PIDS 2>/dev/null || return
+        sleep 1
+    done
+    printf 'enclave did not stop in %s seconds, kill it\n' "$STOP_TIMEOUT" >&2
+    kill -9 $PIDS 2>/dev/null
 }
 
 die () {
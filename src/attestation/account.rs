@@ -0,0 +1,657 @@
+use std::{
+	fs::{self, File, OpenOptions},
+	io::Write,
+	path::Path,
+	str::FromStr,
+	sync::atomic::{AtomicBool, Ordering},
+};
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use subxt::{
+	ext::sp_core::{crypto::Ss58Codec, sr25519, Pair},
+	utils::AccountId32,
+};
+use tracing::{debug, error, info, warn};
+use zeroize::Zeroizing;
+
+use crate::{
+	attestation::keys::{derive_subkey, KeyPurpose},
+	backup::{audit::audit_head_message, sync::verify_signature},
+	chain::{
+		audit::AuditHead,
+		constants::{
+			ACCOUNT_ROTATION_FILE, ACCOUNT_ROTATION_FUNDING, ACCOUNT_ROTATION_PENDING_FILE,
+			ENCLAVE_ACCOUNT_FILE, INTEGRITY_LOG_FILE, LEGACY_ACCOUNT_FILE,
+		},
+		core::{
+			get_enclave_operator, get_registered_enclave, submit_signed_extrinsic,
+			system_remark_oracle, transfer_oracle,
+		},
+		integrity::resign_repair_log,
+	},
+	servers::{
+		auth::VerifiedCaller,
+		state::{get_blocknumber, get_keypair, reset_nonce, set_keypair, SharedState},
+	},
+};
+
+/* ---------------------------------------
+	ENCLAVE ACCOUNT
+--------------------------------------- */
+
+// The sr25519 account of the enclave is generated inside the enclave and sealed to its
+// measurement :
+// - the phrase is written in the /keys mount, encrypted with the MRENCLAVE sealing key, so another
+//   binary, even of the same signer, can not read it
+// - an upgraded binary receives the phrase with the storage key, over the attested upgrade handoff
+// - the account of an older version, or of a restored backup, in the /nft mount is imported once
+//   and removed from the storage key mount
+// The admin quorum rotates the account without a new binary, in two steps :
+// - prepare : the previous and the new account both sign the handover
+//   "enclave-account-rotation_OLD_NEW_BLOCK", the previous account announces it on chain in a
+//   remark and funds the new account, which is sealed aside
+// - complete : once the operator registration of the new account is finalized, the new account is
+//   installed, the signed sealed files and the audit log head are signed again with its subkeys
+
+pub const ROTATION_REMARK_PREFIX: &str = "TEE-ACCOUNT-ROTATION:";
+
+// A rotation in progress, the account is not rotated twice at once
+static ROTATING: AtomicBool = AtomicBool::new(false);
+
+/// Rotation in progress, released when the request ends or is dropped
+struct RotationGuard;
+
+impl RotationGuard {
+	fn acquire() -> Option<RotationGuard> {
+		(!ROTATING.swap(true, Ordering::SeqCst)).then_some(RotationGuard)
+	}
+}
+
+impl Drop for RotationGuard {
+	fn drop(&mut self) {
+		ROTATING.store(false, Ordering::SeqCst);
+	}
+}
+
+/// Handover from the previous enclave account to the new one
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AccountRotation {
+	pub old_account: String,
+	pub new_account: String,
+	pub block_number: u32,
+	// Signatures of the rotation message by both accounts
+	pub old_signature: String,
+	pub new_signature: String,
+	// Audit log head at the installation, signed by the audit log subkeys of both accounts
+	#[serde(default)]
+	pub audit_head: Option<AuditHead>,
+	#[serde(default)]
+	pub old_audit_signature: Option<String>,
+	#[serde(default)]
+	pub new_audit_signature: Option<String>,
+}
+
+impl AccountRotation {
+	/// Handover signed by both accounts
+	/// # Arguments
+	/// * `old` - current account of the enclave
+	/// * `new` - generated account
+	/// * `block_number` - block of the rotation
+	pub fn sign(old: &sr25519::Pair, new: &sr25519::Pair, block_number: u32) -> AccountRotation {
+		let old_account = old.public().to_ss58check();
+		let new_account = new.public().to_ss58check();
+		let message = rotation_message(&old_account, &new_account, block_number);
+
+		AccountRotation {
+			old_signature: format!("0x{}", hex::encode(old.sign(message.as_bytes()).0)),
+			new_signature: format!("0x{}", hex::encode(new.sign(message.as_bytes()).0)),
+			old_account,
+			new_account,
+			block_number,
+			audit_head: None,
+			old_audit_signature: None,
+			new_audit_signature: None,
+		}
+	}
+
+	/// Whether both accounts signed the handover
+	pub fn verify(&self) -> bool {
+		let message = rotation_message(&self.old_account, &self.new_account, self.block_number);
+
+		verify_signature(&self.old_account, self.old_signature.clone(), message.as_bytes()) &&
+			verify_signature(&self.new_account, self.new_signature.clone(), message.as_bytes())
+	}
+
+	/// Remark of the previous account : "TEE-ACCOUNT-ROTATION:NEW:BLOCK:NEWSIGNATURE"
+	pub fn remark(&self) -> String {
+		format!(
+			"{ROTATION_REMARK_PREFIX}{}:{}:{}",
+			self.new_account, self.block_number, self.new_signature
+		)
+	}
+}
+
+/// Message signed by both accounts of a rotation
+pub fn rotation_message(old_account: &str, new_account: &str, block_number: u32) -> String {
+	format!("enclave-account-rotation_{old_account}_{new_account}_{block_number}")
+}
+
+/// Data hash signed by the admins for the rotation of an account
+pub fn account_rotation_data_hash(enclave_account: &str) -> String {
+	sha256::digest(format!("account-rotation_{enclave_account}").as_bytes())
+}
+
+/// Data hash signed by the admins for the installation of a rotated account
+pub fn account_rotation_completion_data_hash(enclave_account: &str, new_account: &str) -> String {
+	sha256::digest(format!("account-rotation-complete_{enclave_account}_{new_account}").as_bytes())
+}
+
+/* ---------------------------------------
+	SEALED PHRASE
+--------------------------------------- */
+
+/// Keypair of a phrase
+fn keypair_from_phrase(phrase: &str) -> Result<sr25519::Pair, anyhow::Error> {
+	sr25519::Pair::from_phrase(phrase.trim(), None)
+		.map(|(keypair, _seed)| keypair)
+		.map_err(|err| anyhow::anyhow!("ENCLAVE ACCOUNT : invalid phrase : {err:?}"))
+}
+
+/// Write a file at once, an interrupted write leaves the previous file
+fn write_sealed(path: &str, content: &[u8]) -> Result<(), anyhow::Error> {
+	let staging = format!("{path}.staging");
+	let mut file = File::create(&staging)?;
+	file.write_all(content)?;
+	file.sync_all()?;
+	fs::rename(&staging, path)?;
+	Ok(())
+}
+
+/// Phrase of the enclave account, sealed to this binary
+pub fn sealed_account_phrase() -> Result<Zeroizing<String>, anyhow::Error> {
+	Ok(Zeroizing::new(fs::read_to_string(ENCLAVE_ACCOUNT_FILE)?))
+}
+
+/// Seal the phrase of the enclave account to this binary
+/// # Arguments
+/// * `phrase` - phrase of the account, i.e. received over the upgrade handoff
+pub fn seal_account_phrase(phrase: &str) -> Result<sr25519::Pair, anyhow::Error> {
+	let keypair = keypair_from_phrase(phrase)?;
+	write_sealed(ENCLAVE_ACCOUNT_FILE, phrase.trim().as_bytes())?;
+	Ok(keypair)
+}
+
+/// Import the account of the storage key mount, written by an older version or restored from a
+/// backup, and seal it to this binary
+/// # Returns
+/// * `Option<sr25519::Pair>` - imported account, None if there is none
+pub fn import_legacy_account() -> Result<Option<sr25519::Pair>, anyhow::Error> {
+	if !Path::new(LEGACY_ACCOUNT_FILE).exists() {
+		return Ok(None)
+	}
+
+	let phrase = Zeroizing::new(fs::read_to_string(LEGACY_ACCOUNT_FILE)?);
+	let keypair = seal_account_phrase(&phrase)?;
+	fs::remove_file(LEGACY_ACCOUNT_FILE)?;
+
+	info!(
+		"ENCLAVE ACCOUNT : {} is imported from {} and sealed to the binary",
+		keypair.public().to_ss58check(),
+		LEGACY_ACCOUNT_FILE
+	);
+
+	Ok(Some(keypair))
+}
+
+/// Account of the enclave at startup : sealed, imported or generated
+pub fn load_enclave_account() -> Result<sr25519::Pair, anyhow::Error> {
+	// A rotation waiting for its registration, the current account is kept until it completes
+	if let Some(pending) = pending_account_rotation() {
+		warn!(
+			"ENCLAVE ACCOUNT : rotation to {} is waiting for its registration by {}",
+			pending.rotation.new_account, pending.operator
+		);
+	}
+
+	if let Some(keypair) = import_legacy_account()? {
+		return Ok(keypair)
+	}
+
+	if Path::new(ENCLAVE_ACCOUNT_FILE).exists() {
+		info!("ENCLAVE ACCOUNT : import the sealed account");
+		return keypair_from_phrase(&sealed_account_phrase()?)
+	}
+
+	info!("ENCLAVE ACCOUNT : Creating new Enclave Account, Remember to send 1 CAPS to it!");
+	let (keypair, phrase, _seed) = sr25519::Pair::generate_with_phrase(None);
+	let phrase = Zeroizing::new(phrase);
+	write_sealed(ENCLAVE_ACCOUNT_FILE, phrase.as_bytes())?;
+
+	Ok(keypair)
+}
+
+/* ---------------------------------------
+	ROTATION
+--------------------------------------- */
+
+/// Rotation announced on chain, waiting for the registration of the new account
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PendingRotation {
+	pub rotation: AccountRotation,
+	// Operator of the enclave, who registers the new account
+	pub operator: String,
+	// Block of the announcement remark
+	pub announced_in: String,
+	// Block of the funding of the new account, None if it failed
+	pub funded_in: Option<String>,
+}
+
+/// Handovers of the enclave account, oldest first
+pub fn read_account_rotations() -> Result<Vec<AccountRotation>, anyhow::Error> {
+	if !Path::new(ACCOUNT_ROTATION_FILE).exists() {
+		return Ok(Vec::new())
+	}
+
+	fs::read_to_string(ACCOUNT_ROTATION_FILE)?
+		.lines()
+		.map(|line| serde_json::from_str(line).map_err(anyhow::Error::from))
+		.collect()
+}
+
+fn append_account_rotation(rotation: &AccountRotation) -> Result<(), anyhow::Error> {
+	let mut file = OpenOptions::new().create(true).append(true).open(ACCOUNT_ROTATION_FILE)?;
+	file.write_all(format!("{}\n", serde_json::to_string(rotation)?).as_bytes())?;
+	Ok(())
+}
+
+fn pending_phrase_file() -> String {
+	format!("{ENCLAVE_ACCOUNT_FILE}.pending")
+}
+
+/// Rotation waiting for its registration, None if there is none
+pub fn pending_account_rotation() -> Option<PendingRotation> {
+	let content = fs::read_to_string(ACCOUNT_ROTATION_PENDING_FILE).ok()?;
+	match serde_json::from_str(&content) {
+		Ok(pending) if Path::new(&pending_phrase_file()).exists() => Some(pending),
+		Ok(_) => {
+			warn!("ENCLAVE ACCOUNT : pending rotation has no sealed phrase, it is ignored");
+			None
+		},
+		Err(err) => {
+			error!("ENCLAVE ACCOUNT : error reading the pending rotation : {err:?}");
+			None
+		},
+	}
+}
+
+/// First step of a rotation : generate and seal the new account, announce the handover from the
+/// current account and fund the new one. The current account stays installed until the operator
+/// registers the new one.
+/// # Arguments
+/// * `state` - SharedState
+pub async fn prepare_account_rotation(
+	state: &SharedState,
+) -> Result<PendingRotation, (StatusCode, String)> {
+	let internal = |err: anyhow::Error| (StatusCode::INTERNAL_SERVER_ERROR, format!("{err:?}"));
+
+	let old = get_keypair(state).await;
+	let operator = match get_enclave_operator(state, AccountId32::from(old.public().0)).await {
+		Ok(Some(operator)) => operator,
+		Ok(None) =>
+			return Err((
+				StatusCode::CONFLICT,
+				"enclave account is not registered on chain".to_string(),
+			)),
+		Err(err) => return Err((StatusCode::BAD_GATEWAY, format!("{err:?}"))),
+	};
+
+	let (new, phrase, _seed) = sr25519::Pair::generate_with_phrase(None);
+	let phrase = Zeroizing::new(phrase);
+	let rotation = AccountRotation::sign(&old, &new, get_blocknumber(state).await);
+
+	write_sealed(&pending_phrase_file(), phrase.as_bytes()).map_err(internal)?;
+
+	let announced_in = match system_remark_oracle(state, rotation.remark().into_bytes()).await {
+		Ok(block_hash) => format!("{block_hash:?}"),
+		Err(err) => {
+			let _ = fs::remove_file(pending_phrase_file());
+			return Err((StatusCode::BAD_GATEWAY, format!("rotation is not announced : {err:?}")))
+		},
+	};
+
+	// The new account pays the extrinsics of the enclave once it is installed
+	let funded_in =
+		match transfer_oracle(state, AccountId32::from(new.public().0), ACCOUNT_ROTATION_FUNDING)
+			.await
+		{
+			Ok(block_hash) => Some(format!("{block_hash:?}")),
+			Err(err) => {
+				warn!(
+					"ENCLAVE ACCOUNT : new account {} is not funded : {err:?}",
+					rotation.new_account
+				);
+				None
+			},
+		};
+
+	let pending =
+		PendingRotation { rotation, operator: operator.to_string(), announced_in, funded_in };
+	let content = serde_json::to_string(&pending).map_err(|err| internal(err.into()))?;
+	write_sealed(ACCOUNT_ROTATION_PENDING_FILE, content.as_bytes()).map_err(internal)?;
+
+	info!(
+		"ENCLAVE ACCOUNT : rotation from {} to {} is announced in block {}, waiting for the registration by {}",
+		pending.rotation.old_account,
+		pending.rotation.new_account,
+		pending.announced_in,
+		pending.operator
+	);
+
+	Ok(pending)
+}
+
+/// Second step of a rotation : once the new account is registered in a finalized block, install
+/// it and sign the sealed logs again with its subkeys
+/// # Arguments
+/// * `state` - SharedState
+/// * `registration` - registration update signed by the operator, submitted before the check
+pub async fn complete_account_rotation(
+	state: &SharedState,
+	registration: Option<Vec<u8>>,
+) -> Result<AccountRotation, (StatusCode, String)> {
+	let internal = |err: anyhow::Error| (StatusCode::INTERNAL_SERVER_ERROR, format!("{err:?}"));
+
+	let pending = pending_account_rotation()
+		.ok_or((StatusCode::CONFLICT, "no account rotation is pending".to_string()))?;
+	let mut rotation = pending.rotation;
+
+	if let Some(registration) = registration {
+		match submit_signed_extrinsic(state, registration).await {
+			Ok(block_hash) =>
+				info!("ENCLAVE ACCOUNT : registration update is finalized in block {block_hash:?}"),
+			Err(err) =>
+				return Err((
+					StatusCode::BAD_GATEWAY,
+					format!("registration update is not finalized : {err:?}"),
+				)),
+		}
+	}
+
+	let operator = AccountId32::from_str(&pending.operator).map_err(|err| {
+		(StatusCode::INTERNAL_SERVER_ERROR, format!("invalid operator account : {err:?}"))
+	})?;
+	match get_registered_enclave(state, operator).await {
+		Ok(Some(registered)) if registered.to_string() == rotation.new_account => (),
+		Ok(registered) =>
+			return Err((
+				StatusCode::CONFLICT,
+				format!(
+					"{} is not registered by {} in a finalized block, the registered account is {registered:?}",
+					rotation.new_account, pending.operator
+				),
+			)),
+		Err(err) => return Err((StatusCode::BAD_GATEWAY, format!("{err:?}"))),
+	}
+
+	let old = get_keypair(state).await;
+	if old.public().to_ss58check() != rotation.old_account {
+		return Err((StatusCode::CONFLICT, "enclave account has changed".to_string()))
+	}
+	let phrase = Zeroizing::new(
+		fs::read_to_string(pending_phrase_file()).map_err(|err| internal(err.into()))?,
+	);
+	let new = keypair_from_phrase(&phrase).map_err(internal)?;
+
+	fs::rename(pending_phrase_file(), ENCLAVE_ACCOUNT_FILE).map_err(|err| internal(err.into()))?;
+	if let Err(err) = fs::remove_file(ACCOUNT_ROTATION_PENDING_FILE) {
+		warn!("ENCLAVE ACCOUNT : error removing the pending rotation : {err:?}");
+	}
+
+	// The signed sealed files follow the new account
+	let old_audit = derive_subkey(&old, KeyPurpose::AUDITLOG);
+	let new_audit = derive_subkey(&new, KeyPurpose::AUDITLOG);
+	match resign_repair_log(INTEGRITY_LOG_FILE, &old_audit.public(), &new_audit) {
+		Ok(entries) => debug!("ENCLAVE ACCOUNT : {entries} integrity log entries are signed again"),
+		Err(err) => warn!("ENCLAVE ACCOUNT : integrity log is not signed again : {err:?}"),
+	}
+
+	// Both audit log subkeys sign the head, the exports of both accounts are chained
+	let audit_head = state.read().await.get_audit_head().clone();
+	let head_message = audit_head_message(&audit_head);
+	rotation.old_audit_signature =
+		Some(format!("0x{}", hex::encode(old_audit.sign(head_message.as_bytes()).0)));
+	rotation.new_audit_signature =
+		Some(format!("0x{}", hex::encode(new_audit.sign(head_message.as_bytes()).0)));
+	rotation.audit_head = Some(audit_head);
+
+	if let Err(err) = append_account_rotation(&rotation) {
+		error!("ENCLAVE ACCOUNT : error recording the rotation : {err:?}");
+	}
+
+	set_keypair(state, new).await;
+	reset_nonce(state).await;
+
+	info!(
+		"ENCLAVE ACCOUNT : account is rotated from {} to {}",
+		rotation.old_account, rotation.new_account
+	);
+
+	Ok(rotation)
+}
+
+/// Rotation request, signed by the admin quorum
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AccountRotationPacket {
+	// Current account of the enclave, a signed request rotates only this account
+	enclave_account: String,
+}
+
+/// Completion of a rotation, signed by the admin quorum
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AccountRotationCompletionPacket {
+	enclave_account: String,
+	new_account: String,
+	// Signed "tee.update_enclave(NEW_ACCOUNT, API_URI)" extrinsic of the operator, hex encoded,
+	// None if the operator submitted it
+	#[serde(default)]
+	registration: Option<String>,
+}
+
+/// Start the rotation of the enclave account on a request of the admin quorum
+/// # Arguments
+/// * `state` - SharedState
+/// * `caller` - verified signers of the quorum
+/// * `request` - AccountRotationPacket
+pub async fn admin_account_rotate(
+	State(state): State<SharedState>,
+	caller: VerifiedCaller,
+	Json(request): Json<AccountRotationPacket>,
+) -> impl IntoResponse {
+	debug!("ADMIN ACCOUNT ROTATION : start");
+
+	if let Err((status, message)) =
+		caller.verify_data_hash(&account_rotation_data_hash(&request.enclave_account))
+	{
+		let message = format!("ADMIN ACCOUNT ROTATION : {message}");
+		warn!(message);
+		return (status, Json(json!({ "error": message })))
+	}
+
+	let enclave_account = get_keypair(&state).await.public().to_ss58check();
+	if request.enclave_account != enclave_account {
+		let message = format!(
+			"ADMIN ACCOUNT ROTATION : {} is not the enclave account {enclave_account}",
+			request.enclave_account
+		);
+		warn!(message);
+		return (StatusCode::CONFLICT, Json(json!({ "error": message })))
+	}
+
+	let _guard = match RotationGuard::acquire() {
+		Some(guard) => guard,
+		None => {
+			let message = "ADMIN ACCOUNT ROTATION : a rotation is in progress".to_string();
+			warn!(message);
+			return (StatusCode::CONFLICT, Json(json!({ "error": message })))
+		},
+	};
+
+	if let Some(pending) = pending_account_rotation() {
+		let message = format!(
+			"ADMIN ACCOUNT ROTATION : rotation to {} is pending, complete it first",
+			pending.rotation.new_account
+		);
+		warn!(message);
+		return (StatusCode::CONFLICT, Json(json!({ "error": message, "pending": pending })))
+	}
+
+	match prepare_account_rotation(&state).await {
+		Ok(pending) => (
+			StatusCode::ACCEPTED,
+			Json(json!({
+				"pending": pending,
+				// The operator registers the new account, then the quorum completes the rotation
+				"registration": "tee.update_enclave(NEW_ACCOUNT, API_URI) signed by the operator",
+			})),
+		),
+		Err((status, message)) => {
+			let message = format!("ADMIN ACCOUNT ROTATION : {message}");
+			error!(message);
+			(status, Json(json!({ "error": message })))
+		},
+	}
+}
+
+/// Install the new enclave account once it is registered, on a request of the admin quorum
+/// # Arguments
+/// * `state` - SharedState
+/// * `caller` - verified signers of the quorum
+/// * `request` - AccountRotationCompletionPacket
+pub async fn admin_account_rotate_complete(
+	State(state): State<SharedState>,
+	caller: VerifiedCaller,
+	Json(request): Json<AccountRotationCompletionPacket>,
+) -> impl IntoResponse {
+	debug!("ADMIN ACCOUNT ROTATION COMPLETE : start");
+
+	if let Err((status, message)) = caller.verify_data_hash(&account_rotation_completion_data_hash(
+		&request.enclave_account,
+		&request.new_account,
+	)) {
+		let message = format!("ADMIN ACCOUNT ROTATION COMPLETE : {message}");
+		warn!(message);
+		return (status, Json(json!({ "error": message })))
+	}
+
+	let registration = match request
+		.registration
+		.as_deref()
+		.map(|hex_extrinsic| hex::decode(hex_extrinsic.trim_start_matches("0x")))
+	{
+		Some(Ok(registration)) => Some(registration),
+		Some(Err(err)) => {
+			let message =
+				format!("ADMIN ACCOUNT ROTATION COMPLETE : invalid registration : {err:?}");
+			warn!(message);
+			return (StatusCode::BAD_REQUEST, Json(json!({ "error": message })))
+		},
+		None => None,
+	};
+
+	let _guard = match RotationGuard::acquire() {
+		Some(guard) => guard,
+		None => {
+			let message = "ADMIN ACCOUNT ROTATION COMPLETE : a rotation is in progress".to_string();
+			warn!(message);
+			return (StatusCode::CONFLICT, Json(json!({ "error": message })))
+		},
+	};
+
+	match pending_account_rotation() {
+		Some(pending)
+			if pending.rotation.old_account == request.enclave_account &&
+				pending.rotation.new_account == request.new_account => {},
+		_ => {
+			let message = format!(
+				"ADMIN ACCOUNT ROTATION COMPLETE : no pending rotation from {} to {}",
+				request.enclave_account, request.new_account
+			);
+			warn!(message);
+			return (StatusCode::CONFLICT, Json(json!({ "error": message })))
+		},
+	}
+
+	match complete_account_rotation(&state, registration).await {
+		Ok(rotation) => (StatusCode::OK, Json(json!({ "rotation": rotation }))),
+		Err((status, message)) => {
+			let message = format!("ADMIN ACCOUNT ROTATION COMPLETE : {message}");
+			warn!(message);
+			(status, Json(json!({ "error": message })))
+		},
+	}
+}
+
+/// Handovers of the enclave account, and the pending one
+pub async fn admin_account_rotations() -> impl IntoResponse {
+	match read_account_rotations() {
+		Ok(rotations) => (
+			StatusCode::OK,
+			Json(json!({ "rotations": rotations, "pending": pending_account_rotation() })),
+		),
+		Err(err) => {
+			let message = format!("ENCLAVE ACCOUNT : error reading the rotations : {err:?}");
+			error!(message);
+			(StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })))
+		},
+	}
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn account_rotation_test() {
+		let old = sr25519::Pair::from_seed(&[1u8; 32]);
+		let new = sr25519::Pair::from_seed(&[2u8; 32]);
+
+		let rotation = AccountRotation::sign(&old, &new, 1200);
+		assert!(rotation.verify());
+		assert_eq!(rotation.old_account, old.public().to_ss58check());
+		assert!(rotation.remark().starts_with(&format!(
+			"{ROTATION_REMARK_PREFIX}{}:1200:0x",
+			new.public().to_ss58check()
+		)));
+
+		// Both accounts must sign the same handover
+		let mut forged = rotation.clone();
+		forged.block_number = 1201;
+		assert!(!forged.verify());
+		let mut forged = rotation.clone();
+		forged.new_signature = rotation.old_signature.clone();
+		assert!(!forged.verify());
+
+		assert_ne!(
+			account_rotation_data_hash(&rotation.old_account),
+			account_rotation_data_hash(&rotation.new_account)
+		);
+		assert_ne!(
+			account_rotation_data_hash(&rotation.old_account),
+			account_rotation_completion_data_hash(&rotation.old_account, &rotation.new_account)
+		);
+
+		// Rotations recorded before the audit head signatures are still read
+		let mut recorded = serde_json::to_value(&rotation).unwrap();
+		recorded.as_object_mut().unwrap().remove("audit_head");
+		let recorded: AccountRotation = serde_json::from_value(recorded).unwrap();
+		assert_eq!(recorded, rotation);
+		assert!(recorded.old_audit_signature.is_none());
+		assert!(keypair_from_phrase("not a phrase").is_err());
+	}
+}
@@ -0,0 +1,552 @@
+use std::{path::Path, sync::RwLock};
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use subxt::{blocks::Block, OnlineClient, PolkadotConfig};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+	attestation::verifier::{parse_measurement, verifier_config, VerifierConfig},
+	chain::{
+		constants::{ALLOWLIST_FILE, MAX_ALLOWLIST_ENTRIES},
+		killswitch::{is_governance_signer, system_remarks},
+	},
+	servers::{
+		auth::VerifiedCaller,
+		state::{get_blocknumber, SharedState},
+	},
+};
+
+/* ---------------------------------------
+	MEASUREMENT ALLOWLIST
+--------------------------------------- */
+
+// Peer synchronization and disaster recovery only trust the approved enclave builds, the quote
+// verifier checks the MRENCLAVE and MRSIGNER of a peer against an allowlist :
+// - the measurements of the command line are the base of the allowlist, they change with a restart
+// - the admin quorum adds and revokes measurements with a signed request, or with a
+//   "TEE-ALLOWLIST:{ADD|REVOKE}:{MRENCLAVE|MRSIGNER}:HEX" remark of a quorum member onchain
+// - a revoked measurement is never trusted, also when it is on the command line
+// - the changes are persisted in the sealed directory and loaded at startup
+// The MRSIGNER of this enclave is trusted while nothing is allowed, like before the allowlist.
+
+pub const ALLOWLIST_REMARK_PREFIX: &str = "TEE-ALLOWLIST:";
+
+static ALLOWLIST: RwLock<MeasurementAllowlist> =
+	RwLock::new(MeasurementAllowlist { allowed: Vec::new(), revoked: Vec::new() });
+
+/// Measurement of an enclave build
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum MeasurementKind {
+	MRENCLAVE,
+	MRSIGNER,
+}
+
+/// Origin of an allowlist entry
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum AllowlistSource {
+	// Command line, not persisted
+	CONFIG,
+	// Signed request of the admin quorum
+	ADMIN,
+	// Remark of a quorum member
+	CHAIN,
+}
+
+/// Change of the allowlist
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum AllowlistAction {
+	ADD,
+	REVOKE,
+}
+
+/// Allowed or revoked measurement
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AllowlistEntry {
+	pub kind: MeasurementKind,
+	// Lowercase hex, without 0x prefix
+	pub measurement: String,
+	pub label: String,
+	pub source: AllowlistSource,
+	// Block of the change, 0 for the command line
+	pub block_number: u32,
+}
+
+/// Measurements allowed and revoked by the admin quorum, on top of the command line
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct MeasurementAllowlist {
+	pub allowed: Vec<AllowlistEntry>,
+	pub revoked: Vec<AllowlistEntry>,
+}
+
+impl MeasurementAllowlist {
+	fn position(
+		entries: &[AllowlistEntry],
+		kind: MeasurementKind,
+		measurement: &str,
+	) -> Option<usize> {
+		entries
+			.iter()
+			.position(|entry| entry.kind == kind && entry.measurement == measurement)
+	}
+
+	/// Whether a measurement is revoked
+	pub fn is_revoked(&self, kind: MeasurementKind, measurement: &str) -> bool {
+		Self::position(&self.revoked, kind, measurement).is_some()
+	}
+
+	/// Apply a change, false if the allowlist is unchanged
+	/// # Arguments
+	/// * `action` - add or revoke the measurement
+	/// * `entry` - measurement of the change
+	pub fn apply(&mut self, action: AllowlistAction, entry: AllowlistEntry) -> bool {
+		let (from, to) = match action {
+			AllowlistAction::ADD => (&mut self.revoked, &mut self.allowed),
+			AllowlistAction::REVOKE => (&mut self.allowed, &mut self.revoked),
+		};
+
+		let removed = Self::position(from, entry.kind, &entry.measurement)
+			.map(|index| from.remove(index))
+			.is_some();
+
+		if Self::position(to, entry.kind, &entry.measurement).is_some() {
+			return removed
+		}
+
+		to.push(entry);
+		true
+	}
+
+	/// Allowed measurements of the command line and the admin quorum, without the revoked ones
+	/// # Arguments
+	/// * `config` - quote verifier configuration of the command line
+	pub fn effective(&self, config: &VerifierConfig) -> Vec<AllowlistEntry> {
+		let configured = |kind: MeasurementKind, measurements: &Vec<String>| {
+			measurements
+				.iter()
+				.map(|measurement| AllowlistEntry {
+					kind,
+					measurement: measurement.clone(),
+					label: "command line".to_string(),
+					source: AllowlistSource::CONFIG,
+					block_number: 0,
+				})
+				.collect::<Vec<_>>()
+		};
+
+		let mut entries = configured(MeasurementKind::MRENCLAVE, &config.mrenclaves);
+		entries.extend(configured(MeasurementKind::MRSIGNER, &config.mrsigners));
+		entries.retain(|entry| {
+			Self::position(&self.allowed, entry.kind, &entry.measurement).is_none()
+		});
+		entries.extend(self.allowed.iter().cloned());
+		entries.retain(|entry| !self.is_revoked(entry.kind, &entry.measurement));
+
+		entries
+	}
+
+	/// Whether the measurements of an enclave are trusted
+	/// # Arguments
+	/// * `config` - quote verifier configuration of the command line
+	/// * `local_mrsigner` - MRSIGNER of this enclave, trusted when nothing is allowed
+	/// * `mrenclave` - MRENCLAVE of the enclave
+	/// * `mrsigner` - MRSIGNER of the enclave
+	pub fn is_trusted(
+		&self,
+		config: &VerifierConfig,
+		local_mrsigner: Option<&str>,
+		mrenclave: &str,
+		mrsigner: &str,
+	) -> bool {
+		if self.is_revoked(MeasurementKind::MRENCLAVE, mrenclave) ||
+			self.is_revoked(MeasurementKind::MRSIGNER, mrsigner)
+		{
+			return false
+		}
+
+		let entries = self.effective(config);
+		if entries.is_empty() {
+			return local_mrsigner == Some(mrsigner)
+		}
+
+		entries.iter().any(|entry| match entry.kind {
+			MeasurementKind::MRENCLAVE => entry.measurement == mrenclave,
+			MeasurementKind::MRSIGNER => entry.measurement == mrsigner,
+		})
+	}
+}
+
+/// Current allowlist of the admin quorum
+pub fn measurement_allowlist() -> MeasurementAllowlist {
+	match ALLOWLIST.read() {
+		Ok(allowlist) => allowlist.clone(),
+		Err(err) => {
+			// Changes are persisted before they are applied, the allowlist is consistent
+			error!("ALLOWLIST : lock error : {err:?}");
+			err.into_inner().clone()
+		},
+	}
+}
+
+/// Apply a change to the allowlist, persisted before it takes effect
+/// # Arguments
+/// * `action` - add or revoke the measurement
+/// * `entry` - measurement of the change
+/// # Returns
+/// * `bool` - false if the allowlist is unchanged
+pub fn update_allowlist(
+	action: AllowlistAction,
+	entry: AllowlistEntry,
+) -> Result<bool, anyhow::Error> {
+	let mut current = ALLOWLIST
+		.write()
+		.map_err(|err| anyhow::anyhow!("ALLOWLIST : lock error : {err:?}"))?;
+
+	let mut allowlist = current.clone();
+	if !allowlist.apply(action, entry) {
+		return Ok(false)
+	}
+
+	if allowlist.allowed.len() + allowlist.revoked.len() > MAX_ALLOWLIST_ENTRIES {
+		return Err(anyhow::anyhow!(
+			"ALLOWLIST : more than {MAX_ALLOWLIST_ENTRIES} allowed and revoked measurements"
+		))
+	}
+
+	std::fs::write(ALLOWLIST_FILE, serde_json::to_string(&allowlist)?)?;
+	*current = allowlist;
+
+	Ok(true)
+}
+
+/// Load the sealed allowlist at startup
+pub fn load_allowlist() -> Result<(), anyhow::Error> {
+	if !Path::new(ALLOWLIST_FILE).exists() {
+		debug!("ALLOWLIST : no allowlist file, only the command line measurements are allowed");
+		return Ok(())
+	}
+
+	let allowlist: MeasurementAllowlist =
+		serde_json::from_str(&std::fs::read_to_string(ALLOWLIST_FILE)?)?;
+	info!(
+		"ALLOWLIST : {} allowed and {} revoked measurements",
+		allowlist.allowed.len(),
+		allowlist.revoked.len()
+	);
+
+	let mut current = ALLOWLIST
+		.write()
+		.map_err(|err| anyhow::anyhow!("ALLOWLIST : lock error : {err:?}"))?;
+	*current = allowlist;
+
+	Ok(())
+}
+
+/* ---------------------------------------
+	CHAIN REGISTRY
+--------------------------------------- */
+
+/// Parse an allowlist remark : "TEE-ALLOWLIST:{ADD|REVOKE}:{MRENCLAVE|MRSIGNER}:HEX"
+pub fn parse_allowlist_remark(remark: &[u8]) -> Option<(AllowlistAction, MeasurementKind, String)> {
+	let remark = std::str::from_utf8(remark).ok()?;
+	let mut fields = remark.trim().strip_prefix(ALLOWLIST_REMARK_PREFIX)?.split(':');
+
+	let action = match fields.next()? {
+		"ADD" => AllowlistAction::ADD,
+		"REVOKE" => AllowlistAction::REVOKE,
+		_ => return None,
+	};
+
+	let kind = match fields.next()? {
+		"MRENCLAVE" => MeasurementKind::MRENCLAVE,
+		"MRSIGNER" => MeasurementKind::MRSIGNER,
+		_ => return None,
+	};
+
+	let measurement = parse_measurement(fields.next()?).ok()?;
+	if fields.next().is_some() {
+		return None
+	}
+
+	Some((action, kind, measurement))
+}
+
+/// Apply the allowlist remarks of the admin quorum members in a finalized block
+/// # Arguments
+/// * `state` - SharedState
+/// * `block` - finalized block
+pub async fn watch_block(
+	state: &SharedState,
+	block: &Block<PolkadotConfig, OnlineClient<PolkadotConfig>>,
+) -> Result<(), anyhow::Error> {
+	let block_number = block.header().number;
+
+	for (signer, remark) in system_remarks(block).await? {
+		let (action, kind, measurement) = match parse_allowlist_remark(&remark) {
+			Some(change) => change,
+			None => continue,
+		};
+
+		if !is_governance_signer(state, &signer).await {
+			warn!(
+				"ALLOWLIST : ignored {:?} {:?} {} from non-governance account {:?}",
+				action, kind, measurement, signer
+			);
+			continue
+		}
+
+		let entry = AllowlistEntry {
+			kind,
+			measurement,
+			label: format!("remark of block {block_number}"),
+			source: AllowlistSource::CHAIN,
+			block_number,
+		};
+
+		match update_allowlist(action, entry.clone()) {
+			Ok(true) =>
+				info!("ALLOWLIST : governance {:?} {:?} {}", action, kind, entry.measurement),
+			Ok(false) => debug!("ALLOWLIST : {:?} {:?} is unchanged", action, kind),
+			Err(err) => error!("ALLOWLIST : error applying the remark : {err:?}"),
+		}
+	}
+
+	Ok(())
+}
+
+/* ---------------------------------------
+	ALLOWLIST API
+--------------------------------------- */
+
+/// Allowlist change, signed by the threshold of admin quorum
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AllowlistPacket {
+	action: AllowlistAction,
+	kind: MeasurementKind,
+	measurement: String,
+	#[serde(default)]
+	label: String,
+}
+
+/// Canonical hash of the allowlist change, signed inside the authentication token
+/// # Arguments
+/// * `action` - add or revoke the measurement
+/// * `kind` - MRENCLAVE or MRSIGNER
+/// * `measurement` - hex encoded measurement, as in the request
+/// * `label` - description of the enclave build
+pub fn allowlist_data_hash(
+	action: AllowlistAction,
+	kind: MeasurementKind,
+	measurement: &str,
+	label: &str,
+) -> String {
+	sha256::digest(format!("allowlist_{action:?}_{kind:?}_{measurement}_{label}").as_bytes())
+}
+
+/// Effective and revoked measurements of the allowlist
+pub async fn admin_allowlist_status() -> impl IntoResponse {
+	let allowlist = measurement_allowlist();
+	let effective = allowlist.effective(verifier_config());
+
+	(
+		StatusCode::OK,
+		Json(json!({
+			// The MRSIGNER of this enclave is trusted while nothing is allowed
+			"local_mrsigner_fallback": effective.is_empty(),
+			"allowed": effective,
+			"revoked": allowlist.revoked,
+		})),
+	)
+}
+
+/// Add or revoke a measurement of the allowlist
+/// The request must be signed by the threshold of admin quorum
+/// # Arguments
+/// * `state` - SharedState
+/// * `request` - AllowlistPacket
+#[axum::debug_handler]
+pub async fn admin_allowlist_update(
+	State(state): State<SharedState>,
+	caller: VerifiedCaller,
+	Json(request): Json<AllowlistPacket>,
+) -> impl IntoResponse {
+	debug!("ADMIN ALLOWLIST : start");
+
+	let data_hash =
+		allowlist_data_hash(request.action, request.kind, &request.measurement, &request.label);
+	if let Err((status, message)) = caller.verify_data_hash(&data_hash) {
+		let message = format!("ADMIN ALLOWLIST : {message}");
+		warn!(message);
+		return (status, Json(json!({ "error": message })))
+	}
+
+	let measurement = match parse_measurement(&request.measurement) {
+		Ok(measurement) => measurement,
+		Err(err) => {
+			let message = format!("ADMIN ALLOWLIST : {err}");
+			warn!(message);
+			return (StatusCode::BAD_REQUEST, Json(json!({ "error": message })))
+		},
+	};
+
+	let entry = AllowlistEntry {
+		kind: request.kind,
+		measurement,
+		label: request.label,
+		source: AllowlistSource::ADMIN,
+		block_number: get_blocknumber(&state).await,
+	};
+
+	let changed = match update_allowlist(request.action, entry.clone()) {
+		Ok(changed) => changed,
+		Err(err) => {
+			let message = format!("ADMIN ALLOWLIST : error saving the allowlist : {err:?}");
+			error!(message);
+			return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })))
+		},
+	};
+
+	info!(
+		"ADMIN ALLOWLIST : {:?} {:?} {} with {} approvals, changed : {}",
+		request.action,
+		entry.kind,
+		entry.measurement,
+		caller.approvals(),
+		changed
+	);
+
+	(
+		StatusCode::OK,
+		Json(json!({
+			"changed": changed,
+			"entry": entry,
+			"allowed": measurement_allowlist().effective(verifier_config()),
+		})),
+	)
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn entry(kind: MeasurementKind, measurement: &str) -> AllowlistEntry {
+		AllowlistEntry {
+			kind,
+			measurement: measurement.to_string(),
+			label: "v0.5.0".to_string(),
+			source: AllowlistSource::ADMIN,
+			block_number: 1000,
+		}
+	}
+
+	#[test]
+	fn measurement_allowlist_test() {
+		let (mrenclave_a, mrenclave_b, mrsigner) =
+			("aa".repeat(32), "bb".repeat(32), "cc".repeat(32));
+		let config = VerifierConfig { mrenclaves: vec![mrenclave_a.clone()], ..Default::default() };
+		let empty = VerifierConfig::default();
+
+		// Nothing allowed, the local MRSIGNER is trusted
+		let mut allowlist = MeasurementAllowlist::default();
+		assert!(allowlist.is_trusted(&empty, Some(&mrsigner), &mrenclave_b, &mrsigner));
+		assert!(!allowlist.is_trusted(&empty, None, &mrenclave_b, &mrsigner));
+
+		// Command line measurements
+		assert!(allowlist.is_trusted(&config, Some(&mrsigner), &mrenclave_a, &"dd".repeat(32)));
+		assert!(!allowlist.is_trusted(&config, Some(&mrsigner), &mrenclave_b, &mrsigner));
+
+		// Admin measurements
+		assert!(
+			allowlist.apply(AllowlistAction::ADD, entry(MeasurementKind::MRENCLAVE, &mrenclave_b))
+		);
+		assert!(
+			!allowlist.apply(AllowlistAction::ADD, entry(MeasurementKind::MRENCLAVE, &mrenclave_b))
+		);
+		assert!(allowlist.is_trusted(&config, None, &mrenclave_b, &mrsigner));
+		assert_eq!(allowlist.effective(&config).len(), 2);
+
+		// Revoked measurements are never trusted, also those of the command line
+		assert!(allowlist
+			.apply(AllowlistAction::REVOKE, entry(MeasurementKind::MRENCLAVE, &mrenclave_a)));
+		assert!(!allowlist.is_trusted(&config, None, &mrenclave_a, &mrsigner));
+		assert!(
+			allowlist.apply(AllowlistAction::REVOKE, entry(MeasurementKind::MRSIGNER, &mrsigner))
+		);
+		assert!(!allowlist.is_trusted(&config, None, &mrenclave_b, &mrsigner));
+		assert!(!allowlist.is_trusted(&empty, Some(&mrsigner), &mrenclave_b, &mrsigner));
+		assert_eq!(allowlist.effective(&config).len(), 1);
+
+		// A revoked measurement is allowed again
+		assert!(
+			allowlist.apply(AllowlistAction::ADD, entry(MeasurementKind::MRENCLAVE, &mrenclave_a))
+		);
+		assert!(!allowlist.is_revoked(MeasurementKind::MRENCLAVE, &mrenclave_a));
+		assert_eq!(allowlist.effective(&config).len(), 2);
+
+		assert_eq!(
+			parse_allowlist_remark(
+				format!("TEE-ALLOWLIST:ADD:MRENCLAVE:0x{}", "AA".repeat(32)).as_bytes()
+			),
+			Some((AllowlistAction::ADD, MeasurementKind::MRENCLAVE, mrenclave_a.clone()))
+		);
+		assert_eq!(
+			parse_allowlist_remark(format!("TEE-ALLOWLIST:REVOKE:MRSIGNER:{mrsigner}").as_bytes()),
+			Some((AllowlistAction::REVOKE, MeasurementKind::MRSIGNER, mrsigner.clone()))
+		);
+		assert_eq!(parse_allowlist_remark(b"TEE-ALLOWLIST:ADD:MRENCLAVE:aa"), None);
+		assert_eq!(
+			parse_allowlist_remark(format!("TEE-ALLOWLIST:ADD:QE:{mrsigner}").as_bytes()),
+			None
+		);
+		assert_eq!(parse_allowlist_remark(b"TEE-KILLSWITCH:FULL"), None);
+
+		// Every field is bound to the signed token
+		let hash = allowlist_data_hash(
+			AllowlistAction::ADD,
+			MeasurementKind::MRENCLAVE,
+			&mrenclave_a,
+			"v0.5.0",
+		);
+		assert_ne!(
+			hash,
+			allowlist_data_hash(
+				AllowlistAction::REVOKE,
+				MeasurementKind::MRENCLAVE,
+				&mrenclave_a,
+				"v0.5.0"
+			)
+		);
+		assert_ne!(
+			hash,
+			allowlist_data_hash(
+				AllowlistAction::ADD,
+				MeasurementKind::MRSIGNER,
+				&mrenclave_a,
+				"v0.5.0"
+			)
+		);
+		assert_ne!(
+			hash,
+			allowlist_data_hash(
+				AllowlistAction::ADD,
+				MeasurementKind::MRENCLAVE,
+				&mrenclave_b,
+				"v0.5.0"
+			)
+		);
+		assert_ne!(
+			hash,
+			allowlist_data_hash(
+				AllowlistAction::ADD,
+				MeasurementKind::MRENCLAVE,
+				&mrenclave_a,
+				"v0.6.0"
+			)
+		);
+	}
+}
@@ -0,0 +1,180 @@
+use std::net::SocketAddr;
+
+use axum::{
+	extract::{ConnectInfo, State},
+	http::StatusCode,
+	response::{IntoResponse, Response},
+	Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use subxt::ext::sp_core::{sr25519, Pair};
+use tracing::{debug, error, warn};
+
+use crate::{
+	attestation::ra::{create_nonce_quote, ReportDataPreimage},
+	chain::quota::check_quote_rate,
+	servers::state::{get_accountid, get_keypair, SharedState},
+};
+
+/* ---------------------------------------
+	INTERACTIVE ATTESTATION
+--------------------------------------- */
+
+// Verifiers which need a proof of freshness send their own nonce instead of reading a quote of
+// the current block :
+// - the caller sends a random 32 bytes nonce
+// - the report data of a new quote is SHA256(PUBLICKEY || API_URL || BLOCKNUMBER) followed by the
+//   nonce, see ReportDataPreimage
+// - the response is signed by the enclave account, over the nonce, the block and the quote hash
+// A quote is generated for every request, the responses are never cached. The quotes are rate
+// limited per ip and for all the callers, see check_quote_rate.
+
+/// Attestation request of a verifier
+#[derive(Deserialize, Debug)]
+pub struct AttestRequest {
+	// Hex encoded 32 bytes, with or without 0x prefix
+	pub nonce: String,
+}
+
+/// Fresh quote bound to the nonce of the verifier, signed by the enclave account
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct AttestResponse {
+	pub enclave_account: String,
+	// 0x prefixed nonce of the request
+	pub nonce: String,
+	pub report_data_preimage: ReportDataPreimage,
+	pub quote: String,
+	pub signature: String,
+}
+
+impl AttestResponse {
+	/// Message signed by the enclave account
+	/// "enclave-attest_NONCE_BLOCKNUMBER_SHA256(QUOTE)"
+	pub fn signing_message(&self) -> String {
+		let quote_hash = hex::decode(&self.quote)
+			.map(|quote| sha256::digest(&quote[..]))
+			.unwrap_or_default();
+
+		format!(
+			"enclave-attest_{}_{}_{}",
+			self.nonce, self.report_data_preimage.block_number, quote_hash
+		)
+	}
+
+	/// Sign the response with the enclave account
+	pub fn sign(mut self, enclave_key: &sr25519::Pair) -> AttestResponse {
+		let signature = enclave_key.sign(self.signing_message().as_bytes());
+		self.signature = format!("0x{}", hex::encode(signature.0));
+		self
+	}
+}
+
+/// Parse the nonce of an attestation request
+/// # Arguments
+/// * `nonce` - hex encoded 32 bytes
+pub fn parse_nonce(nonce: &str) -> Result<[u8; 32], String> {
+	let bytes = hex::decode(nonce.trim().trim_start_matches("0x"))
+		.map_err(|err| format!("ATTEST : nonce is not hex encoded : {err:?}"))?;
+
+	bytes
+		.try_into()
+		.map_err(|bytes: Vec<u8>| format!("ATTEST : nonce must be 32 bytes, got {}", bytes.len()))
+}
+
+/// Fresh quote embedding the nonce of the caller, signed by the enclave account
+/// # Arguments
+/// * `state` - SharedState
+/// * `request` - AttestRequest
+pub async fn attest(
+	State(state): State<SharedState>,
+	connect_info: Option<ConnectInfo<SocketAddr>>,
+	Json(request): Json<AttestRequest>,
+) -> Response {
+	let nonce = match parse_nonce(&request.nonce) {
+		Ok(nonce) => nonce,
+		Err(message) => {
+			warn!(message);
+			return (StatusCode::BAD_REQUEST, Json(json!({ "error": message }))).into_response()
+		},
+	};
+
+	let ip = connect_info.map(|ConnectInfo(addr)| addr.ip());
+	if let Err(rejection) = check_quote_rate(ip) {
+		warn!("ATTEST : quote of {ip:?} is rejected : {rejection:?}");
+		return rejection.into_response()
+	}
+
+	debug!("ATTEST : fresh quote for nonce 0x{}", hex::encode(nonce));
+
+	let (preimage, quote) = create_nonce_quote(&state, &nonce).await;
+	let quote = match quote {
+		Ok(quote) => quote,
+		Err(err) => {
+			let message = format!("ATTEST : unable to create the quote : {err}");
+			error!(message);
+			return (err.status_code(), Json(json!({ "error": message, "attestation_error": err })))
+				.into_response()
+		},
+	};
+
+	let response = AttestResponse {
+		enclave_account: get_accountid(&state).await,
+		nonce: format!("0x{}", hex::encode(nonce)),
+		report_data_preimage: preimage,
+		quote: hex::encode(quote),
+		signature: String::new(),
+	}
+	.sign(&get_keypair(&state).await);
+
+	(StatusCode::OK, Json(response)).into_response()
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn attest_response_test() {
+		assert_eq!(parse_nonce(&format!("0x{}", "ab".repeat(32))).unwrap(), [0xABu8; 32]);
+		assert_eq!(parse_nonce(&"01".repeat(32)).unwrap(), [1u8; 32]);
+		assert!(parse_nonce(&"ab".repeat(31)).is_err());
+		assert!(parse_nonce("nonce").is_err());
+
+		let enclave_key = sr25519::Pair::from_seed(&[7u8; 32]);
+		let preimage =
+			ReportDataPreimage::new(&enclave_key.public(), "https://enclave.ternoa.network", 1000);
+
+		// The nonce is the second half of the report data
+		let report_data = preimage.report_data_with_nonce(&[0xABu8; 32]).unwrap();
+		assert_eq!(&report_data[..32], &preimage.report_data().unwrap()[..32]);
+		assert_eq!(&report_data[32..], &[0xABu8; 32][..]);
+
+		let response = AttestResponse {
+			enclave_account: "5C4hrfjw9DjXZTzV3MwzrrAr9P1MJhSrvWGWqi1eSuyUpnhM".to_string(),
+			nonce: format!("0x{}", "ab".repeat(32)),
+			report_data_preimage: preimage,
+			quote: hex::encode([3u8; 64]),
+			signature: String::new(),
+		}
+		.sign(&enclave_key);
+
+		let signature = hex::decode(response.signature.trim_start_matches("0x")).unwrap();
+		let signature = sr25519::Signature::from_slice(&signature).unwrap();
+		assert!(sr25519::Pair::verify(
+			&signature,
+			response.signing_message(),
+			&enclave_key.public()
+		));
+
+		// The nonce and the quote are bound to the signature
+		let other = AttestResponse { nonce: format!("0x{}", "cd".repeat(32)), ..response.clone() };
+		assert!(!sr25519::Pair::verify(&signature, other.signing_message(), &enclave_key.public()));
+		let other = AttestResponse { quote: hex::encode([4u8; 64]), ..response };
+		assert!(!sr25519::Pair::verify(&signature, other.signing_message(), &enclave_key.public()));
+	}
+}
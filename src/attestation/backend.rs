@@ -0,0 +1,182 @@
+use std::sync::OnceLock;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::attestation::{
+	epid::ias_verdict,
+	ra::read_attestation_type,
+	verifier::{quote_verdict, QuoteVerdict, TcbStatus},
+};
+
+/* ---------------------------------------
+	ATTESTATION BACKENDS
+--------------------------------------- */
+
+// The quotes of a platform are verified by the backend of its attestation type :
+// - DCAP, the default, verifies the ECDSA quotes inside the enclave with the PCS collateral
+// - EPID, for the legacy platforms, sends the quote to the Intel Attestation Service and verifies
+//   the signed report
+// The backend is selected once by the attestation type which Gramine reports, the enclaves of a
+// cluster run on the same kind of platform. Both give an AttestationVerdict, so the peer
+// synchronization, the recovery and the quote watchdog do not depend on the backend.
+
+static ATTESTATION_BACKEND: OnceLock<Box<dyn AttestationBackend>> = OnceLock::new();
+
+/// Remote attestation scheme of a platform
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum AttestationType {
+	DCAP,
+	EPID,
+}
+
+impl AttestationType {
+	/// Backend of the Gramine attestation type, DCAP unless the platform is EPID
+	/// # Arguments
+	/// * `attestation_type` - "none", "epid" or "dcap", see read_attestation_type
+	pub fn from_gramine(attestation_type: &str) -> AttestationType {
+		match attestation_type.trim() {
+			"epid" => AttestationType::EPID,
+			_ => AttestationType::DCAP,
+		}
+	}
+}
+
+/// Verdict of a quote, whatever its attestation backend
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct AttestationVerdict {
+	pub attestation_type: AttestationType,
+	pub mrenclave: String,
+	pub mrsigner: String,
+	pub isv_prod_id: u16,
+	pub isv_svn: u16,
+	pub report_data: String,
+	// Platform status of the collateral or of the attestation report
+	pub tcb_status: TcbStatus,
+	pub advisory_ids: Vec<String>,
+	// DCAP only, EPID reports include the quoting enclave in the platform status
+	pub qe_tcb_status: Option<TcbStatus>,
+	// MRENCLAVE or MRSIGNER is in the allowlist
+	pub trusted_measurement: bool,
+	// DEBUG attribute of the enclave report
+	pub debug: bool,
+	pub accepted: bool,
+}
+
+impl From<QuoteVerdict> for AttestationVerdict {
+	fn from(verdict: QuoteVerdict) -> Self {
+		AttestationVerdict {
+			attestation_type: AttestationType::DCAP,
+			mrenclave: verdict.mrenclave,
+			mrsigner: verdict.mrsigner,
+			isv_prod_id: verdict.isv_prod_id,
+			isv_svn: verdict.isv_svn,
+			report_data: verdict.report_data,
+			tcb_status: verdict.tcb_status,
+			advisory_ids: verdict.advisory_ids,
+			qe_tcb_status: Some(verdict.qe_tcb_status),
+			trusted_measurement: verdict.trusted_measurement,
+			debug: verdict.debug,
+			accepted: verdict.accepted,
+		}
+	}
+}
+
+/// Verification of the quotes of an attestation type
+#[async_trait]
+pub trait AttestationBackend: Send + Sync {
+	/// Attestation type of the verified quotes
+	fn attestation_type(&self) -> AttestationType;
+
+	/// Verdict of a quote, accepted or not, an error if it can not be verified
+	/// # Arguments
+	/// * `client` - http client of the enclave
+	/// * `quote` - bytes of the quote
+	async fn verify(
+		&self,
+		client: &reqwest::Client,
+		quote: &[u8],
+	) -> Result<AttestationVerdict, anyhow::Error>;
+}
+
+/// ECDSA quotes, verified with the collateral of the platform
+pub struct DcapBackend;
+
+/// EPID quotes, verified by the Intel Attestation Service
+pub struct EpidBackend;
+
+#[async_trait]
+impl AttestationBackend for DcapBackend {
+	fn attestation_type(&self) -> AttestationType {
+		AttestationType::DCAP
+	}
+
+	async fn verify(
+		&self,
+		client: &reqwest::Client,
+		quote: &[u8],
+	) -> Result<AttestationVerdict, anyhow::Error> {
+		quote_verdict(client, quote).await.map(AttestationVerdict::from)
+	}
+}
+
+#[async_trait]
+impl AttestationBackend for EpidBackend {
+	fn attestation_type(&self) -> AttestationType {
+		AttestationType::EPID
+	}
+
+	async fn verify(
+		&self,
+		client: &reqwest::Client,
+		quote: &[u8],
+	) -> Result<AttestationVerdict, anyhow::Error> {
+		ias_verdict(client, quote).await
+	}
+}
+
+/// Backend of an attestation type
+pub fn backend_of(attestation_type: AttestationType) -> Box<dyn AttestationBackend> {
+	match attestation_type {
+		AttestationType::DCAP => Box::new(DcapBackend),
+		AttestationType::EPID => Box::new(EpidBackend),
+	}
+}
+
+/// Attestation backend of this platform, selected at the first verification
+pub fn attestation_backend() -> &'static dyn AttestationBackend {
+	ATTESTATION_BACKEND
+		.get_or_init(|| {
+			let attestation_type = match read_attestation_type() {
+				Ok(attestation_type) => AttestationType::from_gramine(&attestation_type),
+				Err(err) => {
+					warn!("ATTESTATION BACKEND : {err}, quotes are verified as DCAP");
+					AttestationType::DCAP
+				},
+			};
+
+			info!("ATTESTATION BACKEND : quotes are verified with {:?}", attestation_type);
+			backend_of(attestation_type)
+		})
+		.as_ref()
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn attestation_backend_test() {
+		assert_eq!(AttestationType::from_gramine("epid\n"), AttestationType::EPID);
+		assert_eq!(AttestationType::from_gramine("dcap"), AttestationType::DCAP);
+		assert_eq!(AttestationType::from_gramine("none"), AttestationType::DCAP);
+
+		assert_eq!(backend_of(AttestationType::EPID).attestation_type(), AttestationType::EPID);
+		assert_eq!(backend_of(AttestationType::DCAP).attestation_type(), AttestationType::DCAP);
+	}
+}
@@ -0,0 +1,237 @@
+use std::{
+	collections::BTreeMap,
+	path::Path,
+	sync::{Mutex, OnceLock},
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+	attestation::verifier::{fetch_collateral, Collateral},
+	chain::constants::{
+		COLLATERAL_CACHE_FILE, COLLATERAL_GRACE_PERIOD, COLLATERAL_MAX_AGE,
+		MAX_COLLATERAL_CACHE_ENTRIES,
+	},
+	servers::metrics::record_collateral_fetch,
+};
+
+/* ---------------------------------------
+	COLLATERAL CACHE
+--------------------------------------- */
+
+// DCAP verifications must not fail when the PCCS is unreachable for a short time :
+// - the collateral of a platform is cached by FMSPC in the sealed area and reused until it is older
+//   than the max age, then it is fetched again
+// - when the fetch fails, the cached collateral is still used during the offline grace period after
+//   its max age, older collateral is not used and the verification fails
+// - the cache survives a restart, an enclave restarted during a PCCS outage still verifies its
+//   peers
+// The cached collateral is verified like a fetched one, its signature under the root CA and its
+// next update are checked for every quote, so a stale cache cannot accept an expired TCB info.
+
+static COLLATERAL_CACHE_CONFIG: OnceLock<CollateralCacheConfig> = OnceLock::new();
+static COLLATERAL_CACHE: Mutex<BTreeMap<String, CachedCollateral>> = Mutex::new(BTreeMap::new());
+
+/// Ages of the cached collateral
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct CollateralCacheConfig {
+	// Seconds before a cached collateral is fetched again, 0 fetches it for every quote
+	pub max_age: u64,
+	// Seconds after the max age during which the cached collateral is used if the fetch fails
+	pub grace_period: u64,
+}
+
+impl Default for CollateralCacheConfig {
+	fn default() -> Self {
+		CollateralCacheConfig { max_age: COLLATERAL_MAX_AGE, grace_period: COLLATERAL_GRACE_PERIOD }
+	}
+}
+
+/// Collateral of a platform with its fetch time
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CachedCollateral {
+	// Unix time of the fetch, in seconds
+	pub fetched_at: i64,
+	pub collateral: Collateral,
+}
+
+/// Age of a cached collateral against the configuration
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CacheAge {
+	// Younger than the max age, used without a fetch
+	FRESH,
+	// In the offline grace period, used only if the fetch fails
+	STALE,
+	// Older than the grace period, never used
+	EXPIRED,
+}
+
+impl CollateralCacheConfig {
+	/// Age of a collateral fetched at `fetched_at`
+	pub fn age(&self, fetched_at: i64, now: i64) -> CacheAge {
+		let age = now.saturating_sub(fetched_at).max(0) as u64;
+		if age < self.max_age {
+			CacheAge::FRESH
+		} else if age < self.max_age.saturating_add(self.grace_period) {
+			CacheAge::STALE
+		} else {
+			CacheAge::EXPIRED
+		}
+	}
+}
+
+/// Set the ages of the cached collateral from the command line
+pub fn set_collateral_cache_config(config: CollateralCacheConfig) -> Result<(), anyhow::Error> {
+	COLLATERAL_CACHE_CONFIG
+		.set(config)
+		.map_err(|_| anyhow::anyhow!("COLLATERAL CACHE : configuration is already set"))
+}
+
+/// Ages of the cached collateral
+pub fn collateral_cache_config() -> &'static CollateralCacheConfig {
+	COLLATERAL_CACHE_CONFIG.get_or_init(CollateralCacheConfig::default)
+}
+
+/// Platforms of which the collateral is cached
+pub fn collateral_cache_len() -> usize {
+	COLLATERAL_CACHE.lock().map(|cache| cache.len()).unwrap_or(0)
+}
+
+/// Load the sealed collateral cache at startup
+pub fn load_collateral_cache() -> Result<(), anyhow::Error> {
+	if !Path::new(COLLATERAL_CACHE_FILE).exists() {
+		debug!("COLLATERAL CACHE : no cache file, collateral is fetched on the first verification");
+		return Ok(())
+	}
+
+	// The cache is rebuilt from the PCCS, a corrupted file is not an error
+	let cached: BTreeMap<String, CachedCollateral> =
+		match serde_json::from_str(&std::fs::read_to_string(COLLATERAL_CACHE_FILE)?) {
+			Ok(cached) => cached,
+			Err(err) => {
+				warn!("COLLATERAL CACHE : {COLLATERAL_CACHE_FILE} is ignored : {err:?}");
+				return Ok(())
+			},
+		};
+	info!("COLLATERAL CACHE : collateral of {} platforms is loaded", cached.len());
+
+	let mut cache = COLLATERAL_CACHE
+		.lock()
+		.map_err(|err| anyhow::anyhow!("COLLATERAL CACHE : lock error : {err:?}"))?;
+	*cache = cached;
+
+	Ok(())
+}
+
+/// Cached collateral of a platform
+fn cached(fmspc: &str) -> Option<CachedCollateral> {
+	COLLATERAL_CACHE.lock().ok().and_then(|cache| cache.get(fmspc).cloned())
+}
+
+/// Cache a fetched collateral and write the sealed cache, the oldest platform is dropped when the
+/// cache is full
+fn store(fmspc: &str, entry: CachedCollateral) {
+	let mut cache = match COLLATERAL_CACHE.lock() {
+		Ok(cache) => cache,
+		Err(err) => {
+			error!("COLLATERAL CACHE : lock error : {err:?}");
+			return
+		},
+	};
+
+	cache.insert(fmspc.to_string(), entry);
+	while cache.len() > MAX_COLLATERAL_CACHE_ENTRIES {
+		let oldest = cache
+			.iter()
+			.min_by_key(|(_, cached)| cached.fetched_at)
+			.map(|(fmspc, _)| fmspc.clone());
+		match oldest {
+			Some(oldest) => cache.remove(&oldest),
+			None => break,
+		};
+	}
+
+	let result = serde_json::to_string(&*cache).map_err(anyhow::Error::from).and_then(|cache| {
+		std::fs::write(COLLATERAL_CACHE_FILE, cache).map_err(anyhow::Error::from)
+	});
+	if let Err(err) = result {
+		// The in-memory cache is still used until the enclave restarts
+		warn!("COLLATERAL CACHE : error writing {COLLATERAL_CACHE_FILE} : {err:?}");
+	}
+}
+
+/// Collateral of a platform, from the cache if it is fresh, fetched otherwise, and from the stale
+/// cache if the fetch fails during the offline grace period
+/// # Arguments
+/// * `client` - http client of the enclave
+/// * `pccs_url` - base url of the collateral
+/// * `fmspc` - FMSPC of the platform, hex encoded
+/// * `now` - unix time of the verification
+pub async fn cached_collateral(
+	client: &reqwest::Client,
+	pccs_url: &str,
+	fmspc: &str,
+	now: i64,
+) -> Result<Collateral, anyhow::Error> {
+	let config = collateral_cache_config();
+	let cached = cached(fmspc);
+
+	if let Some(cached) = &cached {
+		if config.age(cached.fetched_at, now) == CacheAge::FRESH {
+			record_collateral_fetch("cache_hit");
+			return Ok(cached.collateral.clone())
+		}
+	}
+
+	match fetch_collateral(client, pccs_url, fmspc).await {
+		Ok(collateral) => {
+			record_collateral_fetch("success");
+			store(fmspc, CachedCollateral { fetched_at: now, collateral: collateral.clone() });
+			Ok(collateral)
+		},
+
+		Err(err) => {
+			record_collateral_fetch("failure");
+
+			match cached {
+				Some(cached) if config.age(cached.fetched_at, now) == CacheAge::STALE => {
+					warn!(
+						"COLLATERAL CACHE : fetch of FMSPC {fmspc} failed, collateral of {} seconds ago is used : {err:?}",
+						now - cached.fetched_at
+					);
+					record_collateral_fetch("stale");
+					Ok(cached.collateral)
+				},
+				_ => Err(err),
+			}
+		},
+	}
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn collateral_cache_age_test() {
+		let config = CollateralCacheConfig { max_age: 3600, grace_period: 86400 };
+		assert_eq!(config.age(1000, 1000), CacheAge::FRESH);
+		assert_eq!(config.age(1000, 4599), CacheAge::FRESH);
+		assert_eq!(config.age(1000, 4600), CacheAge::STALE);
+		assert_eq!(config.age(1000, 4600 + 86399), CacheAge::STALE);
+		assert_eq!(config.age(1000, 4600 + 86400), CacheAge::EXPIRED);
+		// A clock set back does not expire the cache
+		assert_eq!(config.age(1000, 500), CacheAge::FRESH);
+
+		// No max age : always fetched, the cache is only an offline fallback
+		let config = CollateralCacheConfig { max_age: 0, grace_period: 600 };
+		assert_eq!(config.age(1000, 1000), CacheAge::STALE);
+		let config = CollateralCacheConfig { max_age: 0, grace_period: 0 };
+		assert_eq!(config.age(1000, 1000), CacheAge::EXPIRED);
+	}
+}
@@ -0,0 +1,200 @@
+use std::sync::OnceLock;
+
+use axum::{
+	http::{Request, StatusCode},
+	middleware::Next,
+	response::{IntoResponse, Response},
+	Json,
+};
+use serde::Serialize;
+use serde_json::json;
+use tracing::{debug, error};
+
+use crate::{
+	attestation::{
+		inspect::ATTRIBUTE_DEBUG,
+		ra::{gramine_mode, local_attributes, read_attestation_type, GramineMode},
+	},
+	servers::versioning::endpoint_path,
+};
+
+/* ---------------------------------------
+	ENCLAVE ENVIRONMENT
+--------------------------------------- */
+
+// Operators and auditors check how the enclave was launched without reading the host :
+// - the debug flag is read from the SGX attributes of the enclave, a debug enclave can be read and
+//   modified by the host, its secrets are not protected
+// - the enclave size, the thread count, the protected mounts and the Gramine version are set by the
+//   manifest as environment variables, so they are measured in the MRENCLAVE
+// A mainnet build launched as a debug enclave refuses the keyshare APIs, the keyshares must never
+// enter an enclave which the host can inspect.
+
+static ENCLAVE_ENVIRONMENT: OnceLock<EnclaveEnvironment> = OnceLock::new();
+
+// Set by the manifests, see gramine/sgx_server.manifest.template
+const ENCLAVE_SIZE_ENV: &str = "SGX_SERVER_ENCLAVE_SIZE";
+const MAX_THREADS_ENV: &str = "SGX_SERVER_MAX_THREADS";
+const PROTECTED_MOUNTS_ENV: &str = "SGX_SERVER_PROTECTED_MOUNTS";
+const GRAMINE_VERSION_ENV: &str = "SGX_SERVER_GRAMINE_VERSION";
+
+// Backup APIs which export or import keyshares, beside the secret-nft and capsule-nft APIs
+const KEYSHARE_BACKUP_APIS: [&str; 8] = [
+	"/api/backup/sync-",
+	"/api/backup/fetch-",
+	"/api/backup/push-",
+	"/api/backup/download",
+	"/api/backup/artifact",
+	"/api/backup/upload",
+	"/api/backup/recovery-key",
+	"/api/events/keyshares",
+];
+
+/// Encrypted mount of the manifest
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ProtectedMount {
+	pub path: String,
+	// Sealing key of the mount, i.e. "_sgx_mrenclave"
+	pub key_name: String,
+}
+
+/// Runtime environment of the enclave
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct EnclaveEnvironment {
+	pub gramine_mode: GramineMode,
+	// "none", "epid" or "dcap", None outside of SGX
+	pub attestation_type: Option<String>,
+	// SGX debug attribute, None outside of SGX
+	pub debug: Option<bool>,
+	pub enclave_size: Option<String>,
+	pub max_threads: Option<u32>,
+	pub protected_mounts: Vec<ProtectedMount>,
+	pub gramine_version: Option<String>,
+	pub mainnet: bool,
+	// The keyshare APIs are not served
+	pub keyshares_refused: bool,
+}
+
+/// Parse the protected mounts of the manifest, "PATH:KEYNAME" separated by commas
+pub fn parse_protected_mounts(mounts: &str) -> Vec<ProtectedMount> {
+	mounts
+		.split(',')
+		.filter_map(|mount| mount.trim().split_once(':'))
+		.map(|(path, key_name)| ProtectedMount {
+			path: path.to_string(),
+			key_name: key_name.to_string(),
+		})
+		.collect()
+}
+
+/// A mainnet build must not serve keyshares in a debug enclave, nor when the debug attribute can
+/// not be read
+pub fn refuses_keyshares(mainnet: bool, debug: Option<bool>) -> bool {
+	mainnet && debug != Some(false)
+}
+
+/// API which reads or writes keyshares
+/// # Arguments
+/// * `path` - unversioned path of the request
+pub fn is_keyshare_endpoint(path: &str) -> bool {
+	path.starts_with("/api/secret-nft/") ||
+		path.starts_with("/api/capsule-nft/") ||
+		KEYSHARE_BACKUP_APIS.iter().any(|api| path.starts_with(api))
+}
+
+fn manifest_env(name: &str) -> Option<String> {
+	std::env::var(name).ok().filter(|value| !value.trim().is_empty())
+}
+
+/// Runtime environment of the enclave, read once
+pub fn enclave_environment() -> &'static EnclaveEnvironment {
+	ENCLAVE_ENVIRONMENT.get_or_init(|| {
+		let mainnet = cfg!(feature = "mainnet");
+		let debug = local_attributes().map(|attributes| attributes[0] & ATTRIBUTE_DEBUG != 0);
+
+		let environment = EnclaveEnvironment {
+			gramine_mode: gramine_mode(),
+			attestation_type: read_attestation_type().ok(),
+			debug,
+			enclave_size: manifest_env(ENCLAVE_SIZE_ENV),
+			max_threads: manifest_env(MAX_THREADS_ENV).and_then(|threads| threads.parse().ok()),
+			protected_mounts: parse_protected_mounts(
+				&manifest_env(PROTECTED_MOUNTS_ENV).unwrap_or_default(),
+			),
+			gramine_version: manifest_env(GRAMINE_VERSION_ENV),
+			mainnet,
+			keyshares_refused: refuses_keyshares(mainnet, debug),
+		};
+
+		if environment.keyshares_refused {
+			let message =
+				"ENCLAVE ENVIRONMENT : mainnet build in a debug enclave, or of unknown attributes, keyshare APIs are refused"
+					.to_string();
+			error!(message);
+			sentry::capture_message(&message, sentry::Level::Error);
+		}
+
+		environment
+	})
+}
+
+/// Runtime environment of the enclave
+pub async fn get_environment() -> impl IntoResponse {
+	(StatusCode::OK, Json(enclave_environment().clone()))
+}
+
+/// Refuse the keyshare APIs of a mainnet build in a debug enclave
+pub async fn environment_guard<B>(request: Request<B>, next: Next<B>) -> Response {
+	let path = endpoint_path(&request);
+
+	if !enclave_environment().keyshares_refused || !is_keyshare_endpoint(&path) {
+		return next.run(request).await
+	}
+
+	debug!("ENCLAVE ENVIRONMENT : rejected {} in a debug enclave", path);
+
+	(
+		StatusCode::FORBIDDEN,
+		Json(json!({
+			"description": "Enclave is launched in SGX debug mode, keyshare APIs are disabled on mainnet.",
+		})),
+	)
+		.into_response()
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn enclave_environment_test() {
+		assert_eq!(
+			parse_protected_mounts("/keys:_sgx_mrenclave, /nft:storage"),
+			vec![
+				ProtectedMount {
+					path: "/keys".to_string(),
+					key_name: "_sgx_mrenclave".to_string()
+				},
+				ProtectedMount { path: "/nft".to_string(), key_name: "storage".to_string() },
+			]
+		);
+		assert!(parse_protected_mounts("").is_empty());
+
+		assert!(refuses_keyshares(true, Some(true)));
+		assert!(!refuses_keyshares(true, Some(false)));
+		assert!(!refuses_keyshares(false, Some(true)));
+		assert!(refuses_keyshares(true, None));
+		assert!(!refuses_keyshares(false, None));
+
+		assert!(is_keyshare_endpoint("/api/secret-nft/store-keyshare"));
+		assert!(is_keyshare_endpoint("/api/capsule-nft/retrieve-keyshare"));
+		assert!(is_keyshare_endpoint("/api/backup/sync-keyshare"));
+		assert!(is_keyshare_endpoint("/api/backup/push-bulk"));
+		assert!(!is_keyshare_endpoint("/api/health"));
+		assert!(!is_keyshare_endpoint("/api/backup/allowlist"));
+	}
+}
@@ -0,0 +1,253 @@
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use rand::RngCore;
+use ring::signature::{UnparsedPublicKey, RSA_PKCS1_2048_8192_SHA256};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::debug;
+
+use crate::{
+	attestation::{
+		backend::{AttestationType, AttestationVerdict},
+		verifier::{
+			accepts_debug_enclaves, current_time, is_debug_report, is_trusted_measurement,
+			pem_chain, verifier_config, verify_chain, ReportBody, TcbStatus,
+		},
+	},
+	chain::constants::{IAS_API_KEY_ENV, IAS_ROOT_CA_FILE},
+};
+
+/* ---------------------------------------
+	EPID ATTESTATION
+--------------------------------------- */
+
+// Legacy platforms only support EPID, their quotes have no certification data to verify in the
+// enclave. The Intel Attestation Service (IAS) verifies them instead :
+// - the quote is sent to IAS with a random nonce, with the subscription key of the operator
+// - the report is signed by the Intel attestation report signing certificate, its chain must end
+//   with the Intel attestation root CA, which is a trusted file of the manifest
+// - the report must have the nonce and the header and report body of the quote
+// - the quote status of the report gives the TCB status of the platform
+// The subscription key only authenticates the operator to IAS, a host which changes it can not
+// forge a signed report.
+
+// Length of the header of an EPID quote
+const EPID_HEADER_LENGTH: usize = 48;
+const EPID_REPORT_BODY_LENGTH: usize = 384;
+// Header and report body, returned as isvEnclaveQuoteBody
+const EPID_QUOTE_BODY_LENGTH: usize = EPID_HEADER_LENGTH + EPID_REPORT_BODY_LENGTH;
+const IAS_REPORT_VERSION: u32 = 4;
+
+/// Attestation report of IAS, the fields which are verified
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct IasReport {
+	pub id: String,
+	pub timestamp: String,
+	pub version: u32,
+	pub isv_enclave_quote_status: String,
+	// Base64 of the quote header and report body
+	pub isv_enclave_quote_body: String,
+	#[serde(default, rename = "advisoryIDs")]
+	pub advisory_ids: Vec<String>,
+	#[serde(default)]
+	pub nonce: Option<String>,
+}
+
+/// TCB status of an IAS quote status, i.e. "GROUP_OUT_OF_DATE"
+pub fn epid_tcb_status(quote_status: &str) -> TcbStatus {
+	match quote_status {
+		"OK" => TcbStatus::UPTODATE,
+		"SW_HARDENING_NEEDED" => TcbStatus::SWHARDENINGNEEDED,
+		"CONFIGURATION_NEEDED" => TcbStatus::CONFIGURATIONNEEDED,
+		"CONFIGURATION_AND_SW_HARDENING_NEEDED" => TcbStatus::CONFIGURATIONANDSWHARDENINGNEEDED,
+		"GROUP_OUT_OF_DATE" => TcbStatus::OUTOFDATE,
+		"GROUP_REVOKED" | "KEY_REVOKED" => TcbStatus::REVOKED,
+		_ => TcbStatus::UNRECOGNIZED,
+	}
+}
+
+/// Verify the signature of an IAS report
+/// # Arguments
+/// * `body` - json body of the report, as received
+/// * `signature` - base64 of the X-IASReport-Signature header
+/// * `signing_chain` - PEM chain of the X-IASReport-Signing-Certificate header, url decoded
+/// * `root_ca` - DER of the Intel attestation root CA
+/// * `now` - unix time of the verification
+pub fn verify_report_signature(
+	body: &[u8],
+	signature: &str,
+	signing_chain: &str,
+	root_ca: &[u8],
+	now: i64,
+) -> Result<(), anyhow::Error> {
+	let mut chain = pem_chain(signing_chain.as_bytes())?;
+	// IAS sends the signing certificate alone or followed by the root CA
+	if chain.last().map(Vec::as_slice) != Some(root_ca) {
+		chain.push(root_ca.to_vec());
+	}
+
+	let signing_key = verify_chain(&chain, root_ca, now)?;
+	let signature = STANDARD
+		.decode(signature.trim())
+		.map_err(|err| anyhow::anyhow!("EPID : report signature is not base64 : {err:?}"))?;
+
+	UnparsedPublicKey::new(&RSA_PKCS1_2048_8192_SHA256, signing_key)
+		.verify(body, &signature)
+		.map_err(|_| anyhow::anyhow!("EPID : invalid signature of the attestation report"))
+}
+
+/// Verdict of a verified IAS report
+/// # Arguments
+/// * `report` - attestation report, its signature is verified
+/// * `quote` - bytes of the EPID quote sent to IAS
+/// * `nonce` - nonce of the request
+pub fn epid_report_verdict(
+	report: &IasReport,
+	quote: &[u8],
+	nonce: &str,
+) -> Result<AttestationVerdict, anyhow::Error> {
+	if report.version != IAS_REPORT_VERSION {
+		return Err(anyhow::anyhow!("EPID : unsupported report version {}", report.version))
+	}
+
+	if report.nonce.as_deref() != Some(nonce) {
+		return Err(anyhow::anyhow!("EPID : report {} is not bound to the nonce", report.id))
+	}
+
+	let quote_body = STANDARD
+		.decode(&report.isv_enclave_quote_body)
+		.map_err(|err| anyhow::anyhow!("EPID : quote body is not base64 : {err:?}"))?;
+	if quote_body.len() != EPID_QUOTE_BODY_LENGTH ||
+		quote.get(..EPID_QUOTE_BODY_LENGTH) != Some(&quote_body[..])
+	{
+		return Err(anyhow::anyhow!("EPID : report {} is not the report of the quote", report.id))
+	}
+
+	let body = ReportBody::parse(&quote_body[EPID_HEADER_LENGTH..])?;
+	let mrenclave = hex::encode(body.mr_enclave);
+	let mrsigner = hex::encode(body.mr_signer);
+	let trusted_measurement = is_trusted_measurement(&mrenclave, &mrsigner);
+	let tcb_status = epid_tcb_status(&report.isv_enclave_quote_status);
+	let debug = is_debug_report(&body);
+
+	Ok(AttestationVerdict {
+		attestation_type: AttestationType::EPID,
+		accepted: trusted_measurement &&
+			(!debug || accepts_debug_enclaves()) &&
+			tcb_status.is_acceptable(),
+		mrenclave,
+		mrsigner,
+		isv_prod_id: body.isv_prod_id,
+		isv_svn: body.isv_svn,
+		report_data: hex::encode(body.report_data),
+		tcb_status,
+		advisory_ids: report.advisory_ids.clone(),
+		qe_tcb_status: None,
+		trusted_measurement,
+		debug,
+	})
+}
+
+/// DER of the Intel attestation root CA, a trusted file of the manifest
+pub fn load_ias_root_ca() -> Result<Vec<u8>, anyhow::Error> {
+	let pem = std::fs::read(IAS_ROOT_CA_FILE)?;
+	pem_chain(&pem)?
+		.into_iter()
+		.next()
+		.ok_or_else(|| anyhow::anyhow!("EPID : {IAS_ROOT_CA_FILE} has no certificate"))
+}
+
+/// Verdict of an EPID quote, with the report of the Intel Attestation Service
+/// # Arguments
+/// * `client` - http client of the enclave
+/// * `quote` - bytes of the EPID quote
+pub async fn ias_verdict(
+	client: &reqwest::Client,
+	quote: &[u8],
+) -> Result<AttestationVerdict, anyhow::Error> {
+	let root_ca = load_ias_root_ca()?;
+	let api_key = std::env::var(IAS_API_KEY_ENV)
+		.map_err(|_| anyhow::anyhow!("EPID : {IAS_API_KEY_ENV} is not set"))?;
+
+	let mut nonce = [0u8; 16];
+	rand::thread_rng().fill_bytes(&mut nonce);
+	let nonce = hex::encode(nonce);
+
+	let ias_url = &verifier_config().ias_url;
+	debug!("EPID : fetch the attestation report from {ias_url}");
+
+	let response = client
+		.post(format!("{ias_url}/report"))
+		.header("Ocp-Apim-Subscription-Key", api_key)
+		.json(&json!({ "isvEnclaveQuote": STANDARD.encode(quote), "nonce": nonce }))
+		.send()
+		.await?
+		.error_for_status()?;
+
+	let header = |name: &str| {
+		response
+			.headers()
+			.get(name)
+			.and_then(|value| value.to_str().ok())
+			.map(str::to_string)
+			.ok_or_else(|| anyhow::anyhow!("EPID : attestation report has no {name}"))
+	};
+	let signature = header("X-IASReport-Signature")?;
+	let signing_chain =
+		urlencoding::decode(&header("X-IASReport-Signing-Certificate")?)?.into_owned();
+
+	let body = response.bytes().await?;
+	verify_report_signature(&body, &signature, &signing_chain, &root_ca, current_time())?;
+
+	let report: IasReport = serde_json::from_slice(&body)?;
+	epid_report_verdict(&report, quote, &nonce)
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn epid_report_test() {
+		assert_eq!(epid_tcb_status("OK"), TcbStatus::UPTODATE);
+		assert_eq!(epid_tcb_status("SW_HARDENING_NEEDED"), TcbStatus::SWHARDENINGNEEDED);
+		assert_eq!(epid_tcb_status("GROUP_OUT_OF_DATE"), TcbStatus::OUTOFDATE);
+		assert_eq!(epid_tcb_status("KEY_REVOKED"), TcbStatus::REVOKED);
+		assert_eq!(epid_tcb_status("SIGNATURE_INVALID"), TcbStatus::UNRECOGNIZED);
+
+		// EPID quote : header, report body and signature
+		let mut quote = vec![0u8; EPID_QUOTE_BODY_LENGTH + 64];
+		quote[0] = 2;
+		quote[EPID_HEADER_LENGTH + 64..EPID_HEADER_LENGTH + 96].copy_from_slice(&[0xAAu8; 32]);
+		quote[EPID_HEADER_LENGTH + 128..EPID_HEADER_LENGTH + 160].copy_from_slice(&[0xBBu8; 32]);
+
+		let report: IasReport = serde_json::from_value(json!({
+			"id": "165171271757108173876306223827987629752",
+			"timestamp": "2023-10-18T08:00:00.000000",
+			"version": 4,
+			"isvEnclaveQuoteStatus": "SW_HARDENING_NEEDED",
+			"isvEnclaveQuoteBody": STANDARD.encode(&quote[..EPID_QUOTE_BODY_LENGTH]),
+			"advisoryIDs": ["INTEL-SA-00334"],
+			"nonce": "ab12",
+		}))
+		.unwrap();
+
+		let verdict = epid_report_verdict(&report, &quote, "ab12").unwrap();
+		assert_eq!(verdict.attestation_type, AttestationType::EPID);
+		assert_eq!(verdict.mrenclave, "aa".repeat(32));
+		assert_eq!(verdict.mrsigner, "bb".repeat(32));
+		assert_eq!(verdict.tcb_status, TcbStatus::SWHARDENINGNEEDED);
+		assert_eq!(verdict.advisory_ids, vec!["INTEL-SA-00334".to_string()]);
+
+		// The report is bound to the nonce and to the quote
+		assert!(epid_report_verdict(&report, &quote, "cd34").is_err());
+		quote[EPID_HEADER_LENGTH] = 1;
+		assert!(epid_report_verdict(&report, &quote, "ab12").is_err());
+		let report = IasReport { version: 3, ..report };
+		assert!(epid_report_verdict(&report, &quote, "ab12").is_err());
+	}
+}
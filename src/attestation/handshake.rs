@@ -0,0 +1,334 @@
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use hkdf::Hkdf;
+use rand::RngCore;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::debug;
+use x25519_dalek::{PublicKey, StaticSecret};
+use zeroize::Zeroizing;
+
+use crate::{
+	attestation::{
+		backend::AttestationVerdict,
+		history::{record_attestation, QuotePurpose},
+		ra::{generate_quote, QUOTE_REPORT_DATA_LENGTH, QUOTE_REPORT_DATA_OFFSET},
+		verifier::check_peer_quote,
+	},
+	chain::constants::MAX_VALIDATION_PERIOD,
+};
+
+/* ---------------------------------------
+	ATTESTED KEY EXCHANGE
+--------------------------------------- */
+
+// Secure channels between enclaves are keyed by an attested ECDH handshake :
+// - each side generates an ephemeral X25519 key and a quote whose report data is the public key,
+//   followed by SHA256("enclave-handshake_ACCOUNT_BLOCKNUMBER"), see handshake_report_data
+// - each side verifies the quote of the other with the quote verifier, and that its report data
+//   commits to the offered key, for the account and a recent block
+// - the session key is derived with HKDF-SHA256 from the shared secret, salted with both public
+//   keys and bound to the purpose of the channel
+// - the payloads are sealed with ChaCha20-Poly1305 and a random nonce
+// The private key never leaves the enclave memory, so only an attested enclave can open a sealed
+// payload. The binding of the report data is checked in every verification mode, the quote itself
+// is verified by the mode of the peer quotes, see check_peer_quote. An offer is not signed, so a
+// responder only seals secrets to an attested offer, see VerifiedOffer::is_attested, and keeps the
+// key of the signed request otherwise.
+
+// Response header of the handshake offer of the responder, base64 of the json offer
+pub const HANDSHAKE_HEADER: &str = "x-enclave-handshake";
+
+/// Channel of a session key, distinct keys per purpose
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum SessionPurpose {
+	SYNC,
+	RECOVERY,
+}
+
+impl SessionPurpose {
+	/// HKDF info string, distinct per purpose
+	pub fn info(&self) -> &'static str {
+		match self {
+			SessionPurpose::SYNC => "ternoa-enclave/sync-session/v1",
+			SessionPurpose::RECOVERY => "ternoa-enclave/recovery-session/v1",
+		}
+	}
+}
+
+/// Ephemeral key of a side of the handshake, with its quote
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HandshakeOffer {
+	// Account of the side, enclave account or ephemeral account of a new enclave
+	pub account: String,
+	pub block_number: u32,
+	// Hex encoded X25519 public key
+	pub public_key: String,
+	// Hex encoded quote, its report data is handshake_report_data
+	pub quote: String,
+}
+
+impl HandshakeOffer {
+	/// Value of the handshake header
+	pub fn to_header(&self) -> Result<String, anyhow::Error> {
+		Ok(STANDARD.encode(serde_json::to_vec(self)?))
+	}
+
+	/// Parse the value of the handshake header
+	pub fn from_header(header: &str) -> Result<HandshakeOffer, anyhow::Error> {
+		let offer = STANDARD
+			.decode(header.trim())
+			.map_err(|err| anyhow::anyhow!("HANDSHAKE : header is not base64 : {err:?}"))?;
+		Ok(serde_json::from_slice(&offer)?)
+	}
+}
+
+/// Offer of the other side, its quote and its report data are verified
+#[derive(Clone, Debug)]
+pub struct VerifiedOffer {
+	pub account: String,
+	public_key: [u8; 32],
+	// None if the quote is not verified by the verification mode
+	pub verdict: Option<AttestationVerdict>,
+}
+
+impl VerifiedOffer {
+	/// The quote is accepted by the quote verifier, not only bound to the key
+	pub fn is_attested(&self) -> bool {
+		matches!(&self.verdict, Some(verdict) if verdict.accepted)
+	}
+}
+
+/// Report data of a handshake quote
+/// # Arguments
+/// * `account` - account of the side
+/// * `block_number` - current block of the side
+/// * `public_key` - ephemeral X25519 public key
+pub fn handshake_report_data(
+	account: &str,
+	block_number: u32,
+	public_key: &[u8; 32],
+) -> [u8; QUOTE_REPORT_DATA_LENGTH] {
+	let mut report_data = [0u8; QUOTE_REPORT_DATA_LENGTH];
+	report_data[..32].copy_from_slice(public_key);
+	report_data[32..].copy_from_slice(&Sha256::digest(
+		format!("enclave-handshake_{account}_{block_number}").as_bytes(),
+	));
+	report_data
+}
+
+/// Side of an attested key exchange
+/// The key can be agreed with several peers, i.e. the enclaves of the clusters during a sync.
+pub struct Handshake {
+	secret: StaticSecret,
+	offer: HandshakeOffer,
+}
+
+impl Handshake {
+	/// Generate an ephemeral key and its quote
+	/// # Arguments
+	/// * `account` - account of this side
+	/// * `block_number` - current block
+	pub fn new(account: &str, block_number: u32) -> Result<Handshake, anyhow::Error> {
+		let mut seed = Zeroizing::new([0u8; 32]);
+		rand::rngs::OsRng.fill_bytes(&mut seed[..]);
+		let secret = StaticSecret::from(*seed);
+		let public_key = PublicKey::from(&secret).to_bytes();
+
+		let quote = generate_quote(&handshake_report_data(account, block_number, &public_key))
+			.map_err(|err| anyhow::anyhow!("HANDSHAKE : unable to create the quote : {err}"))?;
+		record_attestation(Some(block_number), QuotePurpose::HANDSHAKE, &quote);
+
+		Ok(Handshake {
+			secret,
+			offer: HandshakeOffer {
+				account: account.to_string(),
+				block_number,
+				public_key: hex::encode(public_key),
+				quote: hex::encode(quote),
+			},
+		})
+	}
+
+	/// Offer sent to the other side
+	pub fn offer(&self) -> &HandshakeOffer {
+		&self.offer
+	}
+
+	/// Session key with a verified peer
+	/// # Arguments
+	/// * `peer` - verified offer of the other side
+	/// * `purpose` - channel of the session
+	pub fn session_key(
+		&self,
+		peer: &VerifiedOffer,
+		purpose: SessionPurpose,
+	) -> Result<SessionKey, anyhow::Error> {
+		let public_key = PublicKey::from(&self.secret).to_bytes();
+		let shared = self.secret.diffie_hellman(&PublicKey::from(peer.public_key));
+
+		// Low order points give a shared secret known to anyone
+		if shared.as_bytes().iter().all(|byte| *byte == 0) {
+			return Err(anyhow::anyhow!("HANDSHAKE : peer key is a low order point"))
+		}
+
+		// Both sides get the same salt, whatever their role
+		let (first, second) = if public_key <= peer.public_key {
+			(public_key, peer.public_key)
+		} else {
+			(peer.public_key, public_key)
+		};
+		let salt = Sha256::digest([first, second].concat());
+
+		let hkdf = Hkdf::<Sha256>::new(Some(&salt), shared.as_bytes());
+		let mut key = Zeroizing::new([0u8; 32]);
+		hkdf.expand(purpose.info().as_bytes(), &mut key[..])
+			.map_err(|err| anyhow::anyhow!("HANDSHAKE : key derivation error : {err:?}"))?;
+
+		let key = UnboundKey::new(&CHACHA20_POLY1305, &key[..])
+			.map_err(|_| anyhow::anyhow!("HANDSHAKE : invalid session key"))?;
+
+		debug!("HANDSHAKE : {:?} session key with {}", purpose, peer.account);
+		Ok(SessionKey(LessSafeKey::new(key)))
+	}
+}
+
+/// Verify the offer of the other side
+/// # Arguments
+/// * `client` - http client of the enclave
+/// * `offer` - offer of the other side
+/// * `account` - expected account of the other side
+/// * `current_block` - current block of this side
+pub async fn verify_offer(
+	client: &reqwest::Client,
+	offer: &HandshakeOffer,
+	account: &str,
+	current_block: u32,
+) -> Result<VerifiedOffer, String> {
+	if offer.account != account {
+		return Err(format!("HANDSHAKE : offer of {} is not the offer of {account}", offer.account))
+	}
+
+	if offer.block_number + MAX_VALIDATION_PERIOD < current_block ||
+		offer.block_number > current_block + MAX_VALIDATION_PERIOD
+	{
+		return Err(format!("HANDSHAKE : offer of block {} is expired", offer.block_number))
+	}
+
+	let public_key: [u8; 32] = hex::decode(&offer.public_key)
+		.ok()
+		.and_then(|key| key.try_into().ok())
+		.ok_or_else(|| "HANDSHAKE : public key is not 32 hex encoded bytes".to_string())?;
+
+	let quote = hex::decode(offer.quote.trim_start_matches("0x"))
+		.map_err(|err| format!("HANDSHAKE : quote is not hex encoded : {err:?}"))?;
+	let report_data =
+		quote.get(QUOTE_REPORT_DATA_OFFSET..QUOTE_REPORT_DATA_OFFSET + QUOTE_REPORT_DATA_LENGTH);
+	if report_data != Some(&handshake_report_data(account, offer.block_number, &public_key)[..]) {
+		return Err(format!("HANDSHAKE : quote of {account} does not commit to its key"))
+	}
+
+	let verdict = check_peer_quote(client, account, &offer.quote).await?;
+
+	Ok(VerifiedOffer { account: account.to_string(), public_key, verdict })
+}
+
+/// Symmetric key of a session, from an attested handshake
+pub struct SessionKey(LessSafeKey);
+
+impl SessionKey {
+	/// Encrypt a payload, the random nonce is prepended to the ciphertext
+	pub fn seal(&self, data: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+		let mut nonce = [0u8; NONCE_LEN];
+		rand::rngs::OsRng.fill_bytes(&mut nonce);
+
+		let mut sealed = data.to_vec();
+		self.0
+			.seal_in_place_append_tag(
+				Nonce::assume_unique_for_key(nonce),
+				Aad::empty(),
+				&mut sealed,
+			)
+			.map_err(|_| anyhow::anyhow!("HANDSHAKE : encryption error"))?;
+
+		Ok([&nonce[..], &sealed].concat())
+	}
+
+	/// Decrypt a payload of seal
+	pub fn open(&self, data: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+		if data.len() < NONCE_LEN {
+			return Err(anyhow::anyhow!("HANDSHAKE : sealed payload is truncated"))
+		}
+
+		let (nonce, sealed) = data.split_at(NONCE_LEN);
+		let nonce = Nonce::try_assume_unique_for_key(nonce)
+			.map_err(|_| anyhow::anyhow!("HANDSHAKE : invalid nonce"))?;
+
+		let mut plain = sealed.to_vec();
+		let length = self
+			.0
+			.open_in_place(nonce, Aad::empty(), &mut plain)
+			.map_err(|_| anyhow::anyhow!("HANDSHAKE : payload is not sealed by the session key"))?
+			.len();
+		plain.truncate(length);
+
+		Ok(plain)
+	}
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn handshake(account: &str) -> (Handshake, VerifiedOffer) {
+		let secret = StaticSecret::from([account.len() as u8; 32]);
+		let public_key = PublicKey::from(&secret).to_bytes();
+		let offer = HandshakeOffer {
+			account: account.to_string(),
+			block_number: 1000,
+			public_key: hex::encode(public_key),
+			quote: String::new(),
+		};
+
+		let verified = VerifiedOffer { account: account.to_string(), public_key, verdict: None };
+		(Handshake { secret, offer }, verified)
+	}
+
+	#[test]
+	fn attested_handshake_test() {
+		let (initiator, initiator_offer) = handshake("initiator");
+		let (responder, responder_offer) = handshake("responder-enclave");
+
+		let initiator_key = initiator.session_key(&responder_offer, SessionPurpose::SYNC).unwrap();
+		let responder_key = responder.session_key(&initiator_offer, SessionPurpose::SYNC).unwrap();
+
+		let sealed = responder_key.seal(b"keyshares").unwrap();
+		assert_eq!(initiator_key.open(&sealed).unwrap(), b"keyshares".to_vec());
+		assert!(initiator_key.open(&sealed[..sealed.len() - 1]).is_err());
+		assert!(initiator_key.open(&sealed[..4]).is_err());
+
+		// Keys are bound to the purpose of the channel
+		let recovery_key =
+			initiator.session_key(&responder_offer, SessionPurpose::RECOVERY).unwrap();
+		assert!(recovery_key.open(&sealed).is_err());
+
+		// Low order points are rejected
+		assert!(!responder_offer.is_attested());
+		let low_order = VerifiedOffer { public_key: [0u8; 32], ..responder_offer };
+		assert!(initiator.session_key(&low_order, SessionPurpose::SYNC).is_err());
+
+		// Report data commits to the key, the account and the block
+		let public_key = [7u8; 32];
+		let report_data = handshake_report_data("account", 1000, &public_key);
+		assert_eq!(report_data[..32], public_key);
+		assert_ne!(report_data, handshake_report_data("account", 1001, &public_key));
+		assert_ne!(report_data, handshake_report_data("other", 1000, &public_key));
+
+		let offer = initiator.offer().clone();
+		assert_eq!(HandshakeOffer::from_header(&offer.to_header().unwrap()).unwrap(), offer);
+	}
+}
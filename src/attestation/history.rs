@@ -0,0 +1,424 @@
+use std::{
+	fs::OpenOptions,
+	io::Write,
+	sync::Mutex,
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+	extract::{Query, State},
+	http::StatusCode,
+	response::IntoResponse,
+	Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use subxt::ext::sp_core::Pair;
+use tracing::{debug, error, info};
+
+use crate::{
+	attestation::{
+		ra::{
+			QUOTE_MRENCLAVE_LENGTH, QUOTE_MRENCLAVE_OFFSET, QUOTE_MRSIGNER_LENGTH,
+			QUOTE_MRSIGNER_OFFSET,
+		},
+		verifier::TcbStatus,
+		watchdog::{quote_fingerprint, tcb_health},
+	},
+	chain::{
+		audit::{
+			read_audit_log, verify_audit_chain, AuditBreak, AuditHead, ChainedRecord,
+			AUDIT_GENESIS_HASH,
+		},
+		constants::{ATTESTATION_LOG_FILE, MAX_ATTESTATION_EXPORT},
+	},
+	servers::state::{get_accountid, get_keypair, SharedState},
+};
+
+/* ---------------------------------------
+	ATTESTATION HISTORY
+--------------------------------------- */
+
+// Auditors check that an enclave was attested during its whole operation, not only today :
+// - every quote generated by the enclave is recorded, with its hash, its measurements, its block
+//   and the TCB status of its platform from the quote watchdog
+// - the records are appended to a sealed json-lines file and hash chained like the verification
+//   audit trail, a removed or modified record breaks the chain
+// - the export returns a page of records with the head of the running enclave, signed by the
+//   enclave account, so a removed tail is detected too
+// The quotes themselves are not kept, the quote hash is compared with the quotes collected by the
+// auditor.
+
+static HISTORY_HEAD: Mutex<Option<AuditHead>> = Mutex::new(None);
+
+/// Report data of a recorded quote
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum QuotePurpose {
+	// Enclave account, api url and block, with the nonce of a caller
+	ENCLAVE,
+	// Token of a keyshare synchronization
+	SYNC,
+	// Token of a recovery key request
+	RECOVERY,
+	// Token of an upgrade handoff
+	UPGRADE,
+	// Key of the RA-TLS certificate
+	RATLS,
+	// Ephemeral key of an attested key exchange
+	HANDSHAKE,
+}
+
+/// Generated quote of the enclave
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AttestationRecord {
+	pub index: u64,
+	// None if the quote is not bound to a block
+	pub block_number: Option<u32>,
+	// Unix timestamp in seconds
+	pub timestamp: u64,
+	pub purpose: QuotePurpose,
+	// Hex encoded sha256 of the quote
+	pub quote_hash: String,
+	pub mrenclave: String,
+	pub mrsigner: String,
+	// Last TCB status of the platform of the quote, None before its evaluation
+	pub tcb_status: Option<TcbStatus>,
+	pub prev_hash: String,
+	pub hash: String,
+}
+
+impl ChainedRecord for AttestationRecord {
+	fn index(&self) -> u64 {
+		self.index
+	}
+
+	fn prev_hash(&self) -> &str {
+		&self.prev_hash
+	}
+
+	fn hash(&self) -> &str {
+		&self.hash
+	}
+
+	fn compute_hash(&self) -> String {
+		sha256::digest(
+			format!(
+				"{}_{}_{:?}_{}_{:?}_{}_{}_{}_{:?}",
+				self.prev_hash,
+				self.index,
+				self.block_number,
+				self.timestamp,
+				self.purpose,
+				self.quote_hash,
+				self.mrenclave,
+				self.mrsigner,
+				self.tcb_status
+			)
+			.as_bytes(),
+		)
+	}
+}
+
+impl AttestationRecord {
+	/// Record of a quote, linked to the head of the history
+	/// # Arguments
+	/// * `head` - head of the history, moved to the new record
+	/// * `block_number` - block of the report data, None if the quote is not bound to a block
+	/// * `timestamp` - unix time of the quote
+	/// * `purpose` - report data of the quote
+	/// * `quote` - bytes of the quote
+	/// * `tcb_status` - last TCB status of the platform
+	pub fn append(
+		head: &mut AuditHead,
+		block_number: Option<u32>,
+		timestamp: u64,
+		purpose: QuotePurpose,
+		quote: &[u8],
+		tcb_status: Option<TcbStatus>,
+	) -> AttestationRecord {
+		let field = |offset: usize, length: usize| {
+			quote.get(offset..offset + length).map(hex::encode).unwrap_or_default()
+		};
+
+		let mut record = AttestationRecord {
+			index: head.next_index,
+			block_number,
+			timestamp,
+			purpose,
+			quote_hash: sha256::digest(quote),
+			mrenclave: field(QUOTE_MRENCLAVE_OFFSET, QUOTE_MRENCLAVE_LENGTH),
+			mrsigner: field(QUOTE_MRSIGNER_OFFSET, QUOTE_MRSIGNER_LENGTH),
+			tcb_status,
+			prev_hash: head.last_hash.clone(),
+			hash: String::new(),
+		};
+		record.hash = record.compute_hash();
+
+		head.next_index += 1;
+		head.last_hash = record.hash.clone();
+
+		record
+	}
+}
+
+/// Message signed by the enclave account for an exported page of the history
+/// "attestation-history_NEXTINDEX_LASTHASH_PAGEHASH", the page hash is the hash of its last record
+pub fn history_head_message(head: &AuditHead, page_hash: &str) -> String {
+	format!("attestation-history_{}_{}_{}", head.next_index, head.last_hash, page_hash)
+}
+
+/// Load the head of the attestation history at startup, a broken chain is reported and the new
+/// records are linked to the last record of the file
+pub fn load_attestation_history() -> Result<(), anyhow::Error> {
+	let records: Vec<AttestationRecord> = read_audit_log(ATTESTATION_LOG_FILE)?;
+
+	let head = match verify_audit_chain(&records) {
+		Ok(head) => head,
+		Err(chain_break) => {
+			let message = format!(
+				"ATTESTATION HISTORY : chain is broken at record {} : {}",
+				chain_break.index, chain_break.description
+			);
+			error!(message);
+			sentry::capture_message(&message, sentry::Level::Error);
+
+			match records.last() {
+				Some(last) =>
+					AuditHead { next_index: last.index + 1, last_hash: last.hash.clone() },
+				None => AuditHead::default(),
+			}
+		},
+	};
+
+	info!("ATTESTATION HISTORY : {} records are loaded", head.next_index);
+
+	let mut current = HISTORY_HEAD
+		.lock()
+		.map_err(|err| anyhow::anyhow!("ATTESTATION HISTORY : lock error : {err:?}"))?;
+	*current = Some(head);
+
+	Ok(())
+}
+
+/// Append a generated quote to the sealed attestation history
+/// # Arguments
+/// * `block_number` - block of the report data, None if the quote is not bound to a block
+/// * `purpose` - report data of the quote
+/// * `quote` - bytes of the quote
+pub fn record_attestation(block_number: Option<u32>, purpose: QuotePurpose, quote: &[u8]) {
+	// Status of the last evaluation of the same platform TCB and measurements
+	let fingerprint = quote_fingerprint(quote);
+	let tcb_status = tcb_health()
+		.filter(|health| Some(&health.fingerprint) == fingerprint.as_ref())
+		.and_then(|health| health.tcb_status);
+
+	let timestamp = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|elapsed| elapsed.as_secs())
+		.unwrap_or(0);
+
+	// Append while holding the lock, to keep file and head in the same order
+	let mut current = match HISTORY_HEAD.lock() {
+		Ok(current) => current,
+		Err(err) => {
+			error!("ATTESTATION HISTORY : lock error : {err:?}");
+			return
+		},
+	};
+
+	let mut head =
+		match current.as_ref() {
+			Some(head) => head.clone(),
+			// Quotes before the history is loaded at startup, i.e. of an upgrade handoff, are not
+			// recorded
+			None => {
+				debug!("ATTESTATION HISTORY : history is not loaded, {purpose:?} quote is not recorded");
+				return
+			},
+		};
+
+	let record =
+		AttestationRecord::append(&mut head, block_number, timestamp, purpose, quote, tcb_status);
+
+	let line = match serde_json::to_string(&record) {
+		Ok(line) => line,
+		Err(err) => {
+			error!("ATTESTATION HISTORY : error serializing record {} : {err:?}", record.index);
+			return
+		},
+	};
+
+	let written = OpenOptions::new()
+		.create(true)
+		.append(true)
+		.open(ATTESTATION_LOG_FILE)
+		.and_then(|mut file| writeln!(file, "{line}").and_then(|_| file.sync_data()));
+
+	match written {
+		Ok(_) => {
+			debug!("ATTESTATION HISTORY : record {} {:?} quote", record.index, record.purpose);
+			*current = Some(head);
+		},
+		Err(err) => {
+			let message = format!("ATTESTATION HISTORY : error writing record : {err:?}");
+			error!(message);
+			sentry::capture_message(&message, sentry::Level::Error);
+		},
+	}
+}
+
+/* ---------------------------------------
+	HISTORY EXPORT
+--------------------------------------- */
+
+#[derive(Deserialize, Debug)]
+pub struct HistoryQuery {
+	// First exported record
+	#[serde(default)]
+	from_index: u64,
+}
+
+/// Export a page of the attestation history, signed by the enclave account
+/// # Arguments
+/// * `state` - SharedState
+/// * `query` - HistoryQuery
+/// # Returns
+/// * `Json` - records from `from_index`, the head of the running enclave, the signature of the head
+///   and the page, and the first broken record if the chain is not valid
+pub async fn attestation_history(
+	State(state): State<SharedState>,
+	Query(query): Query<HistoryQuery>,
+) -> impl IntoResponse {
+	// Records are appended under the head lock, the file and the head are read under the same lock
+	let read =
+		HISTORY_HEAD
+			.lock()
+			.map_err(|err| format!("lock error : {err:?}"))
+			.and_then(|current| {
+				let records: Vec<AttestationRecord> =
+					read_audit_log(ATTESTATION_LOG_FILE).map_err(|err| format!("{err:?}"))?;
+				Ok((records, current.clone().unwrap_or_default()))
+			});
+
+	let (records, enclave_head) = match read {
+		Ok(read) => read,
+		Err(err) => {
+			let message = format!("ATTESTATION HISTORY : error reading the history : {err}");
+			error!(message);
+			return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })))
+		},
+	};
+
+	let broken_at = match verify_audit_chain(&records) {
+		Ok(head) if head == enclave_head => None,
+		Ok(head) => Some(AuditBreak {
+			index: head.next_index,
+			description: format!(
+				"history ends at record {}, enclave head is at record {}",
+				head.next_index, enclave_head.next_index
+			),
+		}),
+		Err(chain_break) => Some(chain_break),
+	};
+
+	let exported: Vec<_> = records
+		.into_iter()
+		.skip_while(|record| record.index < query.from_index)
+		.take(MAX_ATTESTATION_EXPORT)
+		.collect();
+	let next_index = exported
+		.last()
+		.map(|record| record.index + 1)
+		.filter(|index| *index < enclave_head.next_index);
+
+	let page_hash = exported
+		.last()
+		.map(|record| record.hash.clone())
+		.unwrap_or_else(|| AUDIT_GENESIS_HASH.to_string());
+	let signature = get_keypair(&state)
+		.await
+		.sign(history_head_message(&enclave_head, &page_hash).as_bytes());
+
+	debug!("ATTESTATION HISTORY : {} records exported from {}", exported.len(), query.from_index);
+
+	(
+		StatusCode::OK,
+		Json(json!({
+			"enclave_account": get_accountid(&state).await,
+			"head": enclave_head,
+			// Signature of "attestation-history_NEXTINDEX_LASTHASH_PAGEHASH" by the enclave account
+			"signature": format!("0x{}", hex::encode(signature.0)),
+			"verified": broken_at.is_none(),
+			"broken_at": broken_at,
+			"records": exported,
+			// Index of the next page, null if the export is complete
+			"next_index": next_index,
+		})),
+	)
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::attestation::ra::QUOTE_REPORT_DATA_OFFSET;
+
+	#[test]
+	fn attestation_history_test() {
+		let mut quote = vec![0u8; QUOTE_REPORT_DATA_OFFSET + 64];
+		quote[QUOTE_MRENCLAVE_OFFSET..QUOTE_MRENCLAVE_OFFSET + 32].copy_from_slice(&[0xAAu8; 32]);
+		quote[QUOTE_MRSIGNER_OFFSET..QUOTE_MRSIGNER_OFFSET + 32].copy_from_slice(&[0xBBu8; 32]);
+
+		let mut head = AuditHead::default();
+		let mut records = vec![
+			AttestationRecord::append(
+				&mut head,
+				Some(100),
+				1_700_000_000,
+				QuotePurpose::ENCLAVE,
+				&quote,
+				None,
+			),
+			AttestationRecord::append(
+				&mut head,
+				None,
+				1_700_000_060,
+				QuotePurpose::RATLS,
+				&quote,
+				Some(TcbStatus::UPTODATE),
+			),
+			AttestationRecord::append(
+				&mut head,
+				Some(110),
+				1_700_000_120,
+				QuotePurpose::SYNC,
+				&quote,
+				Some(TcbStatus::SWHARDENINGNEEDED),
+			),
+		];
+
+		assert_eq!(records[0].mrenclave, "aa".repeat(32));
+		assert_eq!(records[0].mrsigner, "bb".repeat(32));
+		assert_eq!(records[0].quote_hash, sha256::digest(&quote[..]));
+		assert_eq!(records[0].prev_hash, AUDIT_GENESIS_HASH);
+		assert_eq!(records[2].prev_hash, records[1].hash);
+		assert_eq!(verify_audit_chain(&records), Ok(head.clone()));
+
+		// Modified TCB status
+		let mut modified = records.clone();
+		modified[2].tcb_status = Some(TcbStatus::UPTODATE);
+		assert_eq!(verify_audit_chain(&modified).unwrap_err().index, 2);
+
+		// Removed record
+		records.remove(0);
+		assert_eq!(verify_audit_chain(&records).unwrap_err().index, 0);
+
+		assert_ne!(
+			history_head_message(&head, &records[0].hash),
+			history_head_message(&head, &records[1].hash)
+		);
+	}
+}
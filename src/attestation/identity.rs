@@ -0,0 +1,221 @@
+use std::collections::BTreeMap;
+
+use axum::{
+	extract::State,
+	http::{HeaderMap, StatusCode},
+	response::{IntoResponse, Response},
+	Json,
+};
+use serde::Serialize;
+use subxt::ext::sp_core::{sr25519, Pair};
+use tracing::error;
+
+use crate::{
+	attestation::{
+		keys::KeyPurpose,
+		ra::{create_quote, local_mrenclave, local_mrsigner, ReportDataPreimage},
+	},
+	servers::{
+		etag::{if_none_match, not_modified, with_entity_tag},
+		state::{
+			get_accountid, get_blocknumber, get_identity, get_keypair, get_subkeys, get_version,
+			SharedState,
+		},
+	},
+};
+
+/* ---------------------------------------
+	ENCLAVE IDENTITY BUNDLE
+--------------------------------------- */
+
+// SDKs verify an enclave before sending it keyshares, the identity bundle gives them every piece
+// in one call instead of the quote, health and capabilities responses :
+// - the enclave account, its public key, the measurements and the cluster slot of the enclave
+// - a fresh quote, of which the report data binds the enclave account, the api url and the block,
+//   with its preimage
+// - the subkeys and their certificate, i.e. the key of the signed keyshare responses
+// - the signature of the bundle by the enclave account, see IdentityBundle::signing_message
+// The bundle of a block is tagged like the quote, an unchanged block and cluster slot returns
+// "304 Not Modified" before a new quote is generated.
+
+/// Identity of the enclave, signed by the enclave account
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct IdentityBundle {
+	pub enclave_account: String,
+	// 0x prefixed sr25519 public key of the enclave account
+	pub public_key: String,
+	// Measurements, None outside of an enclave
+	pub mrenclave: Option<String>,
+	pub mrsigner: Option<String>,
+	// Cluster slot of the enclave, None until it is registered onchain
+	pub cluster_id: Option<u32>,
+	pub slot_id: Option<u32>,
+	pub version: String,
+	pub subkeys: BTreeMap<KeyPurpose, String>,
+	pub subkeys_certificate: String,
+	// Block of the report data of the quote
+	pub block_number: u32,
+	pub quote: Option<String>,
+	// Preimage of the report data of the quote, see ReportDataPreimage
+	pub report_data_preimage: ReportDataPreimage,
+	pub quote_error: Option<String>,
+	pub signature: String,
+}
+
+impl IdentityBundle {
+	/// Message signed by the enclave account, the quote is bound by its hash
+	/// "enclave-identity_ACCOUNT_PUBLICKEY_MRENCLAVE_MRSIGNER_CLUSTERID_SLOTID_BLOCKNUMBER_SHA256(QUOTE)"
+	/// with an empty field for a missing value
+	pub fn signing_message(&self) -> String {
+		let optional =
+			|value: Option<u32>| value.map(|value| value.to_string()).unwrap_or_default();
+		let quote_hash = self
+			.quote
+			.as_ref()
+			.and_then(|quote| hex::decode(quote).ok())
+			.map(|quote| sha256::digest(&quote[..]))
+			.unwrap_or_default();
+
+		format!(
+			"enclave-identity_{}_{}_{}_{}_{}_{}_{}_{}",
+			self.enclave_account,
+			self.public_key,
+			self.mrenclave.clone().unwrap_or_default(),
+			self.mrsigner.clone().unwrap_or_default(),
+			optional(self.cluster_id),
+			optional(self.slot_id),
+			self.block_number,
+			quote_hash,
+		)
+	}
+
+	/// Sign the bundle with the enclave account
+	pub fn sign(mut self, enclave_key: &sr25519::Pair) -> IdentityBundle {
+		let signature = enclave_key.sign(self.signing_message().as_bytes());
+		self.signature = format!("0x{}", hex::encode(signature.0));
+		self
+	}
+}
+
+/// Entity tag of the identity bundle of a block
+/// # Arguments
+/// * `enclave_id` - account of the enclave
+/// * `identity` - cluster slot of the enclave
+/// * `block_number` - block of the report data
+pub fn identity_etag(enclave_id: &str, identity: Option<(u32, u32)>, block_number: u32) -> String {
+	let (cluster_id, slot_id) = match identity {
+		Some((cluster_id, slot_id)) => (cluster_id.to_string(), slot_id.to_string()),
+		None => (String::new(), String::new()),
+	};
+
+	format!("W/\"identity-{enclave_id}-{cluster_id}-{slot_id}-{block_number}\"")
+}
+
+/// Signed identity bundle of the enclave, a client which already has the bundle of the current
+/// block gets "304 Not Modified"
+pub async fn enclave_identity(State(state): State<SharedState>, headers: HeaderMap) -> Response {
+	let enclave_account = get_accountid(&state).await;
+	let identity = get_identity(&state).await;
+	let current_etag = identity_etag(&enclave_account, identity, get_blocknumber(&state).await);
+	if if_none_match(&headers, &current_etag) {
+		return not_modified(&current_etag)
+	}
+
+	let enclave_key = get_keypair(&state).await;
+	let subkeys = get_subkeys(&state).await;
+	let (preimage, quote) = create_quote(&state).await;
+	let block_number = preimage.block_number;
+
+	let (quote, quote_error) = match quote {
+		Ok(quote) => (Some(hex::encode(quote)), None),
+		Err(err) => {
+			error!("IDENTITY : unable to create the quote : {err}");
+			(None, Some(err.to_string()))
+		},
+	};
+
+	let bundle = IdentityBundle {
+		enclave_account: enclave_account.clone(),
+		public_key: format!("0x{}", hex::encode(enclave_key.public().0)),
+		mrenclave: local_mrenclave(),
+		mrsigner: local_mrsigner(),
+		cluster_id: identity.map(|(cluster_id, _)| cluster_id),
+		slot_id: identity.map(|(_, slot_id)| slot_id),
+		version: get_version(&state).await,
+		subkeys: subkeys.public_keys(),
+		subkeys_certificate: subkeys.certificate(),
+		block_number,
+		quote,
+		report_data_preimage: preimage,
+		quote_error,
+		signature: String::new(),
+	}
+	.sign(&enclave_key);
+
+	// A bundle without quote is not cached, the client asks again
+	if bundle.quote.is_none() {
+		return (StatusCode::OK, Json(bundle)).into_response()
+	}
+
+	with_entity_tag(
+		(StatusCode::OK, Json(bundle)).into_response(),
+		&identity_etag(&enclave_account, identity, block_number),
+	)
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::attestation::keys::EnclaveSubkeys;
+
+	#[test]
+	fn identity_bundle_test() {
+		let enclave_key = sr25519::Pair::from_seed(&[7u8; 32]);
+		let subkeys = EnclaveSubkeys::derive(&enclave_key);
+
+		let bundle = IdentityBundle {
+			enclave_account: "5C4hrfjw9DjXZTzV3MwzrrAr9P1MJhSrvWGWqi1eSuyUpnhM".to_string(),
+			public_key: format!("0x{}", hex::encode(enclave_key.public().0)),
+			mrenclave: Some("aa".repeat(32)),
+			mrsigner: Some("bb".repeat(32)),
+			cluster_id: Some(1),
+			slot_id: Some(2),
+			version: "0.4.4".to_string(),
+			subkeys: subkeys.public_keys(),
+			subkeys_certificate: subkeys.certificate(),
+			block_number: 1000,
+			quote: Some(hex::encode([3u8; 64])),
+			report_data_preimage: ReportDataPreimage::new(
+				&enclave_key.public(),
+				"https://enclave.ternoa.network",
+				1000,
+			),
+			quote_error: None,
+			signature: String::new(),
+		}
+		.sign(&enclave_key);
+
+		let signature = hex::decode(bundle.signature.trim_start_matches("0x")).unwrap();
+		let signature = sr25519::Signature::from_slice(&signature).unwrap();
+		assert!(sr25519::Pair::verify(&signature, bundle.signing_message(), &enclave_key.public()));
+
+		// The quote and the cluster slot are bound to the signature
+		let other = IdentityBundle { quote: Some(hex::encode([4u8; 64])), ..bundle.clone() };
+		assert!(!sr25519::Pair::verify(&signature, other.signing_message(), &enclave_key.public()));
+		let other = IdentityBundle { slot_id: Some(3), ..bundle.clone() };
+		assert!(!sr25519::Pair::verify(&signature, other.signing_message(), &enclave_key.public()));
+
+		assert_ne!(
+			identity_etag(&bundle.enclave_account, Some((1, 2)), 1000),
+			identity_etag(&bundle.enclave_account, Some((1, 3)), 1000)
+		);
+		assert_ne!(
+			identity_etag(&bundle.enclave_account, None, 1000),
+			identity_etag(&bundle.enclave_account, None, 1001)
+		);
+	}
+}
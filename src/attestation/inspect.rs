@@ -0,0 +1,244 @@
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{debug, warn};
+
+use crate::attestation::{
+	ra::MAX_QUOTE_SIZE,
+	verifier::{
+		parse_quote, pck_platform_tcb, PlatformTcb, ReportBody, CERTIFICATION_PCK_CHAIN,
+		QUOTE_HEADER_LENGTH, QUOTE_VERSION, REPORT_BODY_LENGTH,
+	},
+};
+
+/* ---------------------------------------
+	QUOTE INSPECTION
+--------------------------------------- */
+
+// Operators debug a rejected quote, i.e. a MRENCLAVE mismatch after a rebuild or an out of date
+// platform, without external tooling :
+// - the header, the report body and the signature data of a quote are decoded, nothing is verified
+// - version 3 quotes are ECDSA (DCAP), their signature data has the QE report and the PCK
+//   certificate, whose SGX extension gives the FMSPC and the TCB components of the platform
+// - version 2 quotes are EPID, only their header and report body are decoded
+// A decoded quote is not trusted, the verification is done by the attestation backend.
+
+// EPID quotes of legacy platforms
+const EPID_QUOTE_VERSION: u16 = 2;
+// ATTRIBUTES.FLAGS of the report body
+pub const ATTRIBUTE_DEBUG: u8 = 0x02;
+const ATTRIBUTE_MODE64BIT: u8 = 0x04;
+
+/// Decoded header of a quote
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct QuoteHeaderInfo {
+	pub version: u16,
+	// ECDSA key type of a version 3 quote, signature type of an EPID quote
+	pub attestation_key_type: u16,
+	// Hex encoded bytes 4 to 8, TEE type of a version 3 quote
+	pub tee_type: String,
+	// Hex encoded bytes 12 to 28, QE vendor id of a version 3 quote
+	pub qe_vendor_id: String,
+}
+
+/// Decoded report body of an enclave
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ReportBodyInfo {
+	pub mrenclave: String,
+	pub mrsigner: String,
+	pub isv_prod_id: u16,
+	pub isv_svn: u16,
+	// CPUSVN of the platform, the 16 SGX TCB components
+	pub cpu_svn: String,
+	pub misc_select: u32,
+	pub attributes: String,
+	pub debug: bool,
+	pub mode64bit: bool,
+	pub report_data: String,
+}
+
+impl From<&ReportBody> for ReportBodyInfo {
+	fn from(report: &ReportBody) -> Self {
+		ReportBodyInfo {
+			mrenclave: hex::encode(report.mr_enclave),
+			mrsigner: hex::encode(report.mr_signer),
+			isv_prod_id: report.isv_prod_id,
+			isv_svn: report.isv_svn,
+			cpu_svn: hex::encode(report.cpu_svn),
+			misc_select: report.misc_select,
+			attributes: hex::encode(report.attributes),
+			debug: report.attributes[0] & ATTRIBUTE_DEBUG != 0,
+			mode64bit: report.attributes[0] & ATTRIBUTE_MODE64BIT != 0,
+			report_data: hex::encode(report.report_data),
+		}
+	}
+}
+
+/// Decoded signature data of an ECDSA quote
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct SignatureInfo {
+	pub attestation_key: String,
+	// Report body of the quoting enclave
+	pub qe_report: ReportBodyInfo,
+	pub certification_data_type: u16,
+	pub certification_data_length: usize,
+	// Platform TCB of the PCK certificate, None for another certification data type
+	pub platform_tcb: Option<PlatformTcb>,
+	// Why the PCK certificate could not be decoded
+	pub platform_tcb_error: Option<String>,
+}
+
+/// Decoded quote
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct QuoteInspection {
+	pub size: usize,
+	// Hex encoded sha256 of the quote
+	pub quote_hash: String,
+	pub header: QuoteHeaderInfo,
+	pub report: ReportBodyInfo,
+	// None for an EPID quote
+	pub signature: Option<SignatureInfo>,
+}
+
+/// Decode the header, the report body and the signature data of a quote, without verifying it
+/// # Arguments
+/// * `quote` - bytes of an ECDSA or EPID quote
+pub fn inspect_quote(quote: &[u8]) -> Result<QuoteInspection, anyhow::Error> {
+	let header_bytes = quote.get(..QUOTE_HEADER_LENGTH).ok_or_else(|| {
+		anyhow::anyhow!("QUOTE INSPECT : quote of {} bytes has no header", quote.len())
+	})?;
+
+	let header = QuoteHeaderInfo {
+		version: u16::from_le_bytes([header_bytes[0], header_bytes[1]]),
+		attestation_key_type: u16::from_le_bytes([header_bytes[2], header_bytes[3]]),
+		tee_type: hex::encode(&header_bytes[4..8]),
+		qe_vendor_id: hex::encode(&header_bytes[12..28]),
+	};
+
+	let (report, signature) = match header.version {
+		QUOTE_VERSION => {
+			let dcap_quote = parse_quote(quote)?;
+
+			let (platform_tcb, platform_tcb_error) =
+				if dcap_quote.certification_data_type == CERTIFICATION_PCK_CHAIN {
+					match pck_platform_tcb(&dcap_quote.certification_data) {
+						Ok(platform_tcb) => (Some(platform_tcb), None),
+						Err(err) => (None, Some(err.to_string())),
+					}
+				} else {
+					(None, None)
+				};
+
+			let signature = SignatureInfo {
+				attestation_key: hex::encode(dcap_quote.attestation_key),
+				qe_report: ReportBodyInfo::from(&dcap_quote.qe_report),
+				certification_data_type: dcap_quote.certification_data_type,
+				certification_data_length: dcap_quote.certification_data.len(),
+				platform_tcb,
+				platform_tcb_error,
+			};
+
+			(ReportBodyInfo::from(&dcap_quote.report), Some(signature))
+		},
+
+		EPID_QUOTE_VERSION => {
+			let body = quote
+				.get(QUOTE_HEADER_LENGTH..QUOTE_HEADER_LENGTH + REPORT_BODY_LENGTH)
+				.ok_or_else(|| anyhow::anyhow!("QUOTE INSPECT : EPID quote has no report body"))?;
+			(ReportBodyInfo::from(&ReportBody::parse(body)?), None)
+		},
+
+		version =>
+			return Err(anyhow::anyhow!("QUOTE INSPECT : unsupported quote version {version}")),
+	};
+
+	Ok(QuoteInspection {
+		size: quote.len(),
+		quote_hash: sha256::digest(quote),
+		header,
+		report,
+		signature,
+	})
+}
+
+/// Quote to decode
+#[derive(Deserialize, Debug)]
+pub struct InspectRequest {
+	// Hex encoded quote, with or without 0x prefix
+	pub quote: String,
+}
+
+/// Decode a quote for the operators, see inspect_quote
+/// # Arguments
+/// * `request` - InspectRequest
+pub async fn attest_inspect(Json(request): Json<InspectRequest>) -> impl IntoResponse {
+	let quote = request.quote.trim().trim_start_matches("0x");
+	if quote.len() > 2 * MAX_QUOTE_SIZE {
+		let message = format!("QUOTE INSPECT : quote is larger than {MAX_QUOTE_SIZE} bytes");
+		warn!(message);
+		return (StatusCode::PAYLOAD_TOO_LARGE, Json(json!({ "error": message })))
+	}
+
+	let inspection = hex::decode(quote)
+		.map_err(|err| anyhow::anyhow!("QUOTE INSPECT : quote is not hex encoded : {err:?}"))
+		.and_then(|quote| inspect_quote(&quote));
+
+	match inspection {
+		Ok(inspection) => {
+			debug!(
+				"QUOTE INSPECT : quote {} of MRENCLAVE {}",
+				inspection.quote_hash, inspection.report.mrenclave
+			);
+			(StatusCode::OK, Json(json!(inspection)))
+		},
+		Err(err) => {
+			let message = err.to_string();
+			warn!(message);
+			(StatusCode::BAD_REQUEST, Json(json!({ "error": message })))
+		},
+	}
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn inspect_quote_test() {
+		let mut report = vec![0u8; REPORT_BODY_LENGTH];
+		report[..16].copy_from_slice(&[7u8; 16]);
+		report[48] = ATTRIBUTE_DEBUG | ATTRIBUTE_MODE64BIT;
+		report[64..96].copy_from_slice(&[0xAAu8; 32]);
+		report[128..160].copy_from_slice(&[0xBBu8; 32]);
+		report[256..258].copy_from_slice(&12u16.to_le_bytes());
+		report[258..260].copy_from_slice(&103u16.to_le_bytes());
+
+		// EPID quote : header, report body and signature
+		let mut quote = vec![0u8; QUOTE_HEADER_LENGTH];
+		quote[..2].copy_from_slice(&EPID_QUOTE_VERSION.to_le_bytes());
+		quote.extend_from_slice(&report);
+		quote.extend_from_slice(&[0u8; 64]);
+
+		let inspection = inspect_quote(&quote).unwrap();
+		assert_eq!(inspection.header.version, EPID_QUOTE_VERSION);
+		assert_eq!(inspection.report.mrenclave, "aa".repeat(32));
+		assert_eq!(inspection.report.mrsigner, "bb".repeat(32));
+		assert_eq!(inspection.report.isv_prod_id, 12);
+		assert_eq!(inspection.report.isv_svn, 103);
+		assert_eq!(inspection.report.cpu_svn, "07".repeat(16));
+		assert!(inspection.report.debug && inspection.report.mode64bit);
+		assert_eq!(inspection.signature, None);
+
+		// ECDSA quote without signature data
+		quote[..2].copy_from_slice(&QUOTE_VERSION.to_le_bytes());
+		assert!(inspect_quote(&quote).is_err());
+
+		quote[..2].copy_from_slice(&4u16.to_le_bytes());
+		assert!(inspect_quote(&quote).is_err());
+		assert!(inspect_quote(&quote[..40]).is_err());
+	}
+}
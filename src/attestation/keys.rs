@@ -0,0 +1,135 @@
+use std::collections::BTreeMap;
+
+use hkdf::Hkdf;
+use serde::Serialize;
+use sha2::Sha256;
+use subxt::ext::sp_core::{crypto::Ss58Codec, sr25519, Pair};
+
+/* ---------------------------------------
+	ENCLAVE SUBKEY HIERARCHY
+--------------------------------------- */
+
+const SUBKEY_SALT: &[u8] = b"ternoa-enclave-subkeys";
+
+/// Functions of the enclave which sign with their own key instead of the identity key
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum KeyPurpose {
+	RESPONSE,
+	AUDITLOG,
+}
+
+impl KeyPurpose {
+	pub const ALL: [KeyPurpose; 2] = [KeyPurpose::RESPONSE, KeyPurpose::AUDITLOG];
+
+	/// HKDF info string, distinct per purpose
+	pub fn info(&self) -> &'static str {
+		match self {
+			KeyPurpose::RESPONSE => "ternoa-enclave/response-signing/v1",
+			KeyPurpose::AUDITLOG => "ternoa-enclave/audit-log-chaining/v1",
+		}
+	}
+}
+
+/// Derive the subkey of a purpose from the enclave identity with HKDF-SHA256
+/// # Arguments
+/// * `identity` - enclave identity keypair
+/// * `purpose` - function of the subkey
+pub fn derive_subkey(identity: &sr25519::Pair, purpose: KeyPurpose) -> sr25519::Pair {
+	// Secret part of the schnorrkel keypair, public key is the last 32 bytes
+	let identity_bytes = identity.as_ref().to_bytes();
+	let hkdf = Hkdf::<Sha256>::new(Some(SUBKEY_SALT), &identity_bytes[..64]);
+
+	let mut seed = [0u8; 32];
+	// 32 bytes is always a valid HKDF-SHA256 output length
+	if let Err(err) = hkdf.expand(purpose.info().as_bytes(), &mut seed) {
+		tracing::error!("SUBKEY : error expanding {:?} subkey : {err:?}", purpose);
+	}
+
+	sr25519::Pair::from_seed(&seed)
+}
+
+/// Purpose-specific subkeys of the enclave
+#[derive(Clone)]
+pub struct EnclaveSubkeys {
+	response: sr25519::Pair,
+	audit_log: sr25519::Pair,
+	// Identity signature of the published public keys
+	certificate: String,
+}
+
+impl EnclaveSubkeys {
+	pub fn derive(identity: &sr25519::Pair) -> EnclaveSubkeys {
+		let mut subkeys = EnclaveSubkeys {
+			response: derive_subkey(identity, KeyPurpose::RESPONSE),
+			audit_log: derive_subkey(identity, KeyPurpose::AUDITLOG),
+			certificate: String::new(),
+		};
+
+		let signature = identity.sign(subkeys.certificate_message().as_bytes());
+		subkeys.certificate = format!("0x{}", hex::encode(signature.0));
+
+		subkeys
+	}
+
+	pub fn get(&self, purpose: KeyPurpose) -> sr25519::Pair {
+		match purpose {
+			KeyPurpose::RESPONSE => self.response.clone(),
+			KeyPurpose::AUDITLOG => self.audit_log.clone(),
+		}
+	}
+
+	/// Public keys, purpose -> ss58 address
+	pub fn public_keys(&self) -> BTreeMap<KeyPurpose, String> {
+		KeyPurpose::ALL
+			.iter()
+			.map(|purpose| (*purpose, self.get(*purpose).public().to_ss58check()))
+			.collect()
+	}
+
+	/// Identity signature of "PURPOSE=address;..." in purpose order
+	pub fn certificate(&self) -> String {
+		self.certificate.clone()
+	}
+
+	fn certificate_message(&self) -> String {
+		self.public_keys()
+			.iter()
+			.map(|(purpose, address)| format!("{:?}={}", purpose, address))
+			.collect::<Vec<String>>()
+			.join(";")
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn subkey_derivation_test() {
+		let identity = sr25519::Pair::from_seed(&[7u8; 32]);
+		let subkeys = EnclaveSubkeys::derive(&identity);
+
+		// deterministic
+		assert_eq!(
+			derive_subkey(&identity, KeyPurpose::AUDITLOG).public(),
+			subkeys.get(KeyPurpose::AUDITLOG).public()
+		);
+
+		// independent of each other and of the identity
+		let publics = subkeys.public_keys();
+		assert_eq!(publics.len(), 2);
+		let addresses: std::collections::BTreeSet<&String> = publics.values().collect();
+		assert_eq!(addresses.len(), 2);
+		assert!(!publics.values().any(|address| *address == identity.public().to_ss58check()));
+
+		// other identity, other subkeys
+		let other = EnclaveSubkeys::derive(&sr25519::Pair::from_seed(&[8u8; 32]));
+		assert_ne!(other.public_keys(), publics);
+
+		// certificate binds the subkeys to the identity
+		let certificate = hex::decode(subkeys.certificate().trim_start_matches("0x")).unwrap();
+		let signature = sr25519::Signature::from_slice(&certificate).unwrap();
+		let message = subkeys.certificate_message();
+		assert!(sr25519::Pair::verify(&signature, message, &identity.public()));
+	}
+}
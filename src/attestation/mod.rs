@@ -1,2 +1,18 @@
 /// Attestation
+pub mod account;
+pub mod allowlist;
+pub mod attest;
+pub mod backend;
+pub mod collateral;
+pub mod environment;
+pub mod epid;
+pub mod handshake;
+pub mod history;
+pub mod identity;
+pub mod inspect;
+pub mod keys;
 pub mod ra;
+pub mod ratls;
+pub mod selftest;
+pub mod verifier;
+pub mod watchdog;
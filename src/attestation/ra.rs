@@ -1,148 +1,561 @@
 #![allow(dead_code)]
 use std::{
 	fs::{File, OpenOptions},
-	io::{Error, Read, Write},
+	io::{Read, Write},
 	path::Path,
+	sync::{Mutex, OnceLock},
 };
 
-use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum::{
+	extract::State,
+	http::{HeaderMap, StatusCode},
+	response::{IntoResponse, Response},
+	Json,
+};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 //use cached::proc_macro::once;
-use subxt::ext::sp_core::Pair;
+use subxt::ext::sp_core::{sr25519, Pair};
 use tracing::{debug, error, info, trace};
 
-use crate::servers::state::{get_accountid, get_blocknumber, get_keypair, SharedState};
-use anyhow::{anyhow, Result};
+use crate::{
+	attestation::history::{record_attestation, QuotePurpose},
+	servers::{
+		etag::{if_none_match, not_modified, with_entity_tag},
+		state::{get_accountid, get_blocknumber, get_keypair, SharedState},
+	},
+};
+use anyhow::anyhow;
 
 pub const QUOTE_REPORT_DATA_OFFSET: usize = 368;
 pub const QUOTE_REPORT_DATA_LENGTH: usize = 64;
+pub const QUOTE_MRENCLAVE_OFFSET: usize = 112;
+pub const QUOTE_MRENCLAVE_LENGTH: usize = 32;
+pub const QUOTE_MRSIGNER_OFFSET: usize = 176;
+pub const QUOTE_MRSIGNER_LENGTH: usize = 32;
+// Offset of the MRSIGNER in the report body, the quote has a 48 bytes header before it
+const REPORT_MRSIGNER_OFFSET: usize = QUOTE_MRSIGNER_OFFSET - 48;
+// DCAP quotes with their PCK certificate chain are about 5 KB
+pub const MAX_QUOTE_SIZE: usize = 16 * 1024;
+
+// Gramine attestation pseudo-files, only available in an SGX enclave
+const ATTESTATION_QUOTE_FILE: &str = "/dev/attestation/quote";
+const ATTESTATION_USER_REPORT_DATA_FILE: &str = "/dev/attestation/user_report_data";
+const ATTESTATION_TYPE_FILE: &str = "/dev/attestation/attestation_type";
+// Set by the manifests, in gramine-sgx and gramine-direct
+const GRAMINE_ENV: &str = "SGX_SERVER_LIBOS";
+
+/* ---------------------------------------
+	ATTESTATION ERRORS
+--------------------------------------- */
+
+// Quote generation fails outside of an SGX enclave, with a manifest without remote attestation
+// or on a broken attestation device. The errors are typed, so that the endpoints return their
+// reason and status instead of a generic error :
+// - NOTINENCLAVE : the server runs in gramine-direct or natively, without /dev/attestation
+// - UNSUPPORTEDTYPE : the manifest has no remote attestation, i.e. "none"
+// - IOERROR : reading or writing an attestation pseudo-file failed
+// - QUOTETOOLARGE : the quote is larger than MAX_QUOTE_SIZE
+
+static GRAMINE_MODE: OnceLock<GramineMode> = OnceLock::new();
+
+/// Runtime of the server
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum GramineMode {
+	// gramine-sgx, quotes are available
+	SGX,
+	// gramine-direct, the manifest without SGX
+	DIRECT,
+	// Outside of Gramine, i.e. the simulation and the tests
+	NATIVE,
+}
+
+/// Errors of the quote generation
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub enum AttestationError {
+	NOTINENCLAVE(GramineMode),
+	UNSUPPORTEDTYPE(String),
+	IOERROR(String),
+	QUOTETOOLARGE(usize),
+}
+
+impl AttestationError {
+	/// Http status of the error
+	pub fn status_code(&self) -> StatusCode {
+		match self {
+			AttestationError::NOTINENCLAVE(_) | AttestationError::UNSUPPORTEDTYPE(_) =>
+				StatusCode::NOT_IMPLEMENTED,
+			AttestationError::IOERROR(_) | AttestationError::QUOTETOOLARGE(_) =>
+				StatusCode::INTERNAL_SERVER_ERROR,
+		}
+	}
+}
+
+impl std::fmt::Display for AttestationError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			AttestationError::NOTINENCLAVE(mode) =>
+				write!(f, "QUOTE : not running in an SGX enclave, gramine mode is {mode:?}"),
+			AttestationError::UNSUPPORTEDTYPE(attestation_type) =>
+				write!(f, "QUOTE : attestation type '{attestation_type}' does not provide quotes"),
+			AttestationError::IOERROR(message) =>
+				write!(f, "QUOTE : attestation device : {message}"),
+			AttestationError::QUOTETOOLARGE(size) =>
+				write!(f, "QUOTE : quote of {size} bytes is larger than {MAX_QUOTE_SIZE} bytes"),
+		}
+	}
+}
+
+impl std::error::Error for AttestationError {}
+
+/// Detect the runtime of the server, once
+pub fn gramine_mode() -> GramineMode {
+	*GRAMINE_MODE.get_or_init(|| {
+		if Path::new(ATTESTATION_QUOTE_FILE).exists() {
+			GramineMode::SGX
+		} else if std::env::var_os(GRAMINE_ENV).is_some() {
+			GramineMode::DIRECT
+		} else {
+			GramineMode::NATIVE
+		}
+	})
+}
+
+fn io_error(file: &str, err: std::io::Error) -> AttestationError {
+	error!("QUOTE : error accessing {file} : {err:?}");
+	AttestationError::IOERROR(format!("{file} : {err}"))
+}
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct QuoteResponse {
 	pub block_number: u32,
 	pub data: String,
+	// Preimage of the report data, absent from the quotes of older enclaves
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub report_data_preimage: Option<ReportDataPreimage>,
 }
 
-// [performace] : Rate Limit or Cache the Quote API
-//#[once(time = 60, sync_writes = false)]
-pub async fn ra_get_quote(State(state): State<SharedState>) -> impl IntoResponse {
-	// Make a dynamic user data
-	let enclave_id = get_accountid(&state).await;
-	let block_number = get_blocknumber(&state).await;
-	let sign_data = format!("{enclave_id}_{block_number}");
+/* ---------------------------------------
+	REPORT DATA BINDING
+--------------------------------------- */
 
-	debug!("QUOTE : report_data token = {}", sign_data);
+// The report data of the enclave quotes binds them to this instance, a verifier recomputes it
+// from the preimage returned beside the quote :
+// - preimage is the sr25519 public key of the enclave account (32 bytes), the UTF-8 url of the api
+//   and the block number (4 bytes, big-endian)
+// - report data is the sha256 of the preimage, followed by 32 zero bytes
+// A quote replayed by another instance does not match its account or url, an old quote does not
+// match the current block.
 
-	// Signer
-	let enclave_account = get_keypair(&state).await;
+static API_URL: OnceLock<String> = OnceLock::new();
 
-	let signature = enclave_account.sign(sign_data.as_bytes());
+/// Parse the api url bound to the quotes
+/// # Arguments
+/// * `api_url` - url of the api from the command line, None for the domain and port
+/// * `domain` - domain of the enclave
+/// * `port` - port of the public listener
+pub fn parse_api_url(
+	api_url: Option<&str>,
+	domain: &str,
+	port: u16,
+) -> Result<String, anyhow::Error> {
+	let api_url = match api_url {
+		Some(api_url) => api_url.trim().trim_end_matches('/').to_string(),
+		None if port == 443 => format!("https://{domain}"),
+		None => format!("https://{domain}:{port}"),
+	};
 
-	match write_user_report_data(None, &signature.0) {
-		Ok(_) => debug!("QUOTE : Success writing user_data to the quote."),
+	match reqwest::Url::parse(&api_url) {
+		Ok(url) if url.scheme() == "https" && url.host_str().is_some() => Ok(api_url),
+		_ => Err(anyhow!("QUOTE : api url '{api_url}' must be an https url")),
+	}
+}
 
-		Err(err) =>
-			return (
-				StatusCode::INTERNAL_SERVER_ERROR,
-				Json(QuoteResponse { block_number, data: err.to_string() }),
-			),
+/// Set the api url bound to the quotes, only once at startup
+pub fn set_api_url(api_url: String) -> Result<(), anyhow::Error> {
+	API_URL
+		.set(api_url)
+		.map_err(|_| anyhow!("QUOTE : configuration is already set"))
+}
+
+/// Api url bound to the quotes, empty if it is not configured
+pub fn api_url() -> &'static str {
+	API_URL.get_or_init(String::new)
+}
+
+/// Preimage of the report data of a quote
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ReportDataPreimage {
+	// 0x prefixed sr25519 public key of the enclave account
+	pub public_key: String,
+	pub api_url: String,
+	pub block_number: u32,
+	// Hex encoded bytes of PUBLICKEY || API_URL || BLOCKNUMBER
+	pub preimage: String,
+}
+
+impl ReportDataPreimage {
+	/// Preimage binding a quote to the enclave account, its api and a block
+	pub fn new(public_key: &sr25519::Public, api_url: &str, block_number: u32) -> Self {
+		let preimage =
+			[&public_key.0[..], api_url.as_bytes(), &block_number.to_be_bytes()[..]].concat();
+
+		ReportDataPreimage {
+			public_key: format!("0x{}", hex::encode(public_key.0)),
+			api_url: api_url.to_string(),
+			block_number,
+			preimage: hex::encode(preimage),
+		}
+	}
+
+	/// Report data of the quote : SHA256(PREIMAGE) followed by zeros
+	pub fn report_data(&self) -> Result<[u8; QUOTE_REPORT_DATA_LENGTH], anyhow::Error> {
+		self.report_data_with_nonce(&[0u8; 32])
+	}
+
+	/// Report data of an attestation : SHA256(PREIMAGE) followed by the nonce of the caller
+	pub fn report_data_with_nonce(
+		&self,
+		nonce: &[u8; 32],
+	) -> Result<[u8; QUOTE_REPORT_DATA_LENGTH], anyhow::Error> {
+		let mut report_data = [0u8; QUOTE_REPORT_DATA_LENGTH];
+		report_data[..32].copy_from_slice(&Sha256::digest(hex::decode(&self.preimage)?));
+		report_data[32..].copy_from_slice(nonce);
+		Ok(report_data)
+	}
+
+	/// Whether a quote carries the report data of this preimage
+	pub fn is_bound(&self, quote: &[u8]) -> bool {
+		match (
+			self.report_data(),
+			quote
+				.get(QUOTE_REPORT_DATA_OFFSET..QUOTE_REPORT_DATA_OFFSET + QUOTE_REPORT_DATA_LENGTH),
+		) {
+			(Ok(report_data), Some(quote_report_data)) => report_data[..] == quote_report_data[..],
+			_ => false,
+		}
+	}
+}
+
+/// Last quote generation of the enclave, reported by the health check
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LastQuote {
+	pub block_number: u32,
+	pub error: Option<String>,
+}
+
+static LAST_QUOTE: Mutex<Option<LastQuote>> = Mutex::new(None);
+
+/// Last quote generation, None if no quote was requested since the start
+pub fn last_quote() -> Option<LastQuote> {
+	LAST_QUOTE.lock().ok().and_then(|last| last.clone())
+}
+
+fn record_quote(block_number: u32, result: &Result<Vec<u8>, AttestationError>) {
+	match LAST_QUOTE.lock() {
+		Ok(mut last) =>
+			*last = Some(LastQuote {
+				block_number,
+				error: result.as_ref().err().map(|err| err.to_string()),
+			}),
+		Err(err) => error!("QUOTE : lock error : {err:?}"),
+	}
+}
+
+/// Quote of the enclave, its report data binds the enclave account, the api url and the
+/// current block, see ReportDataPreimage
+/// # Returns
+/// * `ReportDataPreimage` - preimage of the report data, with its block number
+/// * `Result<Vec<u8>, AttestationError>` - quote content
+pub async fn create_quote(
+	state: &SharedState,
+) -> (ReportDataPreimage, Result<Vec<u8>, AttestationError>) {
+	create_nonce_quote(state, &[0u8; 32]).await
+}
+
+/// Fresh quote of the enclave, the second half of its report data is the nonce of the caller
+/// # Arguments
+/// * `state` - SharedState
+/// * `nonce` - 32 bytes of the caller, zeros for the quotes without nonce
+pub async fn create_nonce_quote(
+	state: &SharedState,
+	nonce: &[u8; 32],
+) -> (ReportDataPreimage, Result<Vec<u8>, AttestationError>) {
+	let enclave_account = get_keypair(state).await;
+	let block_number = get_blocknumber(state).await;
+	let preimage = ReportDataPreimage::new(&enclave_account.public(), api_url(), block_number);
+
+	debug!("QUOTE : report_data preimage = {}", preimage.preimage);
+
+	let quote = match preimage.report_data_with_nonce(nonce) {
+		Ok(report_data) => generate_quote(&report_data),
+		Err(err) => Err(AttestationError::IOERROR(format!("invalid report data preimage : {err}"))),
 	};
 
-	match get_quote_content() {
-		Ok(quote) =>
-			(StatusCode::OK, Json(QuoteResponse { block_number, data: hex::encode(quote) })),
+	record_quote(block_number, &quote);
+	if let Ok(quote) = &quote {
+		record_attestation(Some(block_number), QuotePurpose::ENCLAVE, quote);
+	}
+
+	(preimage, quote)
+}
+
+// Concurrent requests must not read the quote of each other's report data
+static QUOTE_LOCK: Mutex<()> = Mutex::new(());
 
-		Err(err) => (
-			StatusCode::INTERNAL_SERVER_ERROR,
-			Json(QuoteResponse { block_number, data: err.to_string() }),
-		),
+/// Write the report data and read its quote, under the quote lock
+pub fn generate_quote(
+	report_data: &[u8; QUOTE_REPORT_DATA_LENGTH],
+) -> Result<Vec<u8>, AttestationError> {
+	let _guard = QUOTE_LOCK
+		.lock()
+		.map_err(|err| AttestationError::IOERROR(format!("quote lock : {err:?}")))?;
+
+	let attestation_type = read_attestation_type()?;
+	if attestation_type != "epid" && attestation_type != "dcap" {
+		return Err(AttestationError::UNSUPPORTEDTYPE(attestation_type))
 	}
+
+	write_user_report_data(None, report_data)?;
+	debug!("QUOTE : Success writing user_data to the quote.");
+
+	get_quote_content()
 }
 
-/// Reads the quote or else returns an error
+/// Entity tag of the quote of a block, quotes of the same block share their report data
 /// # Arguments
-/// * `file_path` - The path to the quote
+/// * `enclave_id` - account of the enclave
+/// * `block_number` - block of the report data
+pub fn quote_etag(enclave_id: &str, block_number: u32) -> String {
+	format!("W/\"quote-{enclave_id}-{block_number}\"")
+}
+
+/// Quote of the enclave for the current block, a client which already has it gets
+/// "304 Not Modified" without a new quote generation
+pub async fn ra_get_quote(State(state): State<SharedState>, headers: HeaderMap) -> Response {
+	let enclave_id = get_accountid(&state).await;
+	let current_etag = quote_etag(&enclave_id, get_blocknumber(&state).await);
+	if if_none_match(&headers, &current_etag) {
+		return not_modified(&current_etag)
+	}
+
+	match create_quote(&state).await {
+		(preimage, Ok(quote)) => {
+			let block_number = preimage.block_number;
+			let response = QuoteResponse {
+				block_number,
+				data: hex::encode(quote),
+				report_data_preimage: Some(preimage),
+			};
+			with_entity_tag(
+				(StatusCode::OK, Json(response)).into_response(),
+				&quote_etag(&enclave_id, block_number),
+			)
+		},
+
+		(preimage, Err(err)) => (
+			err.status_code(),
+			Json(QuoteResponse {
+				block_number: preimage.block_number,
+				data: err.to_string(),
+				report_data_preimage: None,
+			}),
+		)
+			.into_response(),
+	}
+}
+
+/// Reads the quote of the last report data
 /// # Returns
-/// * `Result<Vec<u8>, Error>` - The result of the quote
-pub fn get_quote_content() -> Result<Vec<u8>, Error> {
+/// * `Result<Vec<u8>, AttestationError>` - The quote
+pub fn get_quote_content() -> Result<Vec<u8>, AttestationError> {
 	info!("QUOTE : Reading The Quote ...");
-	let default_path = "/dev/attestation/quote";
-	let mut content = vec![];
-
-	File::open(default_path)
-		.and_then(|mut file| {
-			file.read_to_end(&mut content).map_err(|err| {
-				error!("QUOTE : Error opening file /dev/attestation/quote {err:?}");
-				err
-			})
-		})
-		.map(|_| {
+	if gramine_mode() != GramineMode::SGX {
+		return Err(AttestationError::NOTINENCLAVE(gramine_mode()))
+	}
+
+	let content = std::fs::read(ATTESTATION_QUOTE_FILE)
+		.map_err(|err| io_error(ATTESTATION_QUOTE_FILE, err))?;
+
+	match content.len() {
+		0 => Err(AttestationError::IOERROR(format!("{ATTESTATION_QUOTE_FILE} is empty"))),
+		size if size > MAX_QUOTE_SIZE => Err(AttestationError::QUOTETOOLARGE(size)),
+		_ => {
 			trace!("\nQuote : content {:?}\n", content);
-			content
-		})
+			Ok(content)
+		},
+	}
 }
 
-/// Reads the attestation type or else returns an error
-/// # Arguments
-/// * `file_path` - The path to the attestation type
+/// MRENCLAVE of this enclave, the first field of its target info
 /// # Returns
-/// * `Result<String, Error>` - The result of the attestation type
-fn read_attestation_type(file_path: Option<String>) -> Result<String, Error> {
-	let default_path = "/dev/attestation/attestation.attestation_type";
-	let mut attest_type = String::new();
-
-	File::open(file_path.unwrap_or(String::from(default_path)))
-		.and_then(|mut file| {
-			file.read_to_string(&mut attest_type).map_err(|err| {
-				error!("QUOTE : Error reading file: {err:?}");
-				err
-			})
-		})
-		.map(|_| {
-			debug!("QUOTE : attestation type is : {}", attest_type);
-			attest_type
-		})
-}
-
-/// Writes user report data or else throws an Error
-/// # Arguments
-/// * `file_path` - The path to the user report data
+/// * `Option<String>` - hex encoded MRENCLAVE, None outside of an enclave
+pub fn local_mrenclave() -> Option<String> {
+	let mut mrenclave = [0u8; QUOTE_MRENCLAVE_LENGTH];
+
+	match File::open("/dev/attestation/my_target_info")
+		.and_then(|mut file| file.read_exact(&mut mrenclave))
+	{
+		Ok(_) => Some(hex::encode(mrenclave)),
+		Err(err) => {
+			debug!("QUOTE : MRENCLAVE is not available : {err:?}");
+			None
+		},
+	}
+}
+
+/// SGX attributes of this enclave, after the MRENCLAVE in its target info
+/// # Returns
+/// * `Option<[u8; 16]>` - flags and XFRM, None outside of an enclave
+pub fn local_attributes() -> Option<[u8; 16]> {
+	let mut target_info = [0u8; QUOTE_MRENCLAVE_LENGTH + 16];
+
+	match File::open("/dev/attestation/my_target_info")
+		.and_then(|mut file| file.read_exact(&mut target_info))
+	{
+		Ok(_) => target_info[QUOTE_MRENCLAVE_LENGTH..].try_into().ok(),
+		Err(err) => {
+			debug!("QUOTE : attributes are not available : {err:?}");
+			None
+		},
+	}
+}
+
+/// MRSIGNER of this enclave, from a report targeted to itself
 /// # Returns
-/// * `Result<(), Error>` - The result of the user report data
+/// * `Option<String>` - hex encoded MRSIGNER, None outside of an enclave
+pub fn local_mrsigner() -> Option<String> {
+	let report = std::fs::read("/dev/attestation/my_target_info")
+		.and_then(|target_info| std::fs::write("/dev/attestation/target_info", target_info))
+		.and_then(|_| std::fs::read("/dev/attestation/report"));
+
+	match report {
+		Ok(report) if report.len() >= REPORT_MRSIGNER_OFFSET + QUOTE_MRSIGNER_LENGTH =>
+			Some(hex::encode(
+				&report[REPORT_MRSIGNER_OFFSET..REPORT_MRSIGNER_OFFSET + QUOTE_MRSIGNER_LENGTH],
+			)),
+		Ok(_) => {
+			debug!("QUOTE : report is too short");
+			None
+		},
+		Err(err) => {
+			debug!("QUOTE : MRSIGNER is not available : {err:?}");
+			None
+		},
+	}
+}
+
+/// Attestation type of the manifest, "none", "epid" or "dcap"
+pub fn read_attestation_type() -> Result<String, AttestationError> {
+	if gramine_mode() != GramineMode::SGX {
+		return Err(AttestationError::NOTINENCLAVE(gramine_mode()))
+	}
+
+	let attestation_type = std::fs::read_to_string(ATTESTATION_TYPE_FILE)
+		.map_err(|err| io_error(ATTESTATION_TYPE_FILE, err))?;
+	debug!("QUOTE : attestation type is : {}", attestation_type);
+
+	Ok(attestation_type.trim().to_string())
+}
+
+/// Writes the user report data of the next quote
+/// # Arguments
+/// * `file_path` - The path to the user report data, the Gramine pseudo-file if None
+/// * `user_data` - report data of the quote
 pub fn write_user_report_data(
 	file_path: Option<String>,
 	user_data: &[u8; 64],
-) -> Result<(), anyhow::Error> {
-	let default_path = "/dev/attestation/user_report_data";
-	if !is_user_report_data_exist(None) {
-		return Err(anyhow!("QUOTE : user_report_data does not exist!"))
+) -> Result<(), AttestationError> {
+	let file_path = file_path.unwrap_or(String::from(ATTESTATION_USER_REPORT_DATA_FILE));
+	if !Path::new(&file_path).exists() {
+		return Err(AttestationError::NOTINENCLAVE(gramine_mode()))
 	}
 
-	Ok(OpenOptions::new()
+	OpenOptions::new()
 		.write(true)
-		.open(file_path.unwrap_or(String::from(default_path)))
-		.and_then(|mut file| {
-			info!("QUOTE : This is inside Enclave!");
-			file.write_all(user_data.as_slice()).map_err(|err| {
-				error!("QUOTE : Error writing to {} {:?}", default_path, err);
-				err
-			})
-		})
-		.map_err(|err| {
-			error!("QUOTE : Error writing file: {err:?}");
-			err
-		})
-		.map(|_| ())?)
-}
-
-/// Check if file exists with correct permissions or else returns false
-/// # Arguments
-/// * `file_path` - The path to the user report data
-/// # Returns
-/// * `bool` - The result of the user report data
-fn is_user_report_data_exist(file_path: Option<String>) -> bool {
-	return match file_path {
-		None => Path::new("/dev/attestation/user_report_data").exists(),
-		Some(f) => Path::new(&f).exists(),
+		.open(&file_path)
+		.and_then(|mut file| file.write_all(user_data.as_slice()))
+		.map_err(|err| io_error(&file_path, err))
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn report_data_preimage_test() {
+		let enclave_key = sr25519::Pair::from_seed(&[7u8; 32]);
+		let preimage =
+			ReportDataPreimage::new(&enclave_key.public(), "https://enclave.ternoa.network", 1000);
+
+		let bytes = hex::decode(&preimage.preimage).unwrap();
+		assert_eq!(&bytes[..32], &enclave_key.public().0[..]);
+		assert_eq!(&bytes[32..bytes.len() - 4], b"https://enclave.ternoa.network");
+		assert_eq!(&bytes[bytes.len() - 4..], &1000u32.to_be_bytes()[..]);
+
+		let report_data = preimage.report_data().unwrap();
+		assert_eq!(&report_data[..32], &Sha256::digest(&bytes)[..]);
+		assert_eq!(&report_data[32..], &[0u8; 32][..]);
+
+		let mut quote = vec![0u8; QUOTE_REPORT_DATA_OFFSET];
+		quote.extend_from_slice(&report_data);
+		assert!(preimage.is_bound(&quote));
+
+		// Another url or block is another report data
+		let other = ReportDataPreimage::new(&enclave_key.public(), "https://other.network", 1000);
+		assert!(!other.is_bound(&quote));
+		let other =
+			ReportDataPreimage::new(&enclave_key.public(), "https://enclave.ternoa.network", 1001);
+		assert!(!other.is_bound(&quote));
+		assert!(!preimage.is_bound(&quote[..100]));
+
+		assert_eq!(
+			parse_api_url(None, "enclave.ternoa.network", 8000).unwrap(),
+			"https://enclave.ternoa.network:8000"
+		);
+		assert_eq!(
+			parse_api_url(None, "enclave.ternoa.network", 443).unwrap(),
+			"https://enclave.ternoa.network"
+		);
+		assert_eq!(
+			parse_api_url(Some("https://api.ternoa.network/"), "enclave", 8000).unwrap(),
+			"https://api.ternoa.network"
+		);
+		assert!(parse_api_url(Some("http://api.ternoa.network"), "enclave", 8000).is_err());
+	}
+
+	#[test]
+	fn attestation_error_test() {
+		// Tests run outside of Gramine
+		assert_eq!(gramine_mode(), GramineMode::NATIVE);
+		assert_eq!(get_quote_content(), Err(AttestationError::NOTINENCLAVE(GramineMode::NATIVE)));
+		assert_eq!(
+			write_user_report_data(None, &[0u8; 64]),
+			Err(AttestationError::NOTINENCLAVE(GramineMode::NATIVE))
+		);
+
+		let file = std::env::temp_dir().join("user_report_data_test");
+		std::fs::write(&file, []).unwrap();
+		let path = Some(file.to_string_lossy().to_string());
+		assert_eq!(write_user_report_data(path, &[7u8; 64]), Ok(()));
+		assert_eq!(std::fs::read(&file).unwrap(), vec![7u8; 64]);
+		std::fs::remove_file(&file).unwrap();
+
+		assert_eq!(
+			AttestationError::NOTINENCLAVE(GramineMode::DIRECT).status_code(),
+			StatusCode::NOT_IMPLEMENTED
+		);
+		assert_eq!(
+			AttestationError::QUOTETOOLARGE(MAX_QUOTE_SIZE + 1).status_code(),
+			StatusCode::INTERNAL_SERVER_ERROR
+		);
+		assert_eq!(
+			serde_json::to_value(AttestationError::UNSUPPORTEDTYPE("none".to_string())).unwrap(),
+			serde_json::json!({ "UNSUPPORTEDTYPE": "none" })
+		);
 	}
 }
@@ -0,0 +1,147 @@
+use std::{sync::Mutex, time::Duration};
+
+use anyhow::anyhow;
+use axum_server::tls_rustls::RustlsConfig;
+use rcgen::{Certificate, CertificateParams, CustomExtension, KeyPair, PKCS_ECDSA_P256_SHA256};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tracing::{error, info};
+
+use crate::{
+	attestation::{
+		history::{record_attestation, QuotePurpose},
+		ra::{get_quote_content, write_user_report_data, QUOTE_REPORT_DATA_LENGTH},
+	},
+	chain::constants::RA_TLS_REFRESH_INTERVAL,
+};
+
+/* ---------------------------------------
+	RA-TLS
+--------------------------------------- */
+
+// With `--ra-tls` the enclave serves HTTPS itself instead of relying on an external TLS
+// termination. The TLS key is generated inside SGX and never leaves it :
+// - the report data of the quote is the sha256 of the DER public key of the certificate, followed
+//   by zeros, as in the Gramine RA-TLS libraries
+// - the quote is embedded in the self-signed certificate, under the Gramine quote extension
+// - clients verify the quote and compare its report data with the public key of the connection
+// The certificate is renewed periodically, so the embedded quote stays fresh.
+
+// Gramine legacy RA-TLS extension of the SGX quote
+pub const RA_TLS_QUOTE_OID: &[u64] = &[1, 2, 840, 113741, 1337, 6];
+
+/// Public information of the current RA-TLS certificate, reported in the capabilities
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct RaTlsCertificate {
+	// Dotted OID of the quote extension
+	pub quote_oid: String,
+	// Hex encoded sha256 of the DER public key, the first half of the report data
+	pub public_key_sha256: String,
+}
+
+static RA_TLS_CERTIFICATE: Mutex<Option<RaTlsCertificate>> = Mutex::new(None);
+
+/// Current RA-TLS certificate, None if the enclave is not serving RA-TLS
+pub fn ra_tls_certificate() -> Option<RaTlsCertificate> {
+	RA_TLS_CERTIFICATE.lock().ok().and_then(|certificate| certificate.clone())
+}
+
+/// Report data binding a TLS public key to the quote
+/// # Arguments
+/// * `public_key` - DER encoded SubjectPublicKeyInfo of the TLS key
+pub fn ra_tls_report_data(public_key: &[u8]) -> [u8; QUOTE_REPORT_DATA_LENGTH] {
+	let mut report_data = [0u8; QUOTE_REPORT_DATA_LENGTH];
+	report_data[..32].copy_from_slice(&Sha256::digest(public_key));
+	report_data
+}
+
+/// Generate a TLS key and its self-signed certificate embedding the quote of the key
+/// # Arguments
+/// * `domain` - subject alternative name of the certificate
+/// # Returns
+/// * `(Vec<u8>, Vec<u8>)` - DER encoded certificate and PKCS#8 private key
+pub fn generate_ra_tls_certificate(domain: &str) -> Result<(Vec<u8>, Vec<u8>), anyhow::Error> {
+	let key_pair = KeyPair::generate(&PKCS_ECDSA_P256_SHA256)
+		.map_err(|err| anyhow!("RA-TLS : unable to generate the tls key : {err:?}"))?;
+	let public_key = key_pair.public_key_der();
+
+	write_user_report_data(None, &ra_tls_report_data(&public_key))?;
+	let quote = get_quote_content()
+		.map_err(|err| anyhow!("RA-TLS : unable to get the quote of the tls key : {err:?}"))?;
+	record_attestation(None, QuotePurpose::RATLS, &quote);
+
+	let mut params = CertificateParams::new(vec![domain.to_string()]);
+	params.alg = &PKCS_ECDSA_P256_SHA256;
+	params.key_pair = Some(key_pair);
+	params
+		.custom_extensions
+		.push(CustomExtension::from_oid_content(RA_TLS_QUOTE_OID, quote));
+
+	let certificate = Certificate::from_params(params)
+		.map_err(|err| anyhow!("RA-TLS : unable to create the certificate : {err:?}"))?;
+	let certificate_der = certificate
+		.serialize_der()
+		.map_err(|err| anyhow!("RA-TLS : unable to sign the certificate : {err:?}"))?;
+
+	match RA_TLS_CERTIFICATE.lock() {
+		Ok(mut current) =>
+			*current = Some(RaTlsCertificate {
+				quote_oid: RA_TLS_QUOTE_OID
+					.iter()
+					.map(|arc| arc.to_string())
+					.collect::<Vec<_>>()
+					.join("."),
+				public_key_sha256: hex::encode(Sha256::digest(&public_key)),
+			}),
+		Err(err) => error!("RA-TLS : lock error : {err:?}"),
+	}
+
+	Ok((certificate_der, certificate.serialize_private_key_der()))
+}
+
+/// Rust-TLS config of the RA-TLS certificate, renewed in the background
+/// # Arguments
+/// * `domain` - subject alternative name of the certificate
+pub async fn ra_tls_config(domain: &str) -> Result<RustlsConfig, anyhow::Error> {
+	let (certificate, key) = generate_ra_tls_certificate(domain)?;
+	let config = RustlsConfig::from_der(vec![certificate], key).await?;
+	info!("RA-TLS : certificate is bound to the quote");
+
+	let renewed_config = config.clone();
+	let domain = domain.to_string();
+	tokio::spawn(async move {
+		loop {
+			tokio::time::sleep(Duration::from_secs(RA_TLS_REFRESH_INTERVAL)).await;
+
+			let renewed = match generate_ra_tls_certificate(&domain) {
+				Ok((certificate, key)) =>
+					renewed_config.reload_from_der(vec![certificate], key).await.map_err(Into::into),
+				Err(err) => Err(err),
+			};
+
+			match renewed {
+				Ok(_) => info!("RA-TLS : certificate is renewed"),
+				// The previous certificate is still served, with its older quote
+				Err(err) => error!("RA-TLS : certificate renewal failed : {err:?}"),
+			}
+		}
+	});
+
+	Ok(config)
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn ra_tls_report_data_test() {
+		let report_data = ra_tls_report_data(b"public key");
+		assert_eq!(report_data[..32], Sha256::digest(b"public key")[..]);
+		assert_eq!(report_data[32..], [0u8; 32]);
+	}
+}
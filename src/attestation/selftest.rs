@@ -0,0 +1,406 @@
+use std::sync::{Mutex, OnceLock};
+
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use serde::Serialize;
+use serde_json::json;
+use tracing::{error, info, warn};
+
+use crate::{
+	attestation::{
+		backend::{attestation_backend, AttestationVerdict},
+		ra::{
+			create_quote, gramine_mode, local_mrenclave, GramineMode, ReportDataPreimage,
+			QUOTE_MRENCLAVE_LENGTH, QUOTE_MRENCLAVE_OFFSET,
+		},
+	},
+	chain::killswitch::MaintenanceMode,
+	servers::{
+		proxy::with_http_proxy,
+		state::{set_maintenance_mode, SharedState},
+	},
+};
+
+/* ---------------------------------------
+	ATTESTATION SELF-TEST
+--------------------------------------- */
+
+// An enclave must not serve traffic when it can not prove its identity, i.e. after an aesmd or
+// PCCS misconfiguration, an uninstalled DCAP library or a platform which lost its registration.
+// At startup, before the keyshares are synchronized, the enclave :
+// - generates a quote bound to its account, its api url and the current block
+// - checks that the quote carries this report data and the MRENCLAVE of the enclave
+// - verifies the quote with the attestation backend of the platform, against its collateral or the
+//   Intel Attestation Service, and checks the TCB status and the report data of the verdict
+// A failed self-test aborts the start, or switches the API to the FULL maintenance mode where only
+// the health and attestation endpoints are served, with the diagnostic in /api/attest/self-test.
+// The trusted measurements of the peers are not checked, they do not need to include this enclave.
+
+static SELF_TEST_POLICY: OnceLock<SelfTestPolicy> = OnceLock::new();
+static SELF_TEST_REPORT: Mutex<Option<SelfTestReport>> = Mutex::new(None);
+
+/// Reaction to a failed self-test
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum SelfTestPolicy {
+	// The self-test is not run
+	OFF,
+	// The API is switched to the FULL maintenance mode until the next start
+	MAINTENANCE,
+	// The enclave does not start
+	ABORT,
+}
+
+/// Check of the self-test
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum SelfTestCheck {
+	// A quote is generated
+	QUOTE,
+	// The quote carries the report data of the enclave
+	REPORTDATA,
+	// The quote carries the MRENCLAVE of the enclave
+	MEASUREMENT,
+	// The quote is verified and its platform is trusted
+	VERIFICATION,
+}
+
+/// Result of a check
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct SelfTestStep {
+	pub check: SelfTestCheck,
+	pub passed: bool,
+	pub detail: String,
+}
+
+impl SelfTestStep {
+	fn passed(check: SelfTestCheck, detail: String) -> Self {
+		SelfTestStep { check, passed: true, detail }
+	}
+
+	fn failed(check: SelfTestCheck, detail: String) -> Self {
+		SelfTestStep { check, passed: false, detail }
+	}
+}
+
+/// Self-test of the remote attestation at startup
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct SelfTestReport {
+	pub block_number: u32,
+	pub gramine_mode: GramineMode,
+	// Why the self-test is skipped, quotes are only available in SGX
+	pub skipped: Option<String>,
+	// Checks in order, the first failed check ends the self-test
+	pub steps: Vec<SelfTestStep>,
+	pub verdict: Option<AttestationVerdict>,
+	pub passed: bool,
+}
+
+impl SelfTestReport {
+	/// Diagnostic of the first failed check
+	pub fn diagnostic(&self) -> Option<String> {
+		self.steps
+			.iter()
+			.find(|step| !step.passed)
+			.map(|step| format!("{:?} check failed : {}", step.check, step.detail))
+	}
+}
+
+/// Parse the self-test policy from the command line
+/// # Arguments
+/// * `policy` - "off", "maintenance" or "abort"
+pub fn parse_self_test_policy(policy: &str) -> Result<SelfTestPolicy, anyhow::Error> {
+	match policy.trim().to_lowercase().as_str() {
+		"off" => Ok(SelfTestPolicy::OFF),
+		"maintenance" => Ok(SelfTestPolicy::MAINTENANCE),
+		"abort" => Ok(SelfTestPolicy::ABORT),
+		_ => Err(anyhow::anyhow!(
+			"ATTESTATION SELF-TEST : unknown policy '{policy}', expected off, maintenance or abort"
+		)),
+	}
+}
+
+/// Set the self-test policy from the command line
+pub fn set_self_test_policy(policy: SelfTestPolicy) -> Result<(), anyhow::Error> {
+	SELF_TEST_POLICY
+		.set(policy)
+		.map_err(|_| anyhow::anyhow!("ATTESTATION SELF-TEST : configuration is already set"))
+}
+
+/// Reaction to a failed self-test
+pub fn self_test_policy() -> SelfTestPolicy {
+	*SELF_TEST_POLICY.get_or_init(|| SelfTestPolicy::MAINTENANCE)
+}
+
+/// Report of the startup self-test, None if it was not run
+pub fn self_test_report() -> Option<SelfTestReport> {
+	SELF_TEST_REPORT.lock().ok().and_then(|report| report.clone())
+}
+
+/// The quote carries the report data of the preimage
+fn check_report_data(preimage: &ReportDataPreimage, quote: &[u8]) -> SelfTestStep {
+	if preimage.is_bound(quote) {
+		SelfTestStep::passed(
+			SelfTestCheck::REPORTDATA,
+			format!("report data binds block {} and {}", preimage.block_number, preimage.api_url),
+		)
+	} else {
+		SelfTestStep::failed(
+			SelfTestCheck::REPORTDATA,
+			"quote does not carry the report data written by the enclave".to_string(),
+		)
+	}
+}
+
+/// The quote carries the MRENCLAVE of the target info of the enclave
+fn check_measurement(quote: &[u8], mrenclave: Option<&str>) -> SelfTestStep {
+	let quote_mrenclave = quote
+		.get(QUOTE_MRENCLAVE_OFFSET..QUOTE_MRENCLAVE_OFFSET + QUOTE_MRENCLAVE_LENGTH)
+		.map(hex::encode);
+
+	match (quote_mrenclave, mrenclave) {
+		(Some(quote_mrenclave), Some(mrenclave)) if quote_mrenclave == mrenclave =>
+			SelfTestStep::passed(SelfTestCheck::MEASUREMENT, format!("MRENCLAVE {mrenclave}")),
+		(quote_mrenclave, mrenclave) => SelfTestStep::failed(
+			SelfTestCheck::MEASUREMENT,
+			format!(
+				"quote MRENCLAVE {quote_mrenclave:?} is not the enclave MRENCLAVE {mrenclave:?}"
+			),
+		),
+	}
+}
+
+/// The verdict trusts the platform and carries the report data of the quote
+fn check_verdict(verdict: &AttestationVerdict, quote_report_data: &str) -> SelfTestStep {
+	let qe_acceptable = verdict.qe_tcb_status.map_or(true, |status| status.is_acceptable());
+
+	if !verdict.tcb_status.is_acceptable() || !qe_acceptable {
+		return SelfTestStep::failed(
+			SelfTestCheck::VERIFICATION,
+			format!(
+				"{:?} verdict rejects the platform, TCB status {:?}, QE TCB status {:?}, advisories {:?}",
+				verdict.attestation_type,
+				verdict.tcb_status,
+				verdict.qe_tcb_status,
+				verdict.advisory_ids
+			),
+		)
+	}
+
+	if verdict.report_data != quote_report_data {
+		return SelfTestStep::failed(
+			SelfTestCheck::VERIFICATION,
+			"verified report data is not the report data of the quote".to_string(),
+		)
+	}
+
+	SelfTestStep::passed(
+		SelfTestCheck::VERIFICATION,
+		format!("{:?} verdict, TCB status {:?}", verdict.attestation_type, verdict.tcb_status),
+	)
+}
+
+/// Generate a quote and verify it like a peer would
+/// # Arguments
+/// * `state` - SharedState
+pub async fn attestation_self_test(state: &SharedState) -> SelfTestReport {
+	let mut report = SelfTestReport {
+		block_number: 0,
+		gramine_mode: gramine_mode(),
+		skipped: None,
+		steps: Vec::new(),
+		verdict: None,
+		passed: false,
+	};
+
+	if report.gramine_mode != GramineMode::SGX {
+		report.skipped =
+			Some(format!("quotes are not available in {:?} mode", report.gramine_mode));
+		report.passed = true;
+		return report
+	}
+
+	let (preimage, quote) = create_quote(state).await;
+	report.block_number = preimage.block_number;
+
+	let quote = match quote {
+		Ok(quote) => {
+			report.steps.push(SelfTestStep::passed(
+				SelfTestCheck::QUOTE,
+				format!("quote of {} bytes", quote.len()),
+			));
+			quote
+		},
+		Err(err) => {
+			report.steps.push(SelfTestStep::failed(SelfTestCheck::QUOTE, err.to_string()));
+			return report
+		},
+	};
+
+	for step in [
+		check_report_data(&preimage, &quote),
+		check_measurement(&quote, local_mrenclave().as_deref()),
+	] {
+		let passed = step.passed;
+		report.steps.push(step);
+		if !passed {
+			return report
+		}
+	}
+
+	let verdict = match with_http_proxy(reqwest::Client::builder()).build() {
+		Ok(client) => attestation_backend().verify(&client, &quote).await,
+		Err(err) => Err(anyhow::anyhow!(err)),
+	};
+
+	let verdict = match verdict {
+		Ok(verdict) => verdict,
+		Err(err) => {
+			report
+				.steps
+				.push(SelfTestStep::failed(SelfTestCheck::VERIFICATION, format!("{err:?}")));
+			return report
+		},
+	};
+
+	let quote_report_data = match preimage.report_data() {
+		Ok(report_data) => hex::encode(report_data),
+		Err(err) => {
+			report
+				.steps
+				.push(SelfTestStep::failed(SelfTestCheck::VERIFICATION, format!("{err:?}")));
+			return report
+		},
+	};
+
+	let step = check_verdict(&verdict, &quote_report_data);
+	report.passed = step.passed;
+	report.steps.push(step);
+	report.verdict = Some(verdict);
+
+	report
+}
+
+/// Run the self-test at startup and apply the policy to a failure
+/// # Arguments
+/// * `state` - SharedState
+/// # Returns
+/// * `Result<(), anyhow::Error>` - an error with the diagnostic if the enclave must not start
+pub async fn run_self_test(state: &SharedState) -> Result<(), anyhow::Error> {
+	let policy = self_test_policy();
+	if policy == SelfTestPolicy::OFF {
+		warn!("ATTESTATION SELF-TEST : self-test is disabled");
+		return Ok(())
+	}
+
+	let report = attestation_self_test(state).await;
+
+	match SELF_TEST_REPORT.lock() {
+		Ok(mut current) => *current = Some(report.clone()),
+		Err(err) => error!("ATTESTATION SELF-TEST : lock error : {err:?}"),
+	}
+
+	if let Some(skipped) = &report.skipped {
+		info!("ATTESTATION SELF-TEST : skipped, {skipped}");
+		return Ok(())
+	}
+
+	if report.passed {
+		info!("ATTESTATION SELF-TEST : passed at block {}", report.block_number);
+		return Ok(())
+	}
+
+	let message = format!(
+		"ATTESTATION SELF-TEST : enclave can not prove its identity, {}",
+		report.diagnostic().unwrap_or_default()
+	);
+	error!(message);
+	sentry::capture_message(&message, sentry::Level::Error);
+
+	match policy {
+		SelfTestPolicy::ABORT => Err(anyhow::anyhow!(message)),
+		_ => {
+			// Not persisted, the self-test runs again at the next start
+			warn!("ATTESTATION SELF-TEST : API is switched to the FULL maintenance mode");
+			set_maintenance_mode(state, MaintenanceMode::FULL).await;
+			Ok(())
+		},
+	}
+}
+
+/// Report of the startup self-test
+pub async fn attest_self_test() -> impl IntoResponse {
+	match self_test_report() {
+		Some(report) => (StatusCode::OK, Json(json!(report))),
+		None => (
+			StatusCode::NOT_FOUND,
+			Json(json!({ "error": "ATTESTATION SELF-TEST : self-test was not run" })),
+		),
+	}
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::attestation::{
+		backend::AttestationType,
+		ra::{QUOTE_REPORT_DATA_LENGTH, QUOTE_REPORT_DATA_OFFSET},
+		verifier::TcbStatus,
+	};
+	use subxt::ext::sp_core::sr25519;
+
+	#[test]
+	fn attestation_self_test_checks_test() {
+		let preimage =
+			ReportDataPreimage::new(&sr25519::Public([5u8; 32]), "https://enclave.example.com", 42);
+		let report_data = preimage.report_data().unwrap();
+
+		let mut quote = vec![0u8; QUOTE_REPORT_DATA_OFFSET + QUOTE_REPORT_DATA_LENGTH];
+		quote[QUOTE_MRENCLAVE_OFFSET..QUOTE_MRENCLAVE_OFFSET + QUOTE_MRENCLAVE_LENGTH]
+			.copy_from_slice(&[0xAAu8; 32]);
+		assert!(!check_report_data(&preimage, &quote).passed);
+		quote[QUOTE_REPORT_DATA_OFFSET..].copy_from_slice(&report_data);
+		assert!(check_report_data(&preimage, &quote).passed);
+
+		assert!(check_measurement(&quote, Some(&"aa".repeat(32))).passed);
+		assert!(!check_measurement(&quote, Some(&"bb".repeat(32))).passed);
+		assert!(!check_measurement(&quote, None).passed);
+
+		let mut verdict = AttestationVerdict {
+			attestation_type: AttestationType::DCAP,
+			mrenclave: "aa".repeat(32),
+			mrsigner: "bb".repeat(32),
+			isv_prod_id: 0,
+			isv_svn: 0,
+			report_data: hex::encode(report_data),
+			tcb_status: TcbStatus::SWHARDENINGNEEDED,
+			advisory_ids: vec!["INTEL-SA-00615".to_string()],
+			qe_tcb_status: Some(TcbStatus::UPTODATE),
+			// Measurements of the peers are not checked
+			trusted_measurement: false,
+			debug: false,
+			accepted: false,
+		};
+		assert!(check_verdict(&verdict, &hex::encode(report_data)).passed);
+		assert!(!check_verdict(&verdict, &"00".repeat(64)).passed);
+		verdict.qe_tcb_status = Some(TcbStatus::OUTOFDATE);
+		assert!(!check_verdict(&verdict, &hex::encode(report_data)).passed);
+
+		let report = SelfTestReport {
+			block_number: 42,
+			gramine_mode: GramineMode::SGX,
+			skipped: None,
+			steps: vec![
+				SelfTestStep::passed(SelfTestCheck::QUOTE, String::new()),
+				SelfTestStep::failed(SelfTestCheck::MEASUREMENT, "mismatch".to_string()),
+			],
+			verdict: None,
+			passed: false,
+		};
+		assert_eq!(report.diagnostic().unwrap(), "MEASUREMENT check failed : mismatch");
+
+		assert_eq!(parse_self_test_policy("Abort").unwrap(), SelfTestPolicy::ABORT);
+		assert!(parse_self_test_policy("warn").is_err());
+	}
+}
@@ -0,0 +1,1141 @@
+use std::{
+	sync::OnceLock,
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+use ring::signature::{UnparsedPublicKey, ECDSA_P256_SHA256_FIXED};
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use sha2::{Digest, Sha256};
+use tracing::{debug, info, warn};
+use x509_parser::{certificate::X509Certificate, pem::Pem, time::ASN1Time};
+
+use crate::{
+	attestation::{
+		allowlist::measurement_allowlist,
+		backend::{attestation_backend, AttestationVerdict},
+		collateral::cached_collateral,
+		inspect::ATTRIBUTE_DEBUG,
+		ra::local_mrsigner,
+	},
+	chain::constants::{IAS_URL, PCCS_URL, QUOTE_VERIFICATION_MODE, SGX_ROOT_CA_FILE},
+};
+
+/* ---------------------------------------
+	DCAP QUOTE VERIFIER
+--------------------------------------- */
+
+// Peer enclaves prove their identity with an ECDSA (DCAP) quote, which is verified inside the
+// enclave instead of trusting a remote verifier only :
+// - the quote is signed by the attestation key of the quoting enclave (QE), the QE report binds
+//   this key and is signed by the PCK certificate of the platform
+// - the PCK certificate chain must end with the Intel SGX root CA, which is a trusted file of the
+//   manifest and part of the MRENCLAVE
+// - the collateral (TCB info and QE identity) is fetched from Intel PCS or a configured PCCS, it is
+//   signed by the Intel TCB signing certificate under the same root CA
+// - the TCB status of the platform is the first TCB level of the collateral which the PCK
+//   certificate reaches, the QE is checked against its identity the same way
+// - the MRENCLAVE or MRSIGNER of the quote must be in the allowlist of the command line and the
+//   admin quorum, the MRSIGNER of this enclave when none is configured, see allowlist
+// The verdict accepts the up-to-date platforms and those which only need a configuration or a
+// software hardening, out of date and revoked platforms are rejected. Debug enclaves, of which the
+// memory is readable by the host, are only accepted by the development builds, and mainnet builds
+// always enforce the verdict.
+// EPID platforms have no PCK certificate, their quotes are verified by the Intel Attestation
+// Service instead, see the attestation backends.
+
+static VERIFIER_CONFIG: OnceLock<VerifierConfig> = OnceLock::new();
+
+// Lengths and offsets of a version 3 ECDSA quote
+pub const QUOTE_HEADER_LENGTH: usize = 48;
+pub const REPORT_BODY_LENGTH: usize = 384;
+const QUOTE_SIGNED_LENGTH: usize = QUOTE_HEADER_LENGTH + REPORT_BODY_LENGTH;
+const ECDSA_SIGNATURE_LENGTH: usize = 64;
+const ECDSA_KEY_LENGTH: usize = 64;
+
+pub const QUOTE_VERSION: u16 = 3;
+const ATTESTATION_KEY_ECDSA_P256: u16 = 2;
+const TEE_TYPE_SGX: u32 = 0;
+// Certification data of the quote : PCK certificate chain in PEM
+pub const CERTIFICATION_PCK_CHAIN: u16 = 5;
+
+// SGX extension of the PCK certificate, 1.2.840.113741.1.13.1
+const OID_SGX_EXTENSION: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF8, 0x4D, 0x01, 0x0D, 0x01];
+// Children of the SGX extension
+const SGX_TCB: u8 = 2;
+const SGX_FMSPC: u8 = 4;
+// Children of the TCB, 1 to 16 are the CPUSVN components
+const SGX_TCB_PCESVN: u8 = 17;
+
+/// Mode of the quote verification of the peer enclaves
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum VerificationMode {
+	// Quotes are not verified in the enclave
+	OFF,
+	// Verdicts are logged, failed quotes are still accepted
+	REPORT,
+	// Failed quotes are rejected
+	ENFORCE,
+}
+
+/// Configuration of the quote verifier
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct VerifierConfig {
+	pub mode: VerificationMode,
+	// Intel PCS or PCCS base url of the collateral
+	pub pccs_url: String,
+	// Intel Attestation Service url of the EPID platforms
+	pub ias_url: String,
+	// Hex encoded measurements of the trusted enclaves
+	pub mrenclaves: Vec<String>,
+	pub mrsigners: Vec<String>,
+}
+
+impl Default for VerifierConfig {
+	fn default() -> Self {
+		VerifierConfig {
+			mode: if QUOTE_VERIFICATION_MODE == "enforce" {
+				VerificationMode::ENFORCE
+			} else {
+				VerificationMode::REPORT
+			},
+			pccs_url: PCCS_URL.to_string(),
+			ias_url: IAS_URL.to_string(),
+			mrenclaves: Vec::new(),
+			mrsigners: Vec::new(),
+		}
+	}
+}
+
+/// Parse the quote verifier configuration from the command line
+/// # Arguments
+/// * `mode` - "off", "report" or "enforce"
+/// * `pccs_url` - base url of the collateral
+/// * `ias_url` - base url of the EPID attestation reports
+/// * `mrenclaves` - trusted MRENCLAVE values, hex encoded
+/// * `mrsigners` - trusted MRSIGNER values, hex encoded
+pub fn parse_verifier_config(
+	mode: &str,
+	pccs_url: &str,
+	ias_url: &str,
+	mrenclaves: &[String],
+	mrsigners: &[String],
+) -> Result<VerifierConfig, anyhow::Error> {
+	let mode = match mode.trim().to_lowercase().as_str() {
+		"off" => VerificationMode::OFF,
+		"report" => VerificationMode::REPORT,
+		"enforce" => VerificationMode::ENFORCE,
+		_ =>
+			return Err(anyhow::anyhow!(
+				"QUOTE VERIFIER : unknown mode '{mode}', expected off, report or enforce"
+			)),
+	};
+
+	if cfg!(feature = "mainnet") && mode != VerificationMode::ENFORCE {
+		return Err(anyhow::anyhow!("QUOTE VERIFIER : mainnet enclaves only enforce the quotes"))
+	}
+
+	let pccs_url = pccs_url.trim().trim_end_matches('/');
+	if !pccs_url.starts_with("https://") {
+		return Err(anyhow::anyhow!("QUOTE VERIFIER : collateral url must be https"))
+	}
+
+	let ias_url = ias_url.trim().trim_end_matches('/');
+	if !ias_url.starts_with("https://") {
+		return Err(anyhow::anyhow!("QUOTE VERIFIER : attestation service url must be https"))
+	}
+
+	Ok(VerifierConfig {
+		mode,
+		pccs_url: pccs_url.to_string(),
+		ias_url: ias_url.to_string(),
+		mrenclaves: mrenclaves
+			.iter()
+			.map(|value| parse_measurement(value))
+			.collect::<Result<_, _>>()?,
+		mrsigners: mrsigners
+			.iter()
+			.map(|value| parse_measurement(value))
+			.collect::<Result<_, _>>()?,
+	})
+}
+
+/// Normalize a MRENCLAVE or MRSIGNER, 32 bytes lowercase hex without 0x prefix
+/// # Arguments
+/// * `value` - hex encoded measurement, with or without 0x prefix
+pub fn parse_measurement(value: &str) -> Result<String, anyhow::Error> {
+	let value = value.trim().trim_start_matches("0x").to_lowercase();
+	match hex::decode(&value) {
+		Ok(bytes) if bytes.len() == 32 => Ok(value),
+		_ => Err(anyhow::anyhow!("QUOTE VERIFIER : invalid measurement '{value}'")),
+	}
+}
+
+/// Set the quote verifier configuration, only once at startup
+pub fn set_verifier_config(config: VerifierConfig) -> Result<(), anyhow::Error> {
+	VERIFIER_CONFIG
+		.set(config)
+		.map_err(|_| anyhow::anyhow!("QUOTE VERIFIER : configuration is already set"))
+}
+
+/// Effective quote verifier configuration, the defaults if it is not configured
+pub fn verifier_config() -> &'static VerifierConfig {
+	VERIFIER_CONFIG.get_or_init(VerifierConfig::default)
+}
+
+/* ---------------------------------------
+	QUOTE PARSING
+--------------------------------------- */
+
+/// Little-endian reader of the quote structures
+struct QuoteReader<'a> {
+	bytes: &'a [u8],
+	offset: usize,
+}
+
+impl<'a> QuoteReader<'a> {
+	fn take(&mut self, length: usize) -> Result<&'a [u8], anyhow::Error> {
+		let end = self.offset.checked_add(length).filter(|end| *end <= self.bytes.len());
+		match end {
+			Some(end) => {
+				let field = &self.bytes[self.offset..end];
+				self.offset = end;
+				Ok(field)
+			},
+			None => Err(anyhow::anyhow!(
+				"QUOTE VERIFIER : quote is truncated at offset {}",
+				self.offset
+			)),
+		}
+	}
+
+	fn u16(&mut self) -> Result<u16, anyhow::Error> {
+		Ok(u16::from_le_bytes(self.take(2)?.try_into()?))
+	}
+
+	fn u32(&mut self) -> Result<u32, anyhow::Error> {
+		Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+	}
+}
+
+/// Report body of an enclave, in the quote or in the QE report
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReportBody {
+	pub cpu_svn: [u8; 16],
+	pub misc_select: u32,
+	pub attributes: [u8; 16],
+	pub mr_enclave: [u8; 32],
+	pub mr_signer: [u8; 32],
+	pub isv_prod_id: u16,
+	pub isv_svn: u16,
+	pub report_data: [u8; 64],
+}
+
+impl ReportBody {
+	/// Parse the 384 bytes of a report body
+	pub fn parse(bytes: &[u8]) -> Result<ReportBody, anyhow::Error> {
+		let mut reader = QuoteReader { bytes, offset: 0 };
+
+		let cpu_svn = reader.take(16)?.try_into()?;
+		let misc_select = reader.u32()?;
+		reader.take(28)?;
+		let attributes = reader.take(16)?.try_into()?;
+		let mr_enclave = reader.take(32)?.try_into()?;
+		reader.take(32)?;
+		let mr_signer = reader.take(32)?.try_into()?;
+		reader.take(96)?;
+		let isv_prod_id = reader.u16()?;
+		let isv_svn = reader.u16()?;
+		reader.take(60)?;
+		let report_data = reader.take(64)?.try_into()?;
+
+		Ok(ReportBody {
+			cpu_svn,
+			misc_select,
+			attributes,
+			mr_enclave,
+			mr_signer,
+			isv_prod_id,
+			isv_svn,
+			report_data,
+		})
+	}
+}
+
+/// ECDSA quote of an SGX enclave
+#[derive(Clone, Debug, PartialEq)]
+pub struct DcapQuote {
+	pub version: u16,
+	pub attestation_key_type: u16,
+	pub tee_type: u32,
+	pub qe_vendor_id: [u8; 16],
+	pub report: ReportBody,
+	// Header and report body, signed by the attestation key
+	pub signed_data: Vec<u8>,
+	pub isv_signature: [u8; ECDSA_SIGNATURE_LENGTH],
+	pub attestation_key: [u8; ECDSA_KEY_LENGTH],
+	pub qe_report: ReportBody,
+	// QE report body, signed by the PCK certificate
+	pub qe_report_data: Vec<u8>,
+	pub qe_report_signature: [u8; ECDSA_SIGNATURE_LENGTH],
+	pub qe_auth_data: Vec<u8>,
+	pub certification_data_type: u16,
+	pub certification_data: Vec<u8>,
+}
+
+/// Parse an ECDSA quote
+/// # Arguments
+/// * `quote` - bytes of the quote
+pub fn parse_quote(quote: &[u8]) -> Result<DcapQuote, anyhow::Error> {
+	let mut reader = QuoteReader { bytes: quote, offset: 0 };
+
+	let version = reader.u16()?;
+	let attestation_key_type = reader.u16()?;
+	let tee_type = reader.u32()?;
+	reader.take(8)?;
+	let qe_vendor_id = reader.take(16)?.try_into()?;
+	reader.take(20)?;
+	let report = ReportBody::parse(reader.take(REPORT_BODY_LENGTH)?)?;
+
+	let signature_length = reader.u32()? as usize;
+	let mut signature = QuoteReader { bytes: reader.take(signature_length)?, offset: 0 };
+
+	let isv_signature = signature.take(ECDSA_SIGNATURE_LENGTH)?.try_into()?;
+	let attestation_key = signature.take(ECDSA_KEY_LENGTH)?.try_into()?;
+	let qe_report_data = signature.take(REPORT_BODY_LENGTH)?.to_vec();
+	let qe_report = ReportBody::parse(&qe_report_data)?;
+	let qe_report_signature = signature.take(ECDSA_SIGNATURE_LENGTH)?.try_into()?;
+	let qe_auth_length = signature.u16()? as usize;
+	let qe_auth_data = signature.take(qe_auth_length)?.to_vec();
+	let certification_data_type = signature.u16()?;
+	let certification_length = signature.u32()? as usize;
+	let certification_data = signature.take(certification_length)?.to_vec();
+
+	Ok(DcapQuote {
+		version,
+		attestation_key_type,
+		tee_type,
+		qe_vendor_id,
+		report,
+		signed_data: quote[..QUOTE_SIGNED_LENGTH].to_vec(),
+		isv_signature,
+		attestation_key,
+		qe_report,
+		qe_report_data,
+		qe_report_signature,
+		qe_auth_data,
+		certification_data_type,
+		certification_data,
+	})
+}
+
+/* ---------------------------------------
+	CERTIFICATES AND SIGNATURES
+--------------------------------------- */
+
+/// Verify a raw P-256 signature (r || s)
+/// # Arguments
+/// * `public_key` - uncompressed point of the signer, with or without the 0x04 prefix
+/// * `message` - signed bytes
+/// * `signature` - 64 bytes signature
+fn verify_p256(public_key: &[u8], message: &[u8], signature: &[u8]) -> Result<(), anyhow::Error> {
+	let point = match public_key.len() {
+		ECDSA_KEY_LENGTH => [&[0x04u8][..], public_key].concat(),
+		_ => public_key.to_vec(),
+	};
+
+	UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, point)
+		.verify(message, signature)
+		.map_err(|_| anyhow::anyhow!("QUOTE VERIFIER : invalid ECDSA signature"))
+}
+
+/// DER certificates of a PEM chain, leaf first
+pub fn pem_chain(pem: &[u8]) -> Result<Vec<Vec<u8>>, anyhow::Error> {
+	Pem::iter_from_buffer(pem)
+		.map(|pem| {
+			pem.map(|pem| pem.contents).map_err(|err| {
+				anyhow::anyhow!("QUOTE VERIFIER : invalid PEM certificate : {err:?}")
+			})
+		})
+		.collect()
+}
+
+fn parse_certificate(der: &[u8]) -> Result<X509Certificate<'_>, anyhow::Error> {
+	x509_parser::parse_x509_certificate(der)
+		.map(|(_, certificate)| certificate)
+		.map_err(|err| anyhow::anyhow!("QUOTE VERIFIER : invalid certificate : {err:?}"))
+}
+
+/// Verify a certificate chain up to the trusted root CA
+/// # Arguments
+/// * `chain` - DER certificates, leaf first and root CA last
+/// * `root_ca` - DER of the trusted root CA
+/// * `now` - unix time of the verification
+/// # Returns
+/// * `Vec<u8>` - public key of the leaf certificate
+pub fn verify_chain(chain: &[Vec<u8>], root_ca: &[u8], now: i64) -> Result<Vec<u8>, anyhow::Error> {
+	match chain.last() {
+		Some(root) if chain.len() >= 2 && root.as_slice() == root_ca => (),
+		_ => return Err(anyhow::anyhow!("QUOTE VERIFIER : chain does not end with the root CA")),
+	}
+
+	let certificates =
+		chain.iter().map(|der| parse_certificate(der)).collect::<Result<Vec<_>, _>>()?;
+
+	for (index, certificate) in certificates.iter().enumerate() {
+		// The root CA is self-signed
+		let issuer = certificates.get(index + 1).unwrap_or(certificate);
+		certificate.verify_signature(Some(issuer.public_key())).map_err(|err| {
+			anyhow::anyhow!(
+				"QUOTE VERIFIER : invalid signature of {} : {err:?}",
+				certificate.subject()
+			)
+		})?;
+
+		if !certificate.validity().is_valid_at(ASN1Time::from_timestamp(now)) {
+			return Err(anyhow::anyhow!("QUOTE VERIFIER : {} is expired", certificate.subject()))
+		}
+
+		if index > 0 && !certificate.is_ca() {
+			return Err(anyhow::anyhow!("QUOTE VERIFIER : {} is not a CA", certificate.subject()))
+		}
+	}
+
+	Ok(certificates[0].public_key().subject_public_key.data.to_vec())
+}
+
+/// DER items of a constructed value : (tag, content)
+fn der_items(mut bytes: &[u8]) -> Result<Vec<(u8, &[u8])>, anyhow::Error> {
+	let invalid = || anyhow::anyhow!("QUOTE VERIFIER : invalid SGX extension");
+	let mut items = Vec::new();
+
+	while !bytes.is_empty() {
+		let tag = bytes[0];
+		let first = *bytes.get(1).ok_or_else(invalid)? as usize;
+		let (length, header) = match first {
+			0..=0x7F => (first, 2),
+			0x81..=0x84 => {
+				let size = first - 0x80;
+				let length = bytes
+					.get(2..2 + size)
+					.ok_or_else(invalid)?
+					.iter()
+					.fold(0usize, |length, byte| (length << 8) | *byte as usize);
+				(length, 2 + size)
+			},
+			_ => return Err(invalid()),
+		};
+
+		let content = bytes.get(header..header + length).ok_or_else(invalid)?;
+		items.push((tag, content));
+		bytes = &bytes[header + length..];
+	}
+
+	Ok(items)
+}
+
+/// Values of a DER sequence of (OID, value) pairs, by the last arc of their OID
+fn sgx_values(sequence: &[u8]) -> Result<Vec<(u8, (u8, &[u8]))>, anyhow::Error> {
+	let mut values = Vec::new();
+	for (_, pair) in der_items(sequence)? {
+		if let [(0x06, oid), value] = der_items(pair)?.as_slice() {
+			if let Some(arc) = oid.last() {
+				values.push((*arc, *value));
+			}
+		}
+	}
+
+	Ok(values)
+}
+
+/// TCB of the platform, from the SGX extension of its PCK certificate
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct PlatformTcb {
+	pub fmspc: String,
+	pub cpu_svn_components: [u8; 16],
+	pub pce_svn: u16,
+}
+
+/// Read the platform TCB of a PCK certificate
+fn platform_tcb(pck_certificate: &X509Certificate) -> Result<PlatformTcb, anyhow::Error> {
+	let extension = pck_certificate
+		.extensions()
+		.iter()
+		.find(|extension| extension.oid.as_bytes() == OID_SGX_EXTENSION)
+		.ok_or_else(|| anyhow::anyhow!("QUOTE VERIFIER : PCK certificate has no SGX extension"))?;
+
+	let integer =
+		|content: &[u8]| content.iter().fold(0u16, |value, byte| (value << 8) | *byte as u16);
+
+	let mut fmspc = None;
+	let mut cpu_svn_components = [0u8; 16];
+	let mut pce_svn = None;
+
+	let outer = der_items(extension.value)?;
+	let sequence = match outer.first() {
+		Some((0x30, sequence)) => *sequence,
+		_ => return Err(anyhow::anyhow!("QUOTE VERIFIER : invalid SGX extension")),
+	};
+
+	for (arc, (_, content)) in sgx_values(sequence)? {
+		match arc {
+			SGX_FMSPC => fmspc = Some(hex::encode_upper(content)),
+			SGX_TCB =>
+				for (component, (tag, value)) in sgx_values(content)? {
+					match component {
+						1..=16 if tag == 0x02 =>
+							cpu_svn_components[component as usize - 1] = integer(value) as u8,
+						SGX_TCB_PCESVN if tag == 0x02 => pce_svn = Some(integer(value)),
+						_ => (),
+					}
+				},
+			_ => (),
+		}
+	}
+
+	match (fmspc, pce_svn) {
+		(Some(fmspc), Some(pce_svn)) => Ok(PlatformTcb { fmspc, cpu_svn_components, pce_svn }),
+		_ => Err(anyhow::anyhow!("QUOTE VERIFIER : SGX extension has no FMSPC or PCESVN")),
+	}
+}
+
+/// Platform TCB of the PCK certificate of a quote, the certificate chain is not verified
+/// # Arguments
+/// * `certification_data` - PCK certificate chain in PEM, certification data of type 5
+pub fn pck_platform_tcb(certification_data: &[u8]) -> Result<PlatformTcb, anyhow::Error> {
+	let pck_chain = pem_chain(certification_data)?;
+	let pck_certificate = pck_chain
+		.first()
+		.ok_or_else(|| anyhow::anyhow!("QUOTE VERIFIER : certification data has no certificate"))?;
+	platform_tcb(&parse_certificate(pck_certificate)?)
+}
+
+/* ---------------------------------------
+	COLLATERAL
+--------------------------------------- */
+
+/// TCB status of a platform or a quoting enclave
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum TcbStatus {
+	UPTODATE,
+	SWHARDENINGNEEDED,
+	CONFIGURATIONNEEDED,
+	CONFIGURATIONANDSWHARDENINGNEEDED,
+	OUTOFDATE,
+	OUTOFDATECONFIGURATIONNEEDED,
+	REVOKED,
+	UNRECOGNIZED,
+}
+
+impl TcbStatus {
+	/// Status of the collateral, i.e. "SWHardeningNeeded"
+	pub fn parse(status: &str) -> TcbStatus {
+		match status {
+			"UpToDate" => TcbStatus::UPTODATE,
+			"SWHardeningNeeded" => TcbStatus::SWHARDENINGNEEDED,
+			"ConfigurationNeeded" => TcbStatus::CONFIGURATIONNEEDED,
+			"ConfigurationAndSWHardeningNeeded" => TcbStatus::CONFIGURATIONANDSWHARDENINGNEEDED,
+			"OutOfDate" => TcbStatus::OUTOFDATE,
+			"OutOfDateConfigurationNeeded" => TcbStatus::OUTOFDATECONFIGURATIONNEEDED,
+			"Revoked" => TcbStatus::REVOKED,
+			_ => TcbStatus::UNRECOGNIZED,
+		}
+	}
+
+	/// Platforms which only need a configuration or a software hardening are trusted
+	pub fn is_acceptable(&self) -> bool {
+		matches!(
+			self,
+			TcbStatus::UPTODATE |
+				TcbStatus::SWHARDENINGNEEDED |
+				TcbStatus::CONFIGURATIONNEEDED |
+				TcbStatus::CONFIGURATIONANDSWHARDENINGNEEDED
+		)
+	}
+}
+
+/// Signed collateral of a platform, as returned by the PCS
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Collateral {
+	// {"tcbInfo": {...}, "signature": "..."}
+	pub tcb_info: String,
+	pub tcb_info_issuer_chain: String,
+	// {"enclaveIdentity": {...}, "signature": "..."}
+	pub qe_identity: String,
+	pub qe_identity_issuer_chain: String,
+}
+
+#[derive(Deserialize)]
+struct SignedTcbInfo<'a> {
+	#[serde(borrow, rename = "tcbInfo")]
+	tcb_info: &'a RawValue,
+	signature: String,
+}
+
+#[derive(Deserialize)]
+struct SignedQeIdentity<'a> {
+	#[serde(borrow, rename = "enclaveIdentity")]
+	enclave_identity: &'a RawValue,
+	signature: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct TcbInfo {
+	fmspc: String,
+	next_update: String,
+	tcb_levels: Vec<TcbLevel>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct TcbLevel {
+	tcb: PlatformTcbLevel,
+	tcb_status: String,
+	#[serde(default, rename = "advisoryIDs")]
+	advisory_ids: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct PlatformTcbLevel {
+	sgxtcbcomponents: Vec<TcbComponent>,
+	pcesvn: u16,
+}
+
+#[derive(Deserialize, Debug)]
+struct TcbComponent {
+	svn: u8,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct QeIdentity {
+	next_update: String,
+	miscselect: String,
+	miscselect_mask: String,
+	attributes: String,
+	attributes_mask: String,
+	mrsigner: String,
+	isvprodid: u16,
+	tcb_levels: Vec<QeTcbLevel>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct QeTcbLevel {
+	tcb: QeTcb,
+	tcb_status: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct QeTcb {
+	isvsvn: u16,
+}
+
+/// Fetch the collateral of a platform from the PCS or PCCS
+/// # Arguments
+/// * `client` - http client of the enclave
+/// * `pccs_url` - base url of the collateral
+/// * `fmspc` - FMSPC of the platform, hex encoded
+pub async fn fetch_collateral(
+	client: &reqwest::Client,
+	pccs_url: &str,
+	fmspc: &str,
+) -> Result<Collateral, anyhow::Error> {
+	let fetch = move |url: String, issuer_header: &'static str| async move {
+		let response = client.get(&url).send().await?.error_for_status()?;
+		let issuer_chain = response
+			.headers()
+			.get(issuer_header)
+			.and_then(|chain| chain.to_str().ok())
+			.map(|chain| urlencoding::decode(chain).map(|chain| chain.into_owned()))
+			.ok_or_else(|| anyhow::anyhow!("QUOTE VERIFIER : {url} has no {issuer_header}"))??;
+
+		Ok::<(String, String), anyhow::Error>((response.text().await?, issuer_chain))
+	};
+
+	debug!("QUOTE VERIFIER : fetch the collateral of FMSPC {fmspc} from {pccs_url}");
+	let (tcb_info, tcb_info_issuer_chain) = fetch(
+		format!("{pccs_url}/sgx/certification/v4/tcb?fmspc={fmspc}"),
+		"TCB-Info-Issuer-Chain",
+	)
+	.await?;
+	let (qe_identity, qe_identity_issuer_chain) = fetch(
+		format!("{pccs_url}/sgx/certification/v4/qe/identity"),
+		"SGX-Enclave-Identity-Issuer-Chain",
+	)
+	.await?;
+
+	Ok(Collateral { tcb_info, tcb_info_issuer_chain, qe_identity, qe_identity_issuer_chain })
+}
+
+/// Verify the signature of a collateral document, its issuer chain must end with the root CA
+fn verify_collateral_signature(
+	body: &RawValue,
+	signature: &str,
+	issuer_chain: &str,
+	root_ca: &[u8],
+	now: i64,
+) -> Result<(), anyhow::Error> {
+	let signer = verify_chain(&pem_chain(issuer_chain.as_bytes())?, root_ca, now)?;
+	let signature = hex::decode(signature)?;
+	verify_p256(&signer, body.get().as_bytes(), &signature)
+}
+
+fn check_next_update(document: &str, next_update: &str, now: i64) -> Result<(), anyhow::Error> {
+	let next_update = chrono::DateTime::parse_from_rfc3339(next_update)?;
+	if next_update.timestamp() < now {
+		return Err(anyhow::anyhow!("QUOTE VERIFIER : {document} expired at {next_update}"))
+	}
+
+	Ok(())
+}
+
+/// Whether the masked value of a report matches the value of the QE identity
+fn masked_match(value: &[u8], expected: &str, mask: &str) -> Result<bool, anyhow::Error> {
+	let expected = hex::decode(expected)?;
+	let mask = hex::decode(mask)?;
+	if expected.len() != value.len() || mask.len() != value.len() {
+		return Err(anyhow::anyhow!("QUOTE VERIFIER : invalid QE identity mask"))
+	}
+
+	Ok(value
+		.iter()
+		.zip(mask.iter())
+		.zip(expected.iter())
+		.all(|((v, m), e)| v & m == *e))
+}
+
+/* ---------------------------------------
+	VERDICT
+--------------------------------------- */
+
+/// Verdict of a quote verification
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct QuoteVerdict {
+	pub mrenclave: String,
+	pub mrsigner: String,
+	pub isv_prod_id: u16,
+	pub isv_svn: u16,
+	pub report_data: String,
+	pub platform: PlatformTcb,
+	pub tcb_status: TcbStatus,
+	pub advisory_ids: Vec<String>,
+	pub qe_tcb_status: TcbStatus,
+	// MRENCLAVE or MRSIGNER is in the allowlist
+	pub trusted_measurement: bool,
+	// DEBUG attribute of the enclave report
+	pub debug: bool,
+	pub accepted: bool,
+}
+
+/// Whether the debug peer enclaves are accepted, only by the development builds
+pub fn accepts_debug_enclaves() -> bool {
+	!cfg!(any(feature = "mainnet", feature = "alphanet"))
+}
+
+/// Whether the report of an enclave has the DEBUG attribute
+/// # Arguments
+/// * `report` - report body of the quote
+pub fn is_debug_report(report: &ReportBody) -> bool {
+	report.attributes[0] & ATTRIBUTE_DEBUG != 0
+}
+
+/// Verify a quote against its collateral
+/// # Arguments
+/// * `quote` - bytes of the ECDSA quote
+/// * `collateral` - TCB info and QE identity of the platform
+/// * `root_ca` - DER of the Intel SGX root CA
+/// * `now` - unix time of the verification
+pub fn verify_quote(
+	quote: &[u8],
+	collateral: &Collateral,
+	root_ca: &[u8],
+	now: i64,
+) -> Result<QuoteVerdict, anyhow::Error> {
+	let quote = parse_quote(quote)?;
+	if quote.version != QUOTE_VERSION ||
+		quote.attestation_key_type != ATTESTATION_KEY_ECDSA_P256 ||
+		quote.tee_type != TEE_TYPE_SGX
+	{
+		return Err(anyhow::anyhow!(
+			"QUOTE VERIFIER : unsupported quote version {} with key type {}",
+			quote.version,
+			quote.attestation_key_type
+		))
+	}
+
+	if quote.certification_data_type != CERTIFICATION_PCK_CHAIN {
+		return Err(anyhow::anyhow!(
+			"QUOTE VERIFIER : unsupported certification data type {}",
+			quote.certification_data_type
+		))
+	}
+
+	// PCK certificate of the platform signs the QE report
+	let pck_chain = pem_chain(&quote.certification_data)?;
+	let pck_key = verify_chain(&pck_chain, root_ca, now)?;
+	verify_p256(&pck_key, &quote.qe_report_data, &quote.qe_report_signature)?;
+
+	// QE report binds the attestation key
+	let key_hash = Sha256::digest([&quote.attestation_key[..], &quote.qe_auth_data[..]].concat());
+	if quote.qe_report.report_data[..32] != key_hash[..] ||
+		quote.qe_report.report_data[32..].iter().any(|byte| *byte != 0)
+	{
+		return Err(anyhow::anyhow!("QUOTE VERIFIER : QE report does not bind the attestation key"))
+	}
+
+	// Attestation key signs the enclave report
+	verify_p256(&quote.attestation_key, &quote.signed_data, &quote.isv_signature)?;
+
+	let platform = platform_tcb(&parse_certificate(&pck_chain[0])?)?;
+
+	// TCB status of the platform
+	let signed_tcb_info: SignedTcbInfo = serde_json::from_str(&collateral.tcb_info)?;
+	verify_collateral_signature(
+		signed_tcb_info.tcb_info,
+		&signed_tcb_info.signature,
+		&collateral.tcb_info_issuer_chain,
+		root_ca,
+		now,
+	)?;
+	let tcb_info: TcbInfo = serde_json::from_str(signed_tcb_info.tcb_info.get())?;
+	check_next_update("TCB info", &tcb_info.next_update, now)?;
+
+	if !tcb_info.fmspc.eq_ignore_ascii_case(&platform.fmspc) {
+		return Err(anyhow::anyhow!(
+			"QUOTE VERIFIER : TCB info of FMSPC {} for a platform of FMSPC {}",
+			tcb_info.fmspc,
+			platform.fmspc
+		))
+	}
+
+	let tcb_level = tcb_info.tcb_levels.iter().find(|level| {
+		level.tcb.sgxtcbcomponents.len() == 16 &&
+			level
+				.tcb
+				.sgxtcbcomponents
+				.iter()
+				.zip(platform.cpu_svn_components.iter())
+				.all(|(component, svn)| *svn >= component.svn) &&
+			platform.pce_svn >= level.tcb.pcesvn
+	});
+	let (tcb_status, advisory_ids) = match tcb_level {
+		Some(level) => (TcbStatus::parse(&level.tcb_status), level.advisory_ids.clone()),
+		None => (TcbStatus::UNRECOGNIZED, Vec::new()),
+	};
+
+	// TCB status of the quoting enclave
+	let signed_qe_identity: SignedQeIdentity = serde_json::from_str(&collateral.qe_identity)?;
+	verify_collateral_signature(
+		signed_qe_identity.enclave_identity,
+		&signed_qe_identity.signature,
+		&collateral.qe_identity_issuer_chain,
+		root_ca,
+		now,
+	)?;
+	let qe_identity: QeIdentity = serde_json::from_str(signed_qe_identity.enclave_identity.get())?;
+	check_next_update("QE identity", &qe_identity.next_update, now)?;
+
+	let qe = &quote.qe_report;
+	let qe_matches =
+		hex::encode(qe.mr_signer).eq_ignore_ascii_case(&qe_identity.mrsigner) &&
+			qe.isv_prod_id == qe_identity.isvprodid &&
+			masked_match(
+				&qe.misc_select.to_le_bytes(),
+				&qe_identity.miscselect,
+				&qe_identity.miscselect_mask,
+			)? && masked_match(&qe.attributes, &qe_identity.attributes, &qe_identity.attributes_mask)?;
+	if !qe_matches {
+		return Err(anyhow::anyhow!("QUOTE VERIFIER : QE report does not match the QE identity"))
+	}
+
+	let qe_tcb_status = qe_identity
+		.tcb_levels
+		.iter()
+		.find(|level| qe.isv_svn >= level.tcb.isvsvn)
+		.map_or(TcbStatus::UNRECOGNIZED, |level| TcbStatus::parse(&level.tcb_status));
+
+	// Measurements of the enclave
+	let mrenclave = hex::encode(quote.report.mr_enclave);
+	let mrsigner = hex::encode(quote.report.mr_signer);
+	let trusted_measurement = is_trusted_measurement(&mrenclave, &mrsigner);
+	let debug = is_debug_report(&quote.report);
+
+	Ok(QuoteVerdict {
+		accepted: trusted_measurement &&
+			(!debug || accepts_debug_enclaves()) &&
+			tcb_status.is_acceptable() &&
+			qe_tcb_status.is_acceptable(),
+		mrenclave,
+		mrsigner,
+		isv_prod_id: quote.report.isv_prod_id,
+		isv_svn: quote.report.isv_svn,
+		report_data: hex::encode(quote.report.report_data),
+		platform,
+		tcb_status,
+		advisory_ids,
+		qe_tcb_status,
+		trusted_measurement,
+		debug,
+	})
+}
+
+/// Whether the measurements of an enclave are in the allowlist, the MRSIGNER of this enclave
+/// is trusted when the allowlist is empty, see MeasurementAllowlist
+pub fn is_trusted_measurement(mrenclave: &str, mrsigner: &str) -> bool {
+	measurement_allowlist().is_trusted(
+		verifier_config(),
+		local_mrsigner().as_deref(),
+		mrenclave,
+		mrsigner,
+	)
+}
+
+/// DER of the Intel SGX root CA, a trusted file of the manifest
+pub fn load_root_ca() -> Result<Vec<u8>, anyhow::Error> {
+	let pem = std::fs::read(SGX_ROOT_CA_FILE)?;
+	pem_chain(&pem)?
+		.into_iter()
+		.next()
+		.ok_or_else(|| anyhow::anyhow!("QUOTE VERIFIER : {SGX_ROOT_CA_FILE} has no certificate"))
+}
+
+/// Unix time of the verifications, in seconds
+pub fn current_time() -> i64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|elapsed| elapsed.as_secs() as i64)
+		.unwrap_or(0)
+}
+
+/// Verdict of a quote with the collateral of its platform, accepted or not
+/// # Arguments
+/// * `client` - http client of the enclave
+/// * `quote` - bytes of the quote
+pub async fn quote_verdict(
+	client: &reqwest::Client,
+	quote: &[u8],
+) -> Result<QuoteVerdict, anyhow::Error> {
+	let root_ca = load_root_ca()?;
+
+	let pck_chain = pem_chain(&parse_quote(quote)?.certification_data)?;
+	let pck_certificate = pck_chain
+		.first()
+		.ok_or_else(|| anyhow::anyhow!("QUOTE VERIFIER : quote has no PCK certificate"))?;
+	let platform = platform_tcb(&parse_certificate(pck_certificate)?)?;
+
+	let now = current_time();
+	let collateral =
+		cached_collateral(client, &verifier_config().pccs_url, &platform.fmspc, now).await?;
+	verify_quote(quote, &collateral, &root_ca, now)
+}
+
+/// Verify the quote of a peer enclave with the attestation backend of the platform
+/// # Arguments
+/// * `client` - http client of the enclave
+/// * `quote` - hex encoded quote of the peer
+/// # Returns
+/// * `AttestationVerdict` - verdict of an accepted quote, an error with the reason otherwise
+pub async fn verify_peer_quote(
+	client: &reqwest::Client,
+	quote: &str,
+) -> Result<AttestationVerdict, anyhow::Error> {
+	let quote = hex::decode(quote.trim_start_matches("0x"))?;
+	let backend = attestation_backend();
+	let verdict = backend.verify(client, &quote).await?;
+
+	if !verdict.accepted {
+		return Err(anyhow::anyhow!(
+			"QUOTE VERIFIER : {:?} quote is rejected, trusted measurement : {}, debug : {}, TCB status : {:?}, QE TCB status : {:?}",
+			backend.attestation_type(),
+			verdict.trusted_measurement,
+			verdict.debug,
+			verdict.tcb_status,
+			verdict.qe_tcb_status
+		))
+	}
+
+	Ok(verdict)
+}
+
+/// Quote check of the peer synchronization, by the configured mode
+/// # Arguments
+/// * `client` - http client of the enclave
+/// * `peer` - url of the peer enclave
+/// * `quote` - hex encoded quote of the peer
+/// # Returns
+/// * `Option<AttestationVerdict>` - verdict of the quote, None if it is not verified or failed in
+///   REPORT mode, an error with the reason in ENFORCE mode
+pub async fn check_peer_quote(
+	client: &reqwest::Client,
+	peer: &str,
+	quote: &str,
+) -> Result<Option<AttestationVerdict>, String> {
+	let mode = verifier_config().mode;
+	if mode == VerificationMode::OFF {
+		return Ok(None)
+	}
+
+	match verify_peer_quote(client, quote).await {
+		Ok(verdict) => {
+			info!(
+				"QUOTE VERIFIER : quote of {peer} is accepted, MRENCLAVE {}, TCB status {:?}",
+				verdict.mrenclave, verdict.tcb_status
+			);
+			Ok(Some(verdict))
+		},
+
+		Err(err) if mode == VerificationMode::REPORT => {
+			warn!("QUOTE VERIFIER : quote of {peer} failed, accepted in REPORT mode : {err:?}");
+			Ok(None)
+		},
+
+		Err(err) => Err(format!("QUOTE VERIFIER : quote of {peer} is rejected : {err}")),
+	}
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn test_quote() -> Vec<u8> {
+		let mut quote = Vec::new();
+		quote.extend_from_slice(&QUOTE_VERSION.to_le_bytes());
+		quote.extend_from_slice(&ATTESTATION_KEY_ECDSA_P256.to_le_bytes());
+		quote.extend_from_slice(&TEE_TYPE_SGX.to_le_bytes());
+		quote.extend_from_slice(&[0u8; 8]);
+		quote.extend_from_slice(&[0x93u8; 16]);
+		quote.extend_from_slice(&[0u8; 20]);
+
+		let mut report = vec![0u8; REPORT_BODY_LENGTH];
+		report[64..96].copy_from_slice(&[0xAAu8; 32]);
+		report[128..160].copy_from_slice(&[0xBBu8; 32]);
+		report[256..258].copy_from_slice(&12u16.to_le_bytes());
+		report[258..260].copy_from_slice(&103u16.to_le_bytes());
+		report[320..384].copy_from_slice(&[0x11u8; 64]);
+		quote.extend_from_slice(&report);
+
+		let mut signature = Vec::new();
+		signature.extend_from_slice(&[1u8; ECDSA_SIGNATURE_LENGTH]);
+		signature.extend_from_slice(&[2u8; ECDSA_KEY_LENGTH]);
+		signature.extend_from_slice(&[0u8; REPORT_BODY_LENGTH]);
+		signature.extend_from_slice(&[3u8; ECDSA_SIGNATURE_LENGTH]);
+		signature.extend_from_slice(&2u16.to_le_bytes());
+		signature.extend_from_slice(&[4u8; 2]);
+		signature.extend_from_slice(&CERTIFICATION_PCK_CHAIN.to_le_bytes());
+		signature.extend_from_slice(&3u32.to_le_bytes());
+		signature.extend_from_slice(b"PEM");
+
+		quote.extend_from_slice(&(signature.len() as u32).to_le_bytes());
+		quote.extend_from_slice(&signature);
+		quote
+	}
+
+	#[test]
+	fn parse_quote_test() {
+		let bytes = test_quote();
+		let quote = parse_quote(&bytes).unwrap();
+
+		assert_eq!(quote.version, QUOTE_VERSION);
+		assert_eq!(quote.report.mr_enclave, [0xAAu8; 32]);
+		assert_eq!(quote.report.mr_signer, [0xBBu8; 32]);
+		assert_eq!(quote.report.isv_prod_id, 12);
+		assert_eq!(quote.report.isv_svn, 103);
+		assert_eq!(quote.report.report_data, [0x11u8; 64]);
+		assert_eq!(quote.signed_data.len(), QUOTE_SIGNED_LENGTH);
+		assert_eq!(quote.attestation_key, [2u8; ECDSA_KEY_LENGTH]);
+		assert_eq!(quote.qe_auth_data, vec![4u8; 2]);
+		assert_eq!(quote.certification_data, b"PEM".to_vec());
+		assert!(!is_debug_report(&quote.report));
+
+		let mut debug_bytes = bytes.clone();
+		debug_bytes[QUOTE_HEADER_LENGTH + 48] |= ATTRIBUTE_DEBUG;
+		assert!(is_debug_report(&parse_quote(&debug_bytes).unwrap().report));
+
+		// Offsets of the quote generation
+		use crate::attestation::ra::{QUOTE_MRENCLAVE_OFFSET, QUOTE_REPORT_DATA_OFFSET};
+		assert_eq!(bytes[QUOTE_MRENCLAVE_OFFSET], 0xAA);
+		assert_eq!(bytes[QUOTE_REPORT_DATA_OFFSET], 0x11);
+
+		assert!(parse_quote(&bytes[..bytes.len() - 1]).is_err());
+		assert!(parse_quote(&bytes[..100]).is_err());
+	}
+
+	#[test]
+	fn sgx_extension_test() {
+		// SEQUENCE { SEQUENCE { OID .2, SEQUENCE { SEQUENCE { OID .2.1, INTEGER 5 },
+		// SEQUENCE { OID .2.17, INTEGER 13 } } }, SEQUENCE { OID .4, OCTET STRING 00906ED50000 } }
+		let oid = |arcs: &[u8]| {
+			[&[0x06, (OID_SGX_EXTENSION.len() + arcs.len()) as u8][..], OID_SGX_EXTENSION, arcs]
+				.concat()
+		};
+		let sequence = |content: Vec<u8>| [vec![0x30, content.len() as u8], content].concat();
+
+		let component = sequence([oid(&[SGX_TCB, 1]), vec![0x02, 0x01, 0x05]].concat());
+		let pce_svn = sequence([oid(&[SGX_TCB, SGX_TCB_PCESVN]), vec![0x02, 0x01, 0x0D]].concat());
+		let tcb = sequence([oid(&[SGX_TCB]), sequence([component, pce_svn].concat())].concat());
+		let fmspc = sequence(
+			[oid(&[SGX_FMSPC]), vec![0x04, 0x06, 0x00, 0x90, 0x6E, 0xD5, 0x00, 0x00]].concat(),
+		);
+		let extension = sequence([tcb, fmspc].concat());
+
+		let outer = der_items(&extension).unwrap();
+		let values = sgx_values(outer[0].1).unwrap();
+		assert_eq!(values.len(), 2);
+		assert_eq!(values[1], (SGX_FMSPC, (0x04, &[0x00, 0x90, 0x6E, 0xD5, 0x00, 0x00][..])));
+
+		let components = sgx_values(values[0].1 .1).unwrap();
+		assert_eq!(
+			components,
+			vec![(1, (0x02, &[0x05][..])), (SGX_TCB_PCESVN, (0x02, &[0x0D][..]))]
+		);
+
+		assert!(der_items(&[0x30, 0x05, 0x00]).is_err());
+	}
+
+	#[test]
+	fn tcb_status_test() {
+		assert_eq!(TcbStatus::parse("SWHardeningNeeded"), TcbStatus::SWHARDENINGNEEDED);
+		assert!(TcbStatus::parse("UpToDate").is_acceptable());
+		assert!(TcbStatus::parse("ConfigurationAndSWHardeningNeeded").is_acceptable());
+		assert!(!TcbStatus::parse("OutOfDate").is_acceptable());
+		assert!(!TcbStatus::parse("Revoked").is_acceptable());
+		assert!(!TcbStatus::parse("Unknown").is_acceptable());
+
+		assert!(masked_match(&[0x15, 0xFF], "1100", "FB00").unwrap());
+		assert!(!masked_match(&[0x11, 0x00], "1500", "FF00").unwrap());
+		assert!(masked_match(&[0x11], "1100", "FF00").is_err());
+	}
+
+	#[test]
+	fn verifier_config_test() {
+		let measurement = "AA".repeat(32);
+		let config = parse_verifier_config(
+			"Enforce",
+			"https://pccs.example.com/",
+			IAS_URL,
+			&[format!("0x{measurement}")],
+			&[],
+		)
+		.unwrap();
+		assert_eq!(config.mode, VerificationMode::ENFORCE);
+		assert_eq!(config.pccs_url, "https://pccs.example.com");
+		assert_eq!(config.mrenclaves, vec!["aa".repeat(32)]);
+
+		assert!(parse_verifier_config("strict", PCCS_URL, IAS_URL, &[], &[]).is_err());
+		assert_eq!(
+			parse_verifier_config("report", PCCS_URL, IAS_URL, &[], &[]).is_ok(),
+			!cfg!(feature = "mainnet")
+		);
+		assert!(parse_verifier_config("report", "http://pccs.local", IAS_URL, &[], &[]).is_err());
+		assert!(parse_verifier_config("report", PCCS_URL, "http://ias.local", &[], &[]).is_err());
+		assert!(
+			parse_verifier_config("report", PCCS_URL, IAS_URL, &["aa".to_string()], &[]).is_err()
+		);
+	}
+}
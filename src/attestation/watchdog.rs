@@ -0,0 +1,414 @@
+use std::{
+	sync::{Mutex, OnceLock},
+	time::Duration,
+};
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use subxt::{ext::sp_core::Pair, utils::AccountId32};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+	attestation::{
+		backend::attestation_backend,
+		ra::{create_quote, QUOTE_REPORT_DATA_OFFSET},
+		verifier::TcbStatus,
+	},
+	chain::{
+		constants::{QUOTE_FINGERPRINT_FILE, QUOTE_REFRESH_INTERVAL},
+		core::{get_enclave_operator, submit_signed_extrinsic, system_remark_oracle},
+	},
+	servers::{
+		auth::VerifiedCaller,
+		proxy::with_http_proxy,
+		state::{get_identity, get_keypair, SharedState},
+	},
+};
+
+/* ---------------------------------------
+	QUOTE REFRESH AND TCB WATCHDOG
+--------------------------------------- */
+
+// A quote goes stale when the platform TCB is updated (microcode, SGX PSW) or when Intel
+// publishes a new TCB level for it. The watchdog refreshes the quote periodically :
+// - a new quote is generated and its TCB status is evaluated by the attestation backend
+// - "SWHardeningNeeded", "ConfigurationNeeded" and out of date platforms are flagged in the health
+//   report, the enclave is DEGRADED when the TCB status is no longer acceptable
+// - the fingerprint of the quote, its header and report body without the report data, changes with
+//   the platform TCB or the enclave measurements
+// - on a new fingerprint, a registered enclave announces "TEE-REREGISTER:FINGERPRINT:TCBSTATUS" on
+//   chain and its re-registration is pending
+// - the tee pallet only accepts registration updates signed by the operator : the operator signs
+//   the update, the admin quorum hands it to the enclave, which submits it and checks that the
+//   enclave is still registered once it is finalized
+// The fingerprint of the last registration is persisted, a restart after a platform update is
+// also announced.
+
+static QUOTE_REFRESH: OnceLock<u64> = OnceLock::new();
+static TCB_HEALTH: Mutex<Option<TcbHealth>> = Mutex::new(None);
+// Fingerprint of the last announcement, announced once per new quote
+static ANNOUNCED: Mutex<Option<String>> = Mutex::new(None);
+
+/// TCB of the enclave platform, from the last quote refresh
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TcbHealth {
+	pub checked_block: u32,
+	// Hex encoded sha256 of the quote without its report data
+	pub fingerprint: String,
+	// None if the collateral could not be evaluated, see error
+	pub tcb_status: Option<TcbStatus>,
+	pub advisory_ids: Vec<String>,
+	pub error: Option<String>,
+	// Block of the last fingerprint change
+	pub changed_block: Option<u32>,
+	// The fingerprint is not registered yet, the registration update is awaited
+	#[serde(default)]
+	pub reregistration_pending: bool,
+}
+
+impl TcbHealth {
+	/// Platform update recommended by Intel, the quote is still accepted
+	pub fn recovery_needed(&self) -> bool {
+		matches!(self.tcb_status, Some(status) if status != TcbStatus::UPTODATE)
+	}
+
+	/// TCB status which verifiers reject
+	pub fn is_unacceptable(&self) -> bool {
+		matches!(self.tcb_status, Some(status) if !status.is_acceptable())
+	}
+}
+
+/// Set the seconds between quote refreshes, 0 disables the watchdog
+pub fn set_quote_refresh_interval(interval: u64) -> Result<(), anyhow::Error> {
+	QUOTE_REFRESH
+		.set(interval)
+		.map_err(|_| anyhow::anyhow!("QUOTE WATCHDOG : configuration is already set"))
+}
+
+/// Seconds between quote refreshes, 0 if the watchdog is disabled
+pub fn quote_refresh_interval() -> u64 {
+	*QUOTE_REFRESH.get_or_init(|| QUOTE_REFRESH_INTERVAL)
+}
+
+/// TCB of the last quote refresh, None before the first refresh
+pub fn tcb_health() -> Option<TcbHealth> {
+	TCB_HEALTH.lock().ok().and_then(|health| health.clone())
+}
+
+fn record_tcb_health(health: TcbHealth) {
+	match TCB_HEALTH.lock() {
+		Ok(mut last) => *last = Some(health),
+		Err(err) => error!("QUOTE WATCHDOG : lock error : {err:?}"),
+	}
+}
+
+fn clear_reregistration(fingerprint: &str) {
+	match TCB_HEALTH.lock() {
+		Ok(mut last) =>
+			if let Some(health) = last.as_mut().filter(|health| health.fingerprint == fingerprint) {
+				health.reregistration_pending = false;
+			},
+		Err(err) => error!("QUOTE WATCHDOG : lock error : {err:?}"),
+	}
+}
+
+fn is_announced(fingerprint: &str) -> bool {
+	match ANNOUNCED.lock() {
+		Ok(announced) => announced.as_deref() == Some(fingerprint),
+		Err(err) => {
+			error!("QUOTE WATCHDOG : lock error : {err:?}");
+			false
+		},
+	}
+}
+
+fn set_announced(fingerprint: &str) {
+	match ANNOUNCED.lock() {
+		Ok(mut announced) => *announced = Some(fingerprint.to_string()),
+		Err(err) => error!("QUOTE WATCHDOG : lock error : {err:?}"),
+	}
+}
+
+/// Fingerprint of a quote, its header and report body without the report data
+pub fn quote_fingerprint(quote: &[u8]) -> Option<String> {
+	quote.get(..QUOTE_REPORT_DATA_OFFSET).map(sha256::digest)
+}
+
+/// Remark announcing a new quote of the enclave
+pub fn reregistration_remark(fingerprint: &str, tcb_status: Option<TcbStatus>) -> String {
+	let tcb_status = tcb_status.map(|status| format!("{status:?}")).unwrap_or_default();
+	format!("TEE-REREGISTER:{fingerprint}:{tcb_status}")
+}
+
+/// Refresh the quote periodically and evaluate the TCB of the platform
+/// # Arguments
+/// * `state` - SharedState
+pub async fn quote_watchdog(state: SharedState) -> Result<(), anyhow::Error> {
+	let mut interval = tokio::time::interval(Duration::from_secs(quote_refresh_interval()));
+	let client = with_http_proxy(reqwest::Client::builder()).build()?;
+
+	loop {
+		interval.tick().await;
+
+		let (preimage, quote) = create_quote(&state).await;
+		let quote = match quote {
+			Ok(quote) => quote,
+			Err(err) => {
+				// The failure is reported by the health of the last quote
+				warn!("QUOTE WATCHDOG : unable to refresh the quote : {err}");
+				continue
+			},
+		};
+
+		let fingerprint = match quote_fingerprint(&quote) {
+			Some(fingerprint) => fingerprint,
+			None => {
+				error!("QUOTE WATCHDOG : quote of {} bytes is truncated", quote.len());
+				continue
+			},
+		};
+
+		let verdict = attestation_backend().verify(&client, &quote).await;
+		let (tcb_status, advisory_ids, error) = match verdict {
+			Ok(verdict) => (Some(verdict.tcb_status), verdict.advisory_ids, None),
+			Err(err) => {
+				warn!("QUOTE WATCHDOG : unable to evaluate the TCB status : {err:?}");
+				(None, Vec::new(), Some(err.to_string()))
+			},
+		};
+
+		let previous = std::fs::read_to_string(QUOTE_FINGERPRINT_FILE).ok();
+		let changed = previous.as_deref().map(str::trim) != Some(fingerprint.as_str());
+		// A new enclave is registered by its operator with its first quote
+		let reregistration_pending =
+			changed && previous.is_some() && get_identity(&state).await.is_some();
+
+		let changed_block = match (changed, tcb_health()) {
+			(true, _) => Some(preimage.block_number),
+			(false, Some(health)) => health.changed_block,
+			(false, None) => None,
+		};
+
+		let health = TcbHealth {
+			checked_block: preimage.block_number,
+			fingerprint: fingerprint.clone(),
+			tcb_status,
+			advisory_ids,
+			error,
+			changed_block,
+			reregistration_pending,
+		};
+
+		if health.is_unacceptable() {
+			let message = format!(
+				"QUOTE WATCHDOG : TCB status of the platform is {:?}, advisories {:?}",
+				health.tcb_status, health.advisory_ids
+			);
+			error!(message);
+			sentry::with_scope(
+				|scope| {
+					scope.set_tag("quote-watchdog", "tcb");
+				},
+				|| sentry::capture_message(&message, sentry::Level::Error),
+			);
+		} else if health.recovery_needed() {
+			warn!("QUOTE WATCHDOG : TCB status of the platform is {:?}", health.tcb_status);
+		} else {
+			debug!("QUOTE WATCHDOG : quote is refreshed at block {}", preimage.block_number);
+		}
+
+		record_tcb_health(health);
+
+		if reregistration_pending {
+			announce_quote(&state, &fingerprint, tcb_status).await;
+		} else if changed {
+			save_fingerprint(&fingerprint);
+		}
+	}
+}
+
+/// Announce a new quote fingerprint which awaits its registration update
+async fn announce_quote(state: &SharedState, fingerprint: &str, tcb_status: Option<TcbStatus>) {
+	if is_announced(fingerprint) {
+		return
+	}
+
+	let remark = reregistration_remark(fingerprint, tcb_status);
+	match system_remark_oracle(state, remark.into_bytes()).await {
+		Ok(block_hash) => {
+			info!(
+				"QUOTE WATCHDOG : new quote {} is announced in block {:?}",
+				fingerprint, block_hash
+			);
+			set_announced(fingerprint);
+		},
+		// Announced again at the next refresh
+		Err(err) => error!("QUOTE WATCHDOG : error announcing the new quote : {err:?}"),
+	}
+}
+
+/// Persist the fingerprint of the registered quote
+fn save_fingerprint(fingerprint: &str) {
+	if let Err(err) = std::fs::write(QUOTE_FINGERPRINT_FILE, fingerprint) {
+		error!("QUOTE WATCHDOG : error saving the quote fingerprint : {err:?}");
+	}
+}
+
+/* ---------------------------------------
+	RE-REGISTRATION
+--------------------------------------- */
+
+/// Registration update of a new quote, signed by the admin quorum
+#[derive(Deserialize, Debug)]
+pub struct ReregistrationPacket {
+	// Fingerprint of the quote awaiting its registration
+	fingerprint: String,
+	// Signed "tee.update_enclave(ENCLAVE_ACCOUNT, API_URI)" extrinsic of the operator, hex encoded
+	registration: String,
+}
+
+/// Data hash signed by the admin quorum for a re-registration
+pub fn reregistration_data_hash(fingerprint: &str, registration: &str) -> String {
+	sha256::digest(format!("quote-reregister_{fingerprint}_{registration}").as_bytes())
+}
+
+/// Submit the registration update of the operator for the refreshed quote
+/// # Arguments
+/// * `state` - SharedState
+/// * `caller` - verified signers of the quorum
+/// * `request` - ReregistrationPacket
+/// # Returns
+/// * `Json` - the finalized block of the registration update
+pub async fn admin_quote_reregister(
+	State(state): State<SharedState>,
+	caller: VerifiedCaller,
+	Json(request): Json<ReregistrationPacket>,
+) -> impl IntoResponse {
+	debug!("QUOTE REREGISTER : start");
+
+	if let Err((status, message)) = caller
+		.verify_data_hash(&reregistration_data_hash(&request.fingerprint, &request.registration))
+	{
+		let message = format!("QUOTE REREGISTER : {message}");
+		warn!(message);
+		return (status, Json(json!({ "error": message })))
+	}
+
+	match tcb_health() {
+		Some(health)
+			if health.fingerprint == request.fingerprint && health.reregistration_pending =>
+			(),
+		health => {
+			let message = format!(
+				"QUOTE REREGISTER : {} is not the quote awaiting its registration, current quote {:?}",
+				request.fingerprint,
+				health.map(|health| (health.fingerprint, health.reregistration_pending))
+			);
+			warn!(message);
+			return (StatusCode::CONFLICT, Json(json!({ "error": message })))
+		},
+	}
+
+	let registration = match hex::decode(request.registration.trim_start_matches("0x")) {
+		Ok(registration) => registration,
+		Err(err) => {
+			let message = format!("QUOTE REREGISTER : invalid registration : {err:?}");
+			warn!(message);
+			return (StatusCode::BAD_REQUEST, Json(json!({ "error": message })))
+		},
+	};
+
+	let block_hash = match submit_signed_extrinsic(&state, registration).await {
+		Ok(block_hash) => block_hash,
+		Err(err) => {
+			let message =
+				format!("QUOTE REREGISTER : registration update is not finalized : {err:?}");
+			error!(message);
+			return (StatusCode::BAD_GATEWAY, Json(json!({ "error": message })))
+		},
+	};
+
+	// The update must keep this enclave registered
+	let enclave_account = AccountId32::from(get_keypair(&state).await.public().0);
+	match get_enclave_operator(&state, enclave_account.clone()).await {
+		Ok(Some(operator)) =>
+			info!("QUOTE REREGISTER : enclave is registered by {operator} in block {block_hash:?}"),
+		Ok(None) => {
+			let message = format!(
+				"QUOTE REREGISTER : {enclave_account} is not registered after the update in block {block_hash:?}"
+			);
+			error!(message);
+			return (StatusCode::CONFLICT, Json(json!({ "error": message })))
+		},
+		Err(err) => {
+			let message = format!("QUOTE REREGISTER : unable to check the registration : {err:?}");
+			error!(message);
+			return (StatusCode::BAD_GATEWAY, Json(json!({ "error": message })))
+		},
+	}
+
+	save_fingerprint(&request.fingerprint);
+	clear_reregistration(&request.fingerprint);
+
+	(
+		StatusCode::OK,
+		Json(
+			json!({ "fingerprint": request.fingerprint, "block_hash": format!("{block_hash:?}") }),
+		),
+	)
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn quote_watchdog_test() {
+		let mut quote = vec![1u8; QUOTE_REPORT_DATA_OFFSET + 64];
+		let fingerprint = quote_fingerprint(&quote).unwrap();
+
+		// The report data does not change the fingerprint, the report body does
+		quote[QUOTE_REPORT_DATA_OFFSET] = 2;
+		assert_eq!(quote_fingerprint(&quote).unwrap(), fingerprint);
+		quote[0] = 2;
+		assert_ne!(quote_fingerprint(&quote).unwrap(), fingerprint);
+		assert_eq!(quote_fingerprint(&quote[..100]), None);
+
+		assert_eq!(
+			reregistration_remark("ab12", Some(TcbStatus::SWHARDENINGNEEDED)),
+			"TEE-REREGISTER:ab12:SWHARDENINGNEEDED"
+		);
+		assert_eq!(reregistration_remark("ab12", None), "TEE-REREGISTER:ab12:");
+		assert_eq!(
+			reregistration_data_hash("ab12", "0x0102"),
+			sha256::digest("quote-reregister_ab12_0x0102".as_bytes())
+		);
+
+		let health = TcbHealth {
+			checked_block: 1000,
+			fingerprint,
+			tcb_status: Some(TcbStatus::UPTODATE),
+			advisory_ids: Vec::new(),
+			error: None,
+			changed_block: None,
+			reregistration_pending: false,
+		};
+		assert!(!health.recovery_needed());
+		assert!(!health.is_unacceptable());
+
+		let health = TcbHealth { tcb_status: Some(TcbStatus::SWHARDENINGNEEDED), ..health };
+		assert!(health.recovery_needed());
+		assert!(!health.is_unacceptable());
+
+		let health = TcbHealth { tcb_status: Some(TcbStatus::OUTOFDATE), ..health };
+		assert!(health.is_unacceptable());
+
+		let health = TcbHealth { tcb_status: None, ..health };
+		assert!(!health.recovery_needed());
+	}
+}
@@ -2,11 +2,12 @@
 #![allow(unused_imports)]
 #![allow(unused_variables)]
 
+use age::x25519;
 use axum::{
 	body::{Bytes, StreamBody},
 	extract::{FromRequest, Multipart, State},
 	http::header,
-	response::IntoResponse,
+	response::{IntoResponse, Response},
 	Json,
 };
 use hyper::StatusCode;
@@ -26,9 +27,11 @@ use hex::{FromHex, FromHexError};
 use serde_json::{json, Value};
 
 use std::{
-	collections::BTreeMap,
-	fs::{remove_file, File},
+	collections::{BTreeMap, BTreeSet},
+	fs::{remove_file, rename, File},
 	io::{Read, Write},
+	path::Path,
+	time::{Duration, Instant},
 };
 
 use tracing::{debug, error, info, warn};
@@ -36,39 +39,71 @@ use tracing::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
 
 use crate::{
+	attestation::account::import_legacy_account,
 	chain::{
-		constants::{ENCLAVE_ACCOUNT_FILE, MAX_BLOCK_VARIATION, MAX_VALIDATION_PERIOD, SEALPATH},
+		constants::{MAX_BLOCK_VARIATION, MAX_VALIDATION_PERIOD, SEALPATH},
 		core::get_current_block_number,
 		helper,
+		verify::verify_writable,
 	},
-	servers::state::{
-		get_blocknumber, get_clusters, reset_nft_availability, set_keypair, SharedState,
-		StateConfig,
+	servers::{
+		auth::VerifiedCaller,
+		coordination::MaintenanceOperation,
+		health::ReadinessBlocker,
+		metrics::record_backup,
+		state::{
+			get_blocknumber, get_coordinator, get_readiness, get_runtime_config,
+			reset_nft_availability, set_keypair, SharedState, StateConfig,
+		},
 	},
 };
 
 use super::{
-	sync::{set_sync_state, ClusterType},
-	zipdir::{add_dir_zip, zip_extract},
+	admins::{admin_accounts, admin_role, authorize_admin, is_admin, AdminOperation},
+	container::{extract_container, is_container, write_archive, BackupFormat},
+	encryption::{decrypt_archive, decrypt_with, encrypt_archive, is_encrypted_archive},
+	escrow::{escrow_recipient, recover_identity},
+	manifest::{
+		check_archive, install_archive, is_selected_file, signed_manifest, verify_manifest_signer,
+		BackupSelection,
+	},
+	recovery::recovery_recipient,
+	restore::{restore_report, selection_results},
+	sync::set_sync_state,
+	workspace::Workspace,
+	zipdir::zip_extract_selected,
 };
 
 /* *************************************
 		FETCH BULK DATA STRUCTURES
 **************************************** */
 
-// Validity time of Keyshare Data
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
-pub struct FetchAuthenticationToken {
-	pub block_number: u32,
-	pub block_validation: u32,
-}
-
-/// Fetch Bulk Data
+/// Fetch Bulk Data, signed by the threshold of the admin quorum
 #[derive(Serialize, Deserialize)]
 pub struct FetchBulkPacket {
-	admin_address: String,
-	auth_token: String, //FetchAuthenticationToken,
-	signature: String,
+	auth_token: String,
+	// admin_account -> signature of the auth_token
+	#[serde(default)]
+	signatures: BTreeMap<String, String>,
+	// Incremental backup of the keyshares stored or synced since this block
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	since_block: Option<u32>,
+	// Format of the fetched archive, ZIP if it is not given
+	#[serde(default)]
+	format: BackupFormat,
+	// Encrypt to the recovery key of the MRSIGNER, restorable by a replacement enclave
+	#[serde(default)]
+	disaster_recovery: bool,
+}
+
+/// Data hash signed by the admin quorum for a bulk fetch, an empty since_block for a full backup
+pub fn fetch_bulk_data_hash(
+	since_block: Option<u32>,
+	format: BackupFormat,
+	disaster_recovery: bool,
+) -> String {
+	let since_block = since_block.map(|block| block.to_string()).unwrap_or_default();
+	sha256::digest(format!("fetch-bulk_{since_block}_{format:?}_{disaster_recovery}").as_bytes())
 }
 
 /// Fetch Bulk Response
@@ -111,41 +146,6 @@ pub enum ValidationResult {
 	InvalidPeriod,
 }
 
-/// Retrieving the stored Keyshare
-impl FetchAuthenticationToken {
-	pub fn is_valid(&self, current_block_number: u32) -> ValidationResult {
-		if self.block_number > current_block_number + MAX_BLOCK_VARIATION {
-			// for finalization delay
-			debug!(
-				"current block number = {} < request block number = {}",
-				current_block_number, self.block_number
-			);
-			return ValidationResult::FutureBlockNumber
-		}
-
-		if self.block_validation > MAX_VALIDATION_PERIOD {
-			// A finite validity period
-			debug!(
-				"MAX VALIDATION = {} < block_validation = {}",
-				MAX_VALIDATION_PERIOD, self.block_validation
-			);
-			return ValidationResult::InvalidPeriod
-		}
-
-		if self.block_number + self.block_validation < current_block_number {
-			// validity period
-			debug!(
-				"current block number = {} >> request block number = {}",
-				current_block_number, self.block_number
-			);
-
-			return ValidationResult::ExpiredBlockNumber
-		}
-
-		ValidationResult::Success
-	}
-}
-
 impl StoreAuthenticationToken {
 	pub fn is_valid(&self, current_block_number: u32) -> ValidationResult {
 		if self.block_number > current_block_number + MAX_BLOCK_VARIATION {
@@ -196,25 +196,7 @@ impl StoreAuthenticationToken {
 /// # Errors
 /// * `PublicError` - If the account ID is not a valid SS58 string
 async fn verify_account_id(state: &SharedState, account_id: &String) -> bool {
-	let clusters = get_clusters(state).await;
-	let allowed_id: Vec<String> = clusters
-		.into_iter()
-		.filter_map(|c| {
-			if c.cluster_type == ClusterType::Admin {
-				Some(
-					c.enclaves
-						.iter()
-						.map(|e| e.enclave_account.to_string())
-						.collect::<Vec<String>>(),
-				)
-			} else {
-				None
-			}
-		})
-		.flat_map(|x| x.into_iter())
-		.collect();
-
-	allowed_id.contains(&account_id.to_string())
+	is_admin(state, account_id).await
 }
 
 /// Verifies the signature of the backup data
@@ -314,100 +296,111 @@ async fn update_health_status(state: &SharedState, message: String) {
 #[axum::debug_handler]
 pub async fn admin_backup_fetch_bulk(
 	State(state): State<SharedState>,
+	caller: VerifiedCaller,
 	Json(backup_request): Json<FetchBulkPacket>,
 ) -> impl IntoResponse {
 	debug!("ADMIN FETCH BULK : backup fetch bulk");
 	//update_health_status(&state, "Enclave is doing backup, please wait...".to_string()).await;
 
-	if !verify_account_id(&state, &backup_request.admin_address).await {
-		let message = format!(
-			"Error backup key shares : Requester is not whitelisted : {}",
-			backup_request.admin_address
-		);
+	// The token, the signatures and the threshold of the quorum are verified by the auth middleware
+	if let Err((status, message)) = caller.verify_data_hash(&fetch_bulk_data_hash(
+		backup_request.since_block,
+		backup_request.format,
+		backup_request.disaster_recovery,
+	)) {
+		let message = format!("ADMIN FETCH BULK : {message}");
 		warn!(message);
-
-		return (StatusCode::FORBIDDEN, Json(json!({ "error": message }))).into_response()
-	}
-
-	let mut auth = backup_request.auth_token.clone();
-
-	if auth.starts_with("<Bytes>") && auth.ends_with("</Bytes>") {
-		auth = match auth.strip_prefix("<Bytes>") {
-			Some(stripped) => stripped.to_owned(),
-			_ =>
-				return (
-					StatusCode::BAD_REQUEST,
-					Json(json!({"error": "Strip Token prefix error".to_string()})),
-				)
-					.into_response(),
-		};
-
-		auth = match auth.strip_suffix("</Bytes>") {
-			Some(stripped) => stripped.to_owned(),
-			_ =>
-				return (
-					StatusCode::BAD_REQUEST,
-					Json(json!({"error": "Strip Token suffix error".to_string()})),
-				)
-					.into_response(),
-		}
+		return (status, Json(json!({ "error": message }))).into_response()
 	}
 
-	let auth_token: FetchAuthenticationToken = match serde_json::from_str(&auth) {
-		Ok(token) => token,
+	// Each fetch writes its archive to a private workspace, concurrent fetches never share a file
+	let workspace = match Workspace::create("fetch-bulk") {
+		Ok(workspace) => workspace,
 		Err(err) => {
-			let message =
-				format!("Error backup key shares : Authentication token is not parsable : {}", err);
+			let message = format!("ADMIN FETCH BULK : {err}");
 			warn!(message);
-			return (StatusCode::BAD_REQUEST, Json(json!({ "error": message }))).into_response()
+			return (StatusCode::TOO_MANY_REQUESTS, Json(json!({ "error": message })))
+				.into_response()
+		},
+	};
+	let backup_file = workspace.file(&format!("backup.{}", backup_request.format.extension()));
+	let backup_file = backup_file.to_string_lossy().to_string();
+
+	// Writes are held while the archive is written, it matches its signed manifest
+	let operation = match get_coordinator(&state)
+		.await
+		.begin_maintenance(
+			MaintenanceOperation::BACKUP,
+			"fetch-bulk",
+			Duration::from_secs(get_runtime_config(&state).await.maintenance_queue_timeout),
+		)
+		.await
+	{
+		Ok(operation) => operation,
+		Err(conflict) => {
+			warn!("ADMIN FETCH BULK : {conflict}");
+			return conflict.into_response()
 		},
 	};
 
-	if !verify_signature(
-		&backup_request.admin_address,
-		backup_request.signature.clone(),
-		backup_request.auth_token.clone().as_bytes(),
-	) {
-		return (StatusCode::FORBIDDEN, Json(json!({"error": "Invalid Signature".to_string()})))
-			.into_response()
-	}
+	debug!("ADMIN FETCH BULK : Start zippping file");
+	let started = Instant::now();
+	let selection = match backup_request.since_block {
+		Some(since_block) => BackupSelection::SINCE(since_block),
+		None => BackupSelection::ALL,
+	};
 
-	let current_block_number = get_blocknumber(&state).await;
-
-	debug!("ADMIN FETCH BULK : Validating the authentication token");
-	let validation = auth_token.is_valid(current_block_number);
-	match validation {
-		ValidationResult::Success => debug!("ADMIN FETCH BULK : Authentication token is valid."),
-		_ => {
-			let message =
-				format!("Authentication Token is not valid, or expired : {:?}", validation);
-			error!("ADMIN FETCH BULK : {}", message);
-			return (StatusCode::NOT_ACCEPTABLE, Json(json!({ "error": message }))).into_response()
-		},
+	let zipped = match signed_manifest(&state, SEALPATH, &selection, Vec::new()).await {
+		Ok(manifest) =>
+			write_archive(backup_request.format, SEALPATH, &manifest, &backup_file, |_, _| {}),
+		Err(err) => Err(err),
+	};
+	drop(operation);
+
+	if let Err(err) = zipped {
+		record_backup("fetch-bulk", started.elapsed(), false);
+		let message = format!("ADMIN FETCH BULK : Error compressing the keyshares : {err:?}");
+		error!(message);
+		return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })))
+			.into_response()
 	}
 
-	let mut backup_file = "/temporary/backup.zip".to_string();
-	let counter = 1;
-	// remove previously generated backup
-	while std::path::Path::new(&backup_file.clone()).exists() {
-		match std::fs::remove_file(backup_file.clone()) {
-			Ok(_) => {
-				debug!("ADMIN FETCH BULK : Successfully removed previous zip file")
-			},
-			Err(err) => {
-				let message = format!(
-					"ADMIN FETCH BULK : Error backup key shares : Can not remove previous backup file : {}",
-					err
-				);
+	// Escrowed bulk backups are only restored with a threshold of admin shares, disaster-recovery
+	// backups by any enclave of the same MRSIGNER
+	let recipient = if backup_request.disaster_recovery {
+		match recovery_recipient() {
+			Some(recipient) => Some(recipient),
+			None => {
+				let message =
+					"ADMIN FETCH BULK : disaster-recovery key is not available".to_string();
 				warn!(message);
-				//return Json(json!({ "error": message })).into_response()
-				backup_file = format!("/temporary/backup-{counter}.zip");
+				return (StatusCode::PRECONDITION_FAILED, Json(json!({ "error": message })))
+					.into_response()
 			},
 		}
-	}
+	} else {
+		escrow_recipient()
+	};
 
-	debug!("ADMIN FETCH BULK : Start zippping file");
-	add_dir_zip(SEALPATH, &backup_file);
+	let mut file_name = backup_request.format.file_name();
+	let backup_file = match recipient {
+		Some(recipient) => {
+			let encrypted_file = format!("{backup_file}.age");
+			if let Err(err) =
+				encrypt_archive(Path::new(&backup_file), Path::new(&encrypted_file), recipient)
+			{
+				record_backup("fetch-bulk", started.elapsed(), false);
+				let message = format!("ADMIN FETCH BULK : Error encrypting the archive : {err:?}");
+				error!(message);
+				return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })))
+					.into_response()
+			}
+			file_name += ".age";
+			encrypted_file
+		},
+		None => backup_file,
+	};
+	record_backup("fetch-bulk", started.elapsed(), true);
 
 	// `File` implements `AsyncRead`
 	debug!("ADMIN FETCH BULK : Opening backup file");
@@ -421,6 +414,9 @@ pub async fn admin_backup_fetch_bulk(
 				.into_response(),
 	};
 
+	// The open file is still streamed once the workspace is removed
+	drop(workspace);
+
 	// convert the `AsyncRead` into a `Stream`
 	debug!("ADMIN FETCH BULK : Create reader-stream");
 	let stream = ReaderStream::new(file);
@@ -430,8 +426,8 @@ pub async fn admin_backup_fetch_bulk(
 	let body = StreamBody::new(stream);
 
 	let headers = [
-		(header::CONTENT_TYPE, "text/toml; charset=utf-8"),
-		(header::CONTENT_DISPOSITION, "attachment; filename=\"Backup.zip\""),
+		(header::CONTENT_TYPE, "text/toml; charset=utf-8".to_string()),
+		(header::CONTENT_DISPOSITION, format!("attachment; filename=\"{file_name}\"")),
 	];
 
 	//update_health_status(&state, String::new()).await;
@@ -474,16 +470,26 @@ fn get_json_response(status: String, data: Vec<u8>) -> Json<Value> {
 #[axum::debug_handler]
 pub async fn admin_backup_push_bulk(
 	State(state): State<SharedState>,
+	caller: VerifiedCaller,
 	mut store_request: Multipart,
 ) -> impl IntoResponse {
 	debug!("ADMIN PUSH BULK : backup push bulk");
 	debug!("ADMIN PUSH BULK : received request = {:?}", store_request);
 	//update_health_status(&state, "Restoring the backups".to_string()).await;
 
-	let mut admin_address = String::new();
+	if let Err(err) = verify_writable(&state).await {
+		let message = format!("ADMIN PUSH BULK : restore is rejected : {err:?}");
+		warn!(message);
+		return (StatusCode::SERVICE_UNAVAILABLE, Json(json!({ "error": message }))).into_response()
+	}
+
 	let mut restore_file = Vec::<u8>::new();
-	let mut auth_token = String::new();
-	let mut signature = String::new();
+	// A dry-run only reports the changes of the archive
+	let mut apply = true;
+	// A selective restore only extracts the keyshares of these nfts
+	let mut nft_ids: Option<BTreeSet<u32>> = None;
+	// Decrypted admin shares of the escrowed bulk backup key
+	let mut escrow_shares: Vec<String> = Vec::new();
 
 	while let Some(field) = match store_request.next_field().await {
 		Ok(field) => field,
@@ -512,21 +518,8 @@ pub async fn admin_backup_push_bulk(
 		};
 
 		match name.as_str() {
-			"admin_address" =>
-				admin_address = match field.text().await {
-					Ok(bytes) => bytes,
-					Err(err) => {
-						info!("ADMIN PUSH BULK : Error request admin_address {err:?}");
-
-						return (
-							StatusCode::BAD_REQUEST,
-							Json(json!({
-									"error": format!("ADMIN PUSH BULK : Error request admin_address {err:?}"),
-							})),
-						)
-							.into_response()
-					},
-				},
+			// The signature fields are verified by the auth middleware
+			"admin_address" | "auth_token" | "signature" => continue,
 
 			"restore_file" =>
 				restore_file = match field.bytes().await {
@@ -544,46 +537,49 @@ pub async fn admin_backup_push_bulk(
 					},
 				},
 
-			"auth_token" =>
-				auth_token = match field.text().await {
-					Ok(bytes) => bytes,
-					Err(err) => {
-						info!("ADMIN PUSH BULK : Error request auth_token {err:?}");
+			"apply" =>
+				apply = match field.text().await.map(|text| text.trim().parse::<bool>()) {
+					Ok(Ok(apply)) => apply,
+					_ => {
+						info!("ADMIN PUSH BULK : Error request apply, expected true or false");
 
 						return (
 							StatusCode::BAD_REQUEST,
 							Json(json!({
-								"error": format!("ADMIN PUSH BULK : Error request auth_token {err:?}"),
+								"error": "ADMIN PUSH BULK : Error request apply, expected true or false",
 							})),
 						)
 							.into_response()
 					},
 				},
 
-			"signature" =>
-				signature = match field.text().await {
-					Ok(sig) => match sig.strip_prefix("0x") {
-						Some(hexsig) => hexsig.to_owned(),
+			"nftids" =>
+				nft_ids =
+					match field.text().await.map(|text| serde_json::from_str(&text)) {
+						Ok(Ok(ids)) => Some(ids),
 						_ => {
-							info!("ADMIN PUSH BULK : Error request signature format, expectex 0x prefix, {sig}");
+							info!("ADMIN PUSH BULK : Error request nftids, expected an array of nft ids");
 
 							return (
 								StatusCode::BAD_REQUEST,
 								Json(json!({
-										"error": format!("ADMIN PUSH BULK : Error request signature format, expectex 0x prefix"),
+									"error": "ADMIN PUSH BULK : Error request nftids, expected an array of nft ids",
 								})),
 							)
 								.into_response()
 						},
 					},
 
-					Err(err) => {
-						info!("ADMIN PUSH BULK : Error request signature {err:?}");
+			"escrow_shares" =>
+				escrow_shares = match field.text().await.map(|text| serde_json::from_str(&text)) {
+					Ok(Ok(shares)) => shares,
+					_ => {
+						info!("ADMIN PUSH BULK : Error request escrow_shares, expected an array of hex shares");
 
 						return (
 							StatusCode::BAD_REQUEST,
 							Json(json!({
-									"error": format!("ADMIN PUSH BULK : Error request signature {err:?}"),
+								"error": "ADMIN PUSH BULK : Error request escrow_shares, expected an array of hex shares",
 							})),
 						)
 							.into_response()
@@ -603,92 +599,38 @@ pub async fn admin_backup_push_bulk(
 		}
 	}
 
-	if !verify_account_id(&state, &admin_address.clone()).await {
-		let message = format!("ADMIN PUSH BULK : Requester is not whitelisted : {}", admin_address);
-
+	// The whitelist, the role and the signature of the admin are verified by the auth middleware
+	if let Err((status, message)) =
+		caller.verify_data_hash(&sha256::digest(restore_file.as_slice()))
+	{
+		let message = format!("ADMIN PUSH BULK : {message}");
 		warn!(message);
-
-		return (
-			StatusCode::FORBIDDEN,
-			Json(json! ({
-				"error": message,
-			})),
-		)
-			.into_response()
+		return (status, Json(json!({ "error": message }))).into_response()
 	}
 
-	if !verify_signature(&admin_address, signature.clone(), auth_token.clone().as_bytes()) {
-		warn!("Error restore backup keyshares : Invalid signature : admin = {}", admin_address);
-
-		return (
-			StatusCode::FORBIDDEN,
-			Json(json! ({
-				"error": "Invalid token signature",
-			})),
-		)
-			.into_response()
-	}
-
-	if auth_token.starts_with("<Bytes>") && auth_token.ends_with("</Bytes>") {
-		auth_token = match auth_token.strip_prefix("<Bytes>") {
-			Some(stripped) => stripped.to_owned(),
-			_ =>
-				return (
-					StatusCode::BAD_REQUEST,
-					Json(json! ({"error": "ADMIN PUSH BULK : Strip Token prefix error"})),
-				)
-					.into_response(),
-		};
-
-		auth_token = match auth_token.strip_suffix("</Bytes>") {
-			Some(stripped) => stripped.to_owned(),
-			_ =>
-				return (
-					StatusCode::BAD_REQUEST,
-					Json(json! ({"error": "Strip Token suffix error"})),
-				)
-					.into_response(),
+	let identity = if escrow_shares.is_empty() {
+		None
+	} else {
+		match recover_identity(&escrow_shares) {
+			Ok(identity) => Some(identity),
+			Err(err) => {
+				let message = format!("ADMIN PUSH BULK : {err}");
+				warn!(message);
+				return (StatusCode::BAD_REQUEST, Json(json!({ "error": message }))).into_response()
+			},
 		}
-	}
+	};
 
-	let token: StoreAuthenticationToken = match serde_json::from_str(auth_token.as_str()) {
-		Ok(token) => token,
+	let workspace = match Workspace::create("push-bulk") {
+		Ok(workspace) => workspace,
 		Err(err) => {
-			let message =
-				format!("ADMIN PUSH BULK : Can not parse the authentication token : {}", err);
+			let message = format!("ADMIN PUSH BULK : {err}");
 			warn!(message);
-			return (StatusCode::BAD_REQUEST, Json(json!({ "error": message }))).into_response()
+			return (StatusCode::TOO_MANY_REQUESTS, Json(json!({ "error": message })))
+				.into_response()
 		},
 	};
-
-	let current_block_number = get_blocknumber(&state).await;
-
-	let validation = token.is_valid(current_block_number);
-	match validation {
-		ValidationResult::Success => debug!("Authentication token is valid."),
-		_ => {
-			let message =
-				format!("Authentication Token is not valid, or expired : {:?}", validation);
-			error!("ADMIN PUSH BULK : token expired : {}", message);
-			return (StatusCode::NOT_ACCEPTABLE, Json(json!({ "error": message }))).into_response()
-		},
-	}
-
-	let hash = sha256::digest(restore_file.as_slice());
-
-	if token.data_hash != hash {
-		warn!("ADMIN PUSH BULK : mismatch data hash : admin = {}", admin_address);
-
-		return (
-			StatusCode::BAD_REQUEST,
-			Json(json! ({
-				"error": "ADMIN PUSH BULK : Mismatch Data Hash",
-			})),
-		)
-			.into_response()
-	}
-
-	let backup_file = SEALPATH.to_string() + "/" + "backup.zip";
+	let backup_file = workspace.file("backup.zip").to_string_lossy().to_string();
 
 	let mut zipfile = match std::fs::File::create(backup_file.clone()) {
 		Ok(file) => file,
@@ -715,22 +657,199 @@ pub async fn admin_backup_push_bulk(
 		},
 	}
 
-	// Check if the enclave_account or keyshares are invalid
-	match zip_extract(&backup_file, SEALPATH) {
-		Ok(_) => debug!("zip_extract success"),
+	drop(zipfile);
+	let response =
+		restore_backup_archive(&state, backup_file, apply, nft_ids, identity.as_ref()).await;
+	drop(workspace);
+	response
+}
+
+/// Restore a backup archive received by the push-bulk or resumable upload endpoints
+/// The archive is decrypted if it is encrypted to this enclave, checked against its signed
+/// manifest and installed, then the enclave account and the keyshare availability are reloaded.
+/// # Arguments
+/// * `backup_file` - archive in the sealed directory, it is removed
+/// * `apply` - false for a dry-run, which only reports the new, identical and conflicting files
+/// * `nft_ids` - nfts of a selective restore, None for the whole archive
+/// * `identity` - escrowed identity of a bulk backup, None for archives encrypted to this enclave
+pub async fn restore_backup_archive(
+	state: &SharedState,
+	backup_file: String,
+	apply: bool,
+	nft_ids: Option<BTreeSet<u32>>,
+	identity: Option<&x25519::Identity>,
+) -> Response {
+	// A dry-run only reads the archive, a restore holds every keyshare request
+	let _operation = if apply {
+		match get_coordinator(state)
+			.await
+			.begin_maintenance(
+				MaintenanceOperation::RESTORE,
+				"restore",
+				Duration::from_secs(get_runtime_config(state).await.maintenance_queue_timeout),
+			)
+			.await
+		{
+			Ok(operation) => Some(operation),
+			Err(conflict) => {
+				warn!("ADMIN RESTORE : {conflict}");
+				let _ = remove_file(&backup_file);
+				return conflict.into_response()
+			},
+		}
+	} else {
+		None
+	};
+	let _readiness = if apply {
+		Some(get_readiness(state).await.hold(ReadinessBlocker::RESTORING))
+	} else {
+		None
+	};
+
+	let started = Instant::now();
+	let response = restore_archive(state, backup_file, apply, nft_ids, identity).await;
+	if apply {
+		record_backup("restore", started.elapsed(), response.status().is_success());
+	}
+	response
+}
+
+async fn restore_archive(
+	state: &SharedState,
+	backup_file: String,
+	apply: bool,
+	nft_ids: Option<BTreeSet<u32>>,
+	identity: Option<&x25519::Identity>,
+) -> Response {
+	// Archives fetched with a recipient are only decrypted inside this enclave
+	let encrypted = match is_encrypted_archive(Path::new(&backup_file)) {
+		Ok(encrypted) => encrypted,
 		Err(err) => {
-			let message = format!("ADMIN PUSH BULK : extracting zip file {err:?}");
+			let message = format!("ADMIN PUSH BULK : reading zip file header {err:?}");
 			error!(message);
-			return (
-				StatusCode::INTERNAL_SERVER_ERROR,
-				Json(json!({
-					"error": message,
-				})),
-			)
+			return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })))
 				.into_response()
 		},
+	};
+
+	if encrypted {
+		let encrypted_file = backup_file.clone() + ".age";
+		let decrypted = rename(&backup_file, &encrypted_file)
+			.map_err(anyhow::Error::from)
+			.and_then(|_| match identity {
+				Some(identity) =>
+					decrypt_with(identity, Path::new(&encrypted_file), Path::new(&backup_file)),
+				None => decrypt_archive(Path::new(&encrypted_file), Path::new(&backup_file)),
+			});
+
+		if let Err(err) = remove_file(&encrypted_file) {
+			warn!("ADMIN PUSH BULK : Can not remove the encrypted zip file : {err:?}");
+		}
+
+		if let Err(err) = decrypted {
+			let _ = remove_file(&backup_file);
+			let message = format!("ADMIN PUSH BULK : decrypting zip file {err:?}");
+			error!(message);
+			return (StatusCode::BAD_REQUEST, Json(json!({ "error": message }))).into_response()
+		}
+	} else {
+		warn!("ADMIN PUSH BULK : restoring a plaintext backup archive");
 	}
 
+	// Archives are checked against their signed manifest before any file is installed, in a
+	// private staging directory removed on return
+	let workspace = match Workspace::create("restore") {
+		Ok(workspace) => workspace,
+		Err(err) => {
+			let _ = remove_file(&backup_file);
+			let message = format!("ADMIN PUSH BULK : {err}");
+			warn!(message);
+			return (StatusCode::TOO_MANY_REQUESTS, Json(json!({ "error": message })))
+				.into_response()
+		},
+	};
+	let staging_path = workspace.file("staging").to_string_lossy().to_string();
+
+	// v2 containers have a versioned header, other archives are zip files
+	let selected = |name: &str| nft_ids.as_ref().map_or(true, |ids| is_selected_file(name, ids));
+	let extracted = match is_container(Path::new(&backup_file)) {
+		Ok(true) => extract_container(Path::new(&backup_file), Path::new(&staging_path), selected),
+		Ok(false) =>
+			zip_extract_selected(&backup_file, &staging_path, selected).map_err(anyhow::Error::from),
+		Err(err) => Err(err),
+	};
+
+	let manifest = match extracted.and_then(|_| check_archive(&staging_path, nft_ids.as_ref())) {
+		Ok(manifest) => manifest,
+		Err(err) => {
+			let message = format!("ADMIN PUSH BULK : invalid backup archive {err:?}");
+			error!(message);
+			let _ = remove_file(&backup_file);
+			return (StatusCode::UNPROCESSABLE_ENTITY, Json(json!({ "error": message })))
+				.into_response()
+		},
+	};
+
+	if let Err(err) = verify_manifest_signer(state, &manifest).await {
+		let message = format!("ADMIN PUSH BULK : rejected backup archive {err:?}");
+		warn!(message);
+		let _ = remove_file(&backup_file);
+		return (StatusCode::FORBIDDEN, Json(json!({ "error": message }))).into_response()
+	}
+
+	let selection = nft_ids.as_ref().map(|nft_ids| selection_results(nft_ids, &manifest, apply));
+
+	if !apply {
+		let report = restore_report(state, SEALPATH, &manifest).await;
+		let _ = remove_file(&backup_file);
+
+		return match report {
+			Ok(report) => {
+				info!(
+					"ADMIN PUSH BULK : dry-run of archive of block {} by {} : {} new, {} identical, {} conflicting, {} missing onchain",
+					manifest.block_number,
+					manifest.enclave_account,
+					report.new,
+					report.identical,
+					report.conflicting,
+					report.missing_onchain.len()
+				);
+				(
+					StatusCode::OK,
+					Json(json!({
+						"dry_run": true,
+						"enclave_account": manifest.enclave_account,
+						"block_number": manifest.block_number,
+						"report": report,
+						"nft_ids": selection,
+					})),
+				)
+					.into_response()
+			},
+			Err(err) => {
+				let message = format!("ADMIN PUSH BULK : dry-run report {err:?}");
+				error!(message);
+				(StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })))
+					.into_response()
+			},
+		}
+	}
+
+	if let Err(err) = install_archive(&staging_path, SEALPATH, &manifest) {
+		let message = format!("ADMIN PUSH BULK : installing backup archive {err:?}");
+		error!(message);
+		return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })))
+			.into_response()
+	}
+
+	info!(
+		"ADMIN PUSH BULK : restored {} files of {} nfts, archive of block {} by {}",
+		manifest.entries.len(),
+		manifest.nft_ids.len(),
+		manifest.block_number,
+		manifest.enclave_account
+	);
+
 	match remove_file(backup_file) {
 		Ok(_) => debug!("ADMIN PUSH BULK : remove zip file successful"),
 		Err(err) =>
@@ -743,43 +862,16 @@ pub async fn admin_backup_push_bulk(
 				.into_response(),
 	};
 
-	// Update Enclave Account, if it is updated.;
-	if !std::path::Path::new(&ENCLAVE_ACCOUNT_FILE).exists() {
-		return (
-			StatusCode::NO_CONTENT,
-			Json(json!({
-				"error": format!("ADMIN PUSH BULK : Enclave Account file not found"),
-			})),
-		)
-			.into_response()
-	};
-
-	debug!(
-		"ADMIN PUSH BULK : Found Enclave Account, Importing it! : path: {}",
-		ENCLAVE_ACCOUNT_FILE
-	);
-
-	let phrase = match std::fs::read_to_string(ENCLAVE_ACCOUNT_FILE) {
-		Ok(phrase) => phrase,
-		Err(err) => {
-			let message = format!("ADMIN PUSH BULK : Error reading enclave account file: {err:?}");
-			error!(message);
-			return (
-				StatusCode::NO_CONTENT,
-				Json(json!({
-					"error": message,
-				})),
-			)
-				.into_response()
+	// Update Enclave Account, if the backup has one, it is sealed to this binary
+	match import_legacy_account() {
+		Ok(Some(enclave_keypair)) => {
+			set_keypair(state, enclave_keypair).await;
+			debug!("share-state Enclave Account updated");
 		},
-	};
-
-	debug!("ADMIN PUSH BULK : Phrase read, converting it to keypair.");
-
-	let enclave_keypair = match sr25519::Pair::from_phrase(&phrase, None) {
-		Ok((keypair, _seed)) => keypair,
+		Ok(None) =>
+			debug!("ADMIN PUSH BULK : backup has no Enclave Account, the current one is kept"),
 		Err(err) => {
-			let message = format!("ADMIN PUSH BULK : Error creating keypair from phrase: {err:?}");
+			let message = format!("ADMIN PUSH BULK : Error importing enclave account : {err:?}");
 			error!(message);
 			return (
 				StatusCode::INTERNAL_SERVER_ERROR,
@@ -791,12 +883,7 @@ pub async fn admin_backup_push_bulk(
 		},
 	};
 
-	debug!("ADMIN PUSH BULK : Keypair success");
-
-	set_keypair(&state, enclave_keypair).await;
-	debug!("share-state Enclave Account updated");
-
-	//update_health_status(&state, String::new()).await;
+	//update_health_status(state, String::new()).await;
 	let keyshare_list: BTreeMap<u32, helper::Availability> =
 		match helper::query_keyshare_file(SEALPATH.to_string()) {
 			Ok(list) => list,
@@ -811,16 +898,18 @@ pub async fn admin_backup_push_bulk(
 		};
 
 	let last_synced = keyshare_list.values().map(|av| av.block_number).max().unwrap();
-	reset_nft_availability(&state, keyshare_list).await;
+	reset_nft_availability(state, keyshare_list).await;
 	let _ = set_sync_state(last_synced.to_string());
 
-	(
-		StatusCode::OK,
-		Json(json!({
-			"success": format!("Success restoring backups"),
-		})),
-	)
-		.into_response()
+	let mut response = json!({
+		"success": format!("Success restoring backups"),
+	});
+
+	if let Some(selection) = selection {
+		response["nft_ids"] = json!(selection);
+	}
+
+	(StatusCode::OK, Json(response)).into_response()
 }
 
 /* **********************
@@ -829,7 +918,9 @@ pub async fn admin_backup_push_bulk(
 
 #[cfg(test)]
 mod test {
-	use crate::chain::core::get_current_block_number_new_api;
+	use crate::{
+		backup::quorum::QuorumAuthenticationToken, chain::core::get_current_block_number_new_api,
+	};
 
 	use super::*;
 
@@ -841,19 +932,38 @@ mod test {
 		let admin_keypair = sr25519::Pair::from_phrase(seed_phrase, None).unwrap().0;
 		let current_block_number = get_current_block_number_new_api().await.unwrap();
 
-		let auth =
-			FetchAuthenticationToken { block_number: current_block_number, block_validation: 10 };
-		let auth_bytes = serde_json::to_vec(&auth).unwrap();
-		let sig = admin_keypair.sign(&auth_bytes);
-		let sig_str = serde_json::to_string(&sig).unwrap();
+		let auth = QuorumAuthenticationToken {
+			block_number: current_block_number,
+			block_validation: 10,
+			data_hash: fetch_bulk_data_hash(None, BackupFormat::default(), false),
+		};
+		let auth_str = serde_json::to_string(&auth).unwrap();
+		let sig = admin_keypair.sign(auth_str.as_bytes());
+
+		let mut signatures = BTreeMap::new();
+		signatures.insert(admin_keypair.public().to_string(), format!("{}{:?}", "0x", sig));
 
 		let _request = FetchBulkPacket {
-			admin_address: admin_keypair.public().to_string(),
-			auth_token: serde_json::to_string(&auth).unwrap(),
-			signature: sig_str,
+			auth_token: auth_str,
+			signatures,
+			since_block: None,
+			format: BackupFormat::default(),
+			disaster_recovery: false,
 		};
 	}
 
+	#[test]
+	fn fetch_bulk_data_hash_test() {
+		assert_eq!(
+			fetch_bulk_data_hash(Some(1000), BackupFormat::V2, true),
+			sha256::digest("fetch-bulk_1000_V2_true".as_bytes())
+		);
+		assert_eq!(
+			fetch_bulk_data_hash(None, BackupFormat::ZIP, false),
+			sha256::digest("fetch-bulk__ZIP_false".as_bytes())
+		);
+	}
+
 	#[tokio::test]
 	async fn bulk_restore_test() {
 		let seed_phrase: &str =
@@ -12,11 +12,14 @@ use axum::{
 
 use tokio_util::io::ReaderStream;
 
+use anyhow::anyhow;
 use hex::{FromHex, FromHexError};
 use serde_json::{json, Value};
 use std::{
-	collections::BTreeMap,
+	collections::{BTreeMap, BTreeSet},
 	io::{Read, Write},
+	path::Path,
+	time::Duration,
 };
 use subxt::ext::sp_core::{crypto::Ss58Codec, sr25519, Pair};
 
@@ -27,20 +30,40 @@ use serde::{Deserialize, Serialize};
 use subxt::ext::sp_core::{crypto::PublicError, sr25519::Signature};
 
 use crate::{
-	backup::zipdir::add_list_zip,
+	backup::{
+		container::BackupFormat,
+		encryption::{verify_recipient_attestation, RecipientAttestation},
+		jobs::{create_job, run_backup_job},
+		manifest::{BackupSelection, NotFoundEntry, NotFoundReason},
+		restore::check_onchain,
+	},
 	chain::{
-		constants::{MAX_BLOCK_VARIATION, MAX_VALIDATION_PERIOD, SEALPATH},
+		constants::{
+			MAX_BLOCK_VARIATION, MAX_FETCH_ARCHIVE_BYTES, MAX_FETCH_IDS, MAX_FETCH_ID_VECTOR,
+			MAX_VALIDATION_PERIOD, SEALPATH,
+		},
 		core::get_current_block_number,
 		helper,
+		verify::{
+			batch_errors, verify_writable, BatchItemResult, Retryability, ReturnStatus,
+			VerificationStep,
+		},
 	},
-	servers::state::{
-		get_blocknumber, get_clusters, get_nft_availability, set_nft_availability, SharedState,
-		StateConfig,
+	servers::{
+		auth::VerifiedCaller,
+		coordination::MaintenanceOperation,
+		events::{publish_keyshare_event, KeyshareEventKind},
+		health::ReadinessBlocker,
+		proxy::with_http_proxy,
+		state::{
+			get_blocknumber, get_coordinator, get_nft_availability, get_readiness,
+			get_runtime_config, set_nft_availability, SharedState, StateConfig,
+		},
 	},
 };
 
 use super::{
-	sync::ClusterType,
+	admins::{admin_role, authorize_admin, is_admin, AdminOperation},
 	zipdir::{add_dir_zip, zip_extract},
 };
 
@@ -63,6 +86,21 @@ pub struct IdPacket {
 	id_vec: String,
 	auth_token: String,
 	signature: String,
+	// Age X25519 recipient of the fetched archive, covered by the token data hash, required
+	#[serde(default)]
+	recipient: Option<String>,
+	// Body of GET /api/backup/recipient of the target enclave, required
+	#[serde(default)]
+	recipient_attestation: Option<RecipientAttestation>,
+	// Format of the fetched archive, ZIP if it is not given
+	#[serde(default)]
+	format: BackupFormat,
+	// Continuation token of the previous page, None for the first page
+	#[serde(default)]
+	continuation: Option<String>,
+	// Check that the nfts exist onchain, the keyshares of missing nfts are not archived
+	#[serde(default)]
+	check_onchain: bool,
 }
 
 /// Fetch NFTID Response
@@ -135,25 +173,7 @@ impl AuthenticationToken {
 /// # Errors
 /// * `PublicError` - If the account ID is not a valid SS58 string
 async fn verify_account_id(state: &SharedState, account_id: &String) -> bool {
-	let clusters = get_clusters(state).await;
-	let allowed_id: Vec<String> = clusters
-		.into_iter()
-		.filter_map(|c| {
-			if c.cluster_type == ClusterType::Admin {
-				Some(
-					c.enclaves
-						.iter()
-						.map(|e| e.enclave_account.to_string())
-						.collect::<Vec<String>>(),
-				)
-			} else {
-				None
-			}
-		})
-		.flat_map(|x| x.into_iter())
-		.collect();
-
-	allowed_id.contains(&account_id.to_string())
+	is_admin(state, account_id).await
 }
 
 /// Get the public key of an Account ID
@@ -232,12 +252,136 @@ fn verify_signature(account_id: &str, signature: String, message: &[u8]) -> bool
 	}
 }
 
-async fn update_health_status(state: &SharedState, message: String) {
-	let shared_state_write = &mut state.write().await;
-	debug!("got shared state to write.");
+/* ----------------------------------
+	FETCH NFTID PAGINATION
+----------------------------------*/
+
+// Large id vectors are fetched in pages, each page is a backup job of its own. The continuation
+// token is the offset of the next page, bound to the id vector by its digest.
+
+/// Page of a fetch-id request
+#[derive(Debug, Clone, PartialEq)]
+pub struct FetchPage {
+	pub nft_ids: Vec<u32>,
+	// Keyshare bytes of the page, before compression
+	pub bytes: u64,
+	// Offset of the next page in the id vector, None for the last page
+	pub next_offset: Option<usize>,
+}
+
+/// Parse and sanitize the nft ids of a fetch request, duplicates are removed in order
+/// # Errors
+/// * Not a json array, empty vector, or values which are not integer nft ids
+pub fn sanitize_nft_ids(id_vec: &str) -> Result<Vec<u32>, anyhow::Error> {
+	let values: Vec<Value> = serde_json::from_str(id_vec)
+		.map_err(|err| anyhow!("nft id vector is not a json array : {err}"))?;
+
+	if values.is_empty() {
+		return Err(anyhow!("nft id vector is empty"))
+	}
+
+	let invalid: Vec<String> = values
+		.iter()
+		.filter(|value| value.as_u64().map_or(true, |id| id > u32::MAX as u64))
+		.take(10)
+		.map(|value| value.to_string())
+		.collect();
+
+	if !invalid.is_empty() {
+		return Err(anyhow!(
+			"nft ids must be integers from 0 to {}, invalid : {invalid:?}",
+			u32::MAX
+		))
+	}
 
-	shared_state_write.set_maintenance(message);
-	debug!("Maintenance state is set.");
+	let mut seen = BTreeSet::new();
+	Ok(values
+		.iter()
+		.filter_map(|value| value.as_u64().map(|id| id as u32))
+		.filter(|id| seen.insert(*id))
+		.collect())
+}
+
+/// Keyshare bytes of each nft of the sealed directory
+pub fn keyshare_sizes(dir_path: &str) -> Result<BTreeMap<u32, u64>, anyhow::Error> {
+	let mut sizes = BTreeMap::<u32, u64>::new();
+	for direntry in std::fs::read_dir(dir_path)? {
+		let direntry = direntry?;
+		if let Ok((nft_id, _)) = helper::parse_keyshare_file(&direntry.path()) {
+			*sizes.entry(nft_id).or_default() += direntry.metadata()?.len();
+		}
+	}
+
+	Ok(sizes)
+}
+
+/// Continuation token of the page starting at the offset
+pub fn continuation_token(id_vec: &str, offset: usize) -> String {
+	format!("{offset}.{}", &sha256::digest(id_vec.as_bytes())[..16])
+}
+
+/// Offset of a continuation token, None if it does not belong to this id vector
+pub fn parse_continuation(id_vec: &str, token: &str, id_count: usize) -> Option<usize> {
+	let (offset, _) = token.split_once('.')?;
+	let offset = offset.parse::<usize>().ok()?;
+
+	(offset < id_count && continuation_token(id_vec, offset) == token).then_some(offset)
+}
+
+/// Next page of an id vector, limited in number of nfts and in keyshare bytes
+/// # Arguments
+/// * `nft_ids` - id vector of the request
+/// * `offset` - first nft of the page
+/// * `sizes` - keyshare bytes of each stored nft
+/// * `max_ids` - nfts of a page
+/// * `max_bytes` - keyshare bytes of a page
+/// # Errors
+/// * The keyshares of a single nft exceed the bytes of a page
+pub fn fetch_page(
+	nft_ids: &[u32],
+	offset: usize,
+	sizes: &BTreeMap<u32, u64>,
+	max_ids: usize,
+	max_bytes: u64,
+) -> Result<FetchPage, anyhow::Error> {
+	let mut page = FetchPage { nft_ids: Vec::new(), bytes: 0, next_offset: None };
+
+	for (index, nft_id) in nft_ids.iter().enumerate().skip(offset) {
+		let size = sizes.get(nft_id).copied().unwrap_or_default();
+
+		if size > max_bytes {
+			return Err(anyhow!(
+				"keyshares of nft {nft_id} are {size} bytes, more than the {max_bytes} bytes of an archive"
+			))
+		}
+
+		if page.nft_ids.len() == max_ids || page.bytes + size > max_bytes {
+			page.next_offset = Some(index);
+			break
+		}
+
+		page.nft_ids.push(*nft_id);
+		page.bytes += size;
+	}
+
+	Ok(page)
+}
+
+fn limit_exceeded(message: String) -> axum::response::Response {
+	warn!(message);
+	let status = ReturnStatus::BACKUPLIMITEXCEEDED;
+	(
+		StatusCode::PAYLOAD_TOO_LARGE,
+		Json(json!({
+			"status": status,
+			"code": status.code(),
+			"error": message,
+			"max_id_vector": MAX_FETCH_ID_VECTOR,
+			"max_page_ids": MAX_FETCH_IDS,
+			"max_page_bytes": MAX_FETCH_ARCHIVE_BYTES,
+		})),
+	)
+		.into_response()
 }
 
 pub async fn error_handler(message: String, state: &SharedState) -> impl IntoResponse {
@@ -252,7 +396,7 @@ pub async fn error_handler(message: String, state: &SharedState) -> impl IntoRes
 /// * `state` - StateConfig
 /// * `backup_request` - BackupRequest
 /// # Returns
-/// * `Json` - BackupResponse
+/// * `Json` - id of the background job which builds the archive
 /// # Example
 /// ```
 /// backup_key_shares(state, backup_request)
@@ -260,149 +404,185 @@ pub async fn error_handler(message: String, state: &SharedState) -> impl IntoRes
 #[axum::debug_handler]
 pub async fn admin_backup_fetch_id(
 	State(state): State<SharedState>,
+	caller: VerifiedCaller,
 	Json(backup_request): Json<IdPacket>,
 ) -> impl IntoResponse {
 	debug!("ADMIN FETCH ID : backup fetch NFTID");
 
-	update_health_status(
-		&state,
-		"ADMIN FETCH ID : Enclave is doing backup, please wait...".to_string(),
-	)
-	.await;
+	let current_block_number = get_blocknumber(&state).await;
 
-	if !verify_account_id(&state, &backup_request.admin_account).await {
-		let message = format!(
-			"ADMIN FETCH ID : Error backup key shares : Requester is not whitelisted : {}",
-			backup_request.admin_account
-		);
+	// Keyshares never leave the enclave as a plaintext archive
+	let recipient = match &backup_request.recipient {
+		Some(recipient) => recipient,
+		None => {
+			let message = "ADMIN FETCH ID : recipient is required".to_string();
+			warn!(message);
+			return (StatusCode::BAD_REQUEST, Json(json!({ "error": message }))).into_response()
+		},
+	};
 
-		return error_handler(message, &state).await.into_response()
-	}
+	let hash = sha256::digest(format!("{}_{recipient}", backup_request.id_vec).as_bytes());
 
-	let mut auth = backup_request.auth_token.clone();
+	// The whitelist, the role and the signature of the admin are verified by the auth middleware
+	if let Err((status, message)) = caller.verify_data_hash(&hash) {
+		let message = format!("ADMIN FETCH ID : {message}");
+		warn!(message);
+		return (status, Json(json!({ "error": message }))).into_response()
+	}
 
-	if auth.starts_with("<Bytes>") && auth.ends_with("</Bytes>") {
-		auth = match auth.strip_prefix("<Bytes>") {
-			Some(stripped) => stripped.to_owned(),
-			_ =>
-				return error_handler("Strip Token prefix error".to_string(), &state)
-					.await
-					.into_response(),
-		};
+	// The archive is only encrypted to a recipient of an attested enclave
+	let recipient = match &backup_request.recipient_attestation {
+		Some(attestation) if *recipient == attestation.recipient => {
+			let client = match with_http_proxy(reqwest::Client::builder()).https_only(true).build()
+			{
+				Ok(client) => client,
+				Err(err) => {
+					let message =
+						format!("ADMIN FETCH ID : unable to build a Reqwest client : {err:?}");
+					return error_handler(message, &state).await.into_response()
+				},
+			};
 
-		auth = match auth.strip_suffix("</Bytes>") {
-			Some(stripped) => stripped.to_owned(),
-			_ =>
-				return error_handler(
-					"ADMIN FETCH ID : Strip Token suffix error".to_string(),
-					&state,
-				)
-				.await
-				.into_response(),
-		}
-	}
+			match verify_recipient_attestation(&client, attestation, current_block_number).await {
+				Ok(recipient) => Some(recipient),
+				Err(err) => {
+					let message = format!("ADMIN FETCH ID : {err}");
+					warn!(message);
+					return (StatusCode::FORBIDDEN, Json(json!({ "error": message })))
+						.into_response()
+				},
+			}
+		},
+		_ => {
+			let message =
+				"ADMIN FETCH ID : recipient has no attestation of the target enclave".to_string();
+			warn!(message);
+			return (StatusCode::BAD_REQUEST, Json(json!({ "error": message }))).into_response()
+		},
+	};
 
-	let auth_token: AuthenticationToken = match serde_json::from_str(&auth) {
-		Ok(token) => token,
+	let nftidv = match sanitize_nft_ids(&backup_request.id_vec) {
+		Ok(nftidv) => nftidv,
 		Err(err) => {
-			let message =
-				format!("ADMIN FETCH ID :Error backup key shares : Authentication token is not parsable : {}", err);
-			return error_handler(message, &state).await.into_response()
+			let message = format!("ADMIN FETCH ID : {err}");
+			warn!(message);
+			let status = ReturnStatus::INVALIDNFTID;
+			return (
+				StatusCode::BAD_REQUEST,
+				Json(json!({ "status": status, "code": status.code(), "error": message })),
+			)
+				.into_response()
 		},
 	};
 
-	if !verify_signature(
-		&backup_request.admin_account,
-		backup_request.signature.clone(),
-		backup_request.auth_token.as_bytes(),
-	) {
-		return error_handler("ADMIN FETCH ID : Invalid Signature".to_string(), &state)
-			.await
-			.into_response()
+	if nftidv.len() > MAX_FETCH_ID_VECTOR {
+		return limit_exceeded(format!(
+			"ADMIN FETCH ID : {} nft ids, at most {MAX_FETCH_ID_VECTOR} can be fetched",
+			nftidv.len()
+		))
 	}
 
-	let current_block_number = get_blocknumber(&state).await;
-
-	debug!("ADMIN FETCH ID :Validating the authentication token");
-	let validity = auth_token.is_valid(current_block_number);
-	match validity {
-		ValidationResult::Success => debug!("ADMIN FETCH ID : Authentication token is valid."),
-		_ => {
-			let message = format!(
-				"ADMIN FETCH ID : Authentication Token is not valid, or expired : {:?}",
-				validity
-			);
-			return error_handler(message, &state).await.into_response()
+	let offset = match backup_request.continuation.as_deref() {
+		Some(token) => match parse_continuation(&backup_request.id_vec, token, nftidv.len()) {
+			Some(offset) => offset,
+			None => {
+				let message = format!("ADMIN FETCH ID : invalid continuation token {token}");
+				return error_handler(message, &state).await.into_response()
+			},
 		},
-	}
-
-	let hash = sha256::digest(backup_request.id_vec.as_bytes());
-
-	if auth_token.data_hash != hash {
-		return error_handler("ADMIN FETCH ID : Mismatch Data Hash".to_string(), &state)
-			.await
-			.into_response()
-	}
+		None => 0,
+	};
 
-	let nftidv: Vec<u32> = match serde_json::from_str(&backup_request.id_vec) {
-		Ok(v) => v,
+	let sizes = match keyshare_sizes(SEALPATH) {
+		Ok(sizes) => sizes,
 		Err(err) => {
-			let message = format!("ADMIN FETCH ID : unable to deserialize nftid vector : {err:?}");
-			return error_handler(message, &state).await.into_response()
+			let message = format!("ADMIN FETCH ID : unable to read the sealed directory : {err:?}");
+			error!(message);
+			return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })))
+				.into_response()
 		},
 	};
 
-	let nftids: Vec<String> = nftidv.iter().map(|x| x.to_string()).collect::<Vec<String>>();
-
-	let mut backup_file = "/temporary/backup.zip".to_string();
-	let counter = 1;
-	// remove previously generated backup
-	while std::path::Path::new(&backup_file.clone()).exists() {
-		match std::fs::remove_file(backup_file.clone()) {
-			Ok(_) => {
-				debug!("ADMIN FETCH ID : Successfully removed previous zip file")
-			},
-			Err(err) => {
-				let message = format!(
-					"ADMIN FETCH ID : Error backup key shares : Can not remove previous backup file : {}",
-					err
-				);
-				warn!(message);
-				//return Json(json!({ "error": message })).into_response()
-				backup_file = format!("/temporary/backup-{counter}.zip");
-			},
-		}
-	}
-
-	debug!("ADMIN FETCH ID :Start zippping file");
-	add_list_zip(SEALPATH, nftids, &backup_file);
+	let page = match fetch_page(&nftidv, offset, &sizes, MAX_FETCH_IDS, MAX_FETCH_ARCHIVE_BYTES) {
+		Ok(page) => page,
+		Err(err) => return limit_exceeded(format!("ADMIN FETCH ID : {err}")),
+	};
 
-	// `File` implements `AsyncRead`
-	debug!("ADMIN FETCH ID : Opening backup file");
-	let file = match tokio::fs::File::open(backup_file).await {
-		Ok(file) => file,
-		Err(err) =>
-			return Json(json!({ "error": format!("Backup File not found: {}", err) }))
-				.into_response(),
+	// Requested nfts without keyshare are listed in the manifest instead of being omitted
+	let (stored, mut not_found): (Vec<u32>, Vec<NotFoundEntry>) = {
+		let (stored, missing): (Vec<u32>, Vec<u32>) =
+			page.nft_ids.iter().partition(|nft_id| sizes.contains_key(*nft_id));
+		let not_found = missing
+			.into_iter()
+			.map(|nft_id| NotFoundEntry { nft_id, reason: NotFoundReason::NOTSTORED })
+			.collect();
+		(stored, not_found)
 	};
 
-	// convert the `AsyncRead` into a `Stream`
-	debug!("ADMIN FETCH ID : Create reader-stream");
-	let stream = ReaderStream::new(file);
+	let (selection, unchecked_onchain) =
+		if backup_request.check_onchain {
+			let (missing, unchecked) = check_onchain(&state, stored.clone()).await;
+			not_found.extend(missing.iter().map(|nft_id| NotFoundEntry {
+				nft_id: *nft_id,
+				reason: NotFoundReason::NOTONCHAIN,
+			}));
+			(stored.into_iter().filter(|nft_id| !missing.contains(nft_id)).collect(), unchecked)
+		} else {
+			(stored, Vec::new())
+		};
 
-	// convert the `Stream` into an `axum::body::HttpBody`
-	debug!("ADMIN FETCH ID : Create body-stream");
-	let body = StreamBody::new(stream);
+	not_found.sort_by_key(|entry| entry.nft_id);
+	if !not_found.is_empty() {
+		warn!("ADMIN FETCH ID : {} requested nfts are not found", not_found.len());
+	}
 
-	let headers = [
-		(header::CONTENT_TYPE, "text/toml; charset=utf-8"),
-		(header::CONTENT_DISPOSITION, "attachment; filename=\"Backup.zip\""),
-	];
+	// The archive is built in the background, the admin polls the job and downloads it
+	let job = match create_job(current_block_number, recipient.is_some(), backup_request.format) {
+		Ok(job) => job,
+		Err(err) => {
+			let message = format!("ADMIN FETCH ID : {err}");
+			warn!(message);
+			return (StatusCode::TOO_MANY_REQUESTS, Json(json!({ "error": message })))
+				.into_response()
+		},
+	};
 
-	update_health_status(&state, String::new()).await;
+	info!(
+		"ADMIN FETCH ID : backup job {} of {} nfts from offset {offset} is queued, {:?} format",
+		job.job_id,
+		page.nft_ids.len(),
+		backup_request.format
+	);
+	tokio::spawn(run_backup_job(
+		state.clone(),
+		job.job_id.clone(),
+		BackupSelection::NFTIDS(selection),
+		recipient,
+		backup_request.format,
+		not_found.clone(),
+	));
 
-	debug!("ADMIN FETCH ID : Sending the backup data to the client ...");
-	(headers, body).into_response()
+	(
+		StatusCode::ACCEPTED,
+		Json(json!({
+			"job_id": job.job_id,
+			"status": format!("/api/backup/status/{}", job.job_id),
+			// The archive is only served with a one-time download token
+			"download_token": "/api/backup/download-token",
+			"expiry_block": job.expiry_block,
+			"nft_ids": page.nft_ids.len(),
+			"archive_bytes": page.bytes,
+			// Token of the next page, null for the last page
+			"continuation": page
+				.next_offset
+				.map(|next| continuation_token(&backup_request.id_vec, next)),
+			"remaining": nftidv.len() - page.next_offset.unwrap_or(nftidv.len()),
+			"not_found": not_found,
+			// Nfts which could not be checked onchain, they are archived
+			"unchecked_onchain": unchecked_onchain,
+		})),
+	)
+		.into_response()
 }
 
 /*
@@ -411,85 +591,24 @@ pub async fn admin_backup_fetch_id(
 #[axum::debug_handler]
 pub async fn admin_backup_push_id(
 	State(state): State<SharedState>,
+	caller: VerifiedCaller,
 	Json(backup_request): Json<IdPacket>,
 ) -> impl IntoResponse {
 	debug!("ADMIN PUSH ID : backup fetch NFTID");
 
-	update_health_status(
-		&state,
-		"ADMIN PUSH ID : Enclave is doing backup, please wait...".to_string(),
-	)
-	.await;
-
-	if !verify_account_id(&state, &backup_request.admin_account).await {
-		let message = format!(
-			"ADMIN PUSH ID : Error backup key shares : Requester is not whitelisted : {}",
-			backup_request.admin_account
-		);
-
-		return error_handler(message, &state).await.into_response()
-	}
-
-	let mut auth = backup_request.auth_token.clone();
-
-	if auth.starts_with("<Bytes>") && auth.ends_with("</Bytes>") {
-		auth = match auth.strip_prefix("<Bytes>") {
-			Some(stripped) => stripped.to_owned(),
-			_ =>
-				return error_handler("Strip Token prefix error".to_string(), &state)
-					.await
-					.into_response(),
-		};
-
-		auth = match auth.strip_suffix("</Bytes>") {
-			Some(stripped) => stripped.to_owned(),
-			_ =>
-				return error_handler("ADMIN PUSH ID : Strip Token suffix error".to_string(), &state)
-					.await
-					.into_response(),
-		}
-	}
-
-	let auth_token: AuthenticationToken = match serde_json::from_str(&auth) {
-		Ok(token) => token,
-		Err(err) => {
-			let message =
-				format!("ADMIN PUSH ID : Error backup key shares : Authentication token is not parsable : {}", err);
-			return error_handler(message, &state).await.into_response()
-		},
-	};
-
-	if !verify_signature(
-		&backup_request.admin_account,
-		backup_request.signature.clone(),
-		backup_request.auth_token.as_bytes(),
-	) {
-		return error_handler("ADMIN PUSH ID : Invalid Signature".to_string(), &state)
-			.await
-			.into_response()
-	}
-
-	let current_block_number = get_blocknumber(&state).await;
-
-	debug!("ADMIN PUSH ID :Validating the authentication token");
-	let validity = auth_token.is_valid(current_block_number);
-	match validity {
-		ValidationResult::Success => debug!("ADMIN PUSH ID : Authentication token is valid."),
-		_ => {
-			let message = format!(
-				"ADMIN PUSH ID : Authentication Token is not valid, or expired : {:?}",
-				validity
-			);
-			return error_handler(message, &state).await.into_response()
-		},
+	if let Err(err) = verify_writable(&state).await {
+		let message = format!("ADMIN PUSH ID : restore is rejected : {err:?}");
+		warn!(message);
+		return (StatusCode::SERVICE_UNAVAILABLE, Json(json!({ "error": message }))).into_response()
 	}
 
 	let hash = sha256::digest(backup_request.id_vec.as_bytes());
 
-	if auth_token.data_hash != hash {
-		return error_handler("ADMIN PUSH ID : Mismatch Data Hash".to_string(), &state)
-			.await
-			.into_response()
+	// The whitelist, the role and the signature of the admin are verified by the auth middleware
+	if let Err((status, message)) = caller.verify_data_hash(&hash) {
+		let message = format!("ADMIN PUSH ID : {message}");
+		warn!(message);
+		return (status, Json(json!({ "error": message }))).into_response()
 	}
 
 	let nftidv: Vec<String> = match serde_json::from_str(&backup_request.id_vec) {
@@ -500,6 +619,26 @@ pub async fn admin_backup_push_id(
 		},
 	};
 
+	// Keyshare requests are held until the restored keyshares are written
+	let _operation = match get_coordinator(&state)
+		.await
+		.begin_maintenance(
+			MaintenanceOperation::RESTORE,
+			"push-id",
+			Duration::from_secs(get_runtime_config(&state).await.maintenance_queue_timeout),
+		)
+		.await
+	{
+		Ok(operation) => operation,
+		Err(conflict) => {
+			warn!("ADMIN PUSH ID : {conflict}");
+			return conflict.into_response()
+		},
+	};
+	let _readiness = get_readiness(&state).await.hold(ReadinessBlocker::RESTORING);
+
+	let mut results = Vec::<BatchItemResult>::new();
+
 	let id_keyshare: Vec<Option<(&str, &str)>> =
 		nftidv.iter().map(|x| x.rsplit_once('_')).collect();
 	for id_key in id_keyshare {
@@ -518,6 +657,14 @@ pub async fn admin_backup_push_id(
 						},
 						|| sentry::capture_message(&message, sentry::Level::Error),
 					);
+					// nft_id is unknown
+					results.push(BatchItemResult::failure(
+						0,
+						ReturnStatus::INVALIDNFTID,
+						VerificationStep::PARSING,
+						Retryability::PERMANENT,
+						message,
+					));
 					continue
 				},
 			};
@@ -536,6 +683,13 @@ pub async fn admin_backup_push_id(
 						},
 						|| sentry::capture_message(&message, sentry::Level::Error),
 					);
+					results.push(BatchItemResult::failure(
+						nft_id,
+						ReturnStatus::InvalidBlockNumber,
+						VerificationStep::PARSING,
+						Retryability::PERMANENT,
+						message,
+					));
 					continue
 				},
 			};
@@ -555,6 +709,13 @@ pub async fn admin_backup_push_id(
 						},
 						|| sentry::capture_message(&message, sentry::Level::Error),
 					);
+					results.push(BatchItemResult::failure(
+						nft_id,
+						ReturnStatus::INVALIDDATAFORMAT,
+						VerificationStep::PARSING,
+						Retryability::PERMANENT,
+						message,
+					));
 					continue
 				},
 			};
@@ -602,6 +763,13 @@ pub async fn admin_backup_push_id(
 						(nft_id, helper::Availability { block_number, nft_type }),
 					)
 					.await;
+					publish_keyshare_event(
+						KeyshareEventKind::RESTORED,
+						nft_id,
+						nft_type,
+						block_number,
+					);
+					results.push(BatchItemResult::success(nft_id, ReturnStatus::STORESUCCESS));
 				},
 				Err(err) => {
 					let message = format!(
@@ -616,6 +784,13 @@ pub async fn admin_backup_push_id(
 						},
 						|| sentry::capture_message(&message, sentry::Level::Error),
 					);
+					results.push(BatchItemResult::failure(
+						nft_id,
+						ReturnStatus::DATABASEFAILURE,
+						VerificationStep::STORAGE,
+						Retryability::RETRYABLE,
+						message,
+					));
 				},
 			}
 		} else {
@@ -624,10 +799,16 @@ pub async fn admin_backup_push_id(
 		}
 	}
 
+	let errors = batch_errors(&results, ReturnStatus::STORESUCCESS);
+	let failed = errors.len();
+	let status = if failed == 0 { StatusCode::OK } else { StatusCode::MULTI_STATUS };
+
 	(
-		StatusCode::OK,
+		status,
 		Json(json!({
-			"success": format!("Success restoring backups"),
+			"success": format!("Restored {} of {} backups", results.len() - failed, results.len()),
+			"results": results,
+			"errors": errors,
 		})),
 	)
 		.into_response()
@@ -694,6 +875,11 @@ mod test {
 			id_vec: nftids_str,
 			auth_token: auth_str,
 			signature: sig_str,
+			recipient: None,
+			recipient_attestation: None,
+			format: BackupFormat::ZIP,
+			continuation: None,
+			check_onchain: false,
 		};
 
 		let request_body = serde_json::to_string(&request).unwrap();
@@ -714,8 +900,9 @@ mod test {
 
 		//let app = Router::new().route("/admin_backup_fetch_id",
 		// post(admin_backup_fetch_id)).with_state(state_config);
-		let mut app = match crate::servers::http_server::http_server().await {
-			Ok(r) => r,
+		// No heartbeat, no secondary rpc, no compression
+		let mut app = match crate::servers::http_server::http_server(0, None, 0, true).await {
+			Ok((app, _supervisor, _state)) => app,
 			Err(err) => {
 				error!("Error creating http server {}", err);
 				return
@@ -770,6 +957,49 @@ mod test {
 		file.write_all(&body_bytes).unwrap();
 	}
 
+	#[test]
+	fn sanitize_nft_ids_test() {
+		assert_eq!(sanitize_nft_ids("[30, 10, 30, 0, 10]").unwrap(), vec![30, 10, 0]);
+		assert_eq!(sanitize_nft_ids("[4294967295]").unwrap(), vec![u32::MAX]);
+
+		assert!(sanitize_nft_ids("[]").is_err());
+		assert!(sanitize_nft_ids("10").is_err());
+		assert!(sanitize_nft_ids("[-1]").is_err());
+		assert!(sanitize_nft_ids("[4294967296]").is_err());
+		assert!(sanitize_nft_ids("[1.5]").is_err());
+		assert!(sanitize_nft_ids("[\"12\"]").is_err());
+	}
+
+	#[test]
+	fn fetch_page_test() {
+		let nft_ids: Vec<u32> = (1..=10).collect();
+		let sizes: BTreeMap<u32, u64> = nft_ids.iter().map(|id| (*id, 100)).collect();
+
+		let page = fetch_page(&nft_ids, 0, &sizes, 4, 10_000).unwrap();
+		assert_eq!(page.nft_ids, vec![1, 2, 3, 4]);
+		assert_eq!(page.next_offset, Some(4));
+
+		// Bytes limit, nfts without keyshare are free
+		let page = fetch_page(&nft_ids, 4, &sizes, 100, 250).unwrap();
+		assert_eq!(page.nft_ids, vec![5, 6]);
+		assert_eq!(page.bytes, 200);
+		let page = fetch_page(&[20, 21, 22], 0, &sizes, 100, 250).unwrap();
+		assert_eq!(page.next_offset, None);
+
+		let page = fetch_page(&nft_ids, 8, &sizes, 4, 10_000).unwrap();
+		assert_eq!(page.nft_ids, vec![9, 10]);
+		assert_eq!(page.next_offset, None);
+
+		assert!(fetch_page(&nft_ids, 0, &sizes, 4, 50).is_err());
+
+		let id_vec = serde_json::to_string(&nft_ids).unwrap();
+		let token = continuation_token(&id_vec, 4);
+		assert_eq!(parse_continuation(&id_vec, &token, nft_ids.len()), Some(4));
+		assert_eq!(parse_continuation("[1,2]", &token, 2), None);
+		assert_eq!(parse_continuation(&id_vec, &continuation_token(&id_vec, 10), 10), None);
+		assert_eq!(parse_continuation(&id_vec, "4", 10), None);
+	}
+
 	#[test]
 	fn test_get_signature_valid() {
 		let input = "0xb7255023814e304b72bc880cc993d5c654ce060db0c3f0772b453714c760521962943747af605a90d0503812c6a62c5c1080cbf377095551af0c168a8c724da8".to_string();
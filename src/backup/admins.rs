@@ -0,0 +1,271 @@
+use std::{collections::BTreeMap, str::FromStr, sync::OnceLock};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use subxt::utils::AccountId32;
+use tracing::warn;
+
+use crate::{
+	chain::constants::{BOOTSTRAP_ADMINS, CLUSTER_REFRESH_INTERVAL},
+	servers::state::{get_clusters, SharedState},
+};
+
+use super::sync::{Cluster, ClusterType};
+
+/* *************************************
+	ADMIN WHITELIST
+**************************************** */
+
+// Admins are the enclave accounts of the Admin cluster of the TEE pallet, they are rotated
+// onchain without a rebuild. The compiled-in bootstrap admins are only used while the chain has
+// none, they are never taken from the command line which is controlled by the host.
+
+/// Origin of the admin whitelist
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum AdminSource {
+	CHAIN,
+	BOOTSTRAP,
+}
+
+/// Enclave accounts of the Admin clusters
+pub fn chain_admins(clusters: &[Cluster]) -> Vec<String> {
+	clusters
+		.iter()
+		.filter(|cluster| cluster.cluster_type == ClusterType::Admin)
+		.flat_map(|cluster| cluster.enclaves.iter().map(|e| e.enclave_account.to_string()))
+		.collect()
+}
+
+/// Onchain admins, or the bootstrap admins if the chain has none
+pub fn resolve_whitelist(chain: Vec<String>, bootstrap: &[&str]) -> (Vec<String>, AdminSource) {
+	if chain.is_empty() {
+		(bootstrap.iter().map(|admin| admin.to_string()).collect(), AdminSource::BOOTSTRAP)
+	} else {
+		(chain, AdminSource::CHAIN)
+	}
+}
+
+/// Whitelisted admin accounts and their origin
+pub async fn admin_whitelist(state: &SharedState) -> (Vec<String>, AdminSource) {
+	let (admins, source) =
+		resolve_whitelist(chain_admins(&get_clusters(state).await), BOOTSTRAP_ADMINS);
+
+	if source == AdminSource::BOOTSTRAP {
+		warn!(
+			"ADMIN WHITELIST : no Admin cluster onchain, using {} bootstrap admins",
+			admins.len()
+		);
+	}
+
+	(admins, source)
+}
+
+/// Whitelisted admin accounts
+pub async fn admin_accounts(state: &SharedState) -> Vec<String> {
+	admin_whitelist(state).await.0
+}
+
+/// Check if an account is a whitelisted admin
+pub async fn is_admin(state: &SharedState, account_id: &str) -> bool {
+	admin_accounts(state).await.iter().any(|admin| admin == account_id)
+}
+
+/* *************************************
+	ADMIN ROLES
+**************************************** */
+
+// Each whitelisted admin has a role, given by "--admin-role ACCOUNT=ROLE" at startup.
+// Admins without a configured role have the NONE role, a missing option grants no permission.
+static ADMIN_ROLES: OnceLock<BTreeMap<String, AdminRole>> = OnceLock::new();
+
+/// Backup operations allowed to an admin
+#[allow(non_camel_case_types)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum AdminRole {
+	// Fetch archives and inspect the inventories
+	FETCH_ONLY,
+	// Restore archives
+	PUSH_ONLY,
+	// Every admin operation, including the quorum and enclave management
+	FULL,
+	// No admin operation, the role of the admins without a configured role
+	NONE,
+}
+
+/// Kind of an admin request
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum AdminOperation {
+	// fetch-id, fetch-bulk, audit export, inventory comparisons
+	FETCH,
+	// push-id, push-bulk, resumable uploads
+	PUSH,
+	// quorum rotation, read-only switch, provisioning, upgrades
+	MANAGE,
+}
+
+impl AdminRole {
+	pub fn allows(&self, operation: AdminOperation) -> bool {
+		matches!(
+			(self, operation),
+			(AdminRole::FULL, _) |
+				(AdminRole::FETCH_ONLY, AdminOperation::FETCH) |
+				(AdminRole::PUSH_ONLY, AdminOperation::PUSH)
+		)
+	}
+}
+
+impl FromStr for AdminRole {
+	type Err = anyhow::Error;
+
+	fn from_str(role: &str) -> Result<Self, Self::Err> {
+		match role.to_uppercase().as_str() {
+			"FETCH_ONLY" => Ok(AdminRole::FETCH_ONLY),
+			"PUSH_ONLY" => Ok(AdminRole::PUSH_ONLY),
+			"FULL" => Ok(AdminRole::FULL),
+			_ => Err(anyhow!("ADMIN ROLES : unknown role {role}")),
+		}
+	}
+}
+
+/// Parse "ACCOUNT=ROLE" role assignments
+pub fn parse_admin_roles(specs: &[String]) -> Result<BTreeMap<String, AdminRole>, anyhow::Error> {
+	let mut roles = BTreeMap::new();
+	for spec in specs {
+		let (account, role) = spec
+			.split_once('=')
+			.ok_or_else(|| anyhow!("ADMIN ROLES : expected ACCOUNT=ROLE, got {spec}"))?;
+
+		AccountId32::from_str(account)
+			.map_err(|err| anyhow!("ADMIN ROLES : invalid admin {account} : {err:?}"))?;
+
+		if roles.insert(account.to_string(), role.parse()?).is_some() {
+			return Err(anyhow!("ADMIN ROLES : {account} has several roles"))
+		}
+	}
+
+	Ok(roles)
+}
+
+/// Set the roles of the admins, only once at startup
+pub fn set_admin_roles(specs: Vec<String>) -> Result<(), anyhow::Error> {
+	ADMIN_ROLES
+		.set(parse_admin_roles(&specs)?)
+		.map_err(|_| anyhow!("ADMIN ROLES : roles are already set"))
+}
+
+/// Configured roles of the admins
+pub fn admin_roles() -> &'static BTreeMap<String, AdminRole> {
+	ADMIN_ROLES.get_or_init(BTreeMap::new)
+}
+
+/// Role of an admin in a role assignment, NONE if it is not assigned
+pub fn role_of(roles: &BTreeMap<String, AdminRole>, account_id: &str) -> AdminRole {
+	roles.get(account_id).copied().unwrap_or(AdminRole::NONE)
+}
+
+/// Role of an admin, NONE if it is not configured
+pub fn admin_role(account_id: &str) -> AdminRole {
+	role_of(admin_roles(), account_id)
+}
+
+/// Check the role of an admin for an operation
+/// # Errors
+/// * Message of the authorization failure, for a 403 response
+pub fn authorize_admin(account_id: &str, operation: AdminOperation) -> Result<(), String> {
+	let role = admin_role(account_id);
+	if role.allows(operation) {
+		Ok(())
+	} else {
+		Err(format!("admin {account_id} with role {role:?} is not allowed to {operation:?}"))
+	}
+}
+
+/// Clusters are discovered again periodically, in case a TEE event has been missed
+pub fn is_cluster_refresh_due(block_number: u32) -> bool {
+	block_number % CLUSTER_REFRESH_INTERVAL == 0
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::backup::sync::Enclave;
+
+	fn cluster(id: u32, cluster_type: ClusterType, accounts: &[u8]) -> Cluster {
+		Cluster {
+			id,
+			cluster_type,
+			enclaves: accounts
+				.iter()
+				.enumerate()
+				.map(|(slot, account)| Enclave {
+					slot: slot as u32,
+					operator_account: AccountId32([0u8; 32]),
+					enclave_account: AccountId32([*account; 32]),
+					enclave_url: String::new(),
+				})
+				.collect(),
+		}
+	}
+
+	#[test]
+	fn admin_whitelist_test() {
+		let bootstrap_admin = AccountId32([9u8; 32]).to_string();
+		let bootstrap = [bootstrap_admin.as_str()];
+
+		let clusters =
+			vec![cluster(0, ClusterType::Public, &[1, 2]), cluster(1, ClusterType::Admin, &[3])];
+		let (admins, source) = resolve_whitelist(chain_admins(&clusters), &bootstrap);
+		assert_eq!(admins, vec![AccountId32([3u8; 32]).to_string()]);
+		assert_eq!(source, AdminSource::CHAIN);
+
+		// No Admin cluster onchain
+		let clusters = vec![cluster(0, ClusterType::Public, &[1, 2])];
+		let (admins, source) = resolve_whitelist(chain_admins(&clusters), &bootstrap);
+		assert_eq!(admins, vec![bootstrap_admin.clone()]);
+		assert_eq!(source, AdminSource::BOOTSTRAP);
+
+		// The compiled-in admins are valid accounts
+		for admin in BOOTSTRAP_ADMINS {
+			assert!(AccountId32::from_str(admin).is_ok(), "invalid bootstrap admin {admin}");
+		}
+
+		assert!(is_cluster_refresh_due(CLUSTER_REFRESH_INTERVAL * 3));
+		assert!(!is_cluster_refresh_due(CLUSTER_REFRESH_INTERVAL + 1));
+	}
+
+	#[test]
+	fn admin_roles_test() {
+		let fetcher = AccountId32([1u8; 32]).to_string();
+		let pusher = AccountId32([2u8; 32]).to_string();
+
+		let roles =
+			parse_admin_roles(&[format!("{fetcher}=FETCH_ONLY"), format!("{pusher}=push_only")])
+				.unwrap();
+		assert_eq!(roles[&fetcher], AdminRole::FETCH_ONLY);
+		assert_eq!(roles[&pusher], AdminRole::PUSH_ONLY);
+
+		assert!(AdminRole::FETCH_ONLY.allows(AdminOperation::FETCH));
+		assert!(!AdminRole::FETCH_ONLY.allows(AdminOperation::PUSH));
+		assert!(!AdminRole::PUSH_ONLY.allows(AdminOperation::MANAGE));
+		assert!(AdminRole::FULL.allows(AdminOperation::MANAGE));
+
+		assert!(parse_admin_roles(&[format!("{fetcher}=ROOT")]).is_err());
+		assert!(parse_admin_roles(&[fetcher.clone()]).is_err());
+		assert!(parse_admin_roles(&["5xxxx=FULL".to_string()]).is_err());
+		assert!(parse_admin_roles(&[format!("{fetcher}=FULL"), format!("{fetcher}=PUSH_ONLY")])
+			.is_err());
+
+		// Admins without a configured role have no permission
+		let unlisted = AccountId32([3u8; 32]).to_string();
+		assert_eq!(admin_role(&unlisted), AdminRole::NONE);
+		// push-id, push-bulk and uploads
+		assert!(authorize_admin(&unlisted, AdminOperation::PUSH).is_err());
+		// fetch-id and fetch-bulk
+		assert!(authorize_admin(&unlisted, AdminOperation::FETCH).is_err());
+		assert!(authorize_admin(&unlisted, AdminOperation::MANAGE).is_err());
+	}
+}
@@ -0,0 +1,125 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use subxt::ext::sp_core::{crypto::Ss58Codec, Pair};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+	attestation::keys::KeyPurpose,
+	chain::{
+		audit::{read_audit_snapshot, verify_audit_chain, AuditBreak, AuditHead},
+		constants::MAX_AUDIT_EXPORT,
+	},
+	servers::{
+		auth::VerifiedCaller,
+		state::{get_accountid, get_subkey, SharedState},
+	},
+};
+
+/* *************************************
+		AUDIT LOG EXPORT
+**************************************** */
+
+/// Audit log export request, signed by the admin quorum
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AuditExportPacket {
+	// First exported record
+	#[serde(default)]
+	from_index: u64,
+}
+
+/// Data hash signed by the admins for an audit export
+pub fn audit_export_data_hash(from_index: u64) -> String {
+	sha256::digest(format!("audit-export_{from_index}").as_bytes())
+}
+
+/// Message signed by the enclave account for the exported head
+pub fn audit_head_message(head: &AuditHead) -> String {
+	format!("audit-head_{}_{}", head.next_index, head.last_hash)
+}
+
+/// Export the verification audit trail and verify its chain
+/// # Arguments
+/// * `state` - SharedState
+/// * `request` - AuditExportPacket
+/// # Returns
+/// * `Json` - records from `from_index`, the head signed by the enclave, and the first broken
+///   record if the chain is not valid. A removed tail is detected by comparing the head of the file
+///   with the head of the running enclave
+#[axum::debug_handler]
+pub async fn admin_audit_export(
+	State(state): State<SharedState>,
+	caller: VerifiedCaller,
+	Json(request): Json<AuditExportPacket>,
+) -> impl IntoResponse {
+	debug!("ADMIN AUDIT EXPORT : start");
+
+	if let Err((status, message)) =
+		caller.verify_data_hash(&audit_export_data_hash(request.from_index))
+	{
+		let message = format!("ADMIN AUDIT EXPORT : {message}");
+		warn!(message);
+		return (status, Json(json!({ "error": message })))
+	}
+
+	// The file and the head of the running enclave are read between two appends
+	let (records, enclave_head) = match read_audit_snapshot(&state).await {
+		Ok(snapshot) => snapshot,
+		Err(err) => {
+			let message = format!("ADMIN AUDIT EXPORT : error reading audit log : {err:?}");
+			error!(message);
+			return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })))
+		},
+	};
+
+	let broken_at = match verify_audit_chain(&records) {
+		Ok(head) if head == enclave_head => None,
+		Ok(head) => Some(AuditBreak {
+			index: head.next_index,
+			description: format!(
+				"log ends at record {}, enclave head is at record {}",
+				head.next_index, enclave_head.next_index
+			),
+		}),
+		Err(chain_break) => Some(chain_break),
+	};
+
+	if let Some(chain_break) = &broken_at {
+		error!(
+			"ADMIN AUDIT EXPORT : chain is broken at record {} : {}",
+			chain_break.index, chain_break.description
+		);
+	}
+
+	// The head is signed by the audit log subkey, certified by the enclave account
+	let audit_key = get_subkey(&state, KeyPurpose::AUDITLOG).await;
+	let head_signature = audit_key.sign(audit_head_message(&enclave_head).as_bytes());
+
+	let exported: Vec<_> = records
+		.into_iter()
+		.skip_while(|record| record.index < request.from_index)
+		.take(MAX_AUDIT_EXPORT)
+		.collect();
+	let next_index = exported
+		.last()
+		.map(|record| record.index + 1)
+		.filter(|index| *index < enclave_head.next_index);
+
+	info!("ADMIN AUDIT EXPORT : {} records exported from {}", exported.len(), request.from_index);
+
+	(
+		StatusCode::OK,
+		Json(json!({
+			"enclave_account": get_accountid(&state).await,
+			"head": enclave_head,
+			// Signature of "audit-head_NEXTINDEX_LASTHASH" by the audit log subkey
+			"head_signer": audit_key.public().to_ss58check(),
+			"head_signature": format!("{}{:?}", "0x", head_signature),
+			"verified": broken_at.is_none(),
+			"broken_at": broken_at,
+			"records": exported,
+			// Index of the next page, null if the export is complete
+			"next_index": next_index,
+		})),
+	)
+}
@@ -0,0 +1,337 @@
+use std::{
+	collections::BTreeMap,
+	fs::{self, File},
+	io::{self, BufReader, BufWriter, Read, Write},
+	path::Path,
+};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::info;
+
+use crate::chain::constants::MAX_CONTAINER_MANIFEST_SIZE;
+
+use super::{
+	manifest::{BackupManifest, MANIFEST_FILE},
+	zipdir::add_manifest_zip_progress,
+};
+
+/* ---------------------------------------
+	BACKUP CONTAINER V2
+--------------------------------------- */
+
+// Layout of a v2 container, after the header everything is one zstd stream :
+// MAGIC (4) | VERSION (1) | zstd(
+//     MANIFEST_LEN (u32 LE) | signed manifest json |
+//     BLOB_COUNT (u32 LE) | [ SHA256 (32) | LEN (u64 LE) | content ]...
+// )
+// Blobs are keyed by the sha256 of their content, files of the manifest with the same content
+// share one blob. ZIP archives have no such header and are still restored.
+
+pub const CONTAINER_MAGIC: [u8; 4] = *b"TNBK";
+pub const CONTAINER_VERSION: u8 = 2;
+const CONTAINER_LEVEL: i32 = 9;
+
+/// Format of the fetched backup archives
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum BackupFormat {
+	// Deflated zip, one entry per file
+	#[default]
+	ZIP,
+	// zstd container of deduplicated content-addressed blobs
+	V2,
+}
+
+impl BackupFormat {
+	pub fn extension(&self) -> &'static str {
+		match self {
+			BackupFormat::ZIP => "zip",
+			BackupFormat::V2 => "tbk",
+		}
+	}
+
+	/// Download name of the archive
+	pub fn file_name(&self) -> String {
+		format!("Backup.{}", self.extension())
+	}
+}
+
+/// Write the archive of a signed manifest in the requested format
+/// # Returns
+/// * `usize` - number of archived files
+pub fn write_archive(
+	format: BackupFormat,
+	src_dir: &str,
+	manifest: &BackupManifest,
+	dst_file: &str,
+	progress: impl FnMut(usize, u64),
+) -> Result<usize, anyhow::Error> {
+	match format {
+		BackupFormat::ZIP => Ok(add_manifest_zip_progress(src_dir, manifest, dst_file, progress)?),
+		BackupFormat::V2 => write_container(src_dir, manifest, Path::new(dst_file), progress),
+	}
+}
+
+/// Write a v2 container, each distinct content is stored once
+/// # Arguments
+/// * `src_dir` - sealed directory
+/// * `manifest` - signed manifest, its sha256 are the keys of the blobs
+/// * `dst_file` - container file
+/// * `progress` - called with the files and bytes archived so far
+/// # Errors
+/// * A file has changed since the manifest has been built
+pub fn write_container(
+	src_dir: &str,
+	manifest: &BackupManifest,
+	dst_file: &Path,
+	mut progress: impl FnMut(usize, u64),
+) -> Result<usize, anyhow::Error> {
+	let mut writer = BufWriter::new(File::create(dst_file)?);
+	writer.write_all(&CONTAINER_MAGIC)?;
+	writer.write_all(&[CONTAINER_VERSION])?;
+
+	let mut encoder = zstd::stream::Encoder::new(writer, CONTAINER_LEVEL)?;
+
+	let data = serde_json::to_vec(manifest)?;
+	encoder.write_all(&(data.len() as u32).to_le_bytes())?;
+	encoder.write_all(&data)?;
+
+	// First file of each distinct content
+	let mut blobs = BTreeMap::<&String, &String>::new();
+	for entry in &manifest.entries {
+		blobs.entry(&entry.sha256).or_insert(&entry.file);
+	}
+	encoder.write_all(&(blobs.len() as u32).to_le_bytes())?;
+
+	let mut files = 0usize;
+	let mut bytes = 0u64;
+	for (sha256, file) in &blobs {
+		let key: [u8; 32] = hex::decode(sha256)?
+			.try_into()
+			.map_err(|_| anyhow!("BACKUP CONTAINER : invalid sha256 of {file}"))?;
+
+		let mut reader = File::open(Path::new(src_dir).join(file))?;
+		let size = reader.metadata()?.len();
+
+		encoder.write_all(&key)?;
+		encoder.write_all(&size.to_le_bytes())?;
+
+		// Hashed while streamed, the container matches its manifest
+		let mut hasher = Sha256::new();
+		let copied =
+			io::copy(&mut (&mut reader).take(size), &mut HashWriter(&mut encoder, &mut hasher))?;
+
+		if copied != size || hex::encode(hasher.finalize()) != **sha256 {
+			return Err(anyhow!("BACKUP CONTAINER : {file} has changed during the backup"))
+		}
+
+		files += manifest.entries.iter().filter(|entry| entry.sha256 == **sha256).count();
+		bytes += size;
+		progress(files, bytes);
+	}
+
+	encoder.finish()?.flush()?;
+
+	info!(
+		"BACKUP CONTAINER : {} files in {} blobs of {} written to {:?}",
+		manifest.entries.len(),
+		blobs.len(),
+		src_dir,
+		dst_file
+	);
+
+	Ok(manifest.entries.len())
+}
+
+struct HashWriter<'a, W: Write>(&'a mut W, &'a mut Sha256);
+
+impl<W: Write> Write for HashWriter<'_, W> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let written = self.0.write(buf)?;
+		self.1.update(&buf[..written]);
+		Ok(written)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.0.flush()
+	}
+}
+
+/// Check the header of a v2 container
+pub fn is_container(path: &Path) -> Result<bool, anyhow::Error> {
+	let mut header = Vec::with_capacity(CONTAINER_MAGIC.len());
+	File::open(path)?.take(CONTAINER_MAGIC.len() as u64).read_to_end(&mut header)?;
+	Ok(header == CONTAINER_MAGIC)
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+	let mut buffer = [0u8; 4];
+	reader.read_exact(&mut buffer)?;
+	Ok(u32::from_le_bytes(buffer))
+}
+
+/// Extract the selected files of a v2 container, with its manifest
+/// The manifest is not verified here, the extracted directory is checked like a zip archive.
+/// # Arguments
+/// * `src_file` - container file
+/// * `outdir` - staging directory
+/// * `selected` - file names to extract, the manifest is always extracted
+/// # Errors
+/// * Unsupported version, malformed container, blob with another content or not in the manifest
+pub fn extract_container(
+	src_file: &Path,
+	outdir: &Path,
+	selected: impl Fn(&str) -> bool,
+) -> Result<(), anyhow::Error> {
+	let mut reader = BufReader::new(File::open(src_file)?);
+
+	let mut header = [0u8; 5];
+	reader.read_exact(&mut header)?;
+	if header[..4] != CONTAINER_MAGIC {
+		return Err(anyhow!("BACKUP CONTAINER : not a backup container"))
+	}
+	if header[4] != CONTAINER_VERSION {
+		return Err(anyhow!("BACKUP CONTAINER : unsupported version {}", header[4]))
+	}
+
+	let mut decoder = zstd::stream::Decoder::with_buffer(reader)?;
+
+	let manifest_len = read_u32(&mut decoder)? as u64;
+	if manifest_len > MAX_CONTAINER_MANIFEST_SIZE {
+		return Err(anyhow!("BACKUP CONTAINER : manifest of {manifest_len} bytes is too large"))
+	}
+	let mut data = Vec::new();
+	(&mut decoder).take(manifest_len).read_to_end(&mut data)?;
+	let manifest: BackupManifest = serde_json::from_slice(&data)?;
+
+	// Plain file names only, as in zip archives
+	let mut targets = BTreeMap::<&String, Vec<&String>>::new();
+	for entry in &manifest.entries {
+		if Path::new(&entry.file).file_name().and_then(std::ffi::OsStr::to_str) !=
+			Some(entry.file.as_str()) ||
+			entry.file == MANIFEST_FILE
+		{
+			return Err(anyhow!("BACKUP CONTAINER : invalid file name {}", entry.file))
+		}
+
+		let files = targets.entry(&entry.sha256).or_default();
+		if selected(&entry.file) {
+			files.push(&entry.file);
+		}
+	}
+
+	fs::create_dir_all(outdir)?;
+	fs::write(outdir.join(MANIFEST_FILE), &data)?;
+
+	let blob_count = read_u32(&mut decoder)?;
+	for _ in 0..blob_count {
+		let mut key = [0u8; 32];
+		decoder.read_exact(&mut key)?;
+		let mut size = [0u8; 8];
+		decoder.read_exact(&mut size)?;
+		let size = u64::from_le_bytes(size);
+
+		let sha256 = hex::encode(key);
+		let files = targets
+			.get(&sha256)
+			.ok_or_else(|| anyhow!("BACKUP CONTAINER : blob {sha256} is not in the manifest"))?;
+
+		// Unselected blobs are read and dropped
+		let mut hasher = Sha256::new();
+		let copied = match files.first() {
+			Some(first) => {
+				let mut writer = BufWriter::new(File::create(outdir.join(first))?);
+				let copied = io::copy(
+					&mut (&mut decoder).take(size),
+					&mut HashWriter(&mut writer, &mut hasher),
+				)?;
+				writer.flush()?;
+				copied
+			},
+			None => io::copy(
+				&mut (&mut decoder).take(size),
+				&mut HashWriter(&mut io::sink(), &mut hasher),
+			)?,
+		};
+
+		if copied != size || hex::encode(hasher.finalize()) != sha256 {
+			return Err(anyhow!("BACKUP CONTAINER : blob {sha256} is truncated or altered"))
+		}
+
+		for file in files.iter().skip(1) {
+			fs::copy(outdir.join(files[0]), outdir.join(file))?;
+		}
+	}
+
+	Ok(())
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::backup::manifest::{build_manifest, check_archive, BackupSelection};
+	use subxt::ext::sp_core::{sr25519, Pair};
+
+	#[test]
+	fn container_test() {
+		let dir = std::env::temp_dir().join(format!("backup-container-{}", std::process::id()));
+		let sealed = dir.join("sealed");
+		let staging = dir.join("restore");
+		let _ = fs::remove_dir_all(&dir);
+		fs::create_dir_all(&sealed).unwrap();
+
+		let keyshare = "k".repeat(4096);
+		fs::write(sealed.join("nft_10_100.keyshare"), &keyshare).unwrap();
+		fs::write(sealed.join("capsule_10_100.keyshare"), &keyshare).unwrap();
+		fs::write(sealed.join("nft_20_200.keyshare"), b"other").unwrap();
+
+		let keypair = sr25519::Pair::from_seed(&[7u8; 32]);
+		let mut manifest = build_manifest(
+			sealed.to_str().unwrap(),
+			&BackupSelection::ALL,
+			keypair.public().to_string(),
+			300,
+		)
+		.unwrap();
+		manifest.sign(&keypair).unwrap();
+
+		let container = dir.join("Backup.tbk");
+		let mut last_progress = (0, 0);
+		let files = write_archive(
+			BackupFormat::V2,
+			sealed.to_str().unwrap(),
+			&manifest,
+			container.to_str().unwrap(),
+			|files, bytes| last_progress = (files, bytes),
+		)
+		.unwrap();
+		assert_eq!(files, 3);
+		assert_eq!(last_progress, (3, 4096 + 5));
+		assert!(is_container(&container).unwrap());
+		// Same content is stored once, and compressed
+		assert!(fs::metadata(&container).unwrap().len() < 1024);
+
+		extract_container(&container, &staging, |_| true).unwrap();
+		assert_eq!(check_archive(staging.to_str().unwrap(), None).unwrap(), manifest);
+		assert_eq!(fs::read_to_string(staging.join("capsule_10_100.keyshare")).unwrap(), keyshare);
+		fs::remove_dir_all(&staging).unwrap();
+
+		extract_container(&container, &staging, |name| name == "nft_20_200.keyshare").unwrap();
+		assert!(!staging.join("nft_10_100.keyshare").exists());
+		assert_eq!(fs::read(staging.join("nft_20_200.keyshare")).unwrap(), b"other");
+
+		// Other versions and zip archives are not containers
+		let mut data = fs::read(&container).unwrap();
+		data[4] = 3;
+		fs::write(dir.join("v3.tbk"), &data).unwrap();
+		assert!(extract_container(&dir.join("v3.tbk"), &staging, |_| true).is_err());
+		assert!(!is_container(&sealed.join("nft_20_200.keyshare")).unwrap());
+
+		fs::remove_dir_all(&dir).unwrap();
+	}
+}
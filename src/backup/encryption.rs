@@ -0,0 +1,326 @@
+use std::{
+	fs::{self, File},
+	io::{self, BufReader, BufWriter, Read, Write},
+	path::Path,
+	str::FromStr,
+	sync::OnceLock,
+};
+
+use age::{secrecy::ExposeSecret, x25519};
+use anyhow::anyhow;
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use subxt::ext::sp_core::{crypto::Ss58Codec, sr25519, Pair};
+use tracing::{error, info};
+
+use crate::{
+	attestation::{
+		ra::{create_quote, ReportDataPreimage},
+		verifier::verify_peer_quote,
+	},
+	chain::constants::{BACKUP_IDENTITY_FILE, RECIPIENT_QUOTE_VALIDITY},
+	servers::state::{get_accountid, get_keypair, SharedState},
+};
+
+use super::{recovery::recovery_identity, sync::verify_signature};
+
+/* ---------------------------------------
+	ENCRYPTED BACKUP ARCHIVES
+--------------------------------------- */
+
+// Header of the age v1 format, archives starting with it are decrypted before extraction
+const AGE_HEADER: &[u8] = b"age-encryption.org/v1";
+
+// The restore identity is sealed to the binary in /keys, it never leaves the enclave : an archive
+// encrypted to the published recipient can only be restored by the same binary, across restarts.
+// The source enclave only encrypts to a recipient whose quote it has verified itself, so the host
+// of the admin can not substitute its own recipient.
+static BACKUP_IDENTITY: OnceLock<x25519::Identity> = OnceLock::new();
+
+fn backup_identity() -> &'static x25519::Identity {
+	BACKUP_IDENTITY.get_or_init(|| match load_backup_identity() {
+		Ok(Some(identity)) => identity,
+		Ok(None) => {
+			info!("BACKUP ENCRYPTION : generate the restore identity");
+			let identity = x25519::Identity::generate();
+			if let Err(err) = seal_backup_identity(&identity) {
+				error!("BACKUP ENCRYPTION : restore identity is not sealed, it is lost at restart : {err:?}");
+			}
+			identity
+		},
+		Err(err) => {
+			error!("BACKUP ENCRYPTION : sealed restore identity is not readable : {err:?}");
+			x25519::Identity::generate()
+		},
+	})
+}
+
+/// Restore identity sealed by a previous start, None if there is none
+fn load_backup_identity() -> Result<Option<x25519::Identity>, anyhow::Error> {
+	if !Path::new(BACKUP_IDENTITY_FILE).exists() {
+		return Ok(None)
+	}
+
+	x25519::Identity::from_str(fs::read_to_string(BACKUP_IDENTITY_FILE)?.trim())
+		.map(Some)
+		.map_err(|err| anyhow!("BACKUP ENCRYPTION : invalid sealed identity : {err}"))
+}
+
+/// Seal the restore identity, an interrupted write leaves no partial file
+fn seal_backup_identity(identity: &x25519::Identity) -> Result<(), anyhow::Error> {
+	let staging = format!("{BACKUP_IDENTITY_FILE}.staging");
+	let mut file = File::create(&staging)?;
+	file.write_all(identity.to_string().expose_secret().as_bytes())?;
+	file.sync_all()?;
+	fs::rename(&staging, BACKUP_IDENTITY_FILE)?;
+	Ok(())
+}
+
+/// Age recipient of the archives restored by this enclave
+pub fn backup_recipient() -> x25519::Recipient {
+	backup_identity().to_public()
+}
+
+/// Parse an "age1..." X25519 recipient
+pub fn parse_recipient(recipient: &str) -> Result<x25519::Recipient, anyhow::Error> {
+	x25519::Recipient::from_str(recipient.trim())
+		.map_err(|err| anyhow!("BACKUP ENCRYPTION : invalid age recipient {recipient} : {err}"))
+}
+
+/// Recipient of a target enclave, the body of its GET /api/backup/recipient
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RecipientAttestation {
+	pub enclave_account: String,
+	pub recipient: String,
+	// Signature of "backup-recipient_RECIPIENT" by the enclave account
+	pub recipient_certificate: String,
+	// Hex encoded quote, its report data binds the enclave account
+	pub quote: String,
+	pub report_data_preimage: ReportDataPreimage,
+}
+
+/// Verify that a recipient belongs to an attested enclave
+/// # Arguments
+/// * `client` - http client of the enclave
+/// * `attestation` - recipient, certificate and quote of the target enclave
+/// * `block_number` - current block
+/// # Returns
+/// * `x25519::Recipient` - recipient of the archive
+/// # Errors
+/// * The quote is stale, rejected or not bound to the account which certifies the recipient
+pub async fn verify_recipient_attestation(
+	client: &reqwest::Client,
+	attestation: &RecipientAttestation,
+	block_number: u32,
+) -> Result<x25519::Recipient, anyhow::Error> {
+	let recipient = parse_recipient(&attestation.recipient)?;
+
+	let quote_block = attestation.report_data_preimage.block_number;
+	if quote_block > block_number || block_number - quote_block > RECIPIENT_QUOTE_VALIDITY {
+		return Err(anyhow!("BACKUP ENCRYPTION : recipient quote of block {quote_block} is stale"))
+	}
+
+	let public_key = sr25519::Public::from_ss58check(&attestation.enclave_account)
+		.map_err(|err| anyhow!("BACKUP ENCRYPTION : invalid enclave account : {err:?}"))?;
+	if attestation.report_data_preimage.public_key != format!("0x{}", hex::encode(public_key.0)) {
+		return Err(anyhow!("BACKUP ENCRYPTION : recipient quote is not of the enclave account"))
+	}
+
+	let quote = hex::decode(attestation.quote.trim_start_matches("0x"))?;
+	if !attestation.report_data_preimage.is_bound(&quote) {
+		return Err(anyhow!("BACKUP ENCRYPTION : recipient quote does not carry its report data"))
+	}
+
+	if !verify_signature(
+		&attestation.enclave_account,
+		attestation.recipient_certificate.clone(),
+		format!("backup-recipient_{}", attestation.recipient).as_bytes(),
+	) {
+		return Err(anyhow!("BACKUP ENCRYPTION : recipient is not certified by the enclave account"))
+	}
+
+	let verdict = verify_peer_quote(client, &attestation.quote).await?;
+	info!(
+		"BACKUP ENCRYPTION : recipient of {} is attested, MRENCLAVE {}",
+		attestation.enclave_account, verdict.mrenclave
+	);
+
+	Ok(recipient)
+}
+
+/// Encrypt an archive to a recipient, the file is streamed by chunks
+/// # Arguments
+/// * `src` - plaintext archive
+/// * `dst` - encrypted archive
+/// * `recipient` - X25519 age recipient
+/// # Returns
+/// * `u64` - size of the plaintext archive
+pub fn encrypt_archive(
+	src: &Path,
+	dst: &Path,
+	recipient: x25519::Recipient,
+) -> Result<u64, anyhow::Error> {
+	let encryptor = age::Encryptor::with_recipients(vec![Box::new(recipient)])
+		.ok_or_else(|| anyhow!("BACKUP ENCRYPTION : no recipient"))?;
+
+	let mut reader = BufReader::new(File::open(src)?);
+	let mut writer = encryptor.wrap_output(BufWriter::new(File::create(dst)?))?;
+
+	let size = io::copy(&mut reader, &mut writer)?;
+	writer.finish()?.flush()?;
+
+	Ok(size)
+}
+
+/// Check the age header of an archive
+pub fn is_encrypted_archive(path: &Path) -> Result<bool, anyhow::Error> {
+	let mut header = Vec::with_capacity(AGE_HEADER.len());
+	File::open(path)?.take(AGE_HEADER.len() as u64).read_to_end(&mut header)?;
+	Ok(header == AGE_HEADER)
+}
+
+/// Decrypt an archive encrypted to the recipient of this enclave, or to the disaster-recovery
+/// recipient of its MRSIGNER, the file is streamed by chunks
+/// # Arguments
+/// * `src` - encrypted archive
+/// * `dst` - plaintext archive
+/// # Errors
+/// * Passphrase archives, or archives encrypted to another recipient
+pub fn decrypt_archive(src: &Path, dst: &Path) -> Result<u64, anyhow::Error> {
+	let mut identities = vec![backup_identity()];
+	identities.extend(recovery_identity());
+	decrypt_with_any(&identities, src, dst)
+}
+
+/// Decrypt an archive with another identity, i.e. the escrowed identity of the bulk backups
+pub fn decrypt_with(
+	identity: &x25519::Identity,
+	src: &Path,
+	dst: &Path,
+) -> Result<u64, anyhow::Error> {
+	decrypt_with_any(&[identity], src, dst)
+}
+
+fn decrypt_with_any(
+	identities: &[&x25519::Identity],
+	src: &Path,
+	dst: &Path,
+) -> Result<u64, anyhow::Error> {
+	let decryptor = match age::Decryptor::new(BufReader::new(File::open(src)?))? {
+		age::Decryptor::Recipients(decryptor) => decryptor,
+		_ => return Err(anyhow!("BACKUP ENCRYPTION : passphrase archives are not supported")),
+	};
+
+	let mut reader =
+		decryptor.decrypt(identities.iter().map(|identity| *identity as &dyn age::Identity))?;
+	let mut writer = BufWriter::new(File::create(dst)?);
+
+	let size = io::copy(&mut reader, &mut writer)?;
+	writer.flush()?;
+
+	Ok(size)
+}
+
+/// Recipient of the encrypted backups, bound to the enclave by its account and quote
+pub async fn backup_recipient_key(State(state): State<SharedState>) -> impl IntoResponse {
+	let recipient = backup_recipient().to_string();
+	let enclave_account = get_accountid(&state).await;
+	let certificate = get_keypair(&state)
+		.await
+		.sign(format!("backup-recipient_{recipient}").as_bytes());
+	let (preimage, quote) = create_quote(&state).await;
+
+	let (quote, quote_error) = match quote {
+		Ok(quote) => (Some(hex::encode(quote)), None),
+		Err(err) => {
+			error!("BACKUP RECIPIENT : unable to create the quote : {err}");
+			(None, Some(err.to_string()))
+		},
+	};
+
+	(
+		StatusCode::OK,
+		Json(json!({
+			"enclave_account": enclave_account,
+			"recipient": recipient,
+			// Signature of "backup-recipient_RECIPIENT" by the enclave account
+			"recipient_certificate": format!("{}{:?}", "0x", certificate),
+			// Report data is SHA256(PUBLICKEY || API_URL || BLOCKNUMBER) of this preimage
+			"quote_block_number": preimage.block_number,
+			"quote": quote,
+			"report_data_preimage": preimage,
+			"quote_error": quote_error,
+		})),
+	)
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn archive_encryption_test() {
+		let dir = std::env::temp_dir().join(format!("backup-encryption-{}", std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+
+		let plain = dir.join("backup.zip");
+		let encrypted = dir.join("backup.zip.age");
+		let restored = dir.join("restored.zip");
+
+		let data: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+		std::fs::write(&plain, &data).unwrap();
+
+		let recipient = parse_recipient(&backup_recipient().to_string()).unwrap();
+		assert_eq!(encrypt_archive(&plain, &encrypted, recipient).unwrap(), data.len() as u64);
+		assert!(is_encrypted_archive(&encrypted).unwrap());
+		assert!(!is_encrypted_archive(&plain).unwrap());
+
+		assert_eq!(decrypt_archive(&encrypted, &restored).unwrap(), data.len() as u64);
+		assert_eq!(std::fs::read(&restored).unwrap(), data);
+
+		// Another enclave can not restore the archive
+		assert!(decrypt_with(&x25519::Identity::generate(), &encrypted, &restored).is_err());
+		assert!(parse_recipient("age1invalid").is_err());
+
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[tokio::test]
+	async fn recipient_attestation_test() {
+		// The recipient of another account, or of a stale quote, is not attested
+		let client = reqwest::Client::new();
+		let account = sr25519::Pair::from_seed(&[7u8; 32]);
+		let other = sr25519::Pair::from_seed(&[8u8; 32]);
+		let recipient = backup_recipient().to_string();
+		let mut attestation = RecipientAttestation {
+			enclave_account: account.public().to_ss58check(),
+			recipient: recipient.clone(),
+			recipient_certificate: format!(
+				"0x{}",
+				hex::encode(account.sign(format!("backup-recipient_{recipient}").as_bytes()).0)
+			),
+			quote: "00".repeat(432),
+			report_data_preimage: ReportDataPreimage::new(&other.public(), "", 100),
+		};
+
+		let error = |result: Result<x25519::Recipient, anyhow::Error>| {
+			result.err().map(|err| err.to_string()).unwrap_or_default()
+		};
+		assert!(error(
+			verify_recipient_attestation(&client, &attestation, 100 + RECIPIENT_QUOTE_VALIDITY + 1)
+				.await
+		)
+		.contains("stale"));
+		assert!(error(verify_recipient_attestation(&client, &attestation, 100).await)
+			.contains("not of the enclave account"));
+
+		attestation.report_data_preimage = ReportDataPreimage::new(&account.public(), "", 100);
+		assert!(error(verify_recipient_attestation(&client, &attestation, 100).await)
+			.contains("does not carry its report data"));
+	}
+}
@@ -0,0 +1,464 @@
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	io::Write,
+	path::Path,
+	str::FromStr,
+	sync::Mutex,
+};
+
+use age::x25519;
+use anyhow::anyhow;
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use rand::RngCore;
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{debug, error, info, warn};
+use zeroize::Zeroizing;
+
+use crate::{
+	chain::constants::{ESCROW_FILE, MAX_ESCROW_SHARES},
+	servers::{
+		auth::VerifiedCaller,
+		state::{get_blocknumber, SharedState},
+	},
+};
+
+use super::{admins::admin_accounts, encryption::parse_recipient};
+
+/* *************************************
+	BACKUP KEY ESCROW
+**************************************** */
+
+// The bulk backups are encrypted to an escrow recipient whose identity is never stored : it is
+// split with Shamir's secret sharing over GF(256), each share is encrypted to the age recipient of
+// an admin, then the identity is dropped. A restore on any enclave decrypts the archive with the
+// identity recovered from a threshold of shares.
+// A decrypted share is THRESHOLD (1) | INDEX (1) | one byte per byte of the identity.
+
+static BACKUP_ESCROW: Mutex<Option<BackupEscrow>> = Mutex::new(None);
+
+/// Share of the escrowed identity, encrypted to an admin
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct EscrowShare {
+	pub index: u8,
+	// Base64 of the age file of the share
+	pub encrypted_share: String,
+}
+
+/// Escrow of the bulk backup key
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BackupEscrow {
+	// Age recipient of the bulk backups
+	pub recipient: String,
+	pub threshold: u8,
+	pub block_number: u32,
+	// admin_account -> encrypted share
+	pub shares: BTreeMap<String, EscrowShare>,
+}
+
+/// Escrow request, signed by the admin quorum
+#[derive(Serialize, Deserialize, Debug)]
+pub struct EscrowPacket {
+	threshold: u8,
+	// admin_account -> age recipient of its share
+	recipients: BTreeMap<String, String>,
+}
+
+/// Canonical hash of the escrow parameters, signed inside the authentication token
+pub fn escrow_data_hash(threshold: u8, recipients: &BTreeMap<String, String>) -> String {
+	let recipients: Vec<String> = recipients
+		.iter()
+		.map(|(account, recipient)| format!("{account}={recipient}"))
+		.collect();
+	sha256::digest(format!("backup-escrow_{threshold}_{}", recipients.join(",")).as_bytes())
+}
+
+/* ----------------------------------
+	SHAMIR SECRET SHARING
+----------------------------------*/
+
+// The operands are secret bytes : the field operations have no branch and no table lookup which
+// depends on them, their timing and memory accesses are the same for every value.
+
+// Multiplication in GF(256), reduced by the AES polynomial
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+	let mut product = 0u8;
+	for _ in 0..8 {
+		// 0xff if the low bit of b is set, 0 otherwise
+		product ^= a & (b & 1).wrapping_neg();
+		let carry = (a >> 7).wrapping_neg();
+		a = (a << 1) ^ (0x1b & carry);
+		b >>= 1;
+	}
+	product
+}
+
+// Inverse in GF(256), a^254 = a^2 * a^4 * ... * a^128, the exponent is public
+fn gf_inv(a: u8) -> u8 {
+	let mut inverse = 1u8;
+	let mut power = a;
+	for _ in 1..8 {
+		power = gf_mul(power, power);
+		inverse = gf_mul(inverse, power);
+	}
+	inverse
+}
+
+/// Split a secret into shares, any threshold of them recovers it
+/// # Arguments
+/// * `secret` - secret bytes
+/// * `threshold` - shares needed to recover the secret
+/// * `count` - number of shares, at most 255
+pub fn split_secret(
+	secret: &[u8],
+	threshold: u8,
+	count: usize,
+) -> Result<Vec<Zeroizing<Vec<u8>>>, anyhow::Error> {
+	if threshold == 0 || usize::from(threshold) > count || count > MAX_ESCROW_SHARES {
+		return Err(anyhow!(
+			"BACKUP ESCROW : threshold {threshold} of {count} shares, at most {MAX_ESCROW_SHARES}"
+		))
+	}
+
+	let mut shares: Vec<Zeroizing<Vec<u8>>> = (1..=count)
+		.map(|index| {
+			let mut share = Zeroizing::new(Vec::with_capacity(secret.len() + 2));
+			share.extend_from_slice(&[threshold, index as u8]);
+			share
+		})
+		.collect();
+
+	// One random polynomial per byte, the byte is its constant term
+	let mut coefficients = Zeroizing::new(vec![0u8; usize::from(threshold)]);
+	for byte in secret {
+		coefficients[0] = *byte;
+		rand::thread_rng().fill_bytes(&mut coefficients[1..]);
+
+		for share in shares.iter_mut() {
+			let x = share[1];
+			let y =
+				coefficients.iter().rev().fold(0u8, |y, coefficient| gf_mul(y, x) ^ coefficient);
+			share.push(y);
+		}
+	}
+
+	Ok(shares)
+}
+
+/// Recover a secret from a threshold of shares
+/// # Errors
+/// * Malformed shares, shares of another split, or less shares than the threshold
+pub fn combine_shares(shares: &[Zeroizing<Vec<u8>>]) -> Result<Zeroizing<Vec<u8>>, anyhow::Error> {
+	let first = shares.first().ok_or_else(|| anyhow!("BACKUP ESCROW : no share"))?;
+	if first.len() < 3 {
+		return Err(anyhow!("BACKUP ESCROW : malformed share"))
+	}
+	let threshold = first[0];
+
+	let mut indexes = BTreeSet::new();
+	for share in shares {
+		if share.len() != first.len() || share[0] != threshold || share[1] == 0 {
+			return Err(anyhow!("BACKUP ESCROW : shares are not of the same escrow"))
+		}
+		if !indexes.insert(share[1]) {
+			return Err(anyhow!("BACKUP ESCROW : share {} is duplicated", share[1]))
+		}
+	}
+
+	if shares.len() < usize::from(threshold) {
+		return Err(anyhow!("BACKUP ESCROW : {} shares, {threshold} are required", shares.len()))
+	}
+
+	// Lagrange interpolation at zero, over the first threshold shares
+	let shares = &shares[..usize::from(threshold)];
+	let mut secret = Zeroizing::new(vec![0u8; first.len() - 2]);
+	for (i, share) in shares.iter().enumerate() {
+		let mut basis = 1u8;
+		for (j, other) in shares.iter().enumerate() {
+			if i != j {
+				basis = gf_mul(basis, gf_mul(other[1], gf_inv(other[1] ^ share[1])));
+			}
+		}
+
+		for (byte, y) in secret.iter_mut().zip(&share[2..]) {
+			*byte ^= gf_mul(*y, basis);
+		}
+	}
+
+	Ok(secret)
+}
+
+/* ----------------------------------
+	ESCROWED IDENTITY
+----------------------------------*/
+
+fn encrypt_share(share: &[u8], recipient: x25519::Recipient) -> Result<String, anyhow::Error> {
+	let encryptor = age::Encryptor::with_recipients(vec![Box::new(recipient)])
+		.ok_or_else(|| anyhow!("BACKUP ESCROW : no recipient"))?;
+
+	let mut encrypted = Vec::new();
+	let mut writer = encryptor.wrap_output(&mut encrypted)?;
+	writer.write_all(share)?;
+	writer.finish()?;
+
+	Ok(STANDARD.encode(encrypted))
+}
+
+/// Generate an escrowed identity and encrypt its shares to the admins
+/// # Arguments
+/// * `threshold` - shares needed to restore a bulk backup
+/// * `recipients` - admin_account -> age recipient of its share
+/// * `block_number` - block of the escrow
+pub fn create_escrow(
+	threshold: u8,
+	recipients: &BTreeMap<String, String>,
+	block_number: u32,
+) -> Result<BackupEscrow, anyhow::Error> {
+	let recipients = recipients
+		.iter()
+		.map(|(account, recipient)| Ok((account, parse_recipient(recipient)?)))
+		.collect::<Result<Vec<_>, anyhow::Error>>()?;
+
+	let identity = x25519::Identity::generate();
+	let secret = identity.to_string();
+	let shares = split_secret(secret.expose_secret().as_bytes(), threshold, recipients.len())?;
+
+	let mut escrow_shares = BTreeMap::new();
+	for ((account, recipient), share) in recipients.into_iter().zip(shares) {
+		escrow_shares.insert(
+			account.clone(),
+			EscrowShare { index: share[1], encrypted_share: encrypt_share(&share, recipient)? },
+		);
+	}
+
+	Ok(BackupEscrow {
+		recipient: identity.to_public().to_string(),
+		threshold,
+		block_number,
+		shares: escrow_shares,
+	})
+}
+
+/// Recover the escrowed identity from the decrypted shares of the admins
+/// # Arguments
+/// * `shares` - hex encoded decrypted shares
+pub fn recover_identity(shares: &[String]) -> Result<x25519::Identity, anyhow::Error> {
+	let shares = shares
+		.iter()
+		.map(|share| {
+			hex::decode(share.trim().trim_start_matches("0x"))
+				.map(Zeroizing::new)
+				.map_err(|err| anyhow!("BACKUP ESCROW : share is not hex encoded : {err}"))
+		})
+		.collect::<Result<Vec<_>, anyhow::Error>>()?;
+
+	let secret = combine_shares(&shares)?;
+	let secret = std::str::from_utf8(&secret)
+		.map_err(|_| anyhow!("BACKUP ESCROW : shares do not recover an identity"))?;
+
+	x25519::Identity::from_str(secret)
+		.map_err(|_| anyhow!("BACKUP ESCROW : shares do not recover an identity"))
+}
+
+/// Current escrow, None if the bulk backups are not escrowed
+pub fn backup_escrow() -> Option<BackupEscrow> {
+	BACKUP_ESCROW.lock().ok().and_then(|escrow| escrow.clone())
+}
+
+/// Recipient of the bulk backups, None if they are not escrowed
+pub fn escrow_recipient() -> Option<x25519::Recipient> {
+	backup_escrow().and_then(|escrow| parse_recipient(&escrow.recipient).ok())
+}
+
+/* ----------------------------------
+	PERSISTENCE
+----------------------------------*/
+
+fn set_escrow(escrow: BackupEscrow) -> Result<(), anyhow::Error> {
+	std::fs::write(ESCROW_FILE, serde_json::to_string(&escrow)?)?;
+
+	let mut current = BACKUP_ESCROW
+		.lock()
+		.map_err(|err| anyhow!("BACKUP ESCROW : lock error : {err:?}"))?;
+	*current = Some(escrow);
+
+	Ok(())
+}
+
+/// Load the escrow from sealed directory
+pub fn load_escrow() -> Result<(), anyhow::Error> {
+	if !Path::new(ESCROW_FILE).exists() {
+		debug!("BACKUP ESCROW : no escrow file, bulk backups are not encrypted");
+		return Ok(())
+	}
+
+	let escrow: BackupEscrow = serde_json::from_str(&std::fs::read_to_string(ESCROW_FILE)?)?;
+	info!(
+		"BACKUP ESCROW : bulk backups are escrowed to {} admins, threshold {}",
+		escrow.shares.len(),
+		escrow.threshold
+	);
+
+	let mut current = BACKUP_ESCROW
+		.lock()
+		.map_err(|err| anyhow!("BACKUP ESCROW : lock error : {err:?}"))?;
+	*current = Some(escrow);
+
+	Ok(())
+}
+
+/* ----------------------------------
+	ESCROW ENDPOINTS
+----------------------------------*/
+
+/// Escrow of the bulk backup key, with the encrypted shares of the admins
+pub async fn admin_escrow_status() -> impl IntoResponse {
+	(StatusCode::OK, Json(json!({ "escrow": backup_escrow() })))
+}
+
+/// Escrow a new bulk backup key among the admins, the previous escrow is replaced
+/// # Arguments
+/// * `state` - SharedState
+/// * `request` - EscrowPacket
+#[axum::debug_handler]
+pub async fn admin_escrow_setup(
+	State(state): State<SharedState>,
+	caller: VerifiedCaller,
+	Json(request): Json<EscrowPacket>,
+) -> impl IntoResponse {
+	debug!("ADMIN ESCROW : start");
+
+	let data_hash = escrow_data_hash(request.threshold, &request.recipients);
+	if let Err((status, message)) = caller.verify_data_hash(&data_hash) {
+		let message = format!("ADMIN ESCROW : {message}");
+		warn!(message);
+		return (status, Json(json!({ "error": message })))
+	}
+
+	let admins = admin_accounts(&state).await;
+	let strangers: Vec<&String> =
+		request.recipients.keys().filter(|account| !admins.contains(account)).collect();
+	if !strangers.is_empty() {
+		let message =
+			format!("ADMIN ESCROW : shares of accounts which are not admins : {strangers:?}");
+		warn!(message);
+		return (StatusCode::BAD_REQUEST, Json(json!({ "error": message })))
+	}
+
+	let block_number = get_blocknumber(&state).await;
+	let escrow = match create_escrow(request.threshold, &request.recipients, block_number) {
+		Ok(escrow) => escrow,
+		Err(err) => {
+			let message = format!("ADMIN ESCROW : {err}");
+			warn!(message);
+			return (StatusCode::BAD_REQUEST, Json(json!({ "error": message })))
+		},
+	};
+
+	if let Err(err) = set_escrow(escrow.clone()) {
+		let message = format!("ADMIN ESCROW : error saving the escrow : {err:?}");
+		error!(message);
+		return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })))
+	}
+
+	info!(
+		"ADMIN ESCROW : bulk backup key escrowed to {} admins, threshold {}",
+		escrow.shares.len(),
+		escrow.threshold
+	);
+
+	(StatusCode::OK, Json(json!({ "escrow": escrow })))
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use std::iter;
+
+	#[test]
+	fn gf_test() {
+		// Reference multiplication, branching on its operands
+		let reference = |mut a: u8, mut b: u8| {
+			let mut product = 0u8;
+			while b != 0 {
+				if b & 1 != 0 {
+					product ^= a;
+				}
+				a = if a & 0x80 != 0 { (a << 1) ^ 0x1b } else { a << 1 };
+				b >>= 1;
+			}
+			product
+		};
+
+		for a in 0..=255u8 {
+			for b in 0..=255u8 {
+				assert_eq!(gf_mul(a, b), reference(a, b));
+			}
+			if a != 0 {
+				assert_eq!(gf_mul(a, gf_inv(a)), 1);
+			}
+		}
+		assert_eq!(gf_inv(0), 0);
+	}
+
+	#[test]
+	fn shamir_test() {
+		let secret = b"AGE-SECRET-KEY-1 escrowed identity";
+		let shares = split_secret(secret, 3, 5).unwrap();
+		assert_eq!(shares.len(), 5);
+
+		assert_eq!(*combine_shares(&shares[..3]).unwrap(), secret.to_vec());
+		assert_eq!(*combine_shares(&shares[2..]).unwrap(), secret.to_vec());
+		let subset = vec![shares[4].clone(), shares[0].clone(), shares[2].clone()];
+		assert_eq!(*combine_shares(&subset).unwrap(), secret.to_vec());
+
+		assert!(combine_shares(&shares[..2]).is_err());
+		assert!(combine_shares(&[shares[0].clone(), shares[0].clone(), shares[1].clone()]).is_err());
+		assert!(split_secret(secret, 0, 5).is_err());
+		assert!(split_secret(secret, 6, 5).is_err());
+	}
+
+	#[test]
+	fn escrow_test() {
+		let admins: Vec<x25519::Identity> = (0..3).map(|_| x25519::Identity::generate()).collect();
+		let recipients: BTreeMap<String, String> = admins
+			.iter()
+			.enumerate()
+			.map(|(index, identity)| (format!("admin{index}"), identity.to_public().to_string()))
+			.collect();
+
+		let escrow = create_escrow(2, &recipients, 100).unwrap();
+		assert_eq!(escrow.shares.len(), 3);
+
+		// Each admin decrypts its own share
+		let decrypted: Vec<String> = admins
+			.iter()
+			.enumerate()
+			.map(|(index, identity)| {
+				let share = &escrow.shares[&format!("admin{index}")];
+				let encrypted = STANDARD.decode(&share.encrypted_share).unwrap();
+				let decryptor = match age::Decryptor::new(&encrypted[..]).unwrap() {
+					age::Decryptor::Recipients(decryptor) => decryptor,
+					_ => panic!("recipient encryption is expected"),
+				};
+				let mut reader =
+					decryptor.decrypt(iter::once(identity as &dyn age::Identity)).unwrap();
+				let mut share = Vec::new();
+				std::io::Read::read_to_end(&mut reader, &mut share).unwrap();
+				hex::encode(share)
+			})
+			.collect();
+
+		let identity = recover_identity(&decrypted[1..]).unwrap();
+		assert_eq!(identity.to_public().to_string(), escrow.recipient);
+		assert!(recover_identity(&decrypted[..1]).is_err());
+
+		assert_ne!(escrow_data_hash(2, &recipients), escrow_data_hash(3, &recipients));
+	}
+}
@@ -0,0 +1,405 @@
+use std::{collections::BTreeSet, fs, str::FromStr};
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use subxt::utils::AccountId32;
+use tracing::{debug, error, info, warn};
+
+use crate::{
+	chain::{
+		compression,
+		constants::{MAX_INJECTED_KEYSHARES, SEALPATH},
+		helper::{Availability, NftType},
+		policy::keyshare_policy,
+		reader::ChainReader,
+		verify::{
+			batch_errors, verify_writable, BatchItemResult, NftKind, Retryability, ReturnStatus,
+			VerificationError, VerificationStep,
+		},
+	},
+	servers::{
+		auth::VerifiedCaller,
+		events::{publish_keyshare_event, KeyshareEventKind},
+		state::{
+			get_accountid, get_clusters, get_compression_threshold, get_nft_availability,
+			set_nft_availability, SharedState,
+		},
+	},
+};
+
+use super::sync::verify_signature;
+
+/* *************************************
+	TARGETED KEYSHARE INJECTION
+**************************************** */
+
+// Restores individual keyshares exported by another enclave of the clusters, instead of a whole
+// archive. Each keyshare is signed by its origin enclave and is only written if the nft exists
+// onchain with the expected kind, and no newer keyshare of the nft is sealed here.
+
+/// Keyshare exported by an enclave
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct KeyshareInjection {
+	pub nft_id: u32,
+	pub kind: NftKind,
+	// Block of the keyshare file name on the origin enclave
+	pub block_number: u32,
+	// Keyshare as sealed at rest, binary keyshares are base64url encoded
+	pub keyshare: String,
+	pub origin_enclave: String,
+	// Signature of origin_message() by the origin enclave
+	pub origin_signature: String,
+}
+
+/// Keyshare injection request, signed by the admin quorum
+#[derive(Serialize, Deserialize, Debug)]
+pub struct InjectionPacket {
+	// Json array of KeyshareInjection, its sha256 is the data hash of the token
+	keyshares: String,
+}
+
+impl KeyshareInjection {
+	/// Prefix of the sealed file name
+	fn file_prefix(&self) -> &'static str {
+		match self.kind {
+			NftKind::SECRET => "nft",
+			NftKind::CAPSULE => "capsule",
+		}
+	}
+
+	fn nft_type(&self) -> NftType {
+		match self.kind {
+			NftKind::SECRET => NftType::Secret,
+			NftKind::CAPSULE => NftType::Capsule,
+		}
+	}
+
+	/// "keyshare-export_PREFIX_NFTID_BLOCKNUMBER_SHA256(KEYSHARE)", signed by the origin enclave
+	pub fn origin_message(&self) -> String {
+		format!(
+			"keyshare-export_{}_{}_{}_{}",
+			self.file_prefix(),
+			self.nft_id,
+			self.block_number,
+			sha256::digest(self.keyshare.as_bytes())
+		)
+	}
+
+	fn failure(
+		&self,
+		status: ReturnStatus,
+		step: VerificationStep,
+		retryable: Retryability,
+		description: String,
+	) -> BatchItemResult {
+		BatchItemResult::failure(self.nft_id, status, step, retryable, description)
+	}
+}
+
+/// Parse the keyshares of a request, an nft appears once per kind
+/// # Errors
+/// * Malformed json, empty or too many keyshares, duplicated nft
+pub fn parse_injections(keyshares: &str) -> Result<Vec<KeyshareInjection>, String> {
+	let injections: Vec<KeyshareInjection> =
+		serde_json::from_str(keyshares).map_err(|err| format!("invalid keyshares : {err}"))?;
+
+	if injections.is_empty() || injections.len() > MAX_INJECTED_KEYSHARES {
+		return Err(format!("1 to {MAX_INJECTED_KEYSHARES} keyshares are expected"))
+	}
+
+	let mut seen = BTreeSet::new();
+	for injection in &injections {
+		if !seen.insert((injection.nft_id, injection.file_prefix())) {
+			return Err(format!("{} {} is duplicated", injection.kind, injection.nft_id))
+		}
+	}
+
+	Ok(injections)
+}
+
+/// Keyshare policy of the store requests, binary keyshares are checked once decoded
+fn check_keyshare_at_rest(keyshare: &str) -> Result<(), VerificationError> {
+	let policy = keyshare_policy();
+	match policy.validate(keyshare.as_bytes()) {
+		Ok(()) => Ok(()),
+		Err(err) => match URL_SAFE_NO_PAD.decode(keyshare) {
+			Ok(binary) => policy.validate_binary(&binary),
+			Err(_) => Err(err),
+		},
+	}
+}
+
+/// Verify a keyshare and its origin, before any onchain query
+/// # Arguments
+/// * `injection` - exported keyshare
+/// * `enclaves` - this enclave and the registered enclaves of the clusters
+pub fn check_injection(
+	injection: &KeyshareInjection,
+	enclaves: &BTreeSet<AccountId32>,
+) -> Result<(), BatchItemResult> {
+	if let Err(err) = check_keyshare_at_rest(&injection.keyshare) {
+		return Err(err.express_batch_item(injection.nft_id, None))
+	}
+
+	let registered = AccountId32::from_str(&injection.origin_enclave)
+		.map(|account| enclaves.contains(&account))
+		.unwrap_or(false);
+	if !registered {
+		return Err(injection.failure(
+			ReturnStatus::INVALIDSIGNERADDRESS,
+			VerificationStep::SIGNATURE,
+			Retryability::PERMANENT,
+			format!("origin enclave {} is not registered", injection.origin_enclave),
+		))
+	}
+
+	if !verify_signature(
+		&injection.origin_enclave,
+		injection.origin_signature.clone(),
+		injection.origin_message().as_bytes(),
+	) {
+		return Err(injection.failure(
+			ReturnStatus::INVALIDDATASIGNATURE,
+			VerificationStep::SIGNATURE,
+			Retryability::PERMANENT,
+			"invalid signature of the origin enclave".to_string(),
+		))
+	}
+
+	Ok(())
+}
+
+/// Block of a newer sealed keyshare of the nft, which is kept instead of the injected one
+fn newer_sealed(injection: &KeyshareInjection, sealed: Option<Availability>) -> Option<u32> {
+	match sealed {
+		Some(av)
+			if av.nft_type == injection.nft_type() && av.block_number > injection.block_number =>
+			Some(av.block_number),
+		_ => None,
+	}
+}
+
+/// Verify the onchain state of the nft, then write the keyshare into the sealed directory
+async fn inject_keyshare(
+	state: &SharedState,
+	injection: &KeyshareInjection,
+) -> Result<(), BatchItemResult> {
+	let nft_status = state
+		.nft_data(injection.nft_id)
+		.await
+		.map_err(|err| err.express_batch_item(injection.nft_id, None))?;
+
+	if !injection.kind.is_kind(&nft_status) {
+		return Err(injection.kind.kind_error().express_batch_item(injection.nft_id, None))
+	}
+
+	let sealed = get_nft_availability(state, injection.nft_id).await;
+	if let Some(block_number) = newer_sealed(injection, sealed) {
+		return Err(injection.failure(
+			ReturnStatus::NFTIDEXISTS,
+			VerificationStep::STORAGE,
+			Retryability::PERMANENT,
+			format!("a newer keyshare of block {block_number} is sealed"),
+		))
+	}
+
+	let prefix = injection.file_prefix();
+	let file_path =
+		format!("{SEALPATH}/{prefix}_{}_{}.keyshare", injection.nft_id, injection.block_number);
+	let temp_path = format!("{file_path}.{}.inject", rand::random::<u64>());
+
+	let compression_threshold = get_compression_threshold(state).await;
+	let sealed_keyshare =
+		compression::seal_keyshare(injection.keyshare.as_bytes(), compression_threshold);
+
+	let written =
+		fs::write(&temp_path, &sealed_keyshare).and_then(|_| fs::rename(&temp_path, &file_path));
+
+	if let Err(err) = written {
+		let _ = fs::remove_file(&temp_path);
+		let message = format!("ADMIN INJECT : error writing {file_path} : {err:?}");
+		error!(message);
+		return Err(injection.failure(
+			ReturnStatus::DATABASEFAILURE,
+			VerificationStep::STORAGE,
+			Retryability::RETRYABLE,
+			message,
+		))
+	}
+
+	// The other kind of the nft keeps its keyshare
+	let nft_type = match sealed {
+		Some(av) if av.nft_type == injection.nft_type() => {
+			if av.block_number != injection.block_number {
+				let old_path = format!(
+					"{SEALPATH}/{prefix}_{}_{}.keyshare",
+					injection.nft_id, av.block_number
+				);
+				if let Err(err) = fs::remove_file(&old_path) {
+					warn!("ADMIN INJECT : error removing the old keyshare {old_path} : {err:?}");
+				}
+			}
+			av.nft_type
+		},
+		Some(_) => NftType::Hybrid,
+		None => injection.nft_type(),
+	};
+
+	set_nft_availability(
+		state,
+		(injection.nft_id, Availability { block_number: injection.block_number, nft_type }),
+	)
+	.await;
+	publish_keyshare_event(
+		KeyshareEventKind::RESTORED,
+		injection.nft_id,
+		nft_type,
+		injection.block_number,
+	);
+
+	debug!("ADMIN INJECT : {} {} is injected", injection.kind, injection.nft_id);
+	Ok(())
+}
+
+/// Inject keyshares exported by the enclaves of the clusters
+/// # Arguments
+/// * `state` - SharedState
+/// * `request` - InjectionPacket
+/// # Returns
+/// * 200 if every keyshare is written, 207 with per-nft results otherwise
+#[axum::debug_handler]
+pub async fn admin_backup_push_keyshares(
+	State(state): State<SharedState>,
+	caller: VerifiedCaller,
+	Json(request): Json<InjectionPacket>,
+) -> impl IntoResponse {
+	debug!("ADMIN INJECT : start");
+
+	if let Err(err) = verify_writable(&state).await {
+		let message = format!("ADMIN INJECT : injection is rejected : {err:?}");
+		warn!(message);
+		return (StatusCode::SERVICE_UNAVAILABLE, Json(json!({ "error": message })))
+	}
+
+	let data_hash = sha256::digest(request.keyshares.as_bytes());
+	if let Err((status, message)) = caller.verify_data_hash(&data_hash) {
+		let message = format!("ADMIN INJECT : {message}");
+		warn!(message);
+		return (status, Json(json!({ "error": message })))
+	}
+
+	let injections = match parse_injections(&request.keyshares) {
+		Ok(injections) => injections,
+		Err(message) => {
+			let message = format!("ADMIN INJECT : {message}");
+			warn!(message);
+			return (StatusCode::BAD_REQUEST, Json(json!({ "error": message })))
+		},
+	};
+
+	let mut enclaves: BTreeSet<AccountId32> = get_clusters(&state)
+		.await
+		.into_iter()
+		.flat_map(|cluster| cluster.enclaves.into_iter())
+		.map(|enclave| enclave.enclave_account)
+		.collect();
+	if let Ok(account) = AccountId32::from_str(&get_accountid(&state).await) {
+		enclaves.insert(account);
+	}
+
+	let mut results = Vec::<BatchItemResult>::with_capacity(injections.len());
+	for injection in &injections {
+		let result = match check_injection(injection, &enclaves) {
+			Ok(()) => inject_keyshare(&state, injection).await,
+			Err(failure) => Err(failure),
+		};
+
+		results.push(match result {
+			Ok(()) => BatchItemResult::success(injection.nft_id, ReturnStatus::STORESUCCESS),
+			Err(failure) => {
+				warn!(
+					"ADMIN INJECT : {} {} is rejected : {}",
+					injection.kind, injection.nft_id, failure.description
+				);
+				failure
+			},
+		});
+	}
+
+	let errors = batch_errors(&results, ReturnStatus::STORESUCCESS);
+	let failed = errors.len();
+	info!("ADMIN INJECT : {} of {} keyshares injected", results.len() - failed, results.len());
+
+	let status = if failed == 0 { StatusCode::OK } else { StatusCode::MULTI_STATUS };
+	(
+		status,
+		Json(json!({
+			"success": format!("Injected {} of {} keyshares", results.len() - failed, results.len()),
+			"results": results,
+			"errors": errors,
+		})),
+	)
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use subxt::ext::sp_core::{crypto::Ss58Codec, sr25519, Pair};
+
+	fn injection(keypair: &sr25519::Pair, nft_id: u32, keyshare: &str) -> KeyshareInjection {
+		let mut injection = KeyshareInjection {
+			nft_id,
+			kind: NftKind::SECRET,
+			block_number: 1000,
+			keyshare: keyshare.to_string(),
+			origin_enclave: keypair.public().to_ss58check(),
+			origin_signature: String::new(),
+		};
+		let signature = keypair.sign(injection.origin_message().as_bytes());
+		injection.origin_signature = format!("0x{}", hex::encode(signature.0));
+		injection
+	}
+
+	#[test]
+	fn keyshare_injection_test() {
+		let origin = sr25519::Pair::from_seed(&[3u8; 32]);
+		let stranger = sr25519::Pair::from_seed(&[4u8; 32]);
+		let enclaves = BTreeSet::from([AccountId32::from(origin.public().0)]);
+		let keyshare = "0x4f6e65206b6579736861726520666f7220696e6a656374696f6e";
+
+		let valid = injection(&origin, 10, keyshare);
+		assert!(check_injection(&valid, &enclaves).is_ok());
+
+		// Another keyshare than the signed one
+		let mut altered = valid.clone();
+		altered.keyshare = keyshare.replace("4", "5");
+		let failure = check_injection(&altered, &enclaves).unwrap_err();
+		assert_eq!(failure.status, ReturnStatus::INVALIDDATASIGNATURE);
+
+		// Signed by an enclave which is not registered
+		let unregistered = injection(&stranger, 10, keyshare);
+		let failure = check_injection(&unregistered, &enclaves).unwrap_err();
+		assert_eq!(failure.status, ReturnStatus::INVALIDSIGNERADDRESS);
+
+		let short = injection(&origin, 10, "0x01");
+		let failure = check_injection(&short, &enclaves).unwrap_err();
+		assert_eq!(failure.status, ReturnStatus::KEYSHAREISTOOSHORT);
+
+		// A newer sealed keyshare is kept, the other kind is not replaced
+		let sealed = |block_number, nft_type| Some(Availability { block_number, nft_type });
+		assert_eq!(newer_sealed(&valid, sealed(1001, NftType::Secret)), Some(1001));
+		assert_eq!(newer_sealed(&valid, sealed(1000, NftType::Secret)), None);
+		assert_eq!(newer_sealed(&valid, sealed(2000, NftType::Capsule)), None);
+		assert_eq!(newer_sealed(&valid, None), None);
+
+		let keyshares = serde_json::to_string(&vec![valid.clone(), valid]).unwrap();
+		assert!(parse_injections(&keyshares).unwrap_err().contains("duplicated"));
+		assert!(parse_injections("[]").is_err());
+	}
+}
@@ -0,0 +1,749 @@
+use axum::{
+	extract::{ConnectInfo, State},
+	http::{header, StatusCode},
+	response::IntoResponse,
+	Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	net::SocketAddr,
+};
+use subxt::ext::sp_core::Pair;
+use tracing::{debug, info, warn};
+
+use crate::{
+	chain::{
+		commitment::{storage_root_message, StorageTree, TreeHash},
+		helper::NftType,
+	},
+	servers::{
+		auth::VerifiedCaller,
+		proxy::with_http_proxy,
+		state::{get_accountid, get_blocknumber, get_clusters, get_keypair, SharedState},
+	},
+};
+
+use super::sync::{
+	create_sync_request, error_handler, slot_discovery, verify_signature, verify_sync_request,
+	Enclave, FetchIdPacket,
+};
+
+/* *************************************
+	INVENTORY DATA STRUCTURES
+**************************************** */
+
+/// Stored keyshare of an nft, with the block number it has been stored or synced
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct InventoryItem {
+	pub nft_id: u32,
+	pub block_number: u32,
+	pub nft_type: NftType,
+	// Leaf of the storage tree
+	pub keyshare_hash: String,
+}
+
+/// Inventory of an enclave at a block, its storage root is signed by the enclave account
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct InventoryDigest {
+	pub enclave_account: String,
+	pub block_number: u32,
+	pub items: Vec<InventoryItem>,
+	pub root: String,
+	pub signature: String,
+}
+
+/// Storage root of an enclave at a block, signed by the enclave account.
+/// Enclaves with the same root hold the same keyshares, the listing is only needed otherwise.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StorageRoot {
+	pub enclave_account: String,
+	pub block_number: u32,
+	pub root: String,
+	pub count: usize,
+	pub signature: String,
+}
+
+/// Peer comparison request, signed by the admin quorum
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PeerComparePacket {
+	peer_url: String,
+}
+
+/// Same nft stored on both enclaves, in different blocks or with different keyshares
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct BlockMismatch {
+	pub nft_id: u32,
+	pub local_block: u32,
+	pub peer_block: u32,
+}
+
+/// Consistency check of the enclaves of the same slot, signed by the admin quorum
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ConsistencyCheckPacket {}
+
+/// Nft stored in an older block on some enclaves of the slot
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct StaleKeyshare {
+	pub nft_id: u32,
+	pub latest_block: u32,
+	// enclave_account -> older block it holds
+	pub stale_on: BTreeMap<String, u32>,
+}
+
+/// Divergence of the inventories of the enclaves of a slot
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct DivergenceReport {
+	// enclave_account -> nfts stored by another enclave of the slot, missing on this one
+	pub missing: BTreeMap<String, Vec<u32>>,
+	pub stale: Vec<StaleKeyshare>,
+	// Stored in the same latest block with different keyshares
+	pub keyshare_mismatch: Vec<u32>,
+}
+
+impl DivergenceReport {
+	pub fn is_consistent(&self) -> bool {
+		self.missing.is_empty() && self.stale.is_empty() && self.keyshare_mismatch.is_empty()
+	}
+}
+
+/// Symmetric difference of two inventories by nft id
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct InventoryDiff {
+	// Stored on this enclave, missing on the peer
+	pub only_local: Vec<InventoryItem>,
+	// Stored on the peer, missing on this enclave
+	pub only_peer: Vec<InventoryItem>,
+	pub block_mismatch: Vec<BlockMismatch>,
+	// Stored in the same block with a different keyshare
+	pub keyshare_mismatch: Vec<u32>,
+}
+
+impl InventoryDigest {
+	/// Storage root of the items, None if a keyshare hash is malformed
+	pub fn compute_root(items: &[InventoryItem]) -> Option<String> {
+		let mut keyshares = Vec::with_capacity(items.len());
+		for item in items {
+			let keyshare_hash: TreeHash = hex::decode(&item.keyshare_hash).ok()?.try_into().ok()?;
+			keyshares.push((item.nft_id, keyshare_hash));
+		}
+
+		Some(StorageTree::from_keyshares(keyshares).root())
+	}
+
+	/// Check the root of the items and the signature of the enclave account
+	pub fn verify(&self) -> bool {
+		Self::compute_root(&self.items).as_ref() == Some(&self.root) &&
+			verify_signature(
+				&self.enclave_account,
+				self.signature.clone(),
+				storage_root_message(self.block_number, &self.root).as_bytes(),
+			)
+	}
+}
+
+impl StorageRoot {
+	pub fn verify(&self) -> bool {
+		verify_signature(
+			&self.enclave_account,
+			self.signature.clone(),
+			storage_root_message(self.block_number, &self.root).as_bytes(),
+		)
+	}
+}
+
+/// Data hash of the peer comparison request, signed inside the authentication token
+pub fn peer_compare_data_hash(peer_url: &str) -> String {
+	sha256::digest(format!("peer-compare_{}", peer_url.trim_end_matches('/')).as_bytes())
+}
+
+/// Compare local holdings with the inventory of a peer
+/// # Arguments
+/// * `local` - inventory of this enclave
+/// * `peer` - inventory of the peer enclave
+pub fn compare_inventories(local: &[InventoryItem], peer: &[InventoryItem]) -> InventoryDiff {
+	let local_map: BTreeMap<u32, &InventoryItem> =
+		local.iter().map(|item| (item.nft_id, item)).collect();
+	let peer_map: BTreeMap<u32, &InventoryItem> =
+		peer.iter().map(|item| (item.nft_id, item)).collect();
+
+	let mut diff = InventoryDiff::default();
+
+	for (nft_id, local_item) in &local_map {
+		match peer_map.get(nft_id) {
+			None => diff.only_local.push((*local_item).clone()),
+			Some(peer_item) if peer_item.block_number != local_item.block_number =>
+				diff.block_mismatch.push(BlockMismatch {
+					nft_id: *nft_id,
+					local_block: local_item.block_number,
+					peer_block: peer_item.block_number,
+				}),
+			Some(peer_item) if peer_item.keyshare_hash != local_item.keyshare_hash =>
+				diff.keyshare_mismatch.push(*nft_id),
+			_ => {},
+		}
+	}
+
+	diff.only_peer = peer_map
+		.iter()
+		.filter(|(nft_id, _)| !local_map.contains_key(nft_id))
+		.map(|(_, item)| (*item).clone())
+		.collect();
+
+	diff
+}
+
+/// Data hash of the consistency check request, signed inside the authentication token
+pub fn consistency_check_data_hash() -> String {
+	sha256::digest("consistency-check".as_bytes())
+}
+
+/// Compare the inventories of all the enclaves of a slot, the basis of a targeted re-sync
+/// # Arguments
+/// * `inventories` - enclave_account -> inventory
+pub fn divergence_report(inventories: &BTreeMap<String, Vec<InventoryItem>>) -> DivergenceReport {
+	let mut holders = BTreeMap::<u32, BTreeMap<&String, &InventoryItem>>::new();
+	for (enclave, items) in inventories {
+		for item in items {
+			holders.entry(item.nft_id).or_default().insert(enclave, item);
+		}
+	}
+
+	let mut report = DivergenceReport::default();
+
+	for (nft_id, held) in holders {
+		for enclave in inventories.keys().filter(|enclave| !held.contains_key(enclave)) {
+			report.missing.entry(enclave.clone()).or_default().push(nft_id);
+		}
+
+		let latest_block = held.values().map(|item| item.block_number).max().unwrap_or_default();
+
+		let stale_on: BTreeMap<String, u32> = held
+			.iter()
+			.filter(|(_, item)| item.block_number < latest_block)
+			.map(|(enclave, item)| ((*enclave).clone(), item.block_number))
+			.collect();
+
+		if !stale_on.is_empty() {
+			report.stale.push(StaleKeyshare { nft_id, latest_block, stale_on });
+		}
+
+		let latest_hashes: BTreeSet<&String> = held
+			.values()
+			.filter(|item| item.block_number == latest_block)
+			.map(|item| &item.keyshare_hash)
+			.collect();
+
+		if latest_hashes.len() > 1 {
+			report.keyshare_mismatch.push(nft_id);
+		}
+	}
+
+	report
+}
+
+/* *************************************
+		 INVENTORY
+**************************************** */
+
+/// Current inventory of this enclave ordered by nft id, with its storage root
+pub async fn local_inventory(state: &SharedState) -> (Vec<InventoryItem>, String) {
+	let shared_state_read = state.read().await;
+	let tree = shared_state_read.get_storage_tree();
+
+	let items = shared_state_read
+		.get_nft_availability_map()
+		.into_iter()
+		.map(|(nft_id, availability)| InventoryItem {
+			nft_id,
+			block_number: availability.block_number,
+			nft_type: availability.nft_type,
+			keyshare_hash: tree.keyshare_hash(nft_id).unwrap_or_default(),
+		})
+		.collect();
+
+	(items, tree.root())
+}
+
+/// Current storage root of this enclave and the number of its keyshares
+pub async fn local_storage_root(state: &SharedState) -> (String, usize) {
+	let shared_state_read = state.read().await;
+	let tree = shared_state_read.get_storage_tree();
+	(tree.root(), tree.count())
+}
+
+async fn signed_inventory(state: &SharedState) -> InventoryDigest {
+	let block_number = get_blocknumber(state).await;
+	let (items, root) = local_inventory(state).await;
+	let signature = get_keypair(state)
+		.await
+		.sign(storage_root_message(block_number, &root).as_bytes());
+
+	InventoryDigest {
+		enclave_account: get_accountid(state).await,
+		block_number,
+		items,
+		root,
+		signature: format!("{}{:?}", "0x", signature),
+	}
+}
+
+async fn signed_storage_root(state: &SharedState) -> StorageRoot {
+	let block_number = get_blocknumber(state).await;
+	let (root, count) = local_storage_root(state).await;
+	let signature = get_keypair(state)
+		.await
+		.sign(storage_root_message(block_number, &root).as_bytes());
+
+	StorageRoot {
+		enclave_account: get_accountid(state).await,
+		block_number,
+		root,
+		count,
+		signature: format!("{}{:?}", "0x", signature),
+	}
+}
+
+/// Signed inventory of this enclave for another enclave of the same slot (Server Side)
+/// The request is authenticated and attested the same way as keyshare synchronization
+/// # Arguments
+/// * `state` - SharedState
+/// * `request` - FetchIdPacket
+#[axum::debug_handler]
+pub async fn sync_inventory(
+	State(state): State<SharedState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
+	Json(request): Json<FetchIdPacket>,
+) -> impl IntoResponse {
+	debug!("SYNC INVENTORY : START");
+
+	if let Err(message) = verify_sync_request(&state, addr, &request).await {
+		return error_handler(message, &state).await.into_response()
+	}
+
+	let inventory = signed_inventory(&state).await;
+	debug!("SYNC INVENTORY : {} items at block {}", inventory.items.len(), inventory.block_number);
+
+	(StatusCode::OK, Json(inventory)).into_response()
+}
+
+/// Signed storage root of this enclave for another enclave of the same slot (Server Side)
+/// The request is authenticated and attested the same way as keyshare synchronization
+/// # Arguments
+/// * `state` - SharedState
+/// * `request` - FetchIdPacket
+#[axum::debug_handler]
+pub async fn sync_storage_root(
+	State(state): State<SharedState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
+	Json(request): Json<FetchIdPacket>,
+) -> impl IntoResponse {
+	debug!("SYNC STORAGE ROOT : START");
+
+	if let Err(message) = verify_sync_request(&state, addr, &request).await {
+		return error_handler(message, &state).await.into_response()
+	}
+
+	let storage_root = signed_storage_root(&state).await;
+	debug!(
+		"SYNC STORAGE ROOT : {} with {} items at block {}",
+		storage_root.root, storage_root.count, storage_root.block_number
+	);
+
+	(StatusCode::OK, Json(storage_root)).into_response()
+}
+
+/* *************************************
+		 PEER COMPARISON
+**************************************** */
+
+/// Fetch a signed response of a peer enclave (Client Side)
+/// # Arguments
+/// * `state` - SharedState
+/// * `peer` - registered enclave
+/// * `endpoint` - sync endpoint of the peer, i.e. "sync-inventory"
+async fn fetch_peer<T: serde::de::DeserializeOwned>(
+	state: &SharedState,
+	peer: &Enclave,
+	endpoint: &str,
+) -> Result<T, String> {
+	let wildcard = serde_json::to_string(&vec!["*".to_string()]).map_err(|err| err.to_string())?;
+	let (request, _) = create_sync_request(state, wildcard).await.map_err(|err| err.to_string())?;
+
+	let client = with_http_proxy(reqwest::Client::builder())
+		// This is for development, will be removed for production certs
+		.danger_accept_invalid_certs(!cfg!(any(feature = "mainnet", feature = "alphanet")))
+		.https_only(true)
+		.build()
+		.map_err(|err| format!("unable to build a Reqwest client : {err:?}"))?;
+
+	let request_url = format!("{}/api/backup/{endpoint}", peer.enclave_url.trim_end_matches('/'));
+	debug!("PEER COMPARE : request url : {}", request_url);
+
+	let request_body = serde_json::to_string(&request).map_err(|err| err.to_string())?;
+
+	let response = client
+		.post(request_url)
+		.body(request_body)
+		.header(header::CONTENT_TYPE, "application/json")
+		.send()
+		.await
+		.map_err(|err| format!("peer request error : {err:?}"))?;
+
+	let status = response.status();
+	let body = response.text().await.map_err(|err| format!("peer response error : {err:?}"))?;
+	if status != StatusCode::OK {
+		return Err(format!("peer responded with {status} : {body}"))
+	}
+
+	serde_json::from_str(&body)
+		.map_err(|err| format!("can not deserialize peer {endpoint} : {err:?}"))
+}
+
+/// Fetch and verify the signed storage root of a peer enclave (Client Side)
+async fn fetch_peer_storage_root(
+	state: &SharedState,
+	peer: &Enclave,
+) -> Result<StorageRoot, String> {
+	let storage_root: StorageRoot = fetch_peer(state, peer, "sync-root").await?;
+
+	if storage_root.enclave_account != peer.enclave_account.to_string() {
+		return Err(format!(
+			"storage root is signed by {}, expected {}",
+			storage_root.enclave_account, peer.enclave_account
+		))
+	}
+
+	if !storage_root.verify() {
+		return Err("invalid storage root signature".to_string())
+	}
+
+	Ok(storage_root)
+}
+
+/// Fetch and verify the signed inventory of a peer enclave (Client Side)
+async fn fetch_peer_inventory(
+	state: &SharedState,
+	peer: &Enclave,
+) -> Result<InventoryDigest, String> {
+	let inventory: InventoryDigest = fetch_peer(state, peer, "sync-inventory").await?;
+
+	if inventory.enclave_account != peer.enclave_account.to_string() {
+		return Err(format!(
+			"inventory is signed by {}, expected {}",
+			inventory.enclave_account, peer.enclave_account
+		))
+	}
+
+	if !inventory.verify() {
+		return Err("invalid inventory root or signature".to_string())
+	}
+
+	Ok(inventory)
+}
+
+/// Compare local holdings with the signed inventory of another registered enclave
+/// # Arguments
+/// * `state` - SharedState
+/// * `request` - PeerComparePacket
+#[axum::debug_handler]
+pub async fn admin_compare_peer(
+	State(state): State<SharedState>,
+	caller: VerifiedCaller,
+	Json(request): Json<PeerComparePacket>,
+) -> impl IntoResponse {
+	debug!("ADMIN PEER COMPARE : start");
+
+	if let Err((status, message)) =
+		caller.verify_data_hash(&peer_compare_data_hash(&request.peer_url))
+	{
+		let message = format!("ADMIN PEER COMPARE : {message}");
+		warn!(message);
+		return (status, Json(json!({ "error": message })))
+	}
+
+	// Only registered enclaves are contacted
+	let peer_url = request.peer_url.trim_end_matches('/');
+	let peer = match get_clusters(&state)
+		.await
+		.into_iter()
+		.flat_map(|cluster| cluster.enclaves)
+		.find(|enclave| enclave.enclave_url.trim_end_matches('/') == peer_url)
+	{
+		Some(peer) => peer,
+		None => {
+			let message = format!("ADMIN PEER COMPARE : {peer_url} is not a registered enclave");
+			warn!(message);
+			return (StatusCode::NOT_FOUND, Json(json!({ "error": message })))
+		},
+	};
+
+	if peer.enclave_account.to_string() == get_accountid(&state).await {
+		let message = "ADMIN PEER COMPARE : peer is the current enclave".to_string();
+		warn!(message);
+		return (StatusCode::BAD_REQUEST, Json(json!({ "error": message })))
+	}
+
+	// Roots are compared first, the listings are only fetched if they differ
+	let peer_root = match fetch_peer_storage_root(&state, &peer).await {
+		Ok(storage_root) => storage_root,
+		Err(message) => {
+			let message = format!("ADMIN PEER COMPARE : {peer_url} : {message}");
+			warn!(message);
+			return (StatusCode::BAD_GATEWAY, Json(json!({ "error": message })))
+		},
+	};
+
+	let local_block = get_blocknumber(&state).await;
+	let (local_root, local_count) = local_storage_root(&state).await;
+
+	if peer_root.root == local_root {
+		info!("ADMIN PEER COMPARE : {} : storage roots match, {} items", peer_url, local_count);
+
+		return (
+			StatusCode::OK,
+			Json(json!({
+				"peer_url": peer_url,
+				"peer_account": peer_root.enclave_account,
+				"local_block_number": local_block,
+				"peer_block_number": peer_root.block_number,
+				"local_root": local_root,
+				"peer_root": peer_root.root,
+				"in_sync": true,
+				"local_count": local_count,
+				"peer_count": peer_root.count,
+				"only_local": [],
+				"only_peer": [],
+				"block_mismatch": [],
+				"keyshare_mismatch": [],
+			})),
+		)
+	}
+
+	let peer_inventory = match fetch_peer_inventory(&state, &peer).await {
+		Ok(inventory) => inventory,
+		Err(message) => {
+			let message = format!("ADMIN PEER COMPARE : {peer_url} : {message}");
+			warn!(message);
+			return (StatusCode::BAD_GATEWAY, Json(json!({ "error": message })))
+		},
+	};
+
+	let (local_items, local_root) = local_inventory(&state).await;
+	let diff = compare_inventories(&local_items, &peer_inventory.items);
+
+	info!(
+		"ADMIN PEER COMPARE : {} : only local = {}, only peer = {}, block mismatch = {}, keyshare mismatch = {}",
+		peer_url,
+		diff.only_local.len(),
+		diff.only_peer.len(),
+		diff.block_mismatch.len(),
+		diff.keyshare_mismatch.len()
+	);
+
+	(
+		StatusCode::OK,
+		Json(json!({
+			"peer_url": peer_url,
+			"peer_account": peer_inventory.enclave_account,
+			"local_block_number": local_block,
+			"peer_block_number": peer_inventory.block_number,
+			"local_root": local_root,
+			"peer_root": peer_inventory.root,
+			// Roots may match again if the holdings changed between both requests
+			"in_sync": local_root == peer_inventory.root,
+			"local_count": local_items.len(),
+			"peer_count": peer_inventory.items.len(),
+			"only_local": diff.only_local,
+			"only_peer": diff.only_peer,
+			"block_mismatch": diff.block_mismatch,
+			"keyshare_mismatch": diff.keyshare_mismatch,
+		})),
+	)
+}
+
+/// Compare the inventories of this enclave and of the enclaves of the same slot in the other
+/// clusters, which hold the same keyshares
+/// # Arguments
+/// * `state` - SharedState
+/// * `request` - ConsistencyCheckPacket
+#[axum::debug_handler]
+pub async fn admin_consistency_check(
+	State(state): State<SharedState>,
+	caller: VerifiedCaller,
+	Json(_request): Json<ConsistencyCheckPacket>,
+) -> impl IntoResponse {
+	debug!("ADMIN CONSISTENCY CHECK : start");
+
+	if let Err((status, message)) = caller.verify_data_hash(&consistency_check_data_hash()) {
+		let message = format!("ADMIN CONSISTENCY CHECK : {message}");
+		warn!(message);
+		return (status, Json(json!({ "error": message })))
+	}
+
+	let local_account = get_accountid(&state).await;
+	let local_block = get_blocknumber(&state).await;
+	let (local_items, local_root) = local_inventory(&state).await;
+
+	let peers = slot_discovery(&state).await;
+
+	// Roots are compared first, the listings are only fetched if they differ
+	let (state_ref, local_root_ref) = (&state, &local_root);
+	let fetches = peers.iter().map(|(_, peer)| async move {
+		let storage_root = fetch_peer_storage_root(state_ref, peer).await?;
+		if storage_root.root == *local_root_ref {
+			return Ok((storage_root, None))
+		}
+
+		let inventory = fetch_peer_inventory(state_ref, peer).await?;
+		Ok::<_, String>((storage_root, Some(inventory.items)))
+	});
+
+	let results = futures::future::join_all(fetches).await;
+
+	let mut inventories = BTreeMap::new();
+	let mut enclaves = Vec::new();
+	let mut unreachable = BTreeMap::new();
+
+	for ((cluster_id, peer), result) in peers.iter().zip(results) {
+		let account = peer.enclave_account.to_string();
+		match result {
+			Ok((storage_root, items)) => {
+				enclaves.push(json!({
+					"cluster_id": cluster_id,
+					"enclave_account": account,
+					"enclave_url": peer.enclave_url,
+					"block_number": storage_root.block_number,
+					"root": storage_root.root,
+					"count": storage_root.count,
+					"in_sync": items.is_none(),
+				}));
+				inventories.insert(account, items.unwrap_or_else(|| local_items.clone()));
+			},
+			Err(message) => {
+				warn!("ADMIN CONSISTENCY CHECK : {} : {message}", peer.enclave_url);
+				unreachable.insert(account, message);
+			},
+		}
+	}
+
+	inventories.insert(local_account.clone(), local_items);
+	let report = divergence_report(&inventories);
+
+	info!(
+		"ADMIN CONSISTENCY CHECK : {} enclaves, {} unreachable, {} missing, {} stale, {} keyshare mismatch",
+		inventories.len(),
+		unreachable.len(),
+		report.missing.values().map(Vec::len).sum::<usize>(),
+		report.stale.len(),
+		report.keyshare_mismatch.len()
+	);
+
+	(
+		StatusCode::OK,
+		Json(json!({
+			"enclave_account": local_account,
+			"block_number": local_block,
+			"root": local_root,
+			"enclaves": enclaves,
+			"unreachable": unreachable,
+			"consistent": report.is_consistent() && unreachable.is_empty(),
+			"missing": report.missing,
+			"stale": report.stale,
+			"keyshare_mismatch": report.keyshare_mismatch,
+		})),
+	)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use subxt::ext::sp_core::{crypto::Ss58Codec, sr25519};
+
+	fn item(nft_id: u32, block_number: u32) -> InventoryItem {
+		InventoryItem {
+			nft_id,
+			block_number,
+			nft_type: NftType::Secret,
+			keyshare_hash: hex::encode([nft_id as u8; 32]),
+		}
+	}
+
+	#[test]
+	fn compare_inventories_test() {
+		let local = vec![item(1, 100), item(2, 200), item(3, 300)];
+		let peer = vec![item(2, 200), item(3, 350), item(4, 400)];
+
+		let diff = compare_inventories(&local, &peer);
+		assert_eq!(diff.only_local, vec![item(1, 100)]);
+		assert_eq!(diff.only_peer, vec![item(4, 400)]);
+		assert_eq!(
+			diff.block_mismatch,
+			vec![BlockMismatch { nft_id: 3, local_block: 300, peer_block: 350 }]
+		);
+
+		assert_eq!(compare_inventories(&local, &local), InventoryDiff::default());
+
+		let mut changed = local.clone();
+		changed[1].keyshare_hash = hex::encode([9u8; 32]);
+		assert_eq!(compare_inventories(&local, &changed).keyshare_mismatch, vec![2]);
+	}
+
+	#[test]
+	fn divergence_report_test() {
+		let (a, b, c) = ("enclave_a".to_string(), "enclave_b".to_string(), "enclave_c".to_string());
+
+		let mut changed = item(4, 400);
+		changed.keyshare_hash = hex::encode([9u8; 32]);
+
+		let inventories = BTreeMap::from([
+			(a.clone(), vec![item(1, 100), item(2, 200), item(3, 300), item(4, 400)]),
+			(b.clone(), vec![item(1, 100), item(3, 350), changed]),
+			(c.clone(), vec![item(1, 100), item(2, 200), item(3, 350), item(4, 400)]),
+		]);
+
+		let report = divergence_report(&inventories);
+		assert_eq!(report.missing, BTreeMap::from([(b.clone(), vec![2])]));
+		assert_eq!(
+			report.stale,
+			vec![StaleKeyshare {
+				nft_id: 3,
+				latest_block: 350,
+				stale_on: BTreeMap::from([(a.clone(), 300)])
+			}]
+		);
+		assert_eq!(report.keyshare_mismatch, vec![4]);
+		assert!(!report.is_consistent());
+
+		let same = BTreeMap::from([(a, vec![item(1, 100)]), (c, vec![item(1, 100)])]);
+		assert!(divergence_report(&same).is_consistent());
+	}
+
+	#[test]
+	fn inventory_digest_test() {
+		let keypair = sr25519::Pair::from_string("//Alice", None).unwrap();
+		let items = vec![item(1, 100), item(2, 200)];
+		let root = InventoryDigest::compute_root(&items).unwrap();
+
+		let mut inventory = InventoryDigest {
+			enclave_account: keypair.public().to_ss58check(),
+			block_number: 1000,
+			items,
+			root: root.clone(),
+			signature: format!(
+				"{}{:?}",
+				"0x",
+				keypair.sign(storage_root_message(1000, &root).as_bytes())
+			),
+		};
+		assert!(inventory.verify());
+
+		inventory.items[1].keyshare_hash = hex::encode([9u8; 32]);
+		assert!(!inventory.verify());
+		assert_ne!(InventoryDigest::compute_root(&inventory.items).unwrap(), root);
+
+		inventory.items[1].keyshare_hash = "malformed".to_string();
+		assert_eq!(InventoryDigest::compute_root(&inventory.items), None);
+	}
+}
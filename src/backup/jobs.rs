@@ -0,0 +1,658 @@
+use std::{
+	collections::BTreeMap,
+	fs,
+	path::{Path, PathBuf},
+	sync::Mutex,
+	time::{Duration, Instant},
+};
+
+use age::x25519;
+use anyhow::anyhow;
+use axum::{
+	body::StreamBody,
+	extract::{Path as UrlPath, State},
+	http::{header, StatusCode},
+	response::{IntoResponse, Response},
+	Json,
+};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use subxt::ext::sp_core::{sr25519, Pair};
+use tokio_util::io::ReaderStream;
+use tracing::{debug, error, info, warn};
+
+use crate::{
+	chain::constants::{
+		BACKUP_JOB_PATH, BACKUP_JOB_PERIOD, BACKUP_JOB_QUEUE_TIMEOUT, DOWNLOAD_TOKEN_PERIOD,
+		MAX_BACKUP_JOBS, MAX_DOWNLOAD_TOKENS, SEALPATH,
+	},
+	servers::{
+		auth::VerifiedCaller,
+		coordination::MaintenanceOperation,
+		metrics::record_backup,
+		state::{get_blocknumber, get_coordinator, get_keypair, SharedState},
+	},
+};
+
+use super::{
+	container::{write_archive, BackupFormat},
+	encryption::encrypt_archive,
+	manifest::{signed_manifest, BackupSelection, NotFoundEntry},
+	schedule::is_backup_running,
+	workspace::Workspace,
+};
+
+/* *************************************
+	BACKUP JOBS
+**************************************** */
+
+// Backup archives are built in the background instead of while holding the request :
+// 1. The fetch request is validated and returns a job id
+// 2. The status of the job reports the files and bytes zipped so far
+// 3. The finished archive is downloaded until the job expires
+// The job id is the capability of the archive, it is only returned to the admin.
+
+/// State of a backup job
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum JobStatus {
+	QUEUED,
+	RUNNING,
+	DONE,
+	FAILED,
+}
+
+/// Background backup, its archive is kept until the expiry block
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct BackupJob {
+	pub job_id: String,
+	pub status: JobStatus,
+	pub files_total: usize,
+	pub files_zipped: usize,
+	// Keyshare bytes zipped so far, before compression
+	pub bytes_zipped: u64,
+	// Size of the finished archive
+	pub archive_size: Option<u64>,
+	pub format: BackupFormat,
+	// Download name, "Backup.zip" or "Backup.tbk", with ".age" if it is encrypted to a recipient
+	pub filename: String,
+	pub created_block: u32,
+	pub expiry_block: u32,
+	pub error: Option<String>,
+}
+
+/// Request of a one-time download token, signed by an admin.
+/// Its auth_token and signature are verified by the auth middleware, the data hash of the token is
+/// download_data_hash(job_id)
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DownloadTokenPacket {
+	admin_account: String,
+	job_id: String,
+}
+
+static BACKUP_JOBS: Mutex<BTreeMap<String, BackupJob>> = Mutex::new(BTreeMap::new());
+
+// Nonce -> (job id, expiry block) of the download tokens which have not been used
+static DOWNLOAD_TOKENS: Mutex<BTreeMap<String, (String, u32)>> = Mutex::new(BTreeMap::new());
+
+fn valid_job_id(job_id: &str) -> bool {
+	job_id.len() == 32 && job_id.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn artifact_path(job: &BackupJob) -> PathBuf {
+	let age = if job.filename.ends_with(".age") { ".age" } else { "" };
+	Path::new(BACKUP_JOB_PATH).join(format!("{}.{}{age}", job.job_id, job.format.extension()))
+}
+
+fn remove_artifacts(job_id: &str) {
+	for extension in ["zip", "zip.age", "tbk", "tbk.age"] {
+		let path = Path::new(BACKUP_JOB_PATH).join(format!("{job_id}.{extension}"));
+		if path.exists() {
+			if let Err(err) = fs::remove_file(&path) {
+				error!("BACKUP JOB : error removing {path:?} : {err:?}");
+			}
+		}
+	}
+}
+
+fn is_active(job: &BackupJob) -> bool {
+	matches!(job.status, JobStatus::QUEUED | JobStatus::RUNNING)
+}
+
+fn update_job(job_id: &str, update: impl FnOnce(&mut BackupJob)) {
+	match BACKUP_JOBS.lock() {
+		Ok(mut jobs) =>
+			if let Some(job) = jobs.get_mut(job_id) {
+				update(job)
+			},
+		Err(err) => error!("BACKUP JOB : lock error : {err:?}"),
+	}
+}
+
+/// Current state of a job
+pub fn get_job(job_id: &str) -> Option<BackupJob> {
+	BACKUP_JOBS.lock().ok().and_then(|jobs| jobs.get(job_id).cloned())
+}
+
+/// Remove the expired jobs and their archives, running jobs are kept
+pub fn prune_jobs(current_block: u32) {
+	let expired: Vec<String> = match BACKUP_JOBS.lock() {
+		Ok(mut jobs) => {
+			let expired: Vec<String> = jobs
+				.values()
+				.filter(|job| !is_active(job) && job.expiry_block < current_block)
+				.map(|job| job.job_id.clone())
+				.collect();
+			for job_id in &expired {
+				jobs.remove(job_id);
+			}
+			expired
+		},
+		Err(err) => {
+			error!("BACKUP JOB : lock error : {err:?}");
+			return
+		},
+	};
+
+	for job_id in expired {
+		debug!("BACKUP JOB : job {job_id} has expired");
+		remove_artifacts(&job_id);
+	}
+}
+
+/// Wait for the running backups before the enclave exits, the archives of the jobs which are not
+/// done after the wait are removed, they would be incomplete
+/// # Arguments
+/// * `wait` - maximum wait
+/// # Errors
+/// * A scheduled snapshot is still being written
+pub async fn drain_backups(wait: Duration) -> Result<(), anyhow::Error> {
+	let active_jobs = || {
+		BACKUP_JOBS
+			.lock()
+			.map_or(0, |jobs| jobs.values().filter(|job| is_active(job)).count())
+	};
+
+	let deadline = Instant::now() + wait;
+	while (active_jobs() > 0 || is_backup_running()) && Instant::now() < deadline {
+		tokio::time::sleep(Duration::from_millis(200)).await;
+	}
+
+	let aborted: Vec<String> = match BACKUP_JOBS.lock() {
+		Ok(mut jobs) => jobs
+			.values_mut()
+			.filter(|job| is_active(job))
+			.map(|job| {
+				job.status = JobStatus::FAILED;
+				job.error = Some("enclave is shutting down".to_string());
+				job.job_id.clone()
+			})
+			.collect(),
+		Err(err) => return Err(anyhow!("BACKUP JOB : lock error : {err:?}")),
+	};
+
+	for job_id in aborted {
+		warn!("BACKUP JOB : job {job_id} is aborted by the shutdown");
+		remove_artifacts(&job_id);
+	}
+
+	if is_backup_running() {
+		return Err(anyhow!("SCHEDULED BACKUP : snapshot is still being written"))
+	}
+
+	Ok(())
+}
+
+/// Queue a new backup job
+/// # Arguments
+/// * `current_block` - current block number
+/// * `encrypted` - the archive is encrypted to a recipient
+/// * `format` - format of the archive
+/// # Errors
+/// * Too many queued or running jobs
+pub fn create_job(
+	current_block: u32,
+	encrypted: bool,
+	format: BackupFormat,
+) -> Result<BackupJob, anyhow::Error> {
+	prune_jobs(current_block);
+
+	let mut job_id = [0u8; 16];
+	rand::thread_rng().fill_bytes(&mut job_id);
+
+	let job = BackupJob {
+		job_id: hex::encode(job_id),
+		status: JobStatus::QUEUED,
+		files_total: 0,
+		files_zipped: 0,
+		bytes_zipped: 0,
+		archive_size: None,
+		format,
+		filename: if encrypted {
+			format!("{}.age", format.file_name())
+		} else {
+			format.file_name()
+		},
+		created_block: current_block,
+		expiry_block: current_block + BACKUP_JOB_PERIOD,
+		error: None,
+	};
+
+	let mut jobs =
+		BACKUP_JOBS.lock().map_err(|err| anyhow!("BACKUP JOB : lock error : {err:?}"))?;
+	if jobs.values().filter(|job| is_active(job)).count() >= MAX_BACKUP_JOBS {
+		return Err(anyhow!("BACKUP JOB : too many backups in progress, retry later"))
+	}
+
+	jobs.insert(job.job_id.clone(), job.clone());
+	Ok(job)
+}
+
+/// Build the archive of a queued job, the plaintext archive never stays on disk if a
+/// recipient is given
+/// # Arguments
+/// * `job_id` - queued job
+/// * `selection` - files of the archive
+/// * `recipient` - optional age recipient of the archive
+/// * `format` - format of the archive, as requested when the job was created
+/// * `not_found` - requested nfts without keyshare, listed in the manifest
+pub async fn run_backup_job(
+	state: SharedState,
+	job_id: String,
+	selection: BackupSelection,
+	recipient: Option<x25519::Recipient>,
+	format: BackupFormat,
+	not_found: Vec<NotFoundEntry>,
+) {
+	let started = Instant::now();
+	let built = build_archive(&state, &job_id, &selection, recipient, format, not_found).await;
+	record_backup("fetch-id", started.elapsed(), built.is_ok());
+
+	match built {
+		Ok(archive_size) => {
+			info!("BACKUP JOB : job {job_id} is done, archive of {archive_size} bytes");
+			update_job(&job_id, |job| {
+				job.status = JobStatus::DONE;
+				job.archive_size = Some(archive_size);
+			});
+		},
+		Err(err) => {
+			error!("BACKUP JOB : job {job_id} failed : {err:?}");
+			remove_artifacts(&job_id);
+			update_job(&job_id, |job| {
+				job.status = JobStatus::FAILED;
+				job.error = Some(format!("{err:?}"));
+			});
+		},
+	}
+}
+
+async fn build_archive(
+	state: &SharedState,
+	job_id: &str,
+	selection: &BackupSelection,
+	recipient: Option<x25519::Recipient>,
+	format: BackupFormat,
+	not_found: Vec<NotFoundEntry>,
+) -> Result<u64, anyhow::Error> {
+	// The job stays queued until the previous maintenance operation is done, then writes are held
+	// until the archive is written
+	let _operation = get_coordinator(state)
+		.await
+		.begin_maintenance(
+			MaintenanceOperation::BACKUP,
+			"fetch-id",
+			Duration::from_secs(BACKUP_JOB_QUEUE_TIMEOUT),
+		)
+		.await?;
+
+	let manifest = signed_manifest(state, SEALPATH, selection, not_found).await?;
+	let job = get_job(job_id).ok_or_else(|| anyhow!("BACKUP JOB : unknown job {job_id}"))?;
+
+	update_job(job_id, |job| {
+		job.status = JobStatus::RUNNING;
+		job.files_total = manifest.entries.len();
+	});
+
+	// The archive is built in a workspace, the artifact only appears once it is complete
+	fs::create_dir_all(BACKUP_JOB_PATH)?;
+	let workspace = Workspace::create("job")?;
+	let archive_file = workspace.file(&format!("backup.{}", format.extension()));
+	let artifact = artifact_path(&job);
+	let job_id = job_id.to_string();
+
+	tokio::task::spawn_blocking(move || -> Result<u64, anyhow::Error> {
+		write_archive(
+			format,
+			SEALPATH,
+			&manifest,
+			&archive_file.to_string_lossy(),
+			|files_zipped, bytes_zipped| {
+				update_job(&job_id, |job| {
+					job.files_zipped = files_zipped;
+					job.bytes_zipped = bytes_zipped;
+				})
+			},
+		)?;
+
+		if let Some(recipient) = recipient {
+			let encrypted = encrypt_archive(&archive_file, &artifact, recipient);
+
+			// The plaintext archive must not stay on disk
+			if let Err(err) = fs::remove_file(&archive_file) {
+				warn!("BACKUP JOB : Can not remove the plaintext backup file : {err:?}");
+			}
+
+			encrypted?;
+		} else {
+			fs::rename(&archive_file, &artifact)?;
+		}
+
+		drop(workspace);
+
+		Ok(fs::metadata(&artifact)?.len())
+	})
+	.await?
+}
+
+/* *************************************
+	ONE-TIME DOWNLOAD TOKENS
+**************************************** */
+
+// A download tool pulls the archive of a finished job with a plain GET, without the admin packet :
+// "JOBID.NONCE.EXPIRYBLOCK.SIGNATURE", signed by the enclave account. A token is accepted once,
+// until its expiry block, and never outlives its job.
+
+/// Canonical hash of a download token request, signed inside the authentication token
+pub fn download_data_hash(job_id: &str) -> String {
+	sha256::digest(format!("download-token_{job_id}").as_bytes())
+}
+
+fn token_message(job_id: &str, nonce: &str, expiry_block: u32) -> String {
+	format!("backup-download_{job_id}_{nonce}_{expiry_block}")
+}
+
+/// Issue a one-time download token of a job
+/// # Arguments
+/// * `keypair` - enclave account
+/// * `job` - finished job
+/// * `current_block` - current block number
+/// # Errors
+/// * Too many unused tokens
+pub fn issue_download_token(
+	keypair: &sr25519::Pair,
+	job: &BackupJob,
+	current_block: u32,
+) -> Result<(String, u32), anyhow::Error> {
+	let expiry_block = job.expiry_block.min(current_block + DOWNLOAD_TOKEN_PERIOD);
+
+	let mut nonce = [0u8; 16];
+	rand::thread_rng().fill_bytes(&mut nonce);
+	let nonce = hex::encode(nonce);
+
+	let mut tokens = DOWNLOAD_TOKENS
+		.lock()
+		.map_err(|err| anyhow!("BACKUP DOWNLOAD : lock error : {err:?}"))?;
+	tokens.retain(|_, (_, expiry)| *expiry >= current_block);
+	if tokens.len() >= MAX_DOWNLOAD_TOKENS {
+		return Err(anyhow!("BACKUP DOWNLOAD : too many unused download tokens, retry later"))
+	}
+	tokens.insert(nonce.clone(), (job.job_id.clone(), expiry_block));
+
+	let signature = keypair.sign(token_message(&job.job_id, &nonce, expiry_block).as_bytes());
+	let token = format!("{}.{nonce}.{expiry_block}.{}", job.job_id, hex::encode(signature.0));
+
+	Ok((token, expiry_block))
+}
+
+/// Check a download token and consume it
+/// # Returns
+/// * `String` - job id of the token
+/// # Errors
+/// * Malformed, forged, expired or already used token
+pub fn redeem_download_token(
+	public: &sr25519::Public,
+	token: &str,
+	current_block: u32,
+) -> Result<String, anyhow::Error> {
+	let parts: Vec<&str> = token.split('.').collect();
+	let (job_id, nonce, expiry_block, signature) = match parts[..] {
+		[job_id, nonce, expiry_block, signature] => (job_id, nonce, expiry_block, signature),
+		_ => return Err(anyhow!("BACKUP DOWNLOAD : malformed download token")),
+	};
+
+	let expiry_block = expiry_block
+		.parse::<u32>()
+		.map_err(|_| anyhow!("BACKUP DOWNLOAD : malformed download token"))?;
+	let signature = <[u8; 64]>::try_from(hex::decode(signature).unwrap_or_default())
+		.map(sr25519::Signature::from_raw)
+		.map_err(|_| anyhow!("BACKUP DOWNLOAD : malformed download token"))?;
+
+	if !sr25519::Pair::verify(
+		&signature,
+		token_message(job_id, nonce, expiry_block).as_bytes(),
+		public,
+	) {
+		return Err(anyhow!("BACKUP DOWNLOAD : invalid download token signature"))
+	}
+
+	if expiry_block < current_block {
+		return Err(anyhow!("BACKUP DOWNLOAD : download token has expired"))
+	}
+
+	// A token is removed when it is redeemed, a replay does not find it
+	let mut tokens = DOWNLOAD_TOKENS
+		.lock()
+		.map_err(|err| anyhow!("BACKUP DOWNLOAD : lock error : {err:?}"))?;
+	match tokens.remove(nonce) {
+		Some((token_job_id, _)) if token_job_id == job_id => Ok(job_id.to_string()),
+		_ => Err(anyhow!("BACKUP DOWNLOAD : download token is already used")),
+	}
+}
+
+/* *************************************
+		 JOB ENDPOINTS
+**************************************** */
+
+/// Progress of a backup job
+pub async fn admin_backup_status(UrlPath(job_id): UrlPath<String>) -> impl IntoResponse {
+	if !valid_job_id(&job_id) {
+		let message = format!("BACKUP STATUS : invalid job id {job_id}");
+		return (StatusCode::BAD_REQUEST, Json(json!({ "error": message }))).into_response()
+	}
+
+	match get_job(&job_id) {
+		Some(job) => (StatusCode::OK, Json(json!(job))).into_response(),
+		None => {
+			let message = format!("BACKUP STATUS : unknown job id {job_id}");
+			(StatusCode::NOT_FOUND, Json(json!({ "error": message }))).into_response()
+		},
+	}
+}
+
+/// Issue a one-time download token of a finished job, for a download tool
+/// # Arguments
+/// * `state` - SharedState
+/// * `request` - DownloadTokenPacket
+#[axum::debug_handler]
+pub async fn admin_download_token(
+	State(state): State<SharedState>,
+	caller: VerifiedCaller,
+	Json(request): Json<DownloadTokenPacket>,
+) -> impl IntoResponse {
+	debug!("BACKUP DOWNLOAD TOKEN : start");
+
+	if let Err((status, message)) = caller.verify_data_hash(&download_data_hash(&request.job_id)) {
+		let message = format!("BACKUP DOWNLOAD TOKEN : {message}");
+		warn!(message);
+		return (status, Json(json!({ "error": message })))
+	}
+
+	let current_block = get_blocknumber(&state).await;
+	prune_jobs(current_block);
+
+	let job = match get_job(&request.job_id) {
+		Some(job) if job.status == JobStatus::DONE => job,
+		Some(job) => {
+			let message = format!("BACKUP DOWNLOAD TOKEN : job {} is {:?}", job.job_id, job.status);
+			return (StatusCode::CONFLICT, Json(json!({ "error": message, "job": job })))
+		},
+		None => {
+			let message = format!("BACKUP DOWNLOAD TOKEN : unknown job id {}", request.job_id);
+			return (StatusCode::NOT_FOUND, Json(json!({ "error": message })))
+		},
+	};
+
+	match issue_download_token(&get_keypair(&state).await, &job, current_block) {
+		Ok((token, expiry_block)) => {
+			info!(
+				"BACKUP DOWNLOAD TOKEN : token of job {} issued to {}",
+				job.job_id, request.admin_account
+			);
+			(
+				StatusCode::OK,
+				Json(json!({
+					"token": token,
+					"url": format!("/api/backup/artifact/{token}"),
+					"expiry_block": expiry_block,
+				})),
+			)
+		},
+		Err(err) => {
+			let message = format!("{err}");
+			warn!(message);
+			(StatusCode::TOO_MANY_REQUESTS, Json(json!({ "error": message })))
+		},
+	}
+}
+
+/// Stream the archive of a job with a one-time download token
+pub async fn backup_artifact_download(
+	State(state): State<SharedState>,
+	UrlPath(token): UrlPath<String>,
+) -> impl IntoResponse {
+	let current_block = get_blocknumber(&state).await;
+	prune_jobs(current_block);
+
+	let public = get_keypair(&state).await.public();
+	match redeem_download_token(&public, &token, current_block) {
+		Ok(job_id) => stream_artifact(&job_id).await,
+		Err(err) => {
+			let message = format!("{err}");
+			warn!(message);
+			(StatusCode::FORBIDDEN, Json(json!({ "error": message }))).into_response()
+		},
+	}
+}
+
+async fn stream_artifact(job_id: &str) -> Response {
+	let job = match get_job(job_id) {
+		Some(job) => job,
+		None => {
+			let message = format!("BACKUP DOWNLOAD : unknown job id {job_id}");
+			return (StatusCode::NOT_FOUND, Json(json!({ "error": message }))).into_response()
+		},
+	};
+
+	if job.status != JobStatus::DONE {
+		let message = format!("BACKUP DOWNLOAD : job {job_id} is {:?}", job.status);
+		return (StatusCode::CONFLICT, Json(json!({ "error": message, "job": job }))).into_response()
+	}
+
+	let file = match tokio::fs::File::open(artifact_path(&job)).await {
+		Ok(file) => file,
+		Err(err) => {
+			let message = format!("BACKUP DOWNLOAD : archive of job {job_id} not found : {err:?}");
+			error!(message);
+			return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })))
+				.into_response()
+		},
+	};
+
+	let headers = [
+		(header::CONTENT_TYPE, "text/toml; charset=utf-8".to_string()),
+		(header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", job.filename)),
+	];
+
+	debug!("BACKUP DOWNLOAD : Sending the archive of job {job_id} ...");
+	(headers, StreamBody::new(ReaderStream::new(file))).into_response()
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn backup_job_test() {
+		let jobs: Vec<BackupJob> = (0..MAX_BACKUP_JOBS)
+			.map(|_| create_job(1000, false, BackupFormat::ZIP).unwrap())
+			.collect();
+		assert!(jobs.iter().all(|job| valid_job_id(&job.job_id)));
+		assert!(!valid_job_id("../../nft/enclave_account.key"));
+		assert_eq!(artifact_path(&jobs[0]).extension().unwrap(), "zip");
+
+		// Queued jobs are limited
+		assert!(create_job(1000, true, BackupFormat::ZIP).is_err());
+
+		update_job(&jobs[0].job_id, |job| job.status = JobStatus::DONE);
+		prune_jobs(1000 + BACKUP_JOB_PERIOD);
+		assert_eq!(get_job(&jobs[0].job_id).unwrap().status, JobStatus::DONE);
+
+		// Finished jobs expire, active ones are kept
+		prune_jobs(1001 + BACKUP_JOB_PERIOD);
+		assert!(get_job(&jobs[0].job_id).is_none());
+		assert!(get_job(&jobs[1].job_id).is_some());
+
+		let encrypted = create_job(2000, true, BackupFormat::ZIP).unwrap();
+		assert_eq!(encrypted.filename, "Backup.zip.age");
+		assert!(artifact_path(&encrypted).to_string_lossy().ends_with(".zip.age"));
+		update_job(&encrypted.job_id, |job| job.status = JobStatus::DONE);
+
+		let container = create_job(2000, false, BackupFormat::V2).unwrap();
+		assert_eq!(container.filename, "Backup.tbk");
+		assert_eq!(artifact_path(&container).extension().unwrap(), "tbk");
+	}
+
+	#[test]
+	fn download_token_test() {
+		let keypair = sr25519::Pair::from_seed(&[9u8; 32]);
+		// Not registered, the jobs of the other test are limited
+		let job = BackupJob {
+			job_id: "0f".repeat(16),
+			status: JobStatus::DONE,
+			files_total: 1,
+			files_zipped: 1,
+			bytes_zipped: 100,
+			archive_size: Some(200),
+			format: BackupFormat::ZIP,
+			filename: BackupFormat::ZIP.file_name(),
+			created_block: 5000,
+			expiry_block: 5000 + BACKUP_JOB_PERIOD,
+			error: None,
+		};
+
+		let (token, expiry_block) = issue_download_token(&keypair, &job, 5000).unwrap();
+		assert_eq!(expiry_block, 5000 + DOWNLOAD_TOKEN_PERIOD);
+
+		// Tokens are signed by the enclave and expire
+		let other = sr25519::Pair::from_seed(&[8u8; 32]);
+		assert!(redeem_download_token(&other.public(), &token, 5000).is_err());
+		assert!(redeem_download_token(&keypair.public(), &token, expiry_block + 1).is_err());
+		let forged =
+			token.replace(&format!(".{expiry_block}."), &format!(".{}.", expiry_block + 10));
+		assert!(redeem_download_token(&keypair.public(), &forged, 5000).is_err());
+
+		// A token is used once
+		assert_eq!(redeem_download_token(&keypair.public(), &token, 5000).unwrap(), job.job_id);
+		assert!(redeem_download_token(&keypair.public(), &token, 5000).is_err());
+		assert!(redeem_download_token(&keypair.public(), "job.nonce", 5000).is_err());
+
+		// A token never outlives its job
+		let (_, expiry_block) = issue_download_token(&keypair, &job, job.expiry_block - 1).unwrap();
+		assert_eq!(expiry_block, job.expiry_block);
+	}
+}
@@ -0,0 +1,340 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::{
+	extract::State,
+	http::{header::RETRY_AFTER, HeaderValue, Method, Request, StatusCode},
+	middleware::Next,
+	response::{IntoResponse, Response},
+	Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{debug, error, info, warn};
+
+use crate::{
+	chain::constants::{MAINTENANCE_FILE, MAINTENANCE_RETRY_AFTER, MAX_MAINTENANCE_DURATION},
+	servers::{
+		auth::VerifiedCaller,
+		coordination::{operation_intent, OperationIntent},
+		state::{get_blocknumber, get_maintenance_window, set_maintenance_window, SharedState},
+		versioning::endpoint_path,
+	},
+};
+
+/* *************************************
+	ADMIN MAINTENANCE WINDOW
+**************************************** */
+
+// Operators announce their maintenance to the clients instead of failing their requests :
+// - the window is enabled and lifted by the admin quorum, with a message and an expected duration
+// - keyshare requests get "503 Service Unavailable" with the maintenance notice and a Retry-After
+//   header until the expected end of the window
+// - retrievals and the other read endpoints are still served when the window exempts them
+// - probes, quote, admin, synchronization and metric endpoints are never blocked, the window can be
+//   lifted and the backups run during the maintenance
+// The window is not lifted at its expected end, an overdue window asks to retry every minute.
+
+// Endpoints which are never blocked by a maintenance window, besides the admin endpoints
+const EXEMPT_ENDPOINTS: [&str; 10] = [
+	"/api/health",
+	"/api/live",
+	"/api/ready",
+	"/api/quote",
+	"/api/identity",
+	"/api/attest",
+	"/api/attest/history",
+	"/api/attest/inspect",
+	"/api/attest/self-test",
+	"/api/capabilities",
+];
+
+/// Maintenance window of the operator
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MaintenanceWindow {
+	pub message: String,
+	pub activation_block: u32,
+	// Unix timestamps in seconds
+	pub started_at: u64,
+	pub expected_end: u64,
+	// Read endpoints are served during the window
+	pub exempt_reads: bool,
+}
+
+impl MaintenanceWindow {
+	/// Seconds a client waits before retrying, until the expected end of the window
+	pub fn retry_after(&self, now: u64) -> u64 {
+		match self.expected_end.checked_sub(now) {
+			Some(remaining) if remaining > 0 => remaining,
+			_ => MAINTENANCE_RETRY_AFTER,
+		}
+	}
+
+	/// Whether the window rejects an endpoint
+	/// # Arguments
+	/// * `method` - method of the request
+	/// * `path` - unversioned route pattern of the request
+	pub fn is_blocked(&self, method: &Method, path: &str) -> bool {
+		if path.starts_with("/api/backup/") ||
+			path.starts_with("/api/admin/") ||
+			path.starts_with("/api/metric/") ||
+			EXEMPT_ENDPOINTS.contains(&path)
+		{
+			return false
+		}
+
+		let is_read = match operation_intent(path) {
+			Some(intent) => intent == OperationIntent::READ,
+			None => method == Method::GET || path == "/api/access-check",
+		};
+
+		!(is_read && self.exempt_reads)
+	}
+
+	/// Maintenance notice returned to the clients
+	pub fn notice(&self, now: u64) -> serde_json::Value {
+		json!({
+			"message": self.message,
+			"started_at": self.started_at,
+			"expected_end": self.expected_end,
+			"retry_after": self.retry_after(now),
+			"exempt_reads": self.exempt_reads,
+		})
+	}
+}
+
+/// Maintenance request, signed by the threshold of admin quorum
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MaintenancePacket {
+	enable: bool,
+	// Seconds, ignored when lifting the window
+	expected_duration: u64,
+	#[serde(default)]
+	exempt_reads: bool,
+	message: String,
+}
+
+/// Canonical hash of the window parameters, signed inside the authentication token
+/// # Arguments
+/// * `enable` - enable or lift the window
+/// * `expected_duration` - expected seconds of the maintenance
+/// * `exempt_reads` - serve the read endpoints during the window
+/// * `message` - message of the operator to the clients
+pub fn maintenance_data_hash(
+	enable: bool,
+	expected_duration: u64,
+	exempt_reads: bool,
+	message: &str,
+) -> String {
+	sha256::digest(
+		format!("maintenance_{}_{}_{}_{}", enable, expected_duration, exempt_reads, message)
+			.as_bytes(),
+	)
+}
+
+fn current_time() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|elapsed| elapsed.as_secs())
+		.unwrap_or(0)
+}
+
+/// Middleware returning the maintenance notice on the endpoints blocked by the window
+pub async fn maintenance_guard<B>(
+	State(state): State<SharedState>,
+	request: Request<B>,
+	next: Next<B>,
+) -> Response {
+	let window = match get_maintenance_window(&state).await {
+		Some(window) => window,
+		None => return next.run(request).await,
+	};
+
+	let path = endpoint_path(&request);
+	if !window.is_blocked(request.method(), &path) {
+		return next.run(request).await
+	}
+
+	debug!("MAINTENANCE : rejected {} during the maintenance window", path);
+
+	let now = current_time();
+	let mut response = (
+		StatusCode::SERVICE_UNAVAILABLE,
+		Json(json!({
+			"error": format!("Enclave API is under maintenance : {}", window.message),
+			"maintenance": window.notice(now),
+		})),
+	)
+		.into_response();
+
+	response
+		.headers_mut()
+		.insert(RETRY_AFTER, HeaderValue::from(window.retry_after(now)));
+	response
+}
+
+/* *************************************
+		 PERSISTENCE
+**************************************** */
+
+/// Load the persisted maintenance window at startup
+pub async fn load_maintenance_window(state: &SharedState) -> Result<(), anyhow::Error> {
+	if !std::path::Path::new(MAINTENANCE_FILE).exists() {
+		return Ok(())
+	}
+
+	let window: MaintenanceWindow =
+		serde_json::from_str(&std::fs::read_to_string(MAINTENANCE_FILE)?)?;
+	warn!("MAINTENANCE : enclave starts in a maintenance window : {}", window.message);
+
+	set_maintenance_window(state, Some(window)).await;
+
+	Ok(())
+}
+
+fn save_maintenance_window(window: &Option<MaintenanceWindow>) -> Result<(), anyhow::Error> {
+	match window {
+		Some(window) => std::fs::write(MAINTENANCE_FILE, serde_json::to_string(window)?)?,
+		None =>
+			if std::path::Path::new(MAINTENANCE_FILE).exists() {
+				std::fs::remove_file(MAINTENANCE_FILE)?
+			},
+	}
+
+	Ok(())
+}
+
+/* *************************************
+		 MAINTENANCE API
+**************************************** */
+
+/// Maintenance window status
+pub async fn admin_maintenance_status(State(state): State<SharedState>) -> impl IntoResponse {
+	let window = get_maintenance_window(&state).await;
+
+	(
+		StatusCode::OK,
+		Json(json!({
+			"active": window.is_some(),
+			"maintenance": window.map(|window| window.notice(current_time())),
+		})),
+	)
+}
+
+/// Enable or lift the maintenance window
+/// The request must be signed by the threshold of admin quorum
+/// # Arguments
+/// * `state` - SharedState
+/// * `request` - MaintenancePacket
+#[axum::debug_handler]
+pub async fn admin_maintenance_switch(
+	State(state): State<SharedState>,
+	caller: VerifiedCaller,
+	Json(request): Json<MaintenancePacket>,
+) -> impl IntoResponse {
+	debug!("ADMIN MAINTENANCE : start");
+
+	let data_hash = maintenance_data_hash(
+		request.enable,
+		request.expected_duration,
+		request.exempt_reads,
+		&request.message,
+	);
+	if let Err((status, message)) = caller.verify_data_hash(&data_hash) {
+		let message = format!("ADMIN MAINTENANCE : {message}");
+		warn!(message);
+		return (status, Json(json!({ "error": message })))
+	}
+
+	if request.enable &&
+		(request.expected_duration == 0 || request.expected_duration > MAX_MAINTENANCE_DURATION)
+	{
+		let message = format!(
+			"ADMIN MAINTENANCE : expected duration must be between 1 and {} seconds",
+			MAX_MAINTENANCE_DURATION
+		);
+		warn!(message);
+		return (StatusCode::BAD_REQUEST, Json(json!({ "error": message })))
+	}
+
+	let approvals = caller.approvals();
+	let now = current_time();
+
+	let window = if request.enable {
+		Some(MaintenanceWindow {
+			message: request.message,
+			activation_block: get_blocknumber(&state).await,
+			started_at: now,
+			expected_end: now + request.expected_duration,
+			exempt_reads: request.exempt_reads,
+		})
+	} else {
+		None
+	};
+
+	if let Err(err) = save_maintenance_window(&window) {
+		let message = format!("ADMIN MAINTENANCE : error saving maintenance file : {err:?}");
+		error!(message);
+		return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })))
+	}
+
+	set_maintenance_window(&state, window.clone()).await;
+
+	match &window {
+		Some(window) => info!(
+			"ADMIN MAINTENANCE : maintenance window until {} with {} approvals : {}",
+			window.expected_end, approvals, window.message
+		),
+		None =>
+			info!("ADMIN MAINTENANCE : maintenance window is lifted with {} approvals", approvals),
+	}
+
+	(
+		StatusCode::OK,
+		Json(json!({
+			"maintenance": window.map(|window| window.notice(now)),
+			"approvals": approvals,
+		})),
+	)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn maintenance_window_test() {
+		let window = MaintenanceWindow {
+			message: "Storage migration".to_string(),
+			activation_block: 100,
+			started_at: 1_000,
+			expected_end: 4_600,
+			exempt_reads: true,
+		};
+
+		assert_eq!(window.retry_after(1_000), 3_600);
+		assert_eq!(window.retry_after(4_600), MAINTENANCE_RETRY_AFTER);
+		assert_eq!(window.retry_after(9_000), MAINTENANCE_RETRY_AFTER);
+
+		let store = "/api/secret-nft/store-keyshare";
+		let retrieve = "/api/secret-nft/retrieve-keyshare";
+		let views = "/api/secret-nft/get-views-log/:nft_id";
+
+		assert!(window.is_blocked(&Method::POST, store));
+		assert!(!window.is_blocked(&Method::POST, retrieve));
+		assert!(!window.is_blocked(&Method::GET, views));
+		assert!(!window.is_blocked(&Method::POST, "/api/backup/maintenance"));
+		assert!(!window.is_blocked(&Method::GET, "/api/health"));
+
+		let window = MaintenanceWindow { exempt_reads: false, ..window };
+		assert!(window.is_blocked(&Method::POST, retrieve));
+		assert!(window.is_blocked(&Method::GET, views));
+		assert!(!window.is_blocked(&Method::POST, "/api/backup/push-bulk"));
+
+		// Every parameter is bound to the signed token
+		let hash = maintenance_data_hash(true, 3600, true, "Storage migration");
+		assert_ne!(hash, maintenance_data_hash(false, 3600, true, "Storage migration"));
+		assert_ne!(hash, maintenance_data_hash(true, 7200, true, "Storage migration"));
+		assert_ne!(hash, maintenance_data_hash(true, 3600, false, "Storage migration"));
+		assert_ne!(hash, maintenance_data_hash(true, 3600, true, "Upgrade"));
+	}
+}
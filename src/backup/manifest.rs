@@ -0,0 +1,494 @@
+use std::{
+	collections::BTreeSet,
+	fs::{self, File},
+	io,
+	path::Path,
+	str::FromStr,
+};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use subxt::{
+	ext::sp_core::{sr25519, Pair},
+	utils::AccountId32,
+};
+use tracing::{debug, error, warn};
+
+use crate::{
+	attestation::ra::local_mrenclave,
+	chain::{
+		helper::{parse_keyshare_file, NftType},
+		integrity::check_integrity,
+	},
+	servers::state::{get_accountid, get_blocknumber, get_clusters, get_keypair, SharedState},
+};
+
+use super::sync::verify_signature;
+
+/* ---------------------------------------
+	SIGNED BACKUP MANIFEST
+--------------------------------------- */
+
+// Manifest of every backup archive, it has no keyshare extension
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Files of a backup archive
+#[derive(Debug, Clone, PartialEq)]
+pub enum BackupSelection {
+	// Whole sealed directory, with the enclave account
+	ALL,
+	// Keyshares of the nft_ids
+	NFTIDS(Vec<u32>),
+	// Keyshares stored or synced since the block
+	SINCE(u32),
+}
+
+/// File of a backup archive
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ManifestEntry {
+	pub file: String,
+	// None for the enclave account, logs and state files
+	pub nft_id: Option<u32>,
+	pub nft_type: Option<NftType>,
+	// Block of the keyshare file name, when it has been stored or synced
+	pub block_number: Option<u32>,
+	pub size: u64,
+	pub sha256: String,
+}
+
+/// Reason of a requested nft without keyshare in the archive
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum NotFoundReason {
+	// The nft has no keyshare in the sealed directory
+	NOTSTORED,
+	// The nft does not exist onchain, its keyshares are not archived
+	NOTONCHAIN,
+}
+
+/// Requested nft which is not in the archive
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct NotFoundEntry {
+	pub nft_id: u32,
+	pub reason: NotFoundReason,
+}
+
+/// Manifest of a backup archive, signed by the enclave account
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BackupManifest {
+	pub enclave_account: String,
+	// Hex encoded MRENCLAVE of the enclave, None outside of an enclave
+	pub mrenclave: Option<String>,
+	// Creation block, the `since_block` of the next incremental backup
+	pub block_number: u32,
+	// Incremental backup of the keyshares stored or synced since this block
+	pub since_block: Option<u32>,
+	pub nft_ids: Vec<u32>,
+	pub entries: Vec<ManifestEntry>,
+	// Requested nfts without keyshare in the archive, omitted when empty so that the signature
+	// of older manifests is unchanged
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub not_found: Vec<NotFoundEntry>,
+	// Signature of "backup-manifest_SHA256(MANIFEST)", the manifest with an empty signature
+	#[serde(default)]
+	pub signature: String,
+}
+
+pub fn file_digest(path: &Path) -> Result<String, io::Error> {
+	let mut hasher = Sha256::new();
+	io::copy(&mut File::open(path)?, &mut hasher)?;
+	Ok(hex::encode(hasher.finalize()))
+}
+
+impl BackupManifest {
+	fn message(&self) -> Result<String, anyhow::Error> {
+		let unsigned = BackupManifest { signature: String::new(), ..self.clone() };
+		let digest = sha256::digest(serde_json::to_vec(&unsigned)?.as_slice());
+		Ok(format!("backup-manifest_{digest}"))
+	}
+
+	/// Sign the manifest by the enclave account
+	pub fn sign(&mut self, keypair: &sr25519::Pair) -> Result<(), anyhow::Error> {
+		let signature = keypair.sign(self.message()?.as_bytes());
+		self.signature = format!("{}{:?}", "0x", signature);
+		Ok(())
+	}
+
+	/// Check the signature of the enclave account of the manifest
+	pub fn verify(&self) -> bool {
+		match self.message() {
+			Ok(message) =>
+				verify_signature(&self.enclave_account, self.signature.clone(), message.as_bytes()),
+			Err(_) => false,
+		}
+	}
+
+	/// Manifest of the keyshares of the nft_ids only, for a selective restore
+	/// Its signature still covers the whole manifest, it must be verified before.
+	pub fn restricted(&self, nft_ids: &BTreeSet<u32>) -> BackupManifest {
+		let selected = |nft_id: &Option<u32>| nft_id.map_or(false, |id| nft_ids.contains(&id));
+
+		BackupManifest {
+			nft_ids: self.nft_ids.iter().copied().filter(|id| nft_ids.contains(id)).collect(),
+			entries: self.entries.iter().filter(|e| selected(&e.nft_id)).cloned().collect(),
+			not_found: self
+				.not_found
+				.iter()
+				.filter(|e| nft_ids.contains(&e.nft_id))
+				.cloned()
+				.collect(),
+			..self.clone()
+		}
+	}
+}
+
+/// Check if a file of an archive is extracted by a selective restore
+/// The manifest and the keyshares of the nft_ids are extracted, not the enclave account.
+pub fn is_selected_file(name: &str, nft_ids: &BTreeSet<u32>) -> bool {
+	name == MANIFEST_FILE ||
+		parse_keyshare_file(Path::new(name))
+			.map_or(false, |(nft_id, _)| nft_ids.contains(&nft_id))
+}
+
+/// List and hash the files of a backup archive
+/// # Arguments
+/// * `dir_path` - sealed directory
+/// * `selection` - files of the archive
+/// * `enclave_account` - account which signs the manifest
+/// * `block_number` - current block number
+/// # Returns
+/// * `BackupManifest` - unsigned manifest, entries sorted by file name
+pub fn build_manifest(
+	dir_path: &str,
+	selection: &BackupSelection,
+	enclave_account: String,
+	block_number: u32,
+) -> Result<BackupManifest, anyhow::Error> {
+	let dir_iterator = fs::read_dir(dir_path)
+		.map_err(|err| anyhow!("BACKUP MANIFEST : error reading sealed directory {err:?}"))?;
+
+	let mut entries = Vec::new();
+
+	for direntry in dir_iterator {
+		let path = direntry?.path();
+
+		// Sub-directories and files without extension are not backed up
+		if !path.is_file() || path.extension().is_none() {
+			continue
+		}
+
+		let keyshare = parse_keyshare_file(&path).ok();
+
+		let selected = match (selection, keyshare) {
+			(BackupSelection::ALL, _) => true,
+			// Capsules waiting to be synced have a zero block
+			(_, Some((_, av))) if av.block_number == 0 => false,
+			(BackupSelection::NFTIDS(nft_ids), Some((nft_id, _))) => nft_ids.contains(&nft_id),
+			(BackupSelection::SINCE(since_block), Some((_, av))) => av.block_number >= *since_block,
+			(_, None) => false,
+		};
+
+		if !selected {
+			continue
+		}
+
+		let file = match path.file_name().and_then(std::ffi::OsStr::to_str) {
+			Some(name) => name.to_string(),
+			None => continue,
+		};
+
+		entries.push(ManifestEntry {
+			file,
+			nft_id: keyshare.map(|(nft_id, _)| nft_id),
+			nft_type: keyshare.map(|(_, av)| av.nft_type),
+			block_number: keyshare.map(|(_, av)| av.block_number),
+			size: path.metadata()?.len(),
+			sha256: file_digest(&path)?,
+		});
+	}
+
+	entries.sort_by(|a, b| a.file.cmp(&b.file));
+
+	let nft_ids: BTreeSet<u32> = entries.iter().filter_map(|entry| entry.nft_id).collect();
+
+	debug!("BACKUP MANIFEST : {} files of {} nfts", entries.len(), nft_ids.len());
+
+	Ok(BackupManifest {
+		enclave_account,
+		mrenclave: local_mrenclave(),
+		block_number,
+		since_block: match selection {
+			BackupSelection::SINCE(since_block) => Some(*since_block),
+			_ => None,
+		},
+		nft_ids: nft_ids.into_iter().collect(),
+		entries,
+		not_found: Vec::new(),
+		signature: String::new(),
+	})
+}
+
+/// Build the manifest of a backup archive, signed by the enclave account
+/// # Arguments
+/// * `dir_path` - sealed directory
+/// * `selection` - files of the archive
+/// * `not_found` - requested nfts without keyshare in the archive
+pub async fn signed_manifest(
+	state: &SharedState,
+	dir_path: &str,
+	selection: &BackupSelection,
+	not_found: Vec<NotFoundEntry>,
+) -> Result<BackupManifest, anyhow::Error> {
+	let enclave_account = get_accountid(state).await;
+	let block_number = get_blocknumber(state).await;
+
+	let mut manifest = build_manifest(dir_path, selection, enclave_account, block_number)?;
+	manifest.not_found = not_found;
+	manifest.sign(&get_keypair(state).await)?;
+
+	Ok(manifest)
+}
+
+/// Check the files of an extracted archive against its manifest
+/// # Arguments
+/// * `staging_path` - directory the archive has been extracted to
+/// * `nft_ids` - nfts of a selective restore, only their keyshares have been extracted
+/// # Returns
+/// * `BackupManifest` - manifest with a valid signature, its files are not installed yet,
+///   restricted to the selected nfts
+/// # Errors
+/// * Unsigned archive, invalid signature, missing, altered or unlisted files
+pub fn check_archive(
+	staging_path: &str,
+	nft_ids: Option<&BTreeSet<u32>>,
+) -> Result<BackupManifest, anyhow::Error> {
+	let manifest_path = Path::new(staging_path).join(MANIFEST_FILE);
+	let manifest = fs::read(&manifest_path)
+		.map_err(|err| anyhow!("BACKUP MANIFEST : archive without manifest : {err:?}"))?;
+
+	let manifest: BackupManifest = serde_json::from_slice(&manifest)
+		.map_err(|err| anyhow!("BACKUP MANIFEST : invalid manifest : {err:?}"))?;
+
+	if !manifest.verify() {
+		return Err(anyhow!(
+			"BACKUP MANIFEST : invalid signature of enclave {}",
+			manifest.enclave_account
+		))
+	}
+
+	let manifest = match nft_ids {
+		Some(nft_ids) => manifest.restricted(nft_ids),
+		None => manifest,
+	};
+
+	let mut listed = BTreeSet::new();
+	for entry in &manifest.entries {
+		// Plain file names only, the files are moved to the sealed directory
+		if Path::new(&entry.file).file_name().and_then(std::ffi::OsStr::to_str) !=
+			Some(entry.file.as_str()) ||
+			entry.file == MANIFEST_FILE
+		{
+			return Err(anyhow!("BACKUP MANIFEST : invalid file name {}", entry.file))
+		}
+
+		let digest = file_digest(&Path::new(staging_path).join(&entry.file))
+			.map_err(|err| anyhow!("BACKUP MANIFEST : missing file {} : {err:?}", entry.file))?;
+
+		if digest != entry.sha256 {
+			return Err(anyhow!("BACKUP MANIFEST : digest mismatch of {}", entry.file))
+		}
+
+		listed.insert(entry.file.clone());
+	}
+
+	for direntry in fs::read_dir(staging_path)? {
+		let name = direntry?.file_name().to_string_lossy().to_string();
+		if name != MANIFEST_FILE && !listed.contains(&name) {
+			return Err(anyhow!("BACKUP MANIFEST : unlisted file {name}"))
+		}
+	}
+
+	Ok(manifest)
+}
+
+/// Check that the manifest is signed by this enclave or an enclave of the clusters
+pub async fn verify_manifest_signer(
+	state: &SharedState,
+	manifest: &BackupManifest,
+) -> Result<(), anyhow::Error> {
+	if let (Some(source), Some(local)) = (&manifest.mrenclave, local_mrenclave()) {
+		if *source != local {
+			// An upgraded binary restores the archives of the previous one
+			warn!("BACKUP MANIFEST : archive of MRENCLAVE {source}, this enclave is {local}");
+		}
+	}
+
+	if manifest.enclave_account == get_accountid(state).await {
+		return Ok(())
+	}
+
+	let account = AccountId32::from_str(&manifest.enclave_account)
+		.map_err(|err| anyhow!("BACKUP MANIFEST : invalid enclave account : {err:?}"))?;
+
+	let registered = get_clusters(state)
+		.await
+		.iter()
+		.flat_map(|cluster| cluster.enclaves.iter())
+		.any(|enclave| enclave.enclave_account == account);
+
+	if registered {
+		Ok(())
+	} else {
+		Err(anyhow!(
+			"BACKUP MANIFEST : archive of enclave {} which is not registered",
+			manifest.enclave_account
+		))
+	}
+}
+
+/// Move the checked files of an archive to the sealed directory
+/// The restored keyshares shadow the older files of the same nft, which are removed.
+/// # Arguments
+/// * `staging_path` - directory the archive has been extracted to, it is removed
+/// * `dir_path` - sealed directory
+/// * `manifest` - checked manifest of the archive
+pub fn install_archive(
+	staging_path: &str,
+	dir_path: &str,
+	manifest: &BackupManifest,
+) -> Result<(), anyhow::Error> {
+	for entry in &manifest.entries {
+		fs::rename(
+			Path::new(staging_path).join(&entry.file),
+			Path::new(dir_path).join(&entry.file),
+		)
+		.map_err(|err| anyhow!("BACKUP MANIFEST : error installing {} : {err:?}", entry.file))?;
+	}
+
+	if let Err(err) = fs::remove_dir_all(staging_path) {
+		error!("BACKUP MANIFEST : error removing the staging directory : {err:?}");
+	}
+
+	match check_integrity(dir_path, true) {
+		Ok(report) =>
+			if !report.repaired.is_empty() {
+				debug!("BACKUP MANIFEST : removed {:?}", report.repaired)
+			},
+		Err(err) => warn!("BACKUP MANIFEST : integrity check failed : {err:?}"),
+	}
+
+	Ok(())
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn manifest_test() {
+		let dir = std::env::temp_dir().join(format!("backup-manifest-{}", std::process::id()));
+		let staging = dir.join("restore");
+		let _ = fs::remove_dir_all(&dir);
+		fs::create_dir_all(&staging).unwrap();
+		let dir_path = dir.to_str().unwrap();
+		let staging_path = staging.to_str().unwrap();
+
+		fs::write(dir.join("nft_10_100.keyshare"), b"old").unwrap();
+		fs::write(dir.join("nft_20_300.keyshare"), b"new").unwrap();
+		fs::write(dir.join("capsule_30_0.keyshare"), b"unsynced").unwrap();
+		fs::write(dir.join("capsule_40_250.keyshare"), b"capsule").unwrap();
+		fs::write(dir.join("enclave_account.key"), b"phrase").unwrap();
+
+		let keypair = sr25519::Pair::from_seed(&[7u8; 32]);
+		let account = keypair.public().to_string();
+
+		let since =
+			build_manifest(dir_path, &BackupSelection::SINCE(200), account.clone(), 400).unwrap();
+		let files: Vec<&str> = since.entries.iter().map(|e| e.file.as_str()).collect();
+		assert_eq!(files, vec!["capsule_40_250.keyshare", "nft_20_300.keyshare"]);
+		assert_eq!(since.nft_ids, vec![20, 40]);
+		assert_eq!(since.since_block, Some(200));
+		assert_eq!(since.entries[1].sha256, sha256::digest("new".as_bytes()));
+
+		let ids =
+			build_manifest(dir_path, &BackupSelection::NFTIDS(vec![10, 30]), account.clone(), 400)
+				.unwrap();
+		assert_eq!(ids.nft_ids, vec![10]);
+
+		let all = build_manifest(dir_path, &BackupSelection::ALL, account.clone(), 400).unwrap();
+		assert_eq!(all.entries.len(), 5);
+		assert_eq!(all.entries[2].nft_id, None);
+		// Manifests without not-found nfts serialize as before
+		assert!(!serde_json::to_string(&all).unwrap().contains("not_found"));
+
+		// Restore a newer keyshare of nft 10 over the old one
+		fs::write(staging.join("nft_10_350.keyshare"), b"newer").unwrap();
+		let mut manifest = BackupManifest {
+			enclave_account: account,
+			mrenclave: None,
+			block_number: 400,
+			since_block: Some(300),
+			nft_ids: vec![10],
+			entries: vec![ManifestEntry {
+				file: "nft_10_350.keyshare".to_string(),
+				nft_id: Some(10),
+				nft_type: Some(NftType::Secret),
+				block_number: Some(350),
+				size: 5,
+				sha256: sha256::digest("newer".as_bytes()),
+			}],
+			not_found: Vec::new(),
+			signature: String::new(),
+		};
+		let write_manifest = |manifest: &BackupManifest| {
+			fs::write(staging.join(MANIFEST_FILE), serde_json::to_vec(manifest).unwrap()).unwrap()
+		};
+
+		// Unsigned
+		write_manifest(&manifest);
+		assert!(check_archive(staging_path, None).is_err());
+
+		manifest.sign(&keypair).unwrap();
+		assert!(manifest.verify());
+		write_manifest(&manifest);
+		assert_eq!(check_archive(staging_path, None).unwrap(), manifest);
+
+		// Unlisted and altered files
+		fs::write(staging.join("nft_50_350.keyshare"), b"unlisted").unwrap();
+		assert!(check_archive(staging_path, None).is_err());
+		fs::remove_file(staging.join("nft_50_350.keyshare")).unwrap();
+		fs::write(staging.join("nft_10_350.keyshare"), b"tampered").unwrap();
+		assert!(check_archive(staging_path, None).is_err());
+		fs::write(staging.join("nft_10_350.keyshare"), b"newer").unwrap();
+
+		// Altered manifest
+		let mut altered = manifest.clone();
+		altered.nft_ids.push(20);
+		write_manifest(&altered);
+		assert!(check_archive(staging_path, None).is_err());
+		write_manifest(&manifest);
+
+		// Selective restore of another nft
+		let other = BTreeSet::from([20]);
+		assert!(!is_selected_file("nft_10_350.keyshare", &other));
+		assert!(is_selected_file(MANIFEST_FILE, &other));
+		assert!(!is_selected_file("enclave_account.key", &other));
+		fs::rename(staging.join("nft_10_350.keyshare"), dir.join("selected.tmp")).unwrap();
+		let selected = check_archive(staging_path, Some(&other)).unwrap();
+		assert!(selected.entries.is_empty() && selected.nft_ids.is_empty());
+		fs::rename(dir.join("selected.tmp"), staging.join("nft_10_350.keyshare")).unwrap();
+
+		let checked = check_archive(staging_path, Some(&BTreeSet::from([10]))).unwrap();
+		assert_eq!(checked, manifest);
+		install_archive(staging_path, dir_path, &checked).unwrap();
+		assert!(!staging.exists());
+		assert!(!dir.join("nft_10_100.keyshare").exists());
+		assert_eq!(fs::read(dir.join("nft_10_350.keyshare")).unwrap(), b"newer");
+
+		fs::remove_dir_all(&dir).unwrap();
+	}
+}
@@ -1,23 +1,42 @@
 use crate::{
 	backup::sync::ValidationResult,
 	chain::{
-		constants::{MAX_BLOCK_VARIATION, MAX_VALIDATION_PERIOD},
+		compression::compression_stats,
+		constants::{
+			MAX_BLOCK_VARIATION, MAX_RESOURCE_CONSUMERS, MAX_SCAN_INTERVAL, MAX_VALIDATION_PERIOD,
+		},
 		core::{get_metric_server, MetricServer},
+		negative_cache::negative_cache_stats,
+		quota::{ban_policy, ip_guard_stats, ip_rate_limit},
+		scanner::{
+			create_scan_job, get_scan_job, prune_scan_jobs, scan_block_range, update_scan_job,
+			valid_scan_job_id, ScanStatus,
+		},
+	},
+	servers::{
+		resources::{largest_consumers, resource_alert_config, resource_snapshot},
+		state::{
+			get_blocknumber, get_compression_threshold, get_quota_stats, get_runtime_config,
+			set_processed_block, SharedState,
+		},
 	},
-	servers::state::{get_blocknumber, set_processed_block, SharedState},
 };
-use axum::{extract::State, response::IntoResponse, Json};
+use axum::{
+	extract::{Path as UrlPath, State},
+	response::IntoResponse,
+	Json,
+};
 use hex::{FromHex, FromHexError};
 use hyper::StatusCode;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
+use serde_json::{json, Value};
 use subxt::ext::sp_core::{
 	crypto::{PublicError, Ss58Codec},
 	sr25519::{Public, Signature},
 	Pair,
 };
 
-use tracing::{debug, error};
+use tracing::{debug, error, info};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct AuthenticationToken {
@@ -149,6 +168,60 @@ pub async fn error_handler(message: String, _state: &SharedState) -> impl IntoRe
 	(StatusCode::BAD_REQUEST, Json(json!({ "error": message })))
 }
 
+/* --------------------
+ METRIC COMPRESSION
+--------------------*/
+/// Storage savings and CPU cost of at-rest keyshare compression, to tune the threshold
+pub async fn metric_compression(State(state): State<SharedState>) -> impl IntoResponse {
+	let threshold = get_compression_threshold(&state).await;
+
+	(StatusCode::OK, Json(json!({ "threshold": threshold, "stats": compression_stats() })))
+}
+
+/* --------------------
+ METRIC NEGATIVE CACHE
+--------------------*/
+/// Hits and saved rpc calls of the negative onchain lookup cache
+pub async fn metric_negative_cache() -> impl IntoResponse {
+	(StatusCode::OK, Json(json!({ "stats": negative_cache_stats() })))
+}
+
+/* --------------------
+ METRIC RATE LIMIT
+--------------------*/
+/// Token buckets and limited requests of the quota subsystem
+pub async fn metric_quota(State(state): State<SharedState>) -> impl IntoResponse {
+	(
+		StatusCode::OK,
+		Json(json!({
+			"limits": get_runtime_config(&state).await.rate_limits,
+			"stats": get_quota_stats(&state).await,
+			"ip_rate_limit": ip_rate_limit(),
+			"signature_ban": ban_policy(),
+			"ip_guard": ip_guard_stats(),
+		})),
+	)
+}
+
+/* --------------------
+ METRIC RESOURCES
+--------------------*/
+/// Open file descriptors, limits, threads and supervised tasks of the enclave process
+pub async fn metric_resources(State(state): State<SharedState>) -> impl IntoResponse {
+	(
+		StatusCode::OK,
+		Json(json!({
+			"fd_alert_percent": resource_alert_config().fd_threshold_percent,
+			"resources": resource_snapshot(&state).await,
+		})),
+	)
+}
+
+/// Largest groups of open file descriptors, by directory, socket state or kind
+pub async fn metric_resource_consumers() -> impl IntoResponse {
+	(StatusCode::OK, Json(json!({ "consumers": largest_consumers(MAX_RESOURCE_CONSUMERS) })))
+}
+
 /* --------------------
  METRIC GET NFT LIST
 --------------------*/
@@ -251,17 +324,102 @@ pub async fn metric_reconcilliation(
 		return error_handler(message, &state).await.into_response()
 	}
 
-	let shared_state_read = state.read().await;
-	let nft_list = shared_state_read.get_nft_availability_map();
-	let nftid: Vec<u32> = nft_list
-		.into_iter()
-		.filter(|(_, v)| {
-			v.block_number > interval[0] && v.block_number < interval[1] && v.block_number > 0
-		})
-		.map(|(k, _)| k)
-		.collect();
-
-	(StatusCode::OK, Json(json!({ "nftid": nftid }))).into_response()
+	if interval[1] - interval[0] > MAX_SCAN_INTERVAL {
+		let message = format!(
+			"METRIC GET NFT LIST : Error : Block interval is longer than {MAX_SCAN_INTERVAL} blocks"
+		);
+		return error_handler(message, &state).await.into_response()
+	}
+
+	// NFTs that should be held are scanned from on-chain sync events in the background
+	let job = match create_scan_job(interval[0], interval[1], current_block_number) {
+		Ok(job) => job,
+		Err(err) => {
+			let message = format!("METRIC GET NFT LIST : {err}");
+			return (StatusCode::TOO_MANY_REQUESTS, Json(json!({ "error": message })))
+				.into_response()
+		},
+	};
+
+	tokio::spawn(run_reconciliation(state.clone(), job.job_id.clone(), interval[0], interval[1]));
+
+	(
+		StatusCode::ACCEPTED,
+		Json(json!({
+			"job_id": job.job_id,
+			"status_url": format!("/api/metric/interval-nft-list/{}", job.job_id),
+			"expiry_block": job.expiry_block,
+		})),
+	)
+		.into_response()
+}
+
+/// Scan the interval of a reconciliation job and keep its report in the job
+async fn run_reconciliation(state: SharedState, job_id: String, from_block: u32, to_block: u32) {
+	let scanned = scan_block_range(&state, from_block, to_block, |blocks_scanned| {
+		update_scan_job(&job_id, |job| job.blocks_scanned = blocks_scanned)
+	})
+	.await;
+
+	match scanned {
+		Ok(expected) => {
+			let nft_list = {
+				let shared_state_read = state.read().await;
+				shared_state_read.get_nft_availability_map()
+			};
+
+			let nftid: Vec<u32> = nft_list
+				.iter()
+				.filter(|(_, v)| {
+					v.block_number > from_block && v.block_number < to_block && v.block_number > 0
+				})
+				.map(|(k, _)| *k)
+				.collect();
+
+			let missing: Vec<u32> =
+				expected.keys().filter(|id| !nft_list.contains_key(id)).copied().collect();
+
+			info!("METRIC GET NFT LIST : scan {job_id} is done, {} missing nfts", missing.len());
+			let report: Value = json!({
+				"nftid": nftid,
+				"expected": expected.keys().collect::<Vec<&u32>>(),
+				"missing": missing,
+			});
+
+			update_scan_job(&job_id, |job| {
+				job.status = ScanStatus::DONE;
+				job.report = Some(report);
+			});
+		},
+		Err(err) => {
+			error!("METRIC GET NFT LIST : Error : Scanning block interval of {job_id} : {err:?}");
+			update_scan_job(&job_id, |job| {
+				job.status = ScanStatus::FAILED;
+				job.error = Some(format!("{err:?}"));
+			});
+		},
+	}
+}
+
+/// Progress of a reconciliation scan, with its report once it is done
+pub async fn metric_reconcilliation_status(
+	State(state): State<SharedState>,
+	UrlPath(job_id): UrlPath<String>,
+) -> impl IntoResponse {
+	if !valid_scan_job_id(&job_id) {
+		let message = format!("METRIC GET NFT LIST : invalid job id {job_id}");
+		return (StatusCode::BAD_REQUEST, Json(json!({ "error": message })))
+	}
+
+	prune_scan_jobs(get_blocknumber(&state).await);
+
+	match get_scan_job(&job_id) {
+		Some(job) => (StatusCode::OK, Json(json!(job))),
+		None => {
+			let message = format!("METRIC GET NFT LIST : unknown job id {job_id}");
+			(StatusCode::NOT_FOUND, Json(json!({ "error": message })))
+		},
+	}
 }
 
 /* --------------------
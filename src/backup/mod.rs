@@ -1,8 +1,26 @@
 /// Backup module
 pub mod admin_bulk;
 pub mod admin_nftid;
+pub mod admins;
+pub mod audit;
+pub mod container;
+pub mod encryption;
+pub mod escrow;
+pub mod inject;
 //pub mod graphql;
+pub mod inventory;
+pub mod jobs;
+pub mod maintenance;
+pub mod manifest;
 pub mod metric;
+pub mod provision;
+pub mod quorum;
+pub mod readonly;
+pub mod recovery;
+pub mod restore;
+pub mod schedule;
 pub mod sync;
 pub mod upgrade;
+pub mod upload;
+pub mod workspace;
 pub mod zipdir;
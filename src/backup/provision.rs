@@ -0,0 +1,391 @@
+use std::collections::BTreeMap;
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{debug, error, info, warn};
+
+use crate::{
+	chain::{
+		constants::{MAX_PROVISION_RANGE, PROVISION_FILE, PROVISION_RATE_FACTOR},
+		negative_cache::invalidate_negative_range,
+		quota::RateLimit,
+		verify::{normalize_address, APICALL},
+	},
+	servers::{
+		auth::VerifiedCaller,
+		state::{
+			get_blocknumber, get_nft_availability_range, get_provision_windows,
+			set_provision_windows, SharedState,
+		},
+	},
+};
+
+/* *************************************
+	PROVISIONING DATA STRUCTURES
+**************************************** */
+
+/// Expected mint event of a launch partner, kept confidential in sealed directory
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ProvisionWindow {
+	pub first_nft_id: u32,
+	pub last_nft_id: u32,
+	// Expected owner accounts of the collection, empty means any owner
+	pub owners: Vec<String>,
+	pub start_block: u32,
+	pub end_block: u32,
+}
+
+/// Registration request, signed by the admin quorum
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ProvisionPacket {
+	window: ProvisionWindow,
+}
+
+/// Coverage report request of the window starting with first_nft_id, signed by the admin quorum
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ProvisionReportPacket {
+	first_nft_id: u32,
+}
+
+/// Store coverage of the expected range
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ProvisionCoverage {
+	pub window: ProvisionWindow,
+	pub phase: String,
+	pub expected: u32,
+	pub stored: u32,
+	pub missing: Vec<u32>,
+}
+
+impl ProvisionWindow {
+	pub fn contains(&self, nft_id: u32) -> bool {
+		self.first_nft_id <= nft_id && nft_id <= self.last_nft_id
+	}
+
+	pub fn is_active(&self, block_number: u32) -> bool {
+		self.start_block <= block_number && block_number <= self.end_block
+	}
+
+	/// True if the owner may store the nfts of the window
+	pub fn expects_owner(&self, owner: &str) -> bool {
+		let owner_account = normalize_address(owner);
+		self.owners.is_empty() ||
+			self.owners.iter().any(|expected| normalize_address(expected) == owner_account)
+	}
+
+	/// Canonical hash of the window, signed inside the authentication token
+	pub fn data_hash(&self) -> String {
+		sha256::digest(
+			format!(
+				"{}_{}_{}_{}_{}",
+				self.first_nft_id,
+				self.last_nft_id,
+				self.owners.join(","),
+				self.start_block,
+				self.end_block
+			)
+			.as_bytes(),
+		)
+	}
+
+	fn validate(&self) -> Result<(), String> {
+		if self.first_nft_id > self.last_nft_id ||
+			self.last_nft_id - self.first_nft_id >= MAX_PROVISION_RANGE
+		{
+			return Err(format!(
+				"invalid nft range [{}, {}], maximum length is {}",
+				self.first_nft_id, self.last_nft_id, MAX_PROVISION_RANGE
+			))
+		}
+
+		if self.start_block > self.end_block {
+			return Err(format!("invalid block window [{}, {}]", self.start_block, self.end_block))
+		}
+
+		if let Some(invalid) = self.owners.iter().find(|owner| normalize_address(owner).is_none()) {
+			return Err(format!("invalid owner account : {invalid}"))
+		}
+
+		Ok(())
+	}
+}
+
+/// Compute the store coverage of a window
+/// # Arguments
+/// * `window` - provisioning window
+/// * `stored_ids` - available nft ids inside the window range
+/// * `block_number` - current block number
+pub fn window_coverage(
+	window: &ProvisionWindow,
+	stored_ids: &[u32],
+	block_number: u32,
+) -> ProvisionCoverage {
+	let phase = if block_number < window.start_block {
+		"scheduled"
+	} else if block_number <= window.end_block {
+		"active"
+	} else {
+		"ended"
+	};
+
+	let missing = (window.first_nft_id..=window.last_nft_id)
+		.filter(|nft_id| stored_ids.binary_search(nft_id).is_err())
+		.collect();
+
+	ProvisionCoverage {
+		window: window.clone(),
+		phase: phase.to_string(),
+		expected: window.last_nft_id - window.first_nft_id + 1,
+		stored: stored_ids.len() as u32,
+		missing,
+	}
+}
+
+/* *************************************
+		 PERSISTENCE
+**************************************** */
+
+/// Load provisioning windows from sealed directory into the state
+pub async fn load_provision_windows(state: &SharedState) -> Result<(), anyhow::Error> {
+	if !std::path::Path::new(PROVISION_FILE).exists() {
+		return Ok(())
+	}
+
+	let content = std::fs::read_to_string(PROVISION_FILE)?;
+	let windows: Vec<ProvisionWindow> = serde_json::from_str(&content)?;
+	info!("PROVISION : {} provisioning windows are loaded", windows.len());
+
+	set_provision_windows(state, windows).await;
+
+	Ok(())
+}
+
+async fn save_provision_windows(state: &SharedState) -> Result<(), anyhow::Error> {
+	let windows = get_provision_windows(state).await;
+	std::fs::write(PROVISION_FILE, serde_json::to_string(&windows)?)?;
+	Ok(())
+}
+
+/* *************************************
+		 PROVISIONED REQUESTS
+**************************************** */
+
+async fn active_provision_windows(state: &SharedState) -> Vec<ProvisionWindow> {
+	let block_number = get_blocknumber(state).await;
+	let mut windows = get_provision_windows(state).await;
+	windows.retain(|window| window.is_active(block_number));
+	windows
+}
+
+/// Check the owner of a keyshare stored during an active window
+/// # Arguments
+/// * `state` - SharedState
+/// * `nft_id` - stored nft id
+/// * `owner` - owner account of the store request
+/// # Returns
+/// * `bool` - false if an active window of the nft expects other owners
+pub async fn is_expected_owner(state: &SharedState, nft_id: u32, owner: &str) -> bool {
+	match active_provision_windows(state)
+		.await
+		.into_iter()
+		.find(|window| window.contains(nft_id) && !window.expects_owner(owner))
+	{
+		Some(window) => {
+			warn!(
+				"PROVISION : nft_id {} of window [{}, {}] is stored by unexpected owner {}",
+				nft_id, window.first_nft_id, window.last_nft_id, owner
+			);
+			false
+		},
+		None => true,
+	}
+}
+
+/// True if the account is a listed owner of an active window, its store limits are raised
+pub async fn is_provisioned_owner(state: &SharedState, owner: &str) -> bool {
+	active_provision_windows(state)
+		.await
+		.iter()
+		.any(|window| !window.owners.is_empty() && window.expects_owner(owner))
+}
+
+/// True if the nft is expected to be minted by an active window
+pub async fn is_provisioned_nft(state: &SharedState, nft_id: u32) -> bool {
+	active_provision_windows(state)
+		.await
+		.iter()
+		.any(|window| window.contains(nft_id))
+}
+
+/// Rate limits of the provisioned owners : the store limits are multiplied by PROVISION_RATE_FACTOR
+pub fn provisioned_rate_limits(
+	limits: &BTreeMap<APICALL, RateLimit>,
+) -> BTreeMap<APICALL, RateLimit> {
+	let mut limits = limits.clone();
+	for call in [APICALL::NFTSTORE, APICALL::CAPSULESET] {
+		if let Some(limit) = limits.get_mut(&call) {
+			limit.burst = limit.burst.saturating_mul(PROVISION_RATE_FACTOR);
+			limit.per_minute = limit.per_minute.saturating_mul(PROVISION_RATE_FACTOR);
+		}
+	}
+	limits
+}
+
+/* *************************************
+		 PROVISIONING API
+**************************************** */
+
+/// Register the expected nft range and owners of a mint event
+/// # Arguments
+/// * `state` - SharedState
+/// * `request` - ProvisionPacket
+#[axum::debug_handler]
+pub async fn admin_provision_register(
+	State(state): State<SharedState>,
+	caller: VerifiedCaller,
+	Json(request): Json<ProvisionPacket>,
+) -> impl IntoResponse {
+	debug!("ADMIN PROVISION REGISTER : start");
+
+	if let Err(message) = request.window.validate() {
+		let message = format!("ADMIN PROVISION REGISTER : {message}");
+		warn!(message);
+		return (StatusCode::BAD_REQUEST, Json(json!({ "error": message })))
+	}
+
+	if let Err((status, message)) = caller.verify_data_hash(&request.window.data_hash()) {
+		let message = format!("ADMIN PROVISION REGISTER : {message}");
+		warn!(message);
+		return (status, Json(json!({ "error": message })))
+	}
+
+	// A window is identified by its first nft id, re-registering replaces it
+	let mut windows = get_provision_windows(&state).await;
+	windows.retain(|window| window.first_nft_id != request.window.first_nft_id);
+	windows.push(request.window.clone());
+	set_provision_windows(&state, windows).await;
+
+	// Not-found verdicts of the range would reject the first stores after the mint
+	let invalidated =
+		invalidate_negative_range(request.window.first_nft_id, request.window.last_nft_id);
+	debug!("ADMIN PROVISION REGISTER : {invalidated} cached verdicts of the range are dropped");
+
+	if let Err(err) = save_provision_windows(&state).await {
+		let message = format!("ADMIN PROVISION REGISTER : error saving provision file : {err:?}");
+		error!(message);
+		return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })))
+	}
+
+	info!(
+		"ADMIN PROVISION REGISTER : nft range [{}, {}] is expected in blocks [{}, {}]",
+		request.window.first_nft_id,
+		request.window.last_nft_id,
+		request.window.start_block,
+		request.window.end_block
+	);
+
+	(StatusCode::OK, Json(json!({ "window": request.window })))
+}
+
+/// Report actual store coverage against the expected range of a window
+/// # Arguments
+/// * `state` - SharedState
+/// * `request` - ProvisionReportPacket
+#[axum::debug_handler]
+pub async fn admin_provision_report(
+	State(state): State<SharedState>,
+	caller: VerifiedCaller,
+	Json(request): Json<ProvisionReportPacket>,
+) -> impl IntoResponse {
+	debug!("ADMIN PROVISION REPORT : start");
+
+	let data_hash = sha256::digest(format!("provision-report_{}", request.first_nft_id).as_bytes());
+	if let Err((status, message)) = caller.verify_data_hash(&data_hash) {
+		let message = format!("ADMIN PROVISION REPORT : {message}");
+		warn!(message);
+		return (status, Json(json!({ "error": message })))
+	}
+
+	let window = match get_provision_windows(&state)
+		.await
+		.into_iter()
+		.find(|window| window.first_nft_id == request.first_nft_id)
+	{
+		Some(window) => window,
+		None => {
+			let message = format!(
+				"ADMIN PROVISION REPORT : no window is registered for nft_id {}",
+				request.first_nft_id
+			);
+			warn!(message);
+			return (StatusCode::NOT_FOUND, Json(json!({ "error": message })))
+		},
+	};
+
+	let stored_ids =
+		get_nft_availability_range(&state, window.first_nft_id, window.last_nft_id).await;
+	let coverage = window_coverage(&window, &stored_ids, get_blocknumber(&state).await);
+
+	(StatusCode::OK, Json(json!(coverage)))
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn window_coverage_test() {
+		let window = ProvisionWindow {
+			first_nft_id: 100,
+			last_nft_id: 104,
+			owners: vec![],
+			start_block: 1000,
+			end_block: 2000,
+		};
+
+		assert!(window.validate().is_ok());
+		assert!(window.contains(104) && !window.contains(105));
+
+		let coverage = window_coverage(&window, &[100, 101, 104], 1500);
+		assert_eq!(coverage.phase, "active");
+		assert_eq!(coverage.expected, 5);
+		assert_eq!(coverage.stored, 3);
+		assert_eq!(coverage.missing, vec![102, 103]);
+
+		assert_eq!(window_coverage(&window, &[], 999).phase, "scheduled");
+		assert_eq!(window_coverage(&window, &[], 2001).phase, "ended");
+
+		let invalid = ProvisionWindow { owners: vec!["xyz".to_string()], ..window };
+		assert!(invalid.validate().is_err());
+	}
+
+	#[test]
+	fn provisioned_owner_test() {
+		let owner = "5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY";
+		let other = "5FHneW46xGXgs5mUiveU4sbTyGBzmstUspZC92UhjJM694ty";
+		let window = ProvisionWindow {
+			first_nft_id: 100,
+			last_nft_id: 104,
+			owners: vec![owner.to_string()],
+			start_block: 1000,
+			end_block: 2000,
+		};
+
+		assert!(window.expects_owner(owner));
+		assert!(!window.expects_owner(other));
+		assert!(ProvisionWindow { owners: vec![], ..window }.expects_owner(other));
+
+		let limits = crate::chain::quota::default_rate_limits();
+		let raised = provisioned_rate_limits(&limits);
+		assert_eq!(
+			raised[&APICALL::NFTSTORE].burst,
+			limits[&APICALL::NFTSTORE].burst * PROVISION_RATE_FACTOR
+		);
+		assert_eq!(
+			raised[&APICALL::CAPSULESET].per_minute,
+			limits[&APICALL::CAPSULESET].per_minute * PROVISION_RATE_FACTOR
+		);
+		assert_eq!(raised[&APICALL::NFTRETRIEVE], limits[&APICALL::NFTRETRIEVE]);
+	}
+}
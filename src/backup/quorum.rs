@@ -0,0 +1,774 @@
+#![allow(dead_code)]
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use hex::{FromHex, FromHexError};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	sync::OnceLock,
+};
+use subxt::ext::sp_core::{
+	crypto::{PublicError, Ss58Codec},
+	sr25519::{self, Signature},
+	Pair,
+};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+	chain::{
+		constants::{
+			MAX_BLOCK_VARIATION, MAX_VALIDATION_PERIOD, MIN_QUORUM_THRESHOLD,
+			QUORUM_ACTIVATION_DELAY, QUORUM_FILE,
+		},
+		core::system_remark_oracle,
+		verify::verify_writable,
+	},
+	servers::{
+		auth::VerifiedCaller,
+		state::{
+			get_accountid, get_blocknumber, get_pending_quorum, get_quorum, set_pending_quorum,
+			set_quorum, SharedState,
+		},
+	},
+};
+
+use super::{
+	admin_bulk::ValidationResult,
+	admins::{admin_accounts, admin_roles, role_of, AdminOperation, AdminRole},
+};
+
+/* *************************************
+	QUORUM DATA STRUCTURES
+**************************************** */
+
+/// Admin quorum : M-of-N admin accounts
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct QuorumConfig {
+	pub members: Vec<String>,
+	pub threshold: u8,
+	pub activation_block: u32,
+}
+
+/// Persisted quorum configuration in sealed directory
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct QuorumFile {
+	pub active: Option<QuorumConfig>,
+	pub pending: Option<QuorumConfig>,
+}
+
+// Validity time of the rotation request
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct QuorumAuthenticationToken {
+	pub block_number: u32,
+	pub block_validation: u32,
+	pub data_hash: String,
+}
+
+/// Quorum rotation request, signed by current quorum members
+#[derive(Serialize, Deserialize, Debug)]
+pub struct QuorumRotatePacket {
+	members: Vec<String>,
+	threshold: u8,
+	auth_token: String,
+	// admin_account -> signature of auth_token
+	signatures: BTreeMap<String, String>,
+}
+
+/// Cancellation of the pending rotation, signed by current quorum members
+#[derive(Serialize, Deserialize, Debug)]
+pub struct QuorumCancelPacket {
+	auth_token: String,
+	// admin_account -> signature of auth_token
+	signatures: BTreeMap<String, String>,
+}
+
+impl QuorumAuthenticationToken {
+	pub fn is_valid(&self, current_block_number: u32) -> ValidationResult {
+		if self.block_number > current_block_number + MAX_BLOCK_VARIATION {
+			// for finalization delay
+			debug!(
+				"current block number = {} < request block number = {}",
+				current_block_number, self.block_number
+			);
+			return ValidationResult::FutureBlockNumber
+		}
+
+		if self.block_validation > MAX_VALIDATION_PERIOD {
+			// A finite validity period
+			debug!(
+				"MAX VALIDATION = {} < block_validation = {}",
+				MAX_VALIDATION_PERIOD, self.block_validation
+			);
+			return ValidationResult::InvalidPeriod
+		}
+
+		if self.block_number + self.block_validation < current_block_number {
+			// validity period
+			debug!(
+				"current block number = {} >> request block number = {}",
+				current_block_number, self.block_number
+			);
+
+			return ValidationResult::ExpiredBlockNumber
+		}
+
+		ValidationResult::Success
+	}
+}
+
+// Domains of the quorum messages, the quorum signature of another request or of another enclave
+// can not be replayed as a rotation or a cancellation
+const QUORUM_ROTATE_TAG: &[u8] = b"ternoa-quorum-rotate:";
+const QUORUM_CANCEL_TAG: &[u8] = b"ternoa-quorum-cancel:";
+
+/// Hash of a quorum message, prefixed by its domain and the enclave account
+fn tagged_hash(tag: &[u8], enclave_account: &str, payload: &str) -> String {
+	let mut message = tag.to_vec();
+	message.extend_from_slice(enclave_account.as_bytes());
+	message.push(b':');
+	message.extend_from_slice(payload.as_bytes());
+
+	sha256::digest(message.as_slice())
+}
+
+/// Canonical hash of the quorum parameters, signed inside the authentication token
+/// # Arguments
+/// * `enclave_account` - account of the enclave whose quorum is rotated
+/// * `members` - admin accounts
+/// * `threshold` - minimum number of signatures
+pub fn quorum_data_hash(enclave_account: &str, members: &[String], threshold: u8) -> String {
+	tagged_hash(QUORUM_ROTATE_TAG, enclave_account, &format!("{}_{}", members.join(","), threshold))
+}
+
+/// Hash of the cancellation of a pending rotation, signed inside the authentication token
+/// # Arguments
+/// * `enclave_account` - account of the enclave whose rotation is cancelled
+/// * `pending` - pending quorum
+pub fn quorum_cancel_hash(enclave_account: &str, pending: &QuorumConfig) -> String {
+	let rotation = quorum_data_hash(enclave_account, &pending.members, pending.threshold);
+	tagged_hash(
+		QUORUM_CANCEL_TAG,
+		enclave_account,
+		&format!("{}_{}", rotation, pending.activation_block),
+	)
+}
+
+/* *************************************
+		 QUORUM POLICY
+**************************************** */
+
+// Before the first rotation, the quorum is every whitelisted admin with the threshold given by
+// "--quorum-threshold" at startup. A quorum with less than MIN_QUORUM_THRESHOLD signatures, at
+// startup, from the sealed file or by a rotation, is refused unless "--allow-single-admin-quorum"
+// is given.
+static QUORUM_POLICY: OnceLock<QuorumPolicy> = OnceLock::new();
+
+/// Startup configuration of the admin quorum
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct QuorumPolicy {
+	pub bootstrap_threshold: u8,
+	pub allow_single_admin: bool,
+}
+
+impl Default for QuorumPolicy {
+	fn default() -> Self {
+		QuorumPolicy { bootstrap_threshold: MIN_QUORUM_THRESHOLD, allow_single_admin: false }
+	}
+}
+
+impl QuorumPolicy {
+	/// Validated quorum policy
+	/// # Arguments
+	/// * `bootstrap_threshold` - threshold of the quorum before the first rotation
+	/// * `allow_single_admin` - whether a threshold of 1 is accepted
+	pub fn new(bootstrap_threshold: u8, allow_single_admin: bool) -> Result<Self, anyhow::Error> {
+		let policy = QuorumPolicy { bootstrap_threshold, allow_single_admin };
+		policy
+			.check_threshold(bootstrap_threshold)
+			.map_err(|message| anyhow::anyhow!("QUORUM : {message}"))?;
+
+		Ok(policy)
+	}
+
+	/// Lowest threshold of a quorum
+	pub fn minimum_threshold(&self) -> u8 {
+		if self.allow_single_admin {
+			1
+		} else {
+			MIN_QUORUM_THRESHOLD
+		}
+	}
+
+	/// Check the threshold of a quorum against the policy
+	pub fn check_threshold(&self, threshold: u8) -> Result<(), String> {
+		if threshold < self.minimum_threshold() {
+			return Err(format!(
+				"quorum threshold {threshold} is lower than {}, a single-admin quorum must be allowed at startup",
+				self.minimum_threshold()
+			))
+		}
+
+		Ok(())
+	}
+}
+
+/// Set the quorum policy, only once at startup
+pub fn set_quorum_policy(
+	bootstrap_threshold: u8,
+	allow_single_admin: bool,
+) -> Result<(), anyhow::Error> {
+	QUORUM_POLICY
+		.set(QuorumPolicy::new(bootstrap_threshold, allow_single_admin)?)
+		.map_err(|_| anyhow::anyhow!("QUORUM : policy is already set"))
+}
+
+/// Quorum policy, the default policy if it is not configured
+pub fn quorum_policy() -> &'static QuorumPolicy {
+	QUORUM_POLICY.get_or_init(QuorumPolicy::default)
+}
+
+/* *************************************
+		 VERIFICATION FUNCTIONS
+**************************************** */
+
+fn get_public_key(account_id: &str) -> Result<sr25519::Public, PublicError> {
+	sr25519::Public::from_ss58check(account_id).map_err(|err: PublicError| {
+		debug!("Error constructing public key {err:?}");
+		err
+	})
+}
+
+fn get_signature(signature: String) -> Result<Signature, FromHexError> {
+	let stripped = match signature.strip_prefix("0x") {
+		Some(sig) => sig,
+		None => signature.as_str(),
+	};
+
+	<[u8; 64]>::from_hex(stripped).map(sr25519::Signature::from_raw)
+}
+
+fn verify_signature(account_id: &str, signature: String, message: &[u8]) -> bool {
+	match get_public_key(account_id) {
+		Ok(pk) => match get_signature(signature) {
+			Ok(val) => sr25519::Pair::verify(&val, message, &pk),
+			Err(err) => {
+				debug!("Error get signature {err:?}");
+				false
+			},
+		},
+		Err(_) => false,
+	}
+}
+
+/// Currently effective quorum
+/// Before the first rotation, every whitelisted admin is a member with the startup threshold
+/// # Arguments
+/// * `state` - SharedState
+pub async fn current_quorum(state: &SharedState) -> QuorumConfig {
+	if let Some(quorum) = get_quorum(state).await {
+		return quorum
+	}
+
+	let members = admin_accounts(state).await;
+
+	QuorumConfig { members, threshold: quorum_policy().bootstrap_threshold, activation_block: 0 }
+}
+
+/// Count distinct quorum members with a valid signature over the message, whose role allows
+/// the operation
+/// # Arguments
+/// * `quorum` - effective quorum
+/// * `roles` - configured roles of the admins
+/// * `signatures` - admin_account -> signature
+/// * `message` - signed message
+/// * `operation` - kind of the admin request
+pub fn count_quorum_signatures(
+	quorum: &QuorumConfig,
+	roles: &BTreeMap<String, AdminRole>,
+	signatures: &BTreeMap<String, String>,
+	message: &[u8],
+	operation: AdminOperation,
+) -> usize {
+	quorum_signers(quorum, roles, signatures, message, operation).len()
+}
+
+/// Distinct quorum members with a valid signature over the message, whose role allows the
+/// operation
+/// # Arguments
+/// * `quorum` - effective quorum
+/// * `roles` - configured roles of the admins
+/// * `signatures` - admin_account -> signature
+/// * `message` - signed message
+/// * `operation` - kind of the admin request
+pub fn quorum_signers(
+	quorum: &QuorumConfig,
+	roles: &BTreeMap<String, AdminRole>,
+	signatures: &BTreeMap<String, String>,
+	message: &[u8],
+	operation: AdminOperation,
+) -> Vec<String> {
+	signatures
+		.iter()
+		.filter(|(account, signature)| {
+			quorum.members.contains(*account) &&
+				role_of(roles, account).allows(operation) &&
+				verify_signature(account, (*signature).clone(), message)
+		})
+		.map(|(account, _)| account.clone())
+		.collect()
+}
+
+/// Quorum members among the signers whose role does not allow the operation
+pub fn unauthorized_signers(
+	quorum: &QuorumConfig,
+	roles: &BTreeMap<String, AdminRole>,
+	signatures: &BTreeMap<String, String>,
+	operation: AdminOperation,
+) -> Vec<String> {
+	signatures
+		.keys()
+		.filter(|account| {
+			quorum.members.contains(*account) && !role_of(roles, account).allows(operation)
+		})
+		.cloned()
+		.collect()
+}
+
+/// Reason of a rejected quorum request, with the signers whose role does not allow it
+pub fn quorum_failure(
+	quorum: &QuorumConfig,
+	signatures: &BTreeMap<String, String>,
+	approvals: usize,
+	operation: AdminOperation,
+) -> String {
+	let mut message =
+		format!("not enough valid quorum signatures : {} < {}", approvals, quorum.threshold);
+
+	let unauthorized = unauthorized_signers(quorum, admin_roles(), signatures, operation);
+	if !unauthorized.is_empty() {
+		message += &format!(", signers not allowed to {operation:?} : {unauthorized:?}");
+	}
+
+	message
+}
+
+/// Verify the quorum-signed authentication token of an admin request, its data hash is checked by
+/// the handler
+/// # Arguments
+/// * `state` - SharedState
+/// * `auth_token` - serialized QuorumAuthenticationToken
+/// * `signatures` - admin_account -> signature of auth_token
+/// * `operation` - kind of the admin request, only signers whose role allows it are counted
+/// # Returns
+/// * `(QuorumAuthenticationToken, Vec<String>)` - parsed token and counted signers
+pub async fn verify_quorum_signatures(
+	state: &SharedState,
+	auth_token: &str,
+	signatures: &BTreeMap<String, String>,
+	operation: AdminOperation,
+) -> Result<(QuorumAuthenticationToken, Vec<String>), (StatusCode, String)> {
+	let token = parse_authentication_token(state, auth_token).await?;
+
+	let quorum = current_quorum(state).await;
+	if let Err(message) = quorum_policy().check_threshold(quorum.threshold) {
+		return Err((StatusCode::SERVICE_UNAVAILABLE, message))
+	}
+
+	let signers =
+		quorum_signers(&quorum, admin_roles(), signatures, auth_token.as_bytes(), operation);
+	if signers.len() < quorum.threshold as usize {
+		return Err((
+			StatusCode::FORBIDDEN,
+			quorum_failure(&quorum, signatures, signers.len(), operation),
+		))
+	}
+
+	Ok((token, signers))
+}
+
+/// Parse an authentication token and check its validity period
+/// # Arguments
+/// * `state` - SharedState
+/// * `auth_token` - serialized token, optionally wrapped in <Bytes></Bytes>
+pub async fn parse_authentication_token(
+	state: &SharedState,
+	auth_token: &str,
+) -> Result<QuorumAuthenticationToken, (StatusCode, String)> {
+	let mut auth = auth_token.to_string();
+	if auth.starts_with("<Bytes>") && auth.ends_with("</Bytes>") {
+		auth = auth["<Bytes>".len()..auth.len() - "</Bytes>".len()].to_string();
+	}
+
+	let token: QuorumAuthenticationToken = match serde_json::from_str(&auth) {
+		Ok(token) => token,
+		Err(err) =>
+			return Err((
+				StatusCode::BAD_REQUEST,
+				format!("Authentication token is not parsable : {err}"),
+			)),
+	};
+
+	let validation = token.is_valid(get_blocknumber(state).await);
+	if !matches!(validation, ValidationResult::Success) {
+		return Err((
+			StatusCode::NOT_ACCEPTABLE,
+			format!("Authentication Token is not valid, or expired : {validation:?}"),
+		))
+	}
+
+	Ok(token)
+}
+
+/* *************************************
+		 PERSISTENCE
+**************************************** */
+
+/// Load the quorum configuration from sealed directory into the state
+pub async fn load_quorum(state: &SharedState) -> Result<(), anyhow::Error> {
+	if !std::path::Path::new(QUORUM_FILE).exists() {
+		debug!("QUORUM : no quorum file, using admin cluster as bootstrap quorum");
+		return Ok(())
+	}
+
+	let content = std::fs::read_to_string(QUORUM_FILE)?;
+	let quorum_file: QuorumFile = serde_json::from_str(&content)?;
+
+	for quorum in quorum_file.active.iter().chain(quorum_file.pending.iter()) {
+		if let Err(message) = quorum_policy().check_threshold(quorum.threshold) {
+			warn!("QUORUM : sealed quorum is refused : {message}");
+		}
+	}
+
+	set_quorum(state, quorum_file.active).await;
+	set_pending_quorum(state, quorum_file.pending).await;
+
+	Ok(())
+}
+
+async fn save_quorum(state: &SharedState) -> Result<(), anyhow::Error> {
+	let quorum_file =
+		QuorumFile { active: get_quorum(state).await, pending: get_pending_quorum(state).await };
+
+	std::fs::write(QUORUM_FILE, serde_json::to_string(&quorum_file)?)?;
+
+	Ok(())
+}
+
+/// Activate the pending quorum when its time-lock is over, called on every new block
+/// # Arguments
+/// * `state` - SharedState
+/// * `block_number` - current block number
+pub async fn activate_pending_quorum(state: &SharedState, block_number: u32) {
+	let pending = match get_pending_quorum(state).await {
+		Some(pending) if pending.activation_block <= block_number => pending,
+		_ => return,
+	};
+
+	info!(
+		"QUORUM : activating new quorum at block {} : threshold = {}, members = {:?}",
+		block_number, pending.threshold, pending.members
+	);
+
+	set_quorum(state, Some(pending)).await;
+	set_pending_quorum(state, None).await;
+
+	if let Err(err) = save_quorum(state).await {
+		error!("QUORUM : error saving activated quorum : {err:?}");
+	}
+}
+
+/* *************************************
+		 QUORUM API
+**************************************** */
+
+/// Quorum status : active and pending configurations
+pub async fn admin_quorum_status(State(state): State<SharedState>) -> impl IntoResponse {
+	let active = current_quorum(&state).await;
+	let pending = get_pending_quorum(&state).await;
+
+	(
+		StatusCode::OK,
+		Json(json!({ "active": active, "pending": pending, "policy": quorum_policy() })),
+	)
+}
+
+/// Rotate the admin quorum
+/// The request must be signed by the threshold of current quorum, the new configuration
+/// is announced on-chain and takes effect after QUORUM_ACTIVATION_DELAY blocks. A pending
+/// rotation must be cancelled first.
+/// # Arguments
+/// * `state` - SharedState
+/// * `request` - QuorumRotatePacket
+#[axum::debug_handler]
+pub async fn admin_quorum_rotate(
+	State(state): State<SharedState>,
+	caller: VerifiedCaller,
+	Json(request): Json<QuorumRotatePacket>,
+) -> impl IntoResponse {
+	debug!("ADMIN QUORUM ROTATE : start");
+
+	if let Err(err) = verify_writable(&state).await {
+		let message = format!("ADMIN QUORUM ROTATE : rotation is rejected : {err:?}");
+		warn!(message);
+		return (StatusCode::SERVICE_UNAVAILABLE, Json(json!({ "error": message })))
+	}
+
+	let members: Vec<String> = request
+		.members
+		.iter()
+		.cloned()
+		.collect::<BTreeSet<String>>()
+		.into_iter()
+		.collect();
+
+	if let Some(pending) = get_pending_quorum(&state).await {
+		let message = format!(
+			"ADMIN QUORUM ROTATE : a rotation is pending until block {}, it must be cancelled first",
+			pending.activation_block
+		);
+		warn!(message);
+		return (StatusCode::CONFLICT, Json(json!({ "error": message })))
+	}
+
+	if request.threshold == 0 || request.threshold as usize > members.len() {
+		let message = format!(
+			"ADMIN QUORUM ROTATE : invalid threshold {} for {} members",
+			request.threshold,
+			members.len()
+		);
+		warn!(message);
+		return (StatusCode::BAD_REQUEST, Json(json!({ "error": message })))
+	}
+
+	if let Err(message) = quorum_policy().check_threshold(request.threshold) {
+		let message = format!("ADMIN QUORUM ROTATE : {message}");
+		warn!(message);
+		return (StatusCode::BAD_REQUEST, Json(json!({ "error": message })))
+	}
+
+	if let Some(invalid) = members.iter().find(|m| get_public_key(m).is_err()) {
+		let message = format!("ADMIN QUORUM ROTATE : invalid member account : {invalid}");
+		warn!(message);
+		return (StatusCode::BAD_REQUEST, Json(json!({ "error": message })))
+	}
+
+	// The token and the threshold of the current quorum are verified by the auth middleware
+	let data_hash = quorum_data_hash(&get_accountid(&state).await, &members, request.threshold);
+	if let Err((status, message)) = caller.verify_data_hash(&data_hash) {
+		let message = format!("ADMIN QUORUM ROTATE : {message}");
+		warn!(message);
+		return (status, Json(json!({ "error": message })))
+	}
+
+	let current_block_number = get_blocknumber(&state).await;
+	let activation_block = current_block_number + QUORUM_ACTIVATION_DELAY;
+
+	// Announce on-chain before scheduling, an unannounced rotation never takes effect
+	let remark = format!("TEE-QUORUM-ROTATION:{data_hash}:{activation_block}");
+	match system_remark_oracle(&state, remark.into_bytes()).await {
+		Ok(block_hash) => info!("ADMIN QUORUM ROTATE : announced on-chain at {block_hash:?}"),
+		Err(err) => {
+			let message = format!("ADMIN QUORUM ROTATE : on-chain announcement failed : {err:?}");
+			error!(message);
+			return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })))
+		},
+	}
+
+	let pending = QuorumConfig { members, threshold: request.threshold, activation_block };
+	set_pending_quorum(&state, Some(pending.clone())).await;
+
+	if let Err(err) = save_quorum(&state).await {
+		let message = format!("ADMIN QUORUM ROTATE : error saving quorum file : {err:?}");
+		error!(message);
+		return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })))
+	}
+
+	info!(
+		"ADMIN QUORUM ROTATE : new quorum is scheduled for block {} with {} approvals",
+		activation_block,
+		caller.approvals()
+	);
+
+	(StatusCode::OK, Json(json!({ "pending": pending, "approvals": caller.approvals() })))
+}
+
+/// Cancel the pending rotation of the admin quorum
+/// The request must be signed by the threshold of current quorum, the cancellation is announced
+/// on-chain
+/// # Arguments
+/// * `state` - SharedState
+/// * `request` - QuorumCancelPacket
+#[axum::debug_handler]
+pub async fn admin_quorum_cancel(
+	State(state): State<SharedState>,
+	caller: VerifiedCaller,
+	Json(_request): Json<QuorumCancelPacket>,
+) -> impl IntoResponse {
+	debug!("ADMIN QUORUM CANCEL : start");
+
+	if let Err(err) = verify_writable(&state).await {
+		let message = format!("ADMIN QUORUM CANCEL : cancellation is rejected : {err:?}");
+		warn!(message);
+		return (StatusCode::SERVICE_UNAVAILABLE, Json(json!({ "error": message })))
+	}
+
+	let pending = match get_pending_quorum(&state).await {
+		Some(pending) => pending,
+		None => {
+			let message = "ADMIN QUORUM CANCEL : no rotation is pending".to_string();
+			warn!(message);
+			return (StatusCode::NOT_FOUND, Json(json!({ "error": message })))
+		},
+	};
+
+	// The token and the threshold of the current quorum are verified by the auth middleware
+	let data_hash = quorum_cancel_hash(&get_accountid(&state).await, &pending);
+	if let Err((status, message)) = caller.verify_data_hash(&data_hash) {
+		let message = format!("ADMIN QUORUM CANCEL : {message}");
+		warn!(message);
+		return (status, Json(json!({ "error": message })))
+	}
+
+	let remark = format!("TEE-QUORUM-CANCEL:{data_hash}");
+	match system_remark_oracle(&state, remark.into_bytes()).await {
+		Ok(block_hash) => info!("ADMIN QUORUM CANCEL : announced on-chain at {block_hash:?}"),
+		Err(err) => {
+			let message = format!("ADMIN QUORUM CANCEL : on-chain announcement failed : {err:?}");
+			error!(message);
+			return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })))
+		},
+	}
+
+	set_pending_quorum(&state, None).await;
+
+	if let Err(err) = save_quorum(&state).await {
+		let message = format!("ADMIN QUORUM CANCEL : error saving quorum file : {err:?}");
+		error!(message);
+		return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })))
+	}
+
+	info!(
+		"ADMIN QUORUM CANCEL : rotation of block {} is cancelled with {} approvals",
+		pending.activation_block,
+		caller.approvals()
+	);
+
+	(StatusCode::OK, Json(json!({ "cancelled": pending, "approvals": caller.approvals() })))
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn quorum_signature_count_test() {
+		let (admin1, _) = sr25519::Pair::generate();
+		let (admin2, _) = sr25519::Pair::generate();
+		let (outsider, _) = sr25519::Pair::generate();
+
+		let quorum = QuorumConfig {
+			members: vec![admin1.public().to_ss58check(), admin2.public().to_ss58check()],
+			threshold: 2,
+			activation_block: 0,
+		};
+
+		let mut roles: BTreeMap<String, AdminRole> = [&admin1, &admin2, &outsider]
+			.into_iter()
+			.map(|pair| (pair.public().to_ss58check(), AdminRole::FULL))
+			.collect();
+
+		let message = b"quorum-token";
+		let mut signatures = BTreeMap::new();
+		for pair in [&admin1, &outsider] {
+			signatures.insert(pair.public().to_ss58check(), hex::encode(pair.sign(message).0));
+		}
+
+		assert_eq!(
+			count_quorum_signatures(&quorum, &roles, &signatures, message, AdminOperation::MANAGE),
+			1
+		);
+
+		signatures.insert(admin2.public().to_ss58check(), hex::encode(admin2.sign(message).0));
+		assert_eq!(
+			count_quorum_signatures(&quorum, &roles, &signatures, message, AdminOperation::MANAGE),
+			2
+		);
+
+		// A member without a configured role is not counted
+		roles.remove(&admin2.public().to_ss58check());
+		assert_eq!(
+			count_quorum_signatures(&quorum, &roles, &signatures, message, AdminOperation::MANAGE),
+			1
+		);
+		assert_eq!(
+			unauthorized_signers(&quorum, &roles, &signatures, AdminOperation::MANAGE),
+			vec![admin2.public().to_ss58check()]
+		);
+	}
+
+	#[test]
+	fn quorum_policy_test() {
+		// A single-admin quorum must be allowed explicitly
+		assert!(QuorumPolicy::new(1, false).is_err());
+		assert!(QuorumPolicy::new(0, true).is_err());
+		assert!(QuorumPolicy::new(1, true).is_ok());
+
+		let policy = QuorumPolicy::new(3, false).unwrap();
+		assert!(policy.check_threshold(1).is_err());
+		assert!(policy.check_threshold(2).is_ok());
+		assert_eq!(QuorumPolicy::default().bootstrap_threshold, MIN_QUORUM_THRESHOLD);
+
+		// A cancellation is bound to the pending rotation
+		let pending =
+			QuorumConfig { members: vec!["A".to_string()], threshold: 2, activation_block: 100 };
+		let later = QuorumConfig { activation_block: 200, ..pending.clone() };
+		assert_ne!(quorum_cancel_hash("E", &pending), quorum_cancel_hash("E", &later));
+	}
+
+	#[test]
+	fn quorum_domain_test() {
+		let (admin, _) = sr25519::Pair::generate();
+		let account = admin.public().to_ss58check();
+		let quorum =
+			QuorumConfig { members: vec![account.clone()], threshold: 1, activation_block: 0 };
+		let members = vec![account.clone(), "B".to_string()];
+		let roles = BTreeMap::from([(account.clone(), AdminRole::FULL)]);
+
+		// A valid quorum signature over a token of the same shape, for another purpose
+		let sign = |data_hash: String| {
+			let token =
+				QuorumAuthenticationToken { block_number: 1, block_validation: 10, data_hash };
+			let auth_token = serde_json::to_string(&token).unwrap();
+			let signatures = BTreeMap::from([(
+				account.clone(),
+				hex::encode(admin.sign(auth_token.as_bytes()).0),
+			)]);
+			let signers = quorum_signers(
+				&quorum,
+				&roles,
+				&signatures,
+				auth_token.as_bytes(),
+				AdminOperation::MANAGE,
+			);
+			VerifiedCaller {
+				signers: signers.into_iter().map(|signer| (signer, AdminRole::FULL)).collect(),
+				operation: AdminOperation::MANAGE,
+				token,
+			}
+		};
+
+		let rotation = quorum_data_hash("E", &members, 2);
+		assert!(sign(rotation.clone()).verify_data_hash(&rotation).is_ok());
+
+		let undomained = sha256::digest(format!("{}_{}", members.join(","), 2).as_bytes());
+		let other_enclave = quorum_data_hash("F", &members, 2);
+		let pending = QuorumConfig { members: members.clone(), threshold: 2, activation_block: 0 };
+		let cancellation = quorum_cancel_hash("E", &pending);
+		for other_purpose in [undomained, other_enclave, cancellation] {
+			let caller = sign(other_purpose);
+			assert_eq!(caller.approvals(), 1);
+			assert!(caller.verify_data_hash(&rotation).is_err());
+		}
+	}
+}
@@ -0,0 +1,196 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{debug, error, info, warn};
+
+use crate::{
+	chain::constants::{MAX_READONLY_PERIOD, READONLY_FILE},
+	servers::{
+		auth::VerifiedCaller,
+		state::{get_blocknumber, get_read_only, set_read_only, SharedState},
+	},
+};
+
+/* *************************************
+	ADMIN READ-ONLY SWITCH
+**************************************** */
+
+/// Global read-only switch : retrievals are served, all keyshare mutations are rejected
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ReadOnlySwitch {
+	pub activation_block: u32,
+	// Last block number that the switch is effective, it is lifted automatically afterwards
+	pub expiry_block: u32,
+	pub reason: String,
+}
+
+impl ReadOnlySwitch {
+	pub fn is_active(&self, current_block_number: u32) -> bool {
+		current_block_number <= self.expiry_block
+	}
+}
+
+/// Read-only switch request, signed by the threshold of admin quorum
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReadOnlyPacket {
+	enable: bool,
+	// Ignored when disabling the switch
+	expiry_block: u32,
+	reason: String,
+}
+
+/// Canonical hash of the switch parameters, signed inside the authentication token
+/// # Arguments
+/// * `enable` - enable or lift the switch
+/// * `expiry_block` - last block number that the switch is effective
+/// * `reason` - reason of the switch, i.e. incident reference
+pub fn readonly_data_hash(enable: bool, expiry_block: u32, reason: &str) -> String {
+	sha256::digest(format!("{}_{}_{}", enable, expiry_block, reason).as_bytes())
+}
+
+/// Expiry block of the read-only switch, None if the enclave is writable
+/// # Arguments
+/// * `state` - SharedState
+pub async fn read_only_until(state: &SharedState) -> Option<u32> {
+	let current_block_number = get_blocknumber(state).await;
+
+	get_read_only(state)
+		.await
+		.filter(|switch| switch.is_active(current_block_number))
+		.map(|switch| switch.expiry_block)
+}
+
+/* *************************************
+		 PERSISTENCE
+**************************************** */
+
+/// Load the persisted read-only switch at startup, an expired switch has no effect
+pub async fn load_read_only(state: &SharedState) -> Result<(), anyhow::Error> {
+	if !std::path::Path::new(READONLY_FILE).exists() {
+		return Ok(())
+	}
+
+	let switch: ReadOnlySwitch = serde_json::from_str(&std::fs::read_to_string(READONLY_FILE)?)?;
+	warn!(
+		"READ-ONLY : enclave starts with read-only switch until block {} : {}",
+		switch.expiry_block, switch.reason
+	);
+
+	set_read_only(state, Some(switch)).await;
+
+	Ok(())
+}
+
+fn save_read_only(switch: &Option<ReadOnlySwitch>) -> Result<(), anyhow::Error> {
+	match switch {
+		Some(switch) => std::fs::write(READONLY_FILE, serde_json::to_string(switch)?)?,
+		None =>
+			if std::path::Path::new(READONLY_FILE).exists() {
+				std::fs::remove_file(READONLY_FILE)?
+			},
+	}
+
+	Ok(())
+}
+
+/* *************************************
+		 READ-ONLY API
+**************************************** */
+
+/// Read-only switch status
+pub async fn admin_readonly_status(State(state): State<SharedState>) -> impl IntoResponse {
+	let current_block_number = get_blocknumber(&state).await;
+	let switch = get_read_only(&state).await;
+	let active = switch.as_ref().map_or(false, |s| s.is_active(current_block_number));
+
+	(StatusCode::OK, Json(json!({ "active": active, "switch": switch })))
+}
+
+/// Enable or lift the global read-only switch
+/// The request must be signed by the threshold of admin quorum, the switch is lifted
+/// automatically after its expiry block
+/// # Arguments
+/// * `state` - SharedState
+/// * `request` - ReadOnlyPacket
+#[axum::debug_handler]
+pub async fn admin_readonly_switch(
+	State(state): State<SharedState>,
+	caller: VerifiedCaller,
+	Json(request): Json<ReadOnlyPacket>,
+) -> impl IntoResponse {
+	debug!("ADMIN READ-ONLY : start");
+
+	let data_hash = readonly_data_hash(request.enable, request.expiry_block, &request.reason);
+	if let Err((status, message)) = caller.verify_data_hash(&data_hash) {
+		let message = format!("ADMIN READ-ONLY : {message}");
+		warn!(message);
+		return (status, Json(json!({ "error": message })))
+	}
+
+	let current_block_number = get_blocknumber(&state).await;
+	if request.enable &&
+		(request.expiry_block <= current_block_number ||
+			request.expiry_block > current_block_number + MAX_READONLY_PERIOD)
+	{
+		let message = format!(
+			"ADMIN READ-ONLY : expiry block {} must be within {} blocks from current block {}",
+			request.expiry_block, MAX_READONLY_PERIOD, current_block_number
+		);
+		warn!(message);
+		return (StatusCode::BAD_REQUEST, Json(json!({ "error": message })))
+	}
+
+	let approvals = caller.approvals();
+
+	let switch = if request.enable {
+		Some(ReadOnlySwitch {
+			activation_block: current_block_number,
+			expiry_block: request.expiry_block,
+			reason: request.reason,
+		})
+	} else {
+		None
+	};
+
+	if let Err(err) = save_read_only(&switch) {
+		let message = format!("ADMIN READ-ONLY : error saving read-only file : {err:?}");
+		error!(message);
+		return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })))
+	}
+
+	set_read_only(&state, switch.clone()).await;
+
+	match &switch {
+		Some(switch) => info!(
+			"ADMIN READ-ONLY : enclave is read-only until block {} with {} approvals : {}",
+			switch.expiry_block, approvals, switch.reason
+		),
+		None => info!("ADMIN READ-ONLY : read-only switch is lifted with {} approvals", approvals),
+	}
+
+	(StatusCode::OK, Json(json!({ "switch": switch, "approvals": approvals })))
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn readonly_switch_test() {
+		let switch = ReadOnlySwitch {
+			activation_block: 100,
+			expiry_block: 200,
+			reason: "INC-1".to_string(),
+		};
+
+		assert!(switch.is_active(150));
+		assert!(switch.is_active(200));
+		assert!(!switch.is_active(201));
+
+		// Every parameter is bound to the signed token
+		let hash = readonly_data_hash(true, 200, "INC-1");
+		assert_ne!(hash, readonly_data_hash(false, 200, "INC-1"));
+		assert_ne!(hash, readonly_data_hash(true, 201, "INC-1"));
+		assert_ne!(hash, readonly_data_hash(true, 200, "INC-2"));
+	}
+}
@@ -0,0 +1,426 @@
+use std::{fs, path::Path, str::FromStr, sync::OnceLock};
+
+use age::x25519;
+use anyhow::anyhow;
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use ecies::{decrypt, encrypt, utils::generate_keypair};
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use subxt::{
+	ext::sp_core::{crypto::Ss58Codec, sr25519, Pair},
+	utils::AccountId32,
+};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+	attestation::{
+		handshake::{verify_offer, Handshake, HandshakeOffer, SessionPurpose},
+		history::{record_attestation, QuotePurpose},
+		ra::{
+			get_quote_content, local_mrsigner, write_user_report_data, QuoteResponse,
+			QUOTE_MRSIGNER_LENGTH, QUOTE_MRSIGNER_OFFSET, QUOTE_REPORT_DATA_LENGTH,
+			QUOTE_REPORT_DATA_OFFSET,
+		},
+		verifier::is_trusted_measurement,
+	},
+	chain::constants::{MAX_VALIDATION_PERIOD, RECOVERY_KEY_FILE},
+	servers::{
+		proxy::with_http_proxy,
+		state::{get_accountid, get_blocknumber, get_clusters, get_keypair, SharedState},
+	},
+};
+
+use super::{
+	sync::{attest_quote, verify_signature},
+	upgrade::quote_mrenclave,
+};
+
+/* *************************************
+	DISASTER-RECOVERY KEY
+**************************************** */
+
+// Disaster-recovery archives are encrypted to a recovery identity shared by the enclaves of the
+// same MRSIGNER, instead of the restore identity of one instance :
+// - the first enclave generates it, the others receive it from a peer with `--recovery-peer`
+// - the peer only sends it to an attested quote with its own MRSIGNER and a trusted measurement of
+//   the allowlist, encrypted to an ephemeral key bound to the quote, so the identity never leaves
+//   SGX in plaintext
+// - with an attested key exchange, the peer also attests to the new enclave and the identity is
+//   sealed with their session key, the ecies key is kept for the older enclaves
+// - it is kept in the sealed directory, and follows the enclave through upgrades
+
+static RECOVERY_IDENTITY: OnceLock<x25519::Identity> = OnceLock::new();
+static RECOVERY_PEER: OnceLock<Option<String>> = OnceLock::new();
+
+/// Recovery key request of a new enclave
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RecoveryKeyRequest {
+	// Ephemeral account of the new enclave, signs the report data
+	account: String,
+	// Hex encoded ecies public key of the response
+	encryption_account: String,
+	// Serialized QuoteResponse, its block number is in the report data
+	quote: String,
+	// Attested key exchange of the response, with the same measurements as the quote
+	#[serde(default)]
+	handshake: Option<HandshakeOffer>,
+}
+
+/// Recovery identity encrypted to the new enclave, signed by the enclave account of the peer
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RecoveryKeyResponse {
+	enclave_account: String,
+	data: String,
+	signature: String,
+	// Handshake offer of the peer, when the data is sealed with the session key
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	handshake: Option<HandshakeOffer>,
+}
+
+/// Report data of a recovery key request, signed by its ephemeral account
+pub fn recovery_token(account: &str, encryption_account: &str, block_number: u32) -> String {
+	format!("recovery-key_{account}_{encryption_account}_{block_number}")
+}
+
+/// MRSIGNER of a hex encoded quote, None if the quote is too short
+pub fn quote_mrsigner(quote: &str) -> Option<String> {
+	let mrsigner: String = quote
+		.chars()
+		.skip(QUOTE_MRSIGNER_OFFSET * 2)
+		.take(QUOTE_MRSIGNER_LENGTH * 2)
+		.collect();
+
+	(mrsigner.len() == QUOTE_MRSIGNER_LENGTH * 2).then(|| mrsigner.to_lowercase())
+}
+
+/// Set the peer enclave of the recovery key, only once at startup
+/// # Arguments
+/// * `peer` - url of an enclave of the same MRSIGNER, None to generate or load the key
+pub fn set_recovery_peer(peer: Option<String>) -> Result<(), anyhow::Error> {
+	RECOVERY_PEER
+		.set(peer.map(|url| url.trim_end_matches('/').to_string()))
+		.map_err(|_| anyhow!("DISASTER RECOVERY : recovery peer is already set"))
+}
+
+fn recovery_peer() -> Option<&'static String> {
+	RECOVERY_PEER.get_or_init(|| None).as_ref()
+}
+
+/// Recovery identity, None until it is set up
+pub fn recovery_identity() -> Option<&'static x25519::Identity> {
+	RECOVERY_IDENTITY.get()
+}
+
+/// Recipient of the disaster-recovery archives, None until the recovery identity is set up
+pub fn recovery_recipient() -> Option<x25519::Recipient> {
+	recovery_identity().map(|identity| identity.to_public())
+}
+
+fn save_identity(identity: &x25519::Identity) -> Result<(), anyhow::Error> {
+	fs::write(RECOVERY_KEY_FILE, identity.to_string().expose_secret())?;
+	Ok(())
+}
+
+/// Load, receive or generate the recovery identity, after the cluster discovery
+/// # Errors
+/// * The sealed key is invalid, or the peer does not send a valid key
+pub async fn setup_recovery_key(state: &SharedState) -> Result<(), anyhow::Error> {
+	let identity = if Path::new(RECOVERY_KEY_FILE).exists() {
+		debug!("DISASTER RECOVERY : load the sealed recovery key");
+		x25519::Identity::from_str(fs::read_to_string(RECOVERY_KEY_FILE)?.trim())
+			.map_err(|_| anyhow!("DISASTER RECOVERY : sealed recovery key is invalid"))?
+	} else if let Some(peer) = recovery_peer() {
+		let identity = request_recovery_key(state, peer).await?;
+		save_identity(&identity)?;
+		info!("DISASTER RECOVERY : recovery key is received from {peer}");
+		identity
+	} else {
+		warn!("DISASTER RECOVERY : no recovery peer, generate a new recovery key");
+		let identity = x25519::Identity::generate();
+		save_identity(&identity)?;
+		identity
+	};
+
+	RECOVERY_IDENTITY
+		.set(identity)
+		.map_err(|_| anyhow!("DISASTER RECOVERY : recovery key is already set"))
+}
+
+/// Attest to a peer enclave and receive its recovery identity
+/// The response must be signed by a registered enclave of the clusters.
+async fn request_recovery_key(
+	state: &SharedState,
+	peer: &str,
+) -> Result<x25519::Identity, anyhow::Error> {
+	let account_keypair = sr25519::Pair::generate().0;
+	let account = account_keypair.public().to_ss58check();
+	let (sk, pk) = generate_keypair();
+	let encryption_account = hex::encode(pk.serialize());
+
+	let block_number = get_blocknumber(state).await;
+	let token = recovery_token(&account, &encryption_account, block_number);
+	write_user_report_data(None, &account_keypair.sign(token.as_bytes()).0)?;
+	let quote_content = get_quote_content()?;
+	record_attestation(Some(block_number), QuotePurpose::RECOVERY, &quote_content);
+	let quote = serde_json::to_string(&QuoteResponse {
+		block_number,
+		data: hex::encode(quote_content),
+		// Report data is the signature of the recovery token
+		report_data_preimage: None,
+	})?;
+
+	let handshake = Handshake::new(&account, block_number)?;
+
+	let client = with_http_proxy(reqwest::Client::builder()).https_only(true).build()?;
+	let response: RecoveryKeyResponse = client
+		.post(format!("{peer}/api/backup/recovery-key"))
+		.json(&RecoveryKeyRequest {
+			account,
+			encryption_account,
+			quote,
+			handshake: Some(handshake.offer().clone()),
+		})
+		.send()
+		.await?
+		.error_for_status()?
+		.json()
+		.await?;
+
+	let peer_account = AccountId32::from_str(&response.enclave_account)
+		.map_err(|err| anyhow!("DISASTER RECOVERY : invalid peer account : {err:?}"))?;
+	let registered = get_clusters(state)
+		.await
+		.iter()
+		.flat_map(|cluster| cluster.enclaves.iter())
+		.any(|enclave| enclave.enclave_account == peer_account);
+	if !registered {
+		return Err(anyhow!(
+			"DISASTER RECOVERY : peer {} is not a registered enclave",
+			response.enclave_account
+		))
+	}
+
+	let data = hex::decode(&response.data)?;
+	if !verify_signature(&response.enclave_account, response.signature.clone(), &data) {
+		return Err(anyhow!("DISASTER RECOVERY : recovery key is not signed by the peer"))
+	}
+
+	let secret = match &response.handshake {
+		Some(offer) => {
+			let verified = verify_offer(
+				&client,
+				offer,
+				&response.enclave_account,
+				get_blocknumber(state).await,
+			)
+			.await
+			.map_err(|message| anyhow!(message))?;
+			handshake.session_key(&verified, SessionPurpose::RECOVERY)?.open(&data)?
+		},
+
+		// Peers without the attested key exchange
+		None => {
+			warn!("DISASTER RECOVERY : {peer} does not seal with an attested session key");
+			decrypt(&sk.serialize(), &data)
+				.map_err(|err| anyhow!("DISASTER RECOVERY : decryption error : {err:?}"))?
+		},
+	};
+	let secret = zeroize::Zeroizing::new(secret);
+
+	std::str::from_utf8(&secret)
+		.ok()
+		.and_then(|secret| x25519::Identity::from_str(secret).ok())
+		.ok_or_else(|| anyhow!("DISASTER RECOVERY : received recovery key is invalid"))
+}
+
+/// Send the recovery identity to a new enclave of the same MRSIGNER
+/// The quote must be attested, recent, contain the request signed by its ephemeral account, and
+/// have the MRSIGNER of this enclave
+pub async fn recovery_key_exchange(
+	State(state): State<SharedState>,
+	Json(request): Json<RecoveryKeyRequest>,
+) -> impl IntoResponse {
+	debug!("DISASTER RECOVERY : recovery key request");
+
+	let (identity, mrsigner) = match (recovery_identity(), local_mrsigner()) {
+		(Some(identity), Some(mrsigner)) => (identity, mrsigner),
+		_ => {
+			let message = "DISASTER RECOVERY : recovery key is not available".to_string();
+			warn!(message);
+			return (StatusCode::PRECONDITION_FAILED, Json(json!({ "error": message })))
+		},
+	};
+
+	let quote_body: QuoteResponse = match serde_json::from_str(&request.quote) {
+		Ok(body) => body,
+		Err(err) => {
+			let message = format!("DISASTER RECOVERY : can not deserialize the quote : {err:?}");
+			warn!(message);
+			return (StatusCode::BAD_REQUEST, Json(json!({ "error": message })))
+		},
+	};
+
+	let current_block_number = get_blocknumber(&state).await;
+	if quote_body.block_number + MAX_VALIDATION_PERIOD < current_block_number ||
+		quote_body.block_number > current_block_number + MAX_VALIDATION_PERIOD
+	{
+		let message =
+			format!("DISASTER RECOVERY : quote of block {} is expired", quote_body.block_number);
+		warn!(message);
+		return (StatusCode::NOT_ACCEPTABLE, Json(json!({ "error": message })))
+	}
+
+	let client = match with_http_proxy(reqwest::Client::builder()).https_only(true).build() {
+		Ok(client) => client,
+		Err(err) => {
+			let message = format!("DISASTER RECOVERY : unable to build a Reqwest client : {err:?}");
+			error!(message);
+			return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })))
+		},
+	};
+
+	let quote = match attest_quote(&state, &client, &quote_body, "recovery-key").await {
+		Ok(quote) => quote,
+		Err(message) => {
+			warn!(message);
+			return (StatusCode::UNAUTHORIZED, Json(json!({ "error": message })))
+		},
+	};
+
+	let report_data: String = quote
+		.chars()
+		.skip(QUOTE_REPORT_DATA_OFFSET * 2)
+		.take(QUOTE_REPORT_DATA_LENGTH * 2)
+		.collect();
+	let token =
+		recovery_token(&request.account, &request.encryption_account, quote_body.block_number);
+
+	if !verify_signature(&request.account, report_data, token.as_bytes()) {
+		let message = "DISASTER RECOVERY : invalid report data signature".to_string();
+		warn!(message);
+		return (StatusCode::UNAUTHORIZED, Json(json!({ "error": message })))
+	}
+
+	if quote_mrsigner(&quote).as_ref() != Some(&mrsigner) {
+		let message = format!(
+			"DISASTER RECOVERY : mrsigner {:?} is not the mrsigner of this enclave",
+			quote_mrsigner(&quote).unwrap_or_default()
+		);
+		warn!(message);
+		sentry::capture_message(&message, sentry::Level::Warning);
+		return (StatusCode::FORBIDDEN, Json(json!({ "error": message })))
+	}
+
+	// Revoked builds of the same MRSIGNER do not get the recovery key
+	let mrenclave = quote_mrenclave(&quote).unwrap_or_default();
+	if !is_trusted_measurement(&mrenclave, &mrsigner) {
+		let message = format!(
+			"DISASTER RECOVERY : mrenclave {mrenclave} is not in the measurement allowlist"
+		);
+		warn!(message);
+		sentry::capture_message(&message, sentry::Level::Warning);
+		return (StatusCode::FORBIDDEN, Json(json!({ "error": message })))
+	}
+
+	// The session key is only used with an attested handshake quote, the ecies key of the
+	// request is bound to the signed report data
+	let peer = match &request.handshake {
+		Some(offer) => {
+			// The key exchange must be attested by the same enclave build as the request
+			if quote_mrenclave(&offer.quote) != quote_mrenclave(&quote) ||
+				quote_mrsigner(&offer.quote) != quote_mrsigner(&quote)
+			{
+				let message =
+					"DISASTER RECOVERY : handshake quote is not the quote of the requester"
+						.to_string();
+				warn!(message);
+				return (StatusCode::UNAUTHORIZED, Json(json!({ "error": message })))
+			}
+
+			match verify_offer(&client, offer, &request.account, current_block_number).await {
+				Ok(peer) => Some(peer).filter(|peer| peer.is_attested()),
+				Err(message) => {
+					warn!(message);
+					return (StatusCode::UNAUTHORIZED, Json(json!({ "error": message })))
+				},
+			}
+		},
+		None => None,
+	};
+
+	let (encrypted, handshake) = match peer {
+		Some(peer) => {
+			let handshake = match Handshake::new(&get_accountid(&state).await, current_block_number)
+			{
+				Ok(handshake) => handshake,
+				Err(err) => {
+					let message = format!("DISASTER RECOVERY : {err}");
+					error!(message);
+					return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })))
+				},
+			};
+
+			let sealed =
+				handshake.session_key(&peer, SessionPurpose::RECOVERY).and_then(|session_key| {
+					session_key.seal(identity.to_string().expose_secret().as_bytes())
+				});
+			(sealed, Some(handshake.offer().clone()))
+		},
+
+		None => {
+			let encrypted = hex::decode(&request.encryption_account)
+				.map_err(|err| anyhow!("invalid encryption account : {err:?}"))
+				.and_then(|encryption_key| {
+					encrypt(&encryption_key, identity.to_string().expose_secret().as_bytes())
+						.map_err(|err| anyhow!("invalid encryption account : {err:?}"))
+				});
+			(encrypted, None)
+		},
+	};
+
+	let data = match encrypted {
+		Ok(data) => data,
+		Err(err) => {
+			let message = format!("DISASTER RECOVERY : {err}");
+			warn!(message);
+			return (StatusCode::BAD_REQUEST, Json(json!({ "error": message })))
+		},
+	};
+
+	let signature = get_keypair(&state).await.sign(&data);
+	info!(
+		"DISASTER RECOVERY : recovery key is sent to the enclave of quote block {}",
+		quote_body.block_number
+	);
+
+	(
+		StatusCode::OK,
+		Json(json!(RecoveryKeyResponse {
+			enclave_account: get_accountid(&state).await,
+			data: hex::encode(data),
+			signature: format!("{}{:?}", "0x", signature),
+			handshake,
+		})),
+	)
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn quote_mrsigner_test() {
+		let mrsigner = "CD".repeat(QUOTE_MRSIGNER_LENGTH);
+		let quote =
+			format!("{}{}{}", "00".repeat(QUOTE_MRSIGNER_OFFSET), mrsigner, "11".repeat(64));
+		assert_eq!(quote_mrsigner(&quote), Some(mrsigner.to_lowercase()));
+		assert_eq!(quote_mrsigner(&"00".repeat(QUOTE_MRSIGNER_OFFSET + 8)), None);
+
+		assert_ne!(recovery_token("a", "b", 10), recovery_token("a", "b", 11));
+	}
+}
@@ -0,0 +1,318 @@
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	fs,
+	path::Path,
+};
+
+use futures::StreamExt;
+use serde::Serialize;
+use tracing::{debug, warn};
+
+use crate::{
+	chain::{
+		constants::{MAX_RESTORE_CHAIN_CHECKS, RESTORE_CHAIN_CONCURRENCY},
+		core::ternoa,
+		helper::{parse_keyshare_file, NftType},
+	},
+	servers::state::{get_chain_api, SharedState},
+};
+
+use super::manifest::{file_digest, BackupManifest};
+
+/* ---------------------------------------
+	RESTORE DRY-RUN
+--------------------------------------- */
+
+/// Comparison of an archive file with the sealed directory
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum RestoreStatus {
+	// Neither the file nor another keyshare of the nft is stored
+	NEW,
+	// The same file is stored with the same content
+	IDENTICAL,
+	// The file is stored with another content, or the nft has a keyshare of another block
+	CONFLICTING,
+}
+
+/// File of an archive and its restore status
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RestoreItem {
+	pub file: String,
+	pub nft_id: Option<u32>,
+	pub status: RestoreStatus,
+	// Stored files replaced or shadowed by the archive file
+	pub current: Vec<String>,
+}
+
+/// Report of a restore dry-run, nothing is applied
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct RestoreReport {
+	pub new: usize,
+	pub identical: usize,
+	pub conflicting: usize,
+	pub items: Vec<RestoreItem>,
+	// Nfts of the archive which do not exist onchain
+	pub missing_onchain: Vec<u32>,
+	// Nfts which have not been checked onchain, beyond the limit or on error
+	pub unchecked_onchain: Vec<u32>,
+}
+
+/// Compare the files of a checked archive with the sealed directory
+/// # Arguments
+/// * `dir_path` - sealed directory
+/// * `manifest` - checked manifest of the archive
+pub fn compare_archive(
+	dir_path: &str,
+	manifest: &BackupManifest,
+) -> Result<Vec<RestoreItem>, anyhow::Error> {
+	// Stored keyshare files of each nft and type
+	let mut stored = BTreeMap::<u32, Vec<(NftType, String)>>::new();
+	for direntry in fs::read_dir(dir_path)? {
+		let path = direntry?.path();
+		if let Ok((nft_id, availability)) = parse_keyshare_file(&path) {
+			let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+			stored.entry(nft_id).or_default().push((availability.nft_type, name));
+		}
+	}
+
+	let mut items = Vec::new();
+	for entry in &manifest.entries {
+		let path = Path::new(dir_path).join(&entry.file);
+
+		let (status, current) = if path.exists() {
+			if file_digest(&path)? == entry.sha256 {
+				(RestoreStatus::IDENTICAL, vec![entry.file.clone()])
+			} else {
+				(RestoreStatus::CONFLICTING, vec![entry.file.clone()])
+			}
+		} else {
+			// Keyshares of the same nft and type, of another block
+			let current: Vec<String> = entry
+				.nft_id
+				.and_then(|nft_id| stored.get(&nft_id))
+				.into_iter()
+				.flatten()
+				.filter(|(nft_type, _)| Some(*nft_type) == entry.nft_type)
+				.map(|(_, file)| file.clone())
+				.collect();
+
+			if current.is_empty() {
+				(RestoreStatus::NEW, current)
+			} else {
+				(RestoreStatus::CONFLICTING, current)
+			}
+		};
+
+		items.push(RestoreItem { file: entry.file.clone(), nft_id: entry.nft_id, status, current });
+	}
+
+	Ok(items)
+}
+
+/// Nfts of the list which do not exist onchain, and the ones which could not be checked
+pub async fn check_onchain(state: &SharedState, nft_ids: Vec<u32>) -> (Vec<u32>, Vec<u32>) {
+	let mut unchecked: Vec<u32> = nft_ids.iter().skip(MAX_RESTORE_CHAIN_CHECKS).copied().collect();
+	let checked: Vec<u32> = nft_ids.into_iter().take(MAX_RESTORE_CHAIN_CHECKS).collect();
+
+	let api = get_chain_api(state).await;
+	let storage = match api.storage().at_latest().await {
+		Ok(storage) => storage,
+		Err(err) => {
+			warn!("ONCHAIN CHECK : unable to get the chain storage : {err:?}");
+			unchecked.extend(checked);
+			unchecked.sort();
+			return (Vec::new(), unchecked)
+		},
+	};
+
+	let results: Vec<(u32, Result<bool, subxt::Error>)> = futures::stream::iter(checked)
+		.map(|nft_id| {
+			let storage = storage.clone();
+			async move {
+				let address = ternoa::storage().nft().nfts(nft_id);
+				(nft_id, storage.fetch(&address).await.map(|data| data.is_some()))
+			}
+		})
+		.buffer_unordered(RESTORE_CHAIN_CONCURRENCY)
+		.collect()
+		.await;
+
+	let mut missing = Vec::new();
+	for (nft_id, result) in results {
+		match result {
+			Ok(true) => {},
+			Ok(false) => missing.push(nft_id),
+			Err(err) => {
+				debug!("ONCHAIN CHECK : unable to fetch nft {nft_id} : {err:?}");
+				unchecked.push(nft_id);
+			},
+		}
+	}
+
+	missing.sort();
+	unchecked.sort();
+	(missing, unchecked)
+}
+
+/// Report what restoring a checked archive would change, without applying anything
+/// # Arguments
+/// * `dir_path` - sealed directory
+/// * `manifest` - checked manifest of the archive
+pub async fn restore_report(
+	state: &SharedState,
+	dir_path: &str,
+	manifest: &BackupManifest,
+) -> Result<RestoreReport, anyhow::Error> {
+	let items = compare_archive(dir_path, manifest)?;
+
+	let nft_ids: BTreeSet<u32> = items.iter().filter_map(|item| item.nft_id).collect();
+	let (missing_onchain, unchecked_onchain) =
+		check_onchain(state, nft_ids.into_iter().collect()).await;
+
+	let count = |status| items.iter().filter(|item| item.status == status).count();
+
+	Ok(RestoreReport {
+		new: count(RestoreStatus::NEW),
+		identical: count(RestoreStatus::IDENTICAL),
+		conflicting: count(RestoreStatus::CONFLICTING),
+		items,
+		missing_onchain,
+		unchecked_onchain,
+	})
+}
+
+/* ---------------------------------------
+	SELECTIVE RESTORE
+--------------------------------------- */
+
+/// Result of an nft of a selective restore
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum SelectionStatus {
+	// Keyshares of the nft are installed
+	RESTORED,
+	// Keyshares of the nft would be installed, dry-run
+	SELECTED,
+	// The archive has no keyshare of the nft
+	NOTINARCHIVE,
+}
+
+/// Nft of a selective restore with its status and archive files
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SelectionResult {
+	pub nft_id: u32,
+	pub status: SelectionStatus,
+	pub files: Vec<String>,
+}
+
+/// Per nft results of a selective restore
+/// # Arguments
+/// * `nft_ids` - requested nfts
+/// * `manifest` - checked manifest, restricted to the requested nfts
+/// * `apply` - false for a dry-run
+pub fn selection_results(
+	nft_ids: &BTreeSet<u32>,
+	manifest: &BackupManifest,
+	apply: bool,
+) -> Vec<SelectionResult> {
+	nft_ids
+		.iter()
+		.map(|nft_id| {
+			let files: Vec<String> = manifest
+				.entries
+				.iter()
+				.filter(|entry| entry.nft_id == Some(*nft_id))
+				.map(|entry| entry.file.clone())
+				.collect();
+
+			let status = match (files.is_empty(), apply) {
+				(true, _) => SelectionStatus::NOTINARCHIVE,
+				(false, true) => SelectionStatus::RESTORED,
+				(false, false) => SelectionStatus::SELECTED,
+			};
+
+			SelectionResult { nft_id: *nft_id, status, files }
+		})
+		.collect()
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::backup::manifest::{build_manifest, BackupSelection};
+
+	#[test]
+	fn compare_archive_test() {
+		let dir = std::env::temp_dir().join(format!("backup-restore-{}", std::process::id()));
+		let source = dir.join("source");
+		let sealed = dir.join("sealed");
+		let _ = fs::remove_dir_all(&dir);
+		fs::create_dir_all(&source).unwrap();
+		fs::create_dir_all(&sealed).unwrap();
+
+		fs::write(source.join("nft_10_100.keyshare"), b"same").unwrap();
+		fs::write(source.join("nft_20_300.keyshare"), b"archive").unwrap();
+		fs::write(source.join("capsule_30_400.keyshare"), b"newer").unwrap();
+		fs::write(source.join("nft_40_500.keyshare"), b"new").unwrap();
+
+		fs::write(sealed.join("nft_10_100.keyshare"), b"same").unwrap();
+		fs::write(sealed.join("nft_20_300.keyshare"), b"stored").unwrap();
+		fs::write(sealed.join("capsule_30_200.keyshare"), b"older").unwrap();
+		// Secret keyshare of another type, not shadowed by the capsule
+		fs::write(sealed.join("nft_40_100.keyshare"), b"secret").unwrap();
+		fs::write(sealed.join("capsule_40_100.keyshare"), b"capsule").unwrap();
+
+		let manifest = build_manifest(
+			source.to_str().unwrap(),
+			&BackupSelection::ALL,
+			"account".to_string(),
+			600,
+		)
+		.unwrap();
+
+		let items = compare_archive(sealed.to_str().unwrap(), &manifest).unwrap();
+		let status: BTreeMap<&str, (RestoreStatus, Vec<String>)> = items
+			.iter()
+			.map(|item| (item.file.as_str(), (item.status, item.current.clone())))
+			.collect();
+
+		assert_eq!(
+			status["nft_10_100.keyshare"],
+			(RestoreStatus::IDENTICAL, vec!["nft_10_100.keyshare".to_string()])
+		);
+		assert_eq!(
+			status["nft_20_300.keyshare"],
+			(RestoreStatus::CONFLICTING, vec!["nft_20_300.keyshare".to_string()])
+		);
+		assert_eq!(
+			status["capsule_30_400.keyshare"],
+			(RestoreStatus::CONFLICTING, vec!["capsule_30_200.keyshare".to_string()])
+		);
+		assert_eq!(
+			status["nft_40_500.keyshare"],
+			(RestoreStatus::CONFLICTING, vec!["nft_40_100.keyshare".to_string()])
+		);
+
+		fs::remove_file(sealed.join("nft_40_100.keyshare")).unwrap();
+		let items = compare_archive(sealed.to_str().unwrap(), &manifest).unwrap();
+		let new: Vec<&str> = items
+			.iter()
+			.filter(|item| item.status == RestoreStatus::NEW)
+			.map(|item| item.file.as_str())
+			.collect();
+		assert_eq!(new, vec!["nft_40_500.keyshare"]);
+
+		let selection = BTreeSet::from([30, 50]);
+		let results = selection_results(&selection, &manifest.restricted(&selection), true);
+		assert_eq!(results[0].status, SelectionStatus::RESTORED);
+		assert_eq!(results[0].files, vec!["capsule_30_400.keyshare".to_string()]);
+		assert_eq!(results[1].status, SelectionStatus::NOTINARCHIVE);
+		let results = selection_results(&selection, &manifest.restricted(&selection), false);
+		assert_eq!(results[0].status, SelectionStatus::SELECTED);
+
+		fs::remove_dir_all(&dir).unwrap();
+	}
+}
@@ -0,0 +1,334 @@
+use std::{
+	fs,
+	path::{Path, PathBuf},
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Mutex, OnceLock,
+	},
+	time::{Duration, Instant},
+};
+
+use age::x25519;
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+	chain::constants::{BLOCKS_PER_HOUR, SEALPATH},
+	servers::{
+		coordination::MaintenanceOperation,
+		metrics::record_backup,
+		state::{get_coordinator, get_runtime_config, SharedState},
+	},
+};
+
+use super::{
+	encryption::{encrypt_archive, parse_recipient},
+	manifest::{file_digest, signed_manifest, BackupSelection},
+	workspace::Workspace,
+	zipdir::add_manifest_zip,
+};
+
+/* ---------------------------------------
+	SCHEDULED BACKUPS
+--------------------------------------- */
+
+// Snapshots are full archives encrypted to an external age recipient, the in-enclave restore
+// identity does not survive a restart. They are written to the sink directory as
+// "snapshot_BLOCKNUMBER.zip.age", only the newest ones are kept.
+
+const SNAPSHOT_PREFIX: &str = "snapshot_";
+const SNAPSHOT_EXTENSION: &str = ".zip.age";
+
+/// Configuration of the scheduled backups
+#[derive(Clone)]
+pub struct BackupSchedule {
+	// Blocks between two snapshots
+	pub interval: u32,
+	// Number of snapshots kept in the sink
+	pub retention: usize,
+	pub sink: PathBuf,
+	pub recipient: x25519::Recipient,
+}
+
+// age recipients do not implement Debug, the bech32 public key is printed
+impl std::fmt::Debug for BackupSchedule {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("BackupSchedule")
+			.field("interval", &self.interval)
+			.field("retention", &self.retention)
+			.field("sink", &self.sink)
+			.field("recipient", &self.recipient.to_string())
+			.finish()
+	}
+}
+
+/// Metadata of the last scheduled backup
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LastBackup {
+	pub block_number: u32,
+	pub file: Option<String>,
+	pub files: usize,
+	pub size: u64,
+	pub sha256: Option<String>,
+	pub error: Option<String>,
+}
+
+static BACKUP_SCHEDULE: OnceLock<Option<BackupSchedule>> = OnceLock::new();
+static LAST_BACKUP: Mutex<Option<LastBackup>> = Mutex::new(None);
+static BACKUP_RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Parse a backup interval, in blocks "600" or "600b", or in hours "6h"
+pub fn parse_backup_interval(interval: &str) -> Result<u32, anyhow::Error> {
+	let interval = interval.trim();
+	let (value, factor) = match interval.strip_suffix('h') {
+		Some(hours) => (hours, BLOCKS_PER_HOUR),
+		None => (interval.strip_suffix('b').unwrap_or(interval), 1),
+	};
+
+	value
+		.parse::<u32>()
+		.ok()
+		.and_then(|value| value.checked_mul(factor))
+		.ok_or_else(|| anyhow!("SCHEDULED BACKUP : invalid interval {interval}"))
+}
+
+/// Set the backup schedule, only once at startup
+/// # Arguments
+/// * `interval` - interval in blocks or hours, "0" disables the scheduled backups
+/// * `retention` - number of snapshots kept
+/// * `sink` - directory of the snapshots
+/// * `recipient` - age recipient of the snapshots
+pub fn set_backup_schedule(
+	interval: &str,
+	retention: usize,
+	sink: Option<String>,
+	recipient: Option<String>,
+) -> Result<(), anyhow::Error> {
+	let interval = parse_backup_interval(interval)?;
+
+	let schedule = if interval == 0 {
+		None
+	} else {
+		if retention == 0 {
+			return Err(anyhow!("SCHEDULED BACKUP : retention must keep at least one snapshot"))
+		}
+
+		let sink =
+			sink.ok_or_else(|| anyhow!("SCHEDULED BACKUP : a sink directory is required"))?;
+		let recipient =
+			recipient.ok_or_else(|| anyhow!("SCHEDULED BACKUP : an age recipient is required"))?;
+
+		Some(BackupSchedule {
+			interval,
+			retention,
+			sink: PathBuf::from(sink),
+			recipient: parse_recipient(&recipient)?,
+		})
+	};
+
+	BACKUP_SCHEDULE
+		.set(schedule)
+		.map_err(|_| anyhow!("SCHEDULED BACKUP : schedule is already set"))
+}
+
+/// Backup schedule, None if scheduled backups are disabled
+pub fn backup_schedule() -> Option<&'static BackupSchedule> {
+	BACKUP_SCHEDULE.get_or_init(|| None).as_ref()
+}
+
+/// Metadata of the last scheduled backup, None before the first one
+pub fn last_backup() -> Option<LastBackup> {
+	LAST_BACKUP.lock().ok().and_then(|last| last.clone())
+}
+
+/// Whether a scheduled snapshot is being written
+pub fn is_backup_running() -> bool {
+	BACKUP_RUNNING.load(Ordering::SeqCst)
+}
+
+/// Whether a scheduled backup is due at this block
+pub fn is_backup_due(block_number: u32) -> bool {
+	match backup_schedule() {
+		Some(schedule) => block_number % schedule.interval == 0,
+		None => false,
+	}
+}
+
+/// Snapshots beyond the retention, the oldest blocks first
+/// # Arguments
+/// * `names` - file names of the sink directory, other files are ignored
+/// * `retention` - number of snapshots kept
+pub fn expired_snapshots(names: &[String], retention: usize) -> Vec<String> {
+	let mut snapshots: Vec<(u32, &String)> = names
+		.iter()
+		.filter_map(|name| {
+			name.strip_prefix(SNAPSHOT_PREFIX)
+				.and_then(|rest| rest.strip_suffix(SNAPSHOT_EXTENSION))
+				.and_then(|block| block.parse::<u32>().ok())
+				.map(|block| (block, name))
+		})
+		.collect();
+
+	snapshots.sort();
+	let expired = snapshots.len().saturating_sub(retention);
+	snapshots.into_iter().take(expired).map(|(_, name)| name.clone()).collect()
+}
+
+fn enforce_retention(sink: &Path, retention: usize) -> Result<(), anyhow::Error> {
+	let names: Vec<String> = fs::read_dir(sink)?
+		.filter_map(|entry| entry.ok())
+		.map(|entry| entry.file_name().to_string_lossy().to_string())
+		.collect();
+
+	for name in expired_snapshots(&names, retention) {
+		match fs::remove_file(sink.join(&name)) {
+			Ok(_) => debug!("SCHEDULED BACKUP : removed expired snapshot {name}"),
+			Err(err) => warn!("SCHEDULED BACKUP : error removing snapshot {name} : {err:?}"),
+		}
+	}
+
+	Ok(())
+}
+
+async fn write_snapshot(
+	state: &SharedState,
+	schedule: &BackupSchedule,
+	block_number: u32,
+) -> Result<LastBackup, anyhow::Error> {
+	// Writes are held until the snapshot is written, the snapshot matches its manifest
+	let _operation = get_coordinator(state)
+		.await
+		.begin_maintenance(
+			MaintenanceOperation::BACKUP,
+			"scheduled",
+			Duration::from_secs(get_runtime_config(state).await.maintenance_queue_timeout),
+		)
+		.await?;
+
+	let manifest = signed_manifest(state, SEALPATH, &BackupSelection::ALL, Vec::new()).await?;
+	let schedule = schedule.clone();
+
+	tokio::task::spawn_blocking(move || -> Result<LastBackup, anyhow::Error> {
+		fs::create_dir_all(&schedule.sink)?;
+
+		let name = format!("{SNAPSHOT_PREFIX}{block_number}{SNAPSHOT_EXTENSION}");
+		let workspace = Workspace::create("snapshot")?;
+		let plain_file = workspace.file(&format!("{SNAPSHOT_PREFIX}{block_number}.zip"));
+		let partial_file = schedule.sink.join(format!("{name}.partial"));
+
+		let files = add_manifest_zip(SEALPATH, &manifest, &plain_file.to_string_lossy())?;
+		let encrypted = encrypt_archive(&plain_file, &partial_file, schedule.recipient.clone());
+
+		// The plaintext archive must not stay on disk
+		if let Err(err) = fs::remove_file(&plain_file) {
+			warn!("SCHEDULED BACKUP : Can not remove the plaintext snapshot : {err:?}");
+		}
+
+		if let Err(err) = encrypted {
+			let _ = fs::remove_file(&partial_file);
+			return Err(err)
+		}
+
+		// A snapshot is complete once it has its final name
+		let snapshot_file = schedule.sink.join(&name);
+		fs::rename(&partial_file, &snapshot_file)?;
+
+		enforce_retention(&schedule.sink, schedule.retention)?;
+
+		Ok(LastBackup {
+			block_number,
+			file: Some(name),
+			files,
+			size: fs::metadata(&snapshot_file)?.len(),
+			sha256: Some(file_digest(&snapshot_file)?),
+			error: None,
+		})
+	})
+	.await?
+}
+
+/// Write a scheduled snapshot to the sink, a snapshot is skipped if the previous one is still
+/// being written
+/// # Arguments
+/// * `state` - SharedState
+/// * `block_number` - block of the snapshot
+pub async fn run_scheduled_backup(state: &SharedState, block_number: u32) {
+	let schedule = match backup_schedule() {
+		Some(schedule) => schedule,
+		None => return,
+	};
+
+	if BACKUP_RUNNING.swap(true, Ordering::SeqCst) {
+		warn!(
+			"SCHEDULED BACKUP : previous snapshot is in progress, block {block_number} is skipped"
+		);
+		return
+	}
+
+	let started = Instant::now();
+	let snapshot = write_snapshot(state, schedule, block_number).await;
+	record_backup("scheduled", started.elapsed(), snapshot.is_ok());
+
+	let last = match snapshot {
+		Ok(last) => {
+			info!(
+				"SCHEDULED BACKUP : snapshot of {} files written to {:?}, {} bytes",
+				last.files, schedule.sink, last.size
+			);
+			last
+		},
+		Err(err) => {
+			error!("SCHEDULED BACKUP : snapshot of block {block_number} failed : {err:?}");
+			LastBackup {
+				block_number,
+				file: None,
+				files: 0,
+				size: 0,
+				sha256: None,
+				error: Some(format!("{err:?}")),
+			}
+		},
+	};
+
+	match LAST_BACKUP.lock() {
+		Ok(mut last_backup) => *last_backup = Some(last),
+		Err(err) => error!("SCHEDULED BACKUP : lock error : {err:?}"),
+	}
+
+	BACKUP_RUNNING.store(false, Ordering::SeqCst);
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn backup_schedule_test() {
+		assert_eq!(parse_backup_interval("0").unwrap(), 0);
+		assert_eq!(parse_backup_interval("1200").unwrap(), 1200);
+		assert_eq!(parse_backup_interval("300b").unwrap(), 300);
+		assert_eq!(parse_backup_interval("6h").unwrap(), 6 * BLOCKS_PER_HOUR);
+		assert!(parse_backup_interval("6d").is_err());
+		assert!(parse_backup_interval("h").is_err());
+
+		let names: Vec<String> = [
+			"snapshot_1200.zip.age",
+			"snapshot_600.zip.age",
+			"snapshot_1800.zip.age",
+			"snapshot_2400.zip.age.partial",
+			"notes.txt",
+		]
+		.iter()
+		.map(|name| name.to_string())
+		.collect();
+
+		assert_eq!(expired_snapshots(&names, 2), vec!["snapshot_600.zip.age".to_string()]);
+		assert_eq!(expired_snapshots(&names, 1).len(), 2);
+		assert!(expired_snapshots(&names, 5).is_empty());
+	}
+}
@@ -9,12 +9,13 @@ use std::{
 	net::SocketAddr,
 	os::unix::prelude::PermissionsExt,
 	path::Path,
+	time::Duration,
 };
 
 use axum::{
 	body::StreamBody,
 	extract::{ConnectInfo, State},
-	http::{header, StatusCode},
+	http::{header, HeaderValue, StatusCode},
 	response::IntoResponse,
 	Json,
 };
@@ -45,9 +46,15 @@ use tracing::{debug, error, info, trace, warn};
 use zip::result::ZipError;
 
 use crate::{
-	attestation::ra::{
-		get_quote_content, write_user_report_data, QuoteResponse, QUOTE_REPORT_DATA_LENGTH,
-		QUOTE_REPORT_DATA_OFFSET,
+	attestation::{
+		environment::enclave_environment,
+		handshake::{verify_offer, Handshake, HandshakeOffer, SessionPurpose, HANDSHAKE_HEADER},
+		history::{record_attestation, QuotePurpose},
+		ra::{
+			get_quote_content, write_user_report_data, QuoteResponse, QUOTE_REPORT_DATA_LENGTH,
+			QUOTE_REPORT_DATA_OFFSET,
+		},
+		verifier::check_peer_quote,
 	},
 	backup::zipdir::{add_list_zip, zip_extract},
 	chain::{
@@ -62,10 +69,15 @@ use crate::{
 		helper::{Availability, NftType},
 	},
 	servers::{
+		coordination::MaintenanceOperation,
+		events::{publish_keyshare_event, KeyshareEventKind},
+		health::ReadinessBlocker,
 		http_server::HealthResponse,
+		proxy::with_http_proxy,
 		state::{
-			get_accountid, get_blocknumber, get_chain_api, get_clusters, get_identity, get_keypair,
-			get_nft_availability, set_clusters, set_identity, set_nft_availability, SharedState,
+			get_accountid, get_blocknumber, get_chain_api, get_clusters, get_coordinator,
+			get_identity, get_keypair, get_nft_availability, get_readiness, get_runtime_config,
+			set_clusters, set_identity, set_nft_availability, SharedState,
 		},
 	},
 };
@@ -119,6 +131,9 @@ pub struct FetchIdPacket {
 	encryption_account: String,
 	auth_token: String,
 	signature: String,
+	// Attested key exchange of the response, the older enclaves use the encryption account
+	#[serde(default)]
+	handshake: Option<HandshakeOffer>,
 }
 
 /// Fetch NFTID Response
@@ -215,7 +230,7 @@ fn get_signature(signature: String) -> Result<Signature, FromHexError> {
 	}
 }
 
-fn verify_signature(account_id: &str, signature: String, message: &[u8]) -> bool {
+pub fn verify_signature(account_id: &str, signature: String, message: &[u8]) -> bool {
 	match get_public_key(account_id) {
 		Ok(pk) => match get_signature(signature) {
 			Ok(val) => sr25519::Pair::verify(&val, message, &pk),
@@ -245,27 +260,24 @@ pub async fn error_handler(message: String, _state: &SharedState) -> impl IntoRe
 	(StatusCode::BAD_REQUEST, Json(json!({ "error": message })))
 }
 
-/// Sync Key Shares (Server Side)
-/// This function is used to backup the key shares of the validators
+/// Verify a synchronization request of another enclave (Server Side)
+/// The requester must be a registered enclave of the same slot, with a valid
+/// authentication token and an attested quote which contains its report-data token
 /// # Arguments
-/// * `state` - StateConfig
-/// * `backup_request` - BackupRequest
-
-#[axum::debug_handler]
-pub async fn sync_keyshares(
-	State(state): State<SharedState>,
-	ConnectInfo(addr): ConnectInfo<SocketAddr>,
-	Json(request): Json<FetchIdPacket>,
-) -> impl IntoResponse {
-	debug!("\n\t----\nSYNC KEYSHARES : START\n\t----\n");
-
-	//update_health_status(&state, "Enclave is Syncing Keyshare, please
-	// wait...".to_string()).await;
-
-	let current_block_number = get_blocknumber(&state).await;
+/// * `state` - SharedState
+/// * `addr` - address of the requester
+/// * `request` - FetchIdPacket
+/// # Returns
+/// * `(cluster_id, Enclave)` - the requester enclave
+pub async fn verify_sync_request(
+	state: &SharedState,
+	addr: SocketAddr,
+	request: &FetchIdPacket,
+) -> Result<(u32, Enclave), String> {
+	let current_block_number = get_blocknumber(state).await;
 
 	debug!("SYNC KEYSHARES : START CLUSTER DISCOVERY");
-	let slot_enclaves = slot_discovery(&state).await;
+	let slot_enclaves = slot_discovery(state).await;
 
 	debug!("SYNC KEYSHARES : VERIFY ACCOUNT ID");
 	let requester = match verify_account_id(slot_enclaves, &request.enclave_account) {
@@ -276,7 +288,7 @@ pub async fn sync_keyshares(
 				addr
 			);
 
-			return error_handler(message, &state).await.into_response()
+			return Err(message)
 		},
 	};
 
@@ -285,24 +297,12 @@ pub async fn sync_keyshares(
 	if auth.starts_with("<Bytes>") && auth.ends_with("</Bytes>") {
 		auth = match auth.strip_prefix("<Bytes>") {
 			Some(stripped) => stripped.to_owned(),
-			_ =>
-				return error_handler(
-					"SYNC KEYSHARES : Strip Token prefix error".to_string(),
-					&state,
-				)
-				.await
-				.into_response(),
+			_ => return Err("SYNC KEYSHARES : Strip Token prefix error".to_string()),
 		};
 
 		auth = match auth.strip_suffix("</Bytes>") {
 			Some(stripped) => stripped.to_owned(),
-			_ =>
-				return error_handler(
-					"SYNC KEYSHARES : Strip Token suffix error".to_string(),
-					&state,
-				)
-				.await
-				.into_response(),
+			_ => return Err("SYNC KEYSHARES : Strip Token suffix error".to_string()),
 		}
 	}
 
@@ -311,7 +311,7 @@ pub async fn sync_keyshares(
 		Err(err) => {
 			let message =
 				format!("SYNC KEYSHARES : Error : Authentication token is not parsable : {}", err);
-			return error_handler(message, &state).await.into_response()
+			return Err(message)
 		},
 	};
 
@@ -321,9 +321,7 @@ pub async fn sync_keyshares(
 		request.signature.clone(),
 		request.auth_token.as_bytes(),
 	) {
-		return error_handler("SYNC KEYSHARES : Invalid Signature".to_string(), &state)
-			.await
-			.into_response()
+		return Err("SYNC KEYSHARES : Invalid Signature".to_string())
 	}
 
 	debug!("SYNC KEYSHARES : Validating the authentication token");
@@ -335,30 +333,20 @@ pub async fn sync_keyshares(
 				"SYNC KEYSHARES : Authentication Token is not valid, or expired : {:?}",
 				validity
 			);
-			return error_handler(message, &state).await.into_response()
+			return Err(message)
 		},
 	}
 
 	let hash = sha256::digest(request.nftid_vec.as_bytes());
 
 	if auth_token.data_hash != hash {
-		return error_handler("SYNC KEYSHARES : Mismatch Data Hash".to_string(), &state)
-			.await
-			.into_response()
+		return Err("SYNC KEYSHARES : Mismatch Data Hash".to_string())
 	}
 
-	let nftidv: Vec<String> = match serde_json::from_str(&request.nftid_vec) {
-		Ok(v) => v,
-		Err(err) => {
-			let message = format!("SYNC KEYSHARES : unable to deserialize nftid vector : {err:?}");
-			return error_handler(message, &state).await.into_response()
-		},
-	};
-
 	// [future reliability] check nftids , is empty, are they in range, ...
 
 	// Create a client
-	let client = match reqwest::Client::builder()
+	let client = match with_http_proxy(reqwest::Client::builder())
 		// This is for development, will be removed for production certs
 		.danger_accept_invalid_certs(!cfg!(any(feature = "mainnet", feature = "alphanet")))
 		.https_only(true)
@@ -379,7 +367,7 @@ pub async fn sync_keyshares(
 				},
 				|| sentry::capture_message(&message, sentry::Level::Error),
 			);
-			return error_handler(message, &state).await.into_response()
+			return Err(message)
 		},
 	};
 
@@ -479,7 +467,7 @@ pub async fn sync_keyshares(
 			},
 			|| sentry::capture_message(&message, sentry::Level::Error),
 		);
-		return error_handler(message, &state).await.into_response()
+		return Err(message)
 	}
 
 	let quote_body: QuoteResponse = match serde_json::from_str(&request.quote) {
@@ -495,7 +483,7 @@ pub async fn sync_keyshares(
 				},
 				|| sentry::capture_message(&message, sentry::Level::Error),
 			);
-			return error_handler(message, &state).await.into_response()
+			return Err(message)
 		},
 	};
 
@@ -505,8 +493,131 @@ pub async fn sync_keyshares(
 		quote_body
 	);
 
-	let account_keypair = get_keypair(&state).await;
-	let account_id = get_accountid(&state).await;
+	let quote = attest_quote(state, &client, &quote_body, &requester.1.enclave_url).await?;
+
+	// The quote is also verified inside the enclave against the collateral and the allowlist
+	if let Err(message) =
+		check_peer_quote(&client, &requester.1.enclave_url, &quote_body.data).await
+	{
+		sentry::with_scope(
+			|scope| {
+				scope.set_tag("sync-keyshare", "quote-verification");
+			},
+			|| sentry::capture_message(&message, sentry::Level::Error),
+		);
+		return Err(message)
+	}
+
+	let report_data: String = quote
+		.chars()
+		.skip(QUOTE_REPORT_DATA_OFFSET * 2)
+		.take(QUOTE_REPORT_DATA_LENGTH * 2)
+		.collect();
+
+	if report_data.len() < 128 {
+		trace!("SYNC KEYSHARES : quote-body in report = {quote}");
+		let message =
+			format!("SYNC KEYSHARES : Failed to get 'report_data; from th quote : {}", quote);
+		sentry::with_scope(
+			|scope| {
+				scope.set_tag("sync-keyshare", "attestation");
+			},
+			|| sentry::capture_message(&message, sentry::Level::Error),
+		);
+		return Err(message)
+	} // FAILED EXTRACTING REPORT DATA
+
+	// Verify Report_Data
+
+	let token = format!(
+		"{}_{}_{}",
+		request.enclave_account, auth_token.block_number, request.encryption_account
+	);
+
+	debug!("SYNC KEYSHARES : report_data token = {token}");
+
+	if !verify_signature(
+		&request.enclave_account.clone(),
+		report_data.to_string(),
+		token.as_bytes(),
+	) {
+		let message = "SYNC KEYSHARES : Invalid Signature".to_string();
+		sentry::with_scope(
+			|scope| {
+				scope.set_tag("sync-keyshare", "quote");
+			},
+			|| sentry::capture_message(&message, sentry::Level::Error),
+		);
+		return Err(message)
+	}
+
+	let parse_token: Vec<&str> = token.split('_').collect();
+	if request.enclave_account != parse_token[0] {
+		let message =
+			"SYNC KEYSHARES : TOKEN : Mismatch between <Requester Account> and <Report Data Token>"
+				.to_string();
+		sentry::with_scope(
+			|scope| {
+				scope.set_tag("sync-keyshare", "attestation");
+			},
+			|| sentry::capture_message(&message, sentry::Level::Error),
+		);
+		return Err(message)
+	} else {
+		match parse_token[1].parse::<u32>() {
+			Ok(token_block) => {
+				if (token_block != auth_token.block_number) ||
+					(current_block_number < token_block) ||
+					(current_block_number - token_block > 5)
+				{
+					let message = format!("SYNC KEYSHARES : TOKEN : Incompatible/Outdated block numbers :\n Current blocknumber: {current_block_number} >~ Token blocknumber: {token_block} == Request blocknumber: {} ?", auth_token.block_number);
+					sentry::with_scope(
+						|scope| {
+							scope.set_tag("sync-keyshare", "attestation");
+						},
+						|| sentry::capture_message(&message, sentry::Level::Error),
+					);
+					return Err(message)
+				}
+			},
+
+			Err(err) => {
+				let message = format!(
+					"SYNC KEYSHARES : TOKEN : Can not parse Token Block Number {} , error = {:?}",
+					parse_token[1], err
+				);
+				sentry::with_scope(
+					|scope| {
+						scope.set_tag("sync-keyshare", "attestation");
+					},
+					|| sentry::capture_message(&message, sentry::Level::Error),
+				);
+				return Err(message)
+			},
+		} // VALID TOKEN BLOCK
+	} // PARSE TOKEN
+
+	Ok(requester)
+}
+
+/// Verify a quote with the attestation server
+/// The report must be signed by an attestation server registered onchain, with a successful
+/// status and the same quote
+/// # Arguments
+/// * `state` - SharedState
+/// * `client` - http client of the attestation server
+/// * `quote_body` - quote of the requester
+/// * `requester_url` - requester, for the error messages
+/// # Returns
+/// * `String` - attested quote, hex encoded
+pub async fn attest_quote(
+	state: &SharedState,
+	client: &reqwest::Client,
+	quote_body: &QuoteResponse,
+	requester_url: &str,
+) -> Result<String, String> {
+	let account_keypair = get_keypair(state).await;
+	let account_id = get_accountid(state).await;
 	let signature = account_keypair.sign(quote_body.data.as_bytes());
 
 	let attestation_request_body = json!({
@@ -533,7 +644,7 @@ pub async fn sync_keyshares(
 				},
 				|| sentry::capture_message(&message, sentry::Level::Error),
 			);
-			return error_handler(message, &state).await.into_response()
+			return Err(message)
 		},
 	};
 
@@ -547,13 +658,13 @@ pub async fn sync_keyshares(
 				},
 				|| sentry::capture_message(&message, sentry::Level::Error),
 			);
-			return error_handler(message, &state).await.into_response()
+			return Err(message)
 		},
 	};
 
 	trace!(
 		"SYNC KEYSHARES : Attestation Result for url : {} is \n {:#?}\n\n",
-		requester.1.enclave_url,
+		requester_url,
 		attestation_json,
 	);
 
@@ -568,7 +679,7 @@ pub async fn sync_keyshares(
 				},
 				|| sentry::capture_message(&message, sentry::Level::Error),
 			);
-			return error_handler(message, &state).await.into_response()
+			return Err(message)
 		},
 	};
 
@@ -588,7 +699,7 @@ pub async fn sync_keyshares(
 				},
 				|| sentry::capture_message(&message, sentry::Level::Error),
 			);
-			return error_handler(message, &state).await.into_response()
+			return Err(message)
 		},
 	};
 
@@ -604,7 +715,7 @@ pub async fn sync_keyshares(
 					},
 					|| sentry::capture_message(&message, sentry::Level::Error),
 				);
-				return error_handler(message, &state).await.into_response()
+				return Err(message)
 			},
 		};
 
@@ -620,7 +731,7 @@ pub async fn sync_keyshares(
 					},
 					|| sentry::capture_message(&message, sentry::Level::Error),
 				);
-				return error_handler(message, &state).await.into_response()
+				return Err(message)
 			},
 		};
 
@@ -637,10 +748,10 @@ pub async fn sync_keyshares(
 			},
 			|| sentry::capture_message(&message, sentry::Level::Error),
 		);
-		return error_handler(message, &state).await.into_response()
+		return Err(message)
 	}
 
-	if !crate::backup::metric::verify_account_id(&state, &attestation_server_account).await {
+	if !crate::backup::metric::verify_account_id(state, &attestation_server_account).await {
 		let message = format!(
 			"SYNC KEYSHARES : Invalid Attestation Server, It is not registered on blockchain , account : {attestation_server_account}"
 		);
@@ -650,7 +761,7 @@ pub async fn sync_keyshares(
 			},
 			|| sentry::capture_message(&message, sentry::Level::Error),
 		);
-		return error_handler(message, &state).await.into_response()
+		return Err(message)
 	}
 
 	// Deserialize again to Json
@@ -665,7 +776,7 @@ pub async fn sync_keyshares(
 				},
 				|| sentry::capture_message(&message, sentry::Level::Error),
 			);
-			return error_handler(message, &state).await.into_response()
+			return Err(message)
 		},
 	};
 
@@ -675,7 +786,7 @@ pub async fn sync_keyshares(
 	if report["exit status"] != "0" {
 		let message = format!(
 			"SYNC KEYSHARES : Attestation IAS report failed :: Requester: {} , Report : {report}",
-			requester.1.enclave_url
+			requester_url
 		);
 		sentry::with_scope(
 			|scope| {
@@ -683,7 +794,7 @@ pub async fn sync_keyshares(
 			},
 			|| sentry::capture_message(&message, sentry::Level::Error),
 		);
-		return error_handler(message, &state).await.into_response()
+		return Err(message)
 	} // FAILED ATTESTATION REPORT
 
 	// Deserialize the quote
@@ -700,7 +811,7 @@ pub async fn sync_keyshares(
 					},
 					|| sentry::capture_message(&message, sentry::Level::Error),
 				);
-				return error_handler(message, &state).await.into_response()
+				return Err(message)
 			},
 		},
 
@@ -712,7 +823,7 @@ pub async fn sync_keyshares(
 				},
 				|| sentry::capture_message(&message, sentry::Level::Error),
 			);
-			return error_handler(message, &state).await.into_response()
+			return Err(message)
 		},
 	};
 
@@ -727,191 +838,360 @@ pub async fn sync_keyshares(
 			},
 			|| sentry::capture_message(&message, sentry::Level::Error),
 		);
-		return error_handler(message, &state).await.into_response()
+		return Err(message)
 	}
 
-	let report_data: String = quote
-		.chars()
-		.skip(QUOTE_REPORT_DATA_OFFSET * 2)
-		.take(QUOTE_REPORT_DATA_LENGTH * 2)
-		.collect();
-
-	if report_data.len() < 128 {
-		trace!("SYNC KEYSHARES : quote-body in report = {quote}");
-		let message =
-			format!("SYNC KEYSHARES : Failed to get 'report_data; from th quote : {}", quote);
-		sentry::with_scope(
-			|scope| {
-				scope.set_tag("sync-keyshare", "attestation");
-			},
-			|| sentry::capture_message(&message, sentry::Level::Error),
-		);
-		return error_handler(message, &state).await.into_response()
-	} // FAILED EXTRACTING REPORT DATA
+	Ok(quote.to_string())
+}
 
-	// Verify Report_Data
+/// Sync Key Shares (Server Side)
+/// This function is used to backup the key shares of the validators
+/// # Arguments
+/// * `state` - StateConfig
+/// * `backup_request` - BackupRequest
 
-	let token = format!(
-		"{}_{}_{}",
-		request.enclave_account, auth_token.block_number, request.encryption_account
-	);
+#[axum::debug_handler]
+pub async fn sync_keyshares(
+	State(state): State<SharedState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
+	Json(request): Json<FetchIdPacket>,
+) -> impl IntoResponse {
+	debug!("\n\t----\nSYNC KEYSHARES : START\n\t----\n");
 
-	debug!("SYNC KEYSHARES : report_data token = {token}");
+	//update_health_status(&state, "Enclave is Syncing Keyshare, please
+	// wait...".to_string()).await;
 
-	if !verify_signature(
-		&request.enclave_account.clone(),
-		report_data.to_string(),
-		token.as_bytes(),
-	) {
-		let message = "SYNC KEYSHARES : Invalid Signature".to_string();
-		sentry::with_scope(
-			|scope| {
-				scope.set_tag("sync-keyshare", "quote");
-			},
-			|| sentry::capture_message(&message, sentry::Level::Error),
-		);
+	if let Err(message) = verify_sync_request(&state, addr, &request).await {
 		return error_handler(message, &state).await.into_response()
 	}
 
-	let parse_token: Vec<&str> = token.split('_').collect();
-	if request.enclave_account != parse_token[0] {
-		let message =
-			"SYNC KEYSHARES : TOKEN : Mismatch between <Requester Account> and <Report Data Token>"
-				.to_string();
-		sentry::with_scope(
-			|scope| {
-				scope.set_tag("sync-keyshare", "attestation");
-			},
-			|| sentry::capture_message(&message, sentry::Level::Error),
-		);
+	let nftidv: Vec<String> = match serde_json::from_str(&request.nftid_vec) {
+		Ok(v) => v,
+		Err(err) => {
+			let message = format!("SYNC KEYSHARES : unable to deserialize nftid vector : {err:?}");
+			return error_handler(message, &state).await.into_response()
+		},
+	};
+
+	let random_number = rand::rngs::OsRng.next_u32();
+	let backup_file = format!("/temporary/backup_{random_number}.zip");
+
+	debug!("SYNC KEYSHARES : Start zippping file");
+	if let Err(err) = add_list_zip(SEALPATH, nftidv, &backup_file) {
+		let message = format!("SYNC KEYSHARES : Error compressing the keyshares : {err:?}");
 		return error_handler(message, &state).await.into_response()
-	} else {
-		match parse_token[1].parse::<u32>() {
-			Ok(token_block) => {
-				if (token_block != auth_token.block_number) ||
-					(current_block_number < token_block) ||
-					(current_block_number - token_block > 5)
-				{
-					let message = format!("SYNC KEYSHARES : TOKEN : Incompatible/Outdated block numbers :\n Current blocknumber: {current_block_number} >~ Token blocknumber: {token_block} == Request blocknumber: {} ?", auth_token.block_number);
-					sentry::with_scope(
-						|scope| {
-							scope.set_tag("sync-keyshare", "attestation");
-						},
-						|| sentry::capture_message(&message, sentry::Level::Error),
-					);
-					return error_handler(message, &state).await.into_response()
-				}
+	}
+
+	let zip_data = match fs::read(backup_file.clone()) {
+		Ok(data) => data,
+		Err(err) =>
+			return (
+				StatusCode::INTERNAL_SERVER_ERROR,
+				Json(json!({
+					"error": format!("SYNC KEYSHARES : Backup File not found: {}", err)
+				})),
+			)
+				.into_response(),
+	};
+
+	debug!("SYNC KEYSHARES : Encryption zip data length = {}", zip_data.len());
+	// Session key of an attested key exchange with the requester
+	let sealed = match &request.handshake {
+		Some(offer) =>
+			match seal_sync_response(&state, &request.enclave_account, offer, &zip_data).await {
+				Ok(sealed) => sealed,
+				Err(message) => return error_handler(message, &state).await.into_response(),
 			},
+		None => None,
+	};
+
+	let (encrypted_zip_data, handshake_header) = match sealed {
+		Some((sealed, header)) => (sealed, Some(header)),
+
+		// Public-Key Encryption
+		None => {
+			let encryption_key = hex::decode(request.encryption_account).unwrap();
+			trace!("SYNC KEYSHARES : Encryption public key = {:?}", encryption_key);
+			match encrypt(&encryption_key, &zip_data) {
+				Ok(encrypted) => (encrypted, None),
+				Err(err) =>
+					return (
+						StatusCode::INTERNAL_SERVER_ERROR,
+						Json(json!({
+							"error": format!("SYNC KEYSHARES : Failed to encrypt the zip data : {:?}", err)
+						})),
+					)
+						.into_response(),
+			}
+		},
+	};
+
+	// Remove Plain Data
+	match std::fs::remove_file(backup_file) {
+		Ok(_) => {
+			trace!("SYNC KEYSHARES : Successfully removed previous zip file")
+		},
+		Err(err) => {
+			let message =
+				format!("SYNC KEYSHARES : Error : Can not remove previous backup file : {}", err);
+			warn!(message);
+		},
+	}
+
+	// Writing to files is necessary to live enough for async stream
+	// ZIP-file Garbage Collection is needed
+	let encrypted_backup_file = format!("/temporary/encrypted_backup_{random_number}.zip");
+	match std::fs::write(encrypted_backup_file.clone(), encrypted_zip_data) {
+		Ok(_) => trace!("SYNC KEYSHARES : Successfully write encrypted zip data to streamfile"),
+		Err(err) =>
+			return Json(json!({
+				"error":
+					format!(
+						"SYNC KEYSHARES : Failed to write encrypted zip data to stream file : {}",
+						err
+					)
+			}))
+			.into_response(),
+	}
+
+	// `File` implements `AsyncRead`
+	debug!("SYNC KEYSHARES : Opening encrypted backup file");
+	let file = match tokio::fs::File::open(encrypted_backup_file).await {
+		Ok(file) => file,
+		Err(err) =>
+			return (
+				StatusCode::INTERNAL_SERVER_ERROR,
+				Json(json!({
+					"error": format!("SYNC KEYSHARES : Encrypted backup File not found: {}", err)
+				})),
+			)
+				.into_response(),
+	};
+
+	// convert the `AsyncRead` into a `Stream`
+	debug!("SYNC KEYSHARES : Create reader-stream");
+	let stream = ReaderStream::new(file);
+
+	// convert the `Stream` into an `axum::body::HttpBody`
+	debug!("SYNC KEYSHARES : Create body-stream");
+	let body = StreamBody::new(stream);
+
+	let headers = [
+		(header::CONTENT_TYPE, "text/toml; charset=utf-8"),
+		(header::CONTENT_DISPOSITION, "attachment; filename=\"Backup.zip\""),
+	];
+
+	//update_health_status(&state, String::new()).await;
+
+	debug!("SYNC KEYSHARES : Sending the backup data to the client ...");
+	let mut response = (headers, body).into_response();
+	if let Some(handshake_header) = handshake_header {
+		response.headers_mut().insert(HANDSHAKE_HEADER, handshake_header);
+	}
+
+	response
+}
+
+/// Seal the keyshares of a response with the session key of the requester (Server Side)
+/// # Arguments
+/// * `state` - SharedState
+/// * `requester` - enclave account of the requester
+/// * `offer` - handshake offer of the request
+/// * `data` - zip of the keyshares
+/// # Returns
+/// * `(Vec<u8>, HeaderValue)` - sealed data and the handshake offer of this enclave, None if the
+///   quote of the requester is not verified, the data is encrypted to the signed request instead
+async fn seal_sync_response(
+	state: &SharedState,
+	requester: &str,
+	offer: &HandshakeOffer,
+	data: &[u8],
+) -> Result<Option<(Vec<u8>, HeaderValue)>, String> {
+	let client = with_http_proxy(reqwest::Client::builder())
+		.build()
+		.map_err(|err| format!("SYNC KEYSHARES : unable to build a Reqwest client : {err:?}"))?;
+
+	let current_block_number = get_blocknumber(state).await;
+	let peer = verify_offer(&client, offer, requester, current_block_number).await?;
+	if !peer.is_attested() {
+		debug!("SYNC KEYSHARES : handshake quote is not verified, encrypt to the request key");
+		return Ok(None)
+	}
+
+	let handshake = Handshake::new(&get_accountid(state).await, current_block_number)
+		.map_err(|err| format!("SYNC KEYSHARES : {err}"))?;
+	let sealed = handshake
+		.session_key(&peer, SessionPurpose::SYNC)
+		.and_then(|session_key| session_key.seal(data))
+		.map_err(|err| format!("SYNC KEYSHARES : {err}"))?;
+
+	let header = handshake
+		.offer()
+		.to_header()
+		.map_err(|err| err.to_string())
+		.and_then(|header| HeaderValue::from_str(&header).map_err(|err| err.to_string()))
+		.map_err(|err| format!("SYNC KEYSHARES : invalid handshake header : {err}"))?;
+
+	Ok(Some((sealed, header)))
+}
+
+/// Open the keyshares of a response with the session key of the responder (Client Side)
+/// # Arguments
+/// * `client` - http client of the enclave
+/// * `handshake` - handshake of the request
+/// * `offer` - handshake offer of the response
+/// * `responder` - enclave account of the responder
+/// * `current_block_number` - current block
+/// * `data` - sealed zip of the keyshares
+async fn open_sync_response(
+	client: &reqwest::Client,
+	handshake: &Handshake,
+	offer: &HandshakeOffer,
+	responder: &str,
+	current_block_number: u32,
+	data: &[u8],
+) -> Result<Vec<u8>, anyhow::Error> {
+	let peer = verify_offer(client, offer, responder, current_block_number)
+		.await
+		.map_err(|message| anyhow!(message))?;
+
+	handshake.session_key(&peer, SessionPurpose::SYNC)?.open(data)
+}
+
+/// Create a signed synchronization request with the attested quote of this enclave (Client Side)
+/// # Arguments
+/// * `state` - SharedState
+/// * `nftids_request` - serialized vector of requested nftids
+/// # Returns
+/// * `(FetchIdPacket, [u8; 32])` - request and the private key to decrypt the response
+pub async fn create_sync_request(
+	state: &SharedState,
+	nftids_request: String,
+) -> Result<(FetchIdPacket, [u8; 32]), anyhow::Error> {
+	let current_block_number = get_blocknumber(state).await;
+	let account_id = get_accountid(state).await;
+	let account_keypair = get_keypair(state).await;
 
+	let nftid_hash = sha256::digest(nftids_request.as_bytes());
+
+	let (sk, pk) = generate_keypair();
+	let encryption_pk = pk.serialize();
+	let encryption_private_key = sk.serialize();
+
+	debug!("Fetch KEYSHARES : Encryption public key = {:?}", encryption_pk);
+	let encryption_public_key = hex::encode(encryption_pk);
+
+	let user_data_token = format!("{account_id}_{current_block_number}_{encryption_public_key}");
+	trace!("FETCH KEYSHARES : QUOTE : report_data token = {}", user_data_token);
+
+	let user_data = account_keypair.sign(user_data_token.as_bytes());
+	trace!("FETCH KEYSHARES : QUOTE : report_data signature = {:?}", user_data);
+
+	match write_user_report_data(None, &user_data.0) {
+		Ok(_) => debug!("FETCH KEYSHARES : QUOTE : Successfully wrote user_data into the quote."),
+		Err(err) => {
+			let message = format!(
+				"FETCH KEYSHARES : QUOTE : Error -> can not write user_data to the quote : {err:?}"
+			);
+
+			error!(message);
+
+			sentry::with_scope(
+				|scope| {
+					scope.set_tag("fetch-keyshares", "quote");
+				},
+				|| sentry::capture_message(&message, sentry::Level::Error),
+			);
+
+			return Err(anyhow!(message))
+		},
+	};
+
+	let quote_content = get_quote_content();
+	if let Ok(quote) = &quote_content {
+		record_attestation(Some(current_block_number), QuotePurpose::SYNC, quote);
+	}
+
+	let quote = match quote_content {
+		Ok(quote) => match serde_json::to_string(&QuoteResponse {
+			block_number: current_block_number,
+			data: hex::encode(quote),
+			// Report data is the signature of the synchronization token
+			report_data_preimage: None,
+		}) {
+			Ok(ser_quote) => ser_quote,
 			Err(err) => {
-				let message = format!(
-					"SYNC KEYSHARES : TOKEN : Can not parse Token Block Number {} , error = {:?}",
-					parse_token[1], err
-				);
+				let message =
+					format!("FETCH KEYSHARES : QUOTE : Can not serialize the quote : {err:?}");
+
+				error!(message);
+
 				sentry::with_scope(
 					|scope| {
-						scope.set_tag("sync-keyshare", "attestation");
+						scope.set_tag("fetch-keyshare", "quote".to_string());
 					},
 					|| sentry::capture_message(&message, sentry::Level::Error),
 				);
-				return error_handler(message, &state).await.into_response()
-			},
-		} // VALID TOKEN BLOCK
-	} // PARSE TOKEN
 
-	let random_number = rand::rngs::OsRng.next_u32();
-	let backup_file = format!("/temporary/backup_{random_number}.zip");
+				return Err(anyhow!(message))
+			},
+		},
+		Err(err) => {
+			let message = format!("FETCH KEYSHARES : QUOTE : Can not genrate the quote : {err:?}");
+			error!(message);
 
-	debug!("SYNC KEYSHARES : Start zippping file");
-	add_list_zip(SEALPATH, nftidv, &backup_file.clone());
+			sentry::with_scope(
+				|scope| {
+					scope.set_tag("fetch-keyshare", "quote");
+				},
+				|| sentry::capture_message(&message, sentry::Level::Error),
+			);
 
-	let zip_data = match fs::read(backup_file.clone()) {
-		Ok(data) => data,
-		Err(err) =>
-			return (
-				StatusCode::INTERNAL_SERVER_ERROR,
-				Json(json!({
-					"error": format!("SYNC KEYSHARES : Backup File not found: {}", err)
-				})),
-			)
-				.into_response(),
+			return Err(anyhow!(message))
+		},
 	};
 
-	// Public-Key Encryption
-	let encryption_key = hex::decode(request.encryption_account).unwrap();
-	trace!("SYNC KEYSHARES : Encryption public key = {:?}", encryption_key);
-	debug!("SYNC KEYSHARES : Encryption zip data length = {}", zip_data.len());
-	let encrypted_zip_data = match encrypt(&encryption_key, &zip_data) {
-		Ok(encrypted) => encrypted,
-		Err(err) =>
-			return (
-				StatusCode::INTERNAL_SERVER_ERROR,
-				Json(json!({
-					"error": format!("SYNC KEYSHARES : Failed to encrypt the zip data : {:?}", err)
-				})),
-			)
-				.into_response(),
+	let quote_hash = sha256::digest(quote.clone());
+
+	let auth = AuthenticationToken {
+		block_number: current_block_number,
+		block_validation: 15,
+		data_hash: nftid_hash,
+		quote_hash,
 	};
 
-	// Remove Plain Data
-	match std::fs::remove_file(backup_file) {
-		Ok(_) => {
-			trace!("SYNC KEYSHARES : Successfully removed previous zip file")
-		},
+	let auth_str = match serde_json::to_string(&auth) {
+		Ok(authstr) => authstr,
 		Err(err) => {
-			let message =
-				format!("SYNC KEYSHARES : Error : Can not remove previous backup file : {}", err);
-			warn!(message);
+			let message = format!(
+				"FETCH KEYSHARES : AUTH : Can not serialize the authentication token : {:?}",
+				err
+			);
+			error!(message);
+			sentry::with_scope(
+				|scope| {
+					scope.set_tag("fetch-keyshare", "token");
+				},
+				|| sentry::capture_message(&message, sentry::Level::Error),
+			);
+			return Err(anyhow!(message))
 		},
-	}
-
-	// Writing to files is necessary to live enough for async stream
-	// ZIP-file Garbage Collection is needed
-	let encrypted_backup_file = format!("/temporary/encrypted_backup_{random_number}.zip");
-	match std::fs::write(encrypted_backup_file.clone(), encrypted_zip_data) {
-		Ok(_) => trace!("SYNC KEYSHARES : Successfully write encrypted zip data to streamfile"),
-		Err(err) =>
-			return Json(json!({
-				"error":
-					format!(
-						"SYNC KEYSHARES : Failed to write encrypted zip data to stream file : {}",
-						err
-					)
-			}))
-			.into_response(),
-	}
-
-	// `File` implements `AsyncRead`
-	debug!("SYNC KEYSHARES : Opening encrypted backup file");
-	let file = match tokio::fs::File::open(encrypted_backup_file).await {
-		Ok(file) => file,
-		Err(err) =>
-			return (
-				StatusCode::INTERNAL_SERVER_ERROR,
-				Json(json!({
-					"error": format!("SYNC KEYSHARES : Encrypted backup File not found: {}", err)
-				})),
-			)
-				.into_response(),
 	};
 
-	// convert the `AsyncRead` into a `Stream`
-	debug!("SYNC KEYSHARES : Create reader-stream");
-	let stream = ReaderStream::new(file);
-
-	// convert the `Stream` into an `axum::body::HttpBody`
-	debug!("SYNC KEYSHARES : Create body-stream");
-	let body = StreamBody::new(stream);
-
-	let headers = [
-		(header::CONTENT_TYPE, "text/toml; charset=utf-8"),
-		(header::CONTENT_DISPOSITION, "attachment; filename=\"Backup.zip\""),
-	];
+	let sig = account_keypair.sign(auth_str.as_bytes());
+	let sig_str = format!("{}{:?}", "0x", sig);
 
-	//update_health_status(&state, String::new()).await;
+	let request = FetchIdPacket {
+		enclave_account: account_id,
+		nftid_vec: nftids_request,
+		auth_token: auth_str,
+		signature: sig_str,
+		quote,
+		encryption_account: encryption_public_key,
+		handshake: None,
+	};
 
-	debug!("SYNC KEYSHARES : Sending the backup data to the client ...");
-	(headers, body).into_response()
+	Ok((request, encryption_private_key))
 }
 
 /* --------------------------------
@@ -924,10 +1204,13 @@ pub async fn fetch_keyshares(
 ) -> Result<u32, anyhow::Error> {
 	debug!("\n\t----\nFETCH KEYSHARES : START\n\t----\n");
 
+	// Keyshares never enter a debug enclave of a mainnet build
+	if enclave_environment().keyshares_refused {
+		return Err(anyhow!("FETCH KEYSHARES : keyshares are refused in a debug enclave"))
+	}
+
 	let mut last_synced = 0u32;
 	let current_block_number = get_blocknumber(state).await;
-	let account_id = get_accountid(state).await;
-	let account_keypair = get_keypair(state).await;
 
 	// (clustse, slot)
 	let enclave_identity = match get_identity(state).await {
@@ -941,6 +1224,17 @@ pub async fn fetch_keyshares(
 		},
 	};
 
+	// A backup or a restore in progress is finished first, the callers retry after a conflict
+	let _operation = get_coordinator(state)
+		.await
+		.begin_maintenance(
+			MaintenanceOperation::SYNC,
+			"fetch-keyshares",
+			Duration::from_secs(get_runtime_config(state).await.maintenance_queue_timeout),
+		)
+		.await?;
+	let _readiness = get_readiness(state).await.hold(ReadinessBlocker::SYNCING);
+
 	// Convert HashMap to Vector of nftid and filter new ones
 	let new_nftid_vec_str: Vec<String> = new_nft_map
 		.clone()
@@ -1021,6 +1315,12 @@ pub async fn fetch_keyshares(
 							),
 						)
 						.await;
+						publish_keyshare_event(
+							KeyshareEventKind::SYNCED,
+							nftid_num,
+							NftType::Capsule,
+							sync_block.block_number,
+						);
 					},
 					Err(err) => {
 						let message = format!("FETCH KEYSHARES : ORIGINALS : ERROR RENAMING : {capsule_file} to {capsule_new_file} : {err:?}");
@@ -1042,116 +1342,11 @@ pub async fn fetch_keyshares(
 		return Ok(current_block_number)
 	};
 
-	let nftid_hash = sha256::digest(nftids_request.as_bytes());
-
-	let (sk, pk) = generate_keypair();
-	let encryption_pk = pk.serialize();
-	let encryption_private_key = sk.serialize();
-
-	debug!("Fetch KEYSHARES : Encryption public key = {:?}", encryption_pk);
-	let encryption_public_key = hex::encode(encryption_pk);
-
-	let user_data_token = format!("{account_id}_{current_block_number}_{encryption_public_key}");
-	trace!("FETCH KEYSHARES : QUOTE : report_data token = {}", user_data_token);
-
-	let user_data = account_keypair.sign(user_data_token.as_bytes());
-	trace!("FETCH KEYSHARES : QUOTE : report_data signature = {:?}", user_data);
-
-	match write_user_report_data(None, &user_data.0) {
-		Ok(_) => debug!("FETCH KEYSHARES : QUOTE : Successfully wrote user_data into the quote."),
-		Err(err) => {
-			let message = format!(
-				"FETCH KEYSHARES : QUOTE : Error -> can not write user_data to the quote : {err:?}"
-			);
-
-			error!(message);
-
-			sentry::with_scope(
-				|scope| {
-					scope.set_tag("fetch-keyshares", "quote");
-				},
-				|| sentry::capture_message(&message, sentry::Level::Error),
-			);
-
-			return Err(anyhow!(message))
-		},
-	};
-
-	let quote = match get_quote_content() {
-		Ok(quote) => match serde_json::to_string(&QuoteResponse {
-			block_number: current_block_number,
-			data: hex::encode(quote),
-		}) {
-			Ok(ser_quote) => ser_quote,
-			Err(err) => {
-				let message =
-					format!("FETCH KEYSHARES : QUOTE : Can not serialize the quote : {err:?}");
-
-				error!(message);
-
-				sentry::with_scope(
-					|scope| {
-						scope.set_tag("fetch-keyshare", "quote".to_string());
-					},
-					|| sentry::capture_message(&message, sentry::Level::Error),
-				);
-
-				return Err(anyhow!(message))
-			},
-		},
-		Err(err) => {
-			let message = format!("FETCH KEYSHARES : QUOTE : Can not genrate the quote : {err:?}");
-			error!(message);
-
-			sentry::with_scope(
-				|scope| {
-					scope.set_tag("fetch-keyshare", "quote");
-				},
-				|| sentry::capture_message(&message, sentry::Level::Error),
-			);
-
-			return Err(anyhow!(message))
-		},
-	};
-
-	let quote_hash = sha256::digest(quote.clone());
-
-	let auth = AuthenticationToken {
-		block_number: current_block_number,
-		block_validation: 15,
-		data_hash: nftid_hash,
-		quote_hash,
-	};
-
-	let auth_str = match serde_json::to_string(&auth) {
-		Ok(authstr) => authstr,
-		Err(err) => {
-			let message = format!(
-				"FETCH KEYSHARES : AUTH : Can not serialize the authentication token : {:?}",
-				err
-			);
-			error!(message);
-			sentry::with_scope(
-				|scope| {
-					scope.set_tag("fetch-keyshare", "token");
-				},
-				|| sentry::capture_message(&message, sentry::Level::Error),
-			);
-			return Err(anyhow!(message))
-		},
-	};
-
-	let sig = account_keypair.sign(auth_str.as_bytes());
-	let sig_str = format!("{}{:?}", "0x", sig);
+	let (mut request, encryption_private_key) = create_sync_request(state, nftids_request).await?;
 
-	let request = FetchIdPacket {
-		enclave_account: account_id,
-		nftid_vec: nftids_request,
-		auth_token: auth_str,
-		signature: sig_str,
-		quote,
-		encryption_account: encryption_public_key,
-	};
+	// The keyshares are sealed with an attested session key by the enclaves which support it
+	let handshake = Handshake::new(&request.enclave_account, current_block_number)?;
+	request.handshake = Some(handshake.offer().clone());
 
 	let request_body = match serde_json::to_string(&request) {
 		Ok(body) => {
@@ -1195,7 +1390,7 @@ pub async fn fetch_keyshares(
 	let nft_clusters: Vec<u32> = new_nft_map.clone().into_values().map(|c| c.cluster_id).collect();
 	debug!("FETCH KEYSHARES : nfts-cluster {:?}\n", nft_clusters);
 
-	let client = reqwest::Client::builder()
+	let client = with_http_proxy(reqwest::Client::builder())
 		// This is for development, will be removed for production certs
 		.danger_accept_invalid_certs(!cfg!(any(feature = "mainnet", feature = "alphanet")))
 		.https_only(true)
@@ -1322,6 +1517,16 @@ pub async fn fetch_keyshares(
 		let fetch_headers = fetch_response.headers();
 		trace!("FETCH KEYSHARES : zip response header : {:?}", fetch_headers);
 
+		let responder_offer = fetch_headers
+			.get(HANDSHAKE_HEADER)
+			.map(|header| {
+				header
+					.to_str()
+					.map_err(|err| anyhow!("{err:?}"))
+					.and_then(HandshakeOffer::from_header)
+			})
+			.transpose();
+
 		let fetch_body_bytes = fetch_response.bytes().await?;
 		trace!("FETCH KEYSHARES : zip body length : {}", fetch_body_bytes.len());
 
@@ -1341,7 +1546,32 @@ pub async fn fetch_keyshares(
 			},
 		};
 
-		let decrypt_zip_data = match decrypt(&encryption_private_key, &fetch_body_bytes) {
+		let decrypted = match responder_offer {
+			Ok(Some(offer)) =>
+				open_sync_response(
+					&client,
+					&handshake,
+					&offer,
+					&enclave.enclave_account.to_string(),
+					current_block_number,
+					&fetch_body_bytes,
+				)
+				.await,
+
+			// Enclaves without the attested key exchange
+			Ok(None) => {
+				warn!(
+					"FETCH KEYSHARES : {} does not seal with an attested session key",
+					enclave.enclave_url
+				);
+				decrypt(&encryption_private_key, &fetch_body_bytes)
+					.map_err(|err| anyhow!("{err:?}"))
+			},
+
+			Err(err) => Err(anyhow!("FETCH KEYSHARES : invalid handshake header : {err:?}")),
+		};
+
+		let decrypt_zip_data = match decrypted {
 			Ok(decrypted) => decrypted,
 			Err(err) => {
 				let message =
@@ -1975,6 +2205,13 @@ pub fn set_sync_state(state: String) -> Result<()> {
 	Ok(())
 }
 
+// Flush Sync State File, before the enclave exits
+pub fn flush_sync_state() -> Result<()> {
+	fs::File::open(SYNC_STATE_FILE)?.sync_all()?;
+	debug!("SYNC STATE : sync.state file is flushed");
+	Ok(())
+}
+
 /* ----------------------------
 		EXTRACT ARCHIVE
 -------------------------------*/
@@ -2223,6 +2460,12 @@ pub async fn sync_zip_extract(
 
 				// UPDATE MAP
 				set_nft_availability(state, (nftid, availability)).await;
+				publish_keyshare_event(
+					KeyshareEventKind::SYNCED,
+					nftid,
+					availability.nft_type,
+					availability.block_number,
+				);
 			},
 
 			// UPDATE CAPSULE/HYBRID KEY
@@ -2310,6 +2553,12 @@ pub async fn sync_zip_extract(
 						),
 					)
 					.await;
+					publish_keyshare_event(
+						KeyshareEventKind::SYNCED,
+						nftid,
+						NftType::Hybrid,
+						keyshare_blocknumber,
+					);
 				} else if name_parts[0] == "capsule" && av.nft_type == NftType::Capsule {
 					if av.block_number >= keyshare_blocknumber {
 						// OUTDATED SYNCING FILE
@@ -2387,6 +2636,12 @@ pub async fn sync_zip_extract(
 					};
 
 					set_nft_availability(state, (nftid, availability)).await;
+					publish_keyshare_event(
+						KeyshareEventKind::SYNCED,
+						nftid,
+						NftType::Capsule,
+						keyshare_blocknumber,
+					);
 
 					let old_file_path =
 						format!("{SEALPATH}/capsule_{nftid}_{}.keyshare", av.block_number);
@@ -2453,8 +2708,9 @@ mod test {
 			BTreeMap::<u32, helper::Availability>::new(),
 		)));
 
-		let mut app = match crate::servers::http_server::http_server().await {
-			Ok(r) => r,
+		// No heartbeat, no secondary rpc, no compression
+		let mut app = match crate::servers::http_server::http_server(0, None, 0, true).await {
+			Ok((app, _supervisor, _state)) => app,
 			Err(err) => {
 				error!("Error creating http server {}", err);
 				return
@@ -1,14 +1,991 @@
-/*
-
-1. We will introduce Admin enclaves
-2. The operator that wants to upgrade enclave binary will:
-	2-1. Construct payload with its own enclave account key
-	2-2. Sign the payload with operator private key
-	2-3. Send the signed message to Admin TEE enclave.
-	2-4. Admin TEE enclave will expose an API to receive this message.
-3. The operator will install and start the new enclave binary
-4. The new enclave binary will request admin TEE for the key backup. Need an UI where operator can trigger this.
-5. The Admin TEE will do remote attestation of the enclave . If succeeds, it will return the payload signed with the public key of the operator.
-6. The enclave will replace the existing account key , with the backup received from Admin TEE.
-
- */
+use std::{
+	fs::{self, File},
+	io::Write,
+	net::{Ipv4Addr, SocketAddr},
+	path::Path,
+	sync::OnceLock,
+	time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use axum::{
+	extract::State,
+	http::StatusCode,
+	response::IntoResponse,
+	routing::{get, post},
+	Json, Router,
+};
+use axum_server::Handle;
+use ecies::{decrypt, encrypt, utils::generate_keypair};
+use rand::RngCore;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::json;
+use subxt::ext::sp_core::{crypto::Ss58Codec, sr25519, Pair};
+use tracing::{debug, error, info, warn};
+use walkdir::WalkDir;
+use zeroize::Zeroizing;
+
+use crate::{
+	attestation::{
+		account::{pending_account_rotation, sealed_account_phrase},
+		history::{record_attestation, QuotePurpose},
+		inspect::ATTRIBUTE_DEBUG,
+		ra::{
+			generate_quote, get_quote_content, write_user_report_data, QuoteResponse,
+			QUOTE_MRENCLAVE_LENGTH, QUOTE_MRENCLAVE_OFFSET, QUOTE_REPORT_DATA_LENGTH,
+			QUOTE_REPORT_DATA_OFFSET,
+		},
+		verifier::{accepts_debug_enclaves, verify_peer_quote, QUOTE_HEADER_LENGTH},
+	},
+	chain::{
+		audit::AuditHead,
+		constants::{
+			LEGACY_MIGRATION_FILE, LEGACY_SEALED_MOUNTS, STORAGE_KEY_DEVICE, STORAGE_KEY_FILE,
+			UPGRADE_ARM_PERIOD, UPGRADE_DRAIN_TIMEOUT,
+		},
+		negative_cache::{negative_cache_snapshot, restore_negative_cache, NegativeCache},
+		quota::{QuotaLimiter, QuotaSnapshot},
+		replay::{compact_replay_journal, ReplayJournal},
+	},
+	servers::{
+		auth::VerifiedCaller,
+		proxy::with_http_proxy,
+		state::{get_accountid, get_blocknumber, get_keypair, SharedState},
+	},
+};
+
+use super::sync::{attest_quote, verify_signature};
+
+/* *************************************
+	ZERO-DOWNTIME UPGRADE HANDOFF
+**************************************** */
+
+// The encrypted mounts are opened with a storage key instead of the MRENCLAVE sealing key, the
+// storage key itself is sealed to the binary. An upgrade is a handoff between two instances on
+// the same machine :
+// 1. The admin quorum arms the running instance with the MRENCLAVE of the new binary
+// 2. The new instance attests to the running one over a loopback channel, and receives the storage
+//    key encrypted to its ephemeral key. The running instance attests back, its quote binds its
+//    enclave account to the ephemeral key, the new instance installs nothing before it is verified
+// 3. The new instance opens the sealed files, and binds the server port next to the running one
+// 4. On takeover, the running instance stops accepting, drains in-flight requests, and sends its
+//    in-memory state : quota buckets, replay journal, negative cache and audit head
+// 5. The new instance restores the state and starts accepting, the old instance exits
+
+static STORAGE_KEY: OnceLock<StorageKey> = OnceLock::new();
+static SERVER_HANDLE: OnceLock<Handle> = OnceLock::new();
+
+/// Key of the encrypted mounts, 128 bits
+pub type StorageKey = [u8; 16];
+
+/// Admin approval of a handoff to a binary
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct UpgradeArm {
+	// Hex encoded MRENCLAVE of the new binary
+	pub mrenclave: String,
+	// Last block number that the handoff can start
+	pub expiry_block: u32,
+	// Single-use challenge of the attested request
+	#[serde(skip)]
+	challenge: Option<String>,
+	#[serde(skip)]
+	session: Option<HandoffSession>,
+}
+
+/// Handoff in progress, the new instance is attested
+#[derive(Clone, Debug, PartialEq)]
+struct HandoffSession {
+	token: String,
+	encryption_key: Vec<u8>,
+}
+
+/// Upgrade arm request, signed by the admin quorum
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UpgradeArmPacket {
+	mrenclave: String,
+}
+
+/// Attested handoff request of the new instance
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HandoffRequest {
+	// Ephemeral account of the new instance, signs the report data
+	account: String,
+	// Hex encoded ecies public key of the handoff responses
+	encryption_account: String,
+	// Serialized QuoteResponse
+	quote: String,
+}
+
+/// Takeover request of the attested instance
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TakeoverRequest {
+	session: String,
+}
+
+/// Encrypted handoff response, signed by the enclave account of the running instance
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HandoffResponse {
+	enclave_account: String,
+	data: String,
+	signature: String,
+	// Hex encoded quote of the running instance, only in the storage key response
+	#[serde(default)]
+	quote: Option<String>,
+}
+
+/// Storage key and session of an attested handoff
+#[derive(Serialize, Deserialize, Debug)]
+struct HandoffKey {
+	session: String,
+	storage_key: String,
+	// Phrase of the enclave account sealed to the running binary, None for the older versions
+	// which keep it in the storage key mount
+	#[serde(default)]
+	account_phrase: Option<String>,
+}
+
+/// In-memory state of the running instance, sent on takeover
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct HandoffState {
+	pub block_number: u32,
+	pub audit_head: AuditHead,
+	pub replay_journal: ReplayJournal,
+	pub negative_cache: NegativeCache,
+	pub quota: QuotaSnapshot,
+}
+
+/// Data hash signed by the admins for an upgrade arm
+pub fn upgrade_arm_data_hash(mrenclave: &str) -> String {
+	sha256::digest(format!("upgrade-arm_{mrenclave}").as_bytes())
+}
+
+/// Token of the running instance, its report data binds the handoff channel key of the new instance
+fn running_token(enclave_account: &str, challenge: &str, encryption_account: &str) -> String {
+	format!("upgrade-handoff_{enclave_account}_{challenge}_{encryption_account}")
+}
+
+/// Check that the attested report data of the running instance binds this handoff, and that its
+/// enclave account signed the response
+/// # Arguments
+/// * `response` - storage key response of the running instance
+/// * `report_data` - hex encoded report data of its verified quote
+/// * `challenge` - challenge of the handoff
+/// * `encryption_account` - handoff channel key of the new instance
+fn check_running_binding(
+	response: &HandoffResponse,
+	report_data: &str,
+	challenge: &str,
+	encryption_account: &str,
+) -> Result<(), anyhow::Error> {
+	let token = running_token(&response.enclave_account, challenge, encryption_account);
+	if !verify_signature(&response.enclave_account, report_data.to_string(), token.as_bytes()) {
+		return Err(anyhow!(
+			"UPGRADE : report data of the running instance does not bind the handoff"
+		))
+	}
+
+	if !verify_signature(
+		&response.enclave_account,
+		response.signature.clone(),
+		&hex::decode(&response.data)?,
+	) {
+		return Err(anyhow!(
+			"UPGRADE : handoff response is not signed by the attested account {}",
+			response.enclave_account
+		))
+	}
+
+	Ok(())
+}
+
+/// MRENCLAVE of a hex encoded quote, None if the quote is too short
+pub fn quote_mrenclave(quote: &str) -> Option<String> {
+	let mrenclave: String = quote
+		.chars()
+		.skip(QUOTE_MRENCLAVE_OFFSET * 2)
+		.take(QUOTE_MRENCLAVE_LENGTH * 2)
+		.collect();
+
+	(mrenclave.len() == QUOTE_MRENCLAVE_LENGTH * 2).then(|| mrenclave.to_lowercase())
+}
+
+/// DEBUG attribute of a hex encoded quote, None if the quote is too short
+pub fn quote_debug(quote: &str) -> Option<bool> {
+	// Attributes follow the CPUSVN, MISCSELECT and reserved bytes of the report body
+	let offset = (QUOTE_HEADER_LENGTH + 48) * 2;
+	let attributes = quote.get(offset..offset + 2)?;
+	u8::from_str_radix(attributes, 16)
+		.ok()
+		.map(|flags| flags & ATTRIBUTE_DEBUG != 0)
+}
+
+/// Serialize and encrypt a handoff message to the ecies key of the new instance
+fn seal_message<T: Serialize>(
+	encryption_key: &[u8],
+	message: &T,
+) -> Result<Vec<u8>, anyhow::Error> {
+	let plain = serde_json::to_vec(message)?;
+	encrypt(encryption_key, &plain).map_err(|err| anyhow!("UPGRADE : encryption error : {err:?}"))
+}
+
+/// Decrypt and deserialize a handoff message
+fn open_message<T: DeserializeOwned>(
+	encryption_private_key: &[u8],
+	data: &[u8],
+) -> Result<T, anyhow::Error> {
+	let plain = decrypt(encryption_private_key, data)
+		.map_err(|err| anyhow!("UPGRADE : decryption error : {err:?}"))?;
+	Ok(serde_json::from_slice(&plain)?)
+}
+
+/* *************************************
+		 STORAGE KEY
+**************************************** */
+
+fn random_hex(length: usize) -> String {
+	let mut bytes = vec![0u8; length];
+	rand::thread_rng().fill_bytes(&mut bytes);
+	hex::encode(bytes)
+}
+
+/// Seal the storage key to this binary
+fn seal_storage_key(key: &StorageKey) -> Result<(), anyhow::Error> {
+	let mut file = File::create(STORAGE_KEY_FILE)?;
+	file.write_all(key)?;
+	file.sync_all()?;
+	Ok(())
+}
+
+/// Storage key sealed to this binary, generated at the first start
+fn sealed_storage_key() -> Result<StorageKey, anyhow::Error> {
+	if !Path::new(STORAGE_KEY_FILE).exists() {
+		info!("UPGRADE : generate the storage key of the encrypted mounts");
+		let mut key = StorageKey::default();
+		rand::thread_rng().fill_bytes(&mut key);
+		seal_storage_key(&key)?;
+		return Ok(key)
+	}
+
+	fs::read(STORAGE_KEY_FILE)?
+		.try_into()
+		.map_err(|_| anyhow!("UPGRADE : sealed storage key has an invalid size"))
+}
+
+/// Install the storage key of the encrypted mounts, before any sealed file is opened
+/// # Arguments
+/// * `handoff` - attested handoff, the storage key of the running instance is sealed to this binary
+pub fn setup_storage_key(handoff: Option<&HandoffClient>) -> Result<(), anyhow::Error> {
+	let key_device = Path::new(STORAGE_KEY_DEVICE);
+	if !key_device.parent().map_or(false, |keys| keys.exists()) {
+		warn!("UPGRADE : not inside an enclave, the storage key is not installed");
+		return Ok(())
+	}
+
+	let key = match handoff {
+		Some(handoff) => {
+			seal_storage_key(&handoff.storage_key)?;
+			handoff.storage_key
+		},
+		None => sealed_storage_key()?,
+	};
+
+	fs::write(key_device, key)?;
+	STORAGE_KEY
+		.set(key)
+		.map_err(|_| anyhow!("UPGRADE : storage key is already installed"))?;
+
+	info!("UPGRADE : storage key of the encrypted mounts is installed");
+	Ok(())
+}
+
+/// Copy the files sealed to the binary by the previous versions under the storage key, once.
+/// The legacy files are left untouched, the previous binary can still be started.
+/// # Arguments
+/// * `discard` - start without the legacy files which can not be read
+/// # Returns
+/// * `usize` - number of migrated files
+pub fn migrate_legacy_storage(discard: bool) -> Result<usize, anyhow::Error> {
+	if Path::new(LEGACY_MIGRATION_FILE).exists() {
+		return Ok(0)
+	}
+
+	let mut migrated = 0;
+	let mut discarded = 0;
+	for (legacy, current) in LEGACY_SEALED_MOUNTS {
+		let legacy_dir = Path::new(legacy);
+		if !legacy_dir.exists() {
+			continue
+		}
+
+		for entry in WalkDir::new(legacy_dir).min_depth(1) {
+			let entry = entry?;
+			let target = Path::new(current).join(entry.path().strip_prefix(legacy_dir)?);
+
+			if entry.file_type().is_dir() {
+				fs::create_dir_all(&target)?;
+				continue
+			}
+
+			// Written by this version, it is newer
+			if target.exists() {
+				continue
+			}
+
+			// Only the binary which sealed a file can read it
+			let content = match fs::read(entry.path()) {
+				Ok(content) => Zeroizing::new(content),
+				Err(err) if discard => {
+					warn!("UPGRADE : legacy file {:?} is discarded : {err:?}", entry.path());
+					discarded += 1;
+					continue
+				},
+				Err(err) =>
+					return Err(anyhow!(
+						"UPGRADE : legacy file {:?} can not be read, it is sealed to another binary. Start the previous binary to fetch a bulk backup, or restart with --discard-legacy-storage to synchronize from the other clusters : {err:?}",
+						entry.path()
+					)),
+			};
+
+			let staging = format!("{}.migrating", target.display());
+			let mut file = File::create(&staging)?;
+			file.write_all(&content)?;
+			file.sync_all()?;
+			fs::rename(&staging, &target)?;
+			migrated += 1;
+		}
+	}
+
+	fs::write(
+		LEGACY_MIGRATION_FILE,
+		json!({ "migrated": migrated, "discarded": discarded }).to_string(),
+	)?;
+	if migrated + discarded > 0 {
+		info!("UPGRADE : {migrated} legacy files are sealed with the storage key, {discarded} are discarded");
+	}
+
+	Ok(migrated)
+}
+
+/// Handle of the server, drained on takeover
+pub fn set_server_handle(handle: Handle) -> Result<(), anyhow::Error> {
+	SERVER_HANDLE
+		.set(handle)
+		.map_err(|_| anyhow!("UPGRADE : server handle is already set"))
+}
+
+/* *************************************
+		 ADMIN UPGRADE ARM
+**************************************** */
+
+/// Approve a handoff to the binary with the MRENCLAVE, for UPGRADE_ARM_PERIOD blocks
+/// # Arguments
+/// * `state` - SharedState
+/// * `request` - UpgradeArmPacket
+#[axum::debug_handler]
+pub async fn admin_upgrade_arm(
+	State(state): State<SharedState>,
+	caller: VerifiedCaller,
+	Json(request): Json<UpgradeArmPacket>,
+) -> impl IntoResponse {
+	debug!("ADMIN UPGRADE ARM : start");
+
+	let mrenclave = request.mrenclave.trim_start_matches("0x").to_lowercase();
+	if mrenclave.len() != QUOTE_MRENCLAVE_LENGTH * 2 || hex::decode(&mrenclave).is_err() {
+		let message = "ADMIN UPGRADE ARM : mrenclave must be 32 hex encoded bytes".to_string();
+		warn!(message);
+		return (StatusCode::BAD_REQUEST, Json(json!({ "error": message })))
+	}
+
+	if let Err((status, message)) =
+		caller.verify_data_hash(&upgrade_arm_data_hash(&request.mrenclave))
+	{
+		let message = format!("ADMIN UPGRADE ARM : {message}");
+		warn!(message);
+		return (status, Json(json!({ "error": message })))
+	}
+
+	let arm = UpgradeArm {
+		mrenclave,
+		expiry_block: get_blocknumber(&state).await + UPGRADE_ARM_PERIOD,
+		challenge: None,
+		session: None,
+	};
+
+	info!("ADMIN UPGRADE ARM : handoff to {} until block {}", arm.mrenclave, arm.expiry_block);
+	state.write().await.set_upgrade_arm(Some(arm.clone()));
+
+	(StatusCode::OK, Json(json!({ "armed": arm })))
+}
+
+/* *************************************
+	HANDOFF SERVER (RUNNING INSTANCE)
+**************************************** */
+
+/// Routes of the loopback handoff channel
+pub fn handoff_router(state: SharedState) -> Router {
+	Router::new()
+		.route("/upgrade/challenge", get(handoff_challenge))
+		.route("/upgrade/handoff", post(handoff_key))
+		.route("/upgrade/takeover", post(handoff_takeover))
+		.with_state(state)
+}
+
+/// Serve the handoff channel on the loopback interface
+/// # Arguments
+/// * `state` - SharedState
+/// * `port` - local port of the handoff channel
+/// * `handle` - handle to shutdown the channel
+pub async fn serve_handoff(
+	state: SharedState,
+	port: u16,
+	handle: Handle,
+) -> Result<(), anyhow::Error> {
+	let socket_addr = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+	info!("UPGRADE : handoff channel is listening on {}", socket_addr);
+
+	axum_server::bind(socket_addr)
+		.handle(handle)
+		.serve(handoff_router(state).into_make_service())
+		.await?;
+
+	Ok(())
+}
+
+/// Armed handoff of the running instance
+async fn active_arm(state: &SharedState) -> Result<UpgradeArm, (StatusCode, String)> {
+	let current_block_number = get_blocknumber(state).await;
+
+	match state.read().await.get_upgrade_arm() {
+		Some(arm) if arm.expiry_block >= current_block_number => Ok(arm),
+		Some(_) => Err((StatusCode::FORBIDDEN, "UPGRADE : upgrade arm is expired".to_string())),
+		None => Err((StatusCode::FORBIDDEN, "UPGRADE : upgrade is not armed".to_string())),
+	}
+}
+
+/// Single-use challenge, the new instance binds it to its quote
+async fn handoff_challenge(State(state): State<SharedState>) -> impl IntoResponse {
+	let mut arm = match active_arm(&state).await {
+		Ok(arm) => arm,
+		Err((status, message)) => {
+			warn!(message);
+			return (status, Json(json!({ "error": message })))
+		},
+	};
+
+	if arm.session.is_some() {
+		let message = "UPGRADE : a handoff is already in progress".to_string();
+		warn!(message);
+		return (StatusCode::CONFLICT, Json(json!({ "error": message })))
+	}
+
+	let challenge = random_hex(32);
+	arm.challenge = Some(challenge.clone());
+	state.write().await.set_upgrade_arm(Some(arm));
+
+	(StatusCode::OK, Json(json!({ "challenge": challenge })))
+}
+
+/// Sign and encrypt a handoff message to the new instance
+async fn handoff_response<T: Serialize>(
+	state: &SharedState,
+	encryption_key: &[u8],
+	message: &T,
+) -> Result<HandoffResponse, anyhow::Error> {
+	let data = seal_message(encryption_key, message)?;
+	let signature = get_keypair(state).await.sign(&data);
+
+	Ok(HandoffResponse {
+		enclave_account: get_accountid(state).await,
+		data: hex::encode(data),
+		signature: format!("{}{:?}", "0x", signature),
+		quote: None,
+	})
+}
+
+/// Quote of the running instance, its report data is the signature of the running token
+async fn running_quote(
+	state: &SharedState,
+	challenge: &str,
+	encryption_account: &str,
+) -> Result<String, anyhow::Error> {
+	let token = running_token(&get_accountid(state).await, challenge, encryption_account);
+	let report_data = get_keypair(state).await.sign(token.as_bytes()).0;
+
+	let quote = generate_quote(&report_data)
+		.map_err(|err| anyhow!("UPGRADE : can not generate the quote : {err}"))?;
+	record_attestation(Some(get_blocknumber(state).await), QuotePurpose::UPGRADE, &quote);
+
+	Ok(hex::encode(quote))
+}
+
+/// Verify the quote of the new instance, and send it the storage key
+/// The quote must be attested, contain the challenge signed by the ephemeral account of the
+/// request, and have the armed MRENCLAVE
+async fn handoff_key(
+	State(state): State<SharedState>,
+	Json(request): Json<HandoffRequest>,
+) -> impl IntoResponse {
+	debug!("UPGRADE HANDOFF : start");
+
+	// The new build would receive an account which is about to be replaced
+	if let Some(pending) = pending_account_rotation() {
+		let message = format!(
+			"UPGRADE HANDOFF : rotation to {} is pending, complete it first",
+			pending.rotation.new_account
+		);
+		warn!(message);
+		return (StatusCode::CONFLICT, Json(json!({ "error": message })))
+	}
+
+	let mut arm = match active_arm(&state).await {
+		Ok(arm) => arm,
+		Err((status, message)) => {
+			warn!(message);
+			return (status, Json(json!({ "error": message })))
+		},
+	};
+
+	// The challenge is consumed by any attempt
+	let challenge = match arm.challenge.take() {
+		Some(challenge) if arm.session.is_none() => challenge,
+		_ => {
+			let message = "UPGRADE HANDOFF : no pending challenge".to_string();
+			warn!(message);
+			return (StatusCode::CONFLICT, Json(json!({ "error": message })))
+		},
+	};
+	state.write().await.set_upgrade_arm(Some(arm.clone()));
+
+	let quote_body: QuoteResponse = match serde_json::from_str(&request.quote) {
+		Ok(body) => body,
+		Err(err) => {
+			let message = format!("UPGRADE HANDOFF : can not deserialize the quote : {err:?}");
+			warn!(message);
+			return (StatusCode::BAD_REQUEST, Json(json!({ "error": message })))
+		},
+	};
+
+	let client = match with_http_proxy(reqwest::Client::builder()).https_only(true).build() {
+		Ok(client) => client,
+		Err(err) => {
+			let message = format!("UPGRADE HANDOFF : unable to build a Reqwest client : {err:?}");
+			error!(message);
+			return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })))
+		},
+	};
+
+	let quote = match attest_quote(&state, &client, &quote_body, "upgrade-handoff").await {
+		Ok(quote) => quote,
+		Err(message) => {
+			warn!(message);
+			return (StatusCode::UNAUTHORIZED, Json(json!({ "error": message })))
+		},
+	};
+
+	let report_data: String = quote
+		.chars()
+		.skip(QUOTE_REPORT_DATA_OFFSET * 2)
+		.take(QUOTE_REPORT_DATA_LENGTH * 2)
+		.collect();
+	let token = format!("{}_{}_{}", request.account, challenge, request.encryption_account);
+
+	if !verify_signature(&request.account, report_data, token.as_bytes()) {
+		let message = "UPGRADE HANDOFF : invalid report data signature".to_string();
+		warn!(message);
+		return (StatusCode::UNAUTHORIZED, Json(json!({ "error": message })))
+	}
+
+	if quote_mrenclave(&quote).as_ref() != Some(&arm.mrenclave) {
+		let message = format!(
+			"UPGRADE HANDOFF : mrenclave {:?} is not armed",
+			quote_mrenclave(&quote).unwrap_or_default()
+		);
+		warn!(message);
+		sentry::capture_message(&message, sentry::Level::Warning);
+		return (StatusCode::FORBIDDEN, Json(json!({ "error": message })))
+	}
+
+	// The host reads the memory of a debug enclave, it does not receive the storage key
+	if quote_debug(&quote) != Some(false) && !accepts_debug_enclaves() {
+		let message = "UPGRADE HANDOFF : requester is a debug enclave".to_string();
+		warn!(message);
+		sentry::capture_message(&message, sentry::Level::Warning);
+		return (StatusCode::FORBIDDEN, Json(json!({ "error": message })))
+	}
+
+	let (encryption_key, storage_key) =
+		match (hex::decode(&request.encryption_account), STORAGE_KEY.get()) {
+			(Ok(encryption_key), Some(storage_key)) => (encryption_key, storage_key),
+			(Err(err), _) => {
+				let message = format!("UPGRADE HANDOFF : invalid encryption account : {err:?}");
+				warn!(message);
+				return (StatusCode::BAD_REQUEST, Json(json!({ "error": message })))
+			},
+			(_, None) => {
+				let message = "UPGRADE HANDOFF : storage key is not installed".to_string();
+				error!(message);
+				return (StatusCode::PRECONDITION_FAILED, Json(json!({ "error": message })))
+			},
+		};
+
+	let session = HandoffSession { token: random_hex(32), encryption_key };
+	let account_phrase = match sealed_account_phrase() {
+		Ok(phrase) => Some(phrase.to_string()),
+		Err(err) => {
+			let message = format!("UPGRADE HANDOFF : enclave account is not sealed : {err:?}");
+			error!(message);
+			return (StatusCode::PRECONDITION_FAILED, Json(json!({ "error": message })))
+		},
+	};
+	let key = HandoffKey {
+		session: session.token.clone(),
+		storage_key: hex::encode(storage_key),
+		account_phrase,
+	};
+
+	let mut response = match handoff_response(&state, &session.encryption_key, &key).await {
+		Ok(response) => response,
+		Err(err) => {
+			let message = format!("UPGRADE HANDOFF : {err:?}");
+			error!(message);
+			return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })))
+		},
+	};
+
+	// The new instance attests the running one before it installs the storage key
+	response.quote = match running_quote(&state, &challenge, &request.encryption_account).await {
+		Ok(quote) => Some(quote),
+		Err(err) => {
+			let message = format!("UPGRADE HANDOFF : {err:?}");
+			error!(message);
+			return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })))
+		},
+	};
+
+	arm.session = Some(session);
+	state.write().await.set_upgrade_arm(Some(arm));
+
+	info!(
+		"UPGRADE HANDOFF : storage key is sent to {}",
+		quote_mrenclave(&quote).unwrap_or_default()
+	);
+	(StatusCode::OK, Json(json!(response)))
+}
+
+/// Stop accepting, drain in-flight requests, and send the in-memory state to the new instance.
+/// The server of the running instance exits afterwards.
+async fn handoff_takeover(
+	State(state): State<SharedState>,
+	Json(request): Json<TakeoverRequest>,
+) -> impl IntoResponse {
+	debug!("UPGRADE TAKEOVER : start");
+
+	// The session is consumed, a takeover happens once
+	let session = {
+		let shared_state_write = &mut state.write().await;
+		match shared_state_write.get_upgrade_arm().and_then(|arm| arm.session) {
+			Some(session) if session.token == request.session => {
+				shared_state_write.set_upgrade_arm(None);
+				session
+			},
+			_ => {
+				let message = "UPGRADE TAKEOVER : invalid handoff session".to_string();
+				warn!(message);
+				return (StatusCode::FORBIDDEN, Json(json!({ "error": message })))
+			},
+		}
+	};
+
+	if let Some(handle) = SERVER_HANDLE.get() {
+		info!("UPGRADE TAKEOVER : stop accepting, drain {} connections", handle.connection_count());
+		handle.graceful_shutdown(Some(Duration::from_secs(UPGRADE_DRAIN_TIMEOUT)));
+
+		let deadline = Instant::now() + Duration::from_secs(UPGRADE_DRAIN_TIMEOUT + 1);
+		while handle.connection_count() > 0 && Instant::now() < deadline {
+			tokio::time::sleep(Duration::from_millis(100)).await;
+		}
+	}
+
+	// Nothing is served anymore, the state is final
+	let handoff_state = {
+		let shared_state_read = state.read().await;
+		HandoffState {
+			block_number: shared_state_read.get_current_block(),
+			audit_head: shared_state_read.get_audit_head().clone(),
+			replay_journal: shared_state_read.get_replay_journal().clone(),
+			negative_cache: negative_cache_snapshot(),
+			quota: shared_state_read.get_quota().snapshot(Instant::now()),
+		}
+	};
+
+	match handoff_response(&state, &session.encryption_key, &handoff_state).await {
+		Ok(response) => {
+			info!(
+				"UPGRADE TAKEOVER : state is sent, audit head {}, {} replay entries",
+				handoff_state.audit_head.next_index,
+				handoff_state.replay_journal.len()
+			);
+			(StatusCode::OK, Json(json!(response)))
+		},
+		Err(err) => {
+			let message = format!("UPGRADE TAKEOVER : {err:?}");
+			error!(message);
+			(StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })))
+		},
+	}
+}
+
+/* *************************************
+	HANDOFF CLIENT (NEW INSTANCE)
+**************************************** */
+
+/// Attested handoff of the new instance
+pub struct HandoffClient {
+	url: String,
+	client: reqwest::Client,
+	encryption_private_key: [u8; 32],
+	session: String,
+	// Enclave account of the running instance and its signed key response
+	key_response: HandoffResponse,
+	pub storage_key: StorageKey,
+	// Phrase of the enclave account, sealed to this binary before the sealed files are opened
+	pub account_phrase: Option<Zeroizing<String>>,
+}
+
+impl HandoffClient {
+	/// Attest to the running instance and receive its storage key. The quote of the running
+	/// instance is verified before the storage key and the account phrase are decrypted : trusted
+	/// measurement, no debug enclave on mainnet and alphanet, platform status, and report data
+	/// bound to the handoff channel key
+	/// # Arguments
+	/// * `port` - local port of the handoff channel of the running instance
+	pub async fn request(port: u16) -> Result<HandoffClient, anyhow::Error> {
+		let url = format!("http://{}:{}", Ipv4Addr::LOCALHOST, port);
+		let client = reqwest::Client::builder().no_proxy().build()?;
+
+		let challenge: serde_json::Value = client
+			.get(format!("{url}/upgrade/challenge"))
+			.send()
+			.await?
+			.error_for_status()?
+			.json()
+			.await?;
+		let challenge = challenge["challenge"]
+			.as_str()
+			.ok_or_else(|| anyhow!("UPGRADE : invalid challenge response : {challenge}"))?;
+
+		let account_keypair = sr25519::Pair::generate().0;
+		let account = account_keypair.public().to_ss58check();
+		let (sk, pk) = generate_keypair();
+		let encryption_account = hex::encode(pk.serialize());
+
+		let token = format!("{account}_{challenge}_{encryption_account}");
+		write_user_report_data(None, &account_keypair.sign(token.as_bytes()).0)?;
+		let quote_content = get_quote_content()?;
+		record_attestation(None, QuotePurpose::UPGRADE, &quote_content);
+		let quote = serde_json::to_string(&QuoteResponse {
+			block_number: 0,
+			data: hex::encode(quote_content),
+			// Report data is the signature of the upgrade token
+			report_data_preimage: None,
+		})?;
+
+		let key_response: HandoffResponse = client
+			.post(format!("{url}/upgrade/handoff"))
+			.json(&HandoffRequest {
+				account,
+				encryption_account: encryption_account.clone(),
+				quote,
+			})
+			.send()
+			.await?
+			.error_for_status()?
+			.json()
+			.await?;
+
+		let running_quote = key_response
+			.quote
+			.as_deref()
+			.ok_or_else(|| anyhow!("UPGRADE : running instance did not attest the handoff"))?;
+		let attestation_client = with_http_proxy(reqwest::Client::builder()).build()?;
+		let verdict = verify_peer_quote(&attestation_client, running_quote).await?;
+		check_running_binding(&key_response, &verdict.report_data, challenge, &encryption_account)?;
+		info!(
+			"UPGRADE : running instance is attested, MRENCLAVE {}, MRSIGNER {}",
+			verdict.mrenclave, verdict.mrsigner
+		);
+
+		let encryption_private_key = sk.serialize();
+		let key: HandoffKey =
+			open_message(&encryption_private_key, &hex::decode(&key_response.data)?)?;
+		let storage_key = hex::decode(&key.storage_key)?
+			.try_into()
+			.map_err(|_| anyhow!("UPGRADE : received storage key has an invalid size"))?;
+
+		let account_phrase = key.account_phrase.map(Zeroizing::new);
+		if let Some(phrase) = &account_phrase {
+			let account = sr25519::Pair::from_phrase(phrase.trim(), None)
+				.map_err(|err| anyhow!("UPGRADE : received account phrase is invalid : {err:?}"))?
+				.0
+				.public()
+				.to_ss58check();
+			if account != key_response.enclave_account {
+				return Err(anyhow!(
+					"UPGRADE : received account {account} did not sign the handoff response"
+				))
+			}
+		}
+
+		info!("UPGRADE : storage key is received from {}", key_response.enclave_account);
+
+		Ok(HandoffClient {
+			url,
+			client,
+			encryption_private_key,
+			session: key.session,
+			key_response,
+			storage_key,
+			account_phrase,
+		})
+	}
+
+	/// Take over the running instance and restore its in-memory state.
+	/// The takeover response must be signed by the enclave account that the quote of the running
+	/// instance attested, which is also the account read from the sealed files.
+	/// # Arguments
+	/// * `state` - SharedState of the new instance
+	pub async fn takeover(&self, state: &SharedState) -> Result<(), anyhow::Error> {
+		let enclave_account = self.key_response.enclave_account.clone();
+		if get_accountid(state).await != enclave_account {
+			return Err(anyhow!(
+				"UPGRADE : sealed enclave account is not the attested account {enclave_account}"
+			))
+		}
+
+		let verify = |response: &HandoffResponse| -> Result<Vec<u8>, anyhow::Error> {
+			let data = hex::decode(&response.data)?;
+			if response.enclave_account != enclave_account ||
+				!verify_signature(&enclave_account, response.signature.clone(), &data)
+			{
+				return Err(anyhow!(
+					"UPGRADE : handoff response is not signed by the enclave account {enclave_account}"
+				))
+			}
+			Ok(data)
+		};
+
+		verify(&self.key_response)?;
+
+		let takeover_response: HandoffResponse = self
+			.client
+			.post(format!("{}/upgrade/takeover", self.url))
+			.json(&TakeoverRequest { session: self.session.clone() })
+			.send()
+			.await?
+			.error_for_status()?
+			.json()
+			.await?;
+
+		let handoff_state: HandoffState =
+			open_message(&self.encryption_private_key, &verify(&takeover_response)?)?;
+
+		info!(
+			"UPGRADE : restore state of block {}, audit head {}, {} replay entries",
+			handoff_state.block_number,
+			handoff_state.audit_head.next_index,
+			handoff_state.replay_journal.len()
+		);
+
+		{
+			let shared_state_write = &mut state.write().await;
+			shared_state_write.set_audit_head(handoff_state.audit_head);
+			shared_state_write.set_replay_journal(handoff_state.replay_journal);
+			shared_state_write
+				.set_quota(QuotaLimiter::from_snapshot(handoff_state.quota, Instant::now()));
+		}
+
+		restore_negative_cache(handoff_state.negative_cache);
+
+		// The handed over digests survive a restart of the new enclave
+		compact_replay_journal(state).await
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn handoff_message_test() {
+		let (sk, pk) = generate_keypair();
+		let handoff_state = HandoffState {
+			block_number: 1000,
+			audit_head: AuditHead { next_index: 7, last_hash: "ab".repeat(32) },
+			..Default::default()
+		};
+
+		let sealed = seal_message(&pk.serialize(), &handoff_state).unwrap();
+		let opened: HandoffState = open_message(&sk.serialize(), &sealed).unwrap();
+		assert_eq!(opened.block_number, 1000);
+		assert_eq!(opened.audit_head, handoff_state.audit_head);
+
+		// Another key can not open it
+		let (other_sk, _) = generate_keypair();
+		assert!(open_message::<HandoffState>(&other_sk.serialize(), &sealed).is_err());
+	}
+
+	#[test]
+	fn quote_mrenclave_test() {
+		let mrenclave = "AB".repeat(QUOTE_MRENCLAVE_LENGTH);
+		let quote =
+			format!("{}{}{}", "00".repeat(QUOTE_MRENCLAVE_OFFSET), mrenclave, "11".repeat(64));
+		assert_eq!(quote_mrenclave(&quote), Some(mrenclave.to_lowercase()));
+		assert_eq!(quote_mrenclave(&"00".repeat(QUOTE_MRENCLAVE_OFFSET + 8)), None);
+
+		assert_eq!(quote_debug(&quote), Some(false));
+		let offset = (QUOTE_HEADER_LENGTH + 48) * 2;
+		let debug_quote = format!("{}02{}", &quote[..offset], &quote[offset + 2..]);
+		assert_eq!(quote_debug(&debug_quote), Some(true));
+		assert_eq!(quote_debug("00"), None);
+
+		assert_ne!(upgrade_arm_data_hash("aa"), upgrade_arm_data_hash("bb"));
+	}
+
+	#[test]
+	fn running_binding_test() {
+		let enclave_keypair = sr25519::Pair::generate().0;
+		let enclave_account = enclave_keypair.public().to_ss58check();
+		let (_, pk) = generate_keypair();
+		let encryption_account = hex::encode(pk.serialize());
+
+		let data = b"sealed storage key".to_vec();
+		let response = HandoffResponse {
+			enclave_account: enclave_account.clone(),
+			data: hex::encode(&data),
+			signature: format!("0x{:?}", enclave_keypair.sign(&data)),
+			quote: None,
+		};
+
+		let token = running_token(&enclave_account, "challenge", &encryption_account);
+		let report_data = hex::encode(enclave_keypair.sign(token.as_bytes()).0);
+		assert!(check_running_binding(&response, &report_data, "challenge", &encryption_account)
+			.is_ok());
+
+		// Another channel key or challenge is not bound
+		let (_, other_pk) = generate_keypair();
+		let other_account = hex::encode(other_pk.serialize());
+		assert!(
+			check_running_binding(&response, &report_data, "challenge", &other_account).is_err()
+		);
+		assert!(
+			check_running_binding(&response, &report_data, "other", &encryption_account).is_err()
+		);
+
+		// Report data of another account
+		let other_keypair = sr25519::Pair::generate().0;
+		let forged = hex::encode(other_keypair.sign(token.as_bytes()).0);
+		assert!(
+			check_running_binding(&response, &forged, "challenge", &encryption_account).is_err()
+		);
+	}
+}
@@ -0,0 +1,538 @@
+use std::{
+	collections::BTreeSet,
+	fs::{self, File},
+	io::{BufWriter, Read, Write},
+	path::{Path, PathBuf},
+	sync::Mutex,
+};
+
+use anyhow::anyhow;
+use axum::{
+	body::Bytes,
+	extract::{Path as UrlPath, Query, State},
+	http::{HeaderMap, StatusCode},
+	response::IntoResponse,
+	Json,
+};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+	chain::{
+		constants::{
+			MAX_UPLOAD_PART_SIZE, MAX_UPLOAD_SESSIONS, MAX_UPLOAD_SIZE, UPLOAD_PATH,
+			UPLOAD_SESSION_PERIOD,
+		},
+		verify::verify_writable,
+	},
+	servers::{
+		auth::VerifiedCaller,
+		state::{get_blocknumber, SharedState},
+	},
+};
+
+use super::admin_bulk::restore_backup_archive;
+
+/* *************************************
+	RESUMABLE RESTORE UPLOADS
+**************************************** */
+
+// A backup archive is uploaded by parts to the sealed upload directory :
+// 1. The admin quorum opens an upload with the hash and size of the archive
+// 2. Parts are uploaded with their hash, in any order, a failed part is uploaded again
+// 3. On finalize, the parts are assembled and the archive hash is checked before the restore
+// The upload id is the capability of the parts, it is only returned to the admin quorum.
+
+/// Header of the hex encoded sha256 of an uploaded part
+pub const PART_HASH_HEADER: &str = "x-part-sha256";
+
+const SESSION_FILE: &str = "session.json";
+const ARCHIVE_FILE: &str = "archive.zip";
+
+// Uploads being assembled, a session is finalized once
+static FINALIZING: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+/// Resumable upload of a backup archive, persisted next to its parts
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct UploadSession {
+	pub upload_id: String,
+	// Hex encoded sha256 of the whole archive
+	pub archive_hash: String,
+	pub total_size: u64,
+	pub part_size: u64,
+	pub expiry_block: u32,
+}
+
+/// Upload init request, signed by the admin quorum
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UploadInitPacket {
+	archive_hash: String,
+	total_size: u64,
+	part_size: u64,
+}
+
+/// Canonical hash of the upload parameters, signed inside the authentication token
+pub fn upload_data_hash(archive_hash: &str, total_size: u64, part_size: u64) -> String {
+	sha256::digest(format!("upload-init_{archive_hash}_{total_size}_{part_size}").as_bytes())
+}
+
+impl UploadSession {
+	/// Number of parts, the last one may be shorter
+	pub fn part_count(&self) -> u32 {
+		self.total_size.div_ceil(self.part_size) as u32
+	}
+
+	/// Expected size of a part, None if the index is out of the archive
+	pub fn part_len(&self, index: u32) -> Option<u64> {
+		let start = index as u64 * self.part_size;
+		(index < self.part_count()).then(|| self.part_size.min(self.total_size - start))
+	}
+
+	fn dir(&self) -> PathBuf {
+		session_dir(&self.upload_id)
+	}
+
+	/// Indices of the parts received so far
+	pub fn received_parts(&self, dir: &Path) -> Vec<u32> {
+		(0..self.part_count()).filter(|index| part_path(dir, *index).exists()).collect()
+	}
+}
+
+fn part_path(dir: &Path, index: u32) -> PathBuf {
+	dir.join(format!("{index}.part"))
+}
+
+fn session_dir(upload_id: &str) -> PathBuf {
+	Path::new(UPLOAD_PATH).join(upload_id)
+}
+
+// Upload ids are 128 bits random hex strings, anything else never names a directory
+fn valid_upload_id(upload_id: &str) -> bool {
+	upload_id.len() == 32 && upload_id.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn load_session(upload_id: &str) -> Result<UploadSession, (StatusCode, String)> {
+	if !valid_upload_id(upload_id) {
+		return Err((StatusCode::BAD_REQUEST, format!("invalid upload id {upload_id}")))
+	}
+
+	let data = fs::read(session_dir(upload_id).join(SESSION_FILE))
+		.map_err(|_| (StatusCode::NOT_FOUND, format!("unknown upload id {upload_id}")))?;
+
+	serde_json::from_slice(&data)
+		.map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, format!("invalid session : {err:?}")))
+}
+
+fn remove_session(upload_id: &str) {
+	if let Err(err) = fs::remove_dir_all(session_dir(upload_id)) {
+		error!("BACKUP UPLOAD : error removing upload {upload_id} : {err:?}");
+	}
+}
+
+/// Remove the expired uploads, returns the number of active ones
+fn prune_sessions(current_block: u32) -> usize {
+	let entries = match fs::read_dir(UPLOAD_PATH) {
+		Ok(entries) => entries,
+		Err(_) => return 0,
+	};
+
+	let mut active = 0;
+	for entry in entries.flatten() {
+		let upload_id = entry.file_name().to_string_lossy().to_string();
+		match load_session(&upload_id) {
+			Ok(session) if session.expiry_block >= current_block => active += 1,
+			_ => {
+				debug!("BACKUP UPLOAD : removing expired upload {upload_id}");
+				remove_session(&upload_id);
+			},
+		}
+	}
+
+	active
+}
+
+/// Concatenate the parts of an upload into the archive, checking its size and hash
+/// # Arguments
+/// * `session` - upload session
+/// * `dir` - directory of the parts
+/// * `keep_parts` - keep the parts after the assembly, for a dry-run
+/// # Returns
+/// * `PathBuf` - assembled archive in the upload directory
+/// # Errors
+/// * Missing part, size or hash mismatch of the archive
+pub fn assemble_parts(
+	session: &UploadSession,
+	dir: &Path,
+	keep_parts: bool,
+) -> Result<PathBuf, anyhow::Error> {
+	let archive_path = dir.join(ARCHIVE_FILE);
+	let mut writer = BufWriter::new(File::create(&archive_path)?);
+	let mut hasher = Sha256::new();
+	let mut buffer = vec![0u8; 64 * 1024];
+	let mut size = 0u64;
+
+	for index in 0..session.part_count() {
+		let mut part = File::open(part_path(dir, index))
+			.map_err(|err| anyhow!("BACKUP UPLOAD : missing part {index} : {err:?}"))?;
+
+		loop {
+			let read = part.read(&mut buffer)?;
+			if read == 0 {
+				break
+			}
+			hasher.update(&buffer[..read]);
+			writer.write_all(&buffer[..read])?;
+			size += read as u64;
+		}
+	}
+
+	writer.flush()?;
+
+	if size != session.total_size {
+		return Err(anyhow!("BACKUP UPLOAD : archive size {size} != {}", session.total_size))
+	}
+
+	let archive_hash = hex::encode(hasher.finalize());
+	if archive_hash != session.archive_hash {
+		return Err(anyhow!("BACKUP UPLOAD : archive hash mismatch {archive_hash}"))
+	}
+
+	if !keep_parts {
+		for index in 0..session.part_count() {
+			let _ = fs::remove_file(part_path(dir, index));
+		}
+	}
+
+	Ok(archive_path)
+}
+
+/* *************************************
+		 UPLOAD ENDPOINTS
+**************************************** */
+
+/// Open a resumable upload of a backup archive
+/// # Arguments
+/// * `state` - SharedState
+/// * `request` - UploadInitPacket
+#[axum::debug_handler]
+pub async fn admin_upload_init(
+	State(state): State<SharedState>,
+	caller: VerifiedCaller,
+	Json(request): Json<UploadInitPacket>,
+) -> impl IntoResponse {
+	debug!("ADMIN UPLOAD INIT : start");
+
+	if let Err(err) = verify_writable(&state).await {
+		let message = format!("ADMIN UPLOAD INIT : restore is rejected : {err:?}");
+		warn!(message);
+		return (StatusCode::SERVICE_UNAVAILABLE, Json(json!({ "error": message })))
+	}
+
+	let data_hash = upload_data_hash(&request.archive_hash, request.total_size, request.part_size);
+	if let Err((status, message)) = caller.verify_data_hash(&data_hash) {
+		let message = format!("ADMIN UPLOAD INIT : {message}");
+		warn!(message);
+		return (status, Json(json!({ "error": message })))
+	}
+
+	if request.total_size == 0 ||
+		request.total_size > MAX_UPLOAD_SIZE ||
+		request.part_size == 0 ||
+		request.part_size > MAX_UPLOAD_PART_SIZE ||
+		request.archive_hash.len() != 64
+	{
+		let message = format!(
+			"ADMIN UPLOAD INIT : invalid upload, archive is at most {MAX_UPLOAD_SIZE} bytes, parts are at most {MAX_UPLOAD_PART_SIZE} bytes"
+		);
+		warn!(message);
+		return (StatusCode::BAD_REQUEST, Json(json!({ "error": message })))
+	}
+
+	let current_block = get_blocknumber(&state).await;
+	if prune_sessions(current_block) >= MAX_UPLOAD_SESSIONS {
+		let message = format!("ADMIN UPLOAD INIT : {MAX_UPLOAD_SESSIONS} uploads are in progress");
+		warn!(message);
+		return (StatusCode::TOO_MANY_REQUESTS, Json(json!({ "error": message })))
+	}
+
+	let mut upload_id = [0u8; 16];
+	rand::thread_rng().fill_bytes(&mut upload_id);
+
+	let session = UploadSession {
+		upload_id: hex::encode(upload_id),
+		archive_hash: request.archive_hash.to_lowercase(),
+		total_size: request.total_size,
+		part_size: request.part_size,
+		expiry_block: current_block + UPLOAD_SESSION_PERIOD,
+	};
+
+	let saved = fs::create_dir_all(session.dir())
+		.and_then(|_| fs::write(session.dir().join(SESSION_FILE), serde_json::to_vec(&session)?));
+
+	if let Err(err) = saved {
+		let message = format!("ADMIN UPLOAD INIT : error saving the upload session : {err:?}");
+		error!(message);
+		return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })))
+	}
+
+	info!(
+		"ADMIN UPLOAD INIT : upload of {} bytes in {} parts, until block {}",
+		session.total_size,
+		session.part_count(),
+		session.expiry_block
+	);
+
+	(
+		StatusCode::OK,
+		Json(json!({
+			"upload_id": session.upload_id,
+			"part_count": session.part_count(),
+			"part_size": session.part_size,
+			"expiry_block": session.expiry_block,
+		})),
+	)
+}
+
+/// Store a part of an upload, an uploaded part is replaced
+/// # Arguments
+/// * `upload_id`, `index` - url path
+/// * `headers` - `x-part-sha256` hex encoded sha256 of the part
+#[axum::debug_handler]
+pub async fn admin_upload_part(
+	UrlPath((upload_id, index)): UrlPath<(String, u32)>,
+	headers: HeaderMap,
+	part: Bytes,
+) -> impl IntoResponse {
+	let session = match load_session(&upload_id) {
+		Ok(session) => session,
+		Err((status, message)) => {
+			let message = format!("ADMIN UPLOAD PART : {message}");
+			warn!(message);
+			return (status, Json(json!({ "error": message })))
+		},
+	};
+
+	if session.part_len(index) != Some(part.len() as u64) {
+		let message = format!(
+			"ADMIN UPLOAD PART : part {index} of {} bytes, expected {:?}",
+			part.len(),
+			session.part_len(index)
+		);
+		warn!(message);
+		return (StatusCode::BAD_REQUEST, Json(json!({ "error": message })))
+	}
+
+	let part_hash = headers.get(PART_HASH_HEADER).and_then(|value| value.to_str().ok());
+	if part_hash.map(str::to_lowercase) != Some(sha256::digest(part.as_ref())) {
+		let message = format!("ADMIN UPLOAD PART : hash mismatch of part {index}, upload it again");
+		warn!(message);
+		return (StatusCode::UNPROCESSABLE_ENTITY, Json(json!({ "error": message })))
+	}
+
+	// Written aside then renamed, an interrupted write never looks like a received part
+	let temp_path = session.dir().join(format!("{index}.tmp"));
+	if let Err(err) = fs::write(&temp_path, &part)
+		.and_then(|_| fs::rename(&temp_path, part_path(&session.dir(), index)))
+	{
+		let message = format!("ADMIN UPLOAD PART : error storing part {index} : {err:?}");
+		error!(message);
+		return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })))
+	}
+
+	debug!("ADMIN UPLOAD PART : upload {upload_id} part {index} stored");
+
+	(StatusCode::OK, Json(json!({ "upload_id": upload_id, "index": index })))
+}
+
+/// Parts received so far, to resume an interrupted upload
+#[axum::debug_handler]
+pub async fn admin_upload_status(UrlPath(upload_id): UrlPath<String>) -> impl IntoResponse {
+	let session = match load_session(&upload_id) {
+		Ok(session) => session,
+		Err((status, message)) =>
+			return (status, Json(json!({ "error": format!("ADMIN UPLOAD STATUS : {message}") }))),
+	};
+
+	let received = session.received_parts(&session.dir());
+	let missing: Vec<u32> =
+		(0..session.part_count()).filter(|index| !received.contains(index)).collect();
+
+	(
+		StatusCode::OK,
+		Json(json!({
+			"upload_id": session.upload_id,
+			"part_count": session.part_count(),
+			"part_size": session.part_size,
+			"expiry_block": session.expiry_block,
+			"received": received,
+			"missing": missing,
+		})),
+	)
+}
+
+/// Query of the finalize endpoint
+#[derive(Deserialize, Debug)]
+pub struct FinalizeQuery {
+	// False for a dry-run, the parts are kept and the upload can be finalized again
+	pub apply: Option<bool>,
+	// Comma separated nfts of a selective restore
+	pub nftids: Option<String>,
+}
+
+impl FinalizeQuery {
+	/// Nfts of a selective restore, None for the whole archive
+	pub fn nft_ids(&self) -> Result<Option<BTreeSet<u32>>, std::num::ParseIntError> {
+		self.nftids
+			.as_ref()
+			.map(|ids| ids.split(',').map(|id| id.trim().parse::<u32>()).collect())
+			.transpose()
+	}
+}
+
+/// Assemble the parts, check the archive hash and restore the archive
+#[axum::debug_handler]
+pub async fn admin_upload_finalize(
+	State(state): State<SharedState>,
+	UrlPath(upload_id): UrlPath<String>,
+	Query(query): Query<FinalizeQuery>,
+) -> impl IntoResponse {
+	let apply = query.apply.unwrap_or(true);
+	let nft_ids = match query.nft_ids() {
+		Ok(nft_ids) => nft_ids,
+		Err(err) => {
+			let message = format!("ADMIN UPLOAD FINALIZE : invalid nftids : {err:?}");
+			warn!(message);
+			return (StatusCode::BAD_REQUEST, Json(json!({ "error": message }))).into_response()
+		},
+	};
+
+	let session = match load_session(&upload_id) {
+		Ok(session) => session,
+		Err((status, message)) => {
+			let message = format!("ADMIN UPLOAD FINALIZE : {message}");
+			warn!(message);
+			return (status, Json(json!({ "error": message }))).into_response()
+		},
+	};
+
+	if let Err(err) = verify_writable(&state).await {
+		let message = format!("ADMIN UPLOAD FINALIZE : restore is rejected : {err:?}");
+		warn!(message);
+		return (StatusCode::SERVICE_UNAVAILABLE, Json(json!({ "error": message }))).into_response()
+	}
+
+	match FINALIZING.lock() {
+		Ok(mut finalizing) =>
+			if !finalizing.insert(upload_id.clone()) {
+				let message = format!("ADMIN UPLOAD FINALIZE : upload {upload_id} is in progress");
+				return (StatusCode::CONFLICT, Json(json!({ "error": message }))).into_response()
+			},
+		Err(err) => {
+			let message = format!("ADMIN UPLOAD FINALIZE : lock error : {err:?}");
+			error!(message);
+			return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })))
+				.into_response()
+		},
+	}
+
+	let response = match assemble_parts(&session, &session.dir(), !apply) {
+		Ok(archive_path) => {
+			let response = restore_backup_archive(
+				&state,
+				archive_path.to_string_lossy().to_string(),
+				apply,
+				nft_ids,
+				None,
+			)
+			.await;
+			if apply && response.status().is_success() {
+				remove_session(&upload_id);
+			}
+			response
+		},
+		Err(err) => {
+			// The parts stay, missing or corrupted ones can be uploaded again
+			let message = format!("ADMIN UPLOAD FINALIZE : {err:?}");
+			warn!(message);
+			(StatusCode::UNPROCESSABLE_ENTITY, Json(json!({ "error": message }))).into_response()
+		},
+	};
+
+	if let Ok(mut finalizing) = FINALIZING.lock() {
+		finalizing.remove(&upload_id);
+	}
+
+	response
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn part_layout_test() {
+		let session = UploadSession {
+			upload_id: "00112233445566778899aabbccddeeff".to_string(),
+			archive_hash: String::new(),
+			total_size: 25,
+			part_size: 10,
+			expiry_block: 0,
+		};
+
+		assert_eq!(session.part_count(), 3);
+		assert_eq!(session.part_len(0), Some(10));
+		assert_eq!(session.part_len(2), Some(5));
+		assert_eq!(session.part_len(3), None);
+
+		assert!(valid_upload_id(&session.upload_id));
+		assert!(!valid_upload_id("../../nft/enclave_account.key"));
+		assert!(!valid_upload_id("00112233"));
+
+		let query = FinalizeQuery { apply: None, nftids: Some("12, 7,12".to_string()) };
+		assert_eq!(query.nft_ids().unwrap(), Some(BTreeSet::from([7, 12])));
+		let query = FinalizeQuery { apply: None, nftids: Some("12,x".to_string()) };
+		assert!(query.nft_ids().is_err());
+	}
+
+	#[test]
+	fn assemble_parts_test() {
+		let dir = std::env::temp_dir().join(format!("backup-upload-{}", std::process::id()));
+		fs::create_dir_all(&dir).unwrap();
+
+		let archive: Vec<u8> = (0..25u8).collect();
+		let mut session = UploadSession {
+			upload_id: "00112233445566778899aabbccddeeff".to_string(),
+			archive_hash: sha256::digest(archive.as_slice()),
+			total_size: 25,
+			part_size: 10,
+			expiry_block: 0,
+		};
+
+		fs::write(part_path(&dir, 0), &archive[..10]).unwrap();
+		fs::write(part_path(&dir, 2), &archive[20..]).unwrap();
+		assert_eq!(session.received_parts(&dir), vec![0, 2]);
+		assert!(assemble_parts(&session, &dir, false).is_err());
+
+		fs::write(part_path(&dir, 1), &archive[10..20]).unwrap();
+		session.archive_hash = sha256::digest("other".as_bytes());
+		assert!(assemble_parts(&session, &dir, false).is_err());
+
+		session.archive_hash = sha256::digest(archive.as_slice());
+		let archive_path = assemble_parts(&session, &dir, true).unwrap();
+		assert_eq!(fs::read(&archive_path).unwrap(), archive);
+		assert_eq!(session.received_parts(&dir), vec![0, 1, 2]);
+
+		let archive_path = assemble_parts(&session, &dir, false).unwrap();
+		assert_eq!(fs::read(archive_path).unwrap(), archive);
+		assert!(session.received_parts(&dir).is_empty());
+
+		fs::remove_dir_all(&dir).unwrap();
+	}
+}
@@ -0,0 +1,168 @@
+use std::{
+	collections::BTreeSet,
+	fs::{self, DirBuilder},
+	os::unix::fs::DirBuilderExt,
+	path::{Path, PathBuf},
+	sync::Mutex,
+};
+
+use anyhow::anyhow;
+use rand::RngCore;
+use tracing::{debug, error, warn};
+
+use crate::chain::constants::{MAX_WORKSPACES, WORKSPACE_PATH};
+
+/* *************************************
+	BACKUP WORKSPACES
+**************************************** */
+
+// Archives and staging directories of the backups and restores are written to a private
+// directory of their own, inside the sealed mount, instead of fixed shared paths :
+// - the directory name is random and created exclusively, a request never reuses another one
+// - it is only readable by the enclave user and can not be a symlink
+// - it is removed when the request ends, on success or failure, and leftovers of a crash are
+//   removed at startup
+
+static WORKSPACES: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
+/// Private temporary directory of a backup or restore, removed when it is dropped
+#[derive(Debug)]
+pub struct Workspace {
+	path: PathBuf,
+}
+
+impl Workspace {
+	/// Create a workspace in the sealed mount
+	/// # Arguments
+	/// * `purpose` - prefix of the directory name, for the logs
+	/// # Errors
+	/// * Too many workspaces in use, or the directory can not be created
+	pub fn create(purpose: &str) -> Result<Workspace, anyhow::Error> {
+		Workspace::create_in(Path::new(WORKSPACE_PATH), purpose)
+	}
+
+	/// Create a workspace in another root directory
+	pub fn create_in(root: &Path, purpose: &str) -> Result<Workspace, anyhow::Error> {
+		fs::create_dir_all(root)?;
+		if fs::symlink_metadata(root)?.file_type().is_symlink() {
+			return Err(anyhow!("BACKUP WORKSPACE : {root:?} is a symlink"))
+		}
+
+		let mut workspaces = WORKSPACES
+			.lock()
+			.map_err(|err| anyhow!("BACKUP WORKSPACE : lock error : {err:?}"))?;
+		if workspaces.len() >= MAX_WORKSPACES {
+			return Err(anyhow!("BACKUP WORKSPACE : too many backups in progress, retry later"))
+		}
+
+		let mut id = [0u8; 16];
+		rand::thread_rng().fill_bytes(&mut id);
+		let path = root.join(format!("{purpose}-{}", hex::encode(id)));
+
+		// Fails if the path exists, even as a symlink
+		DirBuilder::new().mode(0o700).create(&path)?;
+		workspaces.insert(path.clone());
+
+		debug!("BACKUP WORKSPACE : created {path:?}");
+		Ok(Workspace { path })
+	}
+
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	/// Path of a file of the workspace
+	pub fn file(&self, name: &str) -> PathBuf {
+		self.path.join(name)
+	}
+}
+
+impl Drop for Workspace {
+	fn drop(&mut self) {
+		if let Err(err) = fs::remove_dir_all(&self.path) {
+			if err.kind() != std::io::ErrorKind::NotFound {
+				error!("BACKUP WORKSPACE : error removing {:?} : {err:?}", self.path);
+			}
+		}
+
+		match WORKSPACES.lock() {
+			Ok(mut workspaces) => {
+				workspaces.remove(&self.path);
+			},
+			Err(err) => error!("BACKUP WORKSPACE : lock error : {err:?}"),
+		}
+	}
+}
+
+/// Remove the workspaces which are not in use, left by a crash or a restart
+/// # Returns
+/// * `usize` - number of removed workspaces
+pub fn clean_workspaces(root: &Path) -> usize {
+	let entries = match fs::read_dir(root) {
+		Ok(entries) => entries,
+		Err(_) => return 0,
+	};
+
+	let active = match WORKSPACES.lock() {
+		Ok(workspaces) => workspaces.clone(),
+		Err(err) => {
+			error!("BACKUP WORKSPACE : lock error : {err:?}");
+			return 0
+		},
+	};
+
+	let mut removed = 0;
+	for entry in entries.flatten() {
+		let path = entry.path();
+		if active.contains(&path) {
+			continue
+		}
+
+		let result = match entry.file_type() {
+			Ok(file_type) if file_type.is_dir() => fs::remove_dir_all(&path),
+			_ => fs::remove_file(&path),
+		};
+
+		match result {
+			Ok(_) => removed += 1,
+			Err(err) => warn!("BACKUP WORKSPACE : error removing stale {path:?} : {err:?}"),
+		}
+	}
+
+	removed
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn workspace_test() {
+		let root = std::env::temp_dir().join(format!("backup-workspace-{}", std::process::id()));
+		let _ = fs::remove_dir_all(&root);
+
+		let first = Workspace::create_in(&root, "fetch-bulk").unwrap();
+		let second = Workspace::create_in(&root, "fetch-bulk").unwrap();
+		assert_ne!(first.path(), second.path());
+		fs::write(first.file("backup.zip"), b"first").unwrap();
+		fs::write(second.file("backup.zip"), b"second").unwrap();
+		assert_eq!(fs::read(first.file("backup.zip")).unwrap(), b"first");
+
+		// Leftovers are removed, workspaces in use are kept
+		fs::create_dir(root.join("restore-stale")).unwrap();
+		assert_eq!(clean_workspaces(&root), 1);
+		assert!(first.path().exists());
+
+		let path = first.path().to_path_buf();
+		drop(first);
+		assert!(!path.exists());
+		assert!(second.path().exists());
+
+		drop(second);
+		fs::remove_dir_all(&root).unwrap();
+	}
+}
@@ -1,6 +1,7 @@
+use sha2::{Digest, Sha256};
 use std::{
 	fs,
-	io::{self, prelude::*, Seek, Write},
+	io::{self, prelude::*, BufWriter, Seek, Write},
 	iter::Iterator,
 };
 use tracing::{debug, error, info, trace};
@@ -11,41 +12,134 @@ use walkdir::{DirEntry, WalkDir};
 
 use crate::chain::constants::SEALPATH;
 
+use super::manifest::{BackupManifest, MANIFEST_FILE};
+
 const METHOD_DEFLATED: zip::CompressionMethod = zip::CompressionMethod::Deflated;
 
-pub fn add_list_zip(src_dir: &str, nftids: Vec<String>, dst_file: &str) -> i32 {
+// Entries close to 4GB are written with zip64 sizes, deflate can expand incompressible data.
+// Archives beyond 4GB or 65535 entries get a zip64 central directory from the zip writer.
+const LARGE_FILE_SIZE: u64 = u32::MAX as u64 - 64 * 1024 * 1024;
+
+/// Compress the keyshares of a list of nft_ids, "*" for all the keyshares
+/// # Returns
+/// * `usize` - number of archived files, the archive is incomplete on error
+pub fn add_list_zip(src_dir: &str, nftids: Vec<String>, dst_file: &str) -> Result<usize, ZipError> {
 	match doit(src_dir, nftids, dst_file, METHOD_DEFLATED) {
-		Ok(_) => {
+		Ok(archived) => {
 			tracing::info!(
-				"NFTID-based backup compression done: {} written to {}",
+				"NFTID-based backup compression done: {} files of {} written to {}",
+				archived,
 				src_dir,
 				dst_file
-			)
+			);
+			Ok(archived)
+		},
+		Err(err) => {
+			tracing::error!("Error NFTID-based backup : add_list_zip : {err:?}");
+			Err(err)
 		},
-		Err(err) => tracing::error!("Error NFTID-based backup : add_list_zip : {err:?}"),
 	}
-
-	0
 }
 
-pub fn add_dir_zip(src_dir: &str, dst_file: &str) -> i32 {
+/// Compress a whole directory
+/// # Returns
+/// * `usize` - number of archived files, the archive is incomplete on error
+pub fn add_dir_zip(src_dir: &str, dst_file: &str) -> Result<usize, ZipError> {
 	match doit(src_dir, Vec::<String>::new(), dst_file, METHOD_DEFLATED) {
-		Ok(_) => {
-			tracing::info!("bulk backup compression done: {} written to {}", src_dir, dst_file)
+		Ok(archived) => {
+			tracing::info!(
+				"bulk backup compression done: {} files of {} written to {}",
+				archived,
+				src_dir,
+				dst_file
+			);
+			Ok(archived)
+		},
+		Err(err) => {
+			tracing::error!("Error bulk backup : add_dir_zip : {err:?}");
+			Err(err)
 		},
-		Err(err) => tracing::error!("Error bulk backup : add_dir_zip : {err:?}"),
 	}
+}
 
-	0
+/// Compress the files of a signed manifest, with the manifest
+/// # Returns
+/// * `usize` - number of archived files, the archive is incomplete on error
+/// # Errors
+/// * A file has changed since the manifest has been built
+pub fn add_manifest_zip(
+	src_dir: &str,
+	manifest: &BackupManifest,
+	dst_file: &str,
+) -> Result<usize, ZipError> {
+	add_manifest_zip_progress(src_dir, manifest, dst_file, |_, _| {})
+}
+
+/// Same as `add_manifest_zip`, the progress is called with the files and bytes zipped so far
+pub fn add_manifest_zip_progress(
+	src_dir: &str,
+	manifest: &BackupManifest,
+	dst_file: &str,
+	mut progress: impl FnMut(usize, u64),
+) -> Result<usize, ZipError> {
+	let mut zipped_bytes = 0u64;
+	let mut zip = zip::ZipWriter::new(BufWriter::new(File::create(dst_file)?));
+	let options = FileOptions::default()
+		.compression_method(METHOD_DEFLATED)
+		.unix_permissions(0o755);
+
+	for (index, entry) in manifest.entries.iter().enumerate() {
+		let mut f = File::open(Path::new(src_dir).join(&entry.file))?;
+		let size = f.metadata()?.len();
+
+		zip.start_file(entry.file.as_str(), options.large_file(size >= LARGE_FILE_SIZE))?;
+
+		// Hashed while streamed, the archive matches its manifest
+		let mut hasher = Sha256::new();
+		let mut buffer = vec![0u8; 64 * 1024];
+		loop {
+			let read = f.read(&mut buffer)?;
+			if read == 0 {
+				break
+			}
+			hasher.update(&buffer[..read]);
+			zip.write_all(&buffer[..read])?;
+		}
+
+		if hex::encode(hasher.finalize()) != entry.sha256 {
+			return Err(ZipError::Io(io::Error::new(
+				io::ErrorKind::InvalidData,
+				format!("{} has changed during the backup", entry.file),
+			)))
+		}
+
+		zipped_bytes += size;
+		progress(index + 1, zipped_bytes);
+	}
+
+	let data = serde_json::to_vec_pretty(manifest)
+		.map_err(|err| ZipError::Io(io::Error::new(io::ErrorKind::InvalidData, err)))?;
+	zip.start_file(MANIFEST_FILE, options)?;
+	zip.write_all(&data)?;
+
+	zip.finish()?.flush()?;
+
+	info!(
+		"signed backup compression done: {} files of {} written to {}",
+		manifest.entries.len(),
+		src_dir,
+		dst_file
+	);
+	Ok(manifest.entries.len())
 }
 
 fn zip_dir<T>(
-	it: &mut dyn Iterator<Item = DirEntry>,
+	it: &mut dyn Iterator<Item = walkdir::Result<DirEntry>>,
 	list: Vec<String>,
 	prefix: &str,
 	writer: T,
 	method: zip::CompressionMethod,
-) -> zip::result::ZipResult<()>
+) -> zip::result::ZipResult<usize>
 where
 	T: Write + Seek,
 {
@@ -53,8 +147,10 @@ where
 	let options = FileOptions::default().compression_method(method).unix_permissions(0o755);
 	debug!("\t ZIPDIR => nft-list = {:?}\n", list);
 
-	let mut buffer = Vec::new();
+	let mut archived = 0;
 	for entry in it {
+		// An unreadable entry fails the backup, instead of silently missing from the archive
+		let entry = entry.map_err(|err| ZipError::Io(err.into()))?;
 		let path = entry.path();
 
 		let file_ext = match path.extension().and_then(std::ffi::OsStr::to_str) {
@@ -134,13 +230,15 @@ where
 		// Some unzip tools unzip files with directory paths correctly, some do not!
 		if path.is_file() {
 			trace!("\t ZIPDIR => adding file {:?} as {:?} ...", path, name_ext);
-			#[allow(deprecated)]
-			zip.start_file_from_path(name_ext, options)?;
 			let mut f = File::open(path)?;
+			let size = f.metadata()?.len();
 
-			f.read_to_end(&mut buffer)?;
-			zip.write_all(&buffer)?;
-			buffer.clear();
+			#[allow(deprecated)]
+			zip.start_file_from_path(name_ext, options.large_file(size >= LARGE_FILE_SIZE))?;
+
+			// Streamed through the compressor, files are not loaded whole in memory
+			io::copy(&mut f, &mut zip)?;
+			archived += 1;
 		} else if !name_ext.as_os_str().is_empty() {
 			// Only if not root! Avoids path spec / warning
 			// and mapname conversion failed error on unzip
@@ -150,8 +248,8 @@ where
 		}
 	}
 
-	zip.finish()?;
-	Result::Ok(())
+	zip.finish()?.flush()?;
+	Result::Ok(archived)
 }
 
 /// Compresses a directory into a zip file
@@ -160,25 +258,31 @@ fn doit(
 	list: Vec<String>,
 	dst_file: &str,
 	method: zip::CompressionMethod,
-) -> zip::result::ZipResult<()> {
+) -> zip::result::ZipResult<usize> {
 	if !Path::new(src_dir).is_dir() {
 		return Err(ZipError::FileNotFound)
 	}
 	let path = Path::new(dst_file);
-	let file = File::create(path)?;
+	let file = BufWriter::new(File::create(path)?);
 
 	let walkdir = WalkDir::new(src_dir).max_depth(1);
-	let it = walkdir.into_iter();
-
-	zip_dir(&mut it.filter_map(|e| e.ok()), list, src_dir, file, method)?;
 
-	Ok(())
+	zip_dir(&mut walkdir.into_iter(), list, src_dir, file, method)
 }
 
 /* ----------------------------
 		EXTRACT ARCHIVE
 -------------------------------*/
 pub fn zip_extract(filename: &str, outdir: &str) -> Result<(), ZipError> {
+	zip_extract_selected(filename, outdir, |_| true)
+}
+
+/// Extract the entries of an archive whose name is selected, the others are skipped
+pub fn zip_extract_selected(
+	filename: &str,
+	outdir: &str,
+	selected: impl Fn(&str) -> bool,
+) -> Result<(), ZipError> {
 	let fname = std::path::Path::new(filename);
 
 	let infile = match fs::File::open(fname) {
@@ -228,7 +332,7 @@ pub fn zip_extract(filename: &str, outdir: &str) -> Result<(), ZipError> {
 
 		let fullpath = Path::new(&fullpath_str);
 
-		if (*file.name()).contains("__MACOSX") {
+		if (*file.name()).contains("__MACOSX") || !selected(file.name()) {
 			continue
 		}
 
@@ -297,16 +401,84 @@ mod test {
 
 	use super::*;
 
+	// Synthetic keyshare tree of the given number of files
+	fn keyshare_tree(name: &str, count: u32) -> String {
+		let dir = std::env::temp_dir().join(name);
+		let _ = fs::remove_dir_all(&dir);
+		fs::create_dir_all(&dir).unwrap();
+
+		for nft_id in 0..count {
+			fs::write(dir.join(format!("nft_{nft_id}_100.keyshare")), format!("keyshare_{nft_id}"))
+				.unwrap();
+		}
+
+		dir.to_string_lossy().to_string()
+	}
+
 	#[tokio::test]
 	async fn zip_list_test() {
 		let nftids = ["11", "25", "141", "330"].iter().map(|s| s.to_string()).collect();
-		add_list_zip("/tmp", nftids, "/tmp/zip/backup2.zip");
+		let _ = add_list_zip("/tmp", nftids, "/tmp/zip/backup2.zip");
 		let _ = zip_extract("/tmp/zip/backup2.zip", "/tmp/test2/");
 	}
 
 	#[tokio::test]
 	async fn zip_dir_test() {
-		add_dir_zip("/tmp", "/tmp/zip/backup1.zip");
+		let _ = add_dir_zip("/tmp", "/tmp/zip/backup1.zip");
 		let _ = zip_extract("/tmp/zip/backup1.zip", "/tmp/test1/");
 	}
+
+	#[test]
+	fn zip_missing_dir_test() {
+		let dst = std::env::temp_dir().join("zipdir-missing.zip");
+		assert!(add_dir_zip("/nonexistent-sealpath", &dst.to_string_lossy()).is_err());
+	}
+
+	#[test]
+	fn zip64_entry_count_test() {
+		// More entries than the 16 bits count of a classic zip
+		let count = u16::MAX as u32 + 100;
+		let src_dir = keyshare_tree("zipdir-zip64-entries", count);
+		let dst_file = format!("{src_dir}.zip");
+
+		assert_eq!(add_dir_zip(&src_dir, &dst_file).unwrap(), count as usize);
+
+		let archive = zip::ZipArchive::new(File::open(&dst_file).unwrap()).unwrap();
+		assert_eq!(archive.len(), count as usize);
+
+		let out_dir = format!("{src_dir}-extract");
+		let _ = fs::remove_dir_all(&out_dir);
+		zip_extract(&dst_file, &out_dir).unwrap();
+		assert_eq!(fs::read_dir(&out_dir).unwrap().count(), count as usize);
+		assert_eq!(
+			fs::read_to_string(format!("{out_dir}/nft_{}_100.keyshare", count - 1)).unwrap(),
+			format!("keyshare_{}", count - 1)
+		);
+
+		// Filtered backups of the same tree
+		let list = vec!["7".to_string(), (count - 1).to_string()];
+		assert_eq!(add_list_zip(&src_dir, list, &dst_file).unwrap(), 2);
+
+		let _ = fs::remove_dir_all(&src_dir);
+		let _ = fs::remove_dir_all(&out_dir);
+		let _ = fs::remove_file(&dst_file);
+	}
+
+	#[test]
+	#[ignore = "writes and compresses a 4GB keyshare file"]
+	fn zip64_large_file_test() {
+		let src_dir = keyshare_tree("zipdir-zip64-size", 10);
+		let large_file = File::create(format!("{src_dir}/nft_10_100.keyshare")).unwrap();
+		large_file.set_len(u32::MAX as u64 + 1024).unwrap();
+		let dst_file = format!("{src_dir}.zip");
+
+		assert_eq!(add_dir_zip(&src_dir, &dst_file).unwrap(), 11);
+
+		let mut archive = zip::ZipArchive::new(File::open(&dst_file).unwrap()).unwrap();
+		assert_eq!(archive.len(), 11);
+		assert_eq!(archive.by_name("nft_10_100.keyshare").unwrap().size(), u32::MAX as u64 + 1024);
+
+		let _ = fs::remove_dir_all(&src_dir);
+		let _ = fs::remove_file(&dst_file);
+	}
 }
@@ -0,0 +1,414 @@
+use std::{
+	fs::OpenOptions,
+	io::{BufRead, BufReader, Write},
+	path::Path,
+};
+
+use anyhow::anyhow;
+use axum::{http::StatusCode, Json};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::Mutex;
+use tracing::{debug, error, info};
+
+use crate::{
+	chain::{
+		constants::AUDIT_LOG_FILE,
+		verify::{RequesterType, ReturnStatus, VerificationError, APICALL},
+	},
+	servers::state::{get_audit_head, get_blocknumber, SharedState},
+};
+
+/* ---------------------------------------
+	VERIFICATION AUDIT TRAIL
+--------------------------------------- */
+
+// Previous hash of the first record
+pub const AUDIT_GENESIS_HASH: &str =
+	"0000000000000000000000000000000000000000000000000000000000000000";
+
+// Appends are ordered by this mutex rather than by the state lock, the head of the state is
+// only moved once its record is written
+static AUDIT_WRITER: Mutex<()> = Mutex::const_new(());
+
+/// Verification decision of a keyshare request.
+/// Records are appended to a sealed json-lines file, each one carries the hash of the previous
+/// record, so a removed or modified record breaks the chain.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AuditRecord {
+	pub index: u64,
+	pub block_number: u32,
+	pub call: APICALL,
+	pub nft_id: u32,
+	pub requester: String,
+	// None if the call has no requester role, i.e. remove
+	pub requester_type: Option<RequesterType>,
+	pub result: ReturnStatus,
+	pub prev_hash: String,
+	pub hash: String,
+}
+
+/// Last record of the chain, the next record is linked to it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AuditHead {
+	pub next_index: u64,
+	pub last_hash: String,
+}
+
+impl Default for AuditHead {
+	fn default() -> Self {
+		AuditHead { next_index: 0, last_hash: AUDIT_GENESIS_HASH.to_string() }
+	}
+}
+
+/// First record which breaks the chain
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct AuditBreak {
+	pub index: u64,
+	pub description: String,
+}
+
+/// Record of a hash chain, each record carries the hash of the previous one
+pub trait ChainedRecord {
+	fn index(&self) -> u64;
+	fn prev_hash(&self) -> &str;
+	fn hash(&self) -> &str;
+	/// Hash of the record content and of the previous record
+	fn compute_hash(&self) -> String;
+}
+
+impl ChainedRecord for AuditRecord {
+	fn index(&self) -> u64 {
+		self.index
+	}
+
+	fn prev_hash(&self) -> &str {
+		&self.prev_hash
+	}
+
+	fn hash(&self) -> &str {
+		&self.hash
+	}
+
+	fn compute_hash(&self) -> String {
+		sha256::digest(
+			format!(
+				"{}_{}_{}_{:?}_{}_{}_{:?}_{:?}",
+				self.prev_hash,
+				self.index,
+				self.block_number,
+				self.call,
+				self.nft_id,
+				self.requester,
+				self.requester_type,
+				self.result
+			)
+			.as_bytes(),
+		)
+	}
+}
+
+impl AuditHead {
+	/// Create the next record of the chain and move the head to it
+	/// # Arguments
+	/// * `block_number` - block number of the decision
+	/// * `call` - api call
+	/// * `nft_id` - nft/capsule id, 0 if the request is not parsable
+	/// * `requester` - requester address
+	/// * `requester_type` - role of the requester
+	/// * `result` - verification result
+	pub fn append(
+		&mut self,
+		block_number: u32,
+		call: APICALL,
+		nft_id: u32,
+		requester: String,
+		requester_type: Option<RequesterType>,
+		result: ReturnStatus,
+	) -> AuditRecord {
+		let mut record = AuditRecord {
+			index: self.next_index,
+			block_number,
+			call,
+			nft_id,
+			requester,
+			requester_type,
+			result,
+			prev_hash: self.last_hash.clone(),
+			hash: String::new(),
+		};
+		record.hash = record.compute_hash();
+
+		self.next_index += 1;
+		self.last_hash = record.hash.clone();
+
+		record
+	}
+}
+
+/// Verify the links and hashes of a chain from its first record
+/// # Arguments
+/// * `records` - records in file order
+/// # Returns
+/// * `AuditHead` - head of the valid chain
+/// # Errors
+/// * `AuditBreak` - first record which is missing, modified or linked to another record
+pub fn verify_audit_chain<R: ChainedRecord>(records: &[R]) -> Result<AuditHead, AuditBreak> {
+	let mut head = AuditHead::default();
+
+	for record in records {
+		if record.index() != head.next_index {
+			return Err(AuditBreak {
+				index: head.next_index,
+				description: format!("record {} is missing", head.next_index),
+			})
+		}
+
+		if record.prev_hash() != head.last_hash {
+			return Err(AuditBreak {
+				index: record.index(),
+				description: "previous hash does not match".to_string(),
+			})
+		}
+
+		if record.hash() != record.compute_hash() {
+			return Err(AuditBreak {
+				index: record.index(),
+				description: "record content does not match its hash".to_string(),
+			})
+		}
+
+		head.next_index += 1;
+		head.last_hash = record.hash().to_string();
+	}
+
+	Ok(head)
+}
+
+/// Read the records of a sealed json-lines log
+pub fn read_audit_log<R: DeserializeOwned>(path: &str) -> Result<Vec<R>, anyhow::Error> {
+	if !Path::new(path).exists() {
+		return Ok(Vec::new())
+	}
+
+	let file = std::fs::File::open(path)?;
+	let mut records = Vec::new();
+
+	for (number, line) in BufReader::new(file).lines().enumerate() {
+		let line = line?;
+		if line.trim().is_empty() {
+			continue
+		}
+
+		let record = serde_json::from_str(&line).map_err(|err| {
+			anyhow!("AUDIT LOG : error parsing {path} line {} : {err:?}", number + 1)
+		})?;
+		records.push(record);
+	}
+
+	Ok(records)
+}
+
+/// Load the head of the audit chain into the state at startup.
+/// A broken chain is reported, new records are linked to the last record of the file.
+pub async fn load_audit_head(state: &SharedState) -> Result<(), anyhow::Error> {
+	let records: Vec<AuditRecord> = read_audit_log(AUDIT_LOG_FILE)?;
+
+	let head = match verify_audit_chain(&records) {
+		Ok(head) => head,
+		Err(chain_break) => {
+			error!(
+				"AUDIT LOG : chain is broken at record {} : {}",
+				chain_break.index, chain_break.description
+			);
+			sentry::capture_message(
+				&format!("AUDIT LOG : chain is broken at record {}", chain_break.index),
+				sentry::Level::Error,
+			);
+
+			match records.last() {
+				Some(last) =>
+					AuditHead { next_index: last.index + 1, last_hash: last.hash.clone() },
+				None => AuditHead::default(),
+			}
+		},
+	};
+
+	info!("AUDIT LOG : {} records are loaded", head.next_index);
+
+	let shared_state_write = &mut state.write().await;
+	shared_state_write.set_audit_head(head);
+
+	Ok(())
+}
+
+/// Append a record to a json-lines audit log, synced before the decision is served
+pub fn write_audit_record(path: &str, record: &AuditRecord) -> Result<(), anyhow::Error> {
+	let line = serde_json::to_string(record)?;
+	let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+	writeln!(file, "{line}")?;
+	file.sync_data()?;
+
+	Ok(())
+}
+
+/// Append a verification decision to the sealed audit log
+/// # Arguments
+/// * `state` - SharedState
+/// * `call` - api call
+/// * `nft_id` - nft/capsule id, 0 if the request is not parsable
+/// * `requester` - requester address
+/// * `requester_type` - role of the requester
+/// * `result` - verification result
+/// # Errors
+/// * if the record is not written, the decision must not be served
+pub async fn audit_verification(
+	state: &SharedState,
+	call: APICALL,
+	nft_id: u32,
+	requester: String,
+	requester_type: Option<RequesterType>,
+	result: ReturnStatus,
+) -> Result<(), anyhow::Error> {
+	let block_number = get_blocknumber(state).await;
+
+	// The state lock is only taken to read and move the head, never across the disk write
+	let _writer = AUDIT_WRITER.lock().await;
+	let mut head = get_audit_head(state).await;
+	let record = head.append(block_number, call, nft_id, requester, requester_type, result);
+
+	if let Err(err) = write_audit_record(AUDIT_LOG_FILE, &record) {
+		error!("AUDIT LOG : error writing record {} : {err:?}", record.index);
+		sentry::capture_message(
+			&format!("AUDIT LOG : error writing record : {err:?}"),
+			sentry::Level::Error,
+		);
+		return Err(err)
+	}
+
+	debug!("AUDIT LOG : record {} {:?} {:?}", record.index, record.call, record.result);
+	state.write().await.set_audit_head(head);
+
+	Ok(())
+}
+
+/// Records of the audit log and head of the state, read while no record is being appended
+pub async fn read_audit_snapshot(
+	state: &SharedState,
+) -> Result<(Vec<AuditRecord>, AuditHead), anyhow::Error> {
+	let _writer = AUDIT_WRITER.lock().await;
+	let records = read_audit_log::<AuditRecord>(AUDIT_LOG_FILE)?;
+	Ok((records, get_audit_head(state).await))
+}
+
+/// Response of a request which decision could not be recorded in the audit log
+/// # Arguments
+/// * `call` - api call
+/// * `nft_id` - nft/capsule id
+/// * `enclave_account` - enclave account
+pub fn audit_failure(
+	call: APICALL,
+	nft_id: u32,
+	enclave_account: String,
+) -> (StatusCode, Json<Value>) {
+	error!("AUDIT LOG : {call:?} of nft_id {nft_id} is refused, its decision is not recorded");
+
+	(
+		StatusCode::INTERNAL_SERVER_ERROR,
+		Json(json!({
+			"status": ReturnStatus::DATABASEFAILURE,
+			"nft_id": nft_id,
+			"enclave_account": enclave_account,
+			"description": "Error writing the audit log of the request, use another enclave please.",
+		})),
+	)
+}
+
+/// Status of a successful verification of the call
+fn success_status(call: APICALL) -> ReturnStatus {
+	match call {
+		APICALL::NFTSTORE | APICALL::CAPSULESET | APICALL::NFTBATCHSTORE =>
+			ReturnStatus::STORESUCCESS,
+		APICALL::NFTRETRIEVE | APICALL::CAPSULERETRIEVE | APICALL::NFTBATCHRETRIEVE =>
+			ReturnStatus::RETRIEVESUCCESS,
+		APICALL::NFTREMOVE | APICALL::CAPSULEREMOVE => ReturnStatus::REMOVESUCCESS,
+		APICALL::CAPSULEUPDATE => ReturnStatus::UPDATESUCCESS,
+	}
+}
+
+/// Audited status of a verification result, a verified request has the success status of its
+/// call
+pub fn result_status<T>(call: APICALL, result: &Result<T, VerificationError>) -> ReturnStatus {
+	match result {
+		Ok(_) => success_status(call),
+		Err(err) => err.status(),
+	}
+}
+
+/// Append the verification result of a request, a verified request is recorded with the
+/// success status of its call
+pub async fn audit_result<T>(
+	state: &SharedState,
+	call: APICALL,
+	nft_id: u32,
+	requester: String,
+	requester_type: Option<RequesterType>,
+	result: &Result<T, VerificationError>,
+) -> Result<(), anyhow::Error> {
+	let status = result_status(call, result);
+
+	audit_verification(state, call, nft_id, requester, requester_type, status).await
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn audit_chain_test() {
+		let mut head = AuditHead::default();
+		let mut records = vec![
+			head.append(
+				100,
+				APICALL::NFTSTORE,
+				10,
+				"owner".to_string(),
+				Some(RequesterType::OWNER),
+				ReturnStatus::STORESUCCESS,
+			),
+			head.append(
+				101,
+				APICALL::NFTRETRIEVE,
+				10,
+				"stranger".to_string(),
+				Some(RequesterType::DELEGATEE),
+				ReturnStatus::REQUESTERVERIFICATIONFAILED,
+			),
+			head.append(
+				102,
+				APICALL::NFTRETRIEVE,
+				10,
+				"owner".to_string(),
+				Some(RequesterType::OWNER),
+				ReturnStatus::RETRIEVESUCCESS,
+			),
+		];
+
+		assert_eq!(records[0].prev_hash, AUDIT_GENESIS_HASH);
+		assert_eq!(records[1].prev_hash, records[0].hash);
+		assert_eq!(verify_audit_chain(&records), Ok(head.clone()));
+
+		// Modified decision
+		let mut modified = records.clone();
+		modified[1].result = ReturnStatus::RETRIEVESUCCESS;
+		assert_eq!(verify_audit_chain(&modified).unwrap_err().index, 1);
+
+		// Removed record
+		records.remove(1);
+		assert_eq!(verify_audit_chain(&records).unwrap_err().index, 1);
+
+		// Removed tail can not be detected from the records only, the head is exported too
+		records.truncate(1);
+		assert_eq!(verify_audit_chain(&records).unwrap().next_index, 1);
+	}
+}
@@ -1,8 +1,11 @@
 use crate::{
-	chain::helper,
-	servers::state::{
-		get_accountid, get_blocknumber, get_nft_availability, remove_nft_availability,
-		set_nft_availability, SharedState,
+	chain::{compression, helper},
+	servers::{
+		events::{publish_keyshare_event, KeyshareEventKind},
+		state::{
+			get_accountid, get_blocknumber, get_compression_threshold, get_nft_availability,
+			remove_nft_availability, set_nft_availability, SharedState,
+		},
 	},
 };
 
@@ -18,13 +21,14 @@ use tracing::{debug, error, info, warn};
 use axum::extract::Path as PathExtract;
 
 use crate::chain::{
+	audit::{audit_failure, audit_result},
 	constants::SEALPATH,
 	core::{capsule_keyshare_oracle, get_current_block_number, get_onchain_nft_data},
 	log::*,
 	verify::*,
 };
 use serde::Serialize;
-use serde_json::to_value;
+use serde_json::{to_value, Value};
 
 /* **********************
  KEY-SHARE AVAILABLE API
@@ -303,7 +307,22 @@ pub async fn capsule_set_keyshare(
 	let enclave_account = get_accountid(&state).await;
 	let block_number = get_blocknumber(&state).await;
 
-	match request.verify_store_request(&state, "capsule").await {
+	let verification = request.verify_store_request(&state, NftKind::CAPSULE).await;
+	if audit_result(
+		&state,
+		APICALL::CAPSULESET,
+		request.requested_nft_id(),
+		request.owner_address.to_string(),
+		Some(RequesterType::OWNER),
+		&verification,
+	)
+	.await
+	.is_err()
+	{
+		return audit_failure(APICALL::CAPSULESET, request.requested_nft_id(), enclave_account)
+	}
+
+	match verification {
 		// DATA-FILED IS VALID
 		Ok(verified_data) => {
 			// IS ENCLAVE SEAL-PATH READY?
@@ -417,7 +436,12 @@ pub async fn capsule_set_keyshare(
 			};
 
 			// WRITE KEY-SHARE DATA TO FILE
-			match f.write_all(&verified_data.keyshare) {
+			let compression_threshold = get_compression_threshold(&state).await;
+			let keyshare_at_rest = verified_data.keyshare_at_rest();
+			let sealed_keyshare =
+				compression::seal_keyshare(&keyshare_at_rest, compression_threshold);
+
+			match f.write_all(&sealed_keyshare) {
 				Ok(_) => info!(
 					"Capsule key-share is successfully stored to TEE, nft_id = {} Owner = {}",
 					verified_data.nft_id, request.owner_address
@@ -477,6 +501,12 @@ pub async fn capsule_set_keyshare(
 						),
 					)
 					.await;
+					publish_keyshare_event(
+						KeyshareEventKind::STORED,
+						verified_data.nft_id,
+						helper::NftType::Capsule,
+						0,
+					);
 
 					// Log file for tracing the capsule key-share VIEW history in Marketplace.
 					let file_path = format!("{SEALPATH}/{}.log", verified_data.nft_id);
@@ -658,6 +688,191 @@ pub async fn capsule_set_keyshare(
 	}
 }
 
+/* **********************
+	 UPDATE KEY-SHARE
+********************** */
+
+/// Replace the keyshare of a synced capsule.
+/// The new keyshare is written next to the sealed file then renamed over it, so a concurrent
+/// retrieve reads either the old or the new keyshare, never a missing or partial one.
+/// # Arguments
+/// * `state` - The state of the enclave
+/// * `request` - UpdateKeysharePacket
+/// # Returns
+/// * `impl IntoResponse` - UPDATESUCCESS or the error status
+
+#[axum::debug_handler]
+pub async fn capsule_update_keyshare(
+	State(state): State<SharedState>,
+	Json(request): Json<UpdateKeysharePacket>,
+) -> impl IntoResponse {
+	debug!("\n\t*****\nCAPSULE UPDATE KEYSHARE API\n\t*****\n");
+
+	let enclave_account = get_accountid(&state).await;
+	let block_number = get_blocknumber(&state).await;
+
+	let verification = request.verify_update_request(&state).await;
+	if audit_result(
+		&state,
+		APICALL::CAPSULEUPDATE,
+		request.requested_nft_id(),
+		request.owner_address.to_string(),
+		Some(RequesterType::OWNER),
+		&verification,
+	)
+	.await
+	.is_err()
+	{
+		return audit_failure(APICALL::CAPSULEUPDATE, request.requested_nft_id(), enclave_account)
+	}
+
+	let verified_data = match verification {
+		Ok(verified_data) => verified_data,
+		Err(err) =>
+			return err.express_verification_error(
+				APICALL::CAPSULEUPDATE,
+				request.owner_address.to_string(),
+				request.requested_nft_id(),
+				enclave_account,
+			),
+	};
+
+	// DOES THE OLD KEY-SHARE EXIST?
+	let av = match get_nft_availability(&state, verified_data.nft_id).await {
+		Some(av) if av.nft_type != helper::NftType::Secret => av,
+		_ => {
+			let status = ReturnStatus::KEYNOTEXIST;
+			let description = format!(
+				"TEE Key-share {:?}: capsule nft_id.{} has no keyshare to update, set it first.",
+				APICALL::CAPSULEUPDATE,
+				verified_data.nft_id,
+			);
+			info!("{}, requester : {}", description, request.owner_address);
+
+			return (
+				StatusCode::NOT_FOUND,
+				Json(
+					to_value(ApiErrorResponse {
+						status,
+						nft_id: verified_data.nft_id,
+						enclave_account,
+						description,
+					})
+					.unwrap(),
+				),
+			)
+		},
+	};
+
+	let file_path =
+		format!("{SEALPATH}/capsule_{}_{}.keyshare", verified_data.nft_id, av.block_number);
+	let temp_path = format!("{file_path}.{}.update", rand::random::<u64>());
+
+	if !std::path::Path::new(&file_path).is_file() {
+		let status = ReturnStatus::KEYNOTEXIST;
+		let description = format!(
+			"TEE Key-share {:?}: error nft_id.{} key-share does not exist on enclave.",
+			APICALL::CAPSULEUPDATE,
+			verified_data.nft_id,
+		);
+		error!("{}, requester : {}", description, request.owner_address);
+
+		return (
+			StatusCode::NOT_FOUND,
+			Json(
+				to_value(ApiErrorResponse {
+					status,
+					nft_id: verified_data.nft_id,
+					enclave_account,
+					description,
+				})
+				.unwrap(),
+			),
+		)
+	}
+
+	// WRITE THE NEW KEY-SHARE, THEN SWAP IT ATOMICALLY
+	let compression_threshold = get_compression_threshold(&state).await;
+	let keyshare_at_rest = verified_data.keyshare_at_rest();
+	let sealed_keyshare = compression::seal_keyshare(&keyshare_at_rest, compression_threshold);
+
+	let written = std::fs::write(&temp_path, &sealed_keyshare)
+		.and_then(|_| std::fs::rename(&temp_path, &file_path));
+
+	if let Err(err) = written {
+		let _ = std::fs::remove_file(&temp_path);
+
+		let status = ReturnStatus::DATABASEFAILURE;
+		let description = format!(
+			"TEE Key-share {:?}: error in updating the Keyshare for nft_id.{} on enclave disk.",
+			APICALL::CAPSULEUPDATE,
+			verified_data.nft_id,
+		);
+		let message = format!(
+			"{}, Path: {}, Error : {}, requester : {}",
+			description, file_path, err, request.owner_address
+		);
+
+		error!(message);
+
+		sentry::with_scope(
+			|scope| {
+				scope.set_tag("capsule-update-keyshare", verified_data.nft_id.to_string());
+			},
+			|| sentry::capture_message(&message, sentry::Level::Error),
+		);
+
+		return (
+			StatusCode::INTERNAL_SERVER_ERROR,
+			Json(
+				to_value(ApiErrorResponse {
+					status,
+					nft_id: verified_data.nft_id,
+					enclave_account,
+					description,
+				})
+				.unwrap(),
+			),
+		)
+	}
+
+	// Same availability, the storage commitment is refreshed with the new keyshare hash
+	set_nft_availability(&state, (verified_data.nft_id, av)).await;
+	publish_keyshare_event(
+		KeyshareEventKind::STORED,
+		verified_data.nft_id,
+		av.nft_type,
+		av.block_number,
+	);
+
+	info!(
+		"Capsule key-share is successfully updated in TEE, nft_id = {} Owner = {}",
+		verified_data.nft_id, request.owner_address
+	);
+
+	update_log_file_view(
+		block_number,
+		format!("{SEALPATH}/{}.log", verified_data.nft_id),
+		request.owner_address.to_string(),
+		RequesterType::OWNER,
+		LogType::STORE,
+		"capsule",
+	);
+
+	(
+		StatusCode::OK,
+		Json(
+			to_value(ApiErrorResponse {
+				status: ReturnStatus::UPDATESUCCESS,
+				nft_id: verified_data.nft_id,
+				enclave_account,
+				description: "Capsule key-share is successfully updated in TEE".to_string(),
+			})
+			.unwrap(),
+		),
+	)
+}
+
 /* **********************
 	 RETRIEVE KEY-SHARE
 ********************** */
@@ -677,7 +892,22 @@ pub async fn capsule_retrieve_keyshare(
 
 	let enclave_account = get_accountid(&state).await;
 
-	match request.verify_retrieve_request(&state, "capsule").await {
+	let verification = request.verify_retrieve_request(&state, NftKind::CAPSULE).await;
+	if audit_result(
+		&state,
+		APICALL::CAPSULERETRIEVE,
+		request.requested_nft_id(),
+		request.requester_address.to_string(),
+		Some(request.requester_type),
+		&verification,
+	)
+	.await
+	.is_err()
+	{
+		return audit_failure(APICALL::CAPSULERETRIEVE, request.requested_nft_id(), enclave_account)
+	}
+
+	match verification {
 		Ok(verified_data) => {
 			// DOES KEY-SHARE EXIST?
 			let av = match get_nft_availability(&state, verified_data.nft_id).await {
@@ -801,60 +1031,90 @@ pub async fn capsule_retrieve_keyshare(
 			};
 
 			// READ CAPSULE KEY-SHARE
-			let mut capsule_keyshare = Vec::<u8>::new();
-			match file.read_to_end(&mut capsule_keyshare) {
-				Ok(_) => {
-					info!(
-						"key-shares of {} retrieved by {}",
-						verified_data.nft_id, request.requester_address
-					)
-				},
+			let (response_buffer, binary) =
+				match helper::read_keyshare_into_response(&mut file, verified_data.nft_id) {
+					Ok(response) => {
+						info!(
+							"key-shares of {} retrieved by {}",
+							verified_data.nft_id, request.requester_address
+						);
+						response
+					},
 
-				Err(err) => {
-					let status = ReturnStatus::KEYNOTREADABLE;
-					let description = format!(
+					Err(err) => {
+						let status = ReturnStatus::KEYNOTREADABLE;
+						let description = format!(
 						"TEE Key-share {:?}: error can not read nft_id.{} key-share from enclave.",
 						APICALL::CAPSULERETRIEVE,
 						verified_data.nft_id,
 					);
 
-					let message = format!(
-						"{} , Error : {} , requester : {}",
-						description, err, request.requester_address
-					);
+						let message = format!(
+							"{} , Error : {} , requester : {}",
+							description, err, request.requester_address
+						);
 
-					error!(message);
+						error!(message);
 
-					sentry::with_scope(
-						|scope| {
-							scope.set_tag(
-								"capsule-retrieve-keyshare",
-								verified_data.nft_id.to_string(),
-							);
-						},
-						|| sentry::capture_message(&message, sentry::Level::Error),
-					);
+						sentry::with_scope(
+							|scope| {
+								scope.set_tag(
+									"capsule-retrieve-keyshare",
+									verified_data.nft_id.to_string(),
+								);
+							},
+							|| sentry::capture_message(&message, sentry::Level::Error),
+						);
 
-					return (
-						StatusCode::INTERNAL_SERVER_ERROR,
-						Json(
-							to_value(ApiErrorResponse {
-								status,
-								nft_id: verified_data.nft_id,
-								enclave_account,
-								description,
-							})
-							.unwrap(),
-						),
-					)
-				},
-			};
+						return (
+							StatusCode::INTERNAL_SERVER_ERROR,
+							Json(
+								to_value(ApiErrorResponse {
+									status,
+									nft_id: verified_data.nft_id,
+									enclave_account,
+									description,
+								})
+								.unwrap(),
+							),
+						)
+					},
+				};
 
 			// Put a VIEWING history log
 			let file_path = format!("{SEALPATH}/{}.log", verified_data.nft_id);
 
 			match get_current_block_number(&state).await {
 				Ok(block_number) => {
+					let auth_token =
+						AuthenticationToken { block_number, block_validation: 15 }.serialize();
+					let serialized_keyshare =
+						match helper::finish_keyshare_response(response_buffer, &auth_token) {
+							Ok(serialized_keyshare) => serialized_keyshare,
+							Err(err) => {
+								let status = ReturnStatus::KEYNOTREADABLE;
+								let description = format!(
+									"TEE Key-share {:?}: can not serialize nft_id.{} key-share.",
+									APICALL::CAPSULERETRIEVE,
+									verified_data.nft_id,
+								);
+								error!("{} , Error : {:?}", description, err);
+
+								return (
+									StatusCode::INTERNAL_SERVER_ERROR,
+									Json(
+										to_value(ApiErrorResponse {
+											status,
+											nft_id: verified_data.nft_id,
+											enclave_account,
+											description,
+										})
+										.unwrap(),
+									),
+								)
+							},
+						};
+
 					update_log_file_view(
 						block_number,
 						file_path,
@@ -864,23 +1124,27 @@ pub async fn capsule_retrieve_keyshare(
 						"capsule",
 					);
 
-					let serialized_keyshare = StoreKeyshareData {
-						nft_id: verified_data.nft_id,
-						keyshare: capsule_keyshare,
-						auth_token: AuthenticationToken { block_number, block_validation: 15 },
-					}
-					.serialize();
+					// A lapsed rental is still served read-only during its grace period
+					let status = match verified_data.rental_grace {
+						Some(_) => ReturnStatus::RENTALGRACEPERIOD,
+						None => ReturnStatus::RETRIEVESUCCESS,
+					};
+
 					// TODO [future - security] : SIGN the response
-					(
-						StatusCode::OK,
-						Json(serde_json::json!({
-							"status": ReturnStatus::RETRIEVESUCCESS,
-							"nft_id": verified_data.nft_id,
-							"enclave_account": enclave_account,
-							"keyshare_data": serialized_keyshare,
-							"description": "Success retrieving Capsule key-share.".to_string(),
-						})),
-					)
+					let mut response = serde_json::json!({
+						"status": status,
+						"nft_id": verified_data.nft_id,
+						"enclave_account": enclave_account,
+						"requester_address": canonical_address(&request.requester_address),
+						"description": "Success retrieving Capsule key-share.".to_string(),
+						"binary": binary,
+					});
+					if let Some(grace_end) = verified_data.rental_grace {
+						response["grace_end_block"] = serde_json::json!(grace_end);
+					}
+					response["keyshare_data"] = Value::String(serialized_keyshare);
+
+					(StatusCode::OK, Json(response))
 				},
 				Err(err) => {
 					let status = ReturnStatus::InvalidBlockNumber;
@@ -926,7 +1190,8 @@ pub async fn capsule_retrieve_keyshare(
 /* **********************
 	 REMOVE KEY-SHARE
 ********************** */
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
+#[serde(into = "ApiErrorPayload")]
 pub struct RemoveKeyshareResponse {
 	status: ReturnStatus,
 	nft_id: u32,
@@ -934,6 +1199,17 @@ pub struct RemoveKeyshareResponse {
 	description: String,
 }
 
+impl From<RemoveKeyshareResponse> for ApiErrorPayload {
+	fn from(response: RemoveKeyshareResponse) -> Self {
+		ApiErrorPayload::new(
+			response.status,
+			response.nft_id,
+			response.enclave_account,
+			response.description,
+		)
+	}
+}
+
 /// Remove keyshare from the enclave
 /// # Arguments
 /// * `request` - RemoveKeysharePacket
@@ -947,8 +1223,23 @@ pub async fn capsule_remove_keyshare(
 	debug!("\n\t*****\nCAPSULE REMOVE KEYSHARE API\n\t*****\n");
 	let enclave_account = get_accountid(&state).await;
 
-	// STRUCTURAL VALIDITY OF REQUEST
-	let request_data = match request.verify_remove_request(&state, "capsule-nft").await {
+	// SIGNATURE, AUTH-TOKEN AND BURNT STATE
+	let verification = request.verify_remove_request(&state, NftKind::CAPSULE).await;
+	if audit_result(
+		&state,
+		APICALL::CAPSULEREMOVE,
+		request.requested_nft_id(),
+		request.requester_address.to_string(),
+		None,
+		&verification,
+	)
+	.await
+	.is_err()
+	{
+		return audit_failure(APICALL::CAPSULEREMOVE, request.requested_nft_id(), enclave_account)
+	}
+
+	let request_data = match verification {
 		Ok(rd) => rd,
 		Err(err) => {
 			let parsed_data = match request.parse_retrieve_data() {
@@ -993,31 +1284,6 @@ pub async fn capsule_remove_keyshare(
 		)
 	}
 
-	// Is nft burnt?
-	let nft_data_opts = get_onchain_nft_data(&state, request_data.nft_id).await;
-	if let Some(nft_data) = nft_data_opts {
-		if nft_data.state.is_capsule {
-			error!(
-				"CAPSULE REMOVE : capsule is not in burnt or converted state, nft-id.{}, requester : {}",
-				request_data.nft_id, request.requester_address
-			);
-			return (
-				StatusCode::BAD_REQUEST,
-				Json(
-					to_value(RemoveKeyshareResponse {
-						status: ReturnStatus::NOTBURNT,
-						nft_id: request_data.nft_id,
-						enclave_account,
-						description:
-							"Removing CAPSULE key-share from TEE, CAPSULE is not in burnt or converted state."
-								.to_string(),
-					})
-					.unwrap(),
-				),
-			);
-		}
-	}
-
 	let av = match get_nft_availability(&state, request_data.nft_id).await {
 		Some(av) => {
 			// If it's not Capsule or Hybrid
@@ -1097,6 +1363,12 @@ pub async fn capsule_remove_keyshare(
 			}
 
 			remove_nft_availability(&state, request_data.nft_id).await;
+			publish_keyshare_event(
+				KeyshareEventKind::REMOVED,
+				request_data.nft_id,
+				helper::NftType::Capsule,
+				get_blocknumber(&state).await,
+			);
 			info!(
 				"REMOVE CAPSULE :  Keyshare is successfully removed from enclave. nft_id = {}",
 				request_data.nft_id
@@ -0,0 +1,362 @@
+use std::collections::{BTreeMap, HashMap};
+
+use axum::{
+	extract::{Path as PathExtract, State},
+	http::StatusCode,
+	response::IntoResponse,
+	Json,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use subxt::ext::sp_core::Pair;
+use tracing::{debug, warn};
+
+use crate::{
+	chain::{
+		constants::SEALPATH,
+		helper::{Availability, NftType},
+	},
+	servers::state::{get_accountid, get_blocknumber, get_keypair, get_storage_proof, SharedState},
+};
+
+/* ---------------------------------------
+	SPARSE MERKLE STORAGE COMMITMENT
+--------------------------------------- */
+
+pub type TreeHash = [u8; 32];
+
+// One level per bit of the nft_id
+const TREE_DEPTH: usize = 32;
+
+const LEAF_PREFIX: u8 = 0;
+const BRANCH_PREFIX: u8 = 1;
+
+/// Sparse Merkle tree over nft_id -> keyshare hash.
+/// It is updated on every store and remove, so the root is always the commitment of the current
+/// holdings. Only non-empty nodes are kept, empty subtrees are represented by their default hash.
+#[derive(Clone, Debug)]
+pub struct StorageTree {
+	// nft_id -> keyshare hash
+	leaves: BTreeMap<u32, TreeHash>,
+	// (level, index) -> node hash, level 0 are leaves and level TREE_DEPTH is the root
+	nodes: HashMap<(usize, u64), TreeHash>,
+	// Hash of an empty subtree at each level
+	empty: [TreeHash; TREE_DEPTH + 1],
+}
+
+/// Inclusion or exclusion proof of an nft_id, siblings are listed from the leaf to the root.
+/// Only non-empty siblings are sent, bit `level` of the bitmap is set for each of them.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct StorageProof {
+	pub nft_id: u32,
+	// None proves that the nft_id is not stored
+	pub keyshare_hash: Option<String>,
+	pub bitmap: u32,
+	pub siblings: Vec<String>,
+}
+
+fn leaf_node(nft_id: u32, keyshare_hash: &TreeHash) -> TreeHash {
+	let mut hasher = Sha256::new();
+	hasher.update([LEAF_PREFIX]);
+	hasher.update(nft_id.to_be_bytes());
+	hasher.update(keyshare_hash);
+	hasher.finalize().into()
+}
+
+fn branch_node(left: &TreeHash, right: &TreeHash) -> TreeHash {
+	let mut hasher = Sha256::new();
+	hasher.update([BRANCH_PREFIX]);
+	hasher.update(left);
+	hasher.update(right);
+	hasher.finalize().into()
+}
+
+fn parse_tree_hash(hash: &str) -> Option<TreeHash> {
+	let bytes = hex::decode(hash.trim_start_matches("0x")).ok()?;
+	bytes.try_into().ok()
+}
+
+impl Default for StorageTree {
+	fn default() -> Self {
+		let mut empty = [[0u8; 32]; TREE_DEPTH + 1];
+		for level in 0..TREE_DEPTH {
+			empty[level + 1] = branch_node(&empty[level], &empty[level]);
+		}
+
+		StorageTree { leaves: BTreeMap::new(), nodes: HashMap::new(), empty }
+	}
+}
+
+impl StorageTree {
+	/// Build the tree from keyshare hashes
+	pub fn from_keyshares(keyshares: impl IntoIterator<Item = (u32, TreeHash)>) -> StorageTree {
+		let mut tree = StorageTree::default();
+		for (nft_id, keyshare_hash) in keyshares {
+			tree.update(nft_id, Some(keyshare_hash));
+		}
+		tree
+	}
+
+	/// Build the tree from the sealed keyshares of the availability map
+	pub fn from_availability(availability_map: &BTreeMap<u32, Availability>) -> StorageTree {
+		StorageTree::from_keyshares(
+			availability_map
+				.iter()
+				.map(|(nft_id, av)| (*nft_id, keyshare_hash(*nft_id, av))),
+		)
+	}
+
+	fn node(&self, level: usize, index: u64) -> TreeHash {
+		self.nodes.get(&(level, index)).copied().unwrap_or(self.empty[level])
+	}
+
+	fn set_node(&mut self, level: usize, index: u64, node: TreeHash) {
+		if node == self.empty[level] {
+			self.nodes.remove(&(level, index));
+		} else {
+			self.nodes.insert((level, index), node);
+		}
+	}
+
+	/// Set or remove the keyshare hash of an nft, only its path to the root is recomputed
+	/// # Arguments
+	/// * `nft_id` - nft/capsule id
+	/// * `keyshare_hash` - hash of the sealed keyshare, None if it is removed
+	pub fn update(&mut self, nft_id: u32, keyshare_hash: Option<TreeHash>) {
+		let mut node = match keyshare_hash {
+			Some(keyshare_hash) => {
+				self.leaves.insert(nft_id, keyshare_hash);
+				leaf_node(nft_id, &keyshare_hash)
+			},
+			None => {
+				self.leaves.remove(&nft_id);
+				self.empty[0]
+			},
+		};
+
+		let mut index = nft_id as u64;
+		self.set_node(0, index, node);
+
+		for level in 0..TREE_DEPTH {
+			let sibling = self.node(level, index ^ 1);
+			node = if index & 1 == 0 {
+				branch_node(&node, &sibling)
+			} else {
+				branch_node(&sibling, &node)
+			};
+			index >>= 1;
+			self.set_node(level + 1, index, node);
+		}
+	}
+
+	pub fn root(&self) -> String {
+		hex::encode(self.node(TREE_DEPTH, 0))
+	}
+
+	// Number of stored keyshares
+	pub fn count(&self) -> usize {
+		self.leaves.len()
+	}
+
+	pub fn keyshare_hash(&self, nft_id: u32) -> Option<String> {
+		self.leaves.get(&nft_id).map(hex::encode)
+	}
+
+	/// Proof of the keyshare hash of an nft_id, or of its absence
+	pub fn proof(&self, nft_id: u32) -> StorageProof {
+		let mut bitmap = 0u32;
+		let mut siblings = Vec::new();
+		let mut index = nft_id as u64;
+
+		for level in 0..TREE_DEPTH {
+			let sibling = self.node(level, index ^ 1);
+			if sibling != self.empty[level] {
+				bitmap |= 1 << level;
+				siblings.push(hex::encode(sibling));
+			}
+			index >>= 1;
+		}
+
+		StorageProof { nft_id, keyshare_hash: self.keyshare_hash(nft_id), bitmap, siblings }
+	}
+}
+
+impl StorageProof {
+	/// Root of the tree the proof belongs to, None if the proof is malformed
+	pub fn compute_root(&self) -> Option<String> {
+		let empty = StorageTree::default().empty;
+
+		let mut node = match &self.keyshare_hash {
+			Some(keyshare_hash) => leaf_node(self.nft_id, &parse_tree_hash(keyshare_hash)?),
+			None => empty[0],
+		};
+
+		let mut siblings = self.siblings.iter();
+		let mut index = self.nft_id as u64;
+
+		for (level, empty_sibling) in empty.iter().enumerate().take(TREE_DEPTH) {
+			let sibling = if self.bitmap & (1 << level) != 0 {
+				parse_tree_hash(siblings.next()?)?
+			} else {
+				*empty_sibling
+			};
+			node = if index & 1 == 0 {
+				branch_node(&node, &sibling)
+			} else {
+				branch_node(&sibling, &node)
+			};
+			index >>= 1;
+		}
+
+		if siblings.next().is_some() {
+			return None
+		}
+
+		Some(hex::encode(node))
+	}
+
+	pub fn verify(&self, root: &str) -> bool {
+		self.compute_root().as_deref() == Some(root.trim_start_matches("0x"))
+	}
+}
+
+/// Message signed by the enclave account for a storage root
+pub fn storage_root_message(block_number: u32, root: &str) -> String {
+	format!("storage-root_{block_number}_{root}")
+}
+
+/// Hash of the sealed keyshare files of an nft.
+/// A hybrid nft has a secret and a capsule keyshare, both are hashed.
+/// # Arguments
+/// * `nft_id` - nft/capsule id
+/// * `av` - availability of the nft, it gives the file names
+pub fn keyshare_hash(nft_id: u32, av: &Availability) -> TreeHash {
+	let prefixes: &[&str] = match av.nft_type {
+		NftType::Secret => &["nft"],
+		NftType::Capsule => &["capsule"],
+		NftType::Hybrid => &["nft", "capsule"],
+	};
+
+	let mut hasher = Sha256::new();
+	for prefix in prefixes {
+		match read_keyshare_file(prefix, nft_id, av.block_number) {
+			Some(keyshare) => {
+				hasher.update(prefix.as_bytes());
+				hasher.update(keyshare);
+			},
+			None =>
+				warn!("STORAGE COMMITMENT : {} keyshare of nft_id {} is not found", prefix, nft_id),
+		}
+	}
+
+	hasher.finalize().into()
+}
+
+// The files of a hybrid nft may be stored in different blocks, the directory is only scanned if
+// the file of the availability block does not exist
+fn read_keyshare_file(prefix: &str, nft_id: u32, block_number: u32) -> Option<Vec<u8>> {
+	let file_path = format!("{SEALPATH}/{prefix}_{nft_id}_{block_number}.keyshare");
+	if let Ok(keyshare) = std::fs::read(file_path) {
+		return Some(keyshare)
+	}
+
+	let file_prefix = format!("{prefix}_{nft_id}_");
+	std::fs::read_dir(SEALPATH)
+		.ok()?
+		.filter_map(|entry| entry.ok())
+		.find(|entry| {
+			let file_name = entry.file_name().to_string_lossy().to_string();
+			file_name.starts_with(&file_prefix) && file_name.ends_with(".keyshare")
+		})
+		.and_then(|entry| std::fs::read(entry.path()).ok())
+}
+
+/// Storage proof of an nft_id, with the current root signed by the enclave
+/// # Arguments
+/// * `state` - SharedState
+/// * `nft_id` - nft/capsule id
+/// # Returns
+/// * `Json` - root, its signature and the inclusion or exclusion proof of the nft_id
+pub async fn storage_proof(
+	State(state): State<SharedState>,
+	PathExtract(nft_id): PathExtract<u32>,
+) -> impl IntoResponse {
+	let block_number = get_blocknumber(&state).await;
+	let (root, proof) = get_storage_proof(&state, nft_id).await;
+	let signature = get_keypair(&state)
+		.await
+		.sign(storage_root_message(block_number, &root).as_bytes());
+
+	debug!(
+		"STORAGE PROOF : nft_id {} exists = {}, {} siblings",
+		nft_id,
+		proof.keyshare_hash.is_some(),
+		proof.siblings.len()
+	);
+
+	(
+		StatusCode::OK,
+		Json(serde_json::json!({
+			"enclave_account": get_accountid(&state).await,
+			"block_number": block_number,
+			"root": root,
+			// Signature of "storage-root_BLOCKNUMBER_ROOT" by the enclave account
+			"root_signature": format!("{}{:?}", "0x", signature),
+			"exists": proof.keyshare_hash.is_some(),
+			"proof": proof,
+		})),
+	)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn hash(byte: u8) -> TreeHash {
+		[byte; 32]
+	}
+
+	#[test]
+	fn storage_tree_test() {
+		let mut tree = StorageTree::default();
+		let empty_root = tree.root();
+
+		tree.update(1, Some(hash(1)));
+		tree.update(2, Some(hash(2)));
+		tree.update(u32::MAX, Some(hash(3)));
+		let root = tree.root();
+		assert_ne!(root, empty_root);
+
+		// Same holdings, same root, whatever the order of updates
+		let rebuilt =
+			StorageTree::from_keyshares(vec![(u32::MAX, hash(3)), (2, hash(2)), (1, hash(1))]);
+		assert_eq!(rebuilt.root(), root);
+
+		// Inclusion
+		let proof = tree.proof(2);
+		assert_eq!(proof.keyshare_hash, Some(hex::encode(hash(2))));
+		assert!(proof.verify(&root));
+
+		// Exclusion
+		let proof = tree.proof(3);
+		assert_eq!(proof.keyshare_hash, None);
+		assert!(proof.verify(&root));
+
+		// Forged keyshare hash
+		let mut forged = tree.proof(1);
+		forged.keyshare_hash = Some(hex::encode(hash(9)));
+		assert!(!forged.verify(&root));
+
+		// Changed keyshare, then removal restores the previous roots
+		tree.update(2, Some(hash(4)));
+		assert_ne!(tree.root(), root);
+		tree.update(2, Some(hash(2)));
+		assert_eq!(tree.root(), root);
+
+		tree.update(1, None);
+		tree.update(2, None);
+		tree.update(u32::MAX, None);
+		assert_eq!(tree.root(), empty_root);
+		assert!(tree.nodes.is_empty());
+	}
+}
@@ -0,0 +1,172 @@
+use std::{
+	borrow::Cow,
+	io::Read,
+	sync::atomic::{AtomicU64, Ordering},
+	time::Instant,
+};
+
+use serde::Serialize;
+use tracing::{debug, warn};
+use zeroize::Zeroizing;
+
+use crate::chain::policy::keyshare_policy;
+
+/* ---------------------------------------
+	AT-REST KEYSHARE COMPRESSION
+--------------------------------------- */
+
+// Header of compressed sealed files, 0xFF never appears in utf-8 keyshares
+pub const COMPRESSION_MAGIC: [u8; 4] = [0xFF, b'Z', b'S', b'T'];
+// Header of base64url encoded binary keyshares, under the compression, 0xFE is not utf-8 either
+pub const BINARY_MAGIC: [u8; 4] = [0xFE, b'B', b'6', b'4'];
+const COMPRESSION_LEVEL: i32 = 3;
+
+static COMPRESSED_KEYSHARES: AtomicU64 = AtomicU64::new(0);
+static RAW_BYTES: AtomicU64 = AtomicU64::new(0);
+static STORED_BYTES: AtomicU64 = AtomicU64::new(0);
+static COMPRESS_MICROS: AtomicU64 = AtomicU64::new(0);
+static DECOMPRESSED_KEYSHARES: AtomicU64 = AtomicU64::new(0);
+static DECOMPRESS_MICROS: AtomicU64 = AtomicU64::new(0);
+
+/// Storage savings and CPU cost of compression since enclave start
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct CompressionStats {
+	pub compressed_keyshares: u64,
+	pub raw_bytes: u64,
+	pub stored_bytes: u64,
+	pub compress_micros: u64,
+	pub decompressed_keyshares: u64,
+	pub decompress_micros: u64,
+}
+
+pub fn compression_stats() -> CompressionStats {
+	CompressionStats {
+		compressed_keyshares: COMPRESSED_KEYSHARES.load(Ordering::Relaxed),
+		raw_bytes: RAW_BYTES.load(Ordering::Relaxed),
+		stored_bytes: STORED_BYTES.load(Ordering::Relaxed),
+		compress_micros: COMPRESS_MICROS.load(Ordering::Relaxed),
+		decompressed_keyshares: DECOMPRESSED_KEYSHARES.load(Ordering::Relaxed),
+		decompress_micros: DECOMPRESS_MICROS.load(Ordering::Relaxed),
+	}
+}
+
+/// Prepare a keyshare for sealing, compressed if it is larger than threshold
+/// # Arguments
+/// * `keyshare` - raw keyshare
+/// * `threshold` - minimum size to compress, 0 disables compression
+/// # Returns
+/// * `Cow<[u8]>` - content of the sealed file
+pub fn seal_keyshare(keyshare: &[u8], threshold: usize) -> Cow<[u8]> {
+	if threshold == 0 || keyshare.len() < threshold {
+		return Cow::Borrowed(keyshare)
+	}
+
+	let start = Instant::now();
+	let compressed = match zstd::bulk::compress(keyshare, COMPRESSION_LEVEL) {
+		Ok(compressed) => compressed,
+		Err(err) => {
+			warn!("COMPRESSION : error compressing keyshare, stored raw : {err:?}");
+			return Cow::Borrowed(keyshare)
+		},
+	};
+	let elapsed = start.elapsed().as_micros() as u64;
+
+	// Not worth it
+	if compressed.len() + COMPRESSION_MAGIC.len() >= keyshare.len() {
+		return Cow::Borrowed(keyshare)
+	}
+
+	let mut sealed = Vec::with_capacity(COMPRESSION_MAGIC.len() + compressed.len());
+	sealed.extend_from_slice(&COMPRESSION_MAGIC);
+	sealed.extend_from_slice(&compressed);
+
+	COMPRESSED_KEYSHARES.fetch_add(1, Ordering::Relaxed);
+	RAW_BYTES.fetch_add(keyshare.len() as u64, Ordering::Relaxed);
+	STORED_BYTES.fetch_add(sealed.len() as u64, Ordering::Relaxed);
+	COMPRESS_MICROS.fetch_add(elapsed, Ordering::Relaxed);
+
+	debug!("COMPRESSION : keyshare {} -> {} bytes", keyshare.len(), sealed.len());
+
+	Cow::Owned(sealed)
+}
+
+/// Largest keyshare at rest, binary keyshares are base64url encoded behind their header
+fn max_unsealed_size() -> usize {
+	let max_size = keyshare_policy().max_size;
+	BINARY_MAGIC.len() + base64::encoded_len(max_size, false).unwrap_or(max_size)
+}
+
+/// Restore the raw keyshare of a sealed file content
+/// # Arguments
+/// * `sealed` - content of the sealed file
+/// # Returns
+/// * `Option<Vec<u8>>` - decompressed keyshare, None if the content is not compressed
+/// # Errors
+/// * `InvalidData` - if the content decompresses beyond the keyshare size limit
+pub fn unseal_keyshare(sealed: &[u8]) -> std::io::Result<Option<Vec<u8>>> {
+	let compressed = match sealed.strip_prefix(&COMPRESSION_MAGIC) {
+		Some(compressed) => compressed,
+		None => return Ok(None),
+	};
+
+	let start = Instant::now();
+	let limit = max_unsealed_size();
+
+	// One byte over the limit is enough to reject the content, the partial output is wiped
+	let mut keyshare = Zeroizing::new(Vec::with_capacity(limit.min(compressed.len() * 4)));
+	zstd::stream::Decoder::with_buffer(compressed)?
+		.take(limit as u64 + 1)
+		.read_to_end(&mut keyshare)?;
+
+	if keyshare.len() > limit {
+		return Err(std::io::Error::new(
+			std::io::ErrorKind::InvalidData,
+			format!("COMPRESSION : sealed keyshare decompresses beyond {limit} bytes"),
+		))
+	}
+
+	DECOMPRESSED_KEYSHARES.fetch_add(1, Ordering::Relaxed);
+	DECOMPRESS_MICROS.fetch_add(start.elapsed().as_micros() as u64, Ordering::Relaxed);
+
+	Ok(Some(std::mem::take(&mut *keyshare)))
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn seal_unseal_test() {
+		let keyshare = r#"{"key":"thisIsMySecretDataWhichCannotContainAnyUnderScore"}"#.repeat(40);
+
+		// disabled or small
+		assert!(matches!(seal_keyshare(keyshare.as_bytes(), 0), Cow::Borrowed(_)));
+		assert!(matches!(seal_keyshare(b"short keyshare", 1024), Cow::Borrowed(_)));
+		assert_eq!(unseal_keyshare(keyshare.as_bytes()).unwrap(), None);
+
+		let sealed = seal_keyshare(keyshare.as_bytes(), 1024);
+		assert!(sealed.starts_with(&COMPRESSION_MAGIC));
+		assert!(sealed.len() * 4 < keyshare.len());
+
+		assert_eq!(unseal_keyshare(&sealed).unwrap().unwrap(), keyshare.as_bytes());
+
+		let stats = compression_stats();
+		assert!(stats.compressed_keyshares >= 1);
+		assert!(stats.raw_bytes > stats.stored_bytes);
+	}
+
+	#[test]
+	fn unseal_bound_test() {
+		let oversized = vec![b'a'; max_unsealed_size() + 1];
+		let mut sealed = COMPRESSION_MAGIC.to_vec();
+		sealed.extend_from_slice(&zstd::bulk::compress(&oversized, COMPRESSION_LEVEL).unwrap());
+
+		let err = unseal_keyshare(&sealed).unwrap_err();
+		assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+		let largest = &oversized[1..];
+		let mut sealed = COMPRESSION_MAGIC.to_vec();
+		sealed.extend_from_slice(&zstd::bulk::compress(largest, COMPRESSION_LEVEL).unwrap());
+		assert_eq!(unseal_keyshare(&sealed).unwrap().unwrap(), largest);
+	}
+}
@@ -20,11 +20,258 @@ pub const _MAX_STREAM_SIZE: usize = 1000 * 3 * 1024; // 3KB is the size of keysh
 // ---------- HTTP SERVER
 pub const SEALPATH: &str = "/nft";
 pub const SYNC_STATE_FILE: &str = "/nft/sync.state";
-pub const ENCLAVE_ACCOUNT_FILE: &str = "/nft/enclave_account.key";
+pub const KEYSHARE_INDEX_FILE: &str = "/nft/keyshare.index"; // Availability index of a clean shutdown
 pub const CONTENT_LENGTH_LIMIT: usize = 400 * 1024 * 1024; // 400MB for 6 millions of keyshares
+pub const COMPRESSION_THRESHOLD: usize = 0; // Minimum keyshare size to compress at rest, zero disables
+
+// ---------- TASK SUPERVISOR
+pub const TASK_SHUTDOWN_TIMEOUT: u64 = 10; // Seconds to wait for a background task to stop
+
+// ---------- GRACEFUL SHUTDOWN
+pub const SHUTDOWN_DRAIN_TIMEOUT: u64 = 30; // Seconds to drain the in-flight requests, the request timeout
+pub const SHUTDOWN_HOOK_TIMEOUT: u64 = 30; // Seconds to wait for a subsystem hook
+pub const SHUTDOWN_BACKUP_WAIT: u64 = 20; // Seconds to wait for the running backups
+
+// ---------- METRIC
+pub const HEARTBEAT_INTERVAL: u32 = 600; // ~1 hour of 6 seconds blocks, zero disables heartbeat
+pub const MAX_SCAN_INTERVAL: u32 = 14400; // Maximum blocks in a reconciliation interval
+pub const SCAN_CONCURRENCY: usize = 16; // Blocks of a reconciliation interval fetched at once
+pub const MAX_SCAN_JOBS: usize = 2; // Running reconciliation scans
+pub const SCAN_JOB_PERIOD: u32 = 600; // ~1 hour of 6 seconds blocks to read a reconciliation report
+
+// ---------- INTEGRITY
+pub const INTEGRITY_LOG_FILE: &str = "/nft/integrity.log";
+
+// ---------- ADMIN QUORUM
+pub const QUORUM_FILE: &str = "/nft/quorum.json";
+pub const QUORUM_ACTIVATION_DELAY: u32 = 14400; // ~24 hours of 6 seconds blocks
+pub const MIN_QUORUM_THRESHOLD: u8 = 2; // Unless a single-admin quorum is allowed at startup
+
+// ---------- ADMIN WHITELIST
+pub const CLUSTER_REFRESH_INTERVAL: u32 = 600; // ~1 hour of 6 seconds blocks
+											   // Admins while no Admin cluster is registered onchain, compiled in so they are measured in the
+											   // MRENCLAVE. Empty : no admin until the chain has one.
+pub const BOOTSTRAP_ADMINS: &[&str] = &[];
+
+// ---------- ADMIN READ-ONLY SWITCH
+pub const READONLY_FILE: &str = "/nft/readonly.json";
+pub const MAX_READONLY_PERIOD: u32 = 100800; // ~7 days of 6 seconds blocks
+
+// ---------- ADMIN MAINTENANCE WINDOW
+pub const MAINTENANCE_FILE: &str = "/nft/maintenance.json";
+pub const MAX_MAINTENANCE_DURATION: u64 = 604800; // 7 days in seconds
+pub const MAINTENANCE_RETRY_AFTER: u64 = 60; // seconds, once the expected end is overdue
+
+// ---------- GOVERNANCE KILL-SWITCH
+pub const KILLSWITCH_FILE: &str = "/nft/killswitch.state";
+pub const KILLSWITCH_VOTE_PERIOD: u32 = 600; // ~1 hour of 6 seconds blocks to reach the threshold
+
+// ---------- MINT PROVISIONING
+pub const PROVISION_FILE: &str = "/nft/provision.json";
+pub const MAX_PROVISION_RANGE: u32 = 100_000;
+// Store rate limits of the expected owners are multiplied during their window
+pub const PROVISION_RATE_FACTOR: u32 = 10;
+
+// ---------- AUDIT TRAIL
+pub const AUDIT_LOG_FILE: &str = "/nft/audit.log";
+pub const MAX_AUDIT_EXPORT: usize = 10_000; // Records of an export response
+
+// ---------- ENCLAVE ACCOUNT
+pub const ENCLAVE_ACCOUNT_FILE: &str = "/keys/enclave_account.key"; // Sealed to the binary
+pub const LEGACY_ACCOUNT_FILE: &str = "/nft/enclave_account.key"; // Older versions and restored backups
+pub const ACCOUNT_ROTATION_FILE: &str = "/nft/account_rotations.log"; // Handover signatures
+pub const ACCOUNT_ROTATION_PENDING_FILE: &str = "/keys/account_rotation.pending"; // Waiting for the registration
+pub const ACCOUNT_ROTATION_FUNDING: u128 = 1_000_000_000_000_000_000; // 1 CAPS for the new account
+
+// ---------- UPGRADE HANDOFF
+pub const STORAGE_KEY_FILE: &str = "/keys/storage.key"; // Sealed to the binary
+pub const STORAGE_KEY_DEVICE: &str = "/dev/attestation/keys/storage"; // Key of the encrypted mounts
+pub const UPGRADE_ARM_PERIOD: u32 = 600; // ~1 hour of 6 seconds blocks
+pub const UPGRADE_DRAIN_TIMEOUT: u64 = 30; // Seconds to wait for in-flight requests
+										   // Mounts sealed to the binary by the previous versions, and their storage key mount
+pub const LEGACY_SEALED_MOUNTS: [(&str, &str); 2] =
+	[("/legacy/nft", "/nft"), ("/legacy/certificates", "/certificates")];
+pub const LEGACY_MIGRATION_FILE: &str = "/nft/legacy_migration.json"; // Written once migrated
+
+// ---------- RESUMABLE RESTORE
+pub const UPLOAD_PATH: &str = "/nft/uploads"; // Parts of the uploaded backup archives
+pub const MAX_UPLOAD_PART_SIZE: u64 = 64 * 1024 * 1024;
+pub const MAX_UPLOAD_SIZE: u64 = 64 * 1024 * 1024 * 1024;
+pub const MAX_UPLOAD_SESSIONS: usize = 4;
+pub const UPLOAD_SESSION_PERIOD: u32 = 14400; // ~24 hours of 6 seconds blocks
+
+// ---------- BACKUP WORKSPACES
+pub const WORKSPACE_PATH: &str = "/nft/workspaces"; // Private directories of the backups and restores
+pub const MAX_WORKSPACES: usize = 16; // Backups and restores in progress
+
+// ---------- BACKUP JOBS
+pub const BACKUP_JOB_PATH: &str = "/nft/jobs"; // Archives of the background backups
+pub const MAX_BACKUP_JOBS: usize = 4; // Queued or running jobs
+pub const BACKUP_JOB_PERIOD: u32 = 600; // ~1 hour of 6 seconds blocks to download an archive
+pub const DOWNLOAD_TOKEN_PERIOD: u32 = 50; // ~5 minutes of 6 seconds blocks to use a download token
+pub const MAX_DOWNLOAD_TOKENS: usize = 64; // Unused download tokens
+
+// ---------- SCHEDULED BACKUPS
+pub const BLOCKS_PER_HOUR: u32 = 600; // 6 seconds blocks
+
+// ---------- FETCH-ID PAGINATION
+pub const MAX_FETCH_ID_VECTOR: usize = 1_000_000; // Nft ids of a fetch-id request, over all its pages
+pub const MAX_FETCH_IDS: usize = 10_000; // Nft ids of a page
+pub const MAX_FETCH_ARCHIVE_BYTES: u64 = 1024 * 1024 * 1024; // Keyshare bytes of a page, before compression
+
+// ---------- BACKUP KEY ESCROW
+pub const ESCROW_FILE: &str = "/nft/escrow.json";
+pub const MAX_ESCROW_SHARES: usize = 255; // Admins of a Shamir escrow over GF(256)
+
+// ---------- ENCRYPTED BACKUPS
+pub const BACKUP_IDENTITY_FILE: &str = "/keys/backup_identity.key"; // Sealed to the binary
+pub const RECIPIENT_QUOTE_VALIDITY: u32 = 600; // ~1 hour of 6 seconds blocks
+
+// ---------- DISASTER RECOVERY
+pub const RECOVERY_KEY_FILE: &str = "/nft/recovery.key"; // Shared by the enclaves of the same MRSIGNER
+
+// ---------- KEYSHARE INJECTION
+pub const MAX_INJECTED_KEYSHARES: usize = 10_000; // Keyshares of a push-keyshares request
+
+// ---------- BACKUP CONTAINER
+pub const MAX_CONTAINER_MANIFEST_SIZE: u64 = 256 * 1024 * 1024; // Manifest json of a v2 container
+
+// ---------- RESTORE DRY-RUN
+pub const MAX_RESTORE_CHAIN_CHECKS: usize = 100_000; // Nfts of an archive checked onchain
+pub const RESTORE_CHAIN_CONCURRENCY: usize = 32;
+
+// ---------- REPLAY PROTECTION
+pub const REPLAY_JOURNAL_FILE: &str = "/nft/replay.journal";
+pub const MAX_REPLAY_ENTRIES: usize = 100_000;
+
+// ---------- NEGATIVE LOOKUP CACHE
+pub const NEGATIVE_CACHE_TTL: u32 = 5; // Blocks, creation events invalidate sooner
+pub const MAX_NEGATIVE_CACHE_ENTRIES: usize = 10_000;
+
+// ---------- RATE LIMIT
+pub const MAX_QUOTA_BUCKETS: usize = 100_000; // Token buckets of accounts and ip addresses
+pub const MAX_QUOTA_BODY_SIZE: usize = 2 * 1024 * 1024; // Bytes of a rate-limited request body
+pub const IP_RATE_BURST: u32 = 100; // Api requests of an ip address at once
+pub const IP_RATE_PER_MINUTE: u32 = 600; // Api requests of an ip address per minute
+pub const IP_BAN_FAILURES: u32 = 10; // Signature failures of an ip address before its ban
+pub const IP_BAN_WINDOW: u64 = 600; // Seconds during which the signature failures are counted
+pub const IP_BAN_DURATION: u64 = 900; // Seconds of a ban
+pub const QUOTE_RATE_BURST: u32 = 5; // On-demand quotes of an ip address at once
+pub const QUOTE_RATE_PER_MINUTE: u32 = 10; // On-demand quotes of an ip address per minute
+pub const QUOTE_GLOBAL_BURST: u32 = 20; // On-demand quotes of all the callers at once
+pub const QUOTE_GLOBAL_PER_MINUTE: u32 = 60; // On-demand quotes of all the callers per minute
+
+// ---------- OUTBOUND PROXY
+pub const PROXY_CONNECT_TIMEOUT: u64 = 10; // Seconds to open a tunnel through the proxy
+pub const MAX_PROXY_RESPONSE_SIZE: usize = 8 * 1024; // Bytes of the CONNECT response header
+
+// ---------- HEALTH CHECK
+pub const HEALTH_CHAIN_TIMEOUT: u64 = 60; // Seconds without a finalized block before the chain is disconnected
+pub const HEALTH_SYNC_LAG: u32 = 10; // Unprocessed blocks before the enclave is syncing
+pub const ATTESTATION_FRESHNESS: u32 = 600; // Blocks a quote is fresh, ~1 hour
+pub const LIVENESS_LOCK_TIMEOUT: u64 = 5; // Seconds to read the shared state before the enclave is not alive
+
+// ---------- PROMETHEUS METRICS
+pub const REQUEST_LATENCY_BUCKETS: &[f64] =
+	&[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0]; // Seconds
+pub const BACKUP_DURATION_BUCKETS: &[f64] =
+	&[1.0, 5.0, 15.0, 30.0, 60.0, 300.0, 900.0, 1800.0, 3600.0]; // Seconds
+
+// ---------- RESOURCE MONITOR
+pub const RESOURCE_MONITOR_INTERVAL: u64 = 60; // Seconds between resource samples
+pub const RESOURCE_ALERT_COOLDOWN: u64 = 3600; // Seconds between repeated alerts
+pub const FD_ALERT_PERCENT: f64 = 80.0; // Percentage of the open files limit
+pub const MAX_RESOURCE_CONSUMERS: usize = 20;
+
+// ---------- RESPONSE PADDING
+pub const MAX_PADDING_BUCKET: usize = 1024 * 1024; // Bytes of the largest padding bucket
+pub const MAX_PADDED_BODY_SIZE: usize = 16 * 1024 * 1024; // Larger responses are not padded
+
+// ---------- REQUEST CORRELATION
+pub const MAX_REQUEST_ID_LENGTH: usize = 64; // Longer X-Request-Id headers are replaced
+
+// ---------- OPERATION COORDINATION
+pub const OPERATION_QUEUE_TIMEOUT: u64 = 10; // Seconds a keyshare request waits for a maintenance operation
+pub const MAINTENANCE_QUEUE_TIMEOUT: u64 = 20; // Seconds a restore or bulk backup waits for its turn
+pub const BACKUP_JOB_QUEUE_TIMEOUT: u64 = 600; // Seconds a queued backup job waits for its turn
+pub const MAX_QUEUE_TIMEOUT: u64 = 300; // Longest queuing timeout of the runtime configuration
+
+// ---------- REQUEST BODY LIMITS
+pub const MAX_KEYSHARE_BODY_SIZE: usize = 64 * 1024; // Bytes of a keyshare request
+pub const MAX_BATCH_BODY_SIZE: usize = 2 * 1024 * 1024; // Bytes of a batch request
+pub const MAX_ADMIN_BODY_SIZE: usize = 16 * 1024 * 1024; // Bytes of an admin or sync request
+pub const MAX_FORM_ENVELOPE_SIZE: usize = 64 * 1024; // Bytes of a form before its archive part
+
+// ---------- CORS
+pub const CORS_MAX_AGE: u64 = 3600; // Seconds a preflight response is cached by the browser
+
+// ---------- CONNECTIONS
+pub const HTTP2_KEEPALIVE_INTERVAL: u64 = 20; // Seconds between the pings of an idle HTTP/2 connection
+pub const HTTP2_KEEPALIVE_TIMEOUT: u64 = 20; // Seconds to acknowledge a ping before the connection is closed
+pub const HTTP2_MAX_STREAMS: u32 = 256; // Concurrent requests of an HTTP/2 connection
+pub const HTTP1_HEADER_TIMEOUT: u64 = 30; // Seconds to send the headers of an HTTP/1.1 request
+pub const TCP_KEEPALIVE: u64 = 60; // Seconds of inactivity before the TCP keepalive probes
+
+// ---------- RESPONSE COMPRESSION
+pub const COMPRESSION_MIN_SIZE: u16 = 1024; // Bytes of the smallest compressed response body
+
+// ---------- KEYSHARE EVENTS
+pub const KEYSHARE_EVENT_BUFFER: usize = 1024; // Events kept for a slow subscriber before it lags
+pub const MAX_EVENT_SUBSCRIPTIONS: usize = 1000; // Open websockets of keyshare events
+pub const MAX_SUBSCRIBED_NFTS: usize = 1000; // Nft ids of a subscription
+pub const SUBSCRIPTION_AUTH_TIMEOUT: u64 = 10; // Seconds to send the subscription packet
+
+// ---------- STRUCTURED LOGGING
+pub const LOG_FILE_NAME: &str = "sgx_server.log"; // Current log file, rotated files get a ".N" suffix
+pub const MAX_LOG_FILE_SIZE: u64 = 16 * 1024 * 1024; // Bytes of a log file before its rotation
+pub const MAX_LOG_FILES: usize = 5; // Rotated log files kept beside the current one
+pub const MAX_LOG_DIRECTIVES_LENGTH: usize = 1024; // Characters of runtime filter directives
+
+// ---------- RA-TLS
+pub const RA_TLS_REFRESH_INTERVAL: u64 = 24 * 3600; // Seconds between certificate renewals
+
+// ---------- QUOTE VERIFICATION
+pub const PCCS_URL: &str = "https://api.trustedservices.intel.com"; // Collateral of the peer quotes
+																	// Mainnet enclaves always reject the failed peer quotes, the host can not lower the mode
+pub const QUOTE_VERIFICATION_MODE: &str =
+	if cfg!(feature = "mainnet") { "enforce" } else { "report" };
+pub const SGX_ROOT_CA_FILE: &str = "/etc/sgx/Intel_SGX_Provisioning_Certification_RootCA.pem"; // Trusted file
+pub const IAS_URL: &str = "https://api.trustedservices.intel.com/sgx/attestation/v4"; // EPID attestation reports
+pub const IAS_ROOT_CA_FILE: &str = "/etc/sgx/Intel_SGX_Attestation_RootCA.pem"; // Trusted file
+pub const IAS_API_KEY_ENV: &str = "IAS_API_KEY"; // Subscription key of the attestation service
+
+// ---------- COLLATERAL CACHE
+pub const COLLATERAL_CACHE_FILE: &str = "/nft/collateral_cache.json";
+pub const COLLATERAL_MAX_AGE: u64 = 3600; // Seconds before a cached collateral is fetched again
+pub const COLLATERAL_GRACE_PERIOD: u64 = 24 * 3600; // Seconds a stale collateral is used when the PCCS is offline
+pub const MAX_COLLATERAL_CACHE_ENTRIES: usize = 64; // Platforms (FMSPC) of the cache
+
+// ---------- QUOTE WATCHDOG
+pub const QUOTE_REFRESH_INTERVAL: u64 = 6 * 3600; // Seconds between the quote refreshes
+pub const QUOTE_FINGERPRINT_FILE: &str = "/nft/quote_fingerprint"; // Platform of the registered quote
+
+// ---------- ATTESTATION HISTORY
+pub const ATTESTATION_LOG_FILE: &str = "/nft/attestation.log";
+pub const MAX_ATTESTATION_EXPORT: usize = 10_000; // Records of an export response
+
+// ---------- MEASUREMENT ALLOWLIST
+pub const ALLOWLIST_FILE: &str = "/nft/allowlist.json";
+pub const MAX_ALLOWLIST_ENTRIES: usize = 256; // Allowed and revoked measurements of the admin quorum
+
+// ---------- RESPONSE SIGNING
+pub const MAX_SIGNED_REQUEST_SIZE: usize = 2 * 1024 * 1024; // Bytes of a keyshare request body
+pub const MAX_SIGNED_RESPONSE_SIZE: usize = 16 * 1024 * 1024; // Larger responses are not signed
+
+// ---------- SIMULATION
+pub const SIMULATION_REQUESTS: usize = 2000; // Synthetic requests per phase
+pub const SIMULATION_MAX_UTILIZATION: f64 = 0.8; // Headroom for sync, backup and chain traffic
 
 // ----------- VERIFY
+pub const SS58_PREFIX: u16 = 42; // Canonical address format of responses
 pub const MAX_VALIDATION_PERIOD: u32 = 20;
 pub const MAX_BLOCK_VARIATION: u32 = 2;
 pub const MAX_KEYSHARE_SIZE: u16 = 3000;
 pub const MIN_KEYSHARE_SIZE: u16 = 16;
+pub const MAX_BATCH_SIZE: usize = 100; // Maximum nft_ids in a batch request
+pub const MAX_SESSION_PERIOD: u32 = 100800; // Maximum lifetime of a session key, about a week
+pub const MAX_RENTAL_GRACE_PERIOD: u32 = 100800; // Maximum grace period of a lapsed rental, about a week
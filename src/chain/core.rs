@@ -14,8 +14,8 @@ use std::fmt;
 use subxt::{
 	ext::sp_core::H256,
 	storage::address::{Address, StaticStorageMapKey, Yes},
-	tx::{PairSigner, Signer},
-	utils::AccountId32,
+	tx::{PairSigner, Signer, SubmittableExtrinsic},
+	utils::{AccountId32, MultiAddress},
 	Error, OnlineClient, PolkadotConfig,
 };
 
@@ -33,9 +33,18 @@ use tracing::{debug, error, info, trace};
 #[cfg_attr(feature = "dev0", subxt::subxt(runtime_metadata_path = "./artifacts/ternoa_dev0.scale"))]
 
 pub mod ternoa {}
-use crate::servers::state::*;
+use crate::{
+	chain::rental::RentDuration,
+	servers::{
+		metrics::record_rpc_error,
+		proxy::{proxied_rpc_client, proxy_config, ProxyDestination},
+		state::*,
+	},
+};
 
-use self::ternoa::runtime_types::ternoa_pallets_primitives::nfts::NFTData;
+use self::ternoa::runtime_types::{
+	ternoa_pallets_primitives::nfts::NFTData, ternoa_rent::types::Duration as ContractDuration,
+};
 pub type DefaultApi = OnlineClient<PolkadotConfig>;
 
 const RETRY_COUNT: u8 = 3;
@@ -50,13 +59,9 @@ pub enum ReturnStatus {
 
 // -------------- CHAIN API --------------
 
-/// Creates a new chain API
-/// # Returns
-/// * `DefaultApi` - The chain API
-pub async fn create_chain_api() -> Result<DefaultApi, Error> {
-	debug!("CHAIN : get chain API");
-
-	let rpc_endoint = if cfg!(feature = "mainnet") {
+/// Primary rpc endpoint of the chain
+pub fn rpc_endpoint() -> String {
+	if cfg!(feature = "mainnet") {
 		"wss://mainnet.ternoa.network:443".to_string()
 	} else if cfg!(feature = "alphanet") {
 		"wss://alphanet.ternoa.com:443".to_string()
@@ -66,7 +71,29 @@ pub async fn create_chain_api() -> Result<DefaultApi, Error> {
 		"wss://dev-0.ternoa.network:443".to_string()
 	} else {
 		"ws://localhost:9944".to_string()
-	};
+	}
+}
+
+/// Connects to the rpc endpoint, through the rpc proxy if it is configured
+async fn connect_chain_api(rpc_endpoint: &str) -> Result<DefaultApi, Error> {
+	match proxy_config().proxy_for(ProxyDestination::Rpc) {
+		Some(proxy) => {
+			let client = proxied_rpc_client(rpc_endpoint, proxy)
+				.await
+				.map_err(|err| Error::Other(format!("{err:?}")))?;
+			DefaultApi::from_rpc_client(std::sync::Arc::new(client)).await
+		},
+		None => DefaultApi::from_url(rpc_endpoint).await,
+	}
+}
+
+/// Creates a new chain API
+/// # Returns
+/// * `DefaultApi` - The chain API
+pub async fn create_chain_api() -> Result<DefaultApi, Error> {
+	debug!("CHAIN : get chain API");
+
+	let rpc_endoint = rpc_endpoint();
 
 	// Custome client
 	// let rpc = WsClientBuilder::default().use_webpki_rustls().build(&rpc_endoint).await.unwrap();
@@ -74,7 +101,7 @@ pub async fn create_chain_api() -> Result<DefaultApi, Error> {
 
 	// RE-TRY MECHANISM
 	for retry in 0..RETRY_COUNT {
-		match DefaultApi::from_url(rpc_endoint.clone()).await {
+		match connect_chain_api(&rpc_endoint).await {
 			Ok(api) => {
 				info!("CHAIN : Successfully created chain api.");
 				return Ok(api)
@@ -82,14 +109,36 @@ pub async fn create_chain_api() -> Result<DefaultApi, Error> {
 			Err(err) => {
 				error!("CHAIN : Error acquiring chain api, retry num.{}, {:?}", retry, err);
 				sentry::capture_error(&err);
+				record_rpc_error("create_chain_api");
 			},
 		}
-		std::thread::sleep(std::time::Duration::from_secs(RETRY_DELAY));
+		tokio::time::sleep(std::time::Duration::from_secs(RETRY_DELAY)).await;
 	}
 
 	// LAST NORMAL TRY
 	info!("CHAIN : Successfully created chain api.");
-	DefaultApi::from_url(rpc_endoint).await
+	connect_chain_api(&rpc_endoint).await
+}
+
+/// Creates a chain API for an explicitly configured endpoint (i.e. secondary rpc)
+/// # Arguments
+/// * `rpc_endpoint` - The websocket url of the rpc node
+/// # Returns
+/// * `DefaultApi` - The chain API
+pub async fn create_chain_api_from_url(rpc_endpoint: &str) -> Result<DefaultApi, Error> {
+	debug!("CHAIN : get chain API from {}", rpc_endpoint);
+
+	for retry in 0..RETRY_COUNT {
+		match connect_chain_api(rpc_endpoint).await {
+			Ok(api) => return Ok(api),
+			Err(err) => {
+				error!("CHAIN : Error acquiring chain api, retry num.{}, {:?}", retry, err);
+			},
+		}
+		tokio::time::sleep(std::time::Duration::from_secs(RETRY_DELAY)).await;
+	}
+
+	connect_chain_api(rpc_endpoint).await
 }
 
 // -------------- BLOCK NUMBER --------------
@@ -110,7 +159,8 @@ pub async fn get_current_block_number(state: &SharedState) -> Result<u32, Error>
 			Err(err) => {
 				error!("CHAIN : unable to get latest block, retry num.{}, {:?}", retry, err);
 				sentry::capture_error(&err);
-				std::thread::sleep(std::time::Duration::from_secs(RETRY_DELAY));
+				record_rpc_error("get_current_block_number");
+				tokio::time::sleep(std::time::Duration::from_secs(RETRY_DELAY)).await;
 			},
 		}
 	}
@@ -121,6 +171,7 @@ pub async fn get_current_block_number(state: &SharedState) -> Result<u32, Error>
 		Err(err) => {
 			error!("CHAIN : unable to get latest block, retry num.{} : {}", RETRY_COUNT, err);
 			sentry::capture_error(&err);
+			record_rpc_error("get_current_block_number");
 			return Err(err)
 		},
 	};
@@ -183,6 +234,7 @@ pub async fn get_onchain_nft_data(
 				Err(err) => {
 					error!("CHAIN : Failed to get nft storagem, retry num.{}: {:?}", retry, err);
 					sentry::capture_error(&err);
+					record_rpc_error("get_onchain_nft_data");
 					continue
 				},
 			};
@@ -192,10 +244,11 @@ pub async fn get_onchain_nft_data(
 			Err(err) => {
 				error!("CHAIN : Failed to fetch NFT data, retry num.{} : {:?}", retry, err);
 				sentry::capture_error(&err);
+				record_rpc_error("get_onchain_nft_data");
 			},
 		}
 
-		std::thread::sleep(std::time::Duration::from_secs(RETRY_DELAY));
+		tokio::time::sleep(std::time::Duration::from_secs(RETRY_DELAY)).await;
 	}
 
 	// LAST NORMAL TRY
@@ -205,6 +258,7 @@ pub async fn get_onchain_nft_data(
 			Err(err) => {
 				error!("CHAIN : Failed to get nft storage: {err:?}");
 				sentry::capture_error(&err);
+				record_rpc_error("get_onchain_nft_data");
 				return None
 			},
 		};
@@ -214,6 +268,7 @@ pub async fn get_onchain_nft_data(
 		Err(err) => {
 			error!("CHAIN : Failed to fetch NFT data: {err:?}");
 			sentry::capture_error(&err);
+			record_rpc_error("get_onchain_nft_data");
 			None
 		},
 	}
@@ -240,6 +295,7 @@ pub async fn get_onchain_delegatee(state: &SharedState, nft_id: u32) -> Option<A
 					retry, err
 				);
 				sentry::capture_error(&err);
+				record_rpc_error("get_onchain_delegatee");
 				continue
 			},
 		};
@@ -252,10 +308,11 @@ pub async fn get_onchain_delegatee(state: &SharedState, nft_id: u32) -> Option<A
 					retry, err
 				);
 				sentry::capture_error(&err);
+				record_rpc_error("get_onchain_delegatee");
 			},
 		}
 
-		std::thread::sleep(std::time::Duration::from_secs(RETRY_DELAY));
+		tokio::time::sleep(std::time::Duration::from_secs(RETRY_DELAY)).await;
 	}
 
 	let storage = match api.storage().at_latest().await {
@@ -263,6 +320,7 @@ pub async fn get_onchain_delegatee(state: &SharedState, nft_id: u32) -> Option<A
 		Err(err) => {
 			error!("CHAIN : Failed to get storage for delegatee: {err:?}");
 			sentry::capture_error(&err);
+			record_rpc_error("get_onchain_delegatee");
 			return None
 		},
 	};
@@ -272,6 +330,7 @@ pub async fn get_onchain_delegatee(state: &SharedState, nft_id: u32) -> Option<A
 		Err(err) => {
 			error!("CHAIN : Failed to fetch NFT data for delegatee : {err:?}");
 			sentry::capture_error(&err);
+			record_rpc_error("get_onchain_delegatee");
 			None
 		},
 	}
@@ -295,6 +354,7 @@ pub async fn get_onchain_rent_contract(state: &SharedState, nft_id: u32) -> Opti
 			Err(err) => {
 				error!("CHAIN : Failed to get storage for rentee, retry num.{} : {:?}", retry, err);
 				sentry::capture_error(&err);
+				record_rpc_error("get_onchain_rent_contract");
 				continue
 			},
 		};
@@ -320,10 +380,11 @@ pub async fn get_onchain_rent_contract(state: &SharedState, nft_id: u32) -> Opti
 					retry, err
 				);
 				sentry::capture_error(&err);
+				record_rpc_error("get_onchain_rent_contract");
 			},
 		}
 
-		std::thread::sleep(std::time::Duration::from_secs(RETRY_DELAY));
+		tokio::time::sleep(std::time::Duration::from_secs(RETRY_DELAY)).await;
 	}
 
 	let storage = match api.storage().at_latest().await {
@@ -331,6 +392,7 @@ pub async fn get_onchain_rent_contract(state: &SharedState, nft_id: u32) -> Opti
 		Err(err) => {
 			error!("CHAIN : Failed to get storage for rentee: {err:?}");
 			sentry::capture_error(&err);
+			record_rpc_error("get_onchain_rent_contract");
 			return None
 		},
 	};
@@ -350,11 +412,124 @@ pub async fn get_onchain_rent_contract(state: &SharedState, nft_id: u32) -> Opti
 		Err(err) => {
 			error!("CHAIN : Failed to fetch NFT data: {err:?}");
 			sentry::capture_error(&err);
+			record_rpc_error("get_onchain_rent_contract");
+			None
+		},
+	}
+}
+
+/// Get the start block and duration terms of the NFT/Capsule rent contract
+/// # Arguments
+/// * `nft_id` - The NFT/Capsule ID
+/// # Returns
+/// * `Option<(u32, RentDuration)>` - None if there is no started contract
+pub async fn get_onchain_rent_terms(
+	state: &SharedState,
+	nft_id: u32,
+) -> Option<(u32, RentDuration)> {
+	let api = get_chain_api(state).await;
+
+	let storage_address = ternoa::storage().rent().contracts(nft_id);
+
+	let rent_contract = match api.storage().at_latest().await {
+		Ok(storage) => storage.fetch(&storage_address).await,
+		Err(err) => Err(err),
+	};
+
+	match rent_contract {
+		Ok(Some(contract)) => {
+			let duration = match contract.duration {
+				ContractDuration::Fixed(blocks) => RentDuration::FIXED(blocks),
+				ContractDuration::Subscription(period, max_duration, ..) =>
+					RentDuration::SUBSCRIPTION { period, max_duration },
+				_ => RentDuration::INFINITE,
+			};
+			contract.start_block.map(|start_block| (start_block, duration))
+		},
+		Ok(None) => None,
+		Err(err) => {
+			error!("CHAIN : Failed to fetch rent terms of nft_id {} : {err:?}", nft_id);
+			sentry::capture_error(&err);
+			record_rpc_error("get_onchain_rent_terms");
 			None
 		},
 	}
 }
 
+// -------------- SECONDARY RPC (DUAL VERIFICATION) --------------
+
+/// Hash of the latest block known by both rpc, the lower of their heads.
+/// Storage of both rpc is compared at this block, so different heads do not diverge.
+/// # Arguments
+/// * `primary` - The primary chain API
+/// * `secondary` - The secondary chain API
+pub async fn get_common_block_hash(
+	primary: &DefaultApi,
+	secondary: &DefaultApi,
+) -> Result<H256, Error> {
+	let primary_head = primary.blocks().at_latest().await?;
+	let secondary_head = secondary.blocks().at_latest().await?;
+
+	if secondary_head.number() >= primary_head.number() {
+		return Ok(primary_head.hash())
+	}
+
+	match primary.rpc().block_hash(Some(secondary_head.number().into())).await? {
+		Some(hash) => Ok(hash),
+		None => Err(Error::Other(format!(
+			"CHAIN : primary rpc has no block {} of secondary rpc head",
+			secondary_head.number()
+		))),
+	}
+}
+
+/// Get the NFT/Capsule data from a given chain API at a block, without retry
+/// # Arguments
+/// * `api` - The chain API
+/// * `block_hash` - The block of the storage
+/// * `nft_id` - The NFT/Capsule ID
+pub async fn get_nft_data_from_api(
+	api: &DefaultApi,
+	block_hash: H256,
+	nft_id: u32,
+) -> Result<Option<NFTData<AccountId32>>, Error> {
+	let storage_address = ternoa::storage().nft().nfts(nft_id);
+	api.storage().at(block_hash).fetch(&storage_address).await
+}
+
+/// Get the NFT/Capsule delegatee from a given chain API at a block, without retry
+/// # Arguments
+/// * `api` - The chain API
+/// * `block_hash` - The block of the storage
+/// * `nft_id` - The NFT/Capsule ID
+pub async fn get_delegatee_from_api(
+	api: &DefaultApi,
+	block_hash: H256,
+	nft_id: u32,
+) -> Result<Option<AccountId32>, Error> {
+	let storage_address = ternoa::storage().nft().delegated_nf_ts(nft_id);
+	api.storage().at(block_hash).fetch(&storage_address).await
+}
+
+/// Get the NFT/Capsule rentee from a given chain API at a block, without retry
+/// # Arguments
+/// * `api` - The chain API
+/// * `block_hash` - The block of the storage
+/// * `nft_id` - The NFT/Capsule ID
+pub async fn get_rentee_from_api(
+	api: &DefaultApi,
+	block_hash: H256,
+	nft_id: u32,
+) -> Result<Option<AccountId32>, Error> {
+	let storage_address = ternoa::storage().rent().contracts(nft_id);
+	Ok(api
+		.storage()
+		.at(block_hash)
+		.fetch(&storage_address)
+		.await?
+		.and_then(|contract| contract.rentee))
+}
+
 // -------------- SECRET-NFT SYNC (ORACLE) --------------
 
 // TODO [code style] : Define macro for nft/capsule
@@ -463,6 +638,168 @@ pub async fn capsule_keyshare_oracle(
 	Ok(result)
 }
 
+// -------------- SYSTEM REMARK (ANNOUNCEMENT) --------------
+
+/// Submit a remark extrinsic signed by the enclave
+/// # Arguments
+/// * `state` - The shared state
+/// * `remark` - The remark content
+/// # Returns
+/// * `Result<sp_core::H256, subxt::Error>` - The block hash
+pub async fn system_remark_oracle(
+	state: &SharedState,
+	remark: Vec<u8>,
+) -> Result<H256, subxt::Error> {
+	debug!("CHAIN : REMARK ORACLE");
+
+	let api = get_chain_api(state).await;
+
+	let tx = ternoa::tx().system().remark(remark);
+
+	let offchain_nonce = get_nonce(state).await;
+	debug!("CHAIN : Remark Oracle : nonce = {:?}", offchain_nonce);
+
+	{
+		increment_nonce(state).await;
+		debug!("CHAIN : Remark Oracle : nonce incremented for next extrinsic");
+	}
+
+	// Enclave as the Signer
+	let shared_state_read = state.read().await;
+	let signer = shared_state_read.get_signer();
+
+	let result = api
+		.tx()
+		.create_signed_with_nonce(&tx, signer, offchain_nonce, Default::default())?
+		.submit_and_watch()
+		.await?
+		.wait_for_in_block()
+		.await?
+		.block_hash();
+
+	debug!("CHAIN : Remark Oracle : extrinsic sent : {:?}", result);
+
+	Ok(result)
+}
+
+// -------------- ENCLAVE REGISTRATION --------------
+
+/// Operator of a registered enclave account, at the finalized head
+/// # Arguments
+/// * `state` - The shared state
+/// * `enclave_account` - enclave account
+/// # Returns
+/// * `Option<AccountId32>` - operator account, None if the enclave is not registered
+pub async fn get_enclave_operator(
+	state: &SharedState,
+	enclave_account: AccountId32,
+) -> Result<Option<AccountId32>, subxt::Error> {
+	let api = get_chain_api(state).await;
+	let finalized = api.rpc().finalized_head().await?;
+
+	let address = ternoa::storage().tee().enclave_account_operator(enclave_account);
+	api.storage().at(finalized).fetch(&address).await
+}
+
+/// Enclave account registered by an operator, at the finalized head
+/// # Arguments
+/// * `state` - The shared state
+/// * `operator_account` - operator account
+/// # Returns
+/// * `Option<AccountId32>` - enclave account, None if the operator has no enclave
+pub async fn get_registered_enclave(
+	state: &SharedState,
+	operator_account: AccountId32,
+) -> Result<Option<AccountId32>, subxt::Error> {
+	let api = get_chain_api(state).await;
+	let finalized = api.rpc().finalized_head().await?;
+
+	let address = ternoa::storage().tee().enclave_data(operator_account);
+	Ok(api
+		.storage()
+		.at(finalized)
+		.fetch(&address)
+		.await?
+		.map(|data| data.enclave_address))
+}
+
+/// Submit an extrinsic signed by another account, i.e. a registration update of the operator,
+/// and wait for its finalization
+/// # Arguments
+/// * `state` - The shared state
+/// * `extrinsic` - SCALE encoded signed extrinsic
+/// # Returns
+/// * `Result<sp_core::H256, subxt::Error>` - The finalized block hash
+pub async fn submit_signed_extrinsic(
+	state: &SharedState,
+	extrinsic: Vec<u8>,
+) -> Result<H256, subxt::Error> {
+	debug!("CHAIN : SIGNED EXTRINSIC");
+
+	let api = get_chain_api(state).await;
+
+	let result = SubmittableExtrinsic::from_bytes(api, extrinsic)
+		.submit_and_watch()
+		.await?
+		.wait_for_finalized_success()
+		.await?
+		.block_hash();
+
+	debug!("CHAIN : Signed Extrinsic : finalized in block : {:?}", result);
+
+	Ok(result)
+}
+
+// -------------- BALANCE TRANSFER --------------
+
+/// Transfer from the enclave account, keeping it alive
+/// # Arguments
+/// * `state` - The shared state
+/// * `destination` - receiving account
+/// * `amount` - amount in the smallest unit
+/// # Returns
+/// * `Result<sp_core::H256, subxt::Error>` - The block hash
+pub async fn transfer_oracle(
+	state: &SharedState,
+	destination: AccountId32,
+	amount: u128,
+) -> Result<H256, subxt::Error> {
+	debug!("CHAIN : TRANSFER ORACLE");
+
+	let api = get_chain_api(state).await;
+
+	let tx = ternoa::tx()
+		.balances()
+		.transfer_keep_alive(MultiAddress::Id(destination), amount);
+
+	let offchain_nonce = get_nonce(state).await;
+	debug!("CHAIN : Transfer Oracle : nonce = {:?}", offchain_nonce);
+
+	{
+		increment_nonce(state).await;
+		debug!("CHAIN : Transfer Oracle : nonce incremented for next extrinsic");
+	}
+
+	// Enclave as the Signer, the state is not locked until the finalization
+	let extrinsic = {
+		let shared_state_read = state.read().await;
+		let signer = shared_state_read.get_signer();
+		api.tx()
+			.create_signed_with_nonce(&tx, signer, offchain_nonce, Default::default())?
+	};
+
+	let result = extrinsic
+		.submit_and_watch()
+		.await?
+		.wait_for_finalized_success()
+		.await?
+		.block_hash();
+
+	debug!("CHAIN : Transfer Oracle : extrinsic finalized : {:?}", result);
+
+	Ok(result)
+}
+
 /// Get Metric Server
 /// # Arguments
 /// * `nft_id` - The NFT/Capsule ID
@@ -483,6 +820,7 @@ pub async fn get_metric_server(state: &SharedState) -> Option<Vec<MetricServer>>
 			Err(err) => {
 				error!("CHAIN : GET METRIC SERVER : Failed to get storage for metric server, retry num.{} : {:?}", retry, err);
 				sentry::capture_error(&err);
+				record_rpc_error("get_metric_server");
 				continue
 			},
 		};
@@ -507,7 +845,8 @@ pub async fn get_metric_server(state: &SharedState) -> Option<Vec<MetricServer>>
 					retry, err
 				);
 				sentry::capture_error(&err);
-				std::thread::sleep(std::time::Duration::from_secs(RETRY_DELAY));
+				record_rpc_error("get_metric_server");
+				tokio::time::sleep(std::time::Duration::from_secs(RETRY_DELAY)).await;
 			},
 		}
 	}
@@ -0,0 +1,127 @@
+use std::{collections::BTreeMap, sync::OnceLock};
+
+use serde::Serialize;
+use subxt::ext::sp_core::{crypto::Ss58Codec, sr25519};
+
+use crate::chain::verify::parse_ss58_public;
+
+/* ---------------------------------------
+	HIGH-VALUE NFT CO-SIGNATURE POLICY
+--------------------------------------- */
+
+static COSIGN_POLICY: OnceLock<CosignPolicy> = OnceLock::new();
+
+/// Designated co-signers of high-value nfts.
+/// Retrieve requests of a flagged nft must also be signed by its co-signer. An nft is flagged by
+/// its id, or by its onchain collection so that a whole collection can be protected at once.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CosignPolicy {
+	nfts: BTreeMap<u32, sr25519::Public>,
+	collections: BTreeMap<u32, sr25519::Public>,
+}
+
+/// Public view of the policy for /api/capabilities
+#[derive(Serialize, Debug, PartialEq)]
+pub struct CosignPolicyView {
+	pub nfts: BTreeMap<u32, String>,
+	pub collections: BTreeMap<u32, String>,
+}
+
+impl CosignPolicy {
+	/// Co-signer of an nft, the nft rule takes precedence over its collection
+	/// # Arguments
+	/// * `nft_id` - nft/capsule id
+	/// * `collection_id` - onchain collection of the nft, if any
+	pub fn cosigner(&self, nft_id: u32, collection_id: Option<u32>) -> Option<sr25519::Public> {
+		self.nfts
+			.get(&nft_id)
+			.or_else(|| {
+				collection_id.and_then(|collection_id| self.collections.get(&collection_id))
+			})
+			.copied()
+	}
+
+	pub fn view(&self) -> CosignPolicyView {
+		let to_ss58 = |rules: &BTreeMap<u32, sr25519::Public>| {
+			rules.iter().map(|(id, cosigner)| (*id, cosigner.to_ss58check())).collect()
+		};
+
+		CosignPolicyView { nfts: to_ss58(&self.nfts), collections: to_ss58(&self.collections) }
+	}
+}
+
+// "ID=CO-SIGNER" rules of the command line
+fn parse_rules(rules: &[String]) -> Result<BTreeMap<u32, sr25519::Public>, anyhow::Error> {
+	let mut parsed = BTreeMap::new();
+
+	for rule in rules {
+		let (id, cosigner) = rule
+			.split_once('=')
+			.ok_or_else(|| anyhow::anyhow!("CO-SIGNATURE : expected ID=CO-SIGNER : {rule}"))?;
+
+		let id = id.trim().parse::<u32>()?;
+		let cosigner = parse_ss58_public(cosigner)
+			.ok_or_else(|| anyhow::anyhow!("CO-SIGNATURE : invalid co-signer address : {rule}"))?;
+
+		if parsed.insert(id, cosigner).is_some() {
+			return Err(anyhow::anyhow!("CO-SIGNATURE : duplicated rule for id {id}"))
+		}
+	}
+
+	Ok(parsed)
+}
+
+/// Build the policy from the command line
+/// # Arguments
+/// * `nfts` - "NFTID=CO-SIGNER" rules
+/// * `collections` - "COLLECTIONID=CO-SIGNER" rules
+pub fn parse_cosign_policy(
+	nfts: &[String],
+	collections: &[String],
+) -> Result<CosignPolicy, anyhow::Error> {
+	Ok(CosignPolicy { nfts: parse_rules(nfts)?, collections: parse_rules(collections)? })
+}
+
+/// Set the co-signature policy, only once at startup
+pub fn set_cosign_policy(policy: CosignPolicy) -> Result<(), anyhow::Error> {
+	COSIGN_POLICY
+		.set(policy)
+		.map_err(|_| anyhow::anyhow!("CO-SIGNATURE : policy is already set"))
+}
+
+/// Effective co-signature policy, no nft is flagged if it is not configured
+pub fn cosign_policy() -> &'static CosignPolicy {
+	COSIGN_POLICY.get_or_init(CosignPolicy::default)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use subxt::ext::sp_core::Pair;
+
+	#[test]
+	fn cosign_policy_test() {
+		let nft_cosigner = sr25519::Pair::from_seed(&[21u8; 32]).public();
+		let collection_cosigner = sr25519::Pair::from_seed(&[22u8; 32]).public();
+
+		let policy = parse_cosign_policy(
+			&[format!("100={}", nft_cosigner.to_ss58check())],
+			&[format!(" 7 = 0x{}", hex::encode(collection_cosigner.0))],
+		)
+		.unwrap();
+
+		assert_eq!(policy.cosigner(100, None), Some(nft_cosigner));
+		// nft rule takes precedence over its collection
+		assert_eq!(policy.cosigner(100, Some(7)), Some(nft_cosigner));
+		assert_eq!(policy.cosigner(101, Some(7)), Some(collection_cosigner));
+		assert_eq!(policy.cosigner(101, Some(8)), None);
+		assert_eq!(policy.cosigner(101, None), None);
+
+		assert_eq!(policy.view().collections[&7], collection_cosigner.to_ss58check());
+
+		assert!(parse_cosign_policy(&["100".to_string()], &[]).is_err());
+		assert!(parse_cosign_policy(&["100=notAnAddress".to_string()], &[]).is_err());
+		let rule = format!("100={}", nft_cosigner.to_ss58check());
+		assert!(parse_cosign_policy(&[rule.clone(), rule], &[]).is_err());
+	}
+}
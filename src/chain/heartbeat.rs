@@ -0,0 +1,67 @@
+use tracing::{debug, error, info};
+
+use crate::{
+	backup::sync::get_sync_state,
+	chain::core::system_remark_oracle,
+	servers::state::{get_blocknumber, get_nft_availability_map_len, SharedState},
+};
+
+/* ---------------------------------------
+	KEYSHARE AVAILABILITY HEARTBEAT
+--------------------------------------- */
+
+/// Compact heartbeat remark : "I hold N keyshares up to block B"
+/// # Arguments
+/// * `keyshares` - number of keyshares held by the enclave
+/// * `synced_block` - last synchronized block number
+pub fn heartbeat_remark(keyshares: u32, synced_block: u32) -> String {
+	format!("TEE-HEARTBEAT:{keyshares}:{synced_block}")
+}
+
+/// Whether a heartbeat is due at this block
+/// # Arguments
+/// * `block_number` - current block number
+/// * `interval` - heartbeat interval in blocks, zero disables the heartbeat
+pub fn is_heartbeat_due(block_number: u32, interval: u32) -> bool {
+	interval != 0 && block_number % interval == 0
+}
+
+/// Submit the heartbeat extrinsic, so indexers can detect stale or desynced enclaves
+/// # Arguments
+/// * `state` - SharedState
+pub async fn send_heartbeat(state: &SharedState) {
+	let keyshares = get_nft_availability_map_len(state).await;
+
+	// Setup or unregistered enclaves are not synchronized to any block
+	let synced_block = match get_sync_state() {
+		Ok(sync_state) => sync_state.parse::<u32>().unwrap_or(0),
+		Err(err) => {
+			error!("HEARTBEAT : unable to read sync state : {err:?}");
+			0
+		},
+	};
+
+	let remark = heartbeat_remark(keyshares, synced_block);
+	debug!("HEARTBEAT : {} at block {}", remark, get_blocknumber(state).await);
+
+	match system_remark_oracle(state, remark.into_bytes()).await {
+		Ok(block_hash) => info!(
+			"HEARTBEAT : {} keyshares up to block {} announced in block {:?}",
+			keyshares, synced_block, block_hash
+		),
+		Err(err) => error!("HEARTBEAT : error submitting heartbeat extrinsic : {err:?}"),
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn heartbeat_due_test() {
+		assert!(!is_heartbeat_due(600, 0));
+		assert!(is_heartbeat_due(1200, 600));
+		assert!(!is_heartbeat_due(1201, 600));
+		assert_eq!(heartbeat_remark(12, 3400), "TEE-HEARTBEAT:12:3400");
+	}
+}
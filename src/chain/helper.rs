@@ -1,9 +1,19 @@
-use std::{collections::BTreeMap, path::Path};
+use std::{
+	collections::BTreeMap,
+	fs::File,
+	io::{Read, Write},
+	path::Path,
+	string::FromUtf8Error,
+};
 
 use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
 use tracing::{debug, error, warn};
+use zeroize::Zeroizing;
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+use super::compression;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
 pub enum NftType {
 	Secret,
 	Capsule,
@@ -61,6 +71,104 @@ pub fn query_keyshare_file(dir_path: String) -> Result<BTreeMap<u32, Availabilit
 	Ok(available_keys)
 }
 
+/* ---------------------------------------
+	KEYSHARE INDEX
+--------------------------------------- */
+
+// The availability index is written to the sealed directory by a clean shutdown, and removed
+// once it is loaded at startup : a crash leaves no index and the directory is scanned again.
+// The file is read at once and its fixed-width records are decoded in place, instead of listing
+// and parsing the name of every keyshare file.
+const KEYSHARE_INDEX_MAGIC: &[u8; 4] = b"KSIX";
+// nft_id (u32 le), block_number (u32 le), nft type (u8)
+const KEYSHARE_INDEX_RECORD: usize = 9;
+
+fn nft_type_code(nft_type: NftType) -> u8 {
+	match nft_type {
+		NftType::Secret => 0,
+		NftType::Capsule => 1,
+		NftType::Hybrid => 2,
+	}
+}
+
+/// Number of sealed files of an availability index, a hybrid nft has two keyshares
+pub fn keyshare_file_count(availability: &BTreeMap<u32, Availability>) -> usize {
+	availability
+		.values()
+		.map(|av| if av.nft_type == NftType::Hybrid { 2 } else { 1 })
+		.sum()
+}
+
+/// Write the availability index at once, an interrupted write leaves no index
+/// # Arguments
+/// * `path` - index file
+/// * `availability` - availability index of the state
+pub fn save_keyshare_index(
+	path: &str,
+	availability: &BTreeMap<u32, Availability>,
+) -> Result<(), anyhow::Error> {
+	let mut content = Vec::with_capacity(8 + availability.len() * KEYSHARE_INDEX_RECORD);
+	content.extend_from_slice(KEYSHARE_INDEX_MAGIC);
+	content.extend_from_slice(&u32::try_from(availability.len())?.to_le_bytes());
+	for (nft_id, av) in availability {
+		content.extend_from_slice(&nft_id.to_le_bytes());
+		content.extend_from_slice(&av.block_number.to_le_bytes());
+		content.push(nft_type_code(av.nft_type));
+	}
+
+	let staging = format!("{path}.staging");
+	let mut file = File::create(&staging)?;
+	file.write_all(&content)?;
+	file.sync_all()?;
+	std::fs::rename(&staging, path)?;
+
+	Ok(())
+}
+
+/// Load and remove the availability index of the last clean shutdown
+/// # Arguments
+/// * `path` - index file
+/// # Returns
+/// * `Option<BTreeMap<u32, Availability>>` - None if there is no valid index
+pub fn take_keyshare_index(path: &str) -> Option<BTreeMap<u32, Availability>> {
+	let content = std::fs::read(path).ok()?;
+	if let Err(err) = std::fs::remove_file(path) {
+		// A stale index must never be loaded again
+		error!("KEYSHARE INDEX : error removing {path} : {err:?}");
+		return None
+	}
+
+	if content.len() < 8 {
+		warn!("KEYSHARE INDEX : {path} is truncated");
+		return None
+	}
+
+	let (header, records) = content.split_at(8);
+	let count = u32::from_le_bytes(header[4..8].try_into().ok()?) as usize;
+	if &header[..4] != KEYSHARE_INDEX_MAGIC || records.len() != count * KEYSHARE_INDEX_RECORD {
+		warn!("KEYSHARE INDEX : {path} is not a valid index");
+		return None
+	}
+
+	let mut availability = BTreeMap::new();
+	for record in records.chunks_exact(KEYSHARE_INDEX_RECORD) {
+		let nft_id = u32::from_le_bytes(record[0..4].try_into().ok()?);
+		let block_number = u32::from_le_bytes(record[4..8].try_into().ok()?);
+		let nft_type = match record[8] {
+			0 => NftType::Secret,
+			1 => NftType::Capsule,
+			2 => NftType::Hybrid,
+			code => {
+				warn!("KEYSHARE INDEX : invalid nft type {code} of nft_id {nft_id}");
+				return None
+			},
+		};
+		availability.insert(nft_id, Availability { block_number, nft_type });
+	}
+
+	Some(availability)
+}
+
 pub fn parse_keyshare_file(path: &Path) -> Result<(u32, Availability), anyhow::Error> {
 	//let path = std::path::Path::new(&file_name);
 
@@ -170,3 +278,263 @@ pub fn _query_nftid_file(dir_path: String, nft_id: u32) -> Result<u32, anyhow::E
 
 	Ok(0)
 }
+
+/* ---------------------------------------
+	RETRIEVE HOT PATH
+--------------------------------------- */
+
+// Longest auth-token suffix : "_{u32}_{u32}"
+const AUTH_TOKEN_SUFFIX_CAPACITY: usize = 22;
+
+/// Read a sealed keyshare directly into the response buffer.
+/// The buffer is allocated once from file metadata, prefixed by "{nft_id}_" and has room for
+/// the auth-token suffix, so the keyshare bytes are never copied after reading.
+/// Compressed keyshares are transparently decompressed, the header of binary keyshares is removed
+/// in place and they are returned base64url encoded.
+/// (The availability index is resident in the state, loaded from the keyshare index at startup.)
+/// # Arguments
+/// * `file` - opened keyshare file
+/// * `nft_id` - nft/capsule id
+/// # Returns
+/// * `(Vec<u8>, bool)` - response buffer, to be completed by finish_keyshare_response, and true if
+///   the keyshare is binary
+pub fn read_keyshare_into_response(
+	file: &mut File,
+	nft_id: u32,
+) -> std::io::Result<(Vec<u8>, bool)> {
+	let prefix = format!("{nft_id}_");
+	let file_len = file.metadata().map(|m| m.len() as usize).unwrap_or(0);
+
+	let mut buffer = Vec::with_capacity(prefix.len() + file_len + AUTH_TOKEN_SUFFIX_CAPACITY);
+	buffer.extend_from_slice(prefix.as_bytes());
+	file.read_to_end(&mut buffer)?;
+
+	// The decompressed copy is wiped once it is moved into the response buffer
+	if let Some(keyshare) =
+		compression::unseal_keyshare(&buffer[prefix.len()..])?.map(Zeroizing::new)
+	{
+		buffer.truncate(prefix.len());
+		buffer.reserve(keyshare.len() + AUTH_TOKEN_SUFFIX_CAPACITY);
+		buffer.extend_from_slice(&keyshare);
+	}
+
+	let binary = buffer[prefix.len()..].starts_with(&compression::BINARY_MAGIC);
+	if binary {
+		buffer.drain(prefix.len()..prefix.len() + compression::BINARY_MAGIC.len());
+	}
+
+	Ok((buffer, binary))
+}
+
+/// Append the auth-token to the response buffer, same format as StoreKeyshareData::serialize
+/// # Arguments
+/// * `buffer` - response buffer from read_keyshare_into_response
+/// * `auth_token` - serialized auth-token
+pub fn finish_keyshare_response(
+	mut buffer: Vec<u8>,
+	auth_token: &str,
+) -> Result<String, FromUtf8Error> {
+	buffer.push(b'_');
+	buffer.extend_from_slice(auth_token.as_bytes());
+
+	String::from_utf8(buffer)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use std::{
+		alloc::{GlobalAlloc, Layout, System},
+		cell::Cell,
+		time::{Duration, Instant},
+	};
+
+	// Allocations of the current thread, the tests run in parallel threads
+	struct CountingAllocator;
+
+	thread_local! {
+		static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+	}
+
+	unsafe impl GlobalAlloc for CountingAllocator {
+		unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+			let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+			System.alloc(layout)
+		}
+
+		unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+			System.dealloc(ptr, layout)
+		}
+
+		unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+			let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+			System.realloc(ptr, layout, new_size)
+		}
+	}
+
+	#[global_allocator]
+	static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+	fn allocations<T>(run: impl FnOnce() -> T) -> (T, usize) {
+		let before = ALLOCATIONS.with(|count| count.get());
+		let result = run();
+		(result, ALLOCATIONS.with(|count| count.get()) - before)
+	}
+
+	// Legacy path : read_to_end into empty vector, then format
+	fn legacy_response(path: &Path) -> String {
+		let mut content = Vec::<u8>::new();
+		File::open(path).unwrap().read_to_end(&mut content).unwrap();
+		let keyshare_str = String::from_utf8(content).unwrap();
+		format!("{}_{}_{}", 1300, keyshare_str, "1000_15")
+	}
+
+	fn direct_response(path: &Path) -> String {
+		let (buffer, _) =
+			read_keyshare_into_response(&mut File::open(path).unwrap(), 1300).unwrap();
+		finish_keyshare_response(buffer, "1000_15").unwrap()
+	}
+
+	fn p99(mut latencies: Vec<Duration>) -> Duration {
+		latencies.sort();
+		latencies[(latencies.len() * 99 / 100).min(latencies.len() - 1)]
+	}
+
+	#[test]
+	fn keyshare_response_test() {
+		let path = std::env::temp_dir().join("keyshare_response_test.keyshare");
+		let keyshare = "thisIsMySecretDataWhichCannotContainAnyUnderScore(:-P)".repeat(50);
+		std::fs::write(&path, &keyshare).unwrap();
+
+		let mut file = File::open(&path).unwrap();
+		let (buffer, binary) = read_keyshare_into_response(&mut file, 1300).unwrap();
+		assert!(!binary);
+		let capacity = buffer.capacity();
+
+		let response = finish_keyshare_response(buffer, "4294967295_4294967295").unwrap();
+
+		// Same as legacy serialization, without reallocation
+		assert_eq!(response, format!("1300_{keyshare}_4294967295_4294967295"));
+		assert_eq!(response.capacity(), capacity);
+
+		// The auth-token is appended in place
+		let (buffer, _) =
+			read_keyshare_into_response(&mut File::open(&path).unwrap(), 1300).unwrap();
+		let (_, finish_allocations) =
+			allocations(|| finish_keyshare_response(buffer, "1000_15").unwrap());
+		assert_eq!(finish_allocations, 0);
+
+		// The legacy path grows its buffer while reading, then copies it into the response
+		let (legacy, legacy_allocations) = allocations(|| legacy_response(&path));
+		let (direct, direct_allocations) = allocations(|| direct_response(&path));
+		assert_eq!(legacy, direct);
+		assert!(
+			direct_allocations < legacy_allocations,
+			"direct = {direct_allocations}, legacy = {legacy_allocations} allocations"
+		);
+
+		let _ = std::fs::remove_file(&path);
+	}
+
+	#[test]
+	#[ignore = "benchmark, run with cargo test --release -- --ignored"]
+	fn keyshare_response_benchmark() {
+		let path = std::env::temp_dir().join("keyshare_response_benchmark.keyshare");
+		std::fs::write(&path, "thisIsMySecretDataWhichCannotContainAnyUnderScore(:-P)".repeat(50))
+			.unwrap();
+
+		// Latency of each response, under concurrent retrievals
+		let run = |respond: fn(&Path) -> String| -> Duration {
+			let threads = 8;
+			let iterations = 2000;
+			let latencies = std::thread::scope(|scope| {
+				let workers = (0..threads)
+					.map(|_| {
+						scope.spawn(|| {
+							(0..iterations)
+								.map(|_| {
+									let start = Instant::now();
+									let _ = respond(&path);
+									start.elapsed()
+								})
+								.collect::<Vec<_>>()
+						})
+					})
+					.collect::<Vec<_>>();
+				workers.into_iter().flat_map(|worker| worker.join().unwrap()).collect()
+			});
+			p99(latencies)
+		};
+
+		let legacy_p99 = run(legacy_response);
+		let direct_p99 = run(direct_response);
+		println!("Retrieve path p99 : legacy = {legacy_p99:?}, direct = {direct_p99:?}");
+		assert!(direct_p99 <= legacy_p99);
+
+		let _ = std::fs::remove_file(&path);
+	}
+
+	#[test]
+	fn keyshare_index_test() {
+		let path = std::env::temp_dir().join("keyshare_index_test.index");
+		let path = path.to_str().unwrap();
+
+		let availability = BTreeMap::from([
+			(10, Availability { block_number: 100, nft_type: NftType::Secret }),
+			(11, Availability { block_number: 110, nft_type: NftType::Capsule }),
+			(12, Availability { block_number: 120, nft_type: NftType::Hybrid }),
+		]);
+		assert_eq!(keyshare_file_count(&availability), 4);
+
+		save_keyshare_index(path, &availability).unwrap();
+		let loaded = take_keyshare_index(path).unwrap();
+		assert_eq!(loaded.len(), 3);
+		assert_eq!(loaded[&12].block_number, 120);
+		assert_eq!(loaded[&12].nft_type, NftType::Hybrid);
+
+		// The index is only loaded once, a crash leaves no index
+		assert!(take_keyshare_index(path).is_none());
+
+		// Truncated index
+		save_keyshare_index(path, &availability).unwrap();
+		let content = std::fs::read(path).unwrap();
+		std::fs::write(path, &content[..content.len() - 1]).unwrap();
+		assert!(take_keyshare_index(path).is_none());
+	}
+
+	#[test]
+	fn compressed_keyshare_response_test() {
+		let path = std::env::temp_dir().join("compressed_keyshare_response_test.keyshare");
+		let keyshare =
+			r#"{"capsule":"thisIsMySecretDataWhichCannotContainAnyUnderScore"}"#.repeat(40);
+		std::fs::write(&path, compression::seal_keyshare(keyshare.as_bytes(), 1024)).unwrap();
+
+		let (buffer, _) = read_keyshare_into_response(&mut File::open(&path).unwrap(), 7).unwrap();
+		let response = finish_keyshare_response(buffer, "1000_15").unwrap();
+
+		assert_eq!(response, format!("7_{keyshare}_1000_15"));
+
+		let _ = std::fs::remove_file(&path);
+	}
+
+	#[test]
+	fn binary_keyshare_response_test() {
+		let path = std::env::temp_dir().join("binary_keyshare_response_test.keyshare");
+		let encoded = "_-8A_w".repeat(300);
+		let mut at_rest = compression::BINARY_MAGIC.to_vec();
+		at_rest.extend_from_slice(encoded.as_bytes());
+
+		// Raw and compressed
+		for sealed in [at_rest.clone(), compression::seal_keyshare(&at_rest, 1024).into_owned()] {
+			std::fs::write(&path, &sealed).unwrap();
+
+			let (buffer, binary) =
+				read_keyshare_into_response(&mut File::open(&path).unwrap(), 7).unwrap();
+			assert!(binary);
+			let response = finish_keyshare_response(buffer, "1000_15").unwrap();
+			assert_eq!(response, format!("7_{encoded}_1000_15"));
+		}
+
+		let _ = std::fs::remove_file(&path);
+	}
+}
@@ -0,0 +1,346 @@
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	fs::OpenOptions,
+	io::Write,
+	path::Path,
+};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use subxt::ext::sp_core::{sr25519, Pair};
+use tracing::{debug, error, info, warn};
+
+use super::helper::{parse_keyshare_file, NftType};
+
+/* ---------------------------------------
+	STARTUP INTEGRITY CHECK
+--------------------------------------- */
+
+/// Result of cross-verifying the availability index, the view-log journal
+/// and the sealed keyshare files.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct IntegrityReport {
+	pub keyshare_files: u32,
+	pub log_files: u32,
+	// Older keyshare files of the same nft, shadowed by a newer block_number
+	pub stale_keyshares: Vec<String>,
+	// Keyshare files with zero length
+	pub empty_keyshares: Vec<String>,
+	// *.keyshare files that do not follow the {type}_{nftid}_{block}.keyshare format
+	pub invalid_files: Vec<String>,
+	// View-log files without any keyshare (leftover of an interrupted removal)
+	pub orphan_logs: Vec<u32>,
+	// Files removed while repairing
+	pub repaired: Vec<String>,
+}
+
+impl IntegrityReport {
+	/// Number of discrepancies found, repaired or not
+	pub fn discrepancies(&self) -> usize {
+		self.stale_keyshares.len() +
+			self.empty_keyshares.len() +
+			self.invalid_files.len() +
+			self.orphan_logs.len()
+	}
+}
+
+/// Cross-verify the sealed directory before serving the APIs
+/// # Arguments
+/// * `dir_path` - sealed directory
+/// * `repair` - remove stale/empty keyshares and orphan logs if true
+/// # Returns
+/// * `IntegrityReport` - summary of the discrepancies
+pub fn check_integrity(dir_path: &str, repair: bool) -> Result<IntegrityReport, anyhow::Error> {
+	let mut report = IntegrityReport::default();
+
+	let dir_iterator = match std::fs::read_dir(dir_path) {
+		Ok(it) => it,
+		Err(err) => {
+			let message = format!("INTEGRITY CHECK : error reading sealed directory {err:?}");
+			error!(message);
+			return Err(anyhow!(message))
+		},
+	};
+
+	// (nftid, is_capsule) -> list of (block_number, file path)
+	let mut keyshares = BTreeMap::<(u32, bool), Vec<(u32, String)>>::new();
+	let mut logs = BTreeSet::<u32>::new();
+
+	for direntry in dir_iterator {
+		let entry = match direntry {
+			Ok(entry) => entry,
+			Err(err) => {
+				error!("INTEGRITY CHECK : error reading directory entry {err:?}");
+				continue
+			},
+		};
+
+		let path = entry.path();
+		let path_str = path.to_string_lossy().to_string();
+
+		match path.extension().and_then(std::ffi::OsStr::to_str) {
+			Some("keyshare") => match parse_keyshare_file(&path) {
+				Ok((nftid, av)) => {
+					report.keyshare_files += 1;
+
+					if entry.metadata().map(|m| m.len() == 0).unwrap_or(false) {
+						report.empty_keyshares.push(path_str);
+						continue
+					}
+
+					keyshares
+						.entry((nftid, av.nft_type == NftType::Capsule))
+						.or_default()
+						.push((av.block_number, path_str));
+				},
+				Err(err) => {
+					warn!("INTEGRITY CHECK : {err:?}");
+					report.invalid_files.push(path_str);
+				},
+			},
+
+			Some("log") => {
+				// Only view-log files are named by nftid
+				if let Some(Ok(nftid)) =
+					path.file_stem().and_then(std::ffi::OsStr::to_str).map(|s| s.parse::<u32>())
+				{
+					report.log_files += 1;
+					logs.insert(nftid);
+				}
+			},
+
+			_ => continue,
+		}
+	}
+
+	// Index entry resolves to the newest block, older files are shadowed
+	for files in keyshares.values_mut() {
+		files.sort();
+		files.pop();
+		report.stale_keyshares.extend(files.drain(..).map(|(_, path)| path));
+	}
+
+	let indexed: BTreeSet<u32> = keyshares.keys().map(|(nftid, _)| *nftid).collect();
+	report.orphan_logs = logs.difference(&indexed).copied().collect();
+
+	if repair {
+		let mut targets = report.stale_keyshares.clone();
+		targets.extend(report.empty_keyshares.clone());
+		targets.extend(report.orphan_logs.iter().map(|nftid| format!("{dir_path}/{nftid}.log")));
+
+		for file_path in targets {
+			match std::fs::remove_file(&file_path) {
+				Ok(_) => {
+					debug!("INTEGRITY CHECK : REPAIR : removed {}", file_path);
+					report.repaired.push(file_path);
+				},
+				Err(err) => {
+					error!("INTEGRITY CHECK : REPAIR : error removing {} : {err:?}", file_path)
+				},
+			}
+		}
+	}
+
+	Ok(report)
+}
+
+// prev_hash of the first entry
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Append the repair actions to the integrity log file in sealed directory.
+/// Entries are chained by the hash of the previous line and signed by the audit-log subkey.
+/// # Arguments
+/// * `log_path` - path of the integrity log file
+/// * `block_number` - current block number
+/// * `report` - integrity report
+/// * `audit_key` - audit-log subkey of the enclave
+pub fn write_repair_log(
+	log_path: &str,
+	block_number: u32,
+	report: &IntegrityReport,
+	audit_key: &sr25519::Pair,
+) -> Result<(), anyhow::Error> {
+	if report.discrepancies() == 0 {
+		return Ok(())
+	}
+
+	let prev_hash = match std::fs::read_to_string(log_path) {
+		Ok(content) => match content.lines().last() {
+			Some(line) => sha256::digest(line),
+			None => GENESIS_HASH.to_string(),
+		},
+		Err(_) => GENESIS_HASH.to_string(),
+	};
+
+	let current_date: chrono::DateTime<chrono::offset::Utc> = std::time::SystemTime::now().into();
+	let mut entry = serde_json::json!({
+		"date": current_date.format("%Y-%m-%d %H:%M:%S").to_string(),
+		"block": block_number,
+		"report": report,
+		"prev_hash": prev_hash,
+	});
+
+	let signature = audit_key.sign(entry.to_string().as_bytes());
+	entry["signature"] = serde_json::Value::String(format!("0x{}", hex::encode(signature.0)));
+
+	let mut log_file = OpenOptions::new().create(true).append(true).open(Path::new(log_path))?;
+	log_file.write_all(format!("{entry}\n").as_bytes())?;
+
+	info!("INTEGRITY CHECK : repair log is updated : {}", log_path);
+
+	Ok(())
+}
+
+/// Verify hash chain and signatures of the integrity log file
+/// # Arguments
+/// * `log_path` - path of the integrity log file
+/// * `audit_public` - public audit-log subkey of the enclave
+/// # Returns
+/// * `usize` - number of verified entries
+pub fn verify_repair_log(
+	log_path: &str,
+	audit_public: &sr25519::Public,
+) -> Result<usize, anyhow::Error> {
+	let content = std::fs::read_to_string(log_path)?;
+	let mut prev_hash = GENESIS_HASH.to_string();
+
+	for (index, line) in content.lines().enumerate() {
+		let mut entry: serde_json::Value = serde_json::from_str(line)?;
+
+		let signature = match entry.as_object_mut().and_then(|entry| entry.remove("signature")) {
+			Some(serde_json::Value::String(signature)) => signature,
+			_ => return Err(anyhow!("INTEGRITY LOG : entry {index} is not signed")),
+		};
+
+		if entry["prev_hash"] != prev_hash.as_str() {
+			return Err(anyhow!("INTEGRITY LOG : entry {index} breaks the hash chain"))
+		}
+
+		let signature = hex::decode(signature.trim_start_matches("0x"))
+			.ok()
+			.and_then(|bytes| sr25519::Signature::from_slice(&bytes))
+			.ok_or(anyhow!("INTEGRITY LOG : entry {index} has a malformed signature"))?;
+
+		if !sr25519::Pair::verify(&signature, entry.to_string().as_bytes(), audit_public) {
+			return Err(anyhow!("INTEGRITY LOG : entry {index} has an invalid signature"))
+		}
+
+		prev_hash = sha256::digest(line);
+	}
+
+	Ok(content.lines().count())
+}
+
+/// Sign the integrity log again with a new audit-log subkey, after a rotation of the enclave
+/// account. The log must be valid for the previous subkey, the hash chain is rebuilt.
+/// # Arguments
+/// * `log_path` - path of the integrity log file
+/// * `old_public` - public audit-log subkey of the previous account
+/// * `new_key` - audit-log subkey of the new account
+/// # Returns
+/// * `usize` - number of signed entries, 0 if there is no log
+pub fn resign_repair_log(
+	log_path: &str,
+	old_public: &sr25519::Public,
+	new_key: &sr25519::Pair,
+) -> Result<usize, anyhow::Error> {
+	if !Path::new(log_path).exists() {
+		return Ok(0)
+	}
+
+	verify_repair_log(log_path, old_public)?;
+
+	let content = std::fs::read_to_string(log_path)?;
+	let mut prev_hash = GENESIS_HASH.to_string();
+	let mut signed = String::new();
+
+	for line in content.lines() {
+		let mut entry: serde_json::Value = serde_json::from_str(line)?;
+		if let Some(entry) = entry.as_object_mut() {
+			entry.remove("signature");
+			entry.insert("prev_hash".to_string(), serde_json::Value::String(prev_hash));
+		}
+
+		let signature = new_key.sign(entry.to_string().as_bytes());
+		entry["signature"] = serde_json::Value::String(format!("0x{}", hex::encode(signature.0)));
+
+		let line = entry.to_string();
+		prev_hash = sha256::digest(line.as_str());
+		signed.push_str(&line);
+		signed.push('\n');
+	}
+
+	// Replaced at once, an interrupted rotation leaves the previous log
+	let staging = format!("{log_path}.rotation");
+	std::fs::write(&staging, signed)?;
+	std::fs::rename(&staging, log_path)?;
+
+	Ok(content.lines().count())
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn integrity_check_test() {
+		let dir = std::env::temp_dir().join("integrity_check_test");
+		let _ = std::fs::remove_dir_all(&dir);
+		std::fs::create_dir_all(&dir).unwrap();
+		let dir_path = dir.to_str().unwrap();
+
+		std::fs::write(format!("{dir_path}/nft_10_100.keyshare"), "old").unwrap();
+		std::fs::write(format!("{dir_path}/nft_10_200.keyshare"), "new").unwrap();
+		std::fs::write(format!("{dir_path}/capsule_11_0.keyshare"), "").unwrap();
+		std::fs::write(format!("{dir_path}/nft_x_1.keyshare"), "bad").unwrap();
+		std::fs::write(format!("{dir_path}/10.log"), "{}").unwrap();
+		// Log of the empty keyshare, and log without any keyshare
+		std::fs::write(format!("{dir_path}/11.log"), "{}").unwrap();
+		std::fs::write(format!("{dir_path}/12.log"), "{}").unwrap();
+
+		let report = check_integrity(dir_path, false).unwrap();
+		assert_eq!(report.stale_keyshares, vec![format!("{dir_path}/nft_10_100.keyshare")]);
+		assert_eq!(report.empty_keyshares.len(), 1);
+		assert_eq!(report.invalid_files.len(), 1);
+		assert_eq!(report.orphan_logs, vec![11, 12]);
+		assert!(report.repaired.is_empty());
+
+		let report = check_integrity(dir_path, true).unwrap();
+		assert_eq!(report.repaired.len(), 4);
+		assert!(!Path::new(&format!("{dir_path}/nft_10_100.keyshare")).exists());
+		assert!(!Path::new(&format!("{dir_path}/capsule_11_0.keyshare")).exists());
+		assert!(!Path::new(&format!("{dir_path}/11.log")).exists());
+		assert!(!Path::new(&format!("{dir_path}/12.log")).exists());
+		assert!(Path::new(&format!("{dir_path}/nft_10_200.keyshare")).exists());
+		assert!(Path::new(&format!("{dir_path}/10.log")).exists());
+
+		// Only the invalid file is left, it is never removed
+		let report = check_integrity(dir_path, false).unwrap();
+		assert_eq!(report.discrepancies(), 1);
+		assert_eq!(report.invalid_files.len(), 1);
+
+		// chained and signed repair log
+		let audit_key = sr25519::Pair::from_seed(&[3u8; 32]);
+		let log_path = format!("{dir_path}/integrity.log");
+		write_repair_log(&log_path, 100, &report, &audit_key).unwrap();
+		write_repair_log(&log_path, 200, &report, &audit_key).unwrap();
+		assert_eq!(verify_repair_log(&log_path, &audit_key.public()).unwrap(), 2);
+
+		let other_key = sr25519::Pair::from_seed(&[4u8; 32]);
+		assert!(verify_repair_log(&log_path, &other_key.public()).is_err());
+
+		// account rotation
+		assert!(resign_repair_log(&log_path, &other_key.public(), &other_key).is_err());
+		assert_eq!(resign_repair_log(&log_path, &audit_key.public(), &other_key).unwrap(), 2);
+		assert_eq!(verify_repair_log(&log_path, &other_key.public()).unwrap(), 2);
+		assert!(verify_repair_log(&log_path, &audit_key.public()).is_err());
+		let audit_key = other_key;
+
+		let content = std::fs::read_to_string(&log_path).unwrap();
+		let first_line = content.lines().next().unwrap();
+		std::fs::write(&log_path, content.replacen(first_line, "", 1).trim_start()).unwrap();
+		assert!(verify_repair_log(&log_path, &audit_key.public()).is_err());
+
+		let _ = std::fs::remove_dir_all(&dir);
+	}
+}
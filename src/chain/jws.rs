@@ -0,0 +1,168 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use subxt::ext::sp_core::{sr25519, Pair};
+use zeroize::Zeroize;
+
+use crate::chain::{
+	signature::{verify_raw_signature, SignatureScheme},
+	verify::VerificationError,
+};
+
+/* ---------------------------------------
+	JWS CANONICAL REQUEST FORMAT
+--------------------------------------- */
+
+// Version of the request "data" field
+pub const REQUEST_VERSION_LEGACY: u8 = 1; // "NFTID_secret_block_expiry"
+pub const REQUEST_VERSION_JWS: u8 = 2; // JWS compact serialization of a json payload
+pub const REQUEST_VERSION_BINARY: u8 = 3; // Legacy format with base64url keyshare
+
+pub const JWS_ALGORITHM: &str = "SR25519";
+
+/// Signature scheme of a JWS "alg" header
+fn jws_scheme(alg: &str) -> Option<SignatureScheme> {
+	match alg {
+		"SR25519" => Some(SignatureScheme::SR25519),
+		"ED25519" => Some(SignatureScheme::ED25519),
+		"ECDSA" => Some(SignatureScheme::ECDSA),
+		_ => None,
+	}
+}
+
+/// Protected header of the token
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JwsHeader {
+	pub alg: String,
+	pub typ: String,
+}
+
+/// Payload of a store request
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct StoreJwsPayload {
+	pub nft_id: u32,
+	pub keyshare: String,
+	pub block_number: u32,
+	pub block_validation: u32,
+}
+
+impl Drop for StoreJwsPayload {
+	fn drop(&mut self) {
+		self.keyshare.zeroize();
+	}
+}
+
+/// Payload of a retrieve request
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RetrieveJwsPayload {
+	pub nft_id: u32,
+	pub block_number: u32,
+	pub block_validation: u32,
+}
+
+/// Decoded JWS token, signature is not verified yet
+#[derive(Debug, Clone)]
+pub struct JwsToken<P> {
+	pub payload: P,
+	pub signing_input: String,
+	pub scheme: SignatureScheme,
+	pub signature: Vec<u8>,
+}
+
+impl<P> JwsToken<P> {
+	/// Verify the token signature, signing input is "base64url(header).base64url(payload)"
+	pub fn verify(&self, account: &[u8; 32]) -> Result<bool, VerificationError> {
+		verify_raw_signature(
+			Some(self.scheme),
+			&self.signature,
+			self.signing_input.as_bytes(),
+			account,
+		)
+		.map_err(VerificationError::INVALIDDATASIG)
+	}
+}
+
+/// Decode a JWS compact token
+/// # Arguments
+/// * `token` - "header.payload.signature", base64url without padding
+/// # Returns
+/// * `JwsToken<P>` - parsed payload and signature
+pub fn decode_jws<P: DeserializeOwned>(token: &str) -> Result<JwsToken<P>, VerificationError> {
+	let segments: Vec<&str> = token.trim().split('.').collect();
+	if segments.len() != 3 {
+		return Err(VerificationError::MALFORMATEDDATA)
+	}
+
+	let header: JwsHeader = decode_segment(segments[0])?;
+	let scheme = jws_scheme(&header.alg).ok_or(VerificationError::MALFORMATEDDATA)?;
+
+	let payload: P = decode_segment(segments[1])?;
+
+	let signature = URL_SAFE_NO_PAD
+		.decode(segments[2])
+		.map_err(|_| VerificationError::MALFORMATEDDATA)?;
+
+	Ok(JwsToken {
+		payload,
+		signing_input: format!("{}.{}", segments[0], segments[1]),
+		scheme,
+		signature,
+	})
+}
+
+fn decode_segment<T: DeserializeOwned>(segment: &str) -> Result<T, VerificationError> {
+	let bytes = URL_SAFE_NO_PAD
+		.decode(segment)
+		.map_err(|_| VerificationError::MALFORMATEDDATA)?;
+	serde_json::from_slice(&bytes).map_err(|_| VerificationError::MALFORMATEDDATA)
+}
+
+/// Create a JWS compact token, used by clients and tests
+pub fn encode_jws<P: Serialize>(payload: &P, pair: &sr25519::Pair) -> String {
+	let header = JwsHeader { alg: JWS_ALGORITHM.to_string(), typ: "JWT".to_string() };
+
+	let signing_input = format!(
+		"{}.{}",
+		URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header).unwrap_or_default()),
+		URL_SAFE_NO_PAD.encode(serde_json::to_vec(payload).unwrap_or_default())
+	);
+	let signature = pair.sign(signing_input.as_bytes());
+
+	format!("{}.{}", signing_input, URL_SAFE_NO_PAD.encode(signature.0))
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn jws_roundtrip_test() {
+		let pair = sr25519::Pair::from_seed(&[1u8; 32]);
+		let other = sr25519::Pair::from_seed(&[2u8; 32]);
+
+		let payload = RetrieveJwsPayload { nft_id: 163, block_number: 1000, block_validation: 10 };
+		let token = encode_jws(&payload, &pair);
+
+		let decoded: JwsToken<RetrieveJwsPayload> = decode_jws(&token).unwrap();
+		assert_eq!(decoded.payload, payload);
+		assert_eq!(decoded.verify(&pair.public().0), Ok(true));
+		assert_eq!(decoded.verify(&other.public().0), Ok(false));
+
+		// Tampered payload
+		let forged = encode_jws(
+			&RetrieveJwsPayload { nft_id: 164, block_number: 1000, block_validation: 10 },
+			&other,
+		);
+		let segments: Vec<&str> = token.split('.').collect();
+		let forged_segments: Vec<&str> = forged.split('.').collect();
+		let tampered = format!("{}.{}.{}", segments[0], forged_segments[1], segments[2]);
+		assert_eq!(
+			decode_jws::<RetrieveJwsPayload>(&tampered).unwrap().verify(&pair.public().0),
+			Ok(false)
+		);
+
+		assert!(decode_jws::<StoreJwsPayload>(&token).is_err());
+		assert!(decode_jws::<RetrieveJwsPayload>("163_1000_10").is_err());
+	}
+}
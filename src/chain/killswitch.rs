@@ -0,0 +1,385 @@
+use std::{collections::BTreeMap, sync::Mutex};
+
+use axum::{
+	extract::State,
+	http::{Method, Request, StatusCode},
+	middleware::Next,
+	response::{IntoResponse, Response},
+	Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use subxt::{blocks::Block, utils::AccountId32, OnlineClient, PolkadotConfig};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+	backup::quorum::current_quorum,
+	chain::{
+		constants::{KILLSWITCH_FILE, KILLSWITCH_VOTE_PERIOD},
+		core::ternoa,
+		verify::normalize_address,
+	},
+	servers::{
+		state::{get_maintenance_mode, set_maintenance_mode, SharedState},
+		versioning::endpoint_path,
+	},
+};
+
+/* ---------------------------------------
+	GOVERNANCE KILL-SWITCH
+--------------------------------------- */
+
+pub const KILLSWITCH_REMARK_PREFIX: &str = "TEE-KILLSWITCH:";
+
+// A mode is applied once the threshold of distinct quorum members remarked it within the vote
+// period, the latest remark of a member replaces its previous vote. One compromised member can not
+// switch the API of the enclaves.
+static KILLSWITCH_VOTES: Mutex<BTreeMap<String, (MaintenanceMode, u32)>> =
+	Mutex::new(BTreeMap::new());
+
+/// API availability set by governance
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum MaintenanceMode {
+	#[default]
+	NORMAL,
+	// Retrieve and status APIs only
+	READONLY,
+	// Health and attestation only
+	FULL,
+}
+
+/// Parse a kill-switch remark : "TEE-KILLSWITCH:{NORMAL|READONLY|FULL}"
+pub fn parse_killswitch_remark(remark: &[u8]) -> Option<MaintenanceMode> {
+	let remark = std::str::from_utf8(remark).ok()?;
+
+	match remark.trim().strip_prefix(KILLSWITCH_REMARK_PREFIX)? {
+		"NORMAL" => Some(MaintenanceMode::NORMAL),
+		"READONLY" => Some(MaintenanceMode::READONLY),
+		"FULL" => Some(MaintenanceMode::FULL),
+		_ => None,
+	}
+}
+
+// Endpoints which are served with a body but do not write keyshares, accounts or the enclave
+// configuration. A new POST endpoint is a write until it is listed here.
+const READ_POST_ENDPOINTS: [&str; 19] = [
+	"/api/secret-nft/retrieve-keyshare",
+	"/api/secret-nft/batch-retrieve-keyshare",
+	"/api/capsule-nft/retrieve-keyshare",
+	"/api/attest",
+	"/api/attest/inspect",
+	"/api/access-check",
+	"/api/backup/fetch-id",
+	"/api/backup/fetch-bulk",
+	"/api/backup/download-token",
+	"/api/backup/recovery-key",
+	"/api/backup/sync-inventory",
+	"/api/backup/sync-root",
+	"/api/backup/provision-report",
+	"/api/backup/compare-peer",
+	"/api/backup/consistency-check",
+	"/api/backup/audit-log",
+	"/api/metric/interval-nft-list",
+	// The operator switches stay available to leave the maintenance
+	"/api/backup/read-only",
+	"/api/backup/maintenance",
+];
+
+/// Whether the API endpoint is served in the given mode
+/// # Arguments
+/// * `mode` - current maintenance mode
+/// * `method` - method of the request
+/// * `path` - unversioned uri path of the request
+pub fn is_endpoint_allowed(mode: MaintenanceMode, method: &Method, path: &str) -> bool {
+	match mode {
+		MaintenanceMode::NORMAL => true,
+
+		// Reads only : every GET, and the listed POST endpoints
+		MaintenanceMode::READONLY =>
+			method == Method::GET || method == Method::HEAD || READ_POST_ENDPOINTS.contains(&path),
+
+		MaintenanceMode::FULL => matches!(
+			path,
+			"/api/health" |
+				"/api/live" | "/api/ready" |
+				"/api/quote" | "/api/identity" |
+				"/api/attest" |
+				"/api/attest/history" |
+				"/api/attest/inspect" |
+				"/api/attest/self-test"
+		),
+	}
+}
+
+/// Extract the signer account of a signed extrinsic, MultiAddress::Id only
+fn extrinsic_signer(address_bytes: Option<&[u8]>) -> Option<AccountId32> {
+	match address_bytes {
+		Some(bytes) if bytes.len() == 33 && bytes[0] == 0 => {
+			let mut account = [0u8; 32];
+			account.copy_from_slice(&bytes[1..]);
+			Some(AccountId32(account))
+		},
+		_ => None,
+	}
+}
+
+/// Remarks of the signed System extrinsics of a finalized block, with their signer
+/// # Arguments
+/// * `block` - finalized block
+pub async fn system_remarks(
+	block: &Block<PolkadotConfig, OnlineClient<PolkadotConfig>>,
+) -> Result<Vec<(Option<AccountId32>, Vec<u8>)>, anyhow::Error> {
+	let body = block.body().await?;
+	let mut remarks = Vec::new();
+
+	for ext in body.extrinsics().iter() {
+		let ext = ext?;
+
+		if !ext.pallet_name()?.eq_ignore_ascii_case("System") {
+			continue
+		}
+
+		let remark =
+			if let Some(call) = ext.as_extrinsic::<ternoa::system::calls::types::Remark>()? {
+				call.remark
+			} else if let Some(call) =
+				ext.as_extrinsic::<ternoa::system::calls::types::RemarkWithEvent>()?
+			{
+				call.remark
+			} else {
+				continue
+			};
+
+		remarks.push((extrinsic_signer(ext.address_bytes()), remark));
+	}
+
+	Ok(remarks)
+}
+
+/// Whether the signer of a remark is a member of the admin quorum
+/// # Arguments
+/// * `state` - SharedState
+/// * `signer` - signer of the extrinsic
+pub async fn is_governance_signer(state: &SharedState, signer: &Option<AccountId32>) -> bool {
+	let quorum = current_quorum(state).await;
+	signer.is_some() && quorum.members.iter().any(|m| normalize_address(m) == *signer)
+}
+
+/// Record the vote of a quorum member
+/// # Arguments
+/// * `votes` - latest vote of each member, with its block
+/// * `members` - current quorum members, the votes of former members are dropped
+/// * `signer` - voting member
+/// * `mode` - voted mode
+/// * `block_number` - block of the remark
+/// * `threshold` - votes of distinct members to apply a mode
+/// # Returns
+/// * `Option<MaintenanceMode>` - mode which reached the threshold, the votes are then cleared
+pub fn record_killswitch_vote(
+	votes: &mut BTreeMap<String, (MaintenanceMode, u32)>,
+	members: &[String],
+	signer: String,
+	mode: MaintenanceMode,
+	block_number: u32,
+	threshold: u8,
+) -> Option<MaintenanceMode> {
+	votes.insert(signer, (mode, block_number));
+	votes.retain(|member, (_, voted_in)| {
+		members.contains(member) && block_number.saturating_sub(*voted_in) <= KILLSWITCH_VOTE_PERIOD
+	});
+
+	let approvals = votes.values().filter(|(voted, _)| *voted == mode).count();
+	if approvals < usize::from(threshold.max(1)) {
+		return None
+	}
+
+	votes.clear();
+	Some(mode)
+}
+
+/// Look for kill-switch remarks of the admin quorum members in a finalized block
+/// # Arguments
+/// * `state` - SharedState
+/// * `block` - finalized block
+pub async fn watch_block(
+	state: &SharedState,
+	block: &Block<PolkadotConfig, OnlineClient<PolkadotConfig>>,
+) -> Result<(), anyhow::Error> {
+	let block_number = block.header().number;
+	let quorum = current_quorum(state).await;
+	let members: Vec<String> = quorum
+		.members
+		.iter()
+		.filter_map(|member| normalize_address(member))
+		.map(|member| member.to_string())
+		.collect();
+
+	let mut new_mode = None;
+
+	for (signer, remark) in system_remarks(block).await? {
+		let mode = match parse_killswitch_remark(&remark) {
+			Some(mode) => mode,
+			None => continue,
+		};
+
+		let signer = match signer.map(|signer| signer.to_string()) {
+			Some(signer) if members.contains(&signer) => signer,
+			signer => {
+				warn!(
+					"KILLSWITCH : ignored remark {:?} from non-governance account {:?}",
+					mode, signer
+				);
+				continue
+			},
+		};
+
+		let mut votes = KILLSWITCH_VOTES
+			.lock()
+			.map_err(|err| anyhow::anyhow!("KILLSWITCH : lock error : {err:?}"))?;
+		info!("KILLSWITCH : {signer} votes for the {:?} mode at block {block_number}", mode);
+		if let Some(mode) = record_killswitch_vote(
+			&mut votes,
+			&members,
+			signer,
+			mode,
+			block_number,
+			quorum.threshold,
+		) {
+			new_mode = Some(mode);
+		}
+	}
+
+	if let Some(mode) = new_mode {
+		apply_maintenance_mode(state, mode).await;
+	}
+
+	Ok(())
+}
+
+/// Switch the API mode and persist it, so a restart does not lift the kill-switch
+pub async fn apply_maintenance_mode(state: &SharedState, mode: MaintenanceMode) {
+	if get_maintenance_mode(state).await == mode {
+		return
+	}
+
+	info!("KILLSWITCH : governance set the enclave API mode to {:?}", mode);
+	set_maintenance_mode(state, mode).await;
+
+	match serde_json::to_string(&mode) {
+		Ok(content) =>
+			if let Err(err) = std::fs::write(KILLSWITCH_FILE, content) {
+				error!("KILLSWITCH : error persisting the mode : {err:?}");
+			},
+		Err(err) => error!("KILLSWITCH : error serializing the mode : {err:?}"),
+	}
+}
+
+/// Load the persisted kill-switch mode at startup
+pub async fn load_maintenance_mode(state: &SharedState) -> Result<(), anyhow::Error> {
+	if !std::path::Path::new(KILLSWITCH_FILE).exists() {
+		return Ok(())
+	}
+
+	let mode: MaintenanceMode = serde_json::from_str(&std::fs::read_to_string(KILLSWITCH_FILE)?)?;
+	if mode != MaintenanceMode::NORMAL {
+		warn!("KILLSWITCH : enclave API starts in {:?} mode", mode);
+	}
+
+	set_maintenance_mode(state, mode).await;
+
+	Ok(())
+}
+
+/// Middleware rejecting the endpoints which are disabled by governance
+pub async fn killswitch_guard<B>(
+	State(state): State<SharedState>,
+	request: Request<B>,
+	next: Next<B>,
+) -> Response {
+	let mode = get_maintenance_mode(&state).await;
+	let path = endpoint_path(&request);
+
+	if is_endpoint_allowed(mode, request.method(), &path) {
+		return next.run(request).await
+	}
+
+	debug!("KILLSWITCH : rejected {} in {:?} mode", path, mode);
+
+	(
+		StatusCode::SERVICE_UNAVAILABLE,
+		Json(json!({
+			"description": format!("Enclave API is in governance {mode:?} mode, try later."),
+		})),
+	)
+		.into_response()
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn killswitch_test() {
+		assert_eq!(parse_killswitch_remark(b"TEE-KILLSWITCH:FULL"), Some(MaintenanceMode::FULL));
+		assert_eq!(
+			parse_killswitch_remark(b"TEE-KILLSWITCH:READONLY"),
+			Some(MaintenanceMode::READONLY)
+		);
+		assert_eq!(parse_killswitch_remark(b"TEE-HEARTBEAT:10:20"), None);
+		assert_eq!(parse_killswitch_remark(b"TEE-KILLSWITCH:OFF"), None);
+
+		let store = "/api/secret-nft/store-keyshare";
+		let retrieve = "/api/secret-nft/retrieve-keyshare";
+		let post = Method::POST;
+
+		assert!(is_endpoint_allowed(MaintenanceMode::NORMAL, &post, store));
+		assert!(!is_endpoint_allowed(MaintenanceMode::READONLY, &post, store));
+		assert!(is_endpoint_allowed(MaintenanceMode::READONLY, &post, retrieve));
+		assert!(!is_endpoint_allowed(MaintenanceMode::FULL, &post, retrieve));
+		assert!(is_endpoint_allowed(MaintenanceMode::FULL, &Method::GET, "/api/health"));
+		assert!(is_endpoint_allowed(MaintenanceMode::FULL, &Method::GET, "/api/live"));
+
+		// Writes which are not listed are refused, GET endpoints are served
+		for write in [
+			"/api/backup/push-keyshares",
+			"/api/backup/push-bulk",
+			"/api/backup/upload",
+			"/api/secret-nft/batch-store-keyshare",
+			"/api/admin/config",
+		] {
+			assert!(!is_endpoint_allowed(MaintenanceMode::READONLY, &post, write));
+		}
+		assert!(!is_endpoint_allowed(
+			MaintenanceMode::READONLY,
+			&Method::PUT,
+			"/api/backup/upload/:upload_id/part/:index"
+		));
+		assert!(is_endpoint_allowed(MaintenanceMode::READONLY, &Method::GET, "/api/backup/quorum"));
+
+		// Threshold of distinct current members within the vote period
+		let members = vec!["alice".to_string(), "bob".to_string(), "carol".to_string()];
+		let mut votes = BTreeMap::new();
+		let vote = |votes: &mut BTreeMap<_, _>, signer: &str, mode, block_number| {
+			record_killswitch_vote(votes, &members, signer.to_string(), mode, block_number, 2)
+		};
+		assert_eq!(vote(&mut votes, "alice", MaintenanceMode::FULL, 10), None);
+		assert_eq!(vote(&mut votes, "alice", MaintenanceMode::FULL, 11), None);
+		assert_eq!(vote(&mut votes, "mallory", MaintenanceMode::FULL, 12), None);
+		assert_eq!(vote(&mut votes, "bob", MaintenanceMode::READONLY, 13), None);
+		assert_eq!(
+			vote(&mut votes, "carol", MaintenanceMode::FULL, 14),
+			Some(MaintenanceMode::FULL)
+		);
+		assert!(votes.is_empty());
+		assert_eq!(vote(&mut votes, "alice", MaintenanceMode::NORMAL, 20), None);
+		assert_eq!(
+			vote(&mut votes, "bob", MaintenanceMode::NORMAL, 21 + KILLSWITCH_VOTE_PERIOD),
+			None
+		);
+
+		let mut address = vec![0u8];
+		address.extend_from_slice(&[7u8; 32]);
+		assert_eq!(extrinsic_signer(Some(&address)), Some(AccountId32([7u8; 32])));
+		assert_eq!(extrinsic_signer(None), None);
+	}
+}
@@ -1,7 +1,27 @@
+pub mod audit;
 pub mod capsule;
+pub mod commitment;
+pub mod compression;
 pub mod constants;
 pub mod core;
+pub mod cosign;
+pub mod heartbeat;
 pub mod helper;
+pub mod integrity;
+pub mod jws;
+pub mod killswitch;
 pub mod log;
+pub mod negative_cache;
 pub mod nft;
+pub mod parser;
+pub mod policy;
+pub mod precheck;
+pub mod quota;
+pub mod reader;
+pub mod rental;
+pub mod replay;
+pub mod requester;
+pub mod scanner;
+pub mod signature;
+pub mod simulate;
 pub mod verify;
@@ -0,0 +1,377 @@
+use std::{collections::BTreeMap, sync::Mutex};
+
+use anyhow::anyhow;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use subxt::{blocks::Block, OnlineClient, PolkadotConfig};
+use tracing::{debug, error, warn};
+
+use crate::{
+	backup::provision::is_provisioned_nft,
+	chain::{
+		constants::{MAX_NEGATIVE_CACHE_ENTRIES, NEGATIVE_CACHE_TTL},
+		core::{
+			ternoa::nft::events::{CapsuleConverted, NFTCreated, SecretAddedToNFT},
+			DefaultApi,
+		},
+		reader::OnchainNft,
+		verify::VerificationError,
+	},
+	servers::state::{get_blocknumber, get_secondary_chain_api, SharedState},
+};
+
+/* ---------------------------------------
+	NEGATIVE ONCHAIN LOOKUP CACHE
+--------------------------------------- */
+
+// The cache has its own lock, lookups do not wait for the state lock. Lookups query the best
+// block, so the verdicts are invalidated by the creation events of the best blocks, the finalized
+// blocks only catch up the events of a reorganization.
+static NEGATIVE_CACHE: Mutex<NegativeCache> = Mutex::new(NegativeCache::new());
+
+/// Onchain lookup result which can not become valid without a creation event
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum NegativeVerdict {
+	// Nft id does not exist
+	NOTFOUND,
+	// Nft exists but it is neither secret nor capsule
+	PLAIN(OnchainNft),
+}
+
+impl NegativeVerdict {
+	/// Verdict of a lookup result, None if the result must not be cached
+	pub fn from_result(result: &Result<OnchainNft, VerificationError>) -> Option<NegativeVerdict> {
+		match result {
+			Err(VerificationError::INVALIDNFTID) => Some(NegativeVerdict::NOTFOUND),
+			Ok(nft) if !nft.is_secret && !nft.is_capsule =>
+				Some(NegativeVerdict::PLAIN(nft.clone())),
+			_ => None,
+		}
+	}
+
+	pub fn to_result(&self) -> Result<OnchainNft, VerificationError> {
+		match self {
+			NegativeVerdict::NOTFOUND => Err(VerificationError::INVALIDNFTID),
+			NegativeVerdict::PLAIN(nft) => Ok(nft.clone()),
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct NegativeCacheStats {
+	pub entries: usize,
+	pub hits: u64,
+	pub misses: u64,
+	pub saved_rpc_calls: u64,
+	pub insertions: u64,
+	pub invalidations: u64,
+}
+
+/// Short-lived negative verdicts of nft lookups, keyed by nft id.
+/// Entries expire after NEGATIVE_CACHE_TTL blocks or on a creation event of the nft.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct NegativeCache {
+	// nft id -> (verdict, last block number that the verdict is valid)
+	entries: BTreeMap<u32, (NegativeVerdict, u32)>,
+	stats: NegativeCacheStats,
+}
+
+impl NegativeCache {
+	const fn new() -> NegativeCache {
+		NegativeCache {
+			entries: BTreeMap::new(),
+			stats: NegativeCacheStats {
+				entries: 0,
+				hits: 0,
+				misses: 0,
+				saved_rpc_calls: 0,
+				insertions: 0,
+				invalidations: 0,
+			},
+		}
+	}
+
+	pub fn len(&self) -> usize {
+		self.entries.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.entries.is_empty()
+	}
+
+	/// Remove the expired verdicts
+	pub fn prune(&mut self, current_block: u32) {
+		self.entries.retain(|_, (_, expiry)| *expiry >= current_block);
+	}
+
+	/// Cached verdict of an nft
+	/// # Arguments
+	/// * `nft_id` - nft/capsule id
+	/// * `current_block` - current block number
+	/// * `rpc_calls` - number of rpc calls that a cache hit saves
+	pub fn lookup(
+		&mut self,
+		nft_id: u32,
+		current_block: u32,
+		rpc_calls: u64,
+	) -> Option<NegativeVerdict> {
+		match self.entries.get(&nft_id) {
+			Some((verdict, expiry)) if *expiry >= current_block => {
+				self.stats.hits += 1;
+				self.stats.saved_rpc_calls += rpc_calls;
+				Some(verdict.clone())
+			},
+			_ => {
+				self.stats.misses += 1;
+				None
+			},
+		}
+	}
+
+	/// Cache a verdict for NEGATIVE_CACHE_TTL blocks
+	pub fn insert(&mut self, nft_id: u32, verdict: NegativeVerdict, current_block: u32) {
+		self.prune(current_block);
+
+		// Bounded : evict the verdict which expires sooner
+		while self.entries.len() >= MAX_NEGATIVE_CACHE_ENTRIES {
+			let oldest = match self.entries.iter().min_by_key(|(_, (_, expiry))| *expiry) {
+				Some((id, _)) => *id,
+				None => break,
+			};
+			self.entries.remove(&oldest);
+		}
+
+		self.entries.insert(nft_id, (verdict, current_block + NEGATIVE_CACHE_TTL));
+		self.stats.insertions += 1;
+	}
+
+	/// Drop the verdict of an nft, true if there was one
+	pub fn invalidate(&mut self, nft_id: u32) -> bool {
+		let removed = self.entries.remove(&nft_id).is_some();
+		if removed {
+			self.stats.invalidations += 1;
+		}
+		removed
+	}
+
+	/// Drop the verdicts of an nft range, returns their number
+	pub fn invalidate_range(&mut self, first_nft_id: u32, last_nft_id: u32) -> usize {
+		let before = self.entries.len();
+		self.entries.retain(|nft_id, _| *nft_id < first_nft_id || *nft_id > last_nft_id);
+		let removed = before - self.entries.len();
+		self.stats.invalidations += removed as u64;
+		removed
+	}
+
+	pub fn stats(&self) -> NegativeCacheStats {
+		NegativeCacheStats { entries: self.entries.len(), ..self.stats.clone() }
+	}
+}
+
+fn update_negative_cache<T>(update: impl FnOnce(&mut NegativeCache) -> T) -> Option<T> {
+	match NEGATIVE_CACHE.lock() {
+		Ok(mut cache) => Some(update(&mut cache)),
+		Err(err) => {
+			error!("NEGATIVE CACHE : lock error : {err:?}");
+			None
+		},
+	}
+}
+
+/// Hits and saved rpc calls of the cache
+pub fn negative_cache_stats() -> NegativeCacheStats {
+	update_negative_cache(|cache| cache.stats()).unwrap_or_default()
+}
+
+/// Verdicts of the cache, for the upgrade handoff
+pub fn negative_cache_snapshot() -> NegativeCache {
+	update_negative_cache(|cache| cache.clone()).unwrap_or_default()
+}
+
+/// Replace the cache by the verdicts of the previous instance
+pub fn restore_negative_cache(snapshot: NegativeCache) {
+	update_negative_cache(|cache| *cache = snapshot);
+}
+
+/// Drop the verdicts of an nft range which is about to be minted
+pub fn invalidate_negative_range(first_nft_id: u32, last_nft_id: u32) -> usize {
+	update_negative_cache(|cache| cache.invalidate_range(first_nft_id, last_nft_id)).unwrap_or(0)
+}
+
+/// Cached negative result of an nft lookup, instead of querying the chain
+/// # Arguments
+/// * `state` - SharedState
+/// * `nft_id` - nft/capsule id
+/// # Returns
+/// * `Option<Result<OnchainNft, VerificationError>>` - None on cache miss
+pub async fn cached_lookup(
+	state: &SharedState,
+	nft_id: u32,
+) -> Option<Result<OnchainNft, VerificationError>> {
+	let current_block = get_blocknumber(state).await;
+	// Dual-rpc mode queries both endpoints
+	let rpc_calls = if get_secondary_chain_api(state).await.is_some() { 2 } else { 1 };
+
+	update_negative_cache(|cache| cache.lookup(nft_id, current_block, rpc_calls))
+		.flatten()
+		.map(|verdict| verdict.to_result())
+}
+
+/// Cache the result of an nft lookup if it is negative. The nfts of an active provisioning window
+/// are not cached, they are minted during the window.
+/// # Arguments
+/// * `state` - SharedState
+/// * `nft_id` - nft/capsule id
+/// * `result` - result of the onchain lookup
+pub async fn remember_lookup(
+	state: &SharedState,
+	nft_id: u32,
+	result: &Result<OnchainNft, VerificationError>,
+) {
+	if is_provisioned_nft(state, nft_id).await {
+		return
+	}
+
+	if let Some(verdict) = NegativeVerdict::from_result(result) {
+		let current_block = get_blocknumber(state).await;
+		update_negative_cache(|cache| cache.insert(nft_id, verdict, current_block));
+	}
+}
+
+/// Invalidate cached verdicts of the nfts which are created, or converted to secret/capsule, in a
+/// best or finalized block
+/// # Arguments
+/// * `block` - best or finalized block
+pub async fn watch_block(
+	block: &Block<PolkadotConfig, OnlineClient<PolkadotConfig>>,
+) -> Result<(), anyhow::Error> {
+	let events = block.events().await?;
+
+	let mut nft_ids = Vec::new();
+
+	for event in events.find::<NFTCreated>() {
+		match event {
+			Ok(ev) => nft_ids.push(ev.nft_id),
+			Err(err) => warn!("NEGATIVE CACHE : error decoding NFTCreated event : {err:?}"),
+		}
+	}
+
+	for event in events.find::<SecretAddedToNFT>() {
+		match event {
+			Ok(ev) => nft_ids.push(ev.nft_id),
+			Err(err) => warn!("NEGATIVE CACHE : error decoding SecretAddedToNFT event : {err:?}"),
+		}
+	}
+
+	for event in events.find::<CapsuleConverted>() {
+		match event {
+			Ok(ev) => nft_ids.push(ev.nft_id),
+			Err(err) => warn!("NEGATIVE CACHE : error decoding CapsuleConverted event : {err:?}"),
+		}
+	}
+
+	if nft_ids.is_empty() {
+		return Ok(())
+	}
+
+	update_negative_cache(|cache| {
+		for nft_id in nft_ids {
+			if cache.invalidate(nft_id) {
+				debug!("NEGATIVE CACHE : verdict of nft_id {} is invalidated", nft_id);
+			}
+		}
+	});
+
+	Ok(())
+}
+
+/// Best block subscription, a freshly created nft is served before its block is finalized
+/// # Arguments
+/// * `chain_api` - chain api of the primary rpc
+pub async fn best_block_subscription(chain_api: DefaultApi) -> Result<(), anyhow::Error> {
+	let mut blocks_sub = chain_api.blocks().subscribe_best().await?;
+
+	while let Some(block) = blocks_sub.next().await {
+		let block = block?;
+		if let Err(err) = watch_block(&block).await {
+			warn!("NEGATIVE CACHE : unable to check the events of a best block : {err:?}");
+		}
+	}
+
+	Err(anyhow!("NEGATIVE CACHE : best block subscription is closed"))
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use subxt::utils::AccountId32;
+
+	fn plain_nft() -> OnchainNft {
+		OnchainNft {
+			owner: AccountId32([1u8; 32]),
+			is_secret: false,
+			is_syncing_secret: false,
+			is_capsule: false,
+			is_syncing_capsule: false,
+			collection_id: None,
+		}
+	}
+
+	#[test]
+	fn negative_verdict_test() {
+		assert_eq!(
+			NegativeVerdict::from_result(&Err(VerificationError::INVALIDNFTID)),
+			Some(NegativeVerdict::NOTFOUND)
+		);
+		assert_eq!(
+			NegativeVerdict::from_result(&Ok(plain_nft())),
+			Some(NegativeVerdict::PLAIN(plain_nft()))
+		);
+
+		// Positive or transient results are never cached
+		let secret = OnchainNft { is_secret: true, ..plain_nft() };
+		assert_eq!(NegativeVerdict::from_result(&Ok(secret)), None);
+		assert_eq!(NegativeVerdict::from_result(&Err(VerificationError::ORACLEFAILURE)), None);
+	}
+
+	#[test]
+	fn negative_cache_test() {
+		let mut cache = NegativeCache::default();
+
+		cache.insert(10, NegativeVerdict::NOTFOUND, 1000);
+		cache.insert(11, NegativeVerdict::PLAIN(plain_nft()), 1000);
+		assert_eq!(cache.len(), 2);
+
+		assert_eq!(cache.lookup(10, 1000, 1), Some(NegativeVerdict::NOTFOUND));
+		assert_eq!(
+			cache.lookup(11, 1000 + NEGATIVE_CACHE_TTL, 2).unwrap().to_result(),
+			Ok(plain_nft())
+		);
+		assert_eq!(cache.lookup(12, 1000, 1), None);
+
+		// Expired
+		assert_eq!(cache.lookup(10, 1001 + NEGATIVE_CACHE_TTL, 1), None);
+
+		// Creation event
+		assert!(cache.invalidate(11));
+		assert!(!cache.invalidate(11));
+		assert_eq!(cache.lookup(11, 1000, 1), None);
+
+		let stats = cache.stats();
+		assert_eq!(stats.hits, 2);
+		assert_eq!(stats.misses, 3);
+		assert_eq!(stats.saved_rpc_calls, 3);
+		assert_eq!(stats.insertions, 2);
+		assert_eq!(stats.invalidations, 1);
+
+		// Expired verdicts are pruned on insertion
+		cache.insert(12, NegativeVerdict::NOTFOUND, 2000);
+		assert_eq!(cache.len(), 1);
+
+		// Provisioning window
+		cache.insert(20, NegativeVerdict::NOTFOUND, 2000);
+		assert_eq!(cache.invalidate_range(12, 19), 1);
+		assert_eq!(cache.len(), 1);
+		assert_eq!(cache.lookup(20, 2000, 1), Some(NegativeVerdict::NOTFOUND));
+	}
+}
@@ -1,14 +1,18 @@
 use crate::{
-	chain::helper,
-	servers::state::{
-		get_accountid, get_blocknumber, get_nft_availability, remove_nft_availability,
-		set_nft_availability, SharedState,
+	chain::{compression, helper},
+	servers::{
+		events::{publish_keyshare_event, KeyshareEventKind},
+		state::{
+			get_accountid, get_blocknumber, get_compression_threshold, get_nft_availability,
+			remove_nft_availability, set_nft_availability, SharedState,
+		},
 	},
 };
 
 use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
 
 use std::{
+	collections::BTreeMap,
 	fs::{File, OpenOptions},
 	io::{Read, Write},
 };
@@ -18,14 +22,15 @@ use tracing::{debug, error, info, warn};
 use axum::extract::Path as PathExtract;
 
 use crate::chain::{
+	audit::{audit_failure, audit_result, audit_verification},
 	constants::SEALPATH,
 	core::{get_onchain_nft_data, nft_keyshare_oracle},
 	log::*,
 	verify::*,
 };
 use serde::Serialize;
-use serde_json::{json, to_value};
-use subxt::ext::sp_core::H256;
+use serde_json::{json, to_value, Value};
+use subxt::ext::sp_core::{sr25519, H256};
 
 /* **********************
  KEYSHARE AVAILABLE API
@@ -312,7 +317,22 @@ pub async fn nft_store_keyshare(
 	let enclave_sealpath = SEALPATH.to_string();
 	let block_number = get_blocknumber(&state).await;
 
-	match request.verify_store_request(&state, "secret-nft").await {
+	let verification = request.verify_store_request(&state, NftKind::SECRET).await;
+	if audit_result(
+		&state,
+		APICALL::NFTSTORE,
+		request.requested_nft_id(),
+		request.owner_address.to_string(),
+		Some(RequesterType::OWNER),
+		&verification,
+	)
+	.await
+	.is_err()
+	{
+		return audit_failure(APICALL::NFTSTORE, request.requested_nft_id(), enclave_account)
+	}
+
+	match verification {
 		Ok(verified_data) => {
 			if !std::path::Path::new(&enclave_sealpath).exists() {
 				let status = ReturnStatus::DATABASEFAILURE;
@@ -382,48 +402,9 @@ pub async fn nft_store_keyshare(
 
 			let new_file_path =
 				format!("{SEALPATH}/nft_{}_{block_number}.keyshare", verified_data.nft_id);
+			let compression_threshold = get_compression_threshold(&state).await;
 
-			let mut f = match File::create(new_file_path.clone()) {
-				Ok(file) => file,
-				Err(err) => {
-					let status = ReturnStatus::DATABASEFAILURE;
-					let message = format!(
-						"TEE Key-share {:?}: error in creating file on disk, nft_id : {}, requester : {}, path : {}, error: {}",
-						APICALL::NFTSTORE,
-						verified_data.nft_id,
-						request.owner_address,
-						new_file_path,
-						err
-					);
-
-					error!(message);
-					sentry::with_scope(
-						|scope| {
-							scope.set_tag("nft-store-keyshare", verified_data.nft_id.to_string());
-						},
-						|| sentry::capture_message(&message, sentry::Level::Error),
-					);
-
-					let description =
-						"Error storing NFT key-share to TEE, use another enclave please."
-							.to_string();
-
-					return (
-						StatusCode::INTERNAL_SERVER_ERROR,
-						Json(
-							to_value(ApiErrorResponse {
-								status,
-								nft_id: verified_data.nft_id,
-								enclave_account,
-								description,
-							})
-							.unwrap(),
-						),
-					)
-				},
-			};
-
-			match f.write_all(&verified_data.keyshare) {
+			match write_keyshare_file(&new_file_path, &verified_data, compression_threshold) {
 				Ok(_) => info!(
 					"Keyshare is stored to TEE, nft_id = {} Owner = {}",
 					verified_data.nft_id, request.owner_address
@@ -432,12 +413,12 @@ pub async fn nft_store_keyshare(
 				Err(err) => {
 					let status = ReturnStatus::DATABASEFAILURE;
 					let message = format!(
-						"TEE Key-share {:?}: error in writing data to file, nft_id : {}, requester: {}, path : {}, error: {}",
+						"TEE Key-share {:?}: {}, nft_id : {}, requester: {}, path : {}",
 						APICALL::NFTSTORE,
+						err,
 						verified_data.nft_id,
 						request.owner_address,
 						new_file_path,
-						err
 					);
 
 					error!(message);
@@ -473,8 +454,12 @@ pub async fn nft_store_keyshare(
 				Ok(txh) => {
 					// TODO : Getting of TXH is not sufficient, It must wait until next block to see
 					// if it is submitted.
-					let result =
-						nft_keyshare_oracle_results(block_number, &request, &verified_data, txh);
+					let result = nft_keyshare_oracle_results(
+						block_number,
+						&request.owner_address,
+						&verified_data,
+						txh,
+					);
 
 					if result {
 						set_nft_availability(
@@ -488,6 +473,12 @@ pub async fn nft_store_keyshare(
 							),
 						)
 						.await;
+						publish_keyshare_event(
+							KeyshareEventKind::STORED,
+							verified_data.nft_id,
+							helper::NftType::Secret,
+							block_number,
+						);
 						let status = ReturnStatus::STORESUCCESS;
 						let description = "Keyshare is successfully stored to TEE".to_string();
 						(
@@ -601,21 +592,70 @@ pub async fn nft_store_keyshare(
 	}
 }
 
+/// Error of writing a keyshare file
+#[derive(Debug)]
+pub enum KeyshareFileError {
+	CREATE(std::io::Error),
+	WRITE(std::io::Error),
+}
+
+impl std::fmt::Display for KeyshareFileError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			KeyshareFileError::CREATE(err) =>
+				write!(f, "error in creating file on disk, error: {err}"),
+			KeyshareFileError::WRITE(err) =>
+				write!(f, "error in writing data to file, error: {err}"),
+		}
+	}
+}
+
+/// Seal a verified keyshare to its file
+/// # Arguments
+/// * `file_path` - keyshare file in the seal path
+/// * `verified_data` - verified store request
+/// * `compression_threshold` - keyshares larger than it are compressed
+pub fn write_keyshare_file(
+	file_path: &str,
+	verified_data: &StoreKeyshareData,
+	compression_threshold: usize,
+) -> Result<(), KeyshareFileError> {
+	let mut file = File::create(file_path).map_err(KeyshareFileError::CREATE)?;
+
+	let keyshare_at_rest = verified_data.keyshare_at_rest();
+	let sealed_keyshare = compression::seal_keyshare(&keyshare_at_rest, compression_threshold);
+
+	file.write_all(&sealed_keyshare).map_err(KeyshareFileError::WRITE)
+}
+
 /// Send extrinsic to Secret-NFT Pallet as Storage-Oracle
 fn nft_keyshare_oracle_results(
 	block_number: u32,
-	request: &StoreKeysharePacket,
+	owner_address: &sr25519::Public,
 	verified_data: &StoreKeyshareData,
 	txh: H256,
 ) -> bool {
 	info!(
  "Proof of storage has been sent to blockchain nft-pallet, nft_id = {} Owner = {} tx-hash = {}",
- verified_data.nft_id, request.owner_address, txh
+ verified_data.nft_id, owner_address, txh
  );
 
 	// Log file for tracing the NFT key-share VIEW history in Marketplace.
 	let file_path = format!("{SEALPATH}/{}.log", verified_data.nft_id);
 
+	create_store_log(&file_path, block_number, owner_address)
+}
+
+/// Create the history log of a stored keyshare
+/// # Arguments
+/// * `file_path` - log file in the seal path
+/// * `block_number` - block number of the store
+/// * `owner_address` - owner of the nft
+pub fn create_store_log(
+	file_path: &str,
+	block_number: u32,
+	owner_address: &sr25519::Public,
+) -> bool {
 	let mut file = match File::create(file_path) {
 		Ok(file) => file,
 		Err(err) => {
@@ -625,7 +665,7 @@ fn nft_keyshare_oracle_results(
 	};
 
 	let mut log_file_struct = LogFile::new();
-	let log_account = LogAccount::new(request.owner_address.to_string(), RequesterType::OWNER);
+	let log_account = LogAccount::new(owner_address.to_string(), RequesterType::OWNER);
 	let new_log = LogStruct::new(block_number, log_account, LogType::STORE);
 	log_file_struct.insert_new_nft_log(new_log);
 
@@ -671,7 +711,22 @@ pub async fn nft_retrieve_keyshare(
 	let enclave_account = get_accountid(&state).await;
 	let block_number = get_blocknumber(&state).await;
 
-	match request.verify_retrieve_request(&state, "secret-nft").await {
+	let verification = request.verify_retrieve_request(&state, NftKind::SECRET).await;
+	if audit_result(
+		&state,
+		APICALL::NFTRETRIEVE,
+		request.requested_nft_id(),
+		request.requester_address.to_string(),
+		Some(request.requester_type),
+		&verification,
+	)
+	.await
+	.is_err()
+	{
+		return audit_failure(APICALL::NFTRETRIEVE, request.requested_nft_id(), enclave_account)
+	}
+
+	match verification {
 		Ok(verified_data) => {
 			let av = match get_nft_availability(&state, verified_data.nft_id).await {
 				Some(av) =>
@@ -786,51 +841,82 @@ pub async fn nft_retrieve_keyshare(
 				},
 			};
 
-			let mut nft_keyshare = Vec::<u8>::new();
-
-			match file.read_to_end(&mut nft_keyshare) {
-				Ok(_) => {
-					info!(
-						"Keyshare of {} retrieved by {}",
-						verified_data.nft_id, request.requester_address
-					)
-				},
+			// Keyshare is read directly into the response buffer
+			let (response_buffer, binary) =
+				match helper::read_keyshare_into_response(&mut file, verified_data.nft_id) {
+					Ok(response) => {
+						info!(
+							"Keyshare of {} retrieved by {}",
+							verified_data.nft_id, request.requester_address
+						);
+						response
+					},
 
-				Err(err) => {
-					let status = ReturnStatus::KEYNOTREADABLE;
-					let description = format!(
+					Err(err) => {
+						let status = ReturnStatus::KEYNOTREADABLE;
+						let description = format!(
 						"TEE Key-share {:?}: can not read keyshare file, nft_id : {} Error : {}",
 						APICALL::NFTRETRIEVE,
 						verified_data.nft_id,
 						err
 					);
 
-					let message =
-						format!("{}, requester : {}", description, request.requester_address);
-					error!(message);
+						let message =
+							format!("{}, requester : {}", description, request.requester_address);
+						error!(message);
 
-					sentry::with_scope(
-						|scope| {
-							scope
-								.set_tag("nft-retrieve-keyshare", verified_data.nft_id.to_string());
-						},
-						|| sentry::capture_message(&message, sentry::Level::Error),
-					);
+						sentry::with_scope(
+							|scope| {
+								scope.set_tag(
+									"nft-retrieve-keyshare",
+									verified_data.nft_id.to_string(),
+								);
+							},
+							|| sentry::capture_message(&message, sentry::Level::Error),
+						);
 
-					return (
-						StatusCode::INTERNAL_SERVER_ERROR,
-						Json(
-							to_value(ApiErrorResponse {
-								status,
-								nft_id: verified_data.nft_id,
-								enclave_account,
-								description,
-							})
-							.unwrap(),
-						),
-					)
-				},
-			};
+						return (
+							StatusCode::INTERNAL_SERVER_ERROR,
+							Json(
+								to_value(ApiErrorResponse {
+									status,
+									nft_id: verified_data.nft_id,
+									enclave_account,
+									description,
+								})
+								.unwrap(),
+							),
+						)
+					},
+				};
+
+			let auth_token = AuthenticationToken { block_number, block_validation: 15 }.serialize();
+			let serialized_keyshare =
+				match helper::finish_keyshare_response(response_buffer, &auth_token) {
+					Ok(serialized_keyshare) => serialized_keyshare,
+					Err(err) => {
+						let status = ReturnStatus::KEYNOTREADABLE;
+						let description = format!(
+							"TEE Key-share {:?}: can not serialize keyshare, nft_id : {}",
+							APICALL::NFTRETRIEVE,
+							verified_data.nft_id,
+						);
+						error!("{}, Error : {:?}", description, err);
+
+						return (
+							StatusCode::INTERNAL_SERVER_ERROR,
+							Json(
+								to_value(ApiErrorResponse {
+									status,
+									nft_id: verified_data.nft_id,
+									enclave_account,
+									description,
+								})
+								.unwrap(),
+							),
+						)
+					},
+				};
 
 			// Put a VIEWING history log
 			let file_path = format!("{SEALPATH}/{}.log", verified_data.nft_id);
@@ -844,13 +930,11 @@ pub async fn nft_retrieve_keyshare(
 				"secret-nft",
 			);
 
-			let serialized_keyshare = StoreKeyshareData {
-				nft_id: verified_data.nft_id,
-				keyshare: nft_keyshare,
-				auth_token: AuthenticationToken { block_number, block_validation: 15 },
-			}
-			.serialize();
-			let status = ReturnStatus::RETRIEVESUCCESS;
+			// A lapsed rental is still served read-only during its grace period
+			let status = match verified_data.rental_grace {
+				Some(_) => ReturnStatus::RENTALGRACEPERIOD,
+				None => ReturnStatus::RETRIEVESUCCESS,
+			};
 			let description = format!(
 				"TEE Key-share {:?}: Success retrieving nft_id key-share.",
 				APICALL::NFTRETRIEVE
@@ -858,16 +942,21 @@ pub async fn nft_retrieve_keyshare(
 
 			info!("{}, requester : {}", description, request.requester_address);
 
-			(
-				StatusCode::OK,
-				Json(json!({
-					"status": status,
-					"nft_id": verified_data.nft_id,
-					"enclave_account": enclave_account,
-					"keyshare_data": serialized_keyshare,
-					"description": description,
-				})),
-			)
+			// Move the keyshare into the response, json! macro would copy it
+			let mut response = json!({
+				"status": status,
+				"nft_id": verified_data.nft_id,
+				"enclave_account": enclave_account,
+				"requester_address": canonical_address(&request.requester_address),
+				"description": description,
+				"binary": binary,
+			});
+			if let Some(grace_end) = verified_data.rental_grace {
+				response["grace_end_block"] = json!(grace_end);
+			}
+			response["keyshare_data"] = Value::String(serialized_keyshare);
+
+			(StatusCode::OK, Json(response))
 		},
 
 		Err(err) => {
@@ -892,10 +981,404 @@ pub async fn nft_retrieve_keyshare(
 	}
 }
 
+/* **********************
+	 BATCH KEYSHARES
+********************** */
+
+/// Result list and http status of a batch, MULTI_STATUS if an item failed
+fn batch_response(
+	enclave_account: String,
+	atomicity: BatchAtomicity,
+	results: Vec<BatchItemResult>,
+	success: ReturnStatus,
+) -> (StatusCode, Json<Value>) {
+	let errors = batch_errors(&results, success);
+	let failed = errors.len();
+	let status = if failed == 0 { StatusCode::OK } else { StatusCode::MULTI_STATUS };
+
+	(
+		status,
+		Json(json!({
+			"enclave_account": enclave_account,
+			"atomicity": atomicity,
+			"succeeded": results.len() - failed,
+			"failed": failed,
+			"results": results,
+			"errors": errors,
+		})),
+	)
+}
+
+/// Mark the valid items of a failed all-or-nothing batch
+fn abort_batch_items(results: &mut Vec<BatchItemResult>, nft_ids: Vec<u32>) {
+	for nft_id in nft_ids {
+		results.push(BatchItemResult::failure(
+			nft_id,
+			ReturnStatus::BATCHABORTED,
+			VerificationStep::STORAGE,
+			Retryability::RETRYABLE,
+			"Another item of the all-or-nothing batch has failed".to_string(),
+		));
+	}
+}
+
+/// Store keyshares of multiple secret-nfts with one signature
+/// # Arguments
+/// * `state` - StateConfig
+/// * `request` - BatchStoreKeysharePacket
+/// # Returns
+/// * `Json` - per-item results, ALLORNOTHING writes nothing if an item fails before the proof of
+///   storage is sent to the chain
+#[axum::debug_handler]
+pub async fn nft_batch_store_keyshare(
+	State(state): State<SharedState>,
+	Json(request): Json<BatchStoreKeysharePacket>,
+) -> impl IntoResponse {
+	debug!("\n\t*****\nNFT BATCH STORE KEYSHARE API\n\t*****\n");
+	let enclave_account = get_accountid(&state).await;
+	let block_number = get_blocknumber(&state).await;
+	let atomicity = request.atomicity;
+
+	let items = match request.verify_batch_store_request(&state, NftKind::SECRET).await {
+		Ok(items) => items,
+		Err(err) => {
+			if audit_verification(
+				&state,
+				APICALL::NFTBATCHSTORE,
+				0,
+				request.owner_address.to_string(),
+				Some(RequesterType::OWNER),
+				err.status(),
+			)
+			.await
+			.is_err()
+			{
+				return audit_failure(APICALL::NFTBATCHSTORE, 0, enclave_account)
+			}
+
+			return err.express_verification_error(
+				APICALL::NFTBATCHSTORE,
+				request.owner_address.to_string(),
+				0,
+				enclave_account,
+			)
+		},
+	};
+
+	for (nft_id, item) in &items {
+		if audit_result(
+			&state,
+			APICALL::NFTBATCHSTORE,
+			*nft_id,
+			request.owner_address.to_string(),
+			Some(RequesterType::OWNER),
+			item,
+		)
+		.await
+		.is_err()
+		{
+			return audit_failure(APICALL::NFTBATCHSTORE, *nft_id, enclave_account)
+		}
+	}
+
+	let mut results = Vec::<BatchItemResult>::new();
+	let mut verified = Vec::<StoreKeyshareData>::new();
+
+	for (nft_id, item) in items {
+		match item {
+			Ok(verified_data) => match get_nft_availability(&state, nft_id).await {
+				// Only Capsule is mutable
+				Some(av) if av.nft_type != helper::NftType::Capsule =>
+					results.push(BatchItemResult::failure(
+						nft_id,
+						ReturnStatus::NFTIDEXISTS,
+						VerificationStep::STORAGE,
+						Retryability::PERMANENT,
+						"nft_id already exists".to_string(),
+					)),
+				_ => verified.push(verified_data),
+			},
+			Err(err) => results.push(err.express_batch_item(nft_id, None)),
+		}
+	}
+
+	if atomicity == BatchAtomicity::ALLORNOTHING && !results.is_empty() {
+		info!("NFT BATCH STORE : batch is aborted, requester : {}", request.owner_address);
+		abort_batch_items(&mut results, verified.iter().map(|data| data.nft_id).collect());
+		return batch_response(enclave_account, atomicity, results, ReturnStatus::STORESUCCESS)
+	}
+
+	// Write all keyshares before any proof of storage, which can not be reverted
+	let compression_threshold = get_compression_threshold(&state).await;
+	let mut written = Vec::<(StoreKeyshareData, String)>::new();
+
+	for verified_data in verified {
+		let file_path = format!("{SEALPATH}/nft_{}_{block_number}.keyshare", verified_data.nft_id);
+		let keyshare_at_rest = verified_data.keyshare_at_rest();
+		let sealed_keyshare = compression::seal_keyshare(&keyshare_at_rest, compression_threshold);
+
+		match std::fs::write(&file_path, &sealed_keyshare) {
+			Ok(_) => written.push((verified_data, file_path)),
+			Err(err) => {
+				let message = format!(
+					"NFT BATCH STORE : error writing keyshare to file, nft_id : {}, path : {}, error : {:?}",
+					verified_data.nft_id, file_path, err
+				);
+				error!(message);
+				sentry::capture_message(&message, sentry::Level::Error);
+
+				results.push(BatchItemResult::failure(
+					verified_data.nft_id,
+					ReturnStatus::DATABASEFAILURE,
+					VerificationStep::STORAGE,
+					Retryability::RETRYABLE,
+					"Error storing NFT key-share to TEE".to_string(),
+				));
+			},
+		}
+	}
+
+	if atomicity == BatchAtomicity::ALLORNOTHING && !results.is_empty() {
+		for (_, file_path) in &written {
+			if let Err(err) = std::fs::remove_file(file_path) {
+				error!("NFT BATCH STORE : error rolling back {} : {:?}", file_path, err);
+			}
+		}
+
+		abort_batch_items(&mut results, written.iter().map(|(data, _)| data.nft_id).collect());
+		return batch_response(enclave_account, atomicity, results, ReturnStatus::STORESUCCESS)
+	}
+
+	// Proof of storage of each nft
+	for (verified_data, file_path) in written {
+		let nft_id = verified_data.nft_id;
+
+		let stored = match nft_keyshare_oracle(&state, nft_id).await {
+			Ok(txh) => nft_keyshare_oracle_results(
+				block_number,
+				&request.owner_address,
+				&verified_data,
+				txh,
+			),
+			Err(err) => {
+				error!("NFT BATCH STORE : error sending proof of storage, nft_id : {nft_id}, error : {err:?}");
+				false
+			},
+		};
+
+		if stored {
+			set_nft_availability(
+				&state,
+				(nft_id, helper::Availability { block_number, nft_type: helper::NftType::Secret }),
+			)
+			.await;
+			publish_keyshare_event(
+				KeyshareEventKind::STORED,
+				nft_id,
+				helper::NftType::Secret,
+				block_number,
+			);
+			results.push(BatchItemResult::success(nft_id, ReturnStatus::STORESUCCESS));
+		} else {
+			if let Err(err) = std::fs::remove_file(&file_path) {
+				error!("NFT BATCH STORE : error removing {} : {:?}", file_path, err);
+			}
+
+			results.push(BatchItemResult::failure(
+				nft_id,
+				ReturnStatus::ORACLEFAILURE,
+				VerificationStep::STORAGE,
+				Retryability::RETRYABLE,
+				"Error sending proof of storage to chain".to_string(),
+			));
+		}
+	}
+
+	batch_response(enclave_account, atomicity, results, ReturnStatus::STORESUCCESS)
+}
+
+/// Read the sealed keyshare of a secret-nft into a response buffer, true if it is binary
+fn read_batch_keyshare(
+	nft_id: u32,
+	availability: Option<helper::Availability>,
+) -> Result<(Vec<u8>, bool), BatchItemResult> {
+	let av = match availability {
+		Some(av) if av.nft_type == helper::NftType::Secret => av,
+		_ =>
+			return Err(BatchItemResult::failure(
+				nft_id,
+				ReturnStatus::KEYNOTEXIST,
+				VerificationStep::STORAGE,
+				Retryability::PERMANENT,
+				"NFT Keyshare is not available.".to_string(),
+			)),
+	};
+
+	let file_path = format!("{SEALPATH}/nft_{}_{}.keyshare", nft_id, av.block_number);
+
+	let mut file = File::open(&file_path).map_err(|err| {
+		error!("NFT BATCH RETRIEVE : can not open {} : {:?}", file_path, err);
+		BatchItemResult::failure(
+			nft_id,
+			ReturnStatus::KEYNOTACCESSIBLE,
+			VerificationStep::STORAGE,
+			Retryability::RETRYABLE,
+			"Can not open keyshare file".to_string(),
+		)
+	})?;
+
+	helper::read_keyshare_into_response(&mut file, nft_id).map_err(|err| {
+		error!("NFT BATCH RETRIEVE : can not read {} : {:?}", file_path, err);
+		BatchItemResult::failure(
+			nft_id,
+			ReturnStatus::KEYNOTREADABLE,
+			VerificationStep::STORAGE,
+			Retryability::RETRYABLE,
+			"Can not read keyshare file".to_string(),
+		)
+	})
+}
+
+/// Retrieve keyshares of multiple secret-nfts with one signature
+/// # Arguments
+/// * `state` - StateConfig
+/// * `request` - BatchRetrieveKeysharePacket
+/// # Returns
+/// * `Json` - per-item results and keyshares by nft_id, ALLORNOTHING returns no keyshare if an item
+///   fails
+#[axum::debug_handler]
+pub async fn nft_batch_retrieve_keyshare(
+	State(state): State<SharedState>,
+	Json(request): Json<BatchRetrieveKeysharePacket>,
+) -> impl IntoResponse {
+	debug!("\n\t*****\nNFT BATCH RETRIEVE KEYSHARE API\n\t*****\n");
+	let enclave_account = get_accountid(&state).await;
+	let block_number = get_blocknumber(&state).await;
+	let atomicity = request.atomicity;
+
+	let items = match request.verify_batch_retrieve_request(&state, NftKind::SECRET).await {
+		Ok(items) => items,
+		Err(err) => {
+			if audit_verification(
+				&state,
+				APICALL::NFTBATCHRETRIEVE,
+				0,
+				request.requester_address.to_string(),
+				Some(request.requester_type),
+				err.status(),
+			)
+			.await
+			.is_err()
+			{
+				return audit_failure(APICALL::NFTBATCHRETRIEVE, 0, enclave_account)
+			}
+
+			return err.express_verification_error(
+				APICALL::NFTBATCHRETRIEVE,
+				request.requester_address.to_string(),
+				0,
+				enclave_account,
+			)
+		},
+	};
+
+	for (nft_id, item) in &items {
+		if audit_result(
+			&state,
+			APICALL::NFTBATCHRETRIEVE,
+			*nft_id,
+			request.requester_address.to_string(),
+			Some(request.requester_type),
+			item,
+		)
+		.await
+		.is_err()
+		{
+			return audit_failure(APICALL::NFTBATCHRETRIEVE, *nft_id, enclave_account)
+		}
+	}
+
+	let auth_token = AuthenticationToken { block_number, block_validation: 15 }.serialize();
+	let mut results = Vec::<BatchItemResult>::new();
+	let mut serialized = Vec::<(u32, String)>::new();
+	let mut binary_nft_ids = Vec::<u32>::new();
+
+	for (nft_id, item) in items {
+		if let Err(err) = item {
+			results.push(err.express_batch_item(nft_id, None));
+			continue
+		}
+
+		// A keyshare which can not be serialized fails its item, before the atomicity check
+		let keyshare = read_batch_keyshare(nft_id, get_nft_availability(&state, nft_id).await)
+			.and_then(|(buffer, binary)| {
+				if binary {
+					binary_nft_ids.push(nft_id);
+				}
+				helper::finish_keyshare_response(buffer, &auth_token).map_err(|err| {
+					error!(
+						"NFT BATCH RETRIEVE : can not serialize keyshare {} : {:?}",
+						nft_id, err
+					);
+					BatchItemResult::failure(
+						nft_id,
+						ReturnStatus::KEYNOTREADABLE,
+						VerificationStep::STORAGE,
+						Retryability::PERMANENT,
+						"Can not serialize keyshare".to_string(),
+					)
+				})
+			});
+
+		match keyshare {
+			Ok(keyshare) => serialized.push((nft_id, keyshare)),
+			Err(result) => results.push(result),
+		}
+	}
+
+	if atomicity == BatchAtomicity::ALLORNOTHING && !results.is_empty() {
+		info!("NFT BATCH RETRIEVE : batch is aborted, requester : {}", request.requester_address);
+		abort_batch_items(&mut results, serialized.into_iter().map(|(nft_id, _)| nft_id).collect());
+		return batch_response(enclave_account, atomicity, results, ReturnStatus::RETRIEVESUCCESS)
+	}
+
+	let mut keyshares = BTreeMap::<u32, String>::new();
+
+	for (nft_id, serialized_keyshare) in serialized {
+		// Put a VIEWING history log
+		update_log_file_view(
+			block_number,
+			format!("{SEALPATH}/{nft_id}.log"),
+			request.requester_address.to_string(),
+			request.requester_type,
+			LogType::VIEW,
+			"secret-nft",
+		);
+
+		keyshares.insert(nft_id, serialized_keyshare);
+		results.push(BatchItemResult::success(nft_id, ReturnStatus::RETRIEVESUCCESS));
+	}
+
+	info!(
+		"NFT BATCH RETRIEVE : {} keyshares retrieved by {}",
+		keyshares.len(),
+		request.requester_address
+	);
+
+	let (status, Json(mut response)) =
+		batch_response(enclave_account, atomicity, results, ReturnStatus::RETRIEVESUCCESS);
+	response["requester_address"] = json!(canonical_address(&request.requester_address));
+	response["keyshares"] = json!(keyshares);
+	response["binary_nft_ids"] = json!(binary_nft_ids);
+
+	(status, Json(response))
+}
+
 /* **********************
 	 REMOVE KEYSHARE
 ********************** */
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
+#[serde(into = "ApiErrorPayload")]
 pub struct RemoveKeyshareResponse {
 	status: ReturnStatus,
 	nft_id: u32,
@@ -903,6 +1386,17 @@ pub struct RemoveKeyshareResponse {
 	description: String,
 }
 
+impl From<RemoveKeyshareResponse> for ApiErrorPayload {
+	fn from(response: RemoveKeyshareResponse) -> Self {
+		ApiErrorPayload::new(
+			response.status,
+			response.nft_id,
+			response.enclave_account,
+			response.description,
+		)
+	}
+}
+
 /// Remove keyshare from the enclave
 /// # Arguments
 /// * `request` - RemoveKeysharePacket
@@ -916,8 +1410,23 @@ pub async fn nft_remove_keyshare(
 	debug!("\n\t*****\nNFT REMOVE KEYSHARE API\n\t*****\n");
 	let enclave_account = get_accountid(&state).await;
 
-	// STRUCTURAL VALIDITY OF REQUEST
-	let request_data = match request.verify_remove_request(&state, "secret-nft").await {
+	// SIGNATURE, AUTH-TOKEN AND BURNT STATE
+	let verification = request.verify_remove_request(&state, NftKind::SECRET).await;
+	if audit_result(
+		&state,
+		APICALL::NFTREMOVE,
+		request.requested_nft_id(),
+		request.requester_address.to_string(),
+		None,
+		&verification,
+	)
+	.await
+	.is_err()
+	{
+		return audit_failure(APICALL::NFTREMOVE, request.requested_nft_id(), enclave_account)
+	}
+
+	let request_data = match verification {
 		Ok(rd) => rd,
 		Err(err) => {
 			let parsed_data = match request.parse_retrieve_data() {
@@ -962,31 +1471,6 @@ pub async fn nft_remove_keyshare(
 		)
 	}
 
-	// Is nft burnt?
-	let nft_data_opts = get_onchain_nft_data(&state, request_data.nft_id).await;
-	if let Some(nft_data) = nft_data_opts {
-		if nft_data.state.is_secret {
-			error!(
-				"NFT REMOVE : secret-nft is not in burnt or converted state, nft-id.{}, requester : {}",
-				request_data.nft_id, request.requester_address
-			);
-			return (
-				StatusCode::BAD_REQUEST,
-				Json(
-					to_value(RemoveKeyshareResponse {
-						status: ReturnStatus::NOTBURNT,
-						nft_id: request_data.nft_id,
-						enclave_account,
-						description:
-							"Error removing NFT key-share from TEE, NFT is not in burnt or converted state."
-								.to_string(),
-					})
-					.unwrap(),
-				),
-			);
-		}
-	}
-
 	let av = match get_nft_availability(&state, request_data.nft_id).await {
 		Some(av) => {
 			// If it's not Secret or Hybrid
@@ -1065,6 +1549,12 @@ pub async fn nft_remove_keyshare(
 			}
 
 			remove_nft_availability(&state, request_data.nft_id).await;
+			publish_keyshare_event(
+				KeyshareEventKind::REMOVED,
+				request_data.nft_id,
+				helper::NftType::Secret,
+				get_blocknumber(&state).await,
+			);
 
 			info!(
 				"REMOVE NFT :  Keyshare is successfully removed from enclave. nft_id = {}",
@@ -0,0 +1,269 @@
+#![allow(clippy::upper_case_acronyms)]
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use zeroize::Zeroizing;
+
+/* ---------------------------------------
+	PURE PARSERS OF THE LEGACY REQUEST DATA
+--------------------------------------- */
+
+// Only depends on std, base64 and zeroize so the fuzz targets can include it by path.
+// Policies (keyshare checks, ss58 decoding, auth-token periods) are applied by the callers.
+
+/// Errors of the "_" separated request fields
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+	// Wrong number of fields, `expected` is a minimum for the signer
+	FIELDCOUNT { expected: usize, found: usize },
+	INVALIDNFTID,
+	INVALIDBLOCKNUMBER,
+	INVALIDBLOCKVALIDATION,
+	INVALIDKEYSHAREENCODING,
+}
+
+/// "ADDRESS_block_expiry" signer of a store request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignerFields<'a> {
+	pub address: &'a str,
+	pub block_number: u32,
+	pub block_validation: u32,
+}
+
+/// "NFTID_secret_block_expiry" store data, the keyshare is borrowed from the request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StoreFields<'a> {
+	pub nft_id: u32,
+	pub keyshare: &'a str,
+	pub block_number: u32,
+	pub block_validation: u32,
+}
+
+/// "NFTID_base64url(secret)_block_expiry" store data, the decoded keyshare is wiped when dropped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BinaryStoreFields {
+	pub nft_id: u32,
+	pub keyshare: Zeroizing<Vec<u8>>,
+	pub block_number: u32,
+	pub block_validation: u32,
+}
+
+/// "NFTID_block_expiry" retrieve or remove data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetrieveFields {
+	pub nft_id: u32,
+	pub block_number: u32,
+	pub block_validation: u32,
+}
+
+/// Remove the "<Bytes>...</Bytes>" wrapping of PolkadotJS signRaw, only if both tags are present
+pub fn strip_bytes_wrapping(data: &str) -> &str {
+	data.strip_prefix("<Bytes>")
+		.and_then(|data| data.strip_suffix("</Bytes>"))
+		.unwrap_or(data)
+}
+
+fn parse_nft_id(field: &str) -> Result<u32, ParseError> {
+	field.parse::<u32>().map_err(|_| ParseError::INVALIDNFTID)
+}
+
+fn parse_block_number(field: &str) -> Result<u32, ParseError> {
+	field.parse::<u32>().map_err(|_| ParseError::INVALIDBLOCKNUMBER)
+}
+
+fn parse_block_validation(field: &str) -> Result<u32, ParseError> {
+	field.parse::<u32>().map_err(|_| ParseError::INVALIDBLOCKVALIDATION)
+}
+
+/// Parse the signer of a store request, fields after the block validation are ignored
+/// # Arguments
+/// * `signer` - "ADDRESS_block_expiry", optionally wrapped in "<Bytes>"
+pub fn parse_signer(signer: &str) -> Result<SignerFields<'_>, ParseError> {
+	let fields: Vec<&str> = strip_bytes_wrapping(signer).split('_').collect();
+
+	match fields.as_slice() {
+		[address, block_number, block_validation, ..] => Ok(SignerFields {
+			address,
+			block_number: parse_block_number(block_number)?,
+			block_validation: parse_block_validation(block_validation)?,
+		}),
+		_ => Err(ParseError::FIELDCOUNT { expected: 3, found: fields.len() }),
+	}
+}
+
+/// Parse the data of a legacy store request
+/// # Arguments
+/// * `data` - "NFTID_secret_block_expiry", optionally wrapped in "<Bytes>"
+pub fn parse_store(data: &str) -> Result<StoreFields<'_>, ParseError> {
+	let fields: Vec<&str> = strip_bytes_wrapping(data).split('_').collect();
+
+	match fields.as_slice() {
+		[nft_id, keyshare, block_number, block_validation] => Ok(StoreFields {
+			nft_id: parse_nft_id(nft_id)?,
+			keyshare,
+			block_number: parse_block_number(block_number)?,
+			block_validation: parse_block_validation(block_validation)?,
+		}),
+		_ => Err(ParseError::FIELDCOUNT { expected: 4, found: fields.len() }),
+	}
+}
+
+/// Parse the data of a binary store request, the base64url alphabet contains '_'
+/// # Arguments
+/// * `data` - "NFTID_base64url(secret)_block_expiry"
+pub fn parse_binary_store(data: &str) -> Result<BinaryStoreFields, ParseError> {
+	let (nft_id, rest) =
+		data.split_once('_').ok_or(ParseError::FIELDCOUNT { expected: 4, found: 1 })?;
+	let nft_id = parse_nft_id(nft_id)?;
+
+	let tail: Vec<&str> = rest.rsplitn(3, '_').collect();
+	let (encoded_keyshare, block_number, block_validation) = match tail.as_slice() {
+		[block_validation, block_number, encoded_keyshare] =>
+			(*encoded_keyshare, *block_number, *block_validation),
+		_ => return Err(ParseError::FIELDCOUNT { expected: 4, found: tail.len() + 1 }),
+	};
+
+	let block_number = parse_block_number(block_number)?;
+	let block_validation = parse_block_validation(block_validation)?;
+
+	let keyshare = URL_SAFE_NO_PAD
+		.decode(encoded_keyshare.trim_end_matches('='))
+		.map(Zeroizing::new)
+		.map_err(|_| ParseError::INVALIDKEYSHAREENCODING)?;
+
+	Ok(BinaryStoreFields { nft_id, keyshare, block_number, block_validation })
+}
+
+/// Parse the data of a legacy retrieve or remove request
+/// # Arguments
+/// * `data` - "NFTID_block_expiry", optionally wrapped in "<Bytes>"
+pub fn parse_retrieve(data: &str) -> Result<RetrieveFields, ParseError> {
+	let fields: Vec<&str> = strip_bytes_wrapping(data).split('_').collect();
+
+	match fields.as_slice() {
+		[nft_id, block_number, block_validation] => Ok(RetrieveFields {
+			nft_id: parse_nft_id(nft_id)?,
+			block_number: parse_block_number(block_number)?,
+			block_validation: parse_block_validation(block_validation)?,
+		}),
+		_ => Err(ParseError::FIELDCOUNT { expected: 3, found: fields.len() }),
+	}
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use proptest::prelude::*;
+
+	// PolkadotJS signRaw wraps the message, the sdk does not
+	fn wrap(data: String, polkadotjs: bool) -> String {
+		if polkadotjs {
+			format!("<Bytes>{data}</Bytes>")
+		} else {
+			data
+		}
+	}
+
+	#[test]
+	fn parse_errors_test() {
+		assert_eq!(
+			parse_retrieve("163_1000"),
+			Err(ParseError::FIELDCOUNT { expected: 3, found: 2 })
+		);
+		assert_eq!(parse_retrieve("x_1000_15"), Err(ParseError::INVALIDNFTID));
+		assert_eq!(parse_retrieve("163_-1_15"), Err(ParseError::INVALIDBLOCKNUMBER));
+		assert_eq!(parse_retrieve("163_1000_"), Err(ParseError::INVALIDBLOCKVALIDATION));
+		// Unbalanced wrapping is not removed
+		assert_eq!(parse_retrieve("<Bytes>163_1000_15"), Err(ParseError::INVALIDNFTID));
+
+		assert_eq!(
+			parse_store("163_a_b_1000_15"),
+			Err(ParseError::FIELDCOUNT { expected: 4, found: 5 })
+		);
+		assert_eq!(
+			parse_binary_store("163"),
+			Err(ParseError::FIELDCOUNT { expected: 4, found: 1 })
+		);
+		assert_eq!(
+			parse_binary_store("163_1000_15"),
+			Err(ParseError::FIELDCOUNT { expected: 4, found: 3 })
+		);
+		assert_eq!(parse_binary_store("163_a+b_1000_15"), Err(ParseError::INVALIDKEYSHAREENCODING));
+
+		assert_eq!(parse_signer("5Grwva"), Err(ParseError::FIELDCOUNT { expected: 3, found: 1 }));
+		assert_eq!(parse_signer("5Grwva_1000_15_extra").unwrap().address, "5Grwva");
+	}
+
+	proptest! {
+		#[test]
+		fn retrieve_round_trip(nft_id: u32, block_number: u32, block_validation: u32, polkadotjs: bool) {
+			let data = wrap(format!("{nft_id}_{block_number}_{block_validation}"), polkadotjs);
+			prop_assert_eq!(
+				parse_retrieve(&data),
+				Ok(RetrieveFields { nft_id, block_number, block_validation })
+			);
+		}
+
+		#[test]
+		fn store_round_trip(
+			nft_id: u32,
+			keyshare in "[^_]*",
+			block_number: u32,
+			block_validation: u32,
+			polkadotjs: bool,
+		) {
+			let data = wrap(format!("{nft_id}_{keyshare}_{block_number}_{block_validation}"), polkadotjs);
+			prop_assert_eq!(
+				parse_store(&data),
+				Ok(StoreFields { nft_id, keyshare: &keyshare, block_number, block_validation })
+			);
+		}
+
+		#[test]
+		fn binary_store_round_trip(
+			nft_id: u32,
+			keyshare: Vec<u8>,
+			block_number: u32,
+			block_validation: u32,
+		) {
+			let data = format!(
+				"{nft_id}_{}_{block_number}_{block_validation}",
+				URL_SAFE_NO_PAD.encode(&keyshare)
+			);
+			prop_assert_eq!(
+				parse_binary_store(&data),
+				Ok(BinaryStoreFields {
+					nft_id,
+					keyshare: Zeroizing::new(keyshare),
+					block_number,
+					block_validation
+				})
+			);
+		}
+
+		#[test]
+		fn signer_round_trip(
+			address in "[1-9A-HJ-NP-Za-km-z]{1,48}",
+			block_number: u32,
+			block_validation: u32,
+			polkadotjs: bool,
+		) {
+			let signer = wrap(format!("{address}_{block_number}_{block_validation}"), polkadotjs);
+			prop_assert_eq!(
+				parse_signer(&signer),
+				Ok(SignerFields { address: &address, block_number, block_validation })
+			);
+		}
+
+		#[test]
+		fn parsers_never_panic(data in "\\PC*") {
+			let _ = parse_signer(&data);
+			let _ = parse_store(&data);
+			let _ = parse_binary_store(&data);
+			let _ = parse_retrieve(&data);
+		}
+	}
+}
@@ -0,0 +1,201 @@
+use std::sync::OnceLock;
+
+use base64::{engine::general_purpose, Engine as _};
+use serde::Serialize;
+
+use crate::chain::{
+	constants::{MAX_KEYSHARE_SIZE, MIN_KEYSHARE_SIZE},
+	verify::VerificationError,
+};
+
+/* ---------------------------------------
+	KEYSHARE VALIDATION POLICY
+--------------------------------------- */
+
+static KEYSHARE_POLICY: OnceLock<KeysharePolicy> = OnceLock::new();
+
+/// Expected encoding of keyshares
+#[derive(clap::ValueEnum, Serialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyshareEncoding {
+	// Any printable utf-8 text
+	#[default]
+	Any,
+	// Standard or url-safe alphabet, with or without padding
+	Base64,
+	// Optional "0x" prefix
+	Hex,
+}
+
+/// Validation rules of the keyshares of store requests
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct KeysharePolicy {
+	pub min_size: usize,
+	pub max_size: usize,
+	pub encoding: KeyshareEncoding,
+	// Minimum shannon entropy in bits per byte, None disables the check
+	pub min_entropy: Option<f64>,
+}
+
+impl Default for KeysharePolicy {
+	fn default() -> Self {
+		KeysharePolicy {
+			min_size: MIN_KEYSHARE_SIZE.into(),
+			max_size: MAX_KEYSHARE_SIZE.into(),
+			encoding: KeyshareEncoding::Any,
+			min_entropy: None,
+		}
+	}
+}
+
+impl KeysharePolicy {
+	/// Validate a text keyshare, cheapest checks first
+	/// # Arguments
+	/// * `keyshare` - raw keyshare of the request
+	pub fn validate(&self, keyshare: &[u8]) -> Result<(), VerificationError> {
+		self.validate_size(keyshare)?;
+
+		let text = std::str::from_utf8(keyshare).map_err(|_| VerificationError::INVALIDKEYSHARE)?;
+		if text.chars().any(char::is_control) {
+			return Err(VerificationError::KEYSHARECONTROLCHAR)
+		}
+
+		let is_encoded = match self.encoding {
+			KeyshareEncoding::Any => true,
+			KeyshareEncoding::Base64 => is_base64(text),
+			KeyshareEncoding::Hex => hex::decode(text.strip_prefix("0x").unwrap_or(text)).is_ok(),
+		};
+		if !is_encoded {
+			return Err(VerificationError::INVALIDKEYSHAREENCODING)
+		}
+
+		self.validate_entropy(keyshare)
+	}
+
+	/// Validate a decoded binary keyshare, charset and encoding rules do not apply
+	pub fn validate_binary(&self, keyshare: &[u8]) -> Result<(), VerificationError> {
+		self.validate_size(keyshare)?;
+		self.validate_entropy(keyshare)
+	}
+
+	fn validate_size(&self, keyshare: &[u8]) -> Result<(), VerificationError> {
+		if keyshare.is_empty() {
+			return Err(VerificationError::INVALIDKEYSHARE)
+		}
+
+		if keyshare.len() < self.min_size {
+			return Err(VerificationError::KEYSHAREISTOOSHORT)
+		}
+
+		if keyshare.len() > self.max_size {
+			return Err(VerificationError::KEYSHAREISTOOLONG)
+		}
+
+		Ok(())
+	}
+
+	fn validate_entropy(&self, keyshare: &[u8]) -> Result<(), VerificationError> {
+		if let Some(min_entropy) = self.min_entropy {
+			if shannon_entropy(keyshare) < min_entropy {
+				return Err(VerificationError::LOWENTROPYKEYSHARE)
+			}
+		}
+
+		Ok(())
+	}
+}
+
+fn is_base64(text: &str) -> bool {
+	general_purpose::STANDARD.decode(text).is_ok() ||
+		general_purpose::STANDARD_NO_PAD.decode(text).is_ok() ||
+		general_purpose::URL_SAFE.decode(text).is_ok() ||
+		general_purpose::URL_SAFE_NO_PAD.decode(text).is_ok()
+}
+
+/// Shannon entropy of the byte distribution, in bits per byte
+pub fn shannon_entropy(data: &[u8]) -> f64 {
+	if data.is_empty() {
+		return 0.0
+	}
+
+	let mut counts = [0usize; 256];
+	for byte in data {
+		counts[*byte as usize] += 1;
+	}
+
+	let len = data.len() as f64;
+	counts
+		.iter()
+		.filter(|count| **count > 0)
+		.map(|count| {
+			let p = *count as f64 / len;
+			-p * p.log2()
+		})
+		.sum()
+}
+
+/// Set the enclave keyshare policy, only once at startup
+pub fn set_keyshare_policy(policy: KeysharePolicy) -> Result<(), anyhow::Error> {
+	if policy.min_size == 0 || policy.min_size > policy.max_size {
+		return Err(anyhow::anyhow!(
+			"KEYSHARE POLICY : invalid size range [{}, {}]",
+			policy.min_size,
+			policy.max_size
+		))
+	}
+
+	KEYSHARE_POLICY
+		.set(policy)
+		.map_err(|_| anyhow::anyhow!("KEYSHARE POLICY : policy is already set"))
+}
+
+/// Effective keyshare policy, default limits if it is not configured
+pub fn keyshare_policy() -> &'static KeysharePolicy {
+	KEYSHARE_POLICY.get_or_init(KeysharePolicy::default)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn keyshare_policy_test() {
+		let policy = KeysharePolicy::default();
+		assert_eq!(policy.validate(b"thisIsTheSecretOfNft1300"), Ok(()));
+		assert_eq!(policy.validate(b""), Err(VerificationError::INVALIDKEYSHARE));
+		assert_eq!(policy.validate(b"short"), Err(VerificationError::KEYSHAREISTOOSHORT));
+		assert_eq!(policy.validate(&[b'a'; 3001]), Err(VerificationError::KEYSHAREISTOOLONG));
+		assert_eq!(
+			policy.validate(b"thisIsTheSecret\x00OfNft1300"),
+			Err(VerificationError::KEYSHARECONTROLCHAR)
+		);
+		assert_eq!(policy.validate(&[0xFF; 32]), Err(VerificationError::INVALIDKEYSHARE));
+		assert_eq!(policy.validate_binary(&[0xFF; 32]), Ok(()));
+		assert_eq!(policy.validate_binary(&[0x00; 8]), Err(VerificationError::KEYSHAREISTOOSHORT));
+
+		let base64 = KeysharePolicy { encoding: KeyshareEncoding::Base64, ..policy.clone() };
+		assert_eq!(base64.validate(b"dGhpc0lzVGhlU2VjcmV0T2ZOZnQ="), Ok(()));
+		assert_eq!(base64.validate(b"dGhpc0lzVGhlU2VjcmV0T2ZOZnQ"), Ok(()));
+		assert_eq!(
+			base64.validate(b"this is not base64 !"),
+			Err(VerificationError::INVALIDKEYSHAREENCODING)
+		);
+
+		let hex = KeysharePolicy { encoding: KeyshareEncoding::Hex, ..policy.clone() };
+		assert_eq!(hex.validate(b"0x0123456789abcdef0123"), Ok(()));
+		assert_eq!(
+			hex.validate(b"0123456789abcdef012"),
+			Err(VerificationError::INVALIDKEYSHAREENCODING)
+		);
+
+		let entropy = KeysharePolicy { min_entropy: Some(3.0), ..policy };
+		assert_eq!(
+			entropy.validate(b"aaaaaaaaaaaaaaaaaaaaaaaa"),
+			Err(VerificationError::LOWENTROPYKEYSHARE)
+		);
+		assert_eq!(entropy.validate(b"dGhpc0lzVGhlU2VjcmV0T2ZOZnQ="), Ok(()));
+
+		assert_eq!(shannon_entropy(b"aaaa"), 0.0);
+		assert_eq!(shannon_entropy(b"abab"), 1.0);
+	}
+}
@@ -0,0 +1,175 @@
+use std::{net::SocketAddr, time::Instant};
+
+use axum::{
+	extract::{ConnectInfo, State},
+	http::{Method, StatusCode},
+	response::IntoResponse,
+	Json,
+};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::{
+	chain::{
+		killswitch::{is_endpoint_allowed, MaintenanceMode},
+		quota::{quota_call, QuotaKey},
+		verify::{
+			parse_ss58_public, verify_access_simulation, RequesterType, Retryability, ReturnStatus,
+			VerificationError, VerificationStep, APICALL,
+		},
+	},
+	servers::state::{get_accountid, get_blocknumber, get_maintenance_mode, SharedState},
+};
+
+/* ---------------------------------------
+	ACCESS PRE-CHECK
+--------------------------------------- */
+
+/// Unsigned description of an intended keyshare request
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct AccessCheckRequest {
+	pub requester_address: String,
+	#[serde(default = "default_requester_type")]
+	pub requester_type: RequesterType,
+	pub call: APICALL,
+	pub nft_id: u32,
+	// Block number of the intended auth-token, not checked if missing
+	#[serde(default)]
+	pub block_number: Option<u32>,
+}
+
+fn default_requester_type() -> RequesterType {
+	RequesterType::OWNER
+}
+
+/// Check which would fail the intended request
+#[derive(Serialize, Debug, Clone)]
+pub struct AccessFailure {
+	// None if the endpoint is disabled by governance
+	pub status: Option<ReturnStatus>,
+	pub code: Option<u16>,
+	pub step: VerificationStep,
+	pub retryability: Retryability,
+	pub description: String,
+	// Enclave clock against the intended auth-token, for expiry failures
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub clock_skew: Option<serde_json::Value>,
+}
+
+impl AccessFailure {
+	fn from_error(
+		err: VerificationError,
+		request: &AccessCheckRequest,
+		enclave_account: String,
+	) -> AccessFailure {
+		let status = err.status();
+		let step = err.step();
+		let retryability = err.retryability();
+
+		// Same description as the actual endpoint
+		let (_, Json(body)) = err.express_verification_error(
+			request.call,
+			request.requester_address.clone(),
+			request.nft_id,
+			enclave_account,
+		);
+
+		AccessFailure {
+			status: Some(status),
+			code: Some(status.code()),
+			step,
+			retryability,
+			description: body["description"].as_str().unwrap_or_default().to_string(),
+			clock_skew: body.get("clock_skew").cloned(),
+		}
+	}
+
+	fn maintenance(mode: MaintenanceMode) -> AccessFailure {
+		AccessFailure {
+			status: None,
+			code: None,
+			step: VerificationStep::ENCLAVEMODE,
+			// Governance sets the mode by an onchain remark
+			retryability: Retryability::WAITONCHAIN,
+			description: format!("Enclave API is in governance {mode:?} mode, try later."),
+			clock_skew: None,
+		}
+	}
+}
+
+/// Evaluate the non-cryptographic checks of an intended request : governance mode, quota,
+/// read-only switch, auth-token period, onchain state and ownership.
+/// Wallets can disable doomed actions before asking the user for a signature. Nothing is
+/// charged nor recorded, a passed check does not guarantee that the signed request succeeds.
+/// # Arguments
+/// * `state` - SharedState
+/// * `request` - AccessCheckRequest
+/// # Returns
+/// * `Json` - whether the request would pass, and the first failed check
+#[axum::debug_handler]
+pub async fn access_check(
+	State(state): State<SharedState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
+	Json(request): Json<AccessCheckRequest>,
+) -> impl IntoResponse {
+	let enclave_account = get_accountid(&state).await;
+	let mode = get_maintenance_mode(&state).await;
+
+	let failure = if !is_endpoint_allowed(mode, &Method::POST, request.call.endpoint()) {
+		Some(AccessFailure::maintenance(mode))
+	} else {
+		match check_request(&state, addr, &request).await {
+			Ok(()) => None,
+			Err(err) => Some(AccessFailure::from_error(err, &request, enclave_account.clone())),
+		}
+	};
+
+	debug!(
+		"ACCESS CHECK : {:?} of nft_id {} by {} : {:?}",
+		request.call,
+		request.nft_id,
+		request.requester_address,
+		failure.as_ref().map(|failure| failure.step)
+	);
+
+	(
+		StatusCode::OK,
+		Json(serde_json::json!({
+			"enclave_account": enclave_account,
+			"block_number": get_blocknumber(&state).await,
+			"call": request.call,
+			"nft_id": request.nft_id,
+			"requester_address": request.requester_address,
+			"requester_type": request.requester_type,
+			"would_succeed": failure.is_none(),
+			"failure": failure,
+		})),
+	)
+}
+
+async fn check_request(
+	state: &SharedState,
+	addr: SocketAddr,
+	request: &AccessCheckRequest,
+) -> Result<(), VerificationError> {
+	if let Some(call) = quota_call(request.call.endpoint()) {
+		let mut keys = vec![QuotaKey::IP(addr.ip())];
+		if let Some(public) = parse_ss58_public(&request.requester_address) {
+			keys.push(QuotaKey::ACCOUNT(public.0));
+		}
+
+		let result = state.read().await.peek_quota(call, &keys, 1, Instant::now());
+		result.map_err(VerificationError::RATELIMITED)?;
+	}
+
+	verify_access_simulation(
+		state,
+		request.call,
+		&request.requester_address,
+		request.requester_type,
+		request.nft_id,
+		request.block_number,
+	)
+	.await
+}
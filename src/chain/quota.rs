@@ -0,0 +1,1042 @@
+use std::{
+	cell::Cell,
+	collections::{BTreeMap, HashMap},
+	net::{IpAddr, Ipv6Addr, SocketAddr},
+	sync::{Mutex, OnceLock},
+	time::{Duration, Instant},
+};
+
+use axum::{
+	body::Body,
+	extract::{ConnectInfo, State},
+	http::{header::RETRY_AFTER, HeaderValue, Request, StatusCode},
+	middleware::Next,
+	response::{IntoResponse, Response},
+	Json,
+};
+use hyper::body::HttpBody;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{debug, error, warn};
+
+use crate::{
+	chain::{
+		constants::{
+			IP_BAN_DURATION, IP_BAN_FAILURES, IP_BAN_WINDOW, IP_RATE_BURST, IP_RATE_PER_MINUTE,
+			MAX_QUOTA_BODY_SIZE, MAX_QUOTA_BUCKETS, QUOTE_GLOBAL_BURST, QUOTE_GLOBAL_PER_MINUTE,
+			QUOTE_RATE_BURST, QUOTE_RATE_PER_MINUTE,
+		},
+		verify::{BatchRetrieveData, BatchStoreData, VerificationError, APICALL},
+	},
+	servers::{
+		metrics::record_rejected_request,
+		state::{get_accountid, get_runtime_config, SharedState},
+		versioning::endpoint_path,
+	},
+};
+
+/* ---------------------------------------
+	RATE LIMITS AND QUOTAS
+--------------------------------------- */
+
+static RATE_LIMITS: OnceLock<BTreeMap<APICALL, RateLimit>> = OnceLock::new();
+
+/// Token bucket limit : `burst` requests at once, refilled by `per_minute` requests per minute
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+	pub burst: u32,
+	pub per_minute: u32,
+}
+
+impl RateLimit {
+	fn refill_per_sec(&self) -> f64 {
+		self.per_minute as f64 / 60.0
+	}
+}
+
+/// Default limits of each requester account and each ip address
+pub fn default_rate_limits() -> BTreeMap<APICALL, RateLimit> {
+	BTreeMap::from([
+		(APICALL::NFTSTORE, RateLimit { burst: 10, per_minute: 30 }),
+		(APICALL::NFTRETRIEVE, RateLimit { burst: 20, per_minute: 120 }),
+		(APICALL::CAPSULESET, RateLimit { burst: 10, per_minute: 30 }),
+		(APICALL::CAPSULERETRIEVE, RateLimit { burst: 20, per_minute: 120 }),
+	])
+}
+
+/// Override the default limits from the command line
+/// # Arguments
+/// * `overrides` - "CALL=BURST/PER_MINUTE" or "CALL=off", i.e. "NFTSTORE=5/10"
+pub fn parse_rate_limits(
+	overrides: &[String],
+) -> Result<BTreeMap<APICALL, RateLimit>, anyhow::Error> {
+	apply_rate_limits(default_rate_limits(), overrides)
+}
+
+/// Override some limits, the other calls keep their limit
+/// # Arguments
+/// * `limits` - current limits
+/// * `overrides` - "CALL=BURST/PER_MINUTE" or "CALL=off", i.e. "NFTSTORE=5/10"
+pub fn apply_rate_limits(
+	mut limits: BTreeMap<APICALL, RateLimit>,
+	overrides: &[String],
+) -> Result<BTreeMap<APICALL, RateLimit>, anyhow::Error> {
+	for item in overrides {
+		let (call, limit) = item.split_once('=').ok_or_else(|| {
+			anyhow::anyhow!("RATE LIMIT : expected CALL=BURST/PER_MINUTE : {item}")
+		})?;
+
+		let call = match call.trim().to_uppercase().as_str() {
+			"NFTSTORE" => APICALL::NFTSTORE,
+			"NFTRETRIEVE" => APICALL::NFTRETRIEVE,
+			"CAPSULESET" => APICALL::CAPSULESET,
+			"CAPSULERETRIEVE" => APICALL::CAPSULERETRIEVE,
+			_ => return Err(anyhow::anyhow!("RATE LIMIT : unsupported api call '{call}'")),
+		};
+
+		if limit.trim().eq_ignore_ascii_case("off") {
+			limits.remove(&call);
+			continue
+		}
+
+		let (burst, per_minute) = limit
+			.split_once('/')
+			.ok_or_else(|| anyhow::anyhow!("RATE LIMIT : expected BURST/PER_MINUTE : {limit}"))?;
+		let limit =
+			RateLimit { burst: burst.trim().parse()?, per_minute: per_minute.trim().parse()? };
+
+		if limit.burst == 0 || limit.per_minute == 0 {
+			return Err(anyhow::anyhow!("RATE LIMIT : {call:?} limits must be positive, or off"))
+		}
+
+		limits.insert(call, limit);
+	}
+
+	Ok(limits)
+}
+
+/// Set the rate limits, only once at startup
+pub fn set_rate_limits(limits: BTreeMap<APICALL, RateLimit>) -> Result<(), anyhow::Error> {
+	RATE_LIMITS
+		.set(limits)
+		.map_err(|_| anyhow::anyhow!("RATE LIMIT : limits are already set"))
+}
+
+/// Rate limits of the command line, default limits if they are not configured.
+/// The admin quorum may change the effective limits of the runtime configuration.
+pub fn rate_limits() -> &'static BTreeMap<APICALL, RateLimit> {
+	RATE_LIMITS.get_or_init(default_rate_limits)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum QuotaKey {
+	// Raw public key of the requester, independent of the ss58 prefix
+	ACCOUNT([u8; 32]),
+	IP(IpAddr),
+}
+
+#[derive(Debug, Clone)]
+struct TokenBucket {
+	tokens: f64,
+	updated: Instant,
+}
+
+impl TokenBucket {
+	fn full(limit: &RateLimit, now: Instant) -> TokenBucket {
+		TokenBucket { tokens: limit.burst as f64, updated: now }
+	}
+
+	fn refill(&mut self, limit: &RateLimit, now: Instant) {
+		let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+		self.tokens = (self.tokens + elapsed * limit.refill_per_sec()).min(limit.burst as f64);
+		self.updated = now;
+	}
+
+	// Seconds until `cost` tokens are available
+	fn wait_secs(&self, limit: &RateLimit, cost: f64) -> u64 {
+		if self.tokens >= cost {
+			return 0
+		}
+		((cost - self.tokens) / limit.refill_per_sec()).ceil() as u64
+	}
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct QuotaStats {
+	pub buckets: usize,
+	pub allowed: u64,
+	pub limited: u64,
+}
+
+/// Token bucket of a (api call, key) pair, for the upgrade handoff.
+/// Instants are process-local, the bucket keeps its age instead.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct QuotaBucketSnapshot {
+	pub call: APICALL,
+	pub key: QuotaKey,
+	pub tokens: f64,
+	pub age_ms: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct QuotaSnapshot {
+	pub buckets: Vec<QuotaBucketSnapshot>,
+	pub stats: QuotaStats,
+}
+
+/// Token buckets of every (api call, account) and (api call, ip) pair
+#[derive(Debug, Clone, Default)]
+pub struct QuotaLimiter {
+	buckets: HashMap<(APICALL, QuotaKey), TokenBucket>,
+	stats: QuotaStats,
+}
+
+impl QuotaLimiter {
+	/// Take `cost` tokens from all the buckets of the keys, or none of them
+	/// # Arguments
+	/// * `limits` - effective rate limits
+	/// * `call` - API call kind
+	/// * `keys` - requester account and ip
+	/// * `cost` - number of requested items, a batch counts each item
+	/// * `now` - current time
+	/// # Returns
+	/// * `Result<(), u64>` - seconds to wait before retrying if the quota is exhausted
+	pub fn check(
+		&mut self,
+		limits: &BTreeMap<APICALL, RateLimit>,
+		call: APICALL,
+		keys: &[QuotaKey],
+		cost: u32,
+		now: Instant,
+	) -> Result<(), u64> {
+		let limit = match limits.get(&call) {
+			Some(limit) => *limit,
+			None => return Ok(()),
+		};
+
+		// A batch larger than the burst waits for a full bucket
+		let cost = cost.clamp(1, limit.burst) as f64;
+
+		if self.buckets.len() + keys.len() > MAX_QUOTA_BUCKETS {
+			self.prune(limits, now, keys.len());
+		}
+
+		let mut retry_after = 0;
+		for key in keys {
+			let bucket = self
+				.buckets
+				.entry((call, key.clone()))
+				.or_insert_with(|| TokenBucket::full(&limit, now));
+			bucket.refill(&limit, now);
+			retry_after = retry_after.max(bucket.wait_secs(&limit, cost));
+		}
+
+		if retry_after > 0 {
+			self.stats.limited += 1;
+			return Err(retry_after)
+		}
+
+		for key in keys {
+			if let Some(bucket) = self.buckets.get_mut(&(call, key.clone())) {
+				bucket.tokens -= cost;
+			}
+		}
+		self.stats.allowed += 1;
+
+		Ok(())
+	}
+
+	/// Seconds to wait before `cost` tokens are available in all the buckets, nothing is taken
+	pub fn peek(
+		&self,
+		limits: &BTreeMap<APICALL, RateLimit>,
+		call: APICALL,
+		keys: &[QuotaKey],
+		cost: u32,
+		now: Instant,
+	) -> Result<(), u64> {
+		let limit = match limits.get(&call) {
+			Some(limit) => *limit,
+			None => return Ok(()),
+		};
+
+		let cost = cost.clamp(1, limit.burst) as f64;
+
+		let mut retry_after = 0;
+		for key in keys {
+			if let Some(bucket) = self.buckets.get(&(call, key.clone())) {
+				let mut bucket = bucket.clone();
+				bucket.refill(&limit, now);
+				retry_after = retry_after.max(bucket.wait_secs(&limit, cost));
+			}
+		}
+
+		match retry_after {
+			0 => Ok(()),
+			retry_after => Err(retry_after),
+		}
+	}
+
+	/// Drop the buckets which are refilled, they are equal to new ones
+	fn prune(&mut self, limits: &BTreeMap<APICALL, RateLimit>, now: Instant, reserve: usize) {
+		self.buckets.retain(|(call, _), bucket| match limits.get(call) {
+			Some(limit) => {
+				bucket.refill(limit, now);
+				bucket.tokens < limit.burst as f64
+			},
+			None => false,
+		});
+
+		// Still full of active requesters : forget the least recently used
+		while self.buckets.len() + reserve > MAX_QUOTA_BUCKETS {
+			let oldest = match self.buckets.iter().min_by_key(|(_, bucket)| bucket.updated) {
+				Some((key, _)) => key.clone(),
+				None => break,
+			};
+			self.buckets.remove(&oldest);
+		}
+	}
+
+	pub fn stats(&self) -> QuotaStats {
+		QuotaStats { buckets: self.buckets.len(), ..self.stats.clone() }
+	}
+
+	/// Buckets and counters, to be restored by another process
+	pub fn snapshot(&self, now: Instant) -> QuotaSnapshot {
+		QuotaSnapshot {
+			buckets: self
+				.buckets
+				.iter()
+				.map(|((call, key), bucket)| QuotaBucketSnapshot {
+					call: *call,
+					key: key.clone(),
+					tokens: bucket.tokens,
+					age_ms: now.saturating_duration_since(bucket.updated).as_millis() as u64,
+				})
+				.collect(),
+			stats: self.stats.clone(),
+		}
+	}
+
+	/// Limiter of a snapshot, buckets keep the tokens and the refill time they had
+	pub fn from_snapshot(snapshot: QuotaSnapshot, now: Instant) -> QuotaLimiter {
+		let buckets = snapshot
+			.buckets
+			.into_iter()
+			.take(MAX_QUOTA_BUCKETS)
+			.map(|bucket| {
+				let updated = now.checked_sub(Duration::from_millis(bucket.age_ms)).unwrap_or(now);
+				((bucket.call, bucket.key), TokenBucket { tokens: bucket.tokens, updated })
+			})
+			.collect();
+
+		QuotaLimiter { buckets, stats: snapshot.stats }
+	}
+}
+
+/// Api call kind of a rate-limited endpoint
+pub fn quota_call(path: &str) -> Option<APICALL> {
+	match path {
+		"/api/secret-nft/store-keyshare" | "/api/secret-nft/batch-store-keyshare" =>
+			Some(APICALL::NFTSTORE),
+		"/api/secret-nft/retrieve-keyshare" | "/api/secret-nft/batch-retrieve-keyshare" =>
+			Some(APICALL::NFTRETRIEVE),
+		// An update is charged as a new set
+		"/api/capsule-nft/set-keyshare" | "/api/capsule-nft/update-keyshare" =>
+			Some(APICALL::CAPSULESET),
+		"/api/capsule-nft/retrieve-keyshare" => Some(APICALL::CAPSULERETRIEVE),
+		_ => None,
+	}
+}
+
+/// Number of items of a request body, charged to the ip before the signatures are verified
+fn quota_items(body: &[u8]) -> u32 {
+	let packet: serde_json::Value = match serde_json::from_slice(body) {
+		Ok(packet) => packet,
+		// Malformed requests are rejected by the handler
+		Err(_) => return 1,
+	};
+
+	let data = packet["data"].as_str().unwrap_or_default();
+	let items = if let Ok(batch) = serde_json::from_str::<BatchStoreData>(data) {
+		batch.entries.len()
+	} else if let Ok(batch) = serde_json::from_str::<BatchRetrieveData>(data) {
+		batch.nft_ids.len()
+	} else {
+		1
+	};
+
+	items.try_into().unwrap_or(u32::MAX)
+}
+
+/// Middleware charging the ip of keyshare requests. The requester account is charged by the
+/// verification layer, once its signature is verified.
+pub async fn quota_guard(
+	State(state): State<SharedState>,
+	request: Request<Body>,
+	next: Next<Body>,
+) -> Response {
+	let call = match quota_call(&endpoint_path(&request)) {
+		Some(call) if get_runtime_config(&state).await.rate_limits.contains_key(&call) => call,
+		_ => return next.run(request).await,
+	};
+
+	let ip = match request.extensions().get::<ConnectInfo<SocketAddr>>() {
+		Some(ConnectInfo(addr)) => addr.ip(),
+		None => return next.run(request).await,
+	};
+
+	// The body is read here to find the requester, then handed over to the handler
+	let (parts, mut body) = request.into_parts();
+	let mut bytes = Vec::new();
+	while let Some(chunk) = body.data().await {
+		match chunk {
+			Ok(chunk) if bytes.len() + chunk.len() <= MAX_QUOTA_BODY_SIZE =>
+				bytes.extend_from_slice(&chunk),
+			Ok(_) =>
+				return (
+					StatusCode::PAYLOAD_TOO_LARGE,
+					Json(json!({ "description": "Request body is too large" })),
+				)
+					.into_response(),
+			Err(err) =>
+				return (
+					StatusCode::BAD_REQUEST,
+					Json(json!({ "description": format!("Error reading request body : {err}") })),
+				)
+					.into_response(),
+		}
+	}
+
+	let items = quota_items(&bytes);
+	let keys = [QuotaKey::IP(ip)];
+
+	// The requester is not verified yet, the ip keeps the normal limits. The raised store limits of
+	// a provisioned owner only apply to its account, charged once its signature is verified.
+	let result = {
+		let shared_state_write = &mut state.write().await;
+		shared_state_write.check_quota(call, &keys, items, Instant::now())
+	};
+
+	if let Err(retry_after) = result {
+		debug!("RATE LIMIT : {:?} of {:?} is limited for {} seconds", call, keys, retry_after);
+		record_rejected_request("quota");
+
+		let enclave_account = get_accountid(&state).await;
+		let mut response = VerificationError::RATELIMITED(retry_after)
+			.express_verification_error(call, ip.to_string(), 0, enclave_account)
+			.into_response();
+		response.headers_mut().insert(RETRY_AFTER, HeaderValue::from(retry_after));
+
+		return response
+	}
+
+	next.run(Request::from_parts(parts, Body::from(bytes))).await
+}
+
+/* ---------------------------------------
+	IP RATE LIMIT AND BAN LIST
+--------------------------------------- */
+
+// Every api request is charged to a token bucket of its ip address, before the body is read, on
+// top of the per-call quotas of the keyshare requests : the ip is charged by `quota_guard`, the
+// requester account by the verification, once its signature is verified. An ip address which sends
+// too many requests with invalid signatures is banned for a while :
+// - the handlers count the signature failures of the request they serve
+// - failures of an ip are counted in a window, the ip is banned once they reach the threshold
+// - a banned ip is rejected with 403 Forbidden and Retry-After until the end of its ban
+// Accounts are never banned, a requester address is not verified when the signature fails.
+
+static IP_RATE_LIMIT: OnceLock<Option<RateLimit>> = OnceLock::new();
+static BAN_POLICY: OnceLock<Option<BanPolicy>> = OnceLock::new();
+static IP_GUARD: Mutex<IpGuard> = Mutex::new(IpGuard::new());
+
+tokio::task_local! {
+	// Signature failures of the request served by the task
+	static SIGNATURE_FAILURES: Cell<u32>;
+}
+
+/// Ban of the ip addresses which send invalid signatures
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct BanPolicy {
+	// Signature failures which trigger a ban
+	pub failures: u32,
+	// Seconds during which the failures are counted
+	pub window: u64,
+	// Seconds of the ban
+	pub duration: u64,
+}
+
+fn default_ip_rate_limit() -> Option<RateLimit> {
+	Some(RateLimit { burst: IP_RATE_BURST, per_minute: IP_RATE_PER_MINUTE })
+}
+
+fn default_ban_policy() -> Option<BanPolicy> {
+	Some(BanPolicy { failures: IP_BAN_FAILURES, window: IP_BAN_WINDOW, duration: IP_BAN_DURATION })
+}
+
+/// Parse the ip rate limit from the command line
+/// # Arguments
+/// * `limit` - "BURST/PER_MINUTE" or "off"
+pub fn parse_ip_rate_limit(limit: &str) -> Result<Option<RateLimit>, anyhow::Error> {
+	if limit.trim().eq_ignore_ascii_case("off") {
+		return Ok(None)
+	}
+
+	let (burst, per_minute) = limit
+		.split_once('/')
+		.ok_or_else(|| anyhow::anyhow!("IP GUARD : expected BURST/PER_MINUTE : {limit}"))?;
+	let limit = RateLimit { burst: burst.trim().parse()?, per_minute: per_minute.trim().parse()? };
+
+	if limit.burst == 0 || limit.per_minute == 0 {
+		return Err(anyhow::anyhow!("IP GUARD : ip rate limit must be positive, or off"))
+	}
+
+	Ok(Some(limit))
+}
+
+/// Parse the ban policy from the command line
+/// # Arguments
+/// * `policy` - "FAILURES/WINDOW_SECONDS/BAN_SECONDS" or "off"
+pub fn parse_ban_policy(policy: &str) -> Result<Option<BanPolicy>, anyhow::Error> {
+	if policy.trim().eq_ignore_ascii_case("off") {
+		return Ok(None)
+	}
+
+	let parts = policy.split('/').map(|part| part.trim().parse::<u64>()).collect::<Vec<_>>();
+	let (failures, window, duration) = match parts.as_slice() {
+		[Ok(failures), Ok(window), Ok(duration)] => (*failures, *window, *duration),
+		_ =>
+			return Err(anyhow::anyhow!(
+				"IP GUARD : expected FAILURES/WINDOW_SECONDS/BAN_SECONDS : {policy}"
+			)),
+	};
+
+	if failures == 0 || window == 0 || duration == 0 {
+		return Err(anyhow::anyhow!("IP GUARD : ban policy must be positive, or off"))
+	}
+
+	Ok(Some(BanPolicy { failures: failures.try_into()?, window, duration }))
+}
+
+/// Set the ip rate limit and the ban policy, only once at startup
+pub fn set_ip_guard_config(
+	limit: Option<RateLimit>,
+	policy: Option<BanPolicy>,
+) -> Result<(), anyhow::Error> {
+	IP_RATE_LIMIT
+		.set(limit)
+		.map_err(|_| anyhow::anyhow!("IP GUARD : ip rate limit is already set"))?;
+	BAN_POLICY
+		.set(policy)
+		.map_err(|_| anyhow::anyhow!("IP GUARD : ban policy is already set"))
+}
+
+/// Effective ip rate limit, None if disabled
+pub fn ip_rate_limit() -> Option<RateLimit> {
+	*IP_RATE_LIMIT.get_or_init(default_ip_rate_limit)
+}
+
+/// Effective ban policy, None if disabled
+pub fn ban_policy() -> Option<BanPolicy> {
+	*BAN_POLICY.get_or_init(default_ban_policy)
+}
+
+/// Count a signature failure of the request being served, nothing is done outside of a request
+pub fn record_signature_failure() {
+	let _ = SIGNATURE_FAILURES.try_with(|failures| failures.set(failures.get() + 1));
+}
+
+/* ---------------------------------------
+	GUARD
+--------------------------------------- */
+
+/// Rejection of a request by the ip guard, with the seconds before retrying
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IpRejection {
+	RATELIMITED(u64),
+	BANNED(u64),
+}
+
+impl IpRejection {
+	fn reason(&self) -> &'static str {
+		match self {
+			IpRejection::RATELIMITED(_) => "ip_rate_limit",
+			IpRejection::BANNED(_) => "ip_ban",
+		}
+	}
+}
+
+impl IntoResponse for IpRejection {
+	fn into_response(self) -> Response {
+		let (status, retry_after, description) = match self {
+			IpRejection::RATELIMITED(retry_after) => (
+				StatusCode::TOO_MANY_REQUESTS,
+				retry_after,
+				format!("Too many requests from this ip address, retry after {retry_after} seconds"),
+			),
+			IpRejection::BANNED(retry_after) => (
+				StatusCode::FORBIDDEN,
+				retry_after,
+				format!("Ip address is banned after repeated signature failures, retry after {retry_after} seconds"),
+			),
+		};
+
+		let mut response = (status, Json(json!({ "description": description }))).into_response();
+		response.headers_mut().insert(RETRY_AFTER, HeaderValue::from(retry_after));
+		response
+	}
+}
+
+#[derive(Debug, Clone)]
+struct FailureRecord {
+	count: u32,
+	since: Instant,
+	banned_until: Option<Instant>,
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct IpGuardStats {
+	pub buckets: usize,
+	pub banned: usize,
+	pub rate_limited: u64,
+	pub ban_rejected: u64,
+	pub bans: u64,
+}
+
+/// Token buckets and signature failures of the ip addresses
+#[derive(Debug)]
+pub struct IpGuard {
+	buckets: BTreeMap<IpAddr, TokenBucket>,
+	failures: BTreeMap<IpAddr, FailureRecord>,
+	stats: IpGuardStats,
+}
+
+impl IpGuard {
+	const fn new() -> IpGuard {
+		IpGuard {
+			buckets: BTreeMap::new(),
+			failures: BTreeMap::new(),
+			stats: IpGuardStats {
+				buckets: 0,
+				banned: 0,
+				rate_limited: 0,
+				ban_rejected: 0,
+				bans: 0,
+			},
+		}
+	}
+
+	/// Reject a banned ip, or take a token of its bucket
+	/// # Arguments
+	/// * `ip` - ip address of the request
+	/// * `limit` - ip rate limit, None if disabled
+	/// * `now` - current time
+	pub fn check(
+		&mut self,
+		ip: IpAddr,
+		limit: Option<RateLimit>,
+		now: Instant,
+	) -> Result<(), IpRejection> {
+		if let Some(banned_until) = self.failures.get(&ip).and_then(|record| record.banned_until) {
+			if banned_until > now {
+				self.stats.ban_rejected += 1;
+				let remaining = banned_until.saturating_duration_since(now).as_secs_f64().ceil();
+				return Err(IpRejection::BANNED(remaining as u64))
+			}
+			self.failures.remove(&ip);
+		}
+
+		let limit = match limit {
+			Some(limit) => limit,
+			None => return Ok(()),
+		};
+
+		if self.buckets.len() >= MAX_QUOTA_BUCKETS {
+			self.prune(&limit, now);
+		}
+
+		let bucket = self.buckets.entry(ip).or_insert_with(|| TokenBucket::full(&limit, now));
+		bucket.refill(&limit, now);
+
+		match bucket.wait_secs(&limit, 1.0) {
+			0 => {
+				bucket.tokens -= 1.0;
+				Ok(())
+			},
+			retry_after => {
+				self.stats.rate_limited += 1;
+				Err(IpRejection::RATELIMITED(retry_after))
+			},
+		}
+	}
+
+	/// Count the signature failures of a request
+	/// # Returns
+	/// * `bool` - true if the ip is banned by these failures
+	pub fn record_failures(
+		&mut self,
+		ip: IpAddr,
+		failures: u32,
+		policy: &BanPolicy,
+		now: Instant,
+	) -> bool {
+		if self.failures.len() >= MAX_QUOTA_BUCKETS {
+			self.failures.retain(|_, record| match record.banned_until {
+				Some(banned_until) => banned_until > now,
+				None =>
+					now.saturating_duration_since(record.since) <=
+						Duration::from_secs(policy.window),
+			});
+		}
+
+		let record = self.failures.entry(ip).or_insert(FailureRecord {
+			count: 0,
+			since: now,
+			banned_until: None,
+		});
+
+		if record.banned_until.is_some() {
+			return false
+		}
+
+		if now.saturating_duration_since(record.since) > Duration::from_secs(policy.window) {
+			record.count = 0;
+			record.since = now;
+		}
+
+		record.count = record.count.saturating_add(failures);
+		if record.count < policy.failures {
+			return false
+		}
+
+		record.banned_until = Some(now + Duration::from_secs(policy.duration));
+		self.stats.bans += 1;
+		true
+	}
+
+	/// Drop the refilled buckets and the expired failures, then the least recently used buckets
+	fn prune(&mut self, limit: &RateLimit, now: Instant) {
+		self.buckets.retain(|_, bucket| {
+			bucket.refill(limit, now);
+			bucket.tokens < limit.burst as f64
+		});
+
+		let window = ban_policy().map_or(0, |policy| policy.window);
+		self.failures.retain(|_, record| match record.banned_until {
+			Some(banned_until) => banned_until > now,
+			None => now.saturating_duration_since(record.since) <= Duration::from_secs(window),
+		});
+
+		while self.buckets.len() >= MAX_QUOTA_BUCKETS {
+			let oldest = match self.buckets.iter().min_by_key(|(_, bucket)| bucket.updated) {
+				Some((ip, _)) => *ip,
+				None => break,
+			};
+			self.buckets.remove(&oldest);
+		}
+	}
+
+	pub fn stats(&self, now: Instant) -> IpGuardStats {
+		IpGuardStats {
+			buckets: self.buckets.len(),
+			banned: self
+				.failures
+				.values()
+				.filter(|record| record.banned_until.map_or(false, |until| until > now))
+				.count(),
+			..self.stats.clone()
+		}
+	}
+}
+
+fn update_ip_guard<T>(update: impl FnOnce(&mut IpGuard) -> T) -> Option<T> {
+	match IP_GUARD.lock() {
+		Ok(mut guard) => Some(update(&mut guard)),
+		Err(err) => {
+			error!("IP GUARD : lock error : {err:?}");
+			None
+		},
+	}
+}
+
+/// Buckets, banned ip addresses and rejected requests
+pub fn ip_guard_stats() -> IpGuardStats {
+	update_ip_guard(|guard| guard.stats(Instant::now())).unwrap_or_default()
+}
+
+/// Middleware rejecting the banned ip addresses and charging the others, then counting the
+/// signature failures of the request
+pub async fn ip_guard(request: Request<Body>, next: Next<Body>) -> Response {
+	let ip = match request.extensions().get::<ConnectInfo<SocketAddr>>() {
+		Some(ConnectInfo(addr)) => addr.ip(),
+		None => return next.run(request).await,
+	};
+
+	let now = Instant::now();
+	if let Some(Err(rejection)) = update_ip_guard(|guard| guard.check(ip, ip_rate_limit(), now)) {
+		debug!("IP GUARD : {ip} is rejected : {rejection:?}");
+		record_rejected_request(rejection.reason());
+		return rejection.into_response()
+	}
+
+	let (response, failures) = SIGNATURE_FAILURES
+		.scope(Cell::new(0), async {
+			let response = next.run(request).await;
+			(response, SIGNATURE_FAILURES.with(|failures| failures.get()))
+		})
+		.await;
+
+	if let (true, Some(policy)) = (failures > 0, ban_policy()) {
+		let banned = update_ip_guard(|guard| guard.record_failures(ip, failures, &policy, now));
+		if banned == Some(true) {
+			warn!(
+				"IP GUARD : {ip} is banned for {} seconds after {} signature failures",
+				policy.duration, policy.failures
+			);
+		}
+	}
+
+	response
+}
+
+/* ---------------------------------------
+	QUOTE RATE LIMIT
+--------------------------------------- */
+
+// A quote bound to the nonce of an unauthenticated caller can not be cached, each one is generated
+// by the quoting enclave. These quotes are charged to a bucket of the caller ip, then to a bucket
+// shared by every caller, so that neither one client nor many of them exhaust the quoting enclave.
+
+static QUOTE_GUARD: Mutex<IpGuard> = Mutex::new(IpGuard::new());
+
+// Key of the bucket shared by every caller
+const QUOTE_GLOBAL_KEY: IpAddr = IpAddr::V6(Ipv6Addr::UNSPECIFIED);
+
+/// Take a quote token of the caller, then of every caller
+/// # Arguments
+/// * `guard` - buckets of the on-demand quotes
+/// * `ip` - ip address of the caller, None if it is not known
+/// * `now` - current time
+fn charge_quote(guard: &mut IpGuard, ip: Option<IpAddr>, now: Instant) -> Result<(), IpRejection> {
+	if let Some(ip) = ip {
+		let limit = RateLimit { burst: QUOTE_RATE_BURST, per_minute: QUOTE_RATE_PER_MINUTE };
+		guard.check(ip, Some(limit), now)?;
+	}
+
+	let limit = RateLimit { burst: QUOTE_GLOBAL_BURST, per_minute: QUOTE_GLOBAL_PER_MINUTE };
+	guard.check(QUOTE_GLOBAL_KEY, Some(limit), now)
+}
+
+/// Charge an on-demand quote of an unauthenticated caller
+/// # Arguments
+/// * `ip` - ip address of the caller, None if it is not known
+pub fn check_quote_rate(ip: Option<IpAddr>) -> Result<(), IpRejection> {
+	match QUOTE_GUARD.lock() {
+		Ok(mut guard) => charge_quote(&mut guard, ip, Instant::now()),
+		Err(err) => {
+			error!("QUOTE RATE LIMIT : lock error : {err:?}");
+			Ok(())
+		},
+	}
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use std::time::Duration;
+
+	#[test]
+	fn token_bucket_test() {
+		let limits = default_rate_limits();
+		let mut limiter = QuotaLimiter::default();
+		let start = Instant::now();
+		let account = QuotaKey::ACCOUNT([1u8; 32]);
+		let ip = QuotaKey::IP("10.0.0.1".parse().unwrap());
+
+		// Burst of 10 stores, then 30 per minute
+		for _ in 0..10 {
+			assert_eq!(
+				limiter.check(&limits, APICALL::NFTSTORE, &[account.clone(), ip.clone()], 1, start),
+				Ok(())
+			);
+		}
+		assert_eq!(limiter.check(&limits, APICALL::NFTSTORE, &[account.clone()], 1, start), Err(2));
+
+		// Peeking takes no token
+		let stats = limiter.stats();
+		assert_eq!(limiter.peek(&limits, APICALL::NFTSTORE, &[account.clone()], 1, start), Err(2));
+		assert_eq!(
+			limiter.peek(&limits, APICALL::NFTSTORE, &[QuotaKey::ACCOUNT([3u8; 32])], 1, start),
+			Ok(())
+		);
+		assert_eq!(limiter.stats(), stats);
+
+		// Another account behind the same ip is limited too
+		let other = QuotaKey::ACCOUNT([2u8; 32]);
+		assert_eq!(
+			limiter.check(&limits, APICALL::NFTSTORE, &[other.clone(), ip.clone()], 1, start),
+			Err(2)
+		);
+		assert_eq!(limiter.check(&limits, APICALL::NFTSTORE, &[other], 1, start), Ok(()));
+
+		// Buckets are separate per api call
+		assert_eq!(
+			limiter.check(&limits, APICALL::NFTRETRIEVE, &[account.clone()], 1, start),
+			Ok(())
+		);
+
+		// Refilled
+		let later = start + Duration::from_secs(2);
+		assert_eq!(limiter.check(&limits, APICALL::NFTSTORE, &[account.clone()], 1, later), Ok(()));
+
+		// Batch items are charged, up to the burst
+		let much_later = start + Duration::from_secs(3600);
+		assert_eq!(
+			limiter.check(&limits, APICALL::NFTSTORE, &[account.clone()], 100, much_later),
+			Ok(())
+		);
+		assert_eq!(limiter.check(&limits, APICALL::NFTSTORE, &[account], 1, much_later), Err(2));
+
+		// Unlimited api call
+		assert_eq!(limiter.check(&limits, APICALL::NFTREMOVE, &[ip], 1000, start), Ok(()));
+
+		let stats = limiter.stats();
+		assert_eq!(stats.limited, 3);
+	}
+
+	#[test]
+	fn quota_snapshot_test() {
+		let limits = default_rate_limits();
+		let mut limiter = QuotaLimiter::default();
+		let start = Instant::now();
+		let account = QuotaKey::ACCOUNT([3u8; 32]);
+
+		for _ in 0..10 {
+			assert_eq!(
+				limiter.check(&limits, APICALL::NFTSTORE, &[account.clone()], 1, start),
+				Ok(())
+			);
+		}
+
+		// Serialized by the old instance, restored by the new one later
+		let snapshot = serde_json::to_string(&limiter.snapshot(start)).unwrap();
+		let restored_at = start + Duration::from_millis(500);
+		let mut restored =
+			QuotaLimiter::from_snapshot(serde_json::from_str(&snapshot).unwrap(), restored_at);
+
+		assert_eq!(restored.stats(), limiter.stats());
+		assert_eq!(
+			restored.check(&limits, APICALL::NFTSTORE, &[account.clone()], 1, restored_at),
+			Err(2)
+		);
+		assert_eq!(
+			restored.check(
+				&limits,
+				APICALL::NFTSTORE,
+				&[account],
+				1,
+				restored_at + Duration::from_secs(2)
+			),
+			Ok(())
+		);
+	}
+
+	#[test]
+	fn rate_limit_config_test() {
+		let limits =
+			parse_rate_limits(&["nftstore=5/10".to_string(), "CAPSULESET=off".to_string()])
+				.unwrap();
+		assert_eq!(limits[&APICALL::NFTSTORE], RateLimit { burst: 5, per_minute: 10 });
+		assert!(!limits.contains_key(&APICALL::CAPSULESET));
+		assert_eq!(limits[&APICALL::NFTRETRIEVE], default_rate_limits()[&APICALL::NFTRETRIEVE]);
+
+		assert!(parse_rate_limits(&["NFTREMOVE=1/1".to_string()]).is_err());
+		assert!(parse_rate_limits(&["NFTSTORE=0/1".to_string()]).is_err());
+		assert!(parse_rate_limits(&["NFTSTORE=5".to_string()]).is_err());
+
+		assert_eq!(quota_call("/api/secret-nft/batch-store-keyshare"), Some(APICALL::NFTSTORE));
+		assert_eq!(quota_call("/api/secret-nft/remove-keyshare"), None);
+	}
+
+	#[test]
+	fn ip_guard_test() {
+		let mut guard = IpGuard::new();
+		let start = Instant::now();
+		let ip: IpAddr = "10.0.0.1".parse().unwrap();
+		let limit = Some(RateLimit { burst: 2, per_minute: 60 });
+
+		assert_eq!(guard.check(ip, limit, start), Ok(()));
+		assert_eq!(guard.check(ip, limit, start), Ok(()));
+		assert_eq!(guard.check(ip, limit, start), Err(IpRejection::RATELIMITED(1)));
+		assert_eq!(guard.check(ip, None, start), Ok(()));
+		assert_eq!(guard.check(ip, limit, start + Duration::from_secs(1)), Ok(()));
+
+		// Failures out of the window are forgotten
+		let policy = BanPolicy { failures: 3, window: 60, duration: 600 };
+		assert!(!guard.record_failures(ip, 2, &policy, start));
+		assert!(!guard.record_failures(ip, 2, &policy, start + Duration::from_secs(61)));
+		assert!(guard.record_failures(ip, 1, &policy, start + Duration::from_secs(62)));
+
+		let banned = start + Duration::from_secs(100);
+		assert_eq!(guard.check(ip, None, banned), Err(IpRejection::BANNED(562)));
+		assert_eq!(guard.stats(banned).banned, 1);
+		assert_eq!(guard.check(ip, None, start + Duration::from_secs(700)), Ok(()));
+		assert_eq!(guard.stats(start + Duration::from_secs(700)).bans, 1);
+
+		assert_eq!(
+			parse_ip_rate_limit("20/120").unwrap(),
+			Some(RateLimit { burst: 20, per_minute: 120 })
+		);
+		assert_eq!(parse_ip_rate_limit("off").unwrap(), None);
+		assert!(parse_ip_rate_limit("0/10").is_err());
+		assert_eq!(
+			parse_ban_policy("5/60/300").unwrap(),
+			Some(BanPolicy { failures: 5, window: 60, duration: 300 })
+		);
+		assert!(parse_ban_policy("5/60").is_err());
+	}
+
+	#[test]
+	fn quote_rate_test() {
+		let mut guard = IpGuard::new();
+		let start = Instant::now();
+		let ip: IpAddr = "10.0.0.1".parse().unwrap();
+
+		for _ in 0..QUOTE_RATE_BURST {
+			assert_eq!(charge_quote(&mut guard, Some(ip), start), Ok(()));
+		}
+		assert!(charge_quote(&mut guard, Some(ip), start).is_err());
+
+		// Other callers share the global bucket
+		let mut charged = QUOTE_RATE_BURST;
+		for index in 0..u8::MAX {
+			let other = IpAddr::from([10, 0, 1, index]);
+			if charge_quote(&mut guard, Some(other), start).is_err() {
+				break
+			}
+			charged += 1;
+		}
+		assert_eq!(charged, QUOTE_GLOBAL_BURST);
+		assert!(charge_quote(&mut guard, None, start).is_err());
+	}
+
+	#[tokio::test]
+	async fn signature_failure_test() {
+		// Outside of a request, failures are ignored
+		record_signature_failure();
+
+		let failures = SIGNATURE_FAILURES
+			.scope(Cell::new(0), async {
+				record_signature_failure();
+				record_signature_failure();
+				SIGNATURE_FAILURES.with(|failures| failures.get())
+			})
+			.await;
+		assert_eq!(failures, 2);
+	}
+}
@@ -0,0 +1,281 @@
+use std::time::Instant;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use subxt::{ext::sp_core::sr25519, utils::AccountId32};
+
+use crate::{
+	backup::{
+		provision::{is_expected_owner, is_provisioned_owner},
+		readonly,
+	},
+	chain::{
+		core::get_onchain_rent_terms,
+		cosign::cosign_policy,
+		negative_cache::{cached_lookup, remember_lookup},
+		quota::QuotaKey,
+		rental::paid_until,
+		verify::{
+			get_onchain_delegatee_account, get_onchain_rentee_account, get_verified_nft_data,
+			KeyshareHolder, VerificationError, APICALL,
+		},
+	},
+	servers::{
+		metrics::record_rejected_request,
+		state::{get_blocknumber, SharedState},
+	},
+};
+
+/* ---------------------------------------
+	CHAIN READER ABSTRACTION
+--------------------------------------- */
+
+/// The part of onchain nft/capsule data which is used by request verification
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OnchainNft {
+	pub owner: AccountId32,
+	pub is_secret: bool,
+	pub is_syncing_secret: bool,
+	pub is_capsule: bool,
+	pub is_syncing_capsule: bool,
+	#[serde(default)]
+	pub collection_id: Option<u32>,
+}
+
+/// Onchain queries and enclave state needed by the verification layer.
+/// The enclave uses the SharedState implementation, tests use an in-memory mock.
+#[async_trait]
+pub trait ChainReader: Send + Sync {
+	/// Latest block number known by the enclave
+	async fn current_block_number(&self) -> u32;
+
+	/// Nft/Capsule data, INVALIDNFTID if it does not exist
+	async fn nft_data(&self, nft_id: u32) -> Result<OnchainNft, VerificationError>;
+
+	/// Delegatee of the nft/capsule
+	async fn delegatee(&self, nft_id: u32) -> KeyshareHolder;
+
+	/// Rentee of the nft/capsule
+	async fn rentee(&self, nft_id: u32) -> KeyshareHolder;
+
+	/// Last block of the paid period of the rent contract, None if it does not end
+	async fn rental_end(&self, nft_id: u32) -> Option<u32>;
+
+	/// Expiry block of the admin read-only switch, None if mutations are allowed
+	async fn read_only_until(&self) -> Option<u32>;
+
+	/// Designated co-signer of a high-value nft, None if a single signature is enough
+	async fn cosigner(&self, nft_id: u32, collection_id: Option<u32>) -> Option<sr25519::Public>;
+
+	/// Take the rate limit tokens of a requester account, once its signature is verified
+	/// # Returns
+	/// * `Result<(), u64>` - seconds to wait before retrying if the quota is exhausted
+	async fn charge_quota(
+		&self,
+		call: APICALL,
+		account: &sr25519::Public,
+		items: u32,
+	) -> Result<(), u64>;
+
+	/// False if an active provisioning window of the nft expects other owners
+	async fn is_expected_owner(&self, _nft_id: u32, _owner: &sr25519::Public) -> bool {
+		true
+	}
+}
+
+#[async_trait]
+impl ChainReader for SharedState {
+	async fn current_block_number(&self) -> u32 {
+		get_blocknumber(self).await
+	}
+
+	async fn nft_data(&self, nft_id: u32) -> Result<OnchainNft, VerificationError> {
+		if let Some(result) = cached_lookup(self, nft_id).await {
+			return result
+		}
+
+		let result = get_verified_nft_data(self, nft_id).await.map(|nft_data| OnchainNft {
+			owner: nft_data.owner,
+			is_secret: nft_data.state.is_secret,
+			is_syncing_secret: nft_data.state.is_syncing_secret,
+			is_capsule: nft_data.state.is_capsule,
+			is_syncing_capsule: nft_data.state.is_syncing_capsule,
+			collection_id: nft_data.collection_id,
+		});
+
+		remember_lookup(self, nft_id, &result).await;
+
+		result
+	}
+
+	async fn delegatee(&self, nft_id: u32) -> KeyshareHolder {
+		get_onchain_delegatee_account(self, nft_id).await
+	}
+
+	async fn rentee(&self, nft_id: u32) -> KeyshareHolder {
+		get_onchain_rentee_account(self, nft_id).await
+	}
+
+	async fn rental_end(&self, nft_id: u32) -> Option<u32> {
+		let (start_block, duration) = get_onchain_rent_terms(self, nft_id).await?;
+		paid_until(start_block, duration, get_blocknumber(self).await)
+	}
+
+	async fn read_only_until(&self) -> Option<u32> {
+		readonly::read_only_until(self).await
+	}
+
+	async fn cosigner(&self, nft_id: u32, collection_id: Option<u32>) -> Option<sr25519::Public> {
+		cosign_policy().cosigner(nft_id, collection_id)
+	}
+
+	async fn charge_quota(
+		&self,
+		call: APICALL,
+		account: &sr25519::Public,
+		items: u32,
+	) -> Result<(), u64> {
+		let keys = [QuotaKey::ACCOUNT(account.0)];
+		let result = if is_provisioned_owner(self, &account.to_string()).await {
+			self.write().await.check_provisioned_quota(call, &keys, items, Instant::now())
+		} else {
+			self.write().await.check_quota(call, &keys, items, Instant::now())
+		};
+		if result.is_err() {
+			record_rejected_request("quota");
+		}
+		result
+	}
+
+	async fn is_expected_owner(&self, nft_id: u32, owner: &sr25519::Public) -> bool {
+		is_expected_owner(self, nft_id, &owner.to_string()).await
+	}
+}
+
+/* ---------------------------------------
+	IN-MEMORY MOCK
+--------------------------------------- */
+
+#[cfg(test)]
+pub mod mock {
+	use std::collections::BTreeMap;
+
+	use super::*;
+
+	/// Deterministic chain for offline tests
+	#[derive(Default)]
+	pub struct MockChain {
+		pub block_number: u32,
+		pub nfts: BTreeMap<u32, OnchainNft>,
+		pub delegatees: BTreeMap<u32, AccountId32>,
+		pub rentees: BTreeMap<u32, AccountId32>,
+		pub rental_ends: BTreeMap<u32, u32>,
+		pub read_only_until: Option<u32>,
+		pub cosigners: BTreeMap<u32, sr25519::Public>,
+		// Seconds to wait of an exhausted account quota, None if requests are allowed
+		pub rate_limited: Option<u64>,
+	}
+
+	impl MockChain {
+		pub fn new(block_number: u32) -> MockChain {
+			MockChain { block_number, ..Default::default() }
+		}
+
+		/// Add a secret-nft, syncing or already synced
+		pub fn with_secret_nft(mut self, nft_id: u32, owner: AccountId32, syncing: bool) -> Self {
+			self.nfts.insert(
+				nft_id,
+				OnchainNft {
+					owner,
+					is_secret: true,
+					is_syncing_secret: syncing,
+					is_capsule: false,
+					is_syncing_capsule: false,
+					collection_id: None,
+				},
+			);
+			self
+		}
+
+		/// Add a capsule, syncing or already synced
+		pub fn with_capsule(mut self, nft_id: u32, owner: AccountId32, syncing: bool) -> Self {
+			self.nfts.insert(
+				nft_id,
+				OnchainNft {
+					owner,
+					is_secret: false,
+					is_syncing_secret: false,
+					is_capsule: true,
+					is_syncing_capsule: syncing,
+					collection_id: None,
+				},
+			);
+			self
+		}
+
+		/// Rent an nft, until the end of its paid period if any
+		pub fn with_rental(mut self, nft_id: u32, rentee: AccountId32, end: Option<u32>) -> Self {
+			self.rentees.insert(nft_id, rentee);
+			if let Some(end) = end {
+				self.rental_ends.insert(nft_id, end);
+			}
+			self
+		}
+
+		/// Flag an nft as high-value with its co-signer
+		pub fn with_cosigner(mut self, nft_id: u32, cosigner: sr25519::Public) -> Self {
+			self.cosigners.insert(nft_id, cosigner);
+			self
+		}
+	}
+
+	#[async_trait]
+	impl ChainReader for MockChain {
+		async fn current_block_number(&self) -> u32 {
+			self.block_number
+		}
+
+		async fn nft_data(&self, nft_id: u32) -> Result<OnchainNft, VerificationError> {
+			self.nfts.get(&nft_id).cloned().ok_or(VerificationError::INVALIDNFTID)
+		}
+
+		async fn delegatee(&self, nft_id: u32) -> KeyshareHolder {
+			match self.delegatees.get(&nft_id) {
+				Some(account) => KeyshareHolder::Delegatee(account.clone()),
+				None => KeyshareHolder::NotFound,
+			}
+		}
+
+		async fn rentee(&self, nft_id: u32) -> KeyshareHolder {
+			match self.rentees.get(&nft_id) {
+				Some(account) => KeyshareHolder::Rentee(account.clone()),
+				None => KeyshareHolder::NotFound,
+			}
+		}
+
+		async fn rental_end(&self, nft_id: u32) -> Option<u32> {
+			self.rental_ends.get(&nft_id).copied()
+		}
+
+		async fn read_only_until(&self) -> Option<u32> {
+			self.read_only_until
+		}
+
+		async fn cosigner(
+			&self,
+			nft_id: u32,
+			_collection_id: Option<u32>,
+		) -> Option<sr25519::Public> {
+			self.cosigners.get(&nft_id).copied()
+		}
+
+		async fn charge_quota(
+			&self,
+			_call: APICALL,
+			_account: &sr25519::Public,
+			_items: u32,
+		) -> Result<(), u64> {
+			self.rate_limited.map_or(Ok(()), Err)
+		}
+	}
+}
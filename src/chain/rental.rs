@@ -0,0 +1,203 @@
+use std::{
+	collections::BTreeMap,
+	sync::{Mutex, OnceLock},
+};
+
+use serde::Serialize;
+use subxt::utils::AccountId32;
+
+use crate::chain::{
+	constants::MAX_RENTAL_GRACE_PERIOD,
+	reader::ChainReader,
+	verify::{KeyshareHolder, VerificationError},
+};
+
+/* ---------------------------------------
+	RENTAL GRACE PERIOD
+--------------------------------------- */
+
+static RENTAL_GRACE_PERIOD: OnceLock<u32> = OnceLock::new();
+
+// Last paid period seen for each rented nft, the rent pallet removes a lapsed contract so the
+// rentee of the grace period is only known by the enclave. It is not persisted, a restarted
+// enclave only grants the grace period to rentals it has seen since.
+static LAST_RENTALS: Mutex<BTreeMap<u32, (AccountId32, u32)>> = Mutex::new(BTreeMap::new());
+
+/// Duration terms of a rent contract
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum RentDuration {
+	FIXED(u32),
+	// Renewed every period until the optional maximum duration
+	SUBSCRIPTION { period: u32, max_duration: Option<u32> },
+	INFINITE,
+}
+
+/// Access of a rentee to the keyshare
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum RentalAccess {
+	ACTIVE,
+	// Rental has lapsed, read-only retrieval is allowed until the block
+	GRACE(u32),
+	DENIED,
+}
+
+/// Last block of the paid period of a started rent contract
+/// # Arguments
+/// * `start_block` - block of the contract start
+/// * `duration` - duration terms of the contract
+/// * `current_block` - current block number
+/// # Returns
+/// * `Option<u32>` - None if the rental does not end
+pub fn paid_until(start_block: u32, duration: RentDuration, current_block: u32) -> Option<u32> {
+	match duration {
+		RentDuration::FIXED(blocks) => Some(start_block.saturating_add(blocks)),
+		RentDuration::SUBSCRIPTION { period: 0, .. } => None,
+		RentDuration::SUBSCRIPTION { period, max_duration } => {
+			let periods = current_block.saturating_sub(start_block) / period + 1;
+			let period_end = start_block.saturating_add(periods.saturating_mul(period));
+			match max_duration {
+				Some(max_duration) =>
+					Some(period_end.min(start_block.saturating_add(max_duration))),
+				None => Some(period_end),
+			}
+		},
+		RentDuration::INFINITE => None,
+	}
+}
+
+/// Set the grace period of lapsed rentals in blocks, only once at startup
+pub fn set_rental_grace_period(blocks: u32) -> Result<(), anyhow::Error> {
+	if blocks > MAX_RENTAL_GRACE_PERIOD {
+		return Err(anyhow::anyhow!(
+			"RENTAL : grace period {blocks} is longer than {MAX_RENTAL_GRACE_PERIOD} blocks"
+		))
+	}
+
+	RENTAL_GRACE_PERIOD
+		.set(blocks)
+		.map_err(|_| anyhow::anyhow!("RENTAL : grace period is already set"))
+}
+
+/// Grace period of lapsed rentals in blocks, no grace period if it is not configured
+pub fn rental_grace_period() -> u32 {
+	*RENTAL_GRACE_PERIOD.get_or_init(|| 0)
+}
+
+fn remember_rental(nft_id: u32, rentee: &AccountId32, end_block: u32) {
+	if let Ok(mut rentals) = LAST_RENTALS.lock() {
+		rentals.insert(nft_id, (rentee.clone(), end_block));
+	}
+}
+
+fn last_rental_end(nft_id: u32, requester: &AccountId32) -> Option<u32> {
+	let rentals = LAST_RENTALS.lock().ok()?;
+	match rentals.get(&nft_id) {
+		Some((rentee, end_block)) if rentee == requester => Some(*end_block),
+		_ => None,
+	}
+}
+
+fn grace_access(end_block: u32, current_block: u32, grace_period: u32) -> RentalAccess {
+	let grace_end = end_block.saturating_add(grace_period);
+	if current_block <= grace_end {
+		RentalAccess::GRACE(grace_end)
+	} else {
+		RentalAccess::DENIED
+	}
+}
+
+/// Access of a requester to the keyshare of a rented nft
+/// # Arguments
+/// * `chain` - onchain data reader
+/// * `requester` - normalized requester account
+/// * `nft_id` - nft/capsule id
+/// # Errors
+/// * `ORACLEFAILURE` - if primary and secondary rpc disagree in dual-rpc mode
+pub async fn rental_access(
+	chain: &dyn ChainReader,
+	requester: &AccountId32,
+	nft_id: u32,
+) -> Result<RentalAccess, VerificationError> {
+	check_rental(chain, requester, nft_id, rental_grace_period()).await
+}
+
+async fn check_rental(
+	chain: &dyn ChainReader,
+	requester: &AccountId32,
+	nft_id: u32,
+	grace_period: u32,
+) -> Result<RentalAccess, VerificationError> {
+	let current_block = chain.current_block_number().await;
+
+	match chain.rentee(nft_id).await {
+		KeyshareHolder::Rentee(rentee) if rentee == *requester =>
+			match chain.rental_end(nft_id).await {
+				Some(end_block) => {
+					remember_rental(nft_id, &rentee, end_block);
+					if current_block <= end_block {
+						Ok(RentalAccess::ACTIVE)
+					} else {
+						// Lapsed contract which is not yet removed by the rent pallet
+						Ok(grace_access(end_block, current_block, grace_period))
+					}
+				},
+				None => Ok(RentalAccess::ACTIVE),
+			},
+		KeyshareHolder::Diverged => Err(VerificationError::ORACLEFAILURE),
+		// Contract is removed or rented by someone else
+		_ => match last_rental_end(nft_id, requester) {
+			Some(end_block) => Ok(grace_access(end_block, current_block, grace_period)),
+			None => Ok(RentalAccess::DENIED),
+		},
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::chain::reader::mock::MockChain;
+
+	#[test]
+	fn paid_until_test() {
+		assert_eq!(paid_until(100, RentDuration::FIXED(50), 120), Some(150));
+		assert_eq!(paid_until(100, RentDuration::INFINITE, 120), None);
+
+		let monthly = RentDuration::SUBSCRIPTION { period: 10, max_duration: None };
+		assert_eq!(paid_until(100, monthly, 100), Some(110));
+		assert_eq!(paid_until(100, monthly, 125), Some(130));
+
+		let capped = RentDuration::SUBSCRIPTION { period: 10, max_duration: Some(25) };
+		assert_eq!(paid_until(100, capped, 121), Some(125));
+		assert_eq!(
+			paid_until(100, RentDuration::SUBSCRIPTION { period: 0, max_duration: None }, 1),
+			None
+		);
+	}
+
+	#[tokio::test]
+	async fn rental_access_test() {
+		let rentee = AccountId32([31u8; 32]);
+		let stranger = AccountId32([32u8; 32]);
+
+		let mut chain = MockChain::new(1000).with_rental(2100, rentee.clone(), Some(1005));
+		assert_eq!(check_rental(&chain, &rentee, 2100, 10).await, Ok(RentalAccess::ACTIVE));
+		assert_eq!(check_rental(&chain, &stranger, 2100, 10).await, Ok(RentalAccess::DENIED));
+
+		// Lapsed contract, not yet removed
+		chain.block_number = 1010;
+		assert_eq!(check_rental(&chain, &rentee, 2100, 10).await, Ok(RentalAccess::GRACE(1015)));
+		assert_eq!(check_rental(&chain, &rentee, 2100, 0).await, Ok(RentalAccess::DENIED));
+
+		// Removed contract, the rentee of the last paid period is remembered
+		chain.rentees.remove(&2100);
+		assert_eq!(check_rental(&chain, &rentee, 2100, 10).await, Ok(RentalAccess::GRACE(1015)));
+		assert_eq!(check_rental(&chain, &stranger, 2100, 10).await, Ok(RentalAccess::DENIED));
+
+		chain.block_number = 1016;
+		assert_eq!(check_rental(&chain, &rentee, 2100, 10).await, Ok(RentalAccess::DENIED));
+
+		// Rentals without end are never lapsed
+		let chain = MockChain::new(1000).with_rental(2101, rentee.clone(), None);
+		assert_eq!(check_rental(&chain, &rentee, 2101, 10).await, Ok(RentalAccess::ACTIVE));
+	}
+}
@@ -0,0 +1,285 @@
+use std::{
+	collections::BTreeMap,
+	fs::{File, OpenOptions},
+	io::Write,
+	path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tracing::{debug, error, info, warn};
+
+use crate::{
+	chain::constants::{MAX_REPLAY_ENTRIES, REPLAY_JOURNAL_FILE},
+	servers::state::{get_blocknumber, SharedState},
+};
+
+/* ---------------------------------------
+	REQUEST REPLAY JOURNAL
+--------------------------------------- */
+
+// The journal file is an append-only log of the registered digests, one json entry per line :
+// - a request appends its digest, after the state lock is released
+// - the file is compacted to the active digests once it has MAX_REPLAY_ENTRIES appended lines,
+//   written to a staging file, synced and renamed over the log
+// - a torn last line of an interrupted append is skipped at startup
+// The mutex orders the appends and the compactions, it counts the lines appended since the last
+// compaction.
+static JOURNAL_APPENDS: Mutex<usize> = Mutex::const_new(0);
+
+/// Line of the journal file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct JournalEntry {
+	digest: String,
+	expiry: u32,
+}
+
+/// Recently-seen request digests, scoped to the validity window of their auth-token.
+/// It is persisted in sealed directory, so a restart does not reopen the replay window.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ReplayJournal {
+	// request digest -> last block number that the request is valid
+	entries: BTreeMap<String, u32>,
+}
+
+impl ReplayJournal {
+	pub fn len(&self) -> usize {
+		self.entries.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.entries.is_empty()
+	}
+
+	/// Remove the digests that their auth-token has been expired
+	pub fn prune(&mut self, current_block: u32) {
+		self.entries.retain(|_, expiry| *expiry >= current_block);
+	}
+
+	/// Record a request digest
+	/// # Arguments
+	/// * `digest` - request digest
+	/// * `expiry_block` - last block number that the request is valid
+	/// * `current_block` - current block number
+	/// # Returns
+	/// * `bool` - false if the digest has already been seen (replay)
+	pub fn record(&mut self, digest: String, expiry_block: u32, current_block: u32) -> bool {
+		self.prune(current_block);
+
+		if self.entries.contains_key(&digest) {
+			return false
+		}
+
+		// Bounded : evict the digest which expires sooner
+		while self.entries.len() >= MAX_REPLAY_ENTRIES {
+			let oldest = match self.entries.iter().min_by_key(|(_, expiry)| **expiry) {
+				Some((digest, _)) => digest.clone(),
+				None => break,
+			};
+			warn!("REPLAY JOURNAL : journal is full, evicting {}", oldest);
+			self.entries.remove(&oldest);
+		}
+
+		self.entries.insert(digest, expiry_block);
+
+		true
+	}
+
+	/// Load journal from sealed file, expired digests are dropped
+	pub fn load(path: &str, current_block: u32) -> Result<ReplayJournal, anyhow::Error> {
+		if !Path::new(path).exists() {
+			return Ok(ReplayJournal::default())
+		}
+
+		let content = std::fs::read_to_string(path)?;
+
+		// Journal of the previous versions, a single json object
+		let mut journal = match serde_json::from_str::<ReplayJournal>(&content) {
+			Ok(journal) => journal,
+			Err(_) => {
+				let mut journal = ReplayJournal::default();
+				for line in content.lines().filter(|line| !line.trim().is_empty()) {
+					match serde_json::from_str::<JournalEntry>(line) {
+						Ok(entry) => {
+							let expiry =
+								journal.entries.entry(entry.digest).or_insert(entry.expiry);
+							*expiry = (*expiry).max(entry.expiry);
+						},
+						Err(err) =>
+							warn!("REPLAY JOURNAL : skipping a torn entry of {path} : {err:?}"),
+					}
+				}
+				journal
+			},
+		};
+
+		journal.prune(current_block);
+
+		Ok(journal)
+	}
+
+	/// Write journal to sealed file at once, an interrupted write leaves the previous file
+	pub fn save(&self, path: &str) -> Result<(), anyhow::Error> {
+		let mut content = String::new();
+		for (digest, expiry) in &self.entries {
+			let entry = JournalEntry { digest: digest.clone(), expiry: *expiry };
+			content.push_str(&serde_json::to_string(&entry)?);
+			content.push('\n');
+		}
+
+		let staging = format!("{path}.staging");
+		let mut file = File::create(&staging)?;
+		file.write_all(content.as_bytes())?;
+		file.sync_all()?;
+		std::fs::rename(&staging, path)?;
+
+		Ok(())
+	}
+}
+
+/// Append a digest to the journal file
+fn append_entry(path: &str, digest: &str, expiry: u32) -> Result<(), anyhow::Error> {
+	let mut line = serde_json::to_string(&JournalEntry { digest: digest.to_string(), expiry })?;
+	line.push('\n');
+
+	let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+	file.write_all(line.as_bytes())?;
+	file.sync_data()?;
+
+	Ok(())
+}
+
+/// Append a recorded digest to a journal file
+/// # Arguments
+/// * `appends` - lines appended to the file since its last compaction
+/// * `path` - journal file
+/// * `digest` - request digest
+/// * `expiry_block` - last block number that the request is valid
+/// # Returns
+/// * `usize` - lines appended since the last compaction, including this one
+pub async fn persist_request_digest(
+	appends: &Mutex<usize>,
+	path: &str,
+	digest: &str,
+	expiry_block: u32,
+) -> usize {
+	let mut appends = appends.lock().await;
+	if let Err(err) = append_entry(path, digest, expiry_block) {
+		error!("REPLAY JOURNAL : error persisting request {digest} : {err:?}");
+	}
+	*appends += 1;
+	*appends
+}
+
+/// Rewrite the journal file with the active digests of the state
+/// # Arguments
+/// * `state` - SharedState
+pub async fn compact_replay_journal(state: &SharedState) -> Result<(), anyhow::Error> {
+	let mut appends = JOURNAL_APPENDS.lock().await;
+
+	// Digests recorded after this snapshot wait for the mutex and are appended to the new file
+	let journal = state.read().await.get_replay_journal().clone();
+	journal.save(REPLAY_JOURNAL_FILE)?;
+	*appends = 0;
+
+	debug!("REPLAY JOURNAL : journal is compacted to {} digests", journal.len());
+
+	Ok(())
+}
+
+/// Load the persisted replay journal into the state at startup
+/// # Arguments
+/// * `state` - SharedState
+/// * `current_block` - current block number
+pub async fn load_replay_journal(
+	state: &SharedState,
+	current_block: u32,
+) -> Result<(), anyhow::Error> {
+	let journal = ReplayJournal::load(REPLAY_JOURNAL_FILE, current_block)?;
+	info!("REPLAY JOURNAL : {} active request digests are loaded", journal.len());
+
+	state.write().await.set_replay_journal(journal);
+
+	// Drops the expired digests and a torn entry from the file
+	compact_replay_journal(state).await
+}
+
+/// Register a request in replay journal and persist it
+/// # Arguments
+/// * `state` - SharedState
+/// * `digest` - request digest
+/// * `expiry_block` - last block number that the request is valid
+/// # Returns
+/// * `bool` - false if the request is a replay
+pub async fn register_request(state: &SharedState, digest: String, expiry_block: u32) -> bool {
+	let current_block = get_blocknumber(state).await;
+
+	let recorded =
+		state
+			.write()
+			.await
+			.record_request_digest(digest.clone(), expiry_block, current_block);
+	if !recorded {
+		debug!("REPLAY JOURNAL : replayed request {}", digest);
+		return false
+	}
+
+	// Persisted out of the state lock, the order of the lines does not matter
+	let appends =
+		persist_request_digest(&JOURNAL_APPENDS, REPLAY_JOURNAL_FILE, &digest, expiry_block).await;
+
+	if appends >= MAX_REPLAY_ENTRIES {
+		if let Err(err) = compact_replay_journal(state).await {
+			error!("REPLAY JOURNAL : error compacting journal : {err:?}");
+		}
+	}
+
+	true
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn replay_journal_test() {
+		let mut journal = ReplayJournal::default();
+
+		assert!(journal.record("a".to_string(), 110, 100));
+		assert!(!journal.record("a".to_string(), 110, 105));
+		assert!(journal.record("b".to_string(), 120, 105));
+
+		// "a" is expired and can not be replayed anymore due to its auth-token
+		assert!(journal.record("c".to_string(), 130, 115));
+		assert_eq!(journal.len(), 2);
+
+		let path = std::env::temp_dir().join("replay_journal_test.json");
+		let path = path.to_str().unwrap();
+		journal.save(path).unwrap();
+
+		let loaded = ReplayJournal::load(path, 125).unwrap();
+		assert_eq!(loaded.len(), 1);
+		assert!(!loaded.clone().record("c".to_string(), 130, 125));
+
+		// Appended digests, then a torn entry of an interrupted append
+		append_entry(path, "d", 140).unwrap();
+		append_entry(path, "c", 135).unwrap();
+		std::fs::OpenOptions::new()
+			.append(true)
+			.open(path)
+			.unwrap()
+			.write_all(b"{\"digest\":\"e\",\"exp")
+			.unwrap();
+
+		let loaded = ReplayJournal::load(path, 125).unwrap();
+		assert_eq!(loaded.len(), 2);
+		assert_eq!(loaded.entries["c"], 135);
+		assert!(!loaded.clone().record("d".to_string(), 140, 125));
+
+		// Journal of the previous versions
+		std::fs::write(path, serde_json::to_string(&journal).unwrap()).unwrap();
+		assert_eq!(ReplayJournal::load(path, 125).unwrap().len(), 1);
+
+		let _ = std::fs::remove_file(path);
+	}
+}
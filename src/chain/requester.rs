@@ -0,0 +1,190 @@
+use std::{collections::BTreeMap, sync::OnceLock};
+
+use async_trait::async_trait;
+use subxt::utils::AccountId32;
+
+use crate::chain::{
+	reader::ChainReader,
+	rental::{rental_access, RentalAccess},
+	verify::{KeyshareHolder, RequesterType, VerificationError},
+};
+
+/* ---------------------------------------
+	REQUESTER ROLE POLICIES
+--------------------------------------- */
+
+static REQUESTER_REGISTRY: OnceLock<RequesterRegistry> = OnceLock::new();
+
+/// Onchain check of a requester role.
+/// A new role is a RequesterType variant with its policy registered in `default_registry`,
+/// request verification and call sites stay the same.
+#[async_trait]
+pub trait RequesterPolicy: Send + Sync {
+	/// True if the requester holds the role on the nft/capsule
+	/// # Arguments
+	/// * `chain` - onchain data reader
+	/// * `requester` - normalized requester account
+	/// * `nft_id` - nft/capsule id
+	/// * `owner` - nft/capsule owner
+	/// # Errors
+	/// * `ORACLEFAILURE` - if primary and secondary rpc disagree in dual-rpc mode
+	async fn is_authorized(
+		&self,
+		chain: &dyn ChainReader,
+		requester: &AccountId32,
+		nft_id: u32,
+		owner: &AccountId32,
+	) -> Result<bool, VerificationError>;
+}
+
+/// Owner of the nft/capsule
+pub struct OwnerPolicy;
+
+/// Delegatee account of the nft/capsule
+pub struct DelegateePolicy;
+
+/// Rentee account of the rent contract of the nft/capsule, until the end of the grace period of
+/// a lapsed rental
+pub struct RenteePolicy;
+
+#[async_trait]
+impl RequesterPolicy for OwnerPolicy {
+	async fn is_authorized(
+		&self,
+		_chain: &dyn ChainReader,
+		requester: &AccountId32,
+		_nft_id: u32,
+		owner: &AccountId32,
+	) -> Result<bool, VerificationError> {
+		Ok(requester == owner)
+	}
+}
+
+#[async_trait]
+impl RequesterPolicy for DelegateePolicy {
+	async fn is_authorized(
+		&self,
+		chain: &dyn ChainReader,
+		requester: &AccountId32,
+		nft_id: u32,
+		_owner: &AccountId32,
+	) -> Result<bool, VerificationError> {
+		match chain.delegatee(nft_id).await {
+			KeyshareHolder::Delegatee(delegatee) => Ok(delegatee == *requester),
+			KeyshareHolder::Diverged => Err(VerificationError::ORACLEFAILURE),
+			_ => Ok(false),
+		}
+	}
+}
+
+#[async_trait]
+impl RequesterPolicy for RenteePolicy {
+	async fn is_authorized(
+		&self,
+		chain: &dyn ChainReader,
+		requester: &AccountId32,
+		nft_id: u32,
+		_owner: &AccountId32,
+	) -> Result<bool, VerificationError> {
+		Ok(rental_access(chain, requester, nft_id).await? != RentalAccess::DENIED)
+	}
+}
+
+/* ---------------------------------------
+	POLICY REGISTRY
+--------------------------------------- */
+
+/// Requester roles which are accepted by the enclave, with their onchain checks
+#[derive(Default)]
+pub struct RequesterRegistry {
+	policies: BTreeMap<RequesterType, Box<dyn RequesterPolicy>>,
+}
+
+impl RequesterRegistry {
+	/// Register the policy of a role, it replaces the previous policy of the role
+	pub fn with(mut self, role: RequesterType, policy: impl RequesterPolicy + 'static) -> Self {
+		self.policies.insert(role, Box::new(policy));
+		self
+	}
+
+	pub fn policy(&self, role: RequesterType) -> Option<&dyn RequesterPolicy> {
+		self.policies.get(&role).map(|policy| policy.as_ref())
+	}
+
+	/// Registered roles, advertised in capabilities
+	pub fn roles(&self) -> Vec<RequesterType> {
+		self.policies.keys().copied().collect()
+	}
+}
+
+/// Built-in roles of the enclave
+pub fn default_registry() -> RequesterRegistry {
+	RequesterRegistry::default()
+		.with(RequesterType::OWNER, OwnerPolicy)
+		.with(RequesterType::DELEGATEE, DelegateePolicy)
+		.with(RequesterType::RENTEE, RenteePolicy)
+}
+
+/// Effective requester registry, built-in roles if it is not configured
+pub fn requester_registry() -> &'static RequesterRegistry {
+	REQUESTER_REGISTRY.get_or_init(default_registry)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::chain::reader::mock::MockChain;
+
+	const TEST_BLOCK_NUMBER: u32 = 1000;
+
+	// Example of an extension role : any account of a fixed list, i.e. a marketplace escrow
+	struct AllowListPolicy(Vec<AccountId32>);
+
+	#[async_trait]
+	impl RequesterPolicy for AllowListPolicy {
+		async fn is_authorized(
+			&self,
+			_chain: &dyn ChainReader,
+			requester: &AccountId32,
+			_nft_id: u32,
+			_owner: &AccountId32,
+		) -> Result<bool, VerificationError> {
+			Ok(self.0.contains(requester))
+		}
+	}
+
+	#[tokio::test]
+	async fn requester_registry_test() {
+		let owner = AccountId32([1u8; 32]);
+		let delegatee = AccountId32([2u8; 32]);
+		let stranger = AccountId32([3u8; 32]);
+
+		let mut chain = MockChain::new(TEST_BLOCK_NUMBER).with_secret_nft(10, owner.clone(), false);
+		chain.delegatees.insert(10, delegatee.clone());
+
+		let registry = default_registry();
+		assert_eq!(
+			registry.roles(),
+			vec![RequesterType::OWNER, RequesterType::DELEGATEE, RequesterType::RENTEE]
+		);
+
+		let owner_policy = registry.policy(RequesterType::OWNER).unwrap();
+		assert_eq!(owner_policy.is_authorized(&chain, &owner, 10, &owner).await, Ok(true));
+		assert_eq!(owner_policy.is_authorized(&chain, &stranger, 10, &owner).await, Ok(false));
+
+		let delegatee_policy = registry.policy(RequesterType::DELEGATEE).unwrap();
+		assert_eq!(delegatee_policy.is_authorized(&chain, &delegatee, 10, &owner).await, Ok(true));
+		assert_eq!(delegatee_policy.is_authorized(&chain, &owner, 10, &owner).await, Ok(false));
+
+		let rentee_policy = registry.policy(RequesterType::RENTEE).unwrap();
+		assert_eq!(rentee_policy.is_authorized(&chain, &delegatee, 10, &owner).await, Ok(false));
+
+		// A role can be replaced without touching the verification
+		let registry =
+			default_registry().with(RequesterType::RENTEE, AllowListPolicy(vec![stranger.clone()]));
+		let rentee_policy = registry.policy(RequesterType::RENTEE).unwrap();
+		assert_eq!(rentee_policy.is_authorized(&chain, &stranger, 10, &owner).await, Ok(true));
+
+		assert!(RequesterRegistry::default().policy(RequesterType::OWNER).is_none());
+	}
+}
@@ -0,0 +1,294 @@
+use std::{collections::BTreeMap, sync::Mutex};
+
+use anyhow::anyhow;
+use futures::StreamExt;
+use rand::RngCore;
+use serde::Serialize;
+use serde_json::Value;
+use subxt::{rpc::types::BlockNumber, utils::AccountId32};
+use tracing::{debug, error, trace, warn};
+
+use crate::{
+	chain::{
+		constants::{MAX_SCAN_INTERVAL, MAX_SCAN_JOBS, SCAN_CONCURRENCY, SCAN_JOB_PERIOD},
+		core::{
+			ternoa::nft::events::{CapsuleShardAdded, CapsuleSynced, SecretNFTSynced, ShardAdded},
+			DefaultApi,
+		},
+		helper::NftType,
+	},
+	servers::state::{get_chain_api, get_clusters, get_identity, SharedState},
+};
+
+/* ---------------------------------------
+	BLOCK-RANGE NFT SCANNER
+--------------------------------------- */
+
+/// Enumerate the secret-nft/capsule sync events in a block interval
+/// Only NFTs which have been synced by an enclave of this enclave's cluster are returned,
+/// these are the keyshares that this enclave should hold.
+/// # Arguments
+/// * `state` - SharedState
+/// * `from_block` - first block of the interval (inclusive)
+/// * `to_block` - last block of the interval (inclusive)
+/// * `progress` - called with the number of blocks scanned so far
+/// # Returns
+/// * `BTreeMap<u32, (u32, NftType)>` - nft_id -> (sync block number, nft type)
+pub async fn scan_block_range(
+	state: &SharedState,
+	from_block: u32,
+	to_block: u32,
+	mut progress: impl FnMut(u32),
+) -> Result<BTreeMap<u32, (u32, NftType)>, anyhow::Error> {
+	if from_block > to_block || to_block - from_block > MAX_SCAN_INTERVAL {
+		return Err(anyhow!(
+			"SCANNER : invalid block interval [{}, {}], maximum length is {}",
+			from_block,
+			to_block,
+			MAX_SCAN_INTERVAL
+		))
+	}
+
+	let cluster_accounts: Vec<AccountId32> = match get_identity(state).await {
+		Some((cluster_id, _slot_id)) => get_clusters(state)
+			.await
+			.into_iter()
+			.filter(|c| c.id == cluster_id)
+			.flat_map(|c| c.enclaves.into_iter().map(|e| e.enclave_account))
+			.collect(),
+		None => return Err(anyhow!("SCANNER : enclave is not registered in any cluster")),
+	};
+
+	let api = get_chain_api(state).await;
+	let mut expected = BTreeMap::<u32, (u32, NftType)>::new();
+	let mut blocks_scanned = 0;
+
+	// Blocks are fetched concurrently, their events are merged in block order
+	let mut blocks = futures::stream::iter(from_block..=to_block)
+		.map(|block_counter| scan_block(&api, &cluster_accounts, block_counter))
+		.buffered(SCAN_CONCURRENCY);
+
+	while let Some(synced) = blocks.next().await {
+		for (nft_id, block_number, nft_type) in synced? {
+			insert_expected(&mut expected, nft_id, block_number, nft_type);
+		}
+
+		blocks_scanned += 1;
+		progress(blocks_scanned);
+	}
+
+	Ok(expected)
+}
+
+/// Secret-nft/capsule sync events of a block, synced by an enclave of the cluster
+/// # Returns
+/// * `Vec<(u32, u32, NftType)>` - (nft_id, block number, nft type)
+async fn scan_block(
+	api: &DefaultApi,
+	cluster_accounts: &[AccountId32],
+	block_counter: u32,
+) -> Result<Vec<(u32, u32, NftType)>, anyhow::Error> {
+	trace!("SCANNER : block number = {}", block_counter);
+
+	let block_number = BlockNumber::from(block_counter);
+	let block_hash = match api.rpc().block_hash(Some(block_number)).await? {
+		Some(hash) => hash,
+		None => return Err(anyhow!("SCANNER : error getting block hash of {block_counter}")),
+	};
+
+	let events = api.blocks().at(block_hash).await?.events().await?;
+	let mut synced_nfts = Vec::new();
+
+	for synced in events.find::<SecretNFTSynced>() {
+		let nft_id = match synced {
+			Ok(ev) => ev.nft_id,
+			Err(err) => {
+				warn!("SCANNER : error decoding SecretNFTSynced event : {err:?}");
+				continue
+			},
+		};
+
+		let is_cluster_shard = events
+			.find::<ShardAdded>()
+			.flatten()
+			.any(|ev| ev.nft_id == nft_id && cluster_accounts.contains(&ev.enclave));
+
+		if is_cluster_shard {
+			debug!("SCANNER : secret-nft {} synced at block {}", nft_id, block_counter);
+			synced_nfts.push((nft_id, block_counter, NftType::Secret));
+		}
+	}
+
+	for synced in events.find::<CapsuleSynced>() {
+		let nft_id = match synced {
+			Ok(ev) => ev.nft_id,
+			Err(err) => {
+				warn!("SCANNER : error decoding CapsuleSynced event : {err:?}");
+				continue
+			},
+		};
+
+		let is_cluster_shard = events
+			.find::<CapsuleShardAdded>()
+			.flatten()
+			.any(|ev| ev.nft_id == nft_id && cluster_accounts.contains(&ev.enclave));
+
+		if is_cluster_shard {
+			debug!("SCANNER : capsule {} synced at block {}", nft_id, block_counter);
+			synced_nfts.push((nft_id, block_counter, NftType::Capsule));
+		}
+	}
+
+	Ok(synced_nfts)
+}
+
+/// Merge a sync event into the expected map, same logic as keyshare file query
+fn insert_expected(
+	expected: &mut BTreeMap<u32, (u32, NftType)>,
+	nft_id: u32,
+	block_number: u32,
+	nft_type: NftType,
+) {
+	let nft_type = match expected.get(&nft_id) {
+		Some((_, old_type)) if *old_type != nft_type => NftType::Hybrid,
+		_ => nft_type,
+	};
+
+	expected.insert(nft_id, (block_number, nft_type));
+}
+
+/* ---------------------------------------
+	RECONCILIATION SCAN JOBS
+--------------------------------------- */
+
+// A reconciliation interval is scanned in the background instead of while holding the request :
+// 1. The metric request is validated and returns a job id
+// 2. The status of the job reports the blocks scanned so far, then the reconciliation report
+// The job id is the capability of the report, it is only returned to the metric server.
+
+/// State of a reconciliation scan
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum ScanStatus {
+	RUNNING,
+	DONE,
+	FAILED,
+}
+
+/// Background scan of a block interval, kept until the expiry block
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ScanJob {
+	pub job_id: String,
+	pub status: ScanStatus,
+	pub from_block: u32,
+	pub to_block: u32,
+	pub blocks_scanned: u32,
+	pub expiry_block: u32,
+	// Reconciliation report of the finished scan
+	pub report: Option<Value>,
+	pub error: Option<String>,
+}
+
+static SCAN_JOBS: Mutex<BTreeMap<String, ScanJob>> = Mutex::new(BTreeMap::new());
+
+pub fn valid_scan_job_id(job_id: &str) -> bool {
+	job_id.len() == 32 && job_id.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Update a scan job, if it has not expired
+pub fn update_scan_job(job_id: &str, update: impl FnOnce(&mut ScanJob)) {
+	match SCAN_JOBS.lock() {
+		Ok(mut jobs) =>
+			if let Some(job) = jobs.get_mut(job_id) {
+				update(job)
+			},
+		Err(err) => error!("SCANNER : lock error : {err:?}"),
+	}
+}
+
+/// Current state of a scan job
+pub fn get_scan_job(job_id: &str) -> Option<ScanJob> {
+	SCAN_JOBS.lock().ok().and_then(|jobs| jobs.get(job_id).cloned())
+}
+
+/// Remove the finished scan jobs which have expired
+pub fn prune_scan_jobs(current_block: u32) {
+	match SCAN_JOBS.lock() {
+		Ok(mut jobs) => jobs.retain(|_, job| {
+			job.status == ScanStatus::RUNNING || job.expiry_block >= current_block
+		}),
+		Err(err) => error!("SCANNER : lock error : {err:?}"),
+	}
+}
+
+/// Register a running scan job of a block interval
+/// # Arguments
+/// * `from_block` - first block of the interval (inclusive)
+/// * `to_block` - last block of the interval (inclusive)
+/// * `current_block` - current block number
+/// # Errors
+/// * Too many running scans
+pub fn create_scan_job(
+	from_block: u32,
+	to_block: u32,
+	current_block: u32,
+) -> Result<ScanJob, anyhow::Error> {
+	prune_scan_jobs(current_block);
+
+	let mut job_id = [0u8; 16];
+	rand::thread_rng().fill_bytes(&mut job_id);
+
+	let job = ScanJob {
+		job_id: hex::encode(job_id),
+		status: ScanStatus::RUNNING,
+		from_block,
+		to_block,
+		blocks_scanned: 0,
+		expiry_block: current_block + SCAN_JOB_PERIOD,
+		report: None,
+		error: None,
+	};
+
+	let mut jobs = SCAN_JOBS.lock().map_err(|err| anyhow!("SCANNER : lock error : {err:?}"))?;
+	if jobs.values().filter(|job| job.status == ScanStatus::RUNNING).count() >= MAX_SCAN_JOBS {
+		return Err(anyhow!("SCANNER : too many reconciliation scans in progress, retry later"))
+	}
+
+	jobs.insert(job.job_id.clone(), job.clone());
+	Ok(job)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn insert_expected_test() {
+		let mut expected = BTreeMap::new();
+
+		insert_expected(&mut expected, 10, 100, NftType::Secret);
+		insert_expected(&mut expected, 11, 101, NftType::Capsule);
+		insert_expected(&mut expected, 10, 105, NftType::Capsule);
+
+		assert_eq!(expected.get(&10), Some(&(105, NftType::Hybrid)));
+		assert_eq!(expected.get(&11), Some(&(101, NftType::Capsule)));
+	}
+
+	#[test]
+	fn scan_job_test() {
+		let jobs: Vec<ScanJob> =
+			(0..MAX_SCAN_JOBS).map(|_| create_scan_job(100, 200, 1000).unwrap()).collect();
+		assert!(jobs.iter().all(|job| valid_scan_job_id(&job.job_id)));
+		assert!(!valid_scan_job_id("../../nft/enclave_account.key"));
+
+		// Running scans are limited
+		assert!(create_scan_job(100, 200, 1000).is_err());
+
+		update_scan_job(&jobs[0].job_id, |job| job.status = ScanStatus::DONE);
+		assert!(create_scan_job(100, 200, 1000).is_ok());
+
+		// Finished jobs expire, running ones are kept
+		prune_scan_jobs(1001 + SCAN_JOB_PERIOD);
+		assert!(get_scan_job(&jobs[0].job_id).is_none());
+		assert_eq!(get_scan_job(&jobs[1].job_id).unwrap().status, ScanStatus::RUNNING);
+	}
+}
@@ -0,0 +1,155 @@
+use serde::{Deserialize, Serialize};
+use subxt::ext::sp_core::{blake2_256, ecdsa, ed25519, sr25519, Pair};
+
+use crate::chain::verify::SignatureError;
+
+/* ---------------------------------------
+	MULTI-SCHEME SIGNATURES
+--------------------------------------- */
+
+/// Signature schemes of Substrate MultiSignature
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum SignatureScheme {
+	SR25519,
+	ED25519,
+	// secp256k1, account is blake2_256 of the compressed public key
+	ECDSA,
+}
+
+/// Parse an hex signature, 64 bytes for sr25519/ed25519 and 65 bytes for ecdsa
+/// # Arguments
+/// * `signature` - "0x" prefixed hex string
+pub fn parse_hex_signature(signature: &str) -> Result<Vec<u8>, SignatureError> {
+	let strip_sig = match signature.strip_prefix("0x") {
+		Some(ssig) => ssig,
+		_ => return Err(SignatureError::PREFIXERROR),
+	};
+
+	match hex::decode(strip_sig) {
+		Ok(bsig) if bsig.len() == 64 || bsig.len() == 65 => Ok(bsig),
+		_ => Err(SignatureError::LENGHTERROR),
+	}
+}
+
+/// Verify the signature of a message by an account
+/// # Arguments
+/// * `scheme` - signature scheme, None to detect it from the signature
+/// * `signature` - hex signature
+/// * `message` - signed message
+/// * `account` - raw AccountId32 of the signer
+/// # Returns
+/// * `bool` - true if the signature is valid
+pub fn verify_account_signature(
+	scheme: Option<SignatureScheme>,
+	signature: &str,
+	message: &[u8],
+	account: &[u8; 32],
+) -> Result<bool, SignatureError> {
+	let signature = parse_hex_signature(signature)?;
+	verify_raw_signature(scheme, &signature, message, account)
+}
+
+/// Verify a raw signature of a message by an account, see verify_account_signature
+pub fn verify_raw_signature(
+	scheme: Option<SignatureScheme>,
+	signature: &[u8],
+	message: &[u8],
+	account: &[u8; 32],
+) -> Result<bool, SignatureError> {
+	match (scheme, signature.len()) {
+		(Some(SignatureScheme::SR25519), 64) => Ok(verify_sr25519(signature, message, account)),
+		(Some(SignatureScheme::ED25519), 64) => Ok(verify_ed25519(signature, message, account)),
+		(Some(SignatureScheme::ECDSA), 65) | (None, 65) =>
+			Ok(verify_ecdsa(signature, message, account)),
+		// Both are 64 bytes, a valid signature of one scheme does not verify with the other
+		(None, 64) => Ok(verify_sr25519(signature, message, account) ||
+			verify_ed25519(signature, message, account)),
+		_ => Err(SignatureError::LENGHTERROR),
+	}
+}
+
+fn verify_sr25519(signature: &[u8], message: &[u8], account: &[u8; 32]) -> bool {
+	match sr25519::Signature::from_slice(signature) {
+		Some(sig) => sr25519::Pair::verify(&sig, message, &sr25519::Public::from_raw(*account)),
+		None => false,
+	}
+}
+
+fn verify_ed25519(signature: &[u8], message: &[u8], account: &[u8; 32]) -> bool {
+	match ed25519::Signature::from_slice(signature) {
+		Some(sig) => ed25519::Pair::verify(&sig, message, &ed25519::Public::from_raw(*account)),
+		None => false,
+	}
+}
+
+fn verify_ecdsa(signature: &[u8], message: &[u8], account: &[u8; 32]) -> bool {
+	let sig = match ecdsa::Signature::from_slice(signature) {
+		Some(sig) => sig,
+		None => return false,
+	};
+
+	match sig.recover(message) {
+		Some(public) => &blake2_256(public.as_ref()) == account,
+		None => false,
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn hex_signature(signature: &[u8]) -> String {
+		format!("0x{}", hex::encode(signature))
+	}
+
+	#[test]
+	fn multi_signature_test() {
+		let message = b"163_1000_10";
+
+		let sr_pair = sr25519::Pair::from_seed(&[1u8; 32]);
+		let sr_sig = hex_signature(&sr_pair.sign(message).0);
+		let sr_account = sr_pair.public().0;
+
+		let ed_pair = ed25519::Pair::from_seed(&[2u8; 32]);
+		let ed_sig = hex_signature(&ed_pair.sign(message).0);
+		let ed_account = ed_pair.public().0;
+
+		let ec_pair = ecdsa::Pair::from_seed(&[3u8; 32]);
+		let ec_sig = hex_signature(&ec_pair.sign(message).0);
+		let ec_account = blake2_256(ec_pair.public().as_ref());
+
+		// explicit scheme
+		assert_eq!(
+			verify_account_signature(Some(SignatureScheme::SR25519), &sr_sig, message, &sr_account),
+			Ok(true)
+		);
+		assert_eq!(
+			verify_account_signature(Some(SignatureScheme::ED25519), &ed_sig, message, &ed_account),
+			Ok(true)
+		);
+		assert_eq!(
+			verify_account_signature(Some(SignatureScheme::ECDSA), &ec_sig, message, &ec_account),
+			Ok(true)
+		);
+		assert_eq!(
+			verify_account_signature(Some(SignatureScheme::SR25519), &ed_sig, message, &ed_account),
+			Ok(false)
+		);
+		assert_eq!(
+			verify_account_signature(Some(SignatureScheme::ECDSA), &sr_sig, message, &sr_account),
+			Err(SignatureError::LENGHTERROR)
+		);
+
+		// auto-detected
+		assert_eq!(verify_account_signature(None, &sr_sig, message, &sr_account), Ok(true));
+		assert_eq!(verify_account_signature(None, &ed_sig, message, &ed_account), Ok(true));
+		assert_eq!(verify_account_signature(None, &ec_sig, message, &ec_account), Ok(true));
+		assert_eq!(verify_account_signature(None, &ec_sig, b"other", &ec_account), Ok(false));
+		assert_eq!(verify_account_signature(None, &ed_sig, message, &sr_account), Ok(false));
+
+		assert_eq!(
+			verify_account_signature(None, &sr_sig[2..], message, &sr_account),
+			Err(SignatureError::PREFIXERROR)
+		);
+	}
+}
@@ -0,0 +1,575 @@
+use std::{
+	fs::File,
+	path::{Path, PathBuf},
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc,
+	},
+	time::Instant,
+};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use rand::{
+	distributions::{Alphanumeric, Distribution, WeightedIndex},
+	Rng,
+};
+use serde::Serialize;
+use serde_json::json;
+use subxt::{
+	ext::sp_core::{crypto::Ss58Codec, sr25519, Pair},
+	utils::AccountId32,
+};
+use tokio::sync::{Mutex, RwLock};
+use tracing::{debug, info, warn};
+
+use crate::chain::{
+	audit::{result_status, write_audit_record, AuditHead},
+	constants::{MAX_KEYSHARE_SIZE, MIN_KEYSHARE_SIZE, SIMULATION_MAX_UTILIZATION},
+	helper,
+	log::{update_log_file_view, LogType},
+	nft::{create_store_log, write_keyshare_file},
+	reader::{ChainReader, OnchainNft},
+	replay::{persist_request_digest, ReplayJournal},
+	verify::{
+		AuthenticationToken, KeyshareHolder, NftKind, RequesterType, RetrieveKeysharePacket,
+		StoreKeysharePacket, VerificationError, APICALL,
+	},
+};
+
+/* ---------------------------------------
+	CAPACITY PLANNING SIMULATION
+--------------------------------------- */
+
+const SIMULATION_BLOCK: u32 = 1000;
+const SECONDS_PER_DAY: f64 = 86400.0;
+
+/// Synthetic workload and hardware of a simulation
+#[derive(Debug, Clone)]
+pub struct SimulationConfig {
+	pub stores_per_sec: f64,
+	pub retrieves_per_sec: f64,
+	// (keyshare size, weight)
+	pub keyshare_sizes: Vec<(usize, u32)>,
+	// Worker threads of the simulated machine
+	pub cores: usize,
+	// Free disk space of the seal path, in GB
+	pub disk_gb: Option<f64>,
+	pub requests: usize,
+	pub compression_threshold: usize,
+	pub seal_path: PathBuf,
+}
+
+/// Measured capacity of a request type
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct PhaseReport {
+	pub requests: usize,
+	pub failures: usize,
+	pub elapsed_ms: u128,
+	pub max_per_sec: f64,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SimulationReport {
+	pub cores: usize,
+	pub store: PhaseReport,
+	pub retrieve: PhaseReport,
+	pub average_keyshare_bytes: u64,
+	// Allocated on disk, including filesystem block overhead
+	pub average_sealed_bytes: u64,
+	// Fraction of the measured capacity used by the workload
+	pub utilization: f64,
+	pub sustainable: bool,
+	pub disk_growth_per_day_bytes: u64,
+	pub days_until_disk_full: Option<f64>,
+}
+
+/// Parse a keyshare size distribution : "size:weight,size:weight,..."
+/// # Arguments
+/// * `distribution` - e.g. "512:70,2048:25,3000:5", a single "size" has weight 1
+pub fn parse_keyshare_sizes(distribution: &str) -> Result<Vec<(usize, u32)>, anyhow::Error> {
+	let mut sizes = Vec::new();
+
+	for item in distribution.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+		let (size, weight) = match item.split_once(':') {
+			Some((size, weight)) => (size.trim().parse::<usize>()?, weight.trim().parse::<u32>()?),
+			None => (item.parse::<usize>()?, 1),
+		};
+
+		if size < MIN_KEYSHARE_SIZE.into() || size > MAX_KEYSHARE_SIZE.into() {
+			return Err(anyhow!(
+				"SIMULATION : keyshare size {size} is out of [{MIN_KEYSHARE_SIZE}, {MAX_KEYSHARE_SIZE}]"
+			))
+		}
+
+		sizes.push((size, weight));
+	}
+
+	if sizes.iter().all(|(_, weight)| *weight == 0) {
+		return Err(anyhow!("SIMULATION : empty keyshare size distribution"))
+	}
+
+	Ok(sizes)
+}
+
+/// Every nft is a secret-nft of the simulation owner
+struct SimulatedChain {
+	owner: AccountId32,
+	syncing: AtomicBool,
+}
+
+#[async_trait]
+impl ChainReader for SimulatedChain {
+	async fn current_block_number(&self) -> u32 {
+		SIMULATION_BLOCK
+	}
+
+	async fn nft_data(&self, _nft_id: u32) -> Result<OnchainNft, VerificationError> {
+		Ok(OnchainNft {
+			owner: self.owner.clone(),
+			is_secret: true,
+			is_syncing_secret: self.syncing.load(Ordering::Relaxed),
+			is_capsule: false,
+			is_syncing_capsule: false,
+			collection_id: None,
+		})
+	}
+
+	async fn delegatee(&self, _nft_id: u32) -> KeyshareHolder {
+		KeyshareHolder::NotFound
+	}
+
+	async fn rentee(&self, _nft_id: u32) -> KeyshareHolder {
+		KeyshareHolder::NotFound
+	}
+
+	async fn rental_end(&self, _nft_id: u32) -> Option<u32> {
+		None
+	}
+
+	async fn read_only_until(&self) -> Option<u32> {
+		None
+	}
+
+	async fn cosigner(&self, _nft_id: u32, _collection_id: Option<u32>) -> Option<sr25519::Public> {
+		None
+	}
+
+	async fn charge_quota(
+		&self,
+		_call: APICALL,
+		_account: &sr25519::Public,
+		_items: u32,
+	) -> Result<(), u64> {
+		Ok(())
+	}
+}
+
+/// Signed json bodies of store requests, nft_id from 1 to count
+fn generate_store_requests(
+	owner: &sr25519::Pair,
+	signer: &sr25519::Pair,
+	sizes: &[(usize, u32)],
+	count: usize,
+) -> Result<Vec<String>, anyhow::Error> {
+	let distribution = WeightedIndex::new(sizes.iter().map(|(_, weight)| *weight))?;
+	let mut rng = rand::thread_rng();
+
+	let signer_address = format!("{}_{}_10", signer.public().to_ss58check(), SIMULATION_BLOCK);
+	let signersig = owner.sign(signer_address.as_bytes());
+
+	(1..=count as u32)
+		.map(|nft_id| {
+			let size = sizes[distribution.sample(&mut rng)].0;
+			let keyshare: String =
+				(&mut rng).sample_iter(&Alphanumeric).take(size).map(char::from).collect();
+
+			let data = format!("{nft_id}_{keyshare}_{SIMULATION_BLOCK}_10");
+			let signature = signer.sign(data.as_bytes());
+
+			Ok(json!({
+				"owner_address": owner.public().to_ss58check(),
+				"signer_address": signer_address,
+				"signersig": format!("0x{}", hex::encode(signersig.0)),
+				"data": data,
+				"signature": format!("0x{}", hex::encode(signature.0)),
+			})
+			.to_string())
+		})
+		.collect()
+}
+
+/// Signed json bodies of retrieve requests, spread over the stored nft_ids
+fn generate_retrieve_requests(owner: &sr25519::Pair, stored: usize, count: usize) -> Vec<String> {
+	(0..count)
+		.map(|index| {
+			let nft_id = (index % stored.max(1)) + 1;
+			let data = format!("{nft_id}_{SIMULATION_BLOCK}_10");
+			let signature = owner.sign(data.as_bytes());
+
+			json!({
+				"requester_address": owner.public().to_ss58check(),
+				"requester_type": "OWNER",
+				"data": data,
+				"signature": format!("0x{}", hex::encode(signature.0)),
+			})
+			.to_string()
+		})
+		.collect()
+}
+
+/// Sealed state of the simulated enclave, locked as the state of the enclave
+struct SimulatedEnclave {
+	chain: SimulatedChain,
+	seal_path: PathBuf,
+	compression_threshold: usize,
+	replay_journal: RwLock<ReplayJournal>,
+	journal_appends: Mutex<usize>,
+	audit_head: Mutex<AuditHead>,
+}
+
+impl SimulatedEnclave {
+	fn sealed_file(&self, name: String) -> String {
+		self.seal_path.join(name).to_string_lossy().to_string()
+	}
+
+	/// Same audit record as `audit_result`, written to the simulation seal path
+	async fn audit<T>(
+		&self,
+		call: APICALL,
+		nft_id: u32,
+		requester: String,
+		requester_type: Option<RequesterType>,
+		result: &Result<T, VerificationError>,
+	) -> Result<(), anyhow::Error> {
+		let status = result_status(call, result);
+
+		let mut head = self.audit_head.lock().await;
+		let record = head.append(SIMULATION_BLOCK, call, nft_id, requester, requester_type, status);
+
+		write_audit_record(&self.sealed_file("audit.log".to_string()), &record)
+	}
+
+	/// Same replay journal registration as `register_request`
+	async fn register_request(&self, digest: String, expiry_block: u32) -> bool {
+		let recorded = self.replay_journal.write().await.record(
+			digest.clone(),
+			expiry_block,
+			SIMULATION_BLOCK,
+		);
+		if !recorded {
+			return false
+		}
+
+		persist_request_digest(
+			&self.journal_appends,
+			&self.sealed_file("replay.journal".to_string()),
+			&digest,
+			expiry_block,
+		)
+		.await;
+
+		true
+	}
+}
+
+/// Steps of the store-keyshare endpoint : verification, audit, keyshare file and history log.
+/// The proof of storage extrinsic is not simulated.
+async fn simulate_store(enclave: &SimulatedEnclave, body: &str) -> Result<(), anyhow::Error> {
+	let request: StoreKeysharePacket = serde_json::from_str(body)?;
+	let verification = request.verify_store_request(&enclave.chain, NftKind::SECRET).await;
+	enclave
+		.audit(
+			APICALL::NFTSTORE,
+			request.requested_nft_id(),
+			request.owner_address.to_string(),
+			Some(RequesterType::OWNER),
+			&verification,
+		)
+		.await?;
+
+	let verified_data =
+		verification.map_err(|err| anyhow!("SIMULATION : store verification failed : {err:?}"))?;
+
+	let file_path =
+		enclave.sealed_file(format!("nft_{}_{SIMULATION_BLOCK}.keyshare", verified_data.nft_id));
+	write_keyshare_file(&file_path, &verified_data, enclave.compression_threshold)
+		.map_err(|err| anyhow!("SIMULATION : {err}"))?;
+
+	let log_path = enclave.sealed_file(format!("{}.log", verified_data.nft_id));
+	if !create_store_log(&log_path, SIMULATION_BLOCK, &request.owner_address) {
+		return Err(anyhow!("SIMULATION : error creating log file of {}", verified_data.nft_id))
+	}
+
+	Ok(())
+}
+
+/// Steps of the retrieve-keyshare endpoint : verification, replay journal, audit, keyshare read
+/// and history log
+async fn simulate_retrieve(enclave: &SimulatedEnclave, body: &str) -> Result<(), anyhow::Error> {
+	let request: RetrieveKeysharePacket = serde_json::from_str(body)?;
+	let verification = match request.verify_retrieve_access(&enclave.chain, NftKind::SECRET).await {
+		Ok(verified_data) => {
+			let (digest, expiry_block) = request.replay_digest(&verified_data);
+			if enclave.register_request(digest, expiry_block).await {
+				Ok(verified_data)
+			} else {
+				Err(VerificationError::REPLAYEDREQUEST)
+			}
+		},
+		Err(err) => Err(err),
+	};
+	enclave
+		.audit(
+			APICALL::NFTRETRIEVE,
+			request.requested_nft_id(),
+			request.requester_address.to_string(),
+			Some(request.requester_type),
+			&verification,
+		)
+		.await?;
+
+	let verified_data = verification
+		.map_err(|err| anyhow!("SIMULATION : retrieve verification failed : {err:?}"))?;
+
+	let file_path =
+		enclave.sealed_file(format!("nft_{}_{SIMULATION_BLOCK}.keyshare", verified_data.nft_id));
+	let mut file = File::open(file_path)?;
+
+	let (response_buffer, _) =
+		helper::read_keyshare_into_response(&mut file, verified_data.nft_id)?;
+	let auth_token =
+		AuthenticationToken { block_number: SIMULATION_BLOCK, block_validation: 15 }.serialize();
+	helper::finish_keyshare_response(response_buffer, &auth_token)?;
+
+	update_log_file_view(
+		SIMULATION_BLOCK,
+		enclave.sealed_file(format!("{}.log", verified_data.nft_id)),
+		request.requester_address.to_string(),
+		request.requester_type,
+		LogType::VIEW,
+		"secret-nft",
+	);
+
+	Ok(())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Phase {
+	STORE,
+	RETRIEVE,
+}
+
+/// Process all requests with `cores` parallel workers, as fast as possible
+async fn run_phase(
+	runtime: &tokio::runtime::Runtime,
+	phase: Phase,
+	enclave: Arc<SimulatedEnclave>,
+	requests: Vec<String>,
+	config: &SimulationConfig,
+) -> PhaseReport {
+	let total = requests.len();
+	let chunk_size = total.div_ceil(config.cores).max(1);
+	let chunks: Vec<Vec<String>> =
+		requests.chunks(chunk_size).map(|chunk| chunk.to_vec()).collect();
+
+	let start = Instant::now();
+	let mut workers = Vec::new();
+
+	for chunk in chunks {
+		let enclave = enclave.clone();
+
+		workers.push(runtime.spawn(async move {
+			let mut failures = 0;
+			for body in chunk {
+				let result = match phase {
+					Phase::STORE => simulate_store(&enclave, &body).await,
+					Phase::RETRIEVE => simulate_retrieve(&enclave, &body).await,
+				};
+
+				if let Err(err) = result {
+					debug!("SIMULATION : {:?} request failed : {err:?}", phase);
+					failures += 1;
+				}
+			}
+			failures
+		}));
+	}
+
+	let mut failures = 0;
+	for worker in workers {
+		match worker.await {
+			Ok(worker_failures) => failures += worker_failures,
+			Err(err) => warn!("SIMULATION : {:?} worker failed : {err:?}", phase),
+		}
+	}
+
+	let elapsed = start.elapsed();
+	let succeeded = total.saturating_sub(failures);
+
+	PhaseReport {
+		requests: total,
+		failures,
+		elapsed_ms: elapsed.as_millis(),
+		max_per_sec: succeeded as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+	}
+}
+
+/// Allocated size of the sealed keyshares
+fn sealed_disk_usage(seal_path: &Path) -> Result<(u64, u64), anyhow::Error> {
+	use std::os::unix::fs::MetadataExt;
+
+	let mut files = 0;
+	let mut allocated = 0;
+
+	for entry in std::fs::read_dir(seal_path)? {
+		let entry = entry?;
+		let metadata = entry.metadata()?;
+		if metadata.is_file() && entry.path().extension().map_or(false, |ext| ext == "keyshare") {
+			files += 1;
+			allocated += metadata.blocks() * 512;
+		}
+	}
+
+	Ok((files, allocated))
+}
+
+/// Run the simulation against a temporary seal path and estimate the sustainable workload
+/// # Arguments
+/// * `config` - synthetic workload and hardware parameters
+/// # Returns
+/// * `SimulationReport` - measured throughput and disk growth
+pub async fn run_simulation(config: SimulationConfig) -> Result<SimulationReport, anyhow::Error> {
+	if config.cores == 0 || config.requests == 0 {
+		return Err(anyhow!("SIMULATION : cores and requests must be positive"))
+	}
+
+	if config.seal_path.exists() {
+		return Err(anyhow!(
+			"SIMULATION : seal path {:?} already exists, use a new directory",
+			config.seal_path
+		))
+	}
+	std::fs::create_dir_all(&config.seal_path)?;
+
+	let result = simulate(&config).await;
+
+	if let Err(err) = std::fs::remove_dir_all(&config.seal_path) {
+		warn!("SIMULATION : error removing {:?} : {err:?}", config.seal_path);
+	}
+
+	result
+}
+
+async fn simulate(config: &SimulationConfig) -> Result<SimulationReport, anyhow::Error> {
+	let owner = sr25519::Pair::generate().0;
+	let signer = sr25519::Pair::generate().0;
+	let owner_account = AccountId32::from(owner.public().0);
+
+	info!("SIMULATION : generating {} signed requests per phase", config.requests);
+	let store_requests =
+		generate_store_requests(&owner, &signer, &config.keyshare_sizes, config.requests)?;
+	let retrieve_requests = generate_retrieve_requests(&owner, config.requests, config.requests);
+	let average_keyshare_bytes = config
+		.keyshare_sizes
+		.iter()
+		.fold(0u64, |sum, (size, weight)| sum + (*size as u64) * (*weight as u64)) /
+		config.keyshare_sizes.iter().map(|(_, weight)| *weight as u64).sum::<u64>();
+
+	// The simulated machine
+	let runtime = tokio::runtime::Builder::new_multi_thread()
+		.worker_threads(config.cores)
+		.enable_all()
+		.build()?;
+
+	let enclave = Arc::new(SimulatedEnclave {
+		chain: SimulatedChain { owner: owner_account, syncing: AtomicBool::new(true) },
+		seal_path: config.seal_path.clone(),
+		compression_threshold: config.compression_threshold,
+		replay_journal: RwLock::new(ReplayJournal::default()),
+		journal_appends: Mutex::new(0),
+		audit_head: Mutex::new(AuditHead::default()),
+	});
+
+	info!("SIMULATION : store phase on {} cores", config.cores);
+	let store = run_phase(&runtime, Phase::STORE, enclave.clone(), store_requests, config).await;
+
+	// Stored keyshares are synced onchain before they are retrieved
+	enclave.chain.syncing.store(false, Ordering::Relaxed);
+
+	info!("SIMULATION : retrieve phase on {} cores", config.cores);
+	let retrieve =
+		run_phase(&runtime, Phase::RETRIEVE, enclave.clone(), retrieve_requests, config).await;
+
+	runtime.shutdown_background();
+
+	let (files, allocated) = sealed_disk_usage(&config.seal_path)?;
+	let average_sealed_bytes = allocated / files.max(1);
+
+	let utilization = config.stores_per_sec / store.max_per_sec.max(f64::EPSILON) +
+		config.retrieves_per_sec / retrieve.max_per_sec.max(f64::EPSILON);
+
+	let disk_growth_per_day_bytes =
+		(config.stores_per_sec * SECONDS_PER_DAY * average_sealed_bytes as f64) as u64;
+
+	let days_until_disk_full = match config.disk_gb {
+		Some(disk_gb) if disk_growth_per_day_bytes > 0 =>
+			Some(disk_gb * 1e9 / disk_growth_per_day_bytes as f64),
+		_ => None,
+	};
+
+	Ok(SimulationReport {
+		cores: config.cores,
+		store,
+		retrieve,
+		average_keyshare_bytes,
+		average_sealed_bytes,
+		utilization,
+		sustainable: utilization <= SIMULATION_MAX_UTILIZATION,
+		disk_growth_per_day_bytes,
+		days_until_disk_full,
+	})
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn keyshare_sizes_test() {
+		assert_eq!(parse_keyshare_sizes("512:70, 2048:30").unwrap(), vec![(512, 70), (2048, 30)]);
+		assert_eq!(parse_keyshare_sizes("1024").unwrap(), vec![(1024, 1)]);
+		assert!(parse_keyshare_sizes("8:1").is_err());
+		assert!(parse_keyshare_sizes("512:0").is_err());
+		assert!(parse_keyshare_sizes("big").is_err());
+	}
+
+	#[tokio::test]
+	async fn simulation_test() {
+		let seal_path =
+			std::env::temp_dir().join(format!("simulation-test-{}", std::process::id()));
+
+		let report = run_simulation(SimulationConfig {
+			stores_per_sec: 1.0,
+			retrieves_per_sec: 1.0,
+			keyshare_sizes: vec![(256, 1), (2048, 1)],
+			cores: 2,
+			disk_gb: Some(100.0),
+			requests: 20,
+			compression_threshold: 1024,
+			seal_path: seal_path.clone(),
+		})
+		.await
+		.unwrap();
+
+		assert_eq!(report.store.requests, 20);
+		assert_eq!(report.store.failures, 0);
+		assert_eq!(report.retrieve.failures, 0);
+		assert!(report.store.max_per_sec > 0.0);
+		assert!(report.average_sealed_bytes > 0);
+		assert!(report.days_until_disk_full.unwrap() > 0.0);
+
+		// Temporary seal path is removed
+		assert!(!seal_path.exists());
+	}
+}
@@ -3,16 +3,21 @@
 #![allow(unused_variables)]
 #![allow(clippy::upper_case_acronyms)]
 
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
 use hex::FromHex;
 use serde_json::Value;
-use std::str::FromStr;
+use std::{ops::Deref, str::FromStr};
+use zeroize::{Zeroize, Zeroizing};
 
 use subxt::{
-	ext::sp_core::{crypto::Ss58Codec, sr25519, ByteArray, Pair},
+	ext::sp_core::{
+		crypto::{Ss58AddressFormat, Ss58Codec},
+		sr25519, ByteArray, Pair, H256,
+	},
 	utils::AccountId32,
 };
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
 use axum::{
 	http::{header, StatusCode},
@@ -25,23 +30,39 @@ use tracing::{debug, error, info};
 
 use crate::{
 	chain::{
+		compression::BINARY_MAGIC,
 		constants::*,
 		core::{
-			get_current_block_number, get_onchain_delegatee, get_onchain_nft_data,
-			get_onchain_rent_contract,
+			get_common_block_hash, get_current_block_number, get_delegatee_from_api,
+			get_nft_data_from_api, get_onchain_delegatee, get_onchain_nft_data,
+			get_onchain_rent_contract, get_rentee_from_api,
+			ternoa::runtime_types::ternoa_pallets_primitives::nfts::NFTData, DefaultApi,
+		},
+		jws::{
+			decode_jws, RetrieveJwsPayload, StoreJwsPayload, REQUEST_VERSION_BINARY,
+			REQUEST_VERSION_JWS, REQUEST_VERSION_LEGACY,
 		},
+		parser::{
+			parse_binary_store, parse_retrieve, parse_signer, parse_store, ParseError,
+			RetrieveFields,
+		},
+		policy::keyshare_policy,
+		quota::record_signature_failure,
+		reader::{ChainReader, OnchainNft},
+		rental::{rental_access, RentalAccess},
+		replay::register_request,
+		requester::requester_registry,
+		signature::{verify_account_signature, SignatureScheme},
 	},
-	servers::state::{get_blocknumber, SharedState},
+	servers::state::{get_blocknumber, get_chain_api, get_secondary_chain_api, SharedState},
 };
 
-use super::core::get_current_block_number_new_api;
-
 /* **********************
   DATA STRUCTURES
 ********************** */
 
 /// API Call
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum APICALL {
 	NFTSTORE,
 	NFTRETRIEVE,
@@ -49,13 +70,47 @@ pub enum APICALL {
 	CAPSULESET,
 	CAPSULERETRIEVE,
 	CAPSULEREMOVE,
+	NFTBATCHSTORE,
+	NFTBATCHRETRIEVE,
+	CAPSULEUPDATE,
+}
+
+impl APICALL {
+	/// Uri path of the call
+	pub fn endpoint(&self) -> &'static str {
+		match self {
+			APICALL::NFTSTORE => "/api/secret-nft/store-keyshare",
+			APICALL::NFTRETRIEVE => "/api/secret-nft/retrieve-keyshare",
+			APICALL::NFTREMOVE => "/api/secret-nft/remove-keyshare",
+			APICALL::CAPSULESET => "/api/capsule-nft/set-keyshare",
+			APICALL::CAPSULERETRIEVE => "/api/capsule-nft/retrieve-keyshare",
+			APICALL::CAPSULEREMOVE => "/api/capsule-nft/remove-keyshare",
+			APICALL::NFTBATCHSTORE => "/api/secret-nft/batch-store-keyshare",
+			APICALL::NFTBATCHRETRIEVE => "/api/secret-nft/batch-retrieve-keyshare",
+			APICALL::CAPSULEUPDATE => "/api/capsule-nft/update-keyshare",
+		}
+	}
+
+	/// Kind of the nft protected by the keyshare
+	pub fn kind(&self) -> NftKind {
+		match self {
+			APICALL::CAPSULESET |
+			APICALL::CAPSULERETRIEVE |
+			APICALL::CAPSULEREMOVE |
+			APICALL::CAPSULEUPDATE => NftKind::CAPSULE,
+			_ => NftKind::SECRET,
+		}
+	}
 }
 
-#[derive(Serialize, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
 pub enum ReturnStatus {
 	STORESUCCESS,
 	RETRIEVESUCCESS,
 	REMOVESUCCESS,
+	UPDATESUCCESS,
+	// Keyshare is retrieved by the rentee of a lapsed rental, during its grace period
+	RENTALGRACEPERIOD,
 
 	SIGNERSIGVERIFICATIONFAILED,
 	DATASIGVERIFICATIONFAILED,
@@ -77,6 +132,9 @@ pub enum ReturnStatus {
 
 	KEYSHAREISTOOSHORT,
 	KEYSHAREISTOOLONG,
+	KEYSHAREINVALIDENCODING,
+	KEYSHARECONTAINSCONTROLCHAR,
+	KEYSHARELOWENTROPY,
 
 	EXPIREDSIGNER,
 	EXPIREDREQUEST,
@@ -100,6 +158,85 @@ pub enum ReturnStatus {
 
 	INTERNALSTATELOCKED,
 	InvalidBlockNumber,
+
+	REPLAYEDREQUEST,
+	// Call or nft_id is not in the scope of the session key
+	SESSIONOUTOFSCOPE,
+	// High-value nft without a valid signature of its co-signer
+	COSIGNATUREREQUIRED,
+	// Valid item of a failed all-or-nothing batch
+	BATCHABORTED,
+
+	READONLYMODE,
+	RATELIMITED,
+	// Admin fetch beyond the id or archive size limits
+	BACKUPLIMITEXCEEDED,
+}
+
+impl ReturnStatus {
+	/// Stable numeric code of the status, SDKs should match on it instead of the description.
+	/// Codes are grouped by class and are never reused, see docs/error-response.schema.json
+	pub fn code(&self) -> u16 {
+		match self {
+			// 1xxx : success
+			ReturnStatus::STORESUCCESS => 1000,
+			ReturnStatus::RETRIEVESUCCESS => 1001,
+			ReturnStatus::REMOVESUCCESS => 1002,
+			ReturnStatus::UPDATESUCCESS => 1003,
+			ReturnStatus::RENTALGRACEPERIOD => 1004,
+
+			// 2xxx : request format
+			ReturnStatus::INVALIDDATAFORMAT => 2000,
+			ReturnStatus::INVALIDSIGNERFORMAT => 2001,
+			ReturnStatus::INVALIDOWNERADDRESS => 2002,
+			ReturnStatus::INVALIDSIGNERADDRESS => 2003,
+			ReturnStatus::INVALIDNFTID => 2004,
+			ReturnStatus::InvalidBlockNumber => 2005,
+			ReturnStatus::BACKUPLIMITEXCEEDED => 2006,
+			ReturnStatus::INVALIDKEYSHARE => 2100,
+			ReturnStatus::KEYSHAREISTOOSHORT => 2101,
+			ReturnStatus::KEYSHAREISTOOLONG => 2102,
+			ReturnStatus::KEYSHAREINVALIDENCODING => 2103,
+			ReturnStatus::KEYSHARECONTAINSCONTROLCHAR => 2104,
+			ReturnStatus::KEYSHARELOWENTROPY => 2105,
+
+			// 3xxx : signatures and authentication token
+			ReturnStatus::INVALIDSIGNERSIGNATURE => 3000,
+			ReturnStatus::INVALIDDATASIGNATURE => 3001,
+			ReturnStatus::SIGNERSIGVERIFICATIONFAILED => 3002,
+			ReturnStatus::DATASIGVERIFICATIONFAILED => 3003,
+			ReturnStatus::INVALIDAUTHTOKEN => 3100,
+			ReturnStatus::EXPIREDSIGNER => 3101,
+			ReturnStatus::EXPIREDREQUEST => 3102,
+			ReturnStatus::REPLAYEDREQUEST => 3103,
+			ReturnStatus::SESSIONOUTOFSCOPE => 3200,
+			ReturnStatus::COSIGNATUREREQUIRED => 3300,
+
+			// 4xxx : on-chain state and ownership
+			ReturnStatus::OWNERSHIPVERIFICATIONFAILED => 4000,
+			ReturnStatus::REQUESTERVERIFICATIONFAILED => 4001,
+			ReturnStatus::IDISNOTASECRETNFT => 4100,
+			ReturnStatus::IDISNOTACAPSULE => 4101,
+			ReturnStatus::IDISNOTENCRYPTED => 4102,
+			ReturnStatus::NOTBURNT => 4103,
+			ReturnStatus::NOTSYNCING => 4104,
+			ReturnStatus::NOTSYNCED => 4105,
+
+			// 5xxx : keyshare storage
+			ReturnStatus::NFTIDEXISTS => 5000,
+			ReturnStatus::KEYNOTEXIST => 5001,
+			ReturnStatus::KEYNOTACCESSIBLE => 5002,
+			ReturnStatus::KEYNOTREADABLE => 5003,
+			ReturnStatus::DATABASEFAILURE => 5004,
+			ReturnStatus::BATCHABORTED => 5005,
+
+			// 6xxx : enclave
+			ReturnStatus::ORACLEFAILURE => 6000,
+			ReturnStatus::INTERNALSTATELOCKED => 6001,
+			ReturnStatus::READONLYMODE => 6002,
+			ReturnStatus::RATELIMITED => 6003,
+		}
+	}
 }
 
 // Errors when parsing signature
@@ -130,18 +267,32 @@ pub enum VerificationError {
 
 	KEYSHAREISTOOSHORT,
 	KEYSHAREISTOOLONG,
+	INVALIDKEYSHAREENCODING,
+	KEYSHARECONTROLCHAR,
+	LOWENTROPYKEYSHARE,
 
 	INVALIDAUTHTOKEN,
 	INVALIDKEYSHARE,
 	INVALIDNFTID,
 
-	EXPIREDSIGNER(ValidationResult),
-	EXPIREDDATA(ValidationResult),
+	EXPIREDSIGNER(ClockSkew),
+	EXPIREDDATA(ClockSkew),
 
 	IDISNOTSECRETNFT,
 	IDISNOTCAPSULE,
 	NOTSYNCING,
 	NOTSYNCED,
+	NOTBURNT,
+
+	ORACLEFAILURE,
+	REPLAYEDREQUEST,
+	SESSIONOUTOFSCOPE,
+	COSIGNATUREREQUIRED,
+
+	// Admin read-only switch is active until the block number
+	READONLY(u32),
+	// Quota of the requester is exhausted, seconds to wait before retrying
+	RATELIMITED(u64),
 }
 
 // Validity time of Keyshare Data
@@ -151,12 +302,14 @@ pub struct AuthenticationToken {
 	pub block_validation: u32,
 }
 
-// Keyshare Data structure
+// Keyshare Data structure, the keyshare is wiped from memory when dropped
 #[derive(Clone, Debug, PartialEq)]
 pub struct StoreKeyshareData {
 	pub nft_id: u32,
-	pub keyshare: Vec<u8>,
+	pub keyshare: Zeroizing<Vec<u8>>,
 	pub auth_token: AuthenticationToken,
+	// Keyshare is decoded from base64url, REQUEST_VERSION_BINARY
+	pub binary: bool,
 }
 
 // Packet-signer and validity of it
@@ -168,15 +321,31 @@ pub struct Signer {
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct StoreKeysharePacket {
+	#[serde(deserialize_with = "deserialize_ss58")]
 	pub owner_address: sr25519::Public,
 
 	// Signed by owner
 	signer_address: String,
 	signersig: String,
 
-	// Signed by signer
+	// Signed by signer, legacy data or JWS token
 	pub data: String,
+	#[serde(default)]
 	pub signature: String,
+
+	#[serde(default = "legacy_request_version")]
+	pub version: u8,
+
+	// Scheme of the owner wallet, detected from the signature if missing
+	#[serde(default)]
+	pub signature_type: Option<SignatureScheme>,
+}
+
+// The data carries the plaintext keyshare
+impl Drop for StoreKeysharePacket {
+	fn drop(&mut self) {
+		self.data.zeroize();
+	}
 }
 
 // Keyshare Data structure
@@ -184,39 +353,241 @@ pub struct StoreKeysharePacket {
 pub struct RetrieveKeyshareData {
 	pub nft_id: u32,
 	pub auth_token: AuthenticationToken,
+	// End block of the grace period of a lapsed rental, set by the verification
+	pub rental_grace: Option<u32>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+impl From<RetrieveFields> for RetrieveKeyshareData {
+	fn from(fields: RetrieveFields) -> Self {
+		RetrieveKeyshareData {
+			nft_id: fields.nft_id,
+			auth_token: AuthenticationToken {
+				block_number: fields.block_number,
+				block_validation: fields.block_validation,
+			},
+			rental_grace: None,
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum RequesterType {
 	OWNER,
 	DELEGATEE,
 	RENTEE,
 }
 
+/// Scope of a session key, json serialized and signed by the requester wallet.
+/// The session key signs retrieve requests for the requester, so a hot wallet never holds the
+/// requester key.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SessionGrant {
+	pub session_key: String,
+	pub nft_ids: Vec<u32>,
+	// NFTRETRIEVE, CAPSULERETRIEVE or NFTBATCHRETRIEVE
+	pub calls: Vec<APICALL>,
+	// Last block number that the session key is valid
+	pub expiry_block: u32,
+}
+
+// Session key delegation of a retrieve request
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SessionDelegation {
+	// SessionGrant, json serialized
+	pub grant: String,
+	// Signed by requester
+	pub signature: String,
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct RetrieveKeysharePacket {
+	#[serde(deserialize_with = "deserialize_ss58")]
 	pub requester_address: sr25519::Public,
 	pub requester_type: RequesterType,
+	// Legacy data or JWS token, signed by requester or its session key
 	pub data: String,
+	#[serde(default)]
 	pub signature: String,
+
+	#[serde(default = "legacy_request_version")]
+	pub version: u8,
+
+	// Scheme of the requester wallet, detected from the signature if missing
+	#[serde(default)]
+	pub signature_type: Option<SignatureScheme>,
+
+	#[serde(default)]
+	pub session: Option<SessionDelegation>,
+
+	// Signature of the data by the co-signer of a high-value nft
+	#[serde(default)]
+	pub cosignature: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct RemoveKeysharePacket {
+	#[serde(deserialize_with = "deserialize_ss58")]
+	pub requester_address: sr25519::Public,
+	pub data: String,
+	pub signature: String,
+
+	#[serde(default)]
+	pub signature_type: Option<SignatureScheme>,
+}
+
+/// Failure handling of batch requests
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub enum BatchAtomicity {
+	// Every item succeeds or fails on its own
+	#[default]
+	BESTEFFORT,
+	// Nothing is stored or returned if an item fails
+	ALLORNOTHING,
+}
+
+// Item of a batch store data
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct BatchStoreEntry {
+	pub nft_id: u32,
+	pub keyshare: String,
+}
+
+impl Drop for BatchStoreEntry {
+	fn drop(&mut self) {
+		self.keyshare.zeroize();
+	}
+}
+
+// Signed data of a batch store request, json serialized
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct BatchStoreData {
+	pub entries: Vec<BatchStoreEntry>,
+	pub block_number: u32,
+	pub block_validation: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BatchStoreKeysharePacket {
+	#[serde(deserialize_with = "deserialize_ss58")]
+	pub owner_address: sr25519::Public,
+
+	// Signed by owner
+	signer_address: String,
+	signersig: String,
+
+	// Signed by signer, BatchStoreData
+	pub data: String,
+	pub signature: String,
+
+	#[serde(default)]
+	pub atomicity: BatchAtomicity,
+
+	// Scheme of the owner wallet, detected from the signature if missing
+	#[serde(default)]
+	pub signature_type: Option<SignatureScheme>,
+}
+
+// The data carries the plaintext keyshares of the batch
+impl Drop for BatchStoreKeysharePacket {
+	fn drop(&mut self) {
+		self.data.zeroize();
+	}
+}
+
+// Signed data of a batch retrieve request, json serialized
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct BatchRetrieveData {
+	pub nft_ids: Vec<u32>,
+	pub block_number: u32,
+	pub block_validation: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BatchRetrieveKeysharePacket {
+	#[serde(deserialize_with = "deserialize_ss58")]
 	pub requester_address: sr25519::Public,
+	pub requester_type: RequesterType,
+
+	// Signed by requester or its session key, BatchRetrieveData
+	pub data: String,
+	pub signature: String,
+
+	#[serde(default)]
+	pub atomicity: BatchAtomicity,
+
+	// Scheme of the requester wallet, detected from the signature if missing
+	#[serde(default)]
+	pub signature_type: Option<SignatureScheme>,
+
+	#[serde(default)]
+	pub session: Option<SessionDelegation>,
+
+	// Signature of the batch data by the co-signer of its high-value nfts
+	#[serde(default)]
+	pub cosignature: Option<String>,
+}
+
+// Signed data of a capsule keyshare update, json serialized so the keyshare may contain '_'
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateKeyshareData {
+	pub nft_id: u32,
+	pub keyshare: String,
+	pub block_number: u32,
+	pub block_validation: u32,
+}
+
+impl Drop for UpdateKeyshareData {
+	fn drop(&mut self) {
+		self.keyshare.zeroize();
+	}
+}
+
+/// Replace the keyshare of a synced capsule in place, instead of a remove and a new store
+#[derive(Serialize, Deserialize, Clone)]
+pub struct UpdateKeysharePacket {
+	#[serde(deserialize_with = "deserialize_ss58")]
+	pub owner_address: sr25519::Public,
+
+	// Signed by owner
+	signer_address: String,
+	signersig: String,
+
+	// Signed by signer, UpdateKeyshareData
 	pub data: String,
 	pub signature: String,
+
+	// Scheme of the owner wallet, detected from the signature if missing
+	#[serde(default)]
+	pub signature_type: Option<SignatureScheme>,
+}
+
+// The data carries the new plaintext keyshare
+impl Drop for UpdateKeysharePacket {
+	fn drop(&mut self) {
+		self.data.zeroize();
+	}
 }
 
+/// Per-item verification result of a batch request, in request order
+pub type BatchVerification<T> = Vec<(u32, Result<T, VerificationError>)>;
+
 #[derive(Debug, PartialEq)]
 pub enum KeyshareHolder {
 	Owner(AccountId32),
 	Delegatee(AccountId32),
 	Rentee(AccountId32),
 	NotFound,
+	// Primary and secondary rpc disagree
+	Diverged,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
+#[serde(into = "ApiErrorPayload")]
 pub struct ApiErrorResponse {
 	pub status: ReturnStatus,
 	pub nft_id: u32,
@@ -224,6 +595,123 @@ pub struct ApiErrorResponse {
 	pub description: String,
 }
 
+/// Wire format of single-nft responses, docs/error-response.schema.json
+/// The description is for humans, clients must rely on `code` (or `status`)
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ApiErrorPayload {
+	pub status: ReturnStatus,
+	pub code: u16,
+	pub nft_id: u32,
+	pub enclave_account: String,
+	pub description: String,
+}
+
+impl ApiErrorPayload {
+	pub fn new(
+		status: ReturnStatus,
+		nft_id: u32,
+		enclave_account: String,
+		description: String,
+	) -> ApiErrorPayload {
+		ApiErrorPayload { status, code: status.code(), nft_id, enclave_account, description }
+	}
+}
+
+impl From<ApiErrorResponse> for ApiErrorPayload {
+	fn from(response: ApiErrorResponse) -> Self {
+		ApiErrorPayload::new(
+			response.status,
+			response.nft_id,
+			response.enclave_account,
+			response.description,
+		)
+	}
+}
+
+/// Verification step at which a request has failed
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum VerificationStep {
+	PARSING,
+	SIGNATURE,
+	AUTHTOKEN,
+	ONCHAINSTATE,
+	OWNERSHIP,
+	STORAGE,
+	ENCLAVEMODE,
+	QUOTA,
+}
+
+/// How a client should deal with a failed item
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum Retryability {
+	// Retry with the same request, i.e. transient rpc or disk error
+	RETRYABLE,
+	// Retry with a new authentication token
+	RESIGN,
+	// Retry after on-chain state changes, i.e. syncing is finished
+	WAITONCHAIN,
+	// The request will never succeed
+	PERMANENT,
+}
+
+/// Per-NFT result of batch operations
+#[derive(Serialize, Debug, Clone)]
+pub struct BatchItemResult {
+	pub nft_id: u32,
+	pub status: ReturnStatus,
+	pub code: u16,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub step: Option<VerificationStep>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub retryable: Option<Retryability>,
+	// sha256 of on-chain nft data at the time of verification
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub state_hash: Option<String>,
+	pub description: String,
+}
+
+impl BatchItemResult {
+	/// Successful item
+	pub fn success(nft_id: u32, status: ReturnStatus) -> BatchItemResult {
+		BatchItemResult {
+			nft_id,
+			status,
+			code: status.code(),
+			step: None,
+			retryable: None,
+			state_hash: None,
+			description: "Success".to_string(),
+		}
+	}
+
+	/// Failed item, outside of the request verification (i.e. disk failure)
+	pub fn failure(
+		nft_id: u32,
+		status: ReturnStatus,
+		step: VerificationStep,
+		retryable: Retryability,
+		description: String,
+	) -> BatchItemResult {
+		BatchItemResult {
+			nft_id,
+			status,
+			code: status.code(),
+			step: Some(step),
+			retryable: Some(retryable),
+			state_hash: None,
+			description,
+		}
+	}
+}
+
+/// Failed items of a batch, `errors` array of the batch responses
+/// # Arguments
+/// * `results` - per-item results
+/// * `success` - status of a successful item
+pub fn batch_errors(results: &[BatchItemResult], success: ReturnStatus) -> Vec<BatchItemResult> {
+	results.iter().filter(|result| result.status != success).cloned().collect()
+}
+
 impl VerificationError {
 	/// Express the error in JSON format
 	/// # Arguments
@@ -240,6 +728,10 @@ impl VerificationError {
 		nft_id: u32,
 		enclave_account: String,
 	) -> (StatusCode, Json<Value>) {
+		if self.is_signature_failure() {
+			record_signature_failure();
+		}
+
 		match self {
 			// SIGNER SIGNATURE FORMAT
 			VerificationError::INVALIDSIGNERSIG(err) => {
@@ -247,7 +739,7 @@ impl VerificationError {
 				let description = format!(
 					"TEE Key-share {call:?}: Invalid request signer signature format, {err:?} "
 				);
-				info!("{}, requester : {}", description, caller);
+				info!(?status, nft_id, requester = %caller, "{}", description);
 
 				(
 					StatusCode::BAD_REQUEST,
@@ -269,7 +761,7 @@ impl VerificationError {
 				let description = format!(
 					"TEE Key-share {call:?}: Invalid request data signature format, {err:?}"
 				);
-				info!("{}, requester : {}", description, caller);
+				info!(?status, nft_id, requester = %caller, "{}", description);
 
 				(
 					StatusCode::BAD_REQUEST,
@@ -289,7 +781,7 @@ impl VerificationError {
 			VerificationError::INVALIDOWNERADDRESS => {
 				let status = ReturnStatus::INVALIDOWNERADDRESS;
 				let description = format!("TEE Key-share {call:?}: Invalid owner address format");
-				info!("{}, requester : {}", description, caller);
+				info!(?status, nft_id, requester = %caller, "{}", description);
 
 				(
 					StatusCode::BAD_REQUEST,
@@ -309,7 +801,7 @@ impl VerificationError {
 			VerificationError::INVALIDSIGNERADDRESS => {
 				let status = ReturnStatus::INVALIDSIGNERADDRESS;
 				let description = format!("TEE Key-share {call:?}: Invalid signer address format");
-				info!("{}, requester : {}", description, caller);
+				info!(?status, nft_id, requester = %caller, "{}", description);
 
 				(
 					StatusCode::BAD_REQUEST,
@@ -329,7 +821,7 @@ impl VerificationError {
 			VerificationError::SIGNERVERIFICATIONFAILED => {
 				let status = ReturnStatus::SIGNERSIGVERIFICATIONFAILED;
 				let description = format!("TEE Key-share {call:?}: Signer signature verification failed, Signer is not approved by NFT owner");
-				info!("{}, requester : {}", description, caller);
+				info!(?status, nft_id, requester = %caller, "{}", description);
 
 				(
 					StatusCode::BAD_REQUEST,
@@ -350,7 +842,7 @@ impl VerificationError {
 				let status = ReturnStatus::DATASIGVERIFICATIONFAILED;
 				let description =
 					format!("TEE Key-share {call:?}: Data signature verification failed.");
-				info!("{}, requester : {}", description, caller);
+				info!(?status, nft_id, requester = %caller, "{}", description);
 
 				(
 					StatusCode::BAD_REQUEST,
@@ -371,7 +863,7 @@ impl VerificationError {
 				let status = ReturnStatus::INVALIDAUTHTOKEN;
 				let description =
 					format!("TEE Key-share {call:?}: Invalid authentication-token format.");
-				info!("{}, requester : {}", description, caller);
+				info!(?status, nft_id, requester = %caller, "{}", description);
 
 				(
 					StatusCode::BAD_REQUEST,
@@ -393,7 +885,7 @@ impl VerificationError {
 				let description = format!(
 					"TEE Key-share {call:?}: The nft-id is not a valid number or nft does not exist."
 				);
-				info!("{}, requester : {}", description, caller);
+				info!(?status, nft_id, requester = %caller, "{}", description);
 
 				(
 					StatusCode::BAD_REQUEST,
@@ -415,7 +907,7 @@ impl VerificationError {
 				let description = format!(
 					"TEE Key-share {call:?}: The key-share is empty or not a valid string."
 				);
-				info!("{}, requester : {}", description, caller);
+				info!(?status, nft_id, requester = %caller, "{}", description);
 
 				(
 					StatusCode::BAD_REQUEST,
@@ -436,7 +928,7 @@ impl VerificationError {
 				let status = ReturnStatus::OWNERSHIPVERIFICATIONFAILED;
 				let description =
 					format!("TEE Key-share {call:?}: The nft-id is not owned by this owner.");
-				info!("{}, requester : {}", description, caller);
+				info!(?status, nft_id, requester = %caller, "{}", description);
 
 				(
 					StatusCode::UNAUTHORIZED,
@@ -457,7 +949,7 @@ impl VerificationError {
 				let description = format!(
 					"TEE Key-share {call:?}: The requester is not either owner, delegatee or rentee."
 				);
-				info!("{}, requester : {}", description, caller);
+				info!(?status, nft_id, requester = %caller, "{}", description);
 
 				(
 					StatusCode::BAD_REQUEST,
@@ -474,43 +966,41 @@ impl VerificationError {
 			},
 
 			// EPIRATION PERIOD OF SIGNER ACCOUNT  (AUTHENTICATION-TOKEN)
-			VerificationError::EXPIREDSIGNER(err) => {
+			VerificationError::EXPIREDSIGNER(skew) => {
 				let status = ReturnStatus::EXPIREDSIGNER;
 				let description = format!("TEE Key-share {call:?}: The signer account has been expired or is not in valid range.");
-				info!("{}, requester : {}", description, caller);
+				info!(?status, nft_id, requester = %caller, ?skew, "{}", description);
+
+				let mut body = serde_json::to_value(ApiErrorResponse {
+					status,
+					nft_id,
+					enclave_account,
+					description,
+				})
+				.unwrap();
+				// Clients correct their block number from the enclave clock
+				body["clock_skew"] = serde_json::to_value(skew).unwrap_or_default();
 
-				(
-					StatusCode::BAD_REQUEST,
-					Json(
-						serde_json::to_value(ApiErrorResponse {
-							status,
-							nft_id,
-							enclave_account,
-							description,
-						})
-						.unwrap(),
-					),
-				)
+				(StatusCode::BAD_REQUEST, Json(body))
 			},
 
 			// EPIRATION PERIOD OF REQUEST DATA  (AUTHENTICATION-TOKEN)
-			VerificationError::EXPIREDDATA(err) => {
+			VerificationError::EXPIREDDATA(skew) => {
 				let status = ReturnStatus::EXPIREDREQUEST;
 				let description = format!("TEE Key-share {call:?}: The request data field has been expired  or is not in valid range.");
-				info!("{}, requester : {}", description, caller);
+				info!(?status, nft_id, requester = %caller, ?skew, "{}", description);
+
+				let mut body = serde_json::to_value(ApiErrorResponse {
+					status,
+					nft_id,
+					enclave_account,
+					description,
+				})
+				.unwrap();
+				// Clients correct their block number from the enclave clock
+				body["clock_skew"] = serde_json::to_value(skew).unwrap_or_default();
 
-				(
-					StatusCode::BAD_REQUEST,
-					Json(
-						serde_json::to_value(ApiErrorResponse {
-							status,
-							nft_id,
-							enclave_account,
-							description,
-						})
-						.unwrap(),
-					),
-				)
+				(StatusCode::BAD_REQUEST, Json(body))
 			},
 
 			// IS NOT ENCRYPTED ENTITY
@@ -518,7 +1008,7 @@ impl VerificationError {
 				let status = ReturnStatus::IDISNOTASECRETNFT;
 				let description =
 					format!("TEE Key-share {call:?}: The nft-id is not a secret-nft.");
-				info!("{}, requester : {}", description, caller);
+				info!(?status, nft_id, requester = %caller, "{}", description);
 
 				(
 					StatusCode::BAD_REQUEST,
@@ -538,7 +1028,7 @@ impl VerificationError {
 			VerificationError::IDISNOTCAPSULE => {
 				let status = ReturnStatus::IDISNOTACAPSULE;
 				let description = format!("TEE Key-share {call:?}: The nft-id is not a capsule.");
-				info!("{}, requester : {}", description, caller);
+				info!(?status, nft_id, requester = %caller, "{}", description);
 
 				(
 					StatusCode::BAD_REQUEST,
@@ -559,7 +1049,7 @@ impl VerificationError {
 				let status = ReturnStatus::NOTSYNCING;
 				let description =
 					format!("TEE Key-share {call:?}: The nft is not in syncing mode.");
-				info!("{}, requester : {}", description, caller);
+				info!(?status, nft_id, requester = %caller, "{}", description);
 
 				(
 					StatusCode::FORBIDDEN,
@@ -579,7 +1069,7 @@ impl VerificationError {
 			VerificationError::NOTSYNCED => {
 				let status = ReturnStatus::NOTSYNCED;
 				let description = format!("TEE Key-share {call:?}: The nft is not in synced mode.");
-				info!("{}, requester : {}", description, caller);
+				info!(?status, nft_id, requester = %caller, "{}", description);
 
 				(
 					StatusCode::FORBIDDEN,
@@ -595,11 +1085,32 @@ impl VerificationError {
 				)
 			},
 
+			// NFT MUST BE BURNT OR CONVERTED TO REMOVE STORED KEYSHARES
+			VerificationError::NOTBURNT => {
+				let status = ReturnStatus::NOTBURNT;
+				let description =
+					format!("TEE Key-share {call:?}: The nft is not in burnt or converted state.");
+				info!(?status, nft_id, requester = %caller, "{}", description);
+
+				(
+					StatusCode::BAD_REQUEST,
+					Json(
+						serde_json::to_value(ApiErrorResponse {
+							status,
+							nft_id,
+							enclave_account,
+							description,
+						})
+						.unwrap(),
+					),
+				)
+			},
+
 			// PARSE DATA PACKET FAILED
 			VerificationError::MALFORMATEDDATA => {
 				let status = ReturnStatus::INVALIDDATAFORMAT;
 				let description = format!("TEE Key-share {call:?}: Failed to parse data field.");
-				info!("{}, requester : {}", description, caller);
+				info!(?status, nft_id, requester = %caller, "{}", description);
 
 				(
 					StatusCode::BAD_REQUEST,
@@ -619,7 +1130,7 @@ impl VerificationError {
 			VerificationError::MALFORMATEDSIGNER => {
 				let status = ReturnStatus::INVALIDSIGNERFORMAT;
 				let description = format!("TEE Key-share {call:?}: Failed to parse Signer field.");
-				info!("{}, requester : {}", description, caller);
+				info!(?status, nft_id, requester = %caller, "{}", description);
 
 				(
 					StatusCode::BAD_REQUEST,
@@ -640,7 +1151,7 @@ impl VerificationError {
 				let description = format!(
 					"TEE Key-share {call:?}: Secret-Share is too short, it is not secure enough."
 				);
-				info!("{}, requester : {}", description, caller);
+				info!(?status, nft_id, requester = %caller, "{}", description);
 
 				(
 					StatusCode::BAD_REQUEST,
@@ -656,13 +1167,16 @@ impl VerificationError {
 				)
 			},
 
-			VerificationError::KEYSHAREISTOOLONG => {
-				let status = ReturnStatus::KEYSHAREISTOOLONG;
-				let description = format!("TEE Key-share {call:?}: Secret-Share is too long, it is not possible to store it.");
-				info!("{}, requester : {}", description, caller);
+			// SAME REQUEST HAS BEEN SERVED BEFORE
+			VerificationError::REPLAYEDREQUEST => {
+				let status = ReturnStatus::REPLAYEDREQUEST;
+				let description = format!(
+					"TEE Key-share {call:?}: The request has already been served, sign a new request."
+				);
+				info!(?status, nft_id, requester = %caller, "{}", description);
 
 				(
-					StatusCode::BAD_REQUEST,
+					StatusCode::CONFLICT,
 					Json(
 						serde_json::to_value(ApiErrorResponse {
 							status,
@@ -674,73 +1188,563 @@ impl VerificationError {
 					),
 				)
 			},
-		}
-	}
-}
 
-/* ----------------------------------
-		GET ONCHAIN DATA
-----------------------------------*/
+			// SESSION KEY SCOPE
+			VerificationError::SESSIONOUTOFSCOPE => {
+				let status = ReturnStatus::SESSIONOUTOFSCOPE;
+				let description = format!(
+					"TEE Key-share {call:?}: The call or nft_id is not in the scope of the session key."
+				);
+				info!(?status, nft_id, requester = %caller, "{}", description);
 
-/// Fetch onchain owenrship of nft/capsule id
-/// # Arguments
-/// * `nft_id` - nft/capsule id
-/// # Returns
-/// * `KeyshareHolder` - KeyshareHolder enum
-pub async fn get_onchain_delegatee_account(state: &SharedState, nft_id: u32) -> KeyshareHolder {
-	let delegatee_data = get_onchain_delegatee(state, nft_id).await;
+				(
+					StatusCode::FORBIDDEN,
+					Json(
+						serde_json::to_value(ApiErrorResponse {
+							status,
+							nft_id,
+							enclave_account,
+							description,
+						})
+						.unwrap(),
+					),
+				)
+			},
 
-	match delegatee_data {
-		Some(account) => KeyshareHolder::Delegatee(account),
-		None => KeyshareHolder::NotFound,
-	}
-}
+			// HIGH-VALUE NFT CO-SIGNATURE
+			VerificationError::COSIGNATUREREQUIRED => {
+				let status = ReturnStatus::COSIGNATUREREQUIRED;
+				let description = format!(
+					"TEE Key-share {call:?}: The nft requires the signature of its designated co-signer on the request data."
+				);
+				info!(?status, nft_id, requester = %caller, "{}", description);
 
-/// Fetch onchain owenrship of nft/capsule id
-/// # Arguments
-/// * `nft_id` - nft/capsule id
-/// # Returns
-/// * `KeyshareHolder` - KeyshareHolder enum
-pub async fn get_onchain_rentee_account(state: &SharedState, nft_id: u32) -> KeyshareHolder {
-	let rentee_data = get_onchain_rent_contract(state, nft_id).await;
+				(
+					StatusCode::FORBIDDEN,
+					Json(
+						serde_json::to_value(ApiErrorResponse {
+							status,
+							nft_id,
+							enclave_account,
+							description,
+						})
+						.unwrap(),
+					),
+				)
+			},
 
-	match rentee_data {
-		Some(account) => KeyshareHolder::Rentee(account),
+			// ADMIN READ-ONLY SWITCH
+			VerificationError::READONLY(expiry_block) => {
+				let status = ReturnStatus::READONLYMODE;
+				let description = format!(
+					"TEE Key-share {call:?}: Enclave is read-only until block {expiry_block}, mutations are disabled."
+				);
+				info!(?status, nft_id, requester = %caller, "{}", description);
+
+				(
+					StatusCode::SERVICE_UNAVAILABLE,
+					Json(
+						serde_json::to_value(ApiErrorResponse {
+							status,
+							nft_id,
+							enclave_account,
+							description,
+						})
+						.unwrap(),
+					),
+				)
+			},
+
+			// REQUESTER ACCOUNT OR IP QUOTA
+			VerificationError::RATELIMITED(retry_after) => {
+				let status = ReturnStatus::RATELIMITED;
+				let description = format!(
+					"TEE Key-share {call:?}: Too many requests, retry after {retry_after} seconds."
+				);
+				info!(?status, nft_id, requester = %caller, "{}", description);
+
+				(
+					StatusCode::TOO_MANY_REQUESTS,
+					Json(
+						serde_json::to_value(ApiErrorResponse {
+							status,
+							nft_id,
+							enclave_account,
+							description,
+						})
+						.unwrap(),
+					),
+				)
+			},
+
+			// PRIMARY AND SECONDARY RPC DIVERGENCE
+			VerificationError::ORACLEFAILURE => {
+				let status = ReturnStatus::ORACLEFAILURE;
+				let description = format!(
+					"TEE Key-share {call:?}: Onchain data of independent rpc endpoints do not match."
+				);
+				info!(?status, nft_id, requester = %caller, "{}", description);
+
+				(
+					StatusCode::SERVICE_UNAVAILABLE,
+					Json(
+						serde_json::to_value(ApiErrorResponse {
+							status,
+							nft_id,
+							enclave_account,
+							description,
+						})
+						.unwrap(),
+					),
+				)
+			},
+
+			VerificationError::KEYSHAREISTOOLONG => {
+				let status = ReturnStatus::KEYSHAREISTOOLONG;
+				let description = format!("TEE Key-share {call:?}: Secret-Share is too long, it is not possible to store it.");
+				info!(?status, nft_id, requester = %caller, "{}", description);
+
+				(
+					StatusCode::BAD_REQUEST,
+					Json(
+						serde_json::to_value(ApiErrorResponse {
+							status,
+							nft_id,
+							enclave_account,
+							description,
+						})
+						.unwrap(),
+					),
+				)
+			},
+
+			VerificationError::INVALIDKEYSHAREENCODING => {
+				let status = ReturnStatus::KEYSHAREINVALIDENCODING;
+				let description = format!("TEE Key-share {call:?}: Secret-Share is not in the encoding required by the enclave policy.");
+				info!(?status, nft_id, requester = %caller, "{}", description);
+
+				(
+					StatusCode::BAD_REQUEST,
+					Json(
+						serde_json::to_value(ApiErrorResponse {
+							status,
+							nft_id,
+							enclave_account,
+							description,
+						})
+						.unwrap(),
+					),
+				)
+			},
+
+			VerificationError::KEYSHARECONTROLCHAR => {
+				let status = ReturnStatus::KEYSHARECONTAINSCONTROLCHAR;
+				let description =
+					format!("TEE Key-share {call:?}: Secret-Share contains control characters.");
+				info!(?status, nft_id, requester = %caller, "{}", description);
+
+				(
+					StatusCode::BAD_REQUEST,
+					Json(
+						serde_json::to_value(ApiErrorResponse {
+							status,
+							nft_id,
+							enclave_account,
+							description,
+						})
+						.unwrap(),
+					),
+				)
+			},
+
+			VerificationError::LOWENTROPYKEYSHARE => {
+				let status = ReturnStatus::KEYSHARELOWENTROPY;
+				let description = format!("TEE Key-share {call:?}: Secret-Share entropy is lower than the enclave policy, it does not look like a key-share.");
+				info!(?status, nft_id, requester = %caller, "{}", description);
+
+				(
+					StatusCode::BAD_REQUEST,
+					Json(
+						serde_json::to_value(ApiErrorResponse {
+							status,
+							nft_id,
+							enclave_account,
+							description,
+						})
+						.unwrap(),
+					),
+				)
+			},
+		}
+	}
+}
+
+impl From<ParseError> for VerificationError {
+	fn from(err: ParseError) -> Self {
+		match err {
+			ParseError::FIELDCOUNT { .. } => VerificationError::MALFORMATEDDATA,
+			ParseError::INVALIDNFTID => VerificationError::INVALIDNFTID,
+			ParseError::INVALIDBLOCKNUMBER | ParseError::INVALIDBLOCKVALIDATION =>
+				VerificationError::INVALIDAUTHTOKEN,
+			ParseError::INVALIDKEYSHAREENCODING => VerificationError::INVALIDKEYSHAREENCODING,
+		}
+	}
+}
+
+impl VerificationError {
+	/// Return status corresponding to the error
+	pub fn status(&self) -> ReturnStatus {
+		match self {
+			VerificationError::INVALIDSIGNERSIG(_) => ReturnStatus::INVALIDSIGNERSIGNATURE,
+			VerificationError::INVALIDDATASIG(_) => ReturnStatus::INVALIDDATASIGNATURE,
+			VerificationError::SIGNERVERIFICATIONFAILED =>
+				ReturnStatus::SIGNERSIGVERIFICATIONFAILED,
+			VerificationError::DATAVERIFICATIONFAILED => ReturnStatus::DATASIGVERIFICATIONFAILED,
+			VerificationError::OWNERSHIPVERIFICATIONFAILED =>
+				ReturnStatus::OWNERSHIPVERIFICATIONFAILED,
+			VerificationError::REQUESTERVERIFICATIONFAILED =>
+				ReturnStatus::REQUESTERVERIFICATIONFAILED,
+			VerificationError::MALFORMATEDDATA => ReturnStatus::INVALIDDATAFORMAT,
+			VerificationError::MALFORMATEDSIGNER => ReturnStatus::INVALIDSIGNERFORMAT,
+			VerificationError::INVALIDOWNERADDRESS => ReturnStatus::INVALIDOWNERADDRESS,
+			VerificationError::INVALIDSIGNERADDRESS => ReturnStatus::INVALIDSIGNERADDRESS,
+			VerificationError::KEYSHAREISTOOSHORT => ReturnStatus::KEYSHAREISTOOSHORT,
+			VerificationError::KEYSHAREISTOOLONG => ReturnStatus::KEYSHAREISTOOLONG,
+			VerificationError::INVALIDKEYSHAREENCODING => ReturnStatus::KEYSHAREINVALIDENCODING,
+			VerificationError::KEYSHARECONTROLCHAR => ReturnStatus::KEYSHARECONTAINSCONTROLCHAR,
+			VerificationError::LOWENTROPYKEYSHARE => ReturnStatus::KEYSHARELOWENTROPY,
+			VerificationError::INVALIDAUTHTOKEN => ReturnStatus::INVALIDAUTHTOKEN,
+			VerificationError::INVALIDKEYSHARE => ReturnStatus::INVALIDKEYSHARE,
+			VerificationError::INVALIDNFTID => ReturnStatus::INVALIDNFTID,
+			VerificationError::EXPIREDSIGNER(_) => ReturnStatus::EXPIREDSIGNER,
+			VerificationError::EXPIREDDATA(_) => ReturnStatus::EXPIREDREQUEST,
+			VerificationError::IDISNOTSECRETNFT => ReturnStatus::IDISNOTASECRETNFT,
+			VerificationError::IDISNOTCAPSULE => ReturnStatus::IDISNOTACAPSULE,
+			VerificationError::NOTSYNCING => ReturnStatus::NOTSYNCING,
+			VerificationError::NOTSYNCED => ReturnStatus::NOTSYNCED,
+			VerificationError::NOTBURNT => ReturnStatus::NOTBURNT,
+			VerificationError::ORACLEFAILURE => ReturnStatus::ORACLEFAILURE,
+			VerificationError::REPLAYEDREQUEST => ReturnStatus::REPLAYEDREQUEST,
+			VerificationError::SESSIONOUTOFSCOPE => ReturnStatus::SESSIONOUTOFSCOPE,
+			VerificationError::COSIGNATUREREQUIRED => ReturnStatus::COSIGNATUREREQUIRED,
+			VerificationError::READONLY(_) => ReturnStatus::READONLYMODE,
+			VerificationError::RATELIMITED(_) => ReturnStatus::RATELIMITED,
+		}
+	}
+
+	/// Invalid signature of the requester, counted by the ip ban list. A missing co-signature or
+	/// a session key out of its scope are not failures of the requester key.
+	pub fn is_signature_failure(&self) -> bool {
+		matches!(
+			self,
+			VerificationError::INVALIDSIGNERSIG(_) |
+				VerificationError::INVALIDDATASIG(_) |
+				VerificationError::SIGNERVERIFICATIONFAILED |
+				VerificationError::DATAVERIFICATIONFAILED
+		)
+	}
+
+	/// Verification step which produced the error
+	pub fn step(&self) -> VerificationStep {
+		match self {
+			VerificationError::MALFORMATEDDATA |
+			VerificationError::MALFORMATEDSIGNER |
+			VerificationError::INVALIDOWNERADDRESS |
+			VerificationError::INVALIDSIGNERADDRESS |
+			VerificationError::KEYSHAREISTOOSHORT |
+			VerificationError::KEYSHAREISTOOLONG |
+			VerificationError::INVALIDKEYSHAREENCODING |
+			VerificationError::KEYSHARECONTROLCHAR |
+			VerificationError::LOWENTROPYKEYSHARE |
+			VerificationError::INVALIDKEYSHARE => VerificationStep::PARSING,
+
+			VerificationError::INVALIDSIGNERSIG(_) |
+			VerificationError::INVALIDDATASIG(_) |
+			VerificationError::SIGNERVERIFICATIONFAILED |
+			VerificationError::DATAVERIFICATIONFAILED |
+			VerificationError::SESSIONOUTOFSCOPE |
+			VerificationError::COSIGNATUREREQUIRED => VerificationStep::SIGNATURE,
+
+			VerificationError::INVALIDAUTHTOKEN |
+			VerificationError::EXPIREDSIGNER(_) |
+			VerificationError::EXPIREDDATA(_) |
+			VerificationError::REPLAYEDREQUEST => VerificationStep::AUTHTOKEN,
+
+			VerificationError::INVALIDNFTID |
+			VerificationError::IDISNOTSECRETNFT |
+			VerificationError::IDISNOTCAPSULE |
+			VerificationError::NOTSYNCING |
+			VerificationError::NOTSYNCED |
+			VerificationError::NOTBURNT |
+			VerificationError::ORACLEFAILURE => VerificationStep::ONCHAINSTATE,
+
+			VerificationError::OWNERSHIPVERIFICATIONFAILED |
+			VerificationError::REQUESTERVERIFICATIONFAILED => VerificationStep::OWNERSHIP,
+
+			VerificationError::READONLY(_) => VerificationStep::ENCLAVEMODE,
+			VerificationError::RATELIMITED(_) => VerificationStep::QUOTA,
+		}
+	}
+
+	/// Retryability class of the error
+	pub fn retryability(&self) -> Retryability {
+		match self {
+			VerificationError::EXPIREDSIGNER(_) |
+			VerificationError::EXPIREDDATA(_) |
+			VerificationError::REPLAYEDREQUEST |
+			VerificationError::COSIGNATUREREQUIRED => Retryability::RESIGN,
+
+			VerificationError::NOTSYNCING |
+			VerificationError::NOTSYNCED |
+			VerificationError::NOTBURNT => Retryability::WAITONCHAIN,
+
+			VerificationError::ORACLEFAILURE |
+			VerificationError::READONLY(_) |
+			VerificationError::RATELIMITED(_) => Retryability::RETRYABLE,
+
+			// Ownership may change on-chain (transfer, delegation, rent)
+			VerificationError::OWNERSHIPVERIFICATIONFAILED |
+			VerificationError::REQUESTERVERIFICATIONFAILED => Retryability::WAITONCHAIN,
+
+			_ => Retryability::PERMANENT,
+		}
+	}
+
+	/// Express the error as an item of a batch response
+	/// # Arguments
+	/// * `nft_id` - NFT ID
+	/// * `state_hash` - hash of on-chain nft data snapshot, if it has been fetched
+	pub fn express_batch_item(self, nft_id: u32, state_hash: Option<String>) -> BatchItemResult {
+		if self.is_signature_failure() {
+			record_signature_failure();
+		}
+
+		let status = self.status();
+		BatchItemResult {
+			nft_id,
+			status,
+			code: status.code(),
+			step: Some(self.step()),
+			retryable: Some(self.retryability()),
+			state_hash,
+			description: format!("{self:?}"),
+		}
+	}
+}
+
+/* ----------------------------------
+		ADDRESS NORMALIZATION
+----------------------------------*/
+
+/// Parse an address with any SS58 prefix (or 0x-prefixed hex public key)
+/// # Arguments
+/// * `address` - address string
+/// # Returns
+/// * `Option<sr25519::Public>` - raw public key, None if the address is invalid
+pub fn parse_ss58_public(address: &str) -> Option<sr25519::Public> {
+	let address = address.trim();
+
+	if let Some(hex_key) = address.strip_prefix("0x") {
+		return <[u8; 32]>::from_hex(hex_key).ok().map(sr25519::Public::from_raw)
+	}
+
+	sr25519::Public::from_ss58check_with_version(address)
+		.ok()
+		.map(|(public, _)| public)
+}
+
+/// Convert an address with any SS58 prefix to AccountId32, comparison is done on raw public keys
+pub fn normalize_address(address: &str) -> Option<AccountId32> {
+	parse_ss58_public(address).map(|public| AccountId32(public.0))
+}
+
+/// Canonical Ternoa-formatted address of a public key
+pub fn canonical_address(public: &sr25519::Public) -> String {
+	public.to_ss58check_with_version(Ss58AddressFormat::custom(SS58_PREFIX))
+}
+
+/// Serde helper, accepts addresses of any SS58 prefix in request packets
+fn deserialize_ss58<'de, D>(deserializer: D) -> Result<sr25519::Public, D::Error>
+where
+	D: Deserializer<'de>,
+{
+	let address = String::deserialize(deserializer)?;
+	parse_ss58_public(&address)
+		.ok_or_else(|| serde::de::Error::custom(format!("invalid ss58 address : {address}")))
+}
+
+/// Requests without version field use the underscore-delimited data
+fn legacy_request_version() -> u8 {
+	REQUEST_VERSION_LEGACY
+}
+
+/* ----------------------------------
+		GET ONCHAIN DATA
+----------------------------------*/
+
+/// Snapshot hash of the onchain nft/capsule data
+/// # Arguments
+/// * `nft_id` - nft/capsule id
+/// # Returns
+/// * `Option<String>` - sha256 of the onchain data, None if the nft does not exist
+pub async fn get_onchain_state_hash(state: &SharedState, nft_id: u32) -> Option<String> {
+	get_onchain_nft_data(state, nft_id)
+		.await
+		.map(|nft_data| sha256::digest(format!("{nft_data}").as_bytes()))
+}
+
+/// Fetch onchain nft/capsule data, cross-checked with secondary rpc in dual-rpc mode
+/// # Arguments
+/// * `nft_id` - nft/capsule id
+/// # Returns
+/// * `NFTData` - onchain data, INVALIDNFTID if not found, ORACLEFAILURE on divergence
+pub async fn get_verified_nft_data(
+	state: &SharedState,
+	nft_id: u32,
+) -> Result<NFTData<AccountId32>, VerificationError> {
+	let dual_rpc = match get_dual_rpc(state).await {
+		Some(Ok(dual_rpc)) => dual_rpc,
+		Some(Err(_)) => return Err(VerificationError::ORACLEFAILURE),
+		None =>
+			return get_onchain_nft_data(state, nft_id).await.ok_or(VerificationError::INVALIDNFTID),
+	};
+
+	let (primary, secondary) = futures::join!(
+		get_nft_data_from_api(&dual_rpc.primary, dual_rpc.block_hash, nft_id),
+		get_nft_data_from_api(&dual_rpc.secondary, dual_rpc.block_hash, nft_id)
+	);
+
+	let (primary, secondary) = match (primary, secondary) {
+		(Ok(primary), Ok(secondary)) => (primary, secondary),
+		(primary, secondary) => {
+			error!(
+				"DUAL-RPC : rpc failed for nft_id {} : primary {:?}, secondary {:?}",
+				nft_id,
+				primary.err(),
+				secondary.err()
+			);
+			return Err(VerificationError::ORACLEFAILURE)
+		},
+	};
+
+	let primary_str = primary.as_ref().map(|data| format!("{data}"));
+	let secondary_str = secondary.as_ref().map(|data| format!("{data}"));
+
+	if primary_str != secondary_str {
+		error!(
+			"DUAL-RPC : nft data divergence for nft_id {} at block {:?}",
+			nft_id, dual_rpc.block_hash
+		);
+		return Err(VerificationError::ORACLEFAILURE)
+	}
+
+	primary.ok_or(VerificationError::INVALIDNFTID)
+}
+
+/// Chain APIs of dual-rpc mode and the block they are compared at
+struct DualRpc {
+	primary: DefaultApi,
+	secondary: DefaultApi,
+	block_hash: H256,
+}
+
+/// Primary and secondary rpc at their common block, None if dual-rpc mode is off
+async fn get_dual_rpc(state: &SharedState) -> Option<Result<DualRpc, subxt::Error>> {
+	let secondary = get_secondary_chain_api(state).await?;
+	let primary = get_chain_api(state).await;
+
+	match get_common_block_hash(&primary, &secondary).await {
+		Ok(block_hash) => Some(Ok(DualRpc { primary, secondary, block_hash })),
+		Err(err) => {
+			error!("DUAL-RPC : no common block of primary and secondary rpc : {:?}", err);
+			Some(Err(err))
+		},
+	}
+}
+
+/// Fetch onchain owenrship of nft/capsule id
+/// # Arguments
+/// * `nft_id` - nft/capsule id
+/// # Returns
+/// * `KeyshareHolder` - KeyshareHolder enum
+pub async fn get_onchain_delegatee_account(state: &SharedState, nft_id: u32) -> KeyshareHolder {
+	let delegatee_data = match get_dual_rpc(state).await {
+		Some(Ok(dual_rpc)) => match futures::join!(
+			get_delegatee_from_api(&dual_rpc.primary, dual_rpc.block_hash, nft_id),
+			get_delegatee_from_api(&dual_rpc.secondary, dual_rpc.block_hash, nft_id)
+		) {
+			(Ok(primary), Ok(secondary)) if primary == secondary => primary,
+			_ => {
+				error!("DUAL-RPC : delegatee divergence for nft_id {}", nft_id);
+				return KeyshareHolder::Diverged
+			},
+		},
+		Some(Err(_)) => return KeyshareHolder::Diverged,
+		None => get_onchain_delegatee(state, nft_id).await,
+	};
+
+	match delegatee_data {
+		Some(account) => KeyshareHolder::Delegatee(account),
+		None => KeyshareHolder::NotFound,
+	}
+}
+
+/// Fetch onchain owenrship of nft/capsule id
+/// # Arguments
+/// * `nft_id` - nft/capsule id
+/// # Returns
+/// * `KeyshareHolder` - KeyshareHolder enum
+pub async fn get_onchain_rentee_account(state: &SharedState, nft_id: u32) -> KeyshareHolder {
+	let rentee_data = match get_dual_rpc(state).await {
+		Some(Ok(dual_rpc)) => match futures::join!(
+			get_rentee_from_api(&dual_rpc.primary, dual_rpc.block_hash, nft_id),
+			get_rentee_from_api(&dual_rpc.secondary, dual_rpc.block_hash, nft_id)
+		) {
+			(Ok(primary), Ok(secondary)) if primary == secondary => primary,
+			_ => {
+				error!("DUAL-RPC : rentee divergence for nft_id {}", nft_id);
+				return KeyshareHolder::Diverged
+			},
+		},
+		Some(Err(_)) => return KeyshareHolder::Diverged,
+		None => get_onchain_rent_contract(state, nft_id).await,
+	};
+
+	match rentee_data {
+		Some(account) => KeyshareHolder::Rentee(account),
 		None => KeyshareHolder::NotFound,
 	}
 }
 
-/// Check nft/capsule owner/rentee/delegatee
+/// Check nft/capsule owner/rentee/delegatee, by the registered policy of the requester type
 /// # Arguments
 /// * `requester_address` - requester address
 /// * `nft_id` - nft/capsule id
 /// * `owner` - nft/capsule owner
 /// * `requester_type` - requester type
 /// # Returns
-/// * `bool` - true if requester is owner/rentee/delegatee
-pub async fn verify_requester_type(
-	state: &SharedState,
+/// * `bool` - true if requester holds the role of requester type
+/// # Errors
+/// * `ORACLEFAILURE` - if primary and secondary rpc disagree in dual-rpc mode
+pub async fn verify_requester_type<C: ChainReader>(
+	chain: &C,
 	requester_address: String,
 	nft_id: u32,
 	owner: AccountId32,
 	requester_type: RequesterType,
-) -> bool {
-	match AccountId32::from_str(&requester_address) {
-		Ok(converted_requester_address) => match requester_type {
-			RequesterType::OWNER => owner == converted_requester_address,
-
-			RequesterType::DELEGATEE => match get_onchain_delegatee_account(state, nft_id).await {
-				KeyshareHolder::Delegatee(delegatee) => delegatee == converted_requester_address,
-				_ => false,
-			},
-
-			RequesterType::RENTEE => match get_onchain_rentee_account(state, nft_id).await {
-				KeyshareHolder::Rentee(rentee) => rentee == converted_requester_address,
-				_ => false,
-			},
+) -> Result<bool, VerificationError> {
+	let converted_requester_address = match normalize_address(&requester_address) {
+		Some(address) => address,
+		None => return Ok(false),
+	};
+
+	match requester_registry().policy(requester_type) {
+		Some(policy) =>
+			policy.is_authorized(chain, &converted_requester_address, nft_id, &owner).await,
+		None => {
+			debug!("Requester type {:?} has no registered policy", requester_type);
+			Ok(false)
 		},
-
-		Err(_) => false,
 	}
 }
 
@@ -756,13 +1760,61 @@ pub enum ValidationResult {
 	InvalidPeriod,
 }
 
+/// Position of a rejected auth-token against the enclave clock, returned with expiry errors so
+/// wallets can correct their block number and retry
+#[derive(Serialize, PartialEq, Debug)]
+pub struct ClockSkew {
+	pub result: ValidationResult,
+	pub current_block: u32,
+	// Block number of the auth-token, expiry block of a session grant
+	pub token_block: u32,
+	// Validity period of the auth-token, 0 for a session grant
+	pub validity_window: u32,
+	pub max_validity_window: u32,
+	// Blocks a token may be ahead of the enclave, for finalization delay
+	pub max_block_variation: u32,
+	// Blocks the token is ahead (positive) or behind (negative) of the enclave
+	pub skew: i64,
+}
+
+impl ClockSkew {
+	/// Skew of a session grant, only its expiry block is signed
+	pub fn session(result: ValidationResult, current_block: u32, expiry_block: u32) -> ClockSkew {
+		ClockSkew {
+			result,
+			current_block,
+			token_block: expiry_block,
+			validity_window: 0,
+			max_validity_window: MAX_SESSION_PERIOD,
+			max_block_variation: 0,
+			skew: expiry_block as i64 - current_block as i64,
+		}
+	}
+}
+
 // Retrieving the stored Keyshare
 impl AuthenticationToken {
 	/// Serialize AuthenticationToken
-	pub fn serialize(self) -> String {
+	pub fn serialize(&self) -> String {
 		format!("{}_{}", self.block_number, self.block_validation)
 	}
 
+	/// Diagnostics of a failed validation
+	/// # Arguments
+	/// * `current_block_number` - block number of the enclave
+	/// * `result` - result of is_valid
+	pub fn clock_skew(&self, current_block_number: u32, result: ValidationResult) -> ClockSkew {
+		ClockSkew {
+			result,
+			current_block: current_block_number,
+			token_block: self.block_number,
+			validity_window: self.block_validation,
+			max_validity_window: MAX_VALIDATION_PERIOD,
+			max_block_variation: MAX_BLOCK_VARIATION,
+			skew: self.block_number as i64 - current_block_number as i64,
+		}
+	}
+
 	pub fn is_valid(&self, current_block_number: u32) -> ValidationResult {
 		if self.block_number > current_block_number + MAX_BLOCK_VARIATION {
 			// for finalization delay
@@ -800,111 +1852,613 @@ impl AuthenticationToken {
    SECRET-DATA IMPLEMENTATION
 ----------------------------------*/
 
+/// Content of a sealed keyshare file : the verified keyshare itself, or the BINARY_MAGIC header
+/// and base64url encoding of a binary keyshare, which is wiped from memory when dropped
+pub enum KeyshareAtRest<'a> {
+	BORROWED(&'a [u8]),
+	ENCODED(Zeroizing<Vec<u8>>),
+}
+
+impl Deref for KeyshareAtRest<'_> {
+	type Target = [u8];
+
+	fn deref(&self) -> &[u8] {
+		match self {
+			KeyshareAtRest::BORROWED(keyshare) => keyshare,
+			KeyshareAtRest::ENCODED(encoded) => encoded.as_slice(),
+		}
+	}
+}
+
+// Header and base64url of a keyshare, encoded in place so no intermediate String holds it
+fn encode_keyshare(keyshare: &[u8]) -> Zeroizing<Vec<u8>> {
+	let encoded_len = base64::encoded_len(keyshare.len(), false).unwrap_or_default();
+	let mut encoded = Zeroizing::new(vec![0u8; BINARY_MAGIC.len() + encoded_len]);
+	encoded[..BINARY_MAGIC.len()].copy_from_slice(&BINARY_MAGIC);
+	let written = URL_SAFE_NO_PAD
+		.encode_slice(keyshare, &mut encoded[BINARY_MAGIC.len()..])
+		.unwrap_or_default();
+	encoded.truncate(BINARY_MAGIC.len() + written);
+	encoded
+}
+
 // Retrieving the stored Keyshare
 impl StoreKeyshareData {
-	pub fn serialize(self) -> String {
-		let keyshare_str = match String::from_utf8(self.keyshare) {
-			Ok(s) => s,
-			Err(err) => return format!("Error serializing keyshare data: {}", err),
-		};
-		format!("{}_{}_{}", self.nft_id, keyshare_str, self.auth_token.serialize())
+	/// Content of the sealed file, binary keyshares stay base64url encoded at rest behind a
+	/// header, so retrieve responses remain text and flag them as binary
+	pub fn keyshare_at_rest(&self) -> KeyshareAtRest {
+		if self.binary {
+			KeyshareAtRest::ENCODED(encode_keyshare(&self.keyshare))
+		} else {
+			KeyshareAtRest::BORROWED(&self.keyshare)
+		}
+	}
+
+	/// "NFTID_keyshare_block_expiry", written into a single wiped buffer
+	pub fn serialize(&self) -> Zeroizing<String> {
+		let nft_id = self.nft_id.to_string();
+		let auth_token = self.auth_token.serialize();
+		let keyshare = self.keyshare_at_rest();
+
+		let mut buffer =
+			String::with_capacity(nft_id.len() + keyshare.len() + auth_token.len() + 2);
+		buffer.push_str(&nft_id);
+		buffer.push('_');
+		// Text keyshares are parsed from strings, binary ones are encoded
+		let keyshare = keyshare.strip_prefix(&BINARY_MAGIC).unwrap_or(&keyshare);
+		buffer.push_str(std::str::from_utf8(keyshare).unwrap_or_default());
+		buffer.push('_');
+		buffer.push_str(&auth_token);
+
+		Zeroizing::new(buffer)
 	}
 }
 
 /* ----------------------------------
-	STORE-PACKET IMPLEMENTATION
+	SHARED VERIFICATION STEPS
 ----------------------------------*/
 
-impl StoreKeysharePacket {
-	pub fn get_signer(&self) -> Result<Signer, VerificationError> {
-		let mut signer = self.signer_address.clone();
-
-		if signer.starts_with("<Bytes>") && signer.ends_with("</Bytes>") {
-			signer = signer
-				.strip_prefix("<Bytes>")
-				.ok_or(VerificationError::MALFORMATEDSIGNER)?
-				.strip_suffix("</Bytes>")
-				.ok_or(VerificationError::MALFORMATEDSIGNER)?
-				.to_string();
+/// Keyshare mutations (store, remove, restore, rotation) are rejected while the admin
+/// read-only switch is active
+pub async fn verify_writable<C: ChainReader>(chain: &C) -> Result<(), VerificationError> {
+	match chain.read_only_until().await {
+		Some(expiry_block) => Err(VerificationError::READONLY(expiry_block)),
+		None => Ok(()),
+	}
+}
+
+/// Size, charset and entropy of a keyshare, see KeysharePolicy
+fn check_keyshare(keyshare: &[u8], binary: bool) -> Result<(), VerificationError> {
+	if binary {
+		keyshare_policy().validate_binary(keyshare)
+	} else {
+		keyshare_policy().validate(keyshare)
+	}
+}
+
+/* ----------------------------------
+		VERIFICATION PIPELINE
+----------------------------------*/
+
+/// Kind of entity protected by a keyshare.
+/// Store, retrieve and remove requests of every kind go through the same stages (auth-token,
+/// onchain state, ownership), only the hooks below differ between kinds.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NftKind {
+	SECRET,
+	CAPSULE,
+}
+
+impl NftKind {
+	/// Name of the kind in logs and view history : "secret-nft" or "capsule"
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			NftKind::SECRET => "secret-nft",
+			NftKind::CAPSULE => "capsule",
 		}
+	}
 
-		let parsed_data: Vec<&str> = if signer.contains('_') {
-			signer.split('_').collect()
-		} else {
-			return Err(VerificationError::MALFORMATEDSIGNER)
-		};
+	/// The onchain nft is of this kind
+	pub fn is_kind(&self, nft_status: &OnchainNft) -> bool {
+		match self {
+			NftKind::SECRET => nft_status.is_secret,
+			NftKind::CAPSULE => nft_status.is_capsule,
+		}
+	}
+
+	/// Keyshares of the kind are stored while syncing, and retrieved once synced
+	fn is_syncing(&self, nft_status: &OnchainNft) -> bool {
+		match self {
+			NftKind::SECRET => nft_status.is_syncing_secret,
+			NftKind::CAPSULE => nft_status.is_syncing_capsule,
+		}
+	}
+
+	/// Error of an nft_id which is not of this kind
+	pub fn kind_error(&self) -> VerificationError {
+		match self {
+			NftKind::SECRET => VerificationError::IDISNOTSECRETNFT,
+			NftKind::CAPSULE => VerificationError::IDISNOTCAPSULE,
+		}
+	}
+
+	/// Api call of a single retrieve, as scoped by session keys
+	fn retrieve_call(&self) -> APICALL {
+		match self {
+			NftKind::SECRET => APICALL::NFTRETRIEVE,
+			NftKind::CAPSULE => APICALL::CAPSULERETRIEVE,
+		}
+	}
+
+	fn store_call(&self) -> APICALL {
+		match self {
+			NftKind::SECRET => APICALL::NFTSTORE,
+			NftKind::CAPSULE => APICALL::CAPSULESET,
+		}
+	}
+}
+
+impl std::fmt::Display for NftKind {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(self.as_str())
+	}
+}
+
+/// Keyshare operation, it selects the expected syncing state
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum KeyshareOperation {
+	STORE,
+	RETRIEVE,
+	UPDATE,
+}
+
+/// State stage : the nft must be of the kind, syncing to store and synced to retrieve or update
+/// # Errors
+/// * `IDISNOTSECRETNFT`/`IDISNOTCAPSULE` - if the nft is not of the kind
+/// * `NOTSYNCING` - if the keyshare of the nft is already synced (store)
+/// * `NOTSYNCED` - if the keyshare of the nft is not synced yet (retrieve, update)
+async fn verify_state_stage<C: ChainReader>(
+	chain: &C,
+	nft_id: u32,
+	kind: NftKind,
+	operation: KeyshareOperation,
+) -> Result<OnchainNft, VerificationError> {
+	let nft_status = chain.nft_data(nft_id).await?;
+
+	if !kind.is_kind(&nft_status) {
+		return Err(kind.kind_error())
+	}
+
+	let syncing = kind.is_syncing(&nft_status);
+	debug!("{} syncing status : {}", kind, syncing);
+
+	match operation {
+		KeyshareOperation::STORE if !syncing => Err(VerificationError::NOTSYNCING),
+		KeyshareOperation::RETRIEVE | KeyshareOperation::UPDATE if syncing =>
+			Err(VerificationError::NOTSYNCED),
+		_ => Ok(nft_status),
+	}
+}
+
+/// Quota stage : the requester account is charged once its signature is verified, an unsigned
+/// body can not exhaust the quota of another account
+/// # Arguments
+/// * `call` - rate-limited api call, an update is charged as a set and a batch as its call
+/// * `account` - verified requester account
+/// * `items` - number of requested items
+/// # Errors
+/// * `RATELIMITED` - if the quota of the account is exhausted
+async fn verify_quota_stage<C: ChainReader>(
+	chain: &C,
+	call: APICALL,
+	account: &sr25519::Public,
+	items: usize,
+) -> Result<(), VerificationError> {
+	chain
+		.charge_quota(call, account, items.try_into().unwrap_or(u32::MAX))
+		.await
+		.map_err(|retry_after| {
+			debug!("RATE LIMIT : {:?} of {} is limited for {} seconds", call, account, retry_after);
+			VerificationError::RATELIMITED(retry_after)
+		})
+}
+
+/// Auth-token stage : the request is in its validity period
+fn verify_authtoken_stage(
+	auth_token: &AuthenticationToken,
+	current_block_number: u32,
+) -> Result<(), VerificationError> {
+	match auth_token.is_valid(current_block_number) {
+		ValidationResult::Success => {
+			debug!("Data auth-token is valid");
+			Ok(())
+		},
+		verify =>
+			Err(VerificationError::EXPIREDDATA(auth_token.clock_skew(current_block_number, verify))),
+	}
+}
+
+/// Co-signature stage : a high-value nft is only released if its designated co-signer has also
+/// signed the request data
+/// # Arguments
+/// * `cosignature` - signature of the data by the co-signer, if any
+/// * `data` - signed data of the request
+/// # Errors
+/// * `COSIGNATUREREQUIRED` - if the nft has a co-signer and the co-signature is missing or invalid
+async fn verify_cosign_stage<C: ChainReader>(
+	chain: &C,
+	nft_id: u32,
+	nft_status: &OnchainNft,
+	cosignature: &Option<String>,
+	data: &[u8],
+) -> Result<(), VerificationError> {
+	let cosigner = match chain.cosigner(nft_id, nft_status.collection_id).await {
+		Some(cosigner) => cosigner,
+		None => return Ok(()),
+	};
+
+	let cosignature = cosignature.as_ref().ok_or(VerificationError::COSIGNATUREREQUIRED)?;
+	match verify_account_signature(None, cosignature, data, &cosigner.0) {
+		Ok(true) => {
+			debug!("Co-signature of nft_id {} is verified", nft_id);
+			Ok(())
+		},
+		_ => Err(VerificationError::COSIGNATUREREQUIRED),
+	}
+}
+
+/// Rental stage : the rentee of a lapsed rental is only served during the grace period
+/// # Returns
+/// * `Option<u32>` - end block of the grace period, None if the requester is not in grace period
+async fn verify_rental_grace_stage<C: ChainReader>(
+	chain: &C,
+	requester_address: &str,
+	nft_id: u32,
+	requester_type: RequesterType,
+) -> Result<Option<u32>, VerificationError> {
+	if requester_type != RequesterType::RENTEE {
+		return Ok(None)
+	}
+
+	let requester = match normalize_address(requester_address) {
+		Some(address) => address,
+		None => return Ok(None),
+	};
+
+	match rental_access(chain, &requester, nft_id).await? {
+		RentalAccess::GRACE(grace_end) => {
+			info!("Rental of nft_id {} is in grace period until block {}", nft_id, grace_end);
+			Ok(Some(grace_end))
+		},
+		_ => Ok(None),
+	}
+}
+
+/// Ownership stage : the requester holds the role on the nft
+/// # Arguments
+/// * `failure` - error of a requester without the role
+async fn verify_ownership_stage<C: ChainReader>(
+	chain: &C,
+	requester_address: String,
+	nft_id: u32,
+	owner: AccountId32,
+	requester_type: RequesterType,
+	failure: VerificationError,
+) -> Result<(), VerificationError> {
+	if verify_requester_type(chain, requester_address, nft_id, owner, requester_type).await? {
+		Ok(())
+	} else {
+		Err(failure)
+	}
+}
+
+/// Provision stage : during a provisioning window, the nfts of its range are stored by its owners
+/// # Errors
+/// * `OWNERSHIPVERIFICATIONFAILED` - if the window expects other owners
+async fn verify_provision_stage<C: ChainReader>(
+	chain: &C,
+	nft_id: u32,
+	owner: &sr25519::Public,
+) -> Result<(), VerificationError> {
+	if chain.is_expected_owner(nft_id, owner).await {
+		Ok(())
+	} else {
+		Err(VerificationError::OWNERSHIPVERIFICATIONFAILED)
+	}
+}
+
+/// Burn stage : a keyshare is removed once its nft is burnt, or is not of the kind anymore
+/// # Errors
+/// * `NOTBURNT` - if the nft still exists as the kind
+async fn verify_burnt_stage<C: ChainReader>(
+	chain: &C,
+	nft_id: u32,
+	kind: NftKind,
+) -> Result<(), VerificationError> {
+	match chain.nft_data(nft_id).await {
+		Err(VerificationError::INVALIDNFTID) => {
+			debug!("nft_id {} is burnt", nft_id);
+			Ok(())
+		},
+
+		Err(err) => Err(err),
+
+		Ok(nft_status) => {
+			if kind.is_kind(&nft_status) {
+				return Err(VerificationError::NOTBURNT)
+			}
+
+			debug!("nft_id {} is converted", nft_id);
+			Ok(())
+		},
+	}
+}
+
+/// Batch size limits and duplicated nft_ids
+fn check_batch_ids(nft_ids: &[u32]) -> Result<(), VerificationError> {
+	if nft_ids.is_empty() || nft_ids.len() > MAX_BATCH_SIZE {
+		return Err(VerificationError::MALFORMATEDDATA)
+	}
+
+	let unique: std::collections::BTreeSet<&u32> = nft_ids.iter().collect();
+	if unique.len() != nft_ids.len() {
+		return Err(VerificationError::MALFORMATEDDATA)
+	}
+
+	Ok(())
+}
 
-		if parsed_data.len() < 3 {
-			return Err(VerificationError::MALFORMATEDSIGNER)
+/* ----------------------------------
+		ACCESS SIMULATION
+----------------------------------*/
+
+/// Run the non-cryptographic stages of an intended request : read-only switch, auth-token
+/// period, onchain state and ownership. Signatures are not checked, the request is not signed.
+/// # Arguments
+/// * `call` - intended api call
+/// * `requester_address` - account which would sign the request
+/// * `requester_type` - role of the requester, retrieve calls only
+/// * `nft_id` - nft/capsule id
+/// * `block_number` - block number of the intended auth-token, None to skip the check
+pub async fn verify_access_simulation<C: ChainReader>(
+	chain: &C,
+	call: APICALL,
+	requester_address: &str,
+	requester_type: RequesterType,
+	nft_id: u32,
+	block_number: Option<u32>,
+) -> Result<(), VerificationError> {
+	let requester =
+		parse_ss58_public(requester_address).ok_or(VerificationError::MALFORMATEDDATA)?;
+	let current_block_number = chain.current_block_number().await;
+
+	if let Some(block_number) = block_number {
+		let auth_token =
+			AuthenticationToken { block_number, block_validation: MAX_VALIDATION_PERIOD };
+		verify_authtoken_stage(&auth_token, current_block_number)?;
+	}
+
+	let kind = call.kind();
+	match call {
+		APICALL::NFTSTORE | APICALL::CAPSULESET | APICALL::NFTBATCHSTORE => {
+			verify_writable(chain).await?;
+			let nft_status =
+				verify_state_stage(chain, nft_id, kind, KeyshareOperation::STORE).await?;
+			verify_ownership_stage(
+				chain,
+				requester.to_string(),
+				nft_id,
+				nft_status.owner,
+				RequesterType::OWNER,
+				VerificationError::OWNERSHIPVERIFICATIONFAILED,
+			)
+			.await
+		},
+
+		APICALL::NFTRETRIEVE | APICALL::CAPSULERETRIEVE | APICALL::NFTBATCHRETRIEVE => {
+			let nft_status =
+				verify_state_stage(chain, nft_id, kind, KeyshareOperation::RETRIEVE).await?;
+			verify_ownership_stage(
+				chain,
+				requester.to_string(),
+				nft_id,
+				nft_status.owner,
+				requester_type,
+				VerificationError::REQUESTERVERIFICATIONFAILED,
+			)
+			.await
+		},
+
+		APICALL::NFTREMOVE | APICALL::CAPSULEREMOVE => {
+			verify_writable(chain).await?;
+			verify_burnt_stage(chain, nft_id, kind).await
+		},
+
+		APICALL::CAPSULEUPDATE => {
+			verify_writable(chain).await?;
+			let nft_status =
+				verify_state_stage(chain, nft_id, kind, KeyshareOperation::UPDATE).await?;
+			verify_ownership_stage(
+				chain,
+				requester.to_string(),
+				nft_id,
+				nft_status.owner,
+				RequesterType::OWNER,
+				VerificationError::OWNERSHIPVERIFICATIONFAILED,
+			)
+			.await
+		},
+	}
+}
+
+/* ----------------------------------
+	SESSION KEY DELEGATION
+----------------------------------*/
+
+impl SessionDelegation {
+	pub fn parse_grant(&self) -> Result<SessionGrant, VerificationError> {
+		serde_json::from_str(&self.grant).map_err(|_| VerificationError::MALFORMATEDSIGNER)
+	}
+
+	/// Verify the grant is signed by the requester and valid at the current block
+	/// # Arguments
+	/// * `requester` - delegating wallet
+	/// * `scheme` - signature scheme of the requester wallet
+	/// * `current_block_number` - current block number
+	/// # Returns
+	/// * `sr25519::Public` - session key
+	pub fn verify(
+		&self,
+		requester: &sr25519::Public,
+		scheme: Option<SignatureScheme>,
+		current_block_number: u32,
+	) -> Result<sr25519::Public, VerificationError> {
+		let grant = self.parse_grant()?;
+		let session_key =
+			parse_ss58_public(&grant.session_key).ok_or(VerificationError::INVALIDSIGNERADDRESS)?;
+
+		if grant.expiry_block < current_block_number {
+			return Err(VerificationError::EXPIREDSIGNER(ClockSkew::session(
+				ValidationResult::ExpiredBlockNumber,
+				current_block_number,
+				grant.expiry_block,
+			)))
 		}
 
-		let account = sr25519::Public::from_ss58check(parsed_data[0])
-			.map_err(|_| VerificationError::INVALIDSIGNERADDRESS)?;
+		if grant.expiry_block - current_block_number > MAX_SESSION_PERIOD {
+			return Err(VerificationError::EXPIREDSIGNER(ClockSkew::session(
+				ValidationResult::InvalidPeriod,
+				current_block_number,
+				grant.expiry_block,
+			)))
+		}
 
-		let block_num =
-			parsed_data[1].parse::<u32>().map_err(|_| VerificationError::INVALIDAUTHTOKEN)?;
+		match verify_account_signature(scheme, &self.signature, self.grant.as_bytes(), &requester.0)
+		{
+			Ok(true) => Ok(session_key),
+			Ok(false) => Err(VerificationError::SIGNERVERIFICATIONFAILED),
+			Err(err) => Err(VerificationError::INVALIDSIGNERSIG(err)),
+		}
+	}
+}
+
+impl SessionGrant {
+	/// The session key may sign the call for the nft
+	pub fn check_scope(&self, call: APICALL, nft_id: u32) -> Result<(), VerificationError> {
+		if self.calls.contains(&call) && self.nft_ids.contains(&nft_id) {
+			Ok(())
+		} else {
+			Err(VerificationError::SESSIONOUTOFSCOPE)
+		}
+	}
+}
+
+/// Account which signs the data of a retrieve request, with its signature scheme.
+/// The session key signs for the requester if it is delegated, its scheme is detected.
+fn retrieve_data_signer(
+	requester: &sr25519::Public,
+	scheme: Option<SignatureScheme>,
+	session: &Option<SessionDelegation>,
+	current_block_number: u32,
+) -> Result<(sr25519::Public, Option<SignatureScheme>), VerificationError> {
+	match session {
+		Some(session) => Ok((session.verify(requester, scheme, current_block_number)?, None)),
+		None => Ok((*requester, scheme)),
+	}
+}
+
+/// Scope stage : a session key is limited to the calls and nft_ids of its grant
+fn verify_session_scope(
+	session: &Option<SessionDelegation>,
+	call: APICALL,
+	nft_id: u32,
+) -> Result<(), VerificationError> {
+	match session {
+		Some(session) => session.parse_grant()?.check_scope(call, nft_id),
+		None => Ok(()),
+	}
+}
+
+/* ----------------------------------
+	STORE-PACKET IMPLEMENTATION
+----------------------------------*/
+
+impl StoreKeysharePacket {
+	/// Nft id of the request for the audit trail, 0 if the data is not parsable
+	pub fn requested_nft_id(&self) -> u32 {
+		self.parse_store_data().map(|data| data.nft_id).unwrap_or(0)
+	}
+
+	pub fn get_signer(&self) -> Result<Signer, VerificationError> {
+		let signer = parse_signer(&self.signer_address).map_err(|err| match err {
+			ParseError::FIELDCOUNT { .. } => VerificationError::MALFORMATEDSIGNER,
+			err => err.into(),
+		})?;
 
-		let block_valid =
-			parsed_data[2].parse::<u32>().map_err(|_| VerificationError::INVALIDAUTHTOKEN)?;
+		let account =
+			parse_ss58_public(signer.address).ok_or(VerificationError::INVALIDSIGNERADDRESS)?;
 
 		Ok(Signer {
 			account,
 			auth_token: AuthenticationToken {
-				block_number: block_num,
-				block_validation: block_valid,
+				block_number: signer.block_number,
+				block_validation: signer.block_validation,
 			},
 		})
 	}
 
 	pub fn parse_store_data(&self) -> Result<StoreKeyshareData, VerificationError> {
-		let mut data = self.data.clone();
-
-		if data.starts_with("<Bytes>") && data.ends_with("</Bytes>") {
-			data = data
-				.strip_prefix("<Bytes>")
-				.ok_or(VerificationError::MALFORMATEDDATA)?
-				.strip_suffix("</Bytes>")
-				.ok_or(VerificationError::MALFORMATEDDATA)?
-				.to_string();
-		}
-
-		let parsed_data: Vec<&str> = if data.contains('_') {
-			data.split('_').collect()
-		} else {
-			return Err(VerificationError::MALFORMATEDDATA)
+		let parsed_data = match self.version {
+			REQUEST_VERSION_LEGACY => self.parse_legacy_store_data()?,
+			REQUEST_VERSION_JWS => {
+				let mut payload = decode_jws::<StoreJwsPayload>(&self.data)?.payload;
+				StoreKeyshareData {
+					nft_id: payload.nft_id,
+					keyshare: Zeroizing::new(std::mem::take(&mut payload.keyshare).into_bytes()),
+					auth_token: AuthenticationToken {
+						block_number: payload.block_number,
+						block_validation: payload.block_validation,
+					},
+					binary: false,
+				}
+			},
+			REQUEST_VERSION_BINARY => self.parse_binary_store_data()?,
+			_ => return Err(VerificationError::MALFORMATEDDATA),
 		};
 
-		if parsed_data.len() != 4 {
-			return Err(VerificationError::MALFORMATEDDATA)
-		}
-
-		let nft_id = parsed_data[0].parse::<u32>().map_err(|_| VerificationError::INVALIDNFTID)?;
-
-		let keyshare = if !parsed_data[1].is_empty() {
-			parsed_data[1].as_bytes().to_vec()
-		} else {
-			return Err(VerificationError::INVALIDKEYSHARE)
-		};
+		check_keyshare(&parsed_data.keyshare, parsed_data.binary)?;
 
-		let keyshare_size = keyshare.len() as u16;
-		if keyshare_size < MIN_KEYSHARE_SIZE {
-			return Err(VerificationError::KEYSHAREISTOOSHORT)
-		}
+		Ok(parsed_data)
+	}
 
-		if keyshare_size > MAX_KEYSHARE_SIZE {
-			return Err(VerificationError::KEYSHAREISTOOLONG)
-		}
+	// "NFTID_secret_block_expiry"
+	fn parse_legacy_store_data(&self) -> Result<StoreKeyshareData, VerificationError> {
+		// The data is only borrowed, the keyshare is copied once into its wiped buffer
+		let fields = parse_store(&self.data)?;
 
-		let block_number =
-			parsed_data[2].parse::<u32>().map_err(|_| VerificationError::INVALIDAUTHTOKEN)?;
+		Ok(StoreKeyshareData {
+			nft_id: fields.nft_id,
+			keyshare: Zeroizing::new(fields.keyshare.as_bytes().to_vec()),
+			auth_token: AuthenticationToken {
+				block_number: fields.block_number,
+				block_validation: fields.block_validation,
+			},
+			binary: false,
+		})
+	}
 
-		let block_validation =
-			parsed_data[3].parse::<u32>().map_err(|_| VerificationError::INVALIDAUTHTOKEN)?;
+	// "NFTID_base64url(secret)_block_expiry", base64url alphabet contains '_'
+	fn parse_binary_store_data(&self) -> Result<StoreKeyshareData, VerificationError> {
+		let fields = parse_binary_store(&self.data)?;
 
 		Ok(StoreKeyshareData {
-			nft_id,
-			keyshare,
-			auth_token: AuthenticationToken { block_number, block_validation },
+			nft_id: fields.nft_id,
+			keyshare: fields.keyshare,
+			auth_token: AuthenticationToken {
+				block_number: fields.block_number,
+				block_validation: fields.block_validation,
+			},
+			binary: true,
 		})
 	}
 
@@ -939,17 +2493,21 @@ impl StoreKeysharePacket {
 		let verify = signer.auth_token.is_valid(current_block_number);
 		match verify {
 			ValidationResult::Success => debug!("Signer auth-token is valid"),
-			_ => return Err(VerificationError::EXPIREDSIGNER(verify)),
+			_ =>
+				return Err(VerificationError::EXPIREDSIGNER(
+					signer.auth_token.clock_skew(current_block_number, verify),
+				)),
 		}
 
-		let signersig = match self.parse_signature("signer") {
-			Ok(sig) => sig,
-			Err(err) => return Err(VerificationError::INVALIDSIGNERSIG(err)),
-		};
-
-		let result =
-			sr25519::Pair::verify(&signersig, self.signer_address.clone(), &self.owner_address);
-		Ok(result)
+		match verify_account_signature(
+			self.signature_type,
+			&self.signersig,
+			self.signer_address.as_bytes(),
+			&self.owner_address.0,
+		) {
+			Ok(result) => Ok(result),
+			Err(err) => Err(VerificationError::INVALIDSIGNERSIG(err)),
+		}
 	}
 
 	// Verify Keyshare data
@@ -959,23 +2517,33 @@ impl StoreKeysharePacket {
 			Err(err) => return Err(err),
 		};
 
-		let packetsig = match self.parse_signature("owner") {
-			Ok(sig) => sig,
-			Err(err) => return Err(VerificationError::INVALIDDATASIG(err)),
-		};
-
-		let result = sr25519::Pair::verify(&packetsig, self.data.clone(), &signer.account);
+		// JWS token carries its own signature
+		if self.version == REQUEST_VERSION_JWS {
+			let token = decode_jws::<StoreJwsPayload>(&self.data)?;
+			return token.verify(&signer.account.0)
+		}
 
-		Ok(result)
+		// Signer key is generated by the client, its scheme is detected
+		match verify_account_signature(
+			None,
+			&self.signature,
+			self.data.as_bytes(),
+			&signer.account.0,
+		) {
+			Ok(result) => Ok(result),
+			Err(err) => Err(VerificationError::INVALIDDATASIG(err)),
+		}
 	}
 
 	/// Verify store request
-	pub async fn verify_store_request(
+	pub async fn verify_store_request<C: ChainReader>(
 		&self,
-		state: &SharedState,
-		nft_type: &str,
+		chain: &C,
+		kind: NftKind,
 	) -> Result<StoreKeyshareData, VerificationError> {
-		let current_block_number = get_blocknumber(state).await;
+		verify_writable(chain).await?;
+
+		let current_block_number = chain.current_block_number().await;
 
 		match self.verify_signer(current_block_number) {
 			Ok(true) => match self.verify_data() {
@@ -985,55 +2553,31 @@ impl StoreKeysharePacket {
 						Err(err) => return Err(err),
 					};
 
-					let onchain_nft_data =
-						match get_onchain_nft_data(state, parsed_data.nft_id).await {
-							Some(nftdata) => nftdata,
-							_ => return Err(VerificationError::INVALIDNFTID),
-						};
-
-					let nft_status = onchain_nft_data.state;
-
-					if nft_type == "secret-nft" {
-						if !nft_status.is_secret {
-							return Err(VerificationError::IDISNOTSECRETNFT)
-						}
-
-						debug!("nft syncing status : {}", nft_status.is_syncing_secret);
-						if !nft_status.is_syncing_secret {
-							return Err(VerificationError::NOTSYNCING)
-						}
-					}
-
-					if nft_type == "capsule" {
-						if !nft_status.is_capsule {
-							return Err(VerificationError::IDISNOTCAPSULE)
-						}
-
-						debug!("capsule syncing status : {}", nft_status.is_syncing_capsule);
-						if !nft_status.is_syncing_capsule {
-							return Err(VerificationError::NOTSYNCING)
-						}
-					}
-
-					let verify = parsed_data.auth_token.clone().is_valid(current_block_number);
-					match verify {
-						ValidationResult::Success => debug!("Signer auth-token is valid"),
-						_ => return Err(VerificationError::EXPIREDDATA(verify)),
-					}
-
-					if verify_requester_type(
-						state,
+					verify_quota_stage(chain, kind.store_call(), &self.owner_address, 1).await?;
+
+					let nft_status = verify_state_stage(
+						chain,
+						parsed_data.nft_id,
+						kind,
+						KeyshareOperation::STORE,
+					)
+					.await?;
+
+					verify_authtoken_stage(&parsed_data.auth_token, current_block_number)?;
+
+					verify_ownership_stage(
+						chain,
 						self.owner_address.to_string(),
 						parsed_data.nft_id,
-						onchain_nft_data.owner,
+						nft_status.owner,
 						RequesterType::OWNER,
+						VerificationError::OWNERSHIPVERIFICATIONFAILED,
 					)
-					.await
-					{
-						Ok(parsed_data)
-					} else {
-						Err(VerificationError::OWNERSHIPVERIFICATIONFAILED)
-					}
+					.await?;
+
+					verify_provision_stage(chain, parsed_data.nft_id, &self.owner_address).await?;
+
+					Ok(parsed_data)
 				},
 				Ok(false) => Err(VerificationError::DATAVERIFICATIONFAILED),
 				Err(err) => Err(err),
@@ -1078,6 +2622,11 @@ impl StoreKeysharePacket {
 ----------------------------------*/
 
 impl RetrieveKeysharePacket {
+	/// Nft id of the request for the audit trail, 0 if the data is not parsable
+	pub fn requested_nft_id(&self) -> u32 {
+		self.parse_retrieve_data().map(|data| data.nft_id).unwrap_or(0)
+	}
+
 	// Extract signatures from hex
 	pub fn parse_signature(&self) -> Result<sr25519::Signature, SignatureError> {
 		let sig = self.signature.clone();
@@ -1096,46 +2645,27 @@ impl RetrieveKeysharePacket {
 	}
 
 	pub fn parse_retrieve_data(&self) -> Result<RetrieveKeyshareData, VerificationError> {
-		let mut data = self.data.clone();
-
-		if data.starts_with("<Bytes>") && data.ends_with("</Bytes>") {
-			data = data
-				.strip_prefix("<Bytes>")
-				.ok_or(VerificationError::MALFORMATEDDATA)?
-				.strip_suffix("</Bytes>")
-				.ok_or(VerificationError::MALFORMATEDDATA)?
-				.to_string();
-		}
-
-		let parsed_data: Vec<&str> = if data.contains('_') {
-			data.split('_').collect()
-		} else {
-			return Err(VerificationError::MALFORMATEDDATA)
-		};
-
-		if parsed_data.len() != 3 {
-			return Err(VerificationError::MALFORMATEDDATA)
+		match self.version {
+			// Retrieve data has no keyshare, binary version is the legacy format
+			REQUEST_VERSION_LEGACY | REQUEST_VERSION_BINARY => self.parse_legacy_retrieve_data(),
+			REQUEST_VERSION_JWS => {
+				let payload = decode_jws::<RetrieveJwsPayload>(&self.data)?.payload;
+				Ok(RetrieveKeyshareData {
+					nft_id: payload.nft_id,
+					auth_token: AuthenticationToken {
+						block_number: payload.block_number,
+						block_validation: payload.block_validation,
+					},
+					rental_grace: None,
+				})
+			},
+			_ => Err(VerificationError::MALFORMATEDDATA),
 		}
+	}
 
-		let nft_id = match parsed_data[0].parse::<u32>() {
-			Ok(n) => n,
-			Err(_) => return Err(VerificationError::INVALIDNFTID),
-		};
-
-		let block_number = match parsed_data[1].parse::<u32>() {
-			Ok(bn) => bn,
-			Err(_) => return Err(VerificationError::INVALIDAUTHTOKEN),
-		};
-
-		let block_validation = match parsed_data[2].parse::<u32>() {
-			Ok(bv) => bv,
-			Err(_) => return Err(VerificationError::INVALIDAUTHTOKEN),
-		};
-
-		Ok(RetrieveKeyshareData {
-			nft_id,
-			auth_token: AuthenticationToken { block_number, block_validation },
-		})
+	// "NFTID_block_expiry"
+	fn parse_legacy_retrieve_data(&self) -> Result<RetrieveKeyshareData, VerificationError> {
+		Ok(parse_retrieve(&self.data)?.into())
 	}
 
 	// VERIFY KEYSHARE DATA : TOKEN & SIGNATURE
@@ -1148,26 +2678,67 @@ impl RetrieveKeysharePacket {
 		let verify = data.auth_token.is_valid(current_block_number);
 		match verify {
 			ValidationResult::Success => debug!("Data auth-token is valid"),
-			_ => return Err(VerificationError::EXPIREDDATA(verify)),
+			_ =>
+				return Err(VerificationError::EXPIREDDATA(
+					data.auth_token.clock_skew(current_block_number, verify),
+				)),
 		}
 
-		let sig = match self.parse_signature() {
-			Ok(sig) => sig,
-			Err(err) => return Err(VerificationError::INVALIDSIGNERSIG(err)),
-		};
-
-		let result = sr25519::Pair::verify(&sig, self.data.clone(), &self.requester_address);
+		let (signer, scheme) = retrieve_data_signer(
+			&self.requester_address,
+			self.signature_type,
+			&self.session,
+			current_block_number,
+		)?;
+
+		// JWS token carries its own signature
+		if self.version == REQUEST_VERSION_JWS {
+			let token = decode_jws::<RetrieveJwsPayload>(&self.data)?;
+			return token.verify(&signer.0)
+		}
 
-		Ok(result)
+		match verify_account_signature(scheme, &self.signature, self.data.as_bytes(), &signer.0) {
+			Ok(result) => Ok(result),
+			Err(err) => Err(VerificationError::INVALIDSIGNERSIG(err)),
+		}
 	}
 
-	/// Verify the requester is the owner of the NFT
+	/// Verify the retrieve request and register it in replay journal
 	pub async fn verify_retrieve_request(
 		&self,
 		state: &SharedState,
-		nft_type: &str,
+		kind: NftKind,
+	) -> Result<RetrieveKeyshareData, VerificationError> {
+		let parsed_data = self.verify_retrieve_access(state, kind).await?;
+
+		let (digest, expiry_block) = self.replay_digest(&parsed_data);
+		if !register_request(state, digest, expiry_block).await {
+			return Err(VerificationError::REPLAYEDREQUEST)
+		}
+
+		Ok(parsed_data)
+	}
+
+	/// Replay protection : each signed request is served once in its validity window
+	/// # Returns
+	/// * `(String, u32)` - request digest and last block number that the request is valid
+	pub fn replay_digest(&self, parsed_data: &RetrieveKeyshareData) -> (String, u32) {
+		let digest = sha256::digest(format!("{}_{}", self.requester_address, self.data));
+		let expiry_block = parsed_data
+			.auth_token
+			.block_number
+			.saturating_add(parsed_data.auth_token.block_validation);
+
+		(digest, expiry_block)
+	}
+
+	/// Verify the requester is the owner/delegatee/rentee of the NFT
+	pub async fn verify_retrieve_access<C: ChainReader>(
+		&self,
+		chain: &C,
+		kind: NftKind,
 	) -> Result<RetrieveKeyshareData, VerificationError> {
-		let current_block_number = get_blocknumber(state).await;
+		let current_block_number = chain.current_block_number().await;
 
 		match self.verify_data(current_block_number) {
 			Ok(true) => {
@@ -1176,54 +2747,47 @@ impl RetrieveKeysharePacket {
 					Err(err) => return Err(err),
 				};
 
-				let onchain_nft_data = match get_onchain_nft_data(state, parsed_data.nft_id).await {
-					Some(nftdata) => nftdata,
-					_ => return Err(VerificationError::INVALIDNFTID),
-				};
-
-				let nft_status = onchain_nft_data.state;
-
-				if nft_type == "secret-nft" {
-					if !nft_status.is_secret {
-						return Err(VerificationError::IDISNOTSECRETNFT)
-					}
+				verify_session_scope(&self.session, kind.retrieve_call(), parsed_data.nft_id)?;
+				verify_quota_stage(chain, kind.retrieve_call(), &self.requester_address, 1).await?;
 
-					debug!("nft syncing status : {}", nft_status.is_syncing_secret);
-					if nft_status.is_syncing_secret {
-						return Err(VerificationError::NOTSYNCED)
-					}
-				}
+				let nft_status = verify_state_stage(
+					chain,
+					parsed_data.nft_id,
+					kind,
+					KeyshareOperation::RETRIEVE,
+				)
+				.await?;
 
-				if nft_type == "capsule" {
-					if !nft_status.is_capsule {
-						return Err(VerificationError::IDISNOTCAPSULE)
-					}
+				verify_authtoken_stage(&parsed_data.auth_token, current_block_number)?;
 
-					debug!("capsule syncing status : {}", nft_status.is_syncing_capsule);
-					if nft_status.is_syncing_capsule {
-						return Err(VerificationError::NOTSYNCED)
-					}
-				}
+				verify_ownership_stage(
+					chain,
+					self.requester_address.to_string(),
+					parsed_data.nft_id,
+					nft_status.owner.clone(),
+					self.requester_type,
+					VerificationError::REQUESTERVERIFICATIONFAILED,
+				)
+				.await?;
 
-				let verify = parsed_data.auth_token.clone().is_valid(current_block_number);
-				match verify {
-					ValidationResult::Success => debug!("Data auth-token is valid"),
-					_ => return Err(VerificationError::EXPIREDDATA(verify)),
-				}
+				verify_cosign_stage(
+					chain,
+					parsed_data.nft_id,
+					&nft_status,
+					&self.cosignature,
+					self.data.as_bytes(),
+				)
+				.await?;
 
-				if verify_requester_type(
-					state,
-					self.requester_address.to_string(),
+				let rental_grace = verify_rental_grace_stage(
+					chain,
+					&self.requester_address.to_string(),
 					parsed_data.nft_id,
-					onchain_nft_data.owner,
 					self.requester_type,
 				)
-				.await
-				{
-					Ok(parsed_data)
-				} else {
-					Err(VerificationError::REQUESTERVERIFICATIONFAILED)
-				}
+				.await?;
+
+				Ok(RetrieveKeyshareData { rental_grace, ..parsed_data })
 			},
 			// INVALID DATA SIGNATURE
 			Ok(false) => Err(VerificationError::SIGNERVERIFICATIONFAILED),
@@ -1256,6 +2820,11 @@ impl RetrieveKeysharePacket {
 ----------------------------------*/
 
 impl RemoveKeysharePacket {
+	/// Nft id of the request for the audit trail, 0 if the data is not parsable
+	pub fn requested_nft_id(&self) -> u32 {
+		self.parse_retrieve_data().map(|data| data.nft_id).unwrap_or(0)
+	}
+
 	// Extract signatures from hex
 	pub fn parse_signature(&self) -> Result<sr25519::Signature, SignatureError> {
 		let sig = self.signature.clone();
@@ -1274,146 +2843,452 @@ impl RemoveKeysharePacket {
 	}
 
 	pub fn parse_retrieve_data(&self) -> Result<RetrieveKeyshareData, VerificationError> {
-		let mut data = self.data.clone();
-
-		if data.starts_with("<Bytes>") && data.ends_with("</Bytes>") {
-			data = data
-				.strip_prefix("<Bytes>")
-				.ok_or(VerificationError::MALFORMATEDDATA)?
-				.strip_suffix("</Bytes>")
-				.ok_or(VerificationError::MALFORMATEDDATA)?
-				.to_string();
-		}
+		Ok(parse_retrieve(&self.data)?.into())
+	}
 
-		let parsed_data: Vec<&str> = if data.contains('_') {
-			data.split('_').collect()
-		} else {
-			return Err(VerificationError::MALFORMATEDDATA)
+	// VERIFY KEYSHARE DATA : TOKEN & SIGNATURE
+	pub fn verify_data(&self, current_block_number: u32) -> Result<bool, VerificationError> {
+		let data = match self.parse_retrieve_data() {
+			Ok(sec) => sec,
+			Err(err) => return Err(err),
 		};
 
-		if parsed_data.len() != 3 {
-			return Err(VerificationError::MALFORMATEDDATA)
+		let verify = data.auth_token.is_valid(current_block_number);
+		match verify {
+			ValidationResult::Success => debug!("Data auth-token is valid"),
+			_ =>
+				return Err(VerificationError::EXPIREDDATA(
+					data.auth_token.clock_skew(current_block_number, verify),
+				)),
 		}
 
-		let nft_id = match parsed_data[0].parse::<u32>() {
-			Ok(n) => n,
-			Err(_) => return Err(VerificationError::INVALIDNFTID),
-		};
+		match verify_account_signature(
+			self.signature_type,
+			&self.signature,
+			self.data.as_bytes(),
+			&self.requester_address.0,
+		) {
+			Ok(result) => Ok(result),
+			Err(err) => Err(VerificationError::INVALIDSIGNERSIG(err)),
+		}
+	}
 
-		let block_number = match parsed_data[1].parse::<u32>() {
-			Ok(bn) => bn,
-			Err(_) => return Err(VerificationError::INVALIDAUTHTOKEN),
-		};
+	/// Verify the remove request : signed data, valid auth-token and burnt nft/capsule
+	/// # Arguments
+	/// * `chain` - onchain data reader
+	/// * `kind` - secret-nft or capsule
+	/// # Returns
+	/// * `RetrieveKeyshareData` - nft_id and auth-token of the request
+	/// # Errors
+	/// * `NOTBURNT` - if the nft still exists as secret-nft/capsule
+	pub async fn verify_remove_request<C: ChainReader>(
+		&self,
+		chain: &C,
+		kind: NftKind,
+	) -> Result<RetrieveKeyshareData, VerificationError> {
+		verify_writable(chain).await?;
 
-		let block_validation = match parsed_data[2].parse::<u32>() {
-			Ok(bv) => bv,
-			Err(_) => return Err(VerificationError::INVALIDAUTHTOKEN),
-		};
+		let current_block_number = chain.current_block_number().await;
 
-		Ok(RetrieveKeyshareData {
-			nft_id,
-			auth_token: AuthenticationToken { block_number, block_validation },
-		})
+		// Signature and auth-token
+		match self.verify_data(current_block_number) {
+			Ok(true) => debug!("Remove request data is verified"),
+			Ok(false) => return Err(VerificationError::DATAVERIFICATIONFAILED),
+			Err(err) => return Err(err),
+		}
+
+		let parsed_data = self.parse_retrieve_data()?;
+
+		// Burnt nft/capsule does not exist onchain anymore
+		verify_burnt_stage(chain, parsed_data.nft_id, kind).await?;
+
+		Ok(parsed_data)
 	}
 
-	// VERIFY KEYSHARE DATA : TOKEN & SIGNATURE
-	pub fn verify_data(&self, current_block_number: u32) -> Result<bool, VerificationError> {
+	// VERIFTY FREE RETRIVE REQUEST
+	#[allow(dead_code)]
+	pub async fn verify_free_retrieve_request(
+		&self,
+		current_block_number: u32,
+	) -> Result<RetrieveKeyshareData, VerificationError> {
 		let data = match self.parse_retrieve_data() {
 			Ok(sec) => sec,
 			Err(err) => return Err(err),
 		};
 
-		let verify = data.auth_token.is_valid(current_block_number);
+		match self.verify_data(current_block_number) {
+			Ok(true) => Ok(data),
+			Ok(false) => Err(VerificationError::DATAVERIFICATIONFAILED),
+			Err(err) => Err(err),
+		}
+	}
+}
+
+/* ----------------------------------
+	BATCH-PACKETS IMPLEMENTATION
+----------------------------------*/
+
+impl BatchStoreKeysharePacket {
+	/// Store packet of the whole batch data, signer and signatures are verified the same way
+	fn as_store_packet(&self) -> StoreKeysharePacket {
+		StoreKeysharePacket {
+			owner_address: self.owner_address,
+			signer_address: self.signer_address.clone(),
+			signersig: self.signersig.clone(),
+			data: self.data.clone(),
+			signature: self.signature.clone(),
+			version: REQUEST_VERSION_LEGACY,
+			signature_type: self.signature_type,
+		}
+	}
+
+	pub fn parse_batch_data(&self) -> Result<BatchStoreData, VerificationError> {
+		let batch_data: BatchStoreData =
+			serde_json::from_str(&self.data).map_err(|_| VerificationError::MALFORMATEDDATA)?;
+
+		let nft_ids: Vec<u32> = batch_data.entries.iter().map(|entry| entry.nft_id).collect();
+		check_batch_ids(&nft_ids)?;
+
+		Ok(batch_data)
+	}
+
+	/// Verify the batch signatures and auth-token, then the onchain state of each item
+	/// # Arguments
+	/// * `chain` - onchain data reader
+	/// * `kind` - secret-nft or capsule
+	/// # Returns
+	/// * `BatchVerification<StoreKeyshareData>` - verified data or error of each item
+	pub async fn verify_batch_store_request<C: ChainReader>(
+		&self,
+		chain: &C,
+		kind: NftKind,
+	) -> Result<BatchVerification<StoreKeyshareData>, VerificationError> {
+		verify_writable(chain).await?;
+
+		let current_block_number = chain.current_block_number().await;
+		let packet = self.as_store_packet();
+
+		match packet.verify_signer(current_block_number) {
+			Ok(true) => debug!("Batch signer is verified"),
+			Ok(false) => return Err(VerificationError::SIGNERVERIFICATIONFAILED),
+			Err(err) => return Err(err),
+		}
+
+		match packet.verify_data() {
+			Ok(true) => debug!("Batch data is verified"),
+			Ok(false) => return Err(VerificationError::DATAVERIFICATIONFAILED),
+			Err(err) => return Err(err),
+		}
+
+		let batch_data = self.parse_batch_data()?;
+		let auth_token = AuthenticationToken {
+			block_number: batch_data.block_number,
+			block_validation: batch_data.block_validation,
+		};
+
+		let verify = auth_token.is_valid(current_block_number);
 		match verify {
-			ValidationResult::Success => debug!("Data auth-token is valid"),
-			_ => return Err(VerificationError::EXPIREDDATA(verify)),
+			ValidationResult::Success => debug!("Batch auth-token is valid"),
+			_ =>
+				return Err(VerificationError::EXPIREDDATA(
+					auth_token.clock_skew(current_block_number, verify),
+				)),
+		}
+
+		verify_quota_stage(chain, kind.store_call(), &self.owner_address, batch_data.entries.len())
+			.await?;
+
+		let mut items = Vec::new();
+		for entry in batch_data.entries {
+			let nft_id = entry.nft_id;
+			let item = self.verify_batch_item(chain, entry, &auth_token, kind).await;
+			items.push((nft_id, item));
+		}
+
+		Ok(items)
+	}
+
+	async fn verify_batch_item<C: ChainReader>(
+		&self,
+		chain: &C,
+		mut entry: BatchStoreEntry,
+		auth_token: &AuthenticationToken,
+		kind: NftKind,
+	) -> Result<StoreKeyshareData, VerificationError> {
+		let keyshare = Zeroizing::new(std::mem::take(&mut entry.keyshare).into_bytes());
+		check_keyshare(&keyshare, false)?;
+
+		let nft_status =
+			verify_state_stage(chain, entry.nft_id, kind, KeyshareOperation::STORE).await?;
+
+		verify_ownership_stage(
+			chain,
+			self.owner_address.to_string(),
+			entry.nft_id,
+			nft_status.owner,
+			RequesterType::OWNER,
+			VerificationError::OWNERSHIPVERIFICATIONFAILED,
+		)
+		.await?;
+
+		verify_provision_stage(chain, entry.nft_id, &self.owner_address).await?;
+
+		Ok(StoreKeyshareData {
+			nft_id: entry.nft_id,
+			keyshare,
+			auth_token: auth_token.clone(),
+			binary: false,
+		})
+	}
+}
+
+/* ----------------------------------
+	UPDATE-PACKET IMPLEMENTATION
+----------------------------------*/
+
+impl UpdateKeysharePacket {
+	/// Nft id of the request for the audit trail, 0 if the data is not parsable
+	pub fn requested_nft_id(&self) -> u32 {
+		self.parse_update_data().map(|data| data.nft_id).unwrap_or(0)
+	}
+
+	/// Store packet of the update data, signer and signatures are verified the same way
+	fn as_store_packet(&self) -> StoreKeysharePacket {
+		StoreKeysharePacket {
+			owner_address: self.owner_address,
+			signer_address: self.signer_address.clone(),
+			signersig: self.signersig.clone(),
+			data: self.data.clone(),
+			signature: self.signature.clone(),
+			version: REQUEST_VERSION_LEGACY,
+			signature_type: self.signature_type,
+		}
+	}
+
+	pub fn parse_update_data(&self) -> Result<StoreKeyshareData, VerificationError> {
+		let mut update_data: UpdateKeyshareData =
+			serde_json::from_str(&self.data).map_err(|_| VerificationError::MALFORMATEDDATA)?;
+
+		let keyshare = Zeroizing::new(std::mem::take(&mut update_data.keyshare).into_bytes());
+		check_keyshare(&keyshare, false)?;
+
+		Ok(StoreKeyshareData {
+			nft_id: update_data.nft_id,
+			keyshare,
+			auth_token: AuthenticationToken {
+				block_number: update_data.block_number,
+				block_validation: update_data.block_validation,
+			},
+			binary: false,
+		})
+	}
+
+	/// Verify the update request and register it in replay journal, a replayed update would
+	/// restore an older keyshare
+	pub async fn verify_update_request(
+		&self,
+		state: &SharedState,
+	) -> Result<StoreKeyshareData, VerificationError> {
+		let update_data = self.verify_update_access(state).await?;
+
+		let digest = sha256::digest(format!("{}_{}", self.owner_address, self.data));
+		let expiry_block = update_data
+			.auth_token
+			.block_number
+			.saturating_add(update_data.auth_token.block_validation);
+
+		if !register_request(state, digest, expiry_block).await {
+			return Err(VerificationError::REPLAYEDREQUEST)
+		}
+
+		Ok(update_data)
+	}
+
+	/// Verify the signatures and auth-token, then the owner of the synced capsule
+	/// # Arguments
+	/// * `chain` - onchain data reader
+	/// # Returns
+	/// * `StoreKeyshareData` - nft_id and new keyshare of the capsule
+	pub async fn verify_update_access<C: ChainReader>(
+		&self,
+		chain: &C,
+	) -> Result<StoreKeyshareData, VerificationError> {
+		verify_writable(chain).await?;
+
+		let current_block_number = chain.current_block_number().await;
+		let packet = self.as_store_packet();
+
+		match packet.verify_signer(current_block_number) {
+			Ok(true) => debug!("Update signer is verified"),
+			Ok(false) => return Err(VerificationError::SIGNERVERIFICATIONFAILED),
+			Err(err) => return Err(err),
 		}
 
-		let sig = match self.parse_signature() {
-			Ok(sig) => sig,
-			Err(err) => return Err(VerificationError::INVALIDSIGNERSIG(err)),
+		match packet.verify_data() {
+			Ok(true) => debug!("Update data is verified"),
+			Ok(false) => return Err(VerificationError::DATAVERIFICATIONFAILED),
+			Err(err) => return Err(err),
+		}
+
+		let update_data = self.parse_update_data()?;
+		verify_authtoken_stage(&update_data.auth_token, current_block_number)?;
+		verify_quota_stage(chain, APICALL::CAPSULESET, &self.owner_address, 1).await?;
+
+		let nft_status = verify_state_stage(
+			chain,
+			update_data.nft_id,
+			NftKind::CAPSULE,
+			KeyshareOperation::UPDATE,
+		)
+		.await?;
+
+		verify_ownership_stage(
+			chain,
+			self.owner_address.to_string(),
+			update_data.nft_id,
+			nft_status.owner,
+			RequesterType::OWNER,
+			VerificationError::OWNERSHIPVERIFICATIONFAILED,
+		)
+		.await?;
+
+		Ok(update_data)
+	}
+}
+
+impl BatchRetrieveKeysharePacket {
+	pub fn parse_batch_data(&self) -> Result<BatchRetrieveData, VerificationError> {
+		let batch_data: BatchRetrieveData =
+			serde_json::from_str(&self.data).map_err(|_| VerificationError::MALFORMATEDDATA)?;
+
+		check_batch_ids(&batch_data.nft_ids)?;
+
+		Ok(batch_data)
+	}
+
+	// VERIFY BATCH DATA : TOKEN & SIGNATURE
+	pub fn verify_data(&self, current_block_number: u32) -> Result<bool, VerificationError> {
+		let batch_data = self.parse_batch_data()?;
+
+		let auth_token = AuthenticationToken {
+			block_number: batch_data.block_number,
+			block_validation: batch_data.block_validation,
 		};
 
-		let result = sr25519::Pair::verify(&sig, self.data.clone(), &self.requester_address);
+		let verify = auth_token.is_valid(current_block_number);
+		match verify {
+			ValidationResult::Success => debug!("Batch auth-token is valid"),
+			_ =>
+				return Err(VerificationError::EXPIREDDATA(
+					auth_token.clock_skew(current_block_number, verify),
+				)),
+		}
 
-		Ok(result)
+		let (signer, scheme) = retrieve_data_signer(
+			&self.requester_address,
+			self.signature_type,
+			&self.session,
+			current_block_number,
+		)?;
+
+		match verify_account_signature(scheme, &self.signature, self.data.as_bytes(), &signer.0) {
+			Ok(result) => Ok(result),
+			Err(err) => Err(VerificationError::INVALIDSIGNERSIG(err)),
+		}
 	}
 
-	/// Verify the requester is the owner of the NFT
-	pub async fn verify_remove_request(
+	/// Verify the batch retrieve request and register it in replay journal
+	pub async fn verify_batch_retrieve_request(
 		&self,
 		state: &SharedState,
-		nft_type: &str,
-	) -> Result<RetrieveKeyshareData, VerificationError> {
-		let current_block_number = get_blocknumber(state).await;
+		kind: NftKind,
+	) -> Result<BatchVerification<RetrieveKeyshareData>, VerificationError> {
+		let items = self.verify_batch_retrieve_access(state, kind).await?;
+		let batch_data = self.parse_batch_data()?;
 
-		match self.verify_data(current_block_number) {
-			Ok(true) => {
-				let parsed_data = match self.parse_retrieve_data() {
-					Ok(parsed) => parsed,
-					Err(err) => return Err(err),
-				};
+		let digest = sha256::digest(format!("{}_{}", self.requester_address, self.data));
+		let expiry_block = batch_data.block_number.saturating_add(batch_data.block_validation);
 
-				let onchain_nft_data = match get_onchain_nft_data(state, parsed_data.nft_id).await {
-					Some(nftdata) => nftdata,
-					_ => return Err(VerificationError::INVALIDNFTID),
-				};
+		if !register_request(state, digest, expiry_block).await {
+			return Err(VerificationError::REPLAYEDREQUEST)
+		}
 
-				let nft_status = onchain_nft_data.state;
+		Ok(items)
+	}
 
-				if nft_type == "secret-nft" {
-					if !nft_status.is_secret {
-						return Err(VerificationError::IDISNOTSECRETNFT)
-					}
+	/// Verify the batch signature, then the requester access to each item
+	/// # Arguments
+	/// * `chain` - onchain data reader
+	/// * `kind` - secret-nft or capsule
+	/// # Returns
+	/// * `BatchVerification<RetrieveKeyshareData>` - verified data or error of each item
+	pub async fn verify_batch_retrieve_access<C: ChainReader>(
+		&self,
+		chain: &C,
+		kind: NftKind,
+	) -> Result<BatchVerification<RetrieveKeyshareData>, VerificationError> {
+		let current_block_number = chain.current_block_number().await;
 
-					debug!("nft syncing status : {}", nft_status.is_syncing_secret);
-					if nft_status.is_syncing_secret {
-						return Err(VerificationError::NOTSYNCED)
-					}
-				}
+		match self.verify_data(current_block_number) {
+			Ok(true) => debug!("Batch data is verified"),
+			Ok(false) => return Err(VerificationError::SIGNERVERIFICATIONFAILED),
+			Err(err) => return Err(err),
+		}
 
-				if nft_type == "capsule" {
-					if !nft_status.is_capsule {
-						return Err(VerificationError::IDISNOTCAPSULE)
-					}
+		let batch_data = self.parse_batch_data()?;
+		let auth_token = AuthenticationToken {
+			block_number: batch_data.block_number,
+			block_validation: batch_data.block_validation,
+		};
 
-					debug!("capsule syncing status : {}", nft_status.is_syncing_capsule);
-					if nft_status.is_syncing_capsule {
-						return Err(VerificationError::NOTSYNCED)
-					}
-				}
+		verify_quota_stage(
+			chain,
+			kind.retrieve_call(),
+			&self.requester_address,
+			batch_data.nft_ids.len(),
+		)
+		.await?;
 
-				let verify = parsed_data.auth_token.clone().is_valid(current_block_number);
-				match verify {
-					ValidationResult::Success => debug!("Data auth-token is valid"),
-					_ => return Err(VerificationError::EXPIREDDATA(verify)),
-				}
+		let mut items = Vec::new();
+		for nft_id in batch_data.nft_ids {
+			if let Err(err) = verify_session_scope(&self.session, APICALL::NFTBATCHRETRIEVE, nft_id)
+			{
+				items.push((nft_id, Err(err)));
+				continue
+			}
 
-				Ok(parsed_data)
-			},
-			// INVALID DATA SIGNATURE
-			Ok(false) => Err(VerificationError::SIGNERVERIFICATIONFAILED),
+			let item = self.verify_batch_item(chain, nft_id, kind).await.map(|_| {
+				RetrieveKeyshareData { nft_id, auth_token: auth_token.clone(), rental_grace: None }
+			});
 
-			Err(err) => Err(err),
+			items.push((nft_id, item));
 		}
+
+		Ok(items)
 	}
 
-	// VERIFTY FREE RETRIVE REQUEST
-	#[allow(dead_code)]
-	pub async fn verify_free_retrieve_request(
+	// State, ownership and co-signature of one item
+	async fn verify_batch_item<C: ChainReader>(
 		&self,
-		current_block_number: u32,
-	) -> Result<RetrieveKeyshareData, VerificationError> {
-		let data = match self.parse_retrieve_data() {
-			Ok(sec) => sec,
-			Err(err) => return Err(err),
-		};
+		chain: &C,
+		nft_id: u32,
+		kind: NftKind,
+	) -> Result<(), VerificationError> {
+		let nft_status =
+			verify_state_stage(chain, nft_id, kind, KeyshareOperation::RETRIEVE).await?;
+
+		verify_ownership_stage(
+			chain,
+			self.requester_address.to_string(),
+			nft_id,
+			nft_status.owner.clone(),
+			self.requester_type,
+			VerificationError::REQUESTERVERIFICATIONFAILED,
+		)
+		.await?;
 
-		match self.verify_data(current_block_number) {
-			Ok(true) => Ok(data),
-			Ok(false) => Err(VerificationError::DATAVERIFICATIONFAILED),
-			Err(err) => Err(err),
-		}
+		verify_cosign_stage(chain, nft_id, &nft_status, &self.cosignature, self.data.as_bytes())
+			.await
 	}
 }
 
@@ -1425,13 +3300,15 @@ impl RemoveKeysharePacket {
 mod test {
 
 	use super::*;
+	use crate::chain::reader::mock::MockChain;
+
+	const TEST_BLOCK_NUMBER: u32 = 1_000_000;
+
 	/* ----------------------
 		HELPER FUNCTIONS
 	---------------------- */
 	/// Generate a random string of a given length
-	async fn generate_store_request(nftid: u32) -> StoreKeysharePacket {
-		let current_block_number = get_current_block_number_new_api().await.unwrap();
-
+	async fn generate_store_request(nftid: u32, current_block_number: u32) -> StoreKeysharePacket {
 		let owner = sr25519::Pair::from_phrase(
 			"theme affair risk blue world review hazard social arrow usage unveil surge",
 			None,
@@ -1460,6 +3337,8 @@ mod test {
 			signersig: format!("{}{:?}", "0x", signersig),
 			data,
 			signature: format!("{}{:?}", "0x", signature),
+			version: REQUEST_VERSION_LEGACY,
+			signature_type: None,
 		};
 
 		println!("StoreKeysharePacket = {}\n", serde_json::to_string_pretty(&packet).unwrap());
@@ -1468,9 +3347,10 @@ mod test {
 	}
 
 	/// Generate a random string of a given length
-	async fn generate_retrieve_request(nftid: u32) -> RetrieveKeysharePacket {
-		let current_block_number = get_current_block_number_new_api().await.unwrap();
-
+	async fn generate_retrieve_request(
+		nftid: u32,
+		current_block_number: u32,
+	) -> RetrieveKeysharePacket {
 		let owner = sr25519::Pair::from_phrase(
 			"theme affair risk blue world review hazard social arrow usage unveil surge",
 			None,
@@ -1485,6 +3365,10 @@ mod test {
 			requester_type: RequesterType::OWNER,
 			data,
 			signature: format!("{}{:?}", "0x", signature),
+			version: REQUEST_VERSION_LEGACY,
+			signature_type: None,
+			session: None,
+			cosignature: None,
 		};
 
 		println!("RetrieveKeysharePacket = {}\n", serde_json::to_string_pretty(&packet).unwrap());
@@ -1493,7 +3377,10 @@ mod test {
 	}
 
 	/// Generate a random string of a given length
-	async fn generate_remove_request(nftid: u32) -> RemoveKeysharePacket {
+	async fn generate_remove_request(
+		nftid: u32,
+		current_block_number: u32,
+	) -> RemoveKeysharePacket {
 		let signer = sr25519::Pair::from_phrase(
 			"steel announce garden guilt direct give morning gadget milk census poem faith",
 			None,
@@ -1501,14 +3388,15 @@ mod test {
 		.unwrap()
 		.0;
 
-		let current_block_number = get_current_block_number_new_api().await.unwrap();
 		let data = format!("{}_{}_10", nftid, current_block_number);
 		let requester_address = signer.public();
 
+		let signature = format!("{}{:?}", "0x", signer.sign(data.as_bytes()));
 		let packet = RemoveKeysharePacket {
-			requester_address, // Because anybody can ask to remove burnt data
+			requester_address, // Metric server
 			data,
-			signature: format!("{}{:?}", "0x", signer.sign(&nftid.to_le_bytes())),
+			signature,
+			signature_type: None,
 		};
 
 		println!("RemoveKeysharePacket = {}\n", serde_json::to_string_pretty(&packet).unwrap());
@@ -1526,6 +3414,8 @@ mod test {
 			signer_address: sr25519::Public::from_slice(&[1u8; 32]).unwrap().to_string(),
 			data: "163_1234567890abcdef_1000_15".to_string(),
 			signature: "xxx".to_string(),
+			version: REQUEST_VERSION_LEGACY,
+			signature_type: None,
 			signersig: "xxx".to_string(),
 		};
 
@@ -1533,7 +3423,7 @@ mod test {
 		let data = packet_sdk.parse_store_data().unwrap();
 
 		assert_eq!(data.nft_id, 163);
-		assert_eq!(data.keyshare, b"1234567890abcdef");
+		assert_eq!(*data.keyshare, b"1234567890abcdef");
 		assert_eq!(data.auth_token.block_number, 1000);
 		assert_eq!(data.auth_token.block_validation, 15);
 	}
@@ -1545,13 +3435,15 @@ mod test {
 			signer_address: sr25519::Public::from_slice(&[1u8; 32]).unwrap().to_string(),
 			data: "<Bytes>163_1234567890abcdef_1000_15</Bytes>".to_string(),
 			signature: "xxx".to_string(),
+			version: REQUEST_VERSION_LEGACY,
+			signature_type: None,
 			signersig: "xxx".to_string(),
 		};
 		// Signed in Polkadot.JS
 		let data = packet_polkadotjs.parse_store_data().unwrap();
 
 		assert_eq!(data.nft_id, 163);
-		assert_eq!(data.keyshare, b"1234567890abcdef");
+		assert_eq!(*data.keyshare, b"1234567890abcdef");
 		assert_eq!(data.auth_token.block_number, 1000);
 		assert_eq!(data.auth_token.block_validation, 15);
 	}
@@ -1566,6 +3458,8 @@ mod test {
 			signer_address: sr25519::Public::from_slice(&[1u8; 32]).unwrap().to_string(),
 			data: "xxx".to_string(),
 			signature: "xxx".to_string(),
+			version: REQUEST_VERSION_LEGACY,
+			signature_type: None,
 			signersig: "xxx".to_string(),
 		};
 
@@ -1589,6 +3483,8 @@ mod test {
 			signer_address: sr25519::Public::from_slice(&[1u8;32]).unwrap().to_string(),
 			data: "xxx".to_string(),
 			signature: "0x42bb4b16fb9d6f1a7c902edac7d511679827b262cb1d0e5e5fd5d3af6c3dc715ef4c5e1810056db80bfa866c207b786d79987242608ca6944e857772cb1b858b".to_string(),
+			version: REQUEST_VERSION_LEGACY,
+			signature_type: None,
 			signersig: "xxx".to_string(),
 		};
 
@@ -1612,8 +3508,8 @@ mod test {
 
 	#[tokio::test]
 	async fn verify_data_test() {
-		let current_block_number = get_current_block_number_new_api().await.unwrap();
-		let mut packet = generate_store_request(1300).await;
+		let current_block_number = TEST_BLOCK_NUMBER;
+		let mut packet = generate_store_request(1300, current_block_number).await;
 
 		// correct
 		assert!(packet.verify_data().unwrap());
@@ -1644,8 +3540,7 @@ mod test {
 
 	#[tokio::test]
 	async fn verify_polkadotjs_request_test() {
-		let current_block_number = get_current_block_number_new_api().await.unwrap();
-
+		let current_block_number = TEST_BLOCK_NUMBER;
 		let owner = sr25519::Pair::generate().0;
 		let signer = sr25519::Pair::generate().0;
 		let signer_address = format!(
@@ -1666,15 +3561,20 @@ mod test {
 			signersig: format!("{}{:?}", "0x", signersig),
 			data,
 			signature: format!("{}{:?}", "0x", signature),
+			version: REQUEST_VERSION_LEGACY,
+			signature_type: None,
 		};
 
 		let correct_data = StoreKeyshareData {
 			nft_id: 324,
-			keyshare: "thisIsMySecretDataWhichCannotContainAnyUnderScore(:-P)".as_bytes().to_vec(),
+			keyshare: Zeroizing::new(
+				"thisIsMySecretDataWhichCannotContainAnyUnderScore(:-P)".as_bytes().to_vec(),
+			),
 			auth_token: AuthenticationToken {
 				block_number: current_block_number,
 				block_validation: 10,
 			},
+			binary: false,
 		};
 
 		// correct
@@ -1683,7 +3583,7 @@ mod test {
 
 	#[tokio::test]
 	async fn verify_signer_request_test() {
-		let current_block_number = get_current_block_number_new_api().await.unwrap();
+		let current_block_number = TEST_BLOCK_NUMBER;
 		// Test
 		let owner = sr25519::Pair::generate().0;
 		let signer = sr25519::Pair::generate().0;
@@ -1703,15 +3603,20 @@ mod test {
 			signersig: format!("{}{:?}", "0x", signersig),
 			data,
 			signature: format!("{}{:?}", "0x", signature),
+			version: REQUEST_VERSION_LEGACY,
+			signature_type: None,
 		};
 
 		let correct_data = StoreKeyshareData {
 			nft_id: 494,
-			keyshare: "thisIsMySecretDataWhichCannotContainAnyUnderScore(:-P)".as_bytes().to_vec(),
+			keyshare: Zeroizing::new(
+				"thisIsMySecretDataWhichCannotContainAnyUnderScore(:-P)".as_bytes().to_vec(),
+			),
 			auth_token: AuthenticationToken {
 				block_number: current_block_number,
 				block_validation: 10,
 			},
+			binary: false,
 		};
 
 		// correct
@@ -1755,9 +3660,778 @@ mod test {
 		packet.signer_address = expired_signer_address;
 		packet.signersig = format!("{}{:?}", "0x", expired_signersig);
 
+		let err = packet.verify_free_store_request(current_block_number).unwrap_err();
 		assert_eq!(
-			packet.verify_free_store_request(current_block_number).unwrap_err(),
-			VerificationError::EXPIREDSIGNER(ValidationResult::ExpiredBlockNumber)
+			err,
+			VerificationError::EXPIREDSIGNER(ClockSkew {
+				result: ValidationResult::ExpiredBlockNumber,
+				current_block: current_block_number,
+				token_block: current_block_number - 13,
+				validity_window: 10,
+				max_validity_window: MAX_VALIDATION_PERIOD,
+				max_block_variation: MAX_BLOCK_VARIATION,
+				skew: -13,
+			})
+		);
+
+		// skew diagnostics are part of the error response
+		let (status, Json(body)) = err.express_verification_error(
+			APICALL::NFTSTORE,
+			owner.public().to_ss58check(),
+			1000,
+			String::new(),
+		);
+		assert_eq!(status, StatusCode::BAD_REQUEST);
+		assert_eq!(body["code"], 3101);
+		assert_eq!(body["clock_skew"]["current_block"], current_block_number);
+		assert_eq!(body["clock_skew"]["skew"], -13);
+	}
+
+	#[tokio::test]
+	async fn binary_store_data_test() {
+		let mut packet = generate_store_request(1300, TEST_BLOCK_NUMBER).await;
+
+		// Binary share with '_' and '-' in its base64url form, and non-utf8 bytes
+		let keyshare: Vec<u8> = (0u8..=255).rev().collect();
+		let encoded = URL_SAFE_NO_PAD.encode(&keyshare);
+		assert!(encoded.contains('_') && encoded.contains('-'));
+
+		packet.version = REQUEST_VERSION_BINARY;
+		packet.data = format!("1300_{encoded}_{TEST_BLOCK_NUMBER}_10");
+
+		let parsed = packet.parse_store_data().unwrap();
+		assert_eq!(parsed.nft_id, 1300);
+		assert_eq!(*parsed.keyshare, keyshare);
+		assert!(parsed.binary);
+		assert_eq!(parsed.auth_token.block_number, TEST_BLOCK_NUMBER);
+		assert_eq!(*parsed.serialize(), format!("1300_{encoded}_{TEST_BLOCK_NUMBER}_10"));
+
+		// The header flags the sealed keyshare as binary
+		let at_rest = parsed.keyshare_at_rest();
+		assert_eq!(at_rest.strip_prefix(&BINARY_MAGIC), Some(encoded.as_bytes()));
+
+		// Not base64url
+		packet.data = format!("1300_not base64!_{TEST_BLOCK_NUMBER}_10");
+		assert_eq!(
+			packet.parse_store_data().unwrap_err(),
+			VerificationError::INVALIDKEYSHAREENCODING
+		);
+
+		// Missing auth-token
+		packet.data = format!("1300_{encoded}");
+		assert!(packet.parse_store_data().is_err());
+	}
+
+	/* ----------------------
+		 ONCHAIN VERIFICATION (MOCK)
+	---------------------- */
+
+	fn account_of(public: sr25519::Public) -> AccountId32 {
+		AccountId32(public.0)
+	}
+
+	#[tokio::test]
+	async fn verify_store_request_mock_test() {
+		let packet = generate_store_request(1300, TEST_BLOCK_NUMBER).await;
+		let owner = account_of(packet.owner_address);
+		let stranger = account_of(sr25519::Pair::generate().0.public());
+
+		// correct
+		let chain = MockChain::new(TEST_BLOCK_NUMBER).with_secret_nft(1300, owner.clone(), true);
+		assert_eq!(
+			packet.verify_store_request(&chain, NftKind::SECRET).await.unwrap().nft_id,
+			1300
+		);
+
+		// verified owner out of quota
+		let mut chain =
+			MockChain::new(TEST_BLOCK_NUMBER).with_secret_nft(1300, owner.clone(), true);
+		chain.rate_limited = Some(5);
+		assert_eq!(
+			packet.verify_store_request(&chain, NftKind::SECRET).await.unwrap_err(),
+			VerificationError::RATELIMITED(5)
+		);
+
+		// not in syncing state
+		let chain = MockChain::new(TEST_BLOCK_NUMBER).with_secret_nft(1300, owner.clone(), false);
+		assert_eq!(
+			packet.verify_store_request(&chain, NftKind::SECRET).await.unwrap_err(),
+			VerificationError::NOTSYNCING
+		);
+
+		// wrong nft type
+		assert_eq!(
+			packet.verify_store_request(&chain, NftKind::CAPSULE).await.unwrap_err(),
+			VerificationError::IDISNOTCAPSULE
+		);
+
+		// not the owner
+		let chain = MockChain::new(TEST_BLOCK_NUMBER).with_secret_nft(1300, stranger, true);
+		assert_eq!(
+			packet.verify_store_request(&chain, NftKind::SECRET).await.unwrap_err(),
+			VerificationError::OWNERSHIPVERIFICATIONFAILED
+		);
+
+		// nft does not exist
+		let chain = MockChain::new(TEST_BLOCK_NUMBER);
+		assert_eq!(
+			packet.verify_store_request(&chain, NftKind::SECRET).await.unwrap_err(),
+			VerificationError::INVALIDNFTID
+		);
+
+		// expired request
+		let chain =
+			MockChain::new(TEST_BLOCK_NUMBER + 30).with_secret_nft(1300, owner.clone(), true);
+		assert!(matches!(
+			packet.verify_store_request(&chain, NftKind::SECRET).await.unwrap_err(),
+			VerificationError::EXPIREDSIGNER(_)
+		));
+
+		// admin read-only switch
+		let mut chain = MockChain::new(TEST_BLOCK_NUMBER).with_secret_nft(1300, owner, true);
+		chain.read_only_until = Some(TEST_BLOCK_NUMBER + 100);
+		assert_eq!(
+			packet.verify_store_request(&chain, NftKind::SECRET).await.unwrap_err(),
+			VerificationError::READONLY(TEST_BLOCK_NUMBER + 100)
+		);
+	}
+
+	#[tokio::test]
+	async fn verify_remove_request_mock_test() {
+		let packet = generate_remove_request(1500, TEST_BLOCK_NUMBER).await;
+		let owner = account_of(sr25519::Pair::generate().0.public());
+
+		// burnt
+		let chain = MockChain::new(TEST_BLOCK_NUMBER);
+		assert_eq!(
+			packet.verify_remove_request(&chain, NftKind::SECRET).await.unwrap().nft_id,
+			1500
+		);
+
+		// still a secret-nft
+		let chain = MockChain::new(TEST_BLOCK_NUMBER).with_secret_nft(1500, owner.clone(), false);
+		assert_eq!(
+			packet.verify_remove_request(&chain, NftKind::SECRET).await.unwrap_err(),
+			VerificationError::NOTBURNT
+		);
+
+		// converted from secret-nft to capsule
+		assert_eq!(
+			packet.verify_remove_request(&chain, NftKind::CAPSULE).await.unwrap().nft_id,
+			1500
+		);
+
+		// forged signature
+		let mut forged = packet.clone();
+		forged.data = format!("1501_{}_10", TEST_BLOCK_NUMBER);
+		let chain = MockChain::new(TEST_BLOCK_NUMBER);
+		assert_eq!(
+			forged.verify_remove_request(&chain, NftKind::SECRET).await.unwrap_err(),
+			VerificationError::DATAVERIFICATIONFAILED
+		);
+
+		// expired request
+		let chain = MockChain::new(TEST_BLOCK_NUMBER + 30);
+		assert!(matches!(
+			packet.verify_remove_request(&chain, NftKind::SECRET).await.unwrap_err(),
+			VerificationError::EXPIREDDATA(_)
+		));
+
+		// admin read-only switch
+		let mut chain = MockChain::new(TEST_BLOCK_NUMBER);
+		chain.read_only_until = Some(TEST_BLOCK_NUMBER);
+		assert_eq!(
+			packet.verify_remove_request(&chain, NftKind::SECRET).await.unwrap_err(),
+			VerificationError::READONLY(TEST_BLOCK_NUMBER)
+		);
+	}
+
+	fn generate_batch_store_request(
+		nft_ids: &[u32],
+		atomicity: BatchAtomicity,
+	) -> (BatchStoreKeysharePacket, AccountId32) {
+		let owner = sr25519::Pair::from_seed(&[11u8; 32]);
+		let signer = sr25519::Pair::from_seed(&[12u8; 32]);
+
+		let signer_address = format!("{}_{}_10", signer.public().to_ss58check(), TEST_BLOCK_NUMBER);
+		let data = serde_json::to_string(&BatchStoreData {
+			entries: nft_ids
+				.iter()
+				.map(|nft_id| BatchStoreEntry {
+					nft_id: *nft_id,
+					keyshare: format!("thisIsTheSecretOfNft{nft_id}"),
+				})
+				.collect(),
+			block_number: TEST_BLOCK_NUMBER,
+			block_validation: 10,
+		})
+		.unwrap();
+
+		let packet = BatchStoreKeysharePacket {
+			owner_address: owner.public(),
+			signersig: format!("0x{}", hex::encode(owner.sign(signer_address.as_bytes()).0)),
+			signer_address,
+			signature: format!("0x{}", hex::encode(signer.sign(data.as_bytes()).0)),
+			data,
+			atomicity,
+			signature_type: None,
+		};
+
+		(packet, account_of(owner.public()))
+	}
+
+	#[tokio::test]
+	async fn verify_batch_store_request_mock_test() {
+		let (packet, owner) =
+			generate_batch_store_request(&[1600, 1601, 1602], BatchAtomicity::BESTEFFORT);
+		let stranger = account_of(sr25519::Pair::generate().0.public());
+
+		let chain = MockChain::new(TEST_BLOCK_NUMBER)
+			.with_secret_nft(1600, owner.clone(), true)
+			.with_secret_nft(1601, stranger, true);
+
+		let items = packet.verify_batch_store_request(&chain, NftKind::SECRET).await.unwrap();
+		assert_eq!(items.len(), 3);
+		assert_eq!(items[0].0, 1600);
+		assert_eq!(*items[0].1.as_ref().unwrap().keyshare, b"thisIsTheSecretOfNft1600".to_vec());
+		assert_eq!(items[1].1, Err(VerificationError::OWNERSHIPVERIFICATIONFAILED));
+		assert_eq!(items[2].1, Err(VerificationError::INVALIDNFTID));
+
+		// one signature covers all items
+		let mut forged = packet.clone();
+		forged.data = forged.data.replace("1602", "1603");
+		assert_eq!(
+			forged.verify_batch_store_request(&chain, NftKind::SECRET).await.unwrap_err(),
+			VerificationError::DATAVERIFICATIONFAILED
+		);
+
+		// duplicated ids
+		let (duplicated, _) =
+			generate_batch_store_request(&[1600, 1600], BatchAtomicity::BESTEFFORT);
+		assert_eq!(
+			duplicated
+				.verify_batch_store_request(&chain, NftKind::SECRET)
+				.await
+				.unwrap_err(),
+			VerificationError::MALFORMATEDDATA
+		);
+
+		// too large
+		let nft_ids: Vec<u32> = (0..=MAX_BATCH_SIZE as u32).collect();
+		let (large, _) = generate_batch_store_request(&nft_ids, BatchAtomicity::ALLORNOTHING);
+		assert_eq!(large.parse_batch_data().unwrap_err(), VerificationError::MALFORMATEDDATA);
+	}
+
+	#[tokio::test]
+	async fn verify_update_request_mock_test() {
+		let owner = sr25519::Pair::from_seed(&[14u8; 32]);
+		let signer = sr25519::Pair::from_seed(&[15u8; 32]);
+		let owner_account = account_of(owner.public());
+
+		let signer_address = format!("{}_{}_10", signer.public().to_ss58check(), TEST_BLOCK_NUMBER);
+		let data = serde_json::to_string(&UpdateKeyshareData {
+			nft_id: 1900,
+			keyshare: "thisIsTheRotated_SecretOfCapsule1900".to_string(),
+			block_number: TEST_BLOCK_NUMBER,
+			block_validation: 10,
+		})
+		.unwrap();
+
+		let packet = UpdateKeysharePacket {
+			owner_address: owner.public(),
+			signersig: format!("0x{}", hex::encode(owner.sign(signer_address.as_bytes()).0)),
+			signer_address,
+			signature: format!("0x{}", hex::encode(signer.sign(data.as_bytes()).0)),
+			data,
+			signature_type: None,
+		};
+
+		// synced capsule of the owner
+		let chain =
+			MockChain::new(TEST_BLOCK_NUMBER).with_capsule(1900, owner_account.clone(), false);
+		let verified = packet.verify_update_access(&chain).await.unwrap();
+		assert_eq!(verified.nft_id, 1900);
+		assert_eq!(*verified.keyshare, b"thisIsTheRotated_SecretOfCapsule1900".to_vec());
+
+		// capsule is still syncing, a set request is expected
+		let chain =
+			MockChain::new(TEST_BLOCK_NUMBER).with_capsule(1900, owner_account.clone(), true);
+		assert_eq!(
+			packet.verify_update_access(&chain).await.unwrap_err(),
+			VerificationError::NOTSYNCED
+		);
+
+		// not a capsule
+		let chain =
+			MockChain::new(TEST_BLOCK_NUMBER).with_secret_nft(1900, owner_account.clone(), false);
+		assert_eq!(
+			packet.verify_update_access(&chain).await.unwrap_err(),
+			VerificationError::IDISNOTCAPSULE
+		);
+
+		// transferred capsule
+		let stranger = account_of(sr25519::Pair::generate().0.public());
+		let chain = MockChain::new(TEST_BLOCK_NUMBER).with_capsule(1900, stranger, false);
+		assert_eq!(
+			packet.verify_update_access(&chain).await.unwrap_err(),
+			VerificationError::OWNERSHIPVERIFICATIONFAILED
+		);
+
+		// data is covered by the signer signature
+		let chain =
+			MockChain::new(TEST_BLOCK_NUMBER).with_capsule(1900, owner_account.clone(), false);
+		let mut forged = packet.clone();
+		forged.data = forged.data.replace("1900", "1901");
+		assert_eq!(
+			forged.verify_update_access(&chain).await.unwrap_err(),
+			VerificationError::DATAVERIFICATIONFAILED
+		);
+
+		// read-only enclave
+		let mut chain = MockChain::new(TEST_BLOCK_NUMBER).with_capsule(1900, owner_account, false);
+		chain.read_only_until = Some(TEST_BLOCK_NUMBER);
+		assert_eq!(
+			packet.verify_update_access(&chain).await.unwrap_err(),
+			VerificationError::READONLY(TEST_BLOCK_NUMBER)
 		);
 	}
+
+	#[tokio::test]
+	async fn verify_batch_retrieve_request_mock_test() {
+		let requester = sr25519::Pair::from_seed(&[13u8; 32]);
+		let requester_account = account_of(requester.public());
+		let data = serde_json::to_string(&BatchRetrieveData {
+			nft_ids: vec![1700, 1701],
+			block_number: TEST_BLOCK_NUMBER,
+			block_validation: 10,
+		})
+		.unwrap();
+
+		let packet = BatchRetrieveKeysharePacket {
+			requester_address: requester.public(),
+			requester_type: RequesterType::OWNER,
+			signature: format!("0x{}", hex::encode(requester.sign(data.as_bytes()).0)),
+			data,
+			atomicity: BatchAtomicity::ALLORNOTHING,
+			signature_type: None,
+			session: None,
+			cosignature: None,
+		};
+
+		let chain = MockChain::new(TEST_BLOCK_NUMBER)
+			.with_secret_nft(1700, requester_account.clone(), false)
+			.with_secret_nft(1701, requester_account, true);
+
+		let items = packet.verify_batch_retrieve_access(&chain, NftKind::SECRET).await.unwrap();
+		assert_eq!(
+			items[0],
+			(
+				1700,
+				Ok(RetrieveKeyshareData {
+					nft_id: 1700,
+					auth_token: AuthenticationToken {
+						block_number: TEST_BLOCK_NUMBER,
+						block_validation: 10
+					},
+					rental_grace: None,
+				})
+			)
+		);
+		assert_eq!(items[1], (1701, Err(VerificationError::NOTSYNCED)));
+
+		// expired batch
+		let chain = MockChain::new(TEST_BLOCK_NUMBER + 30);
+		assert!(matches!(
+			packet.verify_batch_retrieve_access(&chain, NftKind::SECRET).await.unwrap_err(),
+			VerificationError::EXPIREDDATA(_)
+		));
+	}
+
+	#[tokio::test]
+	async fn verify_retrieve_request_mock_test() {
+		let mut packet = generate_retrieve_request(1400, TEST_BLOCK_NUMBER).await;
+		let requester = account_of(packet.requester_address);
+		let stranger = account_of(sr25519::Pair::generate().0.public());
+
+		// owner
+		let chain = MockChain::new(TEST_BLOCK_NUMBER).with_capsule(1400, requester.clone(), false);
+		assert_eq!(
+			packet.verify_retrieve_access(&chain, NftKind::CAPSULE).await.unwrap().nft_id,
+			1400
+		);
+
+		// still syncing
+		let chain = MockChain::new(TEST_BLOCK_NUMBER).with_capsule(1400, requester.clone(), true);
+		assert_eq!(
+			packet.verify_retrieve_access(&chain, NftKind::CAPSULE).await.unwrap_err(),
+			VerificationError::NOTSYNCED
+		);
+
+		// delegatee
+		packet.requester_type = RequesterType::DELEGATEE;
+		let mut chain = MockChain::new(TEST_BLOCK_NUMBER).with_capsule(1400, stranger, false);
+		assert_eq!(
+			packet.verify_retrieve_access(&chain, NftKind::CAPSULE).await.unwrap_err(),
+			VerificationError::REQUESTERVERIFICATIONFAILED
+		);
+
+		chain.delegatees.insert(1400, requester);
+		assert_eq!(
+			packet.verify_retrieve_access(&chain, NftKind::CAPSULE).await.unwrap().nft_id,
+			1400
+		);
+
+		// rentee is not set
+		packet.requester_type = RequesterType::RENTEE;
+		assert_eq!(
+			packet.verify_retrieve_access(&chain, NftKind::CAPSULE).await.unwrap_err(),
+			VerificationError::REQUESTERVERIFICATIONFAILED
+		);
+	}
+
+	#[tokio::test]
+	async fn verify_cosigned_retrieve_request_test() {
+		let owner = sr25519::Pair::generate().0;
+		let cosigner = sr25519::Pair::generate().0;
+
+		let data = format!("2000_{}_10", TEST_BLOCK_NUMBER);
+		let mut packet = RetrieveKeysharePacket {
+			requester_address: owner.public(),
+			requester_type: RequesterType::OWNER,
+			signature: format!("{}{:?}", "0x", owner.sign(data.as_bytes())),
+			data: data.clone(),
+			version: REQUEST_VERSION_LEGACY,
+			signature_type: None,
+			session: None,
+			cosignature: None,
+		};
+
+		// not flagged : owner signature is enough
+		let chain =
+			MockChain::new(TEST_BLOCK_NUMBER).with_capsule(2000, account_of(owner.public()), false);
+		assert!(packet.verify_retrieve_access(&chain, NftKind::CAPSULE).await.is_ok());
+
+		// flagged : co-signature is missing
+		let chain = MockChain::new(TEST_BLOCK_NUMBER)
+			.with_capsule(2000, account_of(owner.public()), false)
+			.with_cosigner(2000, cosigner.public());
+		assert_eq!(
+			packet.verify_retrieve_access(&chain, NftKind::CAPSULE).await.unwrap_err(),
+			VerificationError::COSIGNATUREREQUIRED
+		);
+
+		// flagged : signed twice by the owner
+		packet.cosignature = Some(packet.signature.clone());
+		assert_eq!(
+			packet.verify_retrieve_access(&chain, NftKind::CAPSULE).await.unwrap_err(),
+			VerificationError::COSIGNATUREREQUIRED
+		);
+
+		// flagged : co-signed
+		packet.cosignature = Some(format!("{}{:?}", "0x", cosigner.sign(data.as_bytes())));
+		assert_eq!(
+			packet.verify_retrieve_access(&chain, NftKind::CAPSULE).await.unwrap().nft_id,
+			2000
+		);
+	}
+
+	#[tokio::test]
+	async fn verify_jws_request_test() {
+		use crate::chain::jws::encode_jws;
+
+		let owner = sr25519::Pair::generate().0;
+		let signer = sr25519::Pair::generate().0;
+
+		// retrieve
+		let payload = RetrieveJwsPayload {
+			nft_id: 1500,
+			block_number: TEST_BLOCK_NUMBER,
+			block_validation: 10,
+		};
+		let mut packet: RetrieveKeysharePacket = serde_json::from_value(serde_json::json!({
+			"requester_address": owner.public().to_ss58check(),
+			"requester_type": "OWNER",
+			"data": encode_jws(&payload, &owner),
+			"version": REQUEST_VERSION_JWS,
+		}))
+		.unwrap();
+
+		assert_eq!(packet.parse_retrieve_data().unwrap().nft_id, 1500);
+		assert_eq!(packet.verify_data(TEST_BLOCK_NUMBER), Ok(true));
+
+		let chain =
+			MockChain::new(TEST_BLOCK_NUMBER).with_capsule(1500, account_of(owner.public()), false);
+		assert_eq!(
+			packet.verify_retrieve_access(&chain, NftKind::CAPSULE).await.unwrap().nft_id,
+			1500
+		);
+
+		// signed by someone else
+		packet.data = encode_jws(&payload, &signer);
+		assert_eq!(packet.verify_data(TEST_BLOCK_NUMBER), Ok(false));
+
+		// legacy data with jws version
+		packet.data = format!("1500_{}_10", TEST_BLOCK_NUMBER);
+		assert_eq!(packet.parse_retrieve_data(), Err(VerificationError::MALFORMATEDDATA));
+
+		// store
+		let signer_address = format!("{}_{}_10", signer.public().to_ss58check(), TEST_BLOCK_NUMBER);
+		let payload = StoreJwsPayload {
+			nft_id: 1500,
+			keyshare: "thisIsMySecretData_WithUnderScore".to_string(),
+			block_number: TEST_BLOCK_NUMBER,
+			block_validation: 10,
+		};
+		let packet = StoreKeysharePacket {
+			owner_address: owner.public(),
+			signersig: format!("{}{:?}", "0x", owner.sign(signer_address.as_bytes())),
+			signer_address,
+			data: encode_jws(&payload, &signer),
+			signature: String::new(),
+			version: REQUEST_VERSION_JWS,
+			signature_type: None,
+		};
+
+		let parsed = packet.verify_free_store_request(TEST_BLOCK_NUMBER).unwrap();
+		assert_eq!(*parsed.keyshare, payload.keyshare.as_bytes());
+	}
+
+	#[tokio::test]
+	async fn verify_session_request_test() {
+		let owner = sr25519::Pair::generate().0;
+		let session_key = sr25519::Pair::generate().0;
+
+		let grant = |expiry_block: u32| {
+			let grant = serde_json::to_string(&SessionGrant {
+				session_key: session_key.public().to_ss58check(),
+				nft_ids: vec![1800],
+				calls: vec![APICALL::CAPSULERETRIEVE],
+				expiry_block,
+			})
+			.unwrap();
+			SessionDelegation {
+				signature: format!("{}{:?}", "0x", owner.sign(grant.as_bytes())),
+				grant,
+			}
+		};
+
+		let data = format!("1800_{}_10", TEST_BLOCK_NUMBER);
+		let mut packet = RetrieveKeysharePacket {
+			requester_address: owner.public(),
+			requester_type: RequesterType::OWNER,
+			signature: format!("{}{:?}", "0x", session_key.sign(data.as_bytes())),
+			data,
+			version: REQUEST_VERSION_LEGACY,
+			signature_type: None,
+			session: Some(grant(TEST_BLOCK_NUMBER + 100)),
+			cosignature: None,
+		};
+
+		let chain =
+			MockChain::new(TEST_BLOCK_NUMBER).with_capsule(1800, account_of(owner.public()), false);
+		assert_eq!(
+			packet.verify_retrieve_access(&chain, NftKind::CAPSULE).await.unwrap().nft_id,
+			1800
+		);
+
+		// out of scope call
+		assert_eq!(
+			packet.verify_retrieve_access(&chain, NftKind::SECRET).await.unwrap_err(),
+			VerificationError::SESSIONOUTOFSCOPE
+		);
+
+		// expired and too long sessions
+		packet.session = Some(grant(TEST_BLOCK_NUMBER - 1));
+		assert_eq!(
+			packet.verify_data(TEST_BLOCK_NUMBER),
+			Err(VerificationError::EXPIREDSIGNER(ClockSkew::session(
+				ValidationResult::ExpiredBlockNumber,
+				TEST_BLOCK_NUMBER,
+				TEST_BLOCK_NUMBER - 1
+			)))
+		);
+		packet.session = Some(grant(TEST_BLOCK_NUMBER + MAX_SESSION_PERIOD + 1));
+		assert_eq!(
+			packet.verify_data(TEST_BLOCK_NUMBER),
+			Err(VerificationError::EXPIREDSIGNER(ClockSkew::session(
+				ValidationResult::InvalidPeriod,
+				TEST_BLOCK_NUMBER,
+				TEST_BLOCK_NUMBER + MAX_SESSION_PERIOD + 1
+			)))
+		);
+
+		// grant is not signed by the requester
+		let mut forged = grant(TEST_BLOCK_NUMBER + 100);
+		forged.signature = format!("{}{:?}", "0x", session_key.sign(forged.grant.as_bytes()));
+		packet.session = Some(forged);
+		assert_eq!(
+			packet.verify_data(TEST_BLOCK_NUMBER),
+			Err(VerificationError::SIGNERVERIFICATIONFAILED)
+		);
+
+		// without session, the data must be signed by the requester
+		packet.session = None;
+		assert_eq!(packet.verify_data(TEST_BLOCK_NUMBER), Ok(false));
+	}
+
+	#[tokio::test]
+	async fn verify_access_simulation_test() {
+		let owner = sr25519::Pair::generate().0.public();
+		let stranger = sr25519::Pair::generate().0.public();
+		let owner_address = owner.to_ss58check();
+
+		let mut chain = MockChain::new(TEST_BLOCK_NUMBER)
+			.with_secret_nft(1900, account_of(owner), true)
+			.with_capsule(1901, account_of(owner), false);
+
+		async fn simulate(
+			chain: &MockChain,
+			call: APICALL,
+			address: String,
+			nft_id: u32,
+			block_number: Option<u32>,
+		) -> Result<(), VerificationError> {
+			verify_access_simulation(
+				chain,
+				call,
+				&address,
+				RequesterType::OWNER,
+				nft_id,
+				block_number,
+			)
+			.await
+		}
+
+		assert_eq!(
+			simulate(&chain, APICALL::NFTSTORE, owner_address.clone(), 1900, None).await,
+			Ok(())
+		);
+		assert_eq!(
+			simulate(&chain, APICALL::NFTSTORE, stranger.to_ss58check(), 1900, None).await,
+			Err(VerificationError::OWNERSHIPVERIFICATIONFAILED)
+		);
+		assert_eq!(
+			simulate(&chain, APICALL::NFTRETRIEVE, owner_address.clone(), 1900, None).await,
+			Err(VerificationError::NOTSYNCED)
+		);
+		assert_eq!(
+			simulate(&chain, APICALL::CAPSULERETRIEVE, owner_address.clone(), 1901, None).await,
+			Ok(())
+		);
+		assert_eq!(
+			simulate(&chain, APICALL::CAPSULEREMOVE, owner_address.clone(), 1901, None).await,
+			Err(VerificationError::NOTBURNT)
+		);
+		assert_eq!(
+			simulate(&chain, APICALL::NFTRETRIEVE, "not-an-address".to_string(), 1900, None).await,
+			Err(VerificationError::MALFORMATEDDATA)
+		);
+
+		// Expired auth-token
+		assert!(matches!(
+			simulate(&chain, APICALL::CAPSULERETRIEVE, owner_address.clone(), 1901, Some(1)).await,
+			Err(VerificationError::EXPIREDDATA(_))
+		));
+
+		// Read-only switch
+		chain.read_only_until = Some(TEST_BLOCK_NUMBER + 10);
+		assert_eq!(
+			simulate(&chain, APICALL::NFTSTORE, owner_address, 1900, None).await,
+			Err(VerificationError::READONLY(TEST_BLOCK_NUMBER + 10))
+		);
+	}
+
+	#[test]
+	fn verify_ed25519_request_test() {
+		use subxt::ext::sp_core::ed25519;
+
+		let requester = ed25519::Pair::from_seed(&[5u8; 32]);
+		let data = format!("1600_{}_10", TEST_BLOCK_NUMBER);
+
+		let mut packet = RetrieveKeysharePacket {
+			requester_address: sr25519::Public::from_raw(requester.public().0),
+			requester_type: RequesterType::OWNER,
+			signature: format!("0x{}", hex::encode(requester.sign(data.as_bytes()).0)),
+			data,
+			version: REQUEST_VERSION_LEGACY,
+			signature_type: Some(SignatureScheme::ED25519),
+			session: None,
+			cosignature: None,
+		};
+		assert_eq!(packet.verify_data(TEST_BLOCK_NUMBER), Ok(true));
+
+		packet.signature_type = None;
+		assert_eq!(packet.verify_data(TEST_BLOCK_NUMBER), Ok(true));
+
+		packet.signature_type = Some(SignatureScheme::SR25519);
+		assert_eq!(packet.verify_data(TEST_BLOCK_NUMBER), Ok(false));
+	}
+
+	#[test]
+	fn address_normalization_test() {
+		let public = sr25519::Pair::generate().0.public();
+		let generic = public.to_ss58check_with_version(Ss58AddressFormat::custom(42));
+		let polkadot = public.to_ss58check_with_version(Ss58AddressFormat::custom(0));
+		let hex_key = format!("0x{}", hex::encode(public.0));
+
+		assert_eq!(parse_ss58_public(&polkadot), Some(public));
+		assert_eq!(normalize_address(&generic), normalize_address(&polkadot));
+		assert_eq!(normalize_address(&hex_key), Some(AccountId32(public.0)));
+		assert_eq!(normalize_address("5xxxx"), None);
+
+		assert_eq!(canonical_address(&public), generic);
+
+		// packets accept any prefix
+		let packet: RetrieveKeysharePacket = serde_json::from_value(serde_json::json!({
+			"requester_address": polkadot,
+			"requester_type": "OWNER",
+			"data": "xxx",
+			"signature": "xxx",
+		}))
+		.unwrap();
+		assert_eq!(packet.requester_address, public);
+	}
+
+	#[test]
+	fn error_payload_test() {
+		let (status, Json(response)) = VerificationError::REPLAYEDREQUEST
+			.express_verification_error(
+				APICALL::NFTRETRIEVE,
+				"caller".to_string(),
+				7,
+				"enclave".to_string(),
+			);
+
+		assert_eq!(status, StatusCode::CONFLICT);
+		assert_eq!(response["status"], "REPLAYEDREQUEST");
+		assert_eq!(response["code"], 3103);
+		assert_eq!(response["nft_id"], 7);
+		assert!(response["description"].is_string());
+
+		let item = VerificationError::NOTSYNCED.express_batch_item(8, None);
+		assert_eq!(item.code, ReturnStatus::NOTSYNCED.code());
+
+		let results = vec![BatchItemResult::success(9, ReturnStatus::STORESUCCESS), item];
+		let errors = batch_errors(&results, ReturnStatus::STORESUCCESS);
+		assert_eq!(errors.len(), 1);
+		assert_eq!(errors[0].nft_id, 8);
+
+		// Codes are part of the public api, they must stay unique
+		let statuses = [
+			ReturnStatus::STORESUCCESS,
+			ReturnStatus::INVALIDDATAFORMAT,
+			ReturnStatus::INVALIDKEYSHARE,
+			ReturnStatus::INVALIDSIGNERSIGNATURE,
+			ReturnStatus::INVALIDAUTHTOKEN,
+			ReturnStatus::OWNERSHIPVERIFICATIONFAILED,
+			ReturnStatus::IDISNOTASECRETNFT,
+			ReturnStatus::NFTIDEXISTS,
+			ReturnStatus::ORACLEFAILURE,
+			ReturnStatus::RATELIMITED,
+			ReturnStatus::BACKUPLIMITEXCEEDED,
+		];
+		let codes: std::collections::BTreeSet<u16> =
+			statuses.iter().map(|status| status.code()).collect();
+		assert_eq!(codes.len(), statuses.len());
+	}
 }
@@ -0,0 +1,486 @@
+use std::{collections::BTreeMap, io, sync::Arc};
+
+use axum::{
+	async_trait,
+	body::{Body, Bytes, HttpBody},
+	extract::{FromRequest, FromRequestParts, Multipart, State},
+	http::{header, request::Parts, HeaderMap, Method, Request, StatusCode},
+	middleware::Next,
+	response::{IntoResponse, Response},
+	Json,
+};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::Mutex;
+use tracing::{debug, error, warn};
+
+use crate::{
+	backup::{
+		admins::{admin_role, authorize_admin, is_admin, AdminOperation, AdminRole},
+		quorum::{parse_authentication_token, verify_quorum_signatures, QuorumAuthenticationToken},
+		sync::verify_signature,
+	},
+	chain::{constants::MAX_FORM_ENVELOPE_SIZE, quota::record_signature_failure},
+	servers::{
+		limits::{body_class, body_limit},
+		state::SharedState,
+		versioning::endpoint_path,
+	},
+};
+
+/* ---------------------------------------
+	REQUEST AUTHENTICATION
+--------------------------------------- */
+
+// Admin requests are authenticated once, by a middleware, before their handler runs :
+// - the policy of the route gives the signature scheme and the admin operation of the request
+// - the authentication token, the signatures, the whitelist and the roles are verified
+// - the VerifiedCaller is attached to the request, handlers extract it and only check that the data
+//   hash of the token matches their request
+// Json packets are buffered up to the limit of their body class. A form is only read up to its
+// archive part, the signature fields come first and the archive is streamed to the handler.
+// Mutations are denied by default, a route without policy is only served if its handler verifies
+// the request itself : nft owners, peer enclaves, upload sessions and attestation requests.
+// A handler which extracts a VerifiedCaller on a route without policy fails closed.
+
+/// Signature scheme of an admin request
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum AuthScheme {
+	// One whitelisted admin signs the token : "admin_account" and "signature"
+	ADMIN,
+	// The threshold of the admin quorum signs the token : "signatures"
+	QUORUM,
+}
+
+/// Authentication of a route
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct AuthPolicy {
+	pub scheme: AuthScheme,
+	pub operation: AdminOperation,
+}
+
+/// Authentication of a route
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum RouteAuth {
+	// Admin request, its signatures are verified before the handler
+	SIGNED(AuthPolicy),
+	// Read, or request verified by its handler
+	HANDLER,
+	// Mutation without policy, rejected
+	DENIED,
+}
+
+/// Authentication of an endpoint, mutations which are neither admin requests nor verified by their
+/// handler are denied
+/// # Arguments
+/// * `method` - method of the request
+/// * `path` - unversioned route pattern of the request
+pub fn auth_policy(method: &Method, path: &str) -> RouteAuth {
+	if matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS) {
+		return RouteAuth::HANDLER
+	}
+
+	let (scheme, operation) = match path {
+		"/api/backup/download-token" | "/api/backup/fetch-id" =>
+			(AuthScheme::ADMIN, AdminOperation::FETCH),
+
+		"/api/backup/push-id" | "/api/backup/push-bulk" => (AuthScheme::ADMIN, AdminOperation::PUSH),
+
+		"/api/backup/fetch-bulk" |
+		"/api/backup/audit-log" |
+		"/api/backup/compare-peer" |
+		"/api/backup/consistency-check" => (AuthScheme::QUORUM, AdminOperation::FETCH),
+
+		"/api/backup/upload" | "/api/backup/push-keyshares" =>
+			(AuthScheme::QUORUM, AdminOperation::PUSH),
+
+		"/api/backup/escrow" |
+		"/api/backup/rotate-quorum" |
+		"/api/backup/rotate-quorum/cancel" |
+		"/api/backup/allowlist" |
+		"/api/backup/provision" |
+		"/api/backup/provision-report" |
+		"/api/backup/upgrade-arm" |
+		"/api/backup/quote-reregister" |
+		"/api/backup/rotate-account" |
+		"/api/backup/rotate-account/complete" |
+		"/api/backup/read-only" |
+		"/api/backup/maintenance" |
+		"/api/backup/log-level" |
+		"/api/admin/config" => (AuthScheme::QUORUM, AdminOperation::MANAGE),
+
+		// Signed by the nft owners or delegatees
+		"/api/secret-nft/store-keyshare" |
+		"/api/secret-nft/retrieve-keyshare" |
+		"/api/secret-nft/remove-keyshare" |
+		"/api/secret-nft/batch-store-keyshare" |
+		"/api/secret-nft/batch-retrieve-keyshare" |
+		"/api/capsule-nft/set-keyshare" |
+		"/api/capsule-nft/update-keyshare" |
+		"/api/capsule-nft/retrieve-keyshare" |
+		"/api/capsule-nft/remove-keyshare" |
+		"/api/access-check" |
+		// Signed by the peer enclaves or the metric server
+		"/api/backup/sync-keyshare" |
+		"/api/backup/sync-inventory" |
+		"/api/backup/sync-root" |
+		"/api/backup/recovery-key" |
+		"/api/metric/interval-nft-list" |
+		"/api/metric/set-crawl-block" |
+		// Bound to an upload opened by the admin quorum
+		"/api/backup/upload/:upload_id/part/:index" |
+		"/api/backup/upload/:upload_id/finalize" |
+		// Public attestation
+		"/api/attest" |
+		"/api/attest/inspect" => return RouteAuth::HANDLER,
+
+		_ => return RouteAuth::DENIED,
+	};
+
+	RouteAuth::SIGNED(AuthPolicy { scheme, operation })
+}
+
+/// Signature fields shared by the admin packets, the other fields are left to the handler
+#[derive(Deserialize, Debug)]
+struct SignedEnvelope {
+	auth_token: String,
+	#[serde(default, alias = "admin_address")]
+	admin_account: Option<String>,
+	#[serde(default)]
+	signature: Option<String>,
+	#[serde(default)]
+	signatures: BTreeMap<String, String>,
+}
+
+/// Verified signers of an admin request, attached to the request by the auth middleware
+#[derive(Clone, Debug)]
+pub struct VerifiedCaller {
+	// admin_account -> role, the accounts with a valid signature allowed to run the operation
+	pub signers: BTreeMap<String, AdminRole>,
+	pub operation: AdminOperation,
+	pub token: QuorumAuthenticationToken,
+}
+
+impl VerifiedCaller {
+	/// Number of counted signatures
+	pub fn approvals(&self) -> usize {
+		self.signers.len()
+	}
+
+	/// Check that the signed token covers the request data
+	/// # Arguments
+	/// * `data_hash` - hash of the request data, computed by the handler
+	pub fn verify_data_hash(&self, data_hash: &str) -> Result<(), (StatusCode, String)> {
+		if self.token.data_hash != data_hash {
+			return Err((StatusCode::BAD_REQUEST, "Mismatch Data Hash".to_string()))
+		}
+
+		Ok(())
+	}
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for VerifiedCaller {
+	type Rejection = (StatusCode, Json<Value>);
+
+	async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+		parts.extensions.get::<VerifiedCaller>().cloned().ok_or_else(|| {
+			error!("ADMIN AUTH : {} has no authentication policy", parts.uri.path());
+			(
+				StatusCode::INTERNAL_SERVER_ERROR,
+				Json(json!({ "error": "ADMIN AUTH : endpoint is not authenticated" })),
+			)
+		})
+	}
+}
+
+/// Beginning of a form read by the auth middleware, replayed to the handler
+struct RecordedBody {
+	body: Body,
+	chunks: Vec<Bytes>,
+	size: usize,
+}
+
+/// Signature fields of a json or multipart admin packet, and the body for the handler
+/// # Arguments
+/// * `headers` - headers of the request, with its content type
+/// * `body` - body of the request
+/// * `limit` - limit of a json body
+async fn signed_envelope(
+	headers: &HeaderMap,
+	body: Body,
+	limit: usize,
+) -> Result<(SignedEnvelope, Body), String> {
+	let is_multipart = headers
+		.get(header::CONTENT_TYPE)
+		.and_then(|value| value.to_str().ok())
+		.map_or(false, |value| value.starts_with("multipart/form-data"));
+
+	if !is_multipart {
+		let bytes = hyper::body::to_bytes(http_body::Limited::new(body, limit))
+			.await
+			.map_err(|err| format!("Error reading request body : {err}"))?;
+		let envelope =
+			serde_json::from_slice::<SignedEnvelope>(&bytes).map_err(|err| err.to_string())?;
+		return Ok((envelope, Body::from(bytes)))
+	}
+
+	// The form is read until its archive part, the chunks read so far are recorded
+	let recorded = Arc::new(Mutex::new(RecordedBody { body, chunks: Vec::new(), size: 0 }));
+	let stream = futures::stream::unfold(recorded.clone(), |recorded| async move {
+		let chunk = {
+			let mut recorded_body = recorded.lock().await;
+			if recorded_body.size > MAX_FORM_ENVELOPE_SIZE {
+				Err(io::Error::new(
+					io::ErrorKind::InvalidData,
+					format!("signature fields exceed {MAX_FORM_ENVELOPE_SIZE} bytes"),
+				))
+			} else {
+				match recorded_body.body.data().await? {
+					Ok(chunk) => {
+						recorded_body.size += chunk.len();
+						recorded_body.chunks.push(chunk.clone());
+						Ok(chunk)
+					},
+					Err(err) => Err(io::Error::new(io::ErrorKind::Other, err)),
+				}
+			}
+		};
+		Some((chunk, recorded))
+	});
+
+	let mut request = Request::new(Body::wrap_stream(stream));
+	*request.headers_mut() = headers.clone();
+	let mut multipart =
+		Multipart::from_request(request, &()).await.map_err(|err| err.body_text())?;
+
+	let mut fields = serde_json::Map::new();
+	while let Some(field) = multipart.next_field().await.map_err(|err| err.body_text())? {
+		let name = field.name().unwrap_or_default().to_string();
+		match name.as_str() {
+			"auth_token" | "admin_account" | "admin_address" | "signature" => {
+				let text = field.text().await.map_err(|err| err.body_text())?;
+				fields.insert(name, Value::String(text));
+			},
+			// The archive is not read, the signature fields come before it
+			"restore_file" => break,
+			_ => continue,
+		}
+	}
+	drop(multipart);
+
+	let envelope = serde_json::from_value(Value::Object(fields)).map_err(|err| err.to_string())?;
+
+	let RecordedBody { body, chunks, .. } = Arc::try_unwrap(recorded)
+		.map_err(|_| "form body is still borrowed".to_string())?
+		.into_inner();
+	let replayed = futures::stream::iter(chunks.into_iter().map(Ok::<_, hyper::Error>));
+
+	Ok((envelope, Body::wrap_stream(replayed.chain(body))))
+}
+
+/// Verify the signatures of an admin request
+/// # Arguments
+/// * `state` - SharedState
+/// * `policy` - authentication of the route
+/// * `envelope` - signature fields of the request
+async fn verify_caller(
+	state: &SharedState,
+	policy: AuthPolicy,
+	envelope: &SignedEnvelope,
+) -> Result<VerifiedCaller, (StatusCode, String)> {
+	let (token, signers) = match policy.scheme {
+		AuthScheme::QUORUM =>
+			verify_quorum_signatures(
+				state,
+				&envelope.auth_token,
+				&envelope.signatures,
+				policy.operation,
+			)
+			.await?,
+
+		AuthScheme::ADMIN => {
+			let (account, signature) = match (&envelope.admin_account, &envelope.signature) {
+				(Some(account), Some(signature)) => (account, signature),
+				_ =>
+					return Err((
+						StatusCode::BAD_REQUEST,
+						"admin_account and signature are expected".to_string(),
+					)),
+			};
+
+			if !is_admin(state, account).await {
+				return Err((
+					StatusCode::FORBIDDEN,
+					format!("Requester is not whitelisted : {account}"),
+				))
+			}
+
+			authorize_admin(account, policy.operation)
+				.map_err(|message| (StatusCode::FORBIDDEN, message))?;
+
+			if !verify_signature(account, signature.clone(), envelope.auth_token.as_bytes()) {
+				return Err((StatusCode::FORBIDDEN, "Invalid Signature".to_string()))
+			}
+
+			(parse_authentication_token(state, &envelope.auth_token).await?, vec![account.clone()])
+		},
+	};
+
+	Ok(VerifiedCaller {
+		signers: signers
+			.into_iter()
+			.map(|account| (account.clone(), admin_role(&account)))
+			.collect(),
+		operation: policy.operation,
+		token,
+	})
+}
+
+/// Middleware authenticating the admin requests of the routes with a policy, the verified signers
+/// are attached to the request for the handler
+pub async fn auth_guard(
+	State(state): State<SharedState>,
+	request: Request<Body>,
+	next: Next<Body>,
+) -> Response {
+	let path = endpoint_path(&request);
+	let policy = match auth_policy(request.method(), &path) {
+		RouteAuth::SIGNED(policy) => policy,
+		RouteAuth::HANDLER => return next.run(request).await,
+		RouteAuth::DENIED => {
+			let message =
+				format!("ADMIN AUTH : {} {path} has no authentication policy", request.method());
+			error!(message);
+			return (StatusCode::FORBIDDEN, Json(json!({ "error": message }))).into_response()
+		},
+	};
+
+	// The signatures are read here, then the body is handed over to the handler
+	let (mut parts, body) = request.into_parts();
+	let limit = body_limit(body_class(&path));
+
+	let (envelope, body) = match signed_envelope(&parts.headers, body, limit).await {
+		Ok(signed) => signed,
+		Err(err) => {
+			let message = format!("ADMIN AUTH : Request is not a signed admin packet : {err}");
+			warn!(message);
+			return (StatusCode::BAD_REQUEST, Json(json!({ "error": message }))).into_response()
+		},
+	};
+
+	match verify_caller(&state, policy, &envelope).await {
+		Ok(caller) => {
+			debug!("ADMIN AUTH : {path} is signed by {:?}", caller.signers.keys());
+			parts.extensions.insert(caller);
+		},
+		Err((status, message)) => {
+			if status == StatusCode::FORBIDDEN {
+				record_signature_failure();
+			}
+			let message = format!("ADMIN AUTH : {path} : {message}");
+			warn!(message);
+			return (status, Json(json!({ "error": message }))).into_response()
+		},
+	}
+
+	next.run(Request::from_parts(parts, body)).await
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn auth_policy_test() {
+		assert_eq!(
+			auth_policy(&Method::POST, "/api/admin/config"),
+			RouteAuth::SIGNED(AuthPolicy {
+				scheme: AuthScheme::QUORUM,
+				operation: AdminOperation::MANAGE
+			})
+		);
+		assert_eq!(
+			auth_policy(&Method::POST, "/api/backup/download-token"),
+			RouteAuth::SIGNED(AuthPolicy {
+				scheme: AuthScheme::ADMIN,
+				operation: AdminOperation::FETCH
+			})
+		);
+		assert_eq!(
+			auth_policy(&Method::POST, "/api/backup/fetch-bulk"),
+			RouteAuth::SIGNED(AuthPolicy {
+				scheme: AuthScheme::QUORUM,
+				operation: AdminOperation::FETCH
+			})
+		);
+		assert_eq!(
+			auth_policy(&Method::POST, "/api/backup/push-bulk"),
+			RouteAuth::SIGNED(AuthPolicy {
+				scheme: AuthScheme::ADMIN,
+				operation: AdminOperation::PUSH
+			})
+		);
+		assert_eq!(auth_policy(&Method::GET, "/api/admin/config"), RouteAuth::HANDLER);
+		assert_eq!(
+			auth_policy(&Method::POST, "/api/secret-nft/store-keyshare"),
+			RouteAuth::HANDLER
+		);
+		// A new mutation is denied until it is given a policy
+		assert_eq!(auth_policy(&Method::POST, "/api/backup/new-endpoint"), RouteAuth::DENIED);
+		assert_eq!(
+			auth_policy(&Method::DELETE, "/api/secret-nft/store-keyshare"),
+			RouteAuth::DENIED
+		);
+
+		let envelope: SignedEnvelope = serde_json::from_str(
+			r#"{"admin_address":"5C4hrfjw9DjXZTzV3MwzrrAr9P1MJhSrvWGWqi1eSuyUpnhM","auth_token":"{}","signature":"0x00","job_id":"1"}"#,
+		)
+		.unwrap();
+		assert!(envelope.admin_account.is_some());
+		assert!(envelope.signatures.is_empty());
+	}
+
+	#[tokio::test]
+	async fn multipart_envelope_test() {
+		let mut headers = HeaderMap::new();
+		headers.insert(header::CONTENT_TYPE, "multipart/form-data; boundary=X".parse().unwrap());
+
+		let body = [
+			"--X\r\nContent-Disposition: form-data; name=\"admin_address\"\r\n\r\n5C4hrfjw9DjXZTzV3MwzrrAr9P1MJhSrvWGWqi1eSuyUpnhM\r\n",
+			"--X\r\nContent-Disposition: form-data; name=\"auth_token\"\r\n\r\n{}\r\n",
+			"--X\r\nContent-Disposition: form-data; name=\"signature\"\r\n\r\n0x00\r\n",
+			"--X\r\nContent-Disposition: form-data; name=\"restore_file\"\r\n\r\nPK\r\n",
+			"--X--\r\n",
+		]
+		.concat();
+
+		let (envelope, replayed) =
+			signed_envelope(&headers, Body::from(body.clone()), 1024).await.unwrap();
+		assert_eq!(envelope.auth_token, "{}");
+		assert_eq!(envelope.signature.as_deref(), Some("0x00"));
+		assert!(envelope.admin_account.is_some());
+		// The handler receives the whole form
+		assert_eq!(hyper::body::to_bytes(replayed).await.unwrap(), Bytes::from(body));
+
+		// The signature fields must come before the archive
+		let late_token = [
+			"--X\r\nContent-Disposition: form-data; name=\"restore_file\"\r\n\r\nPK\r\n",
+			"--X\r\nContent-Disposition: form-data; name=\"auth_token\"\r\n\r\n{}\r\n",
+			"--X--\r\n",
+		]
+		.concat();
+		assert!(signed_envelope(&headers, Body::from(late_token), 1024).await.is_err());
+
+		// A json body is not read as a form, and is read up to the limit
+		let json_body = r#"{"auth_token":"{}","signatures":{}}"#;
+		assert!(signed_envelope(&HeaderMap::new(), Body::from(json_body), 1024).await.is_ok());
+		assert!(signed_envelope(&HeaderMap::new(), Body::from(json_body), 8).await.is_err());
+		assert!(signed_envelope(&headers, Body::from(json_body), 1024).await.is_err());
+	}
+}
@@ -0,0 +1,208 @@
+use std::sync::OnceLock;
+
+use axum::{
+	body::Body,
+	http::{
+		header::{CACHE_CONTROL, CONTENT_DISPOSITION},
+		Extensions, HeaderMap, HeaderName, Request, StatusCode, Version,
+	},
+	middleware::Next,
+	response::Response,
+};
+use serde::Serialize;
+use tower_http::compression::{
+	predicate::{NotForContentType, Predicate, SizeAbove},
+	CompressionLayer,
+};
+
+use crate::{chain::constants::COMPRESSION_MIN_SIZE, servers::versioning::endpoint_path};
+
+/* ---------------------------------------
+	RESPONSE COMPRESSION
+--------------------------------------- */
+
+// Inventories, quotes, metrics and admin reports are large json documents, they are compressed
+// with gzip or zstd when the client accepts it :
+// - the encoding is negotiated with Accept-Encoding, clients without it get identity responses
+// - keyshare responses are never compressed, their compressed size would defeat the response
+//   padding and leak the secrets they carry along with the reflected request (BREACH)
+// - archives and downloads are already compressed and encrypted, they are sent as they are
+// - responses below the minimum size are not worth the compression header
+
+static COMPRESSION_CONFIG: OnceLock<Option<CompressionConfig>> = OnceLock::new();
+
+/// Content encoding of the responses
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+	GZIP,
+	ZSTD,
+}
+
+/// Compression configuration of the api
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct CompressionConfig {
+	// Encodings offered to the clients, the client preference decides
+	pub encodings: Vec<Encoding>,
+	// Smallest compressed body in bytes
+	pub min_size: u16,
+}
+
+fn default_compression_config() -> Option<CompressionConfig> {
+	Some(CompressionConfig {
+		encodings: vec![Encoding::GZIP, Encoding::ZSTD],
+		min_size: COMPRESSION_MIN_SIZE,
+	})
+}
+
+/// Parse the compression configuration from the command line
+/// # Arguments
+/// * `encodings` - "off" or comma separated encodings, i.e. "gzip,zstd"
+/// * `min_size` - smallest compressed body in bytes
+pub fn parse_compression_config(
+	encodings: &str,
+	min_size: u16,
+) -> Result<Option<CompressionConfig>, anyhow::Error> {
+	if encodings.trim().eq_ignore_ascii_case("off") {
+		return Ok(None)
+	}
+
+	let mut parsed = Vec::new();
+	for encoding in encodings.split(',') {
+		let encoding = match encoding.trim().to_lowercase().as_str() {
+			"gzip" => Encoding::GZIP,
+			"zstd" => Encoding::ZSTD,
+			_ =>
+				return Err(anyhow::anyhow!(
+					"RESPONSE COMPRESSION : unknown encoding '{encoding}', expected gzip, zstd or off"
+				)),
+		};
+
+		if !parsed.contains(&encoding) {
+			parsed.push(encoding);
+		}
+	}
+
+	Ok(Some(CompressionConfig { encodings: parsed, min_size }))
+}
+
+/// Set the compression configuration, only once at startup
+pub fn set_compression_config(config: Option<CompressionConfig>) -> Result<(), anyhow::Error> {
+	COMPRESSION_CONFIG
+		.set(config)
+		.map_err(|_| anyhow::anyhow!("RESPONSE COMPRESSION : configuration is already set"))
+}
+
+/// Effective compression configuration, None if disabled
+pub fn compression_config() -> Option<&'static CompressionConfig> {
+	COMPRESSION_CONFIG.get_or_init(default_compression_config).as_ref()
+}
+
+/// Response extension of the endpoints which are never compressed
+#[derive(Clone, Copy, Debug)]
+struct Uncompressed;
+
+/// Whether the responses of an endpoint may be compressed
+/// # Arguments
+/// * `path` - unversioned route pattern of the request
+pub fn is_compressible_endpoint(path: &str) -> bool {
+	!(path.starts_with("/api/secret-nft/") ||
+		path.starts_with("/api/capsule-nft/") ||
+		path == "/api/access-check" ||
+		path == "/api/events/keyshares")
+}
+
+/// Middleware marking the responses of the keyshare endpoints, the compression layer skips them
+pub async fn compression_guard(request: Request<Body>, next: Next<Body>) -> Response {
+	if is_compressible_endpoint(&endpoint_path(&request)) {
+		return next.run(request).await
+	}
+
+	let mut response = next.run(request).await;
+	response.extensions_mut().insert(Uncompressed);
+	response
+}
+
+fn is_compressible(
+	status: StatusCode,
+	_version: Version,
+	headers: &HeaderMap,
+	extensions: &Extensions,
+) -> bool {
+	let header_contains = |name: HeaderName, value: &str| {
+		headers
+			.get(name)
+			.and_then(|header| header.to_str().ok())
+			.map(|header| header.to_lowercase().contains(value))
+			.unwrap_or(false)
+	};
+
+	// Upgrades, empty and not modified responses have no body to compress
+	status.is_success() &&
+		status != StatusCode::NO_CONTENT &&
+		extensions.get::<Uncompressed>().is_none() &&
+		!header_contains(CONTENT_DISPOSITION, "attachment") &&
+		!header_contains(CACHE_CONTROL, "no-transform")
+}
+
+/// Compression layer of the api, None if compression is disabled
+pub fn compression_layer() -> Option<CompressionLayer<impl Predicate>> {
+	let config = compression_config()?;
+
+	let predicate = SizeAbove::new(config.min_size)
+		.and(NotForContentType::GRPC)
+		.and(NotForContentType::IMAGES)
+		.and(NotForContentType::const_new("application/zip"))
+		.and(NotForContentType::const_new("application/octet-stream"))
+		.and(is_compressible);
+
+	Some(
+		CompressionLayer::new()
+			.gzip(config.encodings.contains(&Encoding::GZIP))
+			.zstd(config.encodings.contains(&Encoding::ZSTD))
+			.compress_when(predicate),
+	)
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn compression_config_test() {
+		assert_eq!(parse_compression_config("off", 1024).unwrap(), None);
+		assert_eq!(
+			parse_compression_config("zstd, gzip,zstd", 512).unwrap(),
+			Some(CompressionConfig {
+				encodings: vec![Encoding::ZSTD, Encoding::GZIP],
+				min_size: 512
+			})
+		);
+		assert!(parse_compression_config("br", 1024).is_err());
+
+		assert!(is_compressible_endpoint("/api/backup/sync-inventory"));
+		assert!(is_compressible_endpoint("/api/quote"));
+		assert!(!is_compressible_endpoint("/api/secret-nft/batch-retrieve-keyshare"));
+		assert!(!is_compressible_endpoint("/api/events/keyshares"));
+
+		let mut headers = HeaderMap::new();
+		let extensions = Extensions::new();
+		assert!(is_compressible(StatusCode::OK, Version::HTTP_11, &headers, &extensions));
+		assert!(!is_compressible(
+			StatusCode::SWITCHING_PROTOCOLS,
+			Version::HTTP_11,
+			&headers,
+			&extensions
+		));
+
+		headers.insert(CONTENT_DISPOSITION, "attachment; filename=\"Backup.zip\"".parse().unwrap());
+		assert!(!is_compressible(StatusCode::OK, Version::HTTP_11, &headers, &extensions));
+
+		let mut extensions = Extensions::new();
+		extensions.insert(Uncompressed);
+		assert!(!is_compressible(StatusCode::OK, Version::HTTP_11, &HeaderMap::new(), &extensions));
+	}
+}
@@ -0,0 +1,148 @@
+use std::{sync::OnceLock, time::Duration};
+
+use axum_server::{AddrIncomingConfig, HttpConfig};
+use serde::Serialize;
+
+use crate::chain::constants::{
+	HTTP1_HEADER_TIMEOUT, HTTP2_KEEPALIVE_INTERVAL, HTTP2_KEEPALIVE_TIMEOUT, HTTP2_MAX_STREAMS,
+	TCP_KEEPALIVE,
+};
+
+/* ---------------------------------------
+	CONNECTION TUNING
+--------------------------------------- */
+
+// SDKs send thousands of retrieve calls, a TCP and TLS handshake per call costs more than the call
+// itself :
+// - HTTP/2 is offered in the TLS handshake (ALPN), the calls of a client share one connection and
+//   are multiplexed as streams, HTTP/1.1 clients keep their pooled keep-alive connections
+// - idle HTTP/2 connections are pinged, a peer which does not answer in time is disconnected
+// - TCP keepalive probes detect the HTTP/1.1 peers which are gone without closing
+// - the headers of an HTTP/1.1 request must arrive in time, slow clients do not hold connections
+
+static CONNECTION_CONFIG: OnceLock<ConnectionConfig> = OnceLock::new();
+
+/// Connection settings of the api server
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ConnectionConfig {
+	// HTTP/2 is offered to the TLS clients
+	pub http2: bool,
+	// Seconds between the pings of an idle HTTP/2 connection, 0 disables the pings
+	pub http2_keepalive_interval: u64,
+	// Seconds to acknowledge a ping before the connection is closed
+	pub http2_keepalive_timeout: u64,
+	// Concurrent streams of an HTTP/2 connection
+	pub http2_max_streams: u32,
+	// Seconds to send the headers of an HTTP/1.1 request
+	pub http1_header_timeout: u64,
+	// Seconds of inactivity before the TCP keepalive probes, 0 disables them
+	pub tcp_keepalive: u64,
+}
+
+impl Default for ConnectionConfig {
+	fn default() -> Self {
+		ConnectionConfig {
+			http2: true,
+			http2_keepalive_interval: HTTP2_KEEPALIVE_INTERVAL,
+			http2_keepalive_timeout: HTTP2_KEEPALIVE_TIMEOUT,
+			http2_max_streams: HTTP2_MAX_STREAMS,
+			http1_header_timeout: HTTP1_HEADER_TIMEOUT,
+			tcp_keepalive: TCP_KEEPALIVE,
+		}
+	}
+}
+
+impl ConnectionConfig {
+	/// Validate the settings of the command line
+	pub fn validate(self) -> Result<ConnectionConfig, anyhow::Error> {
+		if self.http2_keepalive_timeout == 0 {
+			return Err(anyhow::anyhow!("CONNECTION : http2 keepalive timeout must be positive"))
+		}
+
+		if self.http2_max_streams == 0 {
+			return Err(anyhow::anyhow!("CONNECTION : http2 max streams must be positive"))
+		}
+
+		if self.http1_header_timeout == 0 {
+			return Err(anyhow::anyhow!("CONNECTION : http1 header timeout must be positive"))
+		}
+
+		Ok(self)
+	}
+
+	/// Protocols offered in the TLS handshake, in the order of preference
+	pub fn alpn_protocols(&self) -> Vec<Vec<u8>> {
+		if self.http2 {
+			vec![b"h2".to_vec(), b"http/1.1".to_vec()]
+		} else {
+			vec![b"http/1.1".to_vec()]
+		}
+	}
+
+	/// HTTP settings of the hyper connections
+	pub fn http_config(&self) -> HttpConfig {
+		let interval = match self.http2_keepalive_interval {
+			0 => None,
+			seconds => Some(Duration::from_secs(seconds)),
+		};
+
+		HttpConfig::new()
+			.http1_keep_alive(true)
+			.http1_header_read_timeout(Duration::from_secs(self.http1_header_timeout))
+			.http2_keep_alive_interval(interval)
+			.http2_keep_alive_timeout(Duration::from_secs(self.http2_keepalive_timeout))
+			.http2_max_concurrent_streams(self.http2_max_streams)
+			.http2_adaptive_window(true)
+			.build()
+	}
+
+	/// TCP settings of the accepted connections
+	pub fn incoming_config(&self) -> AddrIncomingConfig {
+		let keepalive = match self.tcp_keepalive {
+			0 => None,
+			seconds => Some(Duration::from_secs(seconds)),
+		};
+
+		AddrIncomingConfig::new().tcp_nodelay(true).tcp_keepalive(keepalive).build()
+	}
+}
+
+/// Set the connection settings, only once at startup
+pub fn set_connection_config(config: ConnectionConfig) -> Result<(), anyhow::Error> {
+	CONNECTION_CONFIG
+		.set(config)
+		.map_err(|_| anyhow::anyhow!("CONNECTION : configuration is already set"))
+}
+
+/// Effective connection settings, the defaults if they are not configured
+pub fn connection_config() -> &'static ConnectionConfig {
+	CONNECTION_CONFIG.get_or_init(ConnectionConfig::default)
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn connection_config_test() {
+		let config = ConnectionConfig::default().validate().unwrap();
+		assert_eq!(config.alpn_protocols()[0], b"h2".to_vec());
+
+		let config = ConnectionConfig { http2: false, ..Default::default() };
+		assert_eq!(config.alpn_protocols(), vec![b"http/1.1".to_vec()]);
+
+		assert!(ConnectionConfig { http2_keepalive_timeout: 0, ..Default::default() }
+			.validate()
+			.is_err());
+		assert!(ConnectionConfig { http2_max_streams: 0, ..Default::default() }
+			.validate()
+			.is_err());
+		assert!(ConnectionConfig { http2_keepalive_interval: 0, ..Default::default() }
+			.validate()
+			.is_ok());
+	}
+}
@@ -0,0 +1,323 @@
+use std::{
+	fmt,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+use axum::{
+	body::Body,
+	extract::State,
+	http::{Request, StatusCode},
+	middleware::Next,
+	response::{IntoResponse, Response},
+	Json,
+};
+use serde::Serialize;
+use serde_json::json;
+use tokio::sync::{
+	Mutex as AsyncMutex, OwnedMutexGuard, OwnedRwLockReadGuard, OwnedRwLockWriteGuard, RwLock,
+};
+use tracing::{debug, error, info};
+
+use crate::servers::{
+	state::{get_coordinator, get_runtime_config, SharedState},
+	versioning::endpoint_path,
+};
+
+/* ---------------------------------------
+	OPERATION COORDINATION
+--------------------------------------- */
+
+// Keyshare requests declare a read or write intent, backups, restores and synchronizations run as
+// maintenance operations :
+// - maintenance operations are exclusive, only one runs at a time
+// - a backup waits for the in-flight writes, and holds the new ones
+// - a restore also waits for the in-flight reads, and holds the new ones
+// - a synchronization only writes keyshares of other clusters, requests are still served
+// Conflicting calls are queued for a bounded time, then rejected with 409 Conflict.
+
+/// Intent of a keyshare request
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OperationIntent {
+	READ,
+	WRITE,
+}
+
+/// Maintenance operation, exclusive of the other maintenance operations
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MaintenanceOperation {
+	// Consistent snapshot of the sealed directory, reads are still served
+	BACKUP,
+	// Keyshares fetched from the other clusters, requests are still served
+	SYNC,
+	// Keyshares overwritten by an archive, the enclave account may be reloaded
+	RESTORE,
+}
+
+impl MaintenanceOperation {
+	fn holds_writes(&self) -> bool {
+		matches!(self, MaintenanceOperation::BACKUP | MaintenanceOperation::RESTORE)
+	}
+
+	fn holds_reads(&self) -> bool {
+		matches!(self, MaintenanceOperation::RESTORE)
+	}
+}
+
+/// Maintenance operation in progress
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct RunningOperation {
+	pub operation: MaintenanceOperation,
+	// Request or task which runs the operation, i.e. "push-bulk"
+	pub name: String,
+	pub elapsed_seconds: u64,
+}
+
+/// Conflicting call, still blocked after its queuing time
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct OperationConflict {
+	pub running: Option<RunningOperation>,
+	pub waited_seconds: u64,
+}
+
+impl fmt::Display for OperationConflict {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match &self.running {
+			Some(running) => write!(
+				f,
+				"enclave is busy with {:?} ({}) since {} seconds",
+				running.operation, running.name, running.elapsed_seconds
+			),
+			None => write!(f, "enclave is busy with in-flight requests"),
+		}
+	}
+}
+
+impl std::error::Error for OperationConflict {}
+
+impl IntoResponse for OperationConflict {
+	fn into_response(self) -> Response {
+		(
+			StatusCode::CONFLICT,
+			Json(json!({
+				"description": format!("{self}, try again later"),
+				"running": self.running,
+				"waited_seconds": self.waited_seconds,
+			})),
+		)
+			.into_response()
+	}
+}
+
+/// Held while a keyshare request is served
+pub struct IntentGuard {
+	_guard: OwnedRwLockReadGuard<()>,
+}
+
+/// Held while a maintenance operation runs, the operation ends when it is dropped
+pub struct MaintenanceGuard {
+	running: Arc<Mutex<Option<(MaintenanceOperation, String, Instant)>>>,
+	_reads: Option<OwnedRwLockWriteGuard<()>>,
+	_writes: Option<OwnedRwLockWriteGuard<()>>,
+	_maintenance: OwnedMutexGuard<()>,
+}
+
+impl Drop for MaintenanceGuard {
+	fn drop(&mut self) {
+		match self.running.lock() {
+			Ok(mut running) =>
+				if let Some((operation, name, started)) = running.take() {
+					info!(
+						"COORDINATION : {operation:?} ({name}) is done in {:?}",
+						started.elapsed()
+					);
+				},
+			Err(err) => error!("COORDINATION : lock error : {err:?}"),
+		}
+	}
+}
+
+/// Coordination of the keyshare requests and maintenance operations, shared by the SharedState
+#[derive(Clone, Default)]
+pub struct OperationCoordinator {
+	maintenance: Arc<AsyncMutex<()>>,
+	writes: Arc<RwLock<()>>,
+	reads: Arc<RwLock<()>>,
+	running: Arc<Mutex<Option<(MaintenanceOperation, String, Instant)>>>,
+}
+
+impl OperationCoordinator {
+	/// Maintenance operation in progress, None if the enclave is only serving requests
+	pub fn running(&self) -> Option<RunningOperation> {
+		self.running.lock().ok().and_then(|running| {
+			running.as_ref().map(|(operation, name, started)| RunningOperation {
+				operation: *operation,
+				name: name.clone(),
+				elapsed_seconds: started.elapsed().as_secs(),
+			})
+		})
+	}
+
+	fn conflict(&self, wait: Duration) -> OperationConflict {
+		OperationConflict { running: self.running(), waited_seconds: wait.as_secs() }
+	}
+
+	/// Declare the intent of a keyshare request, queued while a conflicting operation runs
+	/// # Arguments
+	/// * `intent` - read or write of keyshares
+	/// * `wait` - queuing time before the conflict
+	pub async fn begin_intent(
+		&self,
+		intent: OperationIntent,
+		wait: Duration,
+	) -> Result<IntentGuard, OperationConflict> {
+		let lock = match intent {
+			OperationIntent::READ => self.reads.clone(),
+			OperationIntent::WRITE => self.writes.clone(),
+		};
+
+		match tokio::time::timeout(wait, lock.read_owned()).await {
+			Ok(guard) => Ok(IntentGuard { _guard: guard }),
+			Err(_) => Err(self.conflict(wait)),
+		}
+	}
+
+	/// Start a maintenance operation, once the previous one is done and the conflicting requests
+	/// are drained
+	/// # Arguments
+	/// * `operation` - kind of the operation
+	/// * `name` - request or task which runs the operation
+	/// * `wait` - queuing time before the conflict
+	pub async fn begin_maintenance(
+		&self,
+		operation: MaintenanceOperation,
+		name: &str,
+		wait: Duration,
+	) -> Result<MaintenanceGuard, OperationConflict> {
+		let acquire = async {
+			let maintenance = self.maintenance.clone().lock_owned().await;
+			let writes = if operation.holds_writes() {
+				Some(self.writes.clone().write_owned().await)
+			} else {
+				None
+			};
+			let reads = if operation.holds_reads() {
+				Some(self.reads.clone().write_owned().await)
+			} else {
+				None
+			};
+			(maintenance, writes, reads)
+		};
+
+		let (maintenance, writes, reads) = match tokio::time::timeout(wait, acquire).await {
+			Ok(guards) => guards,
+			Err(_) => return Err(self.conflict(wait)),
+		};
+
+		match self.running.lock() {
+			Ok(mut running) => *running = Some((operation, name.to_string(), Instant::now())),
+			Err(err) => error!("COORDINATION : lock error : {err:?}"),
+		}
+		debug!("COORDINATION : {operation:?} ({name}) is started");
+
+		Ok(MaintenanceGuard {
+			running: self.running.clone(),
+			_reads: reads,
+			_writes: writes,
+			_maintenance: maintenance,
+		})
+	}
+}
+
+/// Intent of a keyshare endpoint, None for the endpoints which are not coordinated
+/// # Arguments
+/// * `path` - unversioned route pattern of the request
+pub fn operation_intent(path: &str) -> Option<OperationIntent> {
+	match path {
+		"/api/secret-nft/retrieve-keyshare" |
+		"/api/secret-nft/batch-retrieve-keyshare" |
+		"/api/capsule-nft/retrieve-keyshare" |
+		"/api/backup/sync-keyshare" => Some(OperationIntent::READ),
+
+		"/api/secret-nft/store-keyshare" |
+		"/api/secret-nft/batch-store-keyshare" |
+		"/api/secret-nft/remove-keyshare" |
+		"/api/capsule-nft/set-keyshare" |
+		"/api/capsule-nft/update-keyshare" |
+		"/api/capsule-nft/remove-keyshare" |
+		"/api/backup/push-keyshares" => Some(OperationIntent::WRITE),
+
+		_ => None,
+	}
+}
+
+/// Middleware declaring the intent of keyshare requests, they are queued during a conflicting
+/// maintenance operation and rejected with 409 Conflict after the queuing time
+pub async fn coordination_guard(
+	State(state): State<SharedState>,
+	request: Request<Body>,
+	next: Next<Body>,
+) -> Response {
+	let intent = match operation_intent(&endpoint_path(&request)) {
+		Some(intent) => intent,
+		None => return next.run(request).await,
+	};
+
+	let wait = Duration::from_secs(get_runtime_config(&state).await.operation_queue_timeout);
+	let coordinator = get_coordinator(&state).await;
+	let _guard = match coordinator.begin_intent(intent, wait).await {
+		Ok(guard) => guard,
+		Err(conflict) => {
+			debug!("COORDINATION : {intent:?} request is rejected : {conflict}");
+			return conflict.into_response()
+		},
+	};
+
+	next.run(request).await
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[tokio::test]
+	async fn coordination_test() {
+		let coordinator = OperationCoordinator::default();
+		let wait = Duration::from_millis(50);
+
+		// A backup holds the writes, not the reads
+		let backup =
+			coordinator.begin_maintenance(MaintenanceOperation::BACKUP, "test", wait).await;
+		assert!(backup.is_ok());
+		assert!(coordinator.begin_intent(OperationIntent::READ, wait).await.is_ok());
+		let conflict = coordinator.begin_intent(OperationIntent::WRITE, wait).await.err();
+		assert_eq!(
+			conflict.and_then(|conflict| conflict.running).map(|running| running.operation),
+			Some(MaintenanceOperation::BACKUP)
+		);
+
+		// Maintenance operations are exclusive
+		assert!(coordinator
+			.begin_maintenance(MaintenanceOperation::RESTORE, "test", wait)
+			.await
+			.is_err());
+		drop(backup);
+		assert!(coordinator.running().is_none());
+
+		// A restore waits for the in-flight reads
+		let read = coordinator.begin_intent(OperationIntent::READ, wait).await;
+		assert!(coordinator
+			.begin_maintenance(MaintenanceOperation::RESTORE, "test", wait)
+			.await
+			.is_err());
+		drop(read);
+		let restore =
+			coordinator.begin_maintenance(MaintenanceOperation::RESTORE, "test", wait).await;
+		assert!(restore.is_ok());
+		assert!(coordinator.begin_intent(OperationIntent::READ, wait).await.is_err());
+	}
+}
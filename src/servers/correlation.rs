@@ -0,0 +1,87 @@
+use axum::{
+	body::Body,
+	http::{HeaderValue, Request},
+	middleware::Next,
+	response::Response,
+};
+use rand::Rng;
+use tracing::{debug, info_span, Instrument};
+
+use crate::chain::constants::MAX_REQUEST_ID_LENGTH;
+
+/* ---------------------------------------
+	REQUEST CORRELATION
+--------------------------------------- */
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Correlation id of an api request, available to the handlers as a request extension
+#[derive(Clone, Debug, PartialEq)]
+pub struct RequestId(pub String);
+
+/// Correlation id given by the client, if it is short and only has url-safe characters so it
+/// can be logged as is
+fn accepted_request_id(header: Option<&HeaderValue>) -> Option<String> {
+	let id = header?.to_str().ok()?;
+
+	let is_valid = !id.is_empty() &&
+		id.len() <= MAX_REQUEST_ID_LENGTH &&
+		id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+
+	is_valid.then(|| id.to_string())
+}
+
+fn generate_request_id() -> String {
+	hex::encode(rand::thread_rng().gen::<[u8; 16]>())
+}
+
+/// Middleware running each api request in a span carrying its correlation id, taken from the
+/// X-Request-Id header or generated. Verification, chain calls and file I/O of the handler are
+/// logged in the span and the id is returned in the X-Request-Id header of the response.
+pub async fn correlation_guard(mut request: Request<Body>, next: Next<Body>) -> Response {
+	let request_id = accepted_request_id(request.headers().get(REQUEST_ID_HEADER))
+		.unwrap_or_else(generate_request_id);
+
+	let span = info_span!(
+		"api",
+		request_id = %request_id,
+		method = %request.method(),
+		path = %request.uri().path(),
+	);
+
+	request.extensions_mut().insert(RequestId(request_id.clone()));
+
+	let mut response = next.run(request).instrument(span.clone()).await;
+
+	span.in_scope(|| debug!("API : {} response", response.status()));
+
+	if let Ok(value) = HeaderValue::from_str(&request_id) {
+		response.headers_mut().insert(REQUEST_ID_HEADER, value);
+	}
+
+	response
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn request_id_test() {
+		let accepted = HeaderValue::from_static("wallet-7f3a_1.2");
+		assert_eq!(accepted_request_id(Some(&accepted)), Some("wallet-7f3a_1.2".to_string()));
+
+		assert_eq!(accepted_request_id(None), None);
+		assert_eq!(accepted_request_id(Some(&HeaderValue::from_static(""))), None);
+		assert_eq!(accepted_request_id(Some(&HeaderValue::from_static("id with spaces"))), None);
+		let too_long = "a".repeat(MAX_REQUEST_ID_LENGTH + 1);
+		assert_eq!(accepted_request_id(Some(&HeaderValue::from_str(&too_long).unwrap())), None);
+
+		let generated = generate_request_id();
+		assert_eq!(generated.len(), 32);
+		assert_eq!(
+			accepted_request_id(Some(&HeaderValue::from_str(&generated).unwrap())),
+			Some(generated)
+		);
+	}
+}
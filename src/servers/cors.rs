@@ -0,0 +1,212 @@
+use std::{str::FromStr, sync::OnceLock, time::Duration};
+
+use axum::http::{
+	header::{CONTENT_TYPE, ETAG, IF_NONE_MATCH, RETRY_AFTER},
+	HeaderName, HeaderValue, Method,
+};
+use serde::Serialize;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+use crate::{
+	chain::constants::CORS_MAX_AGE,
+	servers::{
+		correlation::REQUEST_ID_HEADER,
+		versioning::{DEPRECATION_HEADER, LINK_HEADER},
+	},
+};
+
+/* ---------------------------------------
+	CORS
+--------------------------------------- */
+
+// Browser wallets and dApps call the enclave directly, their origin must be allowed :
+// - origins are exact ("https://www.ternoa.network") or have one wildcard
+//   ("https://*.ternoa.network", "http://localhost:*"), "*" allows any origin
+// - preflight requests are answered by the layer and cached by the browser for the max-age
+// - private clusters which are never called from a browser can disable CORS entirely
+// The default origins are the Ternoa dApp domains of the network of the binary.
+
+static CORS_CONFIG: OnceLock<Option<CorsConfig>> = OnceLock::new();
+
+/// CORS configuration of the api
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct CorsConfig {
+	// Allowed origin patterns
+	pub origins: Vec<String>,
+	// Request headers allowed in addition to the headers of the api
+	pub headers: Vec<String>,
+	// Seconds a preflight response is cached by the browser
+	pub max_age: u64,
+}
+
+/// Ternoa dApp origins of the network of the binary
+pub fn default_cors_origins() -> Vec<String> {
+	let mut origins =
+		vec!["https://ternoa.network".to_string(), "https://*.ternoa.network".to_string()];
+
+	if !cfg!(feature = "mainnet") {
+		origins.push("https://*.ternoa.dev".to_string());
+	}
+
+	if !cfg!(any(feature = "mainnet", feature = "alphanet")) {
+		origins.push("http://localhost:*".to_string());
+	}
+
+	origins
+}
+
+fn default_cors_config() -> Option<CorsConfig> {
+	Some(CorsConfig { origins: default_cors_origins(), headers: Vec::new(), max_age: CORS_MAX_AGE })
+}
+
+fn is_valid_origin_pattern(pattern: &str) -> bool {
+	pattern == "*" ||
+		((pattern.starts_with("https://") || pattern.starts_with("http://")) &&
+			!pattern.ends_with('/') &&
+			pattern.matches('*').count() <= 1)
+}
+
+/// Parse the CORS configuration from the command line
+/// # Arguments
+/// * `disabled` - no CORS headers, preflight requests are not answered
+/// * `origins` - allowed origin patterns, the default origins of the network if empty
+/// * `headers` - additional allowed request headers
+/// * `max_age` - seconds a preflight response is cached
+pub fn parse_cors_config(
+	disabled: bool,
+	origins: &[String],
+	headers: &[String],
+	max_age: u64,
+) -> Result<Option<CorsConfig>, anyhow::Error> {
+	if disabled {
+		return Ok(None)
+	}
+
+	let origins = if origins.is_empty() {
+		default_cors_origins()
+	} else {
+		origins.iter().map(|origin| origin.trim().to_lowercase()).collect()
+	};
+
+	if let Some(origin) = origins.iter().find(|origin| !is_valid_origin_pattern(origin)) {
+		return Err(anyhow::anyhow!("CORS : invalid origin pattern '{origin}'"))
+	}
+
+	let headers = headers
+		.iter()
+		.map(|header| {
+			HeaderName::from_str(header.trim())
+				.map(|name| name.to_string())
+				.map_err(|err| anyhow::anyhow!("CORS : invalid header '{header}' : {err}"))
+		})
+		.collect::<Result<Vec<_>, _>>()?;
+
+	Ok(Some(CorsConfig { origins, headers, max_age }))
+}
+
+/// Set the CORS configuration, only once at startup
+pub fn set_cors_config(config: Option<CorsConfig>) -> Result<(), anyhow::Error> {
+	CORS_CONFIG
+		.set(config)
+		.map_err(|_| anyhow::anyhow!("CORS : configuration is already set"))
+}
+
+/// Effective CORS configuration, None if disabled
+pub fn cors_config() -> Option<&'static CorsConfig> {
+	CORS_CONFIG.get_or_init(default_cors_config).as_ref()
+}
+
+/// Whether an origin matches an origin pattern
+/// # Arguments
+/// * `pattern` - exact origin, origin with one wildcard, or "*"
+/// * `origin` - Origin header of the request
+pub fn origin_matches(pattern: &str, origin: &str) -> bool {
+	if pattern == "*" {
+		return true
+	}
+
+	match pattern.split_once('*') {
+		Some((prefix, suffix)) => {
+			if origin.len() <= prefix.len() + suffix.len() ||
+				!origin.starts_with(prefix) ||
+				!origin.ends_with(suffix)
+			{
+				return false
+			}
+
+			// A port wildcard only matches digits, a host wildcard only matches host labels
+			let wildcard = &origin[prefix.len()..origin.len() - suffix.len()];
+			if prefix.ends_with(':') {
+				wildcard.chars().all(|c| c.is_ascii_digit())
+			} else {
+				wildcard.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.'))
+			}
+		},
+		None => pattern == origin,
+	}
+}
+
+/// CORS layer of the api, None if CORS is disabled
+pub fn cors_layer() -> Option<CorsLayer> {
+	let config = cors_config()?;
+
+	let allow_origin = if config.origins.iter().any(|origin| origin == "*") {
+		AllowOrigin::any()
+	} else {
+		let origins = config.origins.clone();
+		AllowOrigin::predicate(move |origin: &HeaderValue, _| {
+			let origin = origin.to_str().unwrap_or_default().to_lowercase();
+			origins.iter().any(|pattern| origin_matches(pattern, &origin))
+		})
+	};
+
+	let mut allow_headers =
+		vec![CONTENT_TYPE, IF_NONE_MATCH, HeaderName::from_static(REQUEST_ID_HEADER)];
+	allow_headers.extend(config.headers.iter().filter_map(|header| header.parse().ok()));
+
+	Some(
+		CorsLayer::new()
+			.allow_origin(allow_origin)
+			.allow_methods([Method::GET, Method::POST, Method::PUT])
+			.allow_headers(allow_headers)
+			.expose_headers([
+				HeaderName::from_static(REQUEST_ID_HEADER),
+				RETRY_AFTER,
+				ETAG,
+				HeaderName::from_static(DEPRECATION_HEADER),
+				HeaderName::from_static(LINK_HEADER),
+			])
+			.max_age(Duration::from_secs(config.max_age)),
+	)
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn origin_matches_test() {
+		assert!(origin_matches("https://*.ternoa.network", "https://app.ternoa.network"));
+		assert!(origin_matches("https://*.ternoa.network", "https://a.b.ternoa.network"));
+		assert!(!origin_matches("https://*.ternoa.network", "https://ternoa.network"));
+		assert!(!origin_matches("https://*.ternoa.network", "https://evil.com/.ternoa.network"));
+		assert!(!origin_matches("https://*.ternoa.network", "http://app.ternoa.network"));
+		assert!(origin_matches("http://localhost:*", "http://localhost:3000"));
+		assert!(!origin_matches("http://localhost:*", "http://localhost:3000.evil.com"));
+		assert!(origin_matches("https://www.ternoa.network", "https://www.ternoa.network"));
+		assert!(origin_matches("*", "https://example.com"));
+
+		assert_eq!(parse_cors_config(true, &[], &[], 600).unwrap(), None);
+		let config =
+			parse_cors_config(false, &[], &["X-Wallet".to_string()], 600).unwrap().unwrap();
+		assert_eq!(config.origins, default_cors_origins());
+		assert_eq!(config.headers, vec!["x-wallet".to_string()]);
+		assert!(parse_cors_config(false, &["ternoa.network".to_string()], &[], 600).is_err());
+		assert!(parse_cors_config(false, &["https://*.*.network".to_string()], &[], 600).is_err());
+		assert!(parse_cors_config(false, &[], &["bad header".to_string()], 600).is_err());
+	}
+}
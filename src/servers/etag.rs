@@ -0,0 +1,128 @@
+use axum::{
+	body::{boxed, Body, Full},
+	http::{
+		header::{CACHE_CONTROL, ETAG, IF_NONE_MATCH},
+		HeaderMap, HeaderValue, Method, Request, StatusCode,
+	},
+	middleware::Next,
+	response::{IntoResponse, Response},
+};
+use tracing::{debug, error};
+
+use crate::servers::versioning::endpoint_path;
+
+/* ---------------------------------------
+	ENTITY TAGS
+--------------------------------------- */
+
+// Monitoring and clients poll the quote and the health metadata, which rarely change :
+// - their responses carry a weak ETag, a client sends it back in If-None-Match and gets "304 Not
+//   Modified" without body while the content is the same
+// - the tag of the quote is the enclave and the block of its report data, an unchanged block
+//   returns 304 before a new quote is generated
+// - the tag of the other endpoints is the hash of their json body
+// Tags are weak, the same content is sent compressed or not.
+
+// GET endpoints of which the responses carry an ETag
+const ETAG_ENDPOINTS: [&str; 3] = ["/api/quote", "/api/health", "/api/capabilities"];
+
+/// Weak entity tag of a response body
+/// # Arguments
+/// * `body` - bytes of the response
+pub fn entity_tag(body: &[u8]) -> String {
+	format!("W/\"{}\"", &sha256::digest(body)[..32])
+}
+
+/// Whether the If-None-Match header of a request matches an entity tag, weak comparison
+/// # Arguments
+/// * `headers` - headers of the request
+/// * `etag` - current entity tag of the resource
+pub fn if_none_match(headers: &HeaderMap, etag: &str) -> bool {
+	let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+
+	headers
+		.get_all(IF_NONE_MATCH)
+		.iter()
+		.filter_map(|value| value.to_str().ok())
+		.flat_map(|value| value.split(','))
+		.any(|tag| tag.trim() == "*" || opaque(tag) == opaque(etag))
+}
+
+/// Add the entity tag to a response, clients revalidate it before using their copy
+pub fn with_entity_tag(mut response: Response, etag: &str) -> Response {
+	if let Ok(value) = HeaderValue::from_str(etag) {
+		response.headers_mut().insert(ETAG, value);
+		response
+			.headers_mut()
+			.insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+	}
+
+	response
+}
+
+/// Empty "304 Not Modified" response of an unchanged resource
+pub fn not_modified(etag: &str) -> Response {
+	with_entity_tag(StatusCode::NOT_MODIFIED.into_response(), etag)
+}
+
+/// Middleware tagging the responses of the ETAG_ENDPOINTS, a matching If-None-Match gets 304.
+/// Handlers which set their own ETag are left as they are.
+pub async fn etag_guard(request: Request<Body>, next: Next<Body>) -> Response {
+	if request.method() != Method::GET ||
+		!ETAG_ENDPOINTS.contains(&endpoint_path(&request).as_str())
+	{
+		return next.run(request).await
+	}
+
+	let request_headers = request.headers().clone();
+	let response = next.run(request).await;
+	if response.status() != StatusCode::OK || response.headers().contains_key(ETAG) {
+		return response
+	}
+
+	let (parts, body) = response.into_parts();
+	let bytes = match hyper::body::to_bytes(body).await {
+		Ok(bytes) => bytes,
+		Err(err) => {
+			error!("ENTITY TAG : unable to read the response body : {err:?}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response()
+		},
+	};
+
+	let etag = entity_tag(&bytes);
+	if if_none_match(&request_headers, &etag) {
+		debug!("ENTITY TAG : {etag} is not modified");
+		return not_modified(&etag)
+	}
+
+	with_entity_tag(Response::from_parts(parts, boxed(Full::from(bytes))), &etag)
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn if_none_match_test() {
+		let etag = entity_tag(b"{\"status\":200}");
+		assert!(etag.starts_with("W/\"") && etag.ends_with('"'));
+		assert_ne!(etag, entity_tag(b"{\"status\":503}"));
+
+		let mut headers = HeaderMap::new();
+		assert!(!if_none_match(&headers, &etag));
+
+		let strong = etag.trim_start_matches("W/").to_string();
+		headers.insert(IF_NONE_MATCH, format!("\"other\", {strong}").parse().unwrap());
+		assert!(if_none_match(&headers, &etag));
+
+		headers.insert(IF_NONE_MATCH, "\"other\"".parse().unwrap());
+		assert!(!if_none_match(&headers, &etag));
+
+		headers.insert(IF_NONE_MATCH, "*".parse().unwrap());
+		assert!(if_none_match(&headers, &etag));
+	}
+}
@@ -0,0 +1,322 @@
+use std::{
+	collections::BTreeSet,
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		OnceLock,
+	},
+	time::Duration,
+};
+
+use axum::{
+	extract::{
+		ws::{Message, WebSocket, WebSocketUpgrade},
+		State,
+	},
+	http::StatusCode,
+	response::{IntoResponse, Response},
+	Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, info, warn};
+
+use crate::{
+	backup::{admin_bulk::ValidationResult, sync::verify_signature},
+	chain::{
+		constants::{
+			KEYSHARE_EVENT_BUFFER, MAX_BLOCK_VARIATION, MAX_EVENT_SUBSCRIPTIONS,
+			MAX_SUBSCRIBED_NFTS, MAX_VALIDATION_PERIOD, SUBSCRIPTION_AUTH_TIMEOUT,
+		},
+		helper::NftType,
+	},
+	servers::{
+		shutdown::is_shutting_down,
+		state::{get_blocknumber, SharedState},
+	},
+};
+
+/* ---------------------------------------
+	KEYSHARE EVENTS
+--------------------------------------- */
+
+// Marketplaces subscribe to the keyshare availability of a list of nfts instead of polling :
+// - the client opens a websocket on /api/events/keyshares and sends a signed subscription packet
+// - the enclave answers with the subscribed nft ids, then sends an event each time a keyshare of
+//   these nfts is stored, removed, synced from another cluster or restored by an admin
+// - a client which is too slow to read its events receives a "lagged" message with the number of
+//   missed events, it should check the availability endpoints again
+// Events are not persisted, a client only receives the events after its subscription.
+
+static KEYSHARE_EVENTS: OnceLock<broadcast::Sender<KeyshareEvent>> = OnceLock::new();
+static SUBSCRIPTIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// Change of the keyshare availability
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum KeyshareEventKind {
+	// Stored or updated by the owner
+	STORED,
+	// Removed after the burn of the nft
+	REMOVED,
+	// Fetched from another cluster, or its synced block is detected
+	SYNCED,
+	// Pushed or injected by an admin
+	RESTORED,
+}
+
+/// Event sent to the subscribers of the nft
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct KeyshareEvent {
+	pub kind: KeyshareEventKind,
+	pub nft_id: u32,
+	pub nft_type: NftType,
+	// Block number of the keyshare, 0 for a capsule which is not synced yet
+	pub block_number: u32,
+}
+
+fn keyshare_events() -> &'static broadcast::Sender<KeyshareEvent> {
+	KEYSHARE_EVENTS.get_or_init(|| broadcast::channel(KEYSHARE_EVENT_BUFFER).0)
+}
+
+/// Send a keyshare event to the subscribers, nothing is done without subscriber
+/// # Arguments
+/// * `kind` - change of the availability
+/// * `nft_id` - nft of the keyshare
+/// * `nft_type` - secret, capsule or hybrid
+/// * `block_number` - block number of the keyshare
+pub fn publish_keyshare_event(
+	kind: KeyshareEventKind,
+	nft_id: u32,
+	nft_type: NftType,
+	block_number: u32,
+) {
+	let sender = keyshare_events();
+	if sender.receiver_count() == 0 {
+		return
+	}
+
+	// Fails only if the last subscriber is gone meanwhile
+	let _ = sender.send(KeyshareEvent { kind, nft_id, nft_type, block_number });
+}
+
+/* ---------------------------------------
+	SUBSCRIPTION
+--------------------------------------- */
+
+/// First message of the websocket, signed by the subscriber
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SubscriptionPacket {
+	subscriber_address: String,
+	nft_ids: Vec<u32>,
+	// Block of the signature, the packet is valid during MAX_VALIDATION_PERIOD blocks
+	block_number: u32,
+	// Signature of subscription_message
+	signature: String,
+}
+
+/// Message signed by the subscriber
+/// # Arguments
+/// * `nft_ids` - subscribed nfts, in the order of the packet
+/// * `block_number` - block of the signature
+pub fn subscription_message(nft_ids: &[u32], block_number: u32) -> String {
+	let nft_ids = nft_ids.iter().map(|nft_id| nft_id.to_string()).collect::<Vec<_>>().join(",");
+	format!("keyshare-subscription_{nft_ids}_{block_number}")
+}
+
+impl SubscriptionPacket {
+	fn is_valid(&self, current_block_number: u32) -> ValidationResult {
+		if self.block_number > current_block_number + MAX_BLOCK_VARIATION {
+			return ValidationResult::FutureBlockNumber
+		}
+
+		if self.block_number + MAX_VALIDATION_PERIOD < current_block_number {
+			return ValidationResult::ExpiredBlockNumber
+		}
+
+		ValidationResult::Success
+	}
+
+	/// Verify the subscription packet
+	/// # Returns
+	/// * `BTreeSet<u32>` - subscribed nft ids
+	fn verify(&self, current_block_number: u32) -> Result<BTreeSet<u32>, String> {
+		if self.nft_ids.is_empty() || self.nft_ids.len() > MAX_SUBSCRIBED_NFTS {
+			return Err(format!("between 1 and {MAX_SUBSCRIBED_NFTS} nft ids can be subscribed"))
+		}
+
+		let validation = self.is_valid(current_block_number);
+		if !matches!(validation, ValidationResult::Success) {
+			return Err(format!("subscription block number is not valid : {validation:?}"))
+		}
+
+		let message = subscription_message(&self.nft_ids, self.block_number);
+		if !verify_signature(&self.subscriber_address, self.signature.clone(), message.as_bytes()) {
+			return Err("invalid subscription signature".to_string())
+		}
+
+		Ok(self.nft_ids.iter().copied().collect())
+	}
+}
+
+/// Held by an open subscription, released when the websocket is closed
+struct SubscriptionSlot;
+
+impl SubscriptionSlot {
+	fn acquire() -> Option<Self> {
+		let previous = SUBSCRIPTIONS.fetch_add(1, Ordering::SeqCst);
+		if previous >= MAX_EVENT_SUBSCRIPTIONS {
+			SUBSCRIPTIONS.fetch_sub(1, Ordering::SeqCst);
+			return None
+		}
+
+		Some(SubscriptionSlot)
+	}
+}
+
+impl Drop for SubscriptionSlot {
+	fn drop(&mut self) {
+		SUBSCRIPTIONS.fetch_sub(1, Ordering::SeqCst);
+	}
+}
+
+/* ---------------------------------------
+	WEBSOCKET API
+--------------------------------------- */
+
+/// Websocket of keyshare events, the first message must be a signed SubscriptionPacket
+pub async fn keyshare_events_socket(
+	State(state): State<SharedState>,
+	upgrade: WebSocketUpgrade,
+) -> Response {
+	let slot = match SubscriptionSlot::acquire() {
+		Some(slot) => slot,
+		None => {
+			warn!("KEYSHARE EVENTS : too many subscriptions");
+			return (
+				StatusCode::SERVICE_UNAVAILABLE,
+				Json(json!({
+					"description": format!("Maximum of {MAX_EVENT_SUBSCRIPTIONS} subscriptions is reached, try again later"),
+				})),
+			)
+				.into_response()
+		},
+	};
+
+	upgrade.on_upgrade(move |socket| serve_subscription(state, socket, slot))
+}
+
+async fn send_json(socket: &mut WebSocket, value: serde_json::Value) -> bool {
+	socket.send(Message::Text(value.to_string())).await.is_ok()
+}
+
+async fn serve_subscription(state: SharedState, mut socket: WebSocket, _slot: SubscriptionSlot) {
+	let packet =
+		match tokio::time::timeout(Duration::from_secs(SUBSCRIPTION_AUTH_TIMEOUT), socket.recv())
+			.await
+		{
+			Ok(Some(Ok(Message::Text(packet)))) => packet,
+			_ => {
+				debug!("KEYSHARE EVENTS : no subscription packet");
+				let _ =
+					send_json(&mut socket, json!({ "error": "subscription packet is expected" }))
+						.await;
+				let _ = socket.send(Message::Close(None)).await;
+				return
+			},
+		};
+
+	let current_block_number = get_blocknumber(&state).await;
+	let subscription = serde_json::from_str::<SubscriptionPacket>(&packet)
+		.map_err(|err| format!("subscription packet is not parsable : {err}"))
+		.and_then(|packet| {
+			packet
+				.verify(current_block_number)
+				.map(|nft_ids| (packet.subscriber_address, nft_ids))
+		});
+
+	let (subscriber, nft_ids) = match subscription {
+		Ok(subscription) => subscription,
+		Err(message) => {
+			warn!("KEYSHARE EVENTS : subscription is rejected : {message}");
+			let _ = send_json(&mut socket, json!({ "error": message })).await;
+			let _ = socket.send(Message::Close(None)).await;
+			return
+		},
+	};
+
+	let mut events = keyshare_events().subscribe();
+	info!("KEYSHARE EVENTS : {subscriber} subscribed to {} nfts", nft_ids.len());
+	if !send_json(&mut socket, json!({ "subscribed": nft_ids })).await {
+		return
+	}
+
+	let mut shutdown_check = tokio::time::interval(Duration::from_secs(1));
+	loop {
+		tokio::select! {
+			event = events.recv() => match event {
+				Ok(event) if nft_ids.contains(&event.nft_id) => {
+					if !send_json(&mut socket, json!({ "event": event })).await {
+						break
+					}
+				},
+				Ok(_) => continue,
+				Err(RecvError::Lagged(missed)) => {
+					debug!("KEYSHARE EVENTS : {subscriber} missed {missed} events");
+					if !send_json(&mut socket, json!({ "lagged": missed })).await {
+						break
+					}
+				},
+				Err(RecvError::Closed) => break,
+			},
+			// Pings are answered by the websocket itself
+			message = socket.recv() => match message {
+				Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+				Some(Ok(_)) => continue,
+			},
+			_ = shutdown_check.tick() => if is_shutting_down() {
+				let _ = socket.send(Message::Close(None)).await;
+				break
+			},
+		}
+	}
+
+	debug!("KEYSHARE EVENTS : {subscriber} subscription is closed");
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn subscription_test() {
+		assert_eq!(subscription_message(&[12, 7], 1000), "keyshare-subscription_12,7_1000");
+
+		let packet = SubscriptionPacket {
+			subscriber_address: "5C4hrfjw9DjXZTzV3MwzrrAr9P1MJhSrvWGWqi1eSuyUpnhM".to_string(),
+			nft_ids: vec![12],
+			block_number: 1000,
+			signature: "0x00".to_string(),
+		};
+		assert!(matches!(packet.is_valid(1000 + MAX_VALIDATION_PERIOD), ValidationResult::Success));
+		assert!(matches!(
+			packet.is_valid(1001 + MAX_VALIDATION_PERIOD),
+			ValidationResult::ExpiredBlockNumber
+		));
+		assert!(matches!(packet.is_valid(900), ValidationResult::FutureBlockNumber));
+		assert!(packet.verify(1000).is_err());
+	}
+
+	#[tokio::test]
+	async fn publish_test() {
+		let mut events = keyshare_events().subscribe();
+		publish_keyshare_event(KeyshareEventKind::SYNCED, 12, NftType::Capsule, 1000);
+
+		let event = events.recv().await.unwrap();
+		assert_eq!(event.kind, KeyshareEventKind::SYNCED);
+		assert_eq!(event.nft_id, 12);
+	}
+}
@@ -0,0 +1,483 @@
+use std::{
+	collections::BTreeMap,
+	path::Path,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{debug, error};
+
+use crate::{
+	attestation::{
+		ra::last_quote,
+		watchdog::{tcb_health, TcbHealth},
+	},
+	backup::{schedule::LastBackup, sync::get_sync_state},
+	chain::{
+		constants::{
+			ATTESTATION_FRESHNESS, HEALTH_CHAIN_TIMEOUT, HEALTH_SYNC_LAG, LIVENESS_LOCK_TIMEOUT,
+			SEALPATH,
+		},
+		killswitch::MaintenanceMode,
+	},
+	servers::{
+		shutdown::is_shutting_down,
+		state::{
+			get_block_updated, get_blocknumber, get_identity, get_maintenance,
+			get_maintenance_mode, get_maintenance_window, get_nft_availability_map_len,
+			get_processed_block, get_readiness, SharedState,
+		},
+		supervisor::TaskStatus,
+	},
+};
+
+/* ---------------------------------------
+	HEALTH STATUS
+--------------------------------------- */
+
+// The HTTP status of /api/health is kept for the cluster synchronization, load balancers can read
+// the machine-readable `status` instead of interpreting the codes and the description.
+
+/// Overall status of the enclave
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HealthStatus {
+	// Serving, synchronized and connected to the chain
+	HEALTHY,
+	// Serving, but the last quote generation or scheduled backup failed, or the platform TCB is
+	// out of date
+	DEGRADED,
+	// Not yet synchronized with the cluster, or crawling missed blocks
+	SYNCING,
+	// Maintenance message or governance kill-switch
+	MAINTENANCE,
+	// Chain is disconnected, sealed storage is unavailable or sync state is corrupted
+	UNHEALTHY,
+	// Enclaves of previous versions do not report a status
+	#[default]
+	UNKNOWN,
+}
+
+/// Chain connectivity, from the finalized block subscription
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ChainHealth {
+	pub connected: bool,
+	pub head_block: u32,
+	// Seconds since the last finalized block, None before the first one
+	pub seconds_since_block: Option<u64>,
+}
+
+/// Synchronization with the cluster
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SyncHealth {
+	pub processed_block: u32,
+	pub lag_blocks: u32,
+	// Registered (cluster, slot) of the enclave, None if it is not registered
+	pub identity: Option<(u32, u32)>,
+}
+
+/// Sealed storage of the keyshares
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct StorageHealth {
+	pub keyshares: u32,
+	pub sealed_available: bool,
+}
+
+/// Freshness of the last quote generation
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AttestationHealth {
+	// None if no quote was requested since the start
+	pub last_quote_block: Option<u32>,
+	pub age_blocks: Option<u32>,
+	pub fresh: bool,
+	pub error: Option<String>,
+	// TCB of the platform from the quote watchdog, None before its first refresh
+	#[serde(default)]
+	pub tcb: Option<TcbHealth>,
+}
+
+/// Detailed checks of the health report
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HealthChecks {
+	pub chain: ChainHealth,
+	pub sync: SyncHealth,
+	pub storage: StorageHealth,
+	pub attestation: AttestationHealth,
+}
+
+/// Collect the health checks of the enclave
+pub async fn health_checks(state: &SharedState) -> HealthChecks {
+	let head_block = get_blocknumber(state).await;
+	let seconds_since_block =
+		get_block_updated(state).await.map(|updated| updated.elapsed().as_secs());
+	let processed_block = get_processed_block(state).await;
+
+	let attestation = match last_quote() {
+		Some(quote) => {
+			let age_blocks = head_block.saturating_sub(quote.block_number);
+			AttestationHealth {
+				last_quote_block: Some(quote.block_number),
+				age_blocks: Some(age_blocks),
+				fresh: quote.error.is_none() && age_blocks <= ATTESTATION_FRESHNESS,
+				error: quote.error,
+				tcb: tcb_health(),
+			}
+		},
+		None => AttestationHealth {
+			last_quote_block: None,
+			age_blocks: None,
+			fresh: false,
+			error: None,
+			tcb: tcb_health(),
+		},
+	};
+
+	HealthChecks {
+		chain: ChainHealth {
+			connected: matches!(seconds_since_block, Some(seconds) if seconds <= HEALTH_CHAIN_TIMEOUT),
+			head_block,
+			seconds_since_block,
+		},
+		sync: SyncHealth {
+			processed_block,
+			lag_blocks: head_block.saturating_sub(processed_block),
+			identity: get_identity(state).await,
+		},
+		storage: StorageHealth {
+			keyshares: get_nft_availability_map_len(state).await,
+			sealed_available: Path::new(SEALPATH).is_dir(),
+		},
+		attestation,
+	}
+}
+
+/// Maintenance message of the operator or governance kill-switch, empty in normal mode
+pub async fn maintenance_message(state: &SharedState) -> String {
+	match get_maintenance_mode(state).await {
+		MaintenanceMode::NORMAL => {
+			let message = get_maintenance(state).await;
+			match get_maintenance_window(state).await {
+				Some(window) if message.is_empty() =>
+					format!("Maintenance window : {}", window.message),
+				_ => message,
+			}
+		},
+		mode => format!("Governance kill-switch : {mode:?}"),
+	}
+}
+
+/// Overall status of the health checks
+/// # Arguments
+/// * `checks` - detailed health checks
+/// * `maintenance` - maintenance message or kill-switch, empty in normal mode
+/// * `sync_state` - content of the sync state, a block number once synchronized
+/// * `last_backup` - last scheduled backup
+pub fn overall_status(
+	checks: &HealthChecks,
+	maintenance: &str,
+	sync_state: &str,
+	last_backup: Option<&LastBackup>,
+) -> HealthStatus {
+	if !maintenance.is_empty() {
+		return HealthStatus::MAINTENANCE
+	}
+
+	if !checks.chain.connected || !checks.storage.sealed_available {
+		return HealthStatus::UNHEALTHY
+	}
+
+	match sync_state {
+		"" | "setup" => return HealthStatus::SYNCING,
+		state if state.parse::<u32>().is_err() => return HealthStatus::UNHEALTHY,
+		_ => {},
+	}
+
+	if checks.sync.lag_blocks > HEALTH_SYNC_LAG {
+		return HealthStatus::SYNCING
+	}
+
+	let backup_failed = last_backup.map_or(false, |backup| backup.error.is_some());
+	let tcb_unacceptable =
+		checks.attestation.tcb.as_ref().map_or(false, |tcb| tcb.is_unacceptable());
+	if checks.attestation.error.is_some() || tcb_unacceptable || backup_failed {
+		return HealthStatus::DEGRADED
+	}
+
+	HealthStatus::HEALTHY
+}
+
+/* ---------------------------------------
+	READINESS AND LIVENESS
+--------------------------------------- */
+
+// Orchestrators probe the enclave twice :
+// - /api/live fails only if the process can not recover by itself, the container is restarted
+// - /api/ready fails while the enclave should not receive keyshare requests, it is taken out of the
+//   load balancer until it is ready again
+// The sync and backup subsystems hold the readiness while they fetch or restore keyshares.
+
+/// Reason of an enclave which is not ready
+#[allow(non_camel_case_types)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ReadinessBlocker {
+	// No finalized block since HEALTH_CHAIN_TIMEOUT seconds
+	CHAIN_DISCONNECTED,
+	// Sealed directory is not mounted
+	STORAGE_UNAVAILABLE,
+	// Not yet synchronized with the cluster, crawling missed blocks or fetching keyshares
+	SYNCING,
+	// Keyshares are restored from a backup
+	RESTORING,
+	// Maintenance message or governance kill-switch
+	MAINTENANCE,
+	// In-flight requests are drained before the exit
+	SHUTTING_DOWN,
+}
+
+/// Readiness holds of the subsystems, shared by the SharedState
+#[derive(Clone, Default)]
+pub struct Readiness {
+	holds: Arc<Mutex<BTreeMap<ReadinessBlocker, usize>>>,
+}
+
+/// Held by a subsystem while the enclave is not ready, released when it is dropped
+pub struct ReadinessHold {
+	readiness: Readiness,
+	blocker: ReadinessBlocker,
+}
+
+impl Drop for ReadinessHold {
+	fn drop(&mut self) {
+		match self.readiness.holds.lock() {
+			Ok(mut holds) =>
+				if let Some(count) = holds.get_mut(&self.blocker) {
+					*count -= 1;
+					if *count == 0 {
+						holds.remove(&self.blocker);
+						debug!("READINESS : {:?} is released", self.blocker);
+					}
+				},
+			Err(err) => error!("READINESS : lock error : {err:?}"),
+		}
+	}
+}
+
+impl Readiness {
+	/// Hold the readiness until the returned guard is dropped
+	/// # Arguments
+	/// * `blocker` - reason reported by the readiness probe
+	pub fn hold(&self, blocker: ReadinessBlocker) -> ReadinessHold {
+		match self.holds.lock() {
+			Ok(mut holds) => *holds.entry(blocker).or_insert(0) += 1,
+			Err(err) => error!("READINESS : lock error : {err:?}"),
+		}
+		debug!("READINESS : {blocker:?} is held");
+
+		ReadinessHold { readiness: self.clone(), blocker }
+	}
+
+	/// Blockers held by the subsystems
+	pub fn holds(&self) -> Vec<ReadinessBlocker> {
+		self.holds
+			.lock()
+			.map(|holds| holds.keys().copied().collect())
+			.unwrap_or_default()
+	}
+}
+
+/// Reasons of an enclave which is not ready, empty if it is ready
+/// # Arguments
+/// * `checks` - detailed health checks
+/// * `maintenance` - maintenance message or kill-switch, empty in normal mode
+/// * `sync_state` - content of the sync state, a block number once synchronized
+/// * `holds` - blockers held by the subsystems
+pub fn readiness_blockers(
+	checks: &HealthChecks,
+	maintenance: &str,
+	sync_state: &str,
+	holds: &[ReadinessBlocker],
+) -> Vec<ReadinessBlocker> {
+	let mut blockers = holds.to_vec();
+
+	if !checks.chain.connected {
+		blockers.push(ReadinessBlocker::CHAIN_DISCONNECTED);
+	}
+
+	if !checks.storage.sealed_available {
+		blockers.push(ReadinessBlocker::STORAGE_UNAVAILABLE);
+	}
+
+	if sync_state.parse::<u32>().is_err() || checks.sync.lag_blocks > HEALTH_SYNC_LAG {
+		blockers.push(ReadinessBlocker::SYNCING);
+	}
+
+	if !maintenance.is_empty() {
+		blockers.push(ReadinessBlocker::MAINTENANCE);
+	}
+
+	blockers.sort();
+	blockers.dedup();
+	blockers
+}
+
+/// Readiness probe, 503 Service Unavailable with the blockers while the enclave is not ready
+pub async fn readiness_probe(State(state): State<SharedState>) -> impl IntoResponse {
+	let checks = health_checks(&state).await;
+	let maintenance = maintenance_message(&state).await;
+	// An unreadable sync state is reported as syncing
+	let sync_state = get_sync_state().unwrap_or_default();
+	let holds = get_readiness(&state).await.holds();
+
+	let mut blockers = readiness_blockers(&checks, &maintenance, &sync_state, &holds);
+	if is_shutting_down() {
+		blockers.push(ReadinessBlocker::SHUTTING_DOWN);
+	}
+
+	let status = if blockers.is_empty() { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+
+	(status, Json(json!({ "ready": blockers.is_empty(), "blockers": blockers })))
+}
+
+/// Liveness probe, 503 Service Unavailable if the shared state is deadlocked or a supervised task
+/// exhausted its restart policy
+pub async fn liveness_probe(State(state): State<SharedState>) -> impl IntoResponse {
+	let registry = match tokio::time::timeout(
+		Duration::from_secs(LIVENESS_LOCK_TIMEOUT),
+		state.read(),
+	)
+	.await
+	{
+		Ok(shared_state_read) => shared_state_read.get_task_registry(),
+		Err(_) => {
+			error!("LIVENESS : shared state is locked for {LIVENESS_LOCK_TIMEOUT} seconds");
+			return (
+				StatusCode::SERVICE_UNAVAILABLE,
+				Json(json!({ "alive": false, "description": "Shared state is locked" })),
+			)
+		},
+	};
+
+	let failed_tasks: Vec<String> = registry
+		.read()
+		.await
+		.values()
+		.filter(|task| task.status == TaskStatus::FAILED)
+		.map(|task| task.name.clone())
+		.collect();
+
+	if !failed_tasks.is_empty() {
+		error!("LIVENESS : supervised tasks are failed : {failed_tasks:?}");
+		return (
+			StatusCode::SERVICE_UNAVAILABLE,
+			Json(json!({ "alive": false, "failed_tasks": failed_tasks })),
+		)
+	}
+
+	(StatusCode::OK, Json(json!({ "alive": true })))
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::attestation::verifier::TcbStatus;
+
+	fn healthy_checks() -> HealthChecks {
+		HealthChecks {
+			chain: ChainHealth { connected: true, head_block: 1000, seconds_since_block: Some(3) },
+			sync: SyncHealth { processed_block: 999, lag_blocks: 1, identity: Some((0, 1)) },
+			storage: StorageHealth { keyshares: 10, sealed_available: true },
+			attestation: AttestationHealth {
+				last_quote_block: None,
+				age_blocks: None,
+				fresh: false,
+				error: None,
+				tcb: None,
+			},
+		}
+	}
+
+	#[test]
+	fn overall_status_test() {
+		let checks = healthy_checks();
+		assert_eq!(overall_status(&checks, "", "900", None), HealthStatus::HEALTHY);
+		assert_eq!(overall_status(&checks, "upgrade", "900", None), HealthStatus::MAINTENANCE);
+		assert_eq!(overall_status(&checks, "", "setup", None), HealthStatus::SYNCING);
+		assert_eq!(overall_status(&checks, "", "corrupted", None), HealthStatus::UNHEALTHY);
+
+		let mut lagging = healthy_checks();
+		lagging.sync.lag_blocks = HEALTH_SYNC_LAG + 1;
+		assert_eq!(overall_status(&lagging, "", "900", None), HealthStatus::SYNCING);
+
+		let tcb = TcbHealth {
+			checked_block: 1000,
+			fingerprint: "ab".repeat(32),
+			tcb_status: Some(TcbStatus::SWHARDENINGNEEDED),
+			advisory_ids: vec!["INTEL-SA-00615".to_string()],
+			error: None,
+			changed_block: None,
+			reregistration_pending: false,
+		};
+		let mut hardening = healthy_checks();
+		hardening.attestation.tcb = Some(tcb.clone());
+		assert_eq!(overall_status(&hardening, "", "900", None), HealthStatus::HEALTHY);
+		hardening.attestation.tcb =
+			Some(TcbHealth { tcb_status: Some(TcbStatus::OUTOFDATE), ..tcb });
+		assert_eq!(overall_status(&hardening, "", "900", None), HealthStatus::DEGRADED);
+
+		let mut disconnected = healthy_checks();
+		disconnected.chain.connected = false;
+		assert_eq!(overall_status(&disconnected, "", "900", None), HealthStatus::UNHEALTHY);
+
+		let failed_backup = LastBackup {
+			block_number: 900,
+			file: None,
+			files: 0,
+			size: 0,
+			sha256: None,
+			error: Some("sink is full".to_string()),
+		};
+		assert_eq!(
+			overall_status(&checks, "", "900", Some(&failed_backup)),
+			HealthStatus::DEGRADED
+		);
+
+		// Load balancers match the variant name
+		assert_eq!(serde_json::to_string(&HealthStatus::SYNCING).unwrap(), "\"SYNCING\"");
+	}
+
+	#[test]
+	fn readiness_test() {
+		let checks = healthy_checks();
+		assert!(readiness_blockers(&checks, "", "900", &[]).is_empty());
+		assert_eq!(readiness_blockers(&checks, "", "setup", &[]), vec![ReadinessBlocker::SYNCING]);
+
+		let mut disconnected = healthy_checks();
+		disconnected.chain.connected = false;
+		assert_eq!(
+			readiness_blockers(&disconnected, "upgrade", "900", &[]),
+			vec![ReadinessBlocker::CHAIN_DISCONNECTED, ReadinessBlocker::MAINTENANCE]
+		);
+
+		// Holds are released once every holder is dropped
+		let readiness = Readiness::default();
+		let restore = readiness.hold(ReadinessBlocker::RESTORING);
+		let sync = readiness.hold(ReadinessBlocker::SYNCING);
+		let other_sync = readiness.hold(ReadinessBlocker::SYNCING);
+		assert_eq!(
+			readiness_blockers(&checks, "", "900", &readiness.holds()),
+			vec![ReadinessBlocker::SYNCING, ReadinessBlocker::RESTORING]
+		);
+
+		drop(restore);
+		drop(sync);
+		assert_eq!(readiness.holds(), vec![ReadinessBlocker::SYNCING]);
+		drop(other_sync);
+		assert!(readiness.holds().is_empty());
+	}
+}
@@ -18,61 +18,151 @@ use axum::{
 	error_handling::HandleErrorLayer,
 	extract::{DefaultBodyLimit, State},
 	http::{Method, StatusCode, Uri},
+	middleware,
 	response::IntoResponse,
-	routing::{get, post},
+	routing::{get, post, put},
 	BoxError, Json, Router,
 };
 
 use reqwest;
 
-use subxt::ext::sp_core::{sr25519, Pair};
+use subxt::ext::sp_core::{crypto::Ss58Codec, sr25519, Pair};
 
 use tower::ServiceBuilder;
-use tower_http::{
-	cors::{Any, CorsLayer},
-	limit::RequestBodyLimitLayer,
-};
+use tower_http::limit::RequestBodyLimitLayer;
 
 use anyhow::{anyhow, Error};
 use serde_json::{json, Value};
 use tracing::{debug, error, info, trace, warn};
 
 use crate::{
-	attestation::ra::ra_get_quote,
+	attestation::{
+		account::{
+			admin_account_rotate, admin_account_rotate_complete, admin_account_rotations,
+			load_enclave_account,
+		},
+		allowlist::{
+			self, admin_allowlist_status, admin_allowlist_update, load_allowlist,
+			measurement_allowlist,
+		},
+		attest::attest,
+		backend::attestation_backend,
+		collateral::{collateral_cache_config, load_collateral_cache},
+		environment::{enclave_environment, environment_guard, get_environment},
+		history::{attestation_history, load_attestation_history},
+		identity::enclave_identity,
+		inspect::attest_inspect,
+		keys::{derive_subkey, KeyPurpose},
+		ra::{api_url, gramine_mode, local_mrsigner, ra_get_quote},
+		ratls::ra_tls_certificate,
+		selftest::{attest_self_test, run_self_test, self_test_policy},
+		verifier::verifier_config,
+		watchdog::{admin_quote_reregister, quote_refresh_interval, quote_watchdog},
+	},
 	backup::{
 		admin_nftid::admin_backup_push_id,
-		metric::{metric_reconcilliation, set_crawl_block},
+		audit::admin_audit_export,
+		inventory::{
+			admin_compare_peer, admin_consistency_check, sync_inventory, sync_storage_root,
+		},
+		maintenance::{
+			admin_maintenance_status, admin_maintenance_switch, load_maintenance_window,
+			maintenance_guard,
+		},
+		metric::{
+			metric_compression, metric_negative_cache, metric_quota, metric_reconcilliation,
+			metric_reconcilliation_status, metric_resource_consumers, metric_resources,
+			set_crawl_block,
+		},
+		provision::{admin_provision_register, admin_provision_report, load_provision_windows},
+		quorum::{
+			activate_pending_quorum, admin_quorum_cancel, admin_quorum_rotate, admin_quorum_status,
+			load_quorum,
+		},
+		readonly::{admin_readonly_status, admin_readonly_switch, load_read_only, read_only_until},
 		sync::{
-			cluster_discovery, crawl_sync_events, fetch_keyshares, get_sync_state,
-			parse_block_body, set_sync_state, sync_keyshares, SyncedNFT,
+			cluster_discovery, crawl_sync_events, fetch_keyshares, flush_sync_state,
+			get_sync_state, parse_block_body, set_sync_state, sync_keyshares, SyncedNFT,
+		},
+		upgrade::admin_upgrade_arm,
+		upload::{
+			admin_upload_finalize, admin_upload_init, admin_upload_part, admin_upload_status,
 		},
 	},
 	chain::{
+		audit::load_audit_head,
 		capsule::{
 			capsule_get_views, capsule_remove_keyshare, capsule_retrieve_keyshare,
-			capsule_set_keyshare, is_capsule_available,
+			capsule_set_keyshare, capsule_update_keyshare, is_capsule_available,
 		},
+		commitment::storage_proof,
 		constants::{
-			CONTENT_LENGTH_LIMIT, ENCLAVE_ACCOUNT_FILE, RETRY_COUNT, RETRY_DELAY, SEALPATH,
-			SYNC_STATE_FILE, VERSION,
+			INTEGRITY_LOG_FILE, KEYSHARE_INDEX_FILE, MAX_SUBSCRIBED_NFTS, RETRY_COUNT, RETRY_DELAY,
+			SEALPATH, SHUTDOWN_BACKUP_WAIT, SYNC_STATE_FILE, VERSION, WORKSPACE_PATH,
 		},
-		core::create_chain_api,
-		helper,
+		core::{create_chain_api, create_chain_api_from_url, DefaultApi},
+		cosign::cosign_policy,
+		heartbeat, helper, integrity,
+		jws::{REQUEST_VERSION_BINARY, REQUEST_VERSION_JWS, REQUEST_VERSION_LEGACY},
+		killswitch::{self, killswitch_guard, load_maintenance_mode},
+		negative_cache,
 		nft::{
-			is_nft_available, nft_get_views, nft_remove_keyshare, nft_retrieve_keyshare,
-			nft_store_keyshare,
+			is_nft_available, nft_batch_retrieve_keyshare, nft_batch_store_keyshare, nft_get_views,
+			nft_remove_keyshare, nft_retrieve_keyshare, nft_store_keyshare,
 		},
+		policy::keyshare_policy,
+		precheck::access_check,
+		quota::{ban_policy, ip_guard, ip_rate_limit, quota_guard},
+		replay::load_replay_journal,
+		requester::requester_registry,
+		signature::SignatureScheme,
 	},
-	servers::state::{
-		get_accountid, get_blocknumber, get_identity, get_maintenance,
-		get_nft_availability_map_len, get_nonce, get_processed_block, get_version, reset_nonce,
-		set_blocknumber, set_processed_block, SharedState, StateConfig,
+	servers::{
+		auth::auth_guard,
+		compression::{compression_config, compression_guard, compression_layer},
+		connection::connection_config,
+		coordination::coordination_guard,
+		correlation::correlation_guard,
+		cors::{cors_config, cors_layer},
+		etag::etag_guard,
+		events::keyshare_events_socket,
+		health::{
+			health_checks, liveness_probe, maintenance_message, overall_status, readiness_probe,
+			HealthChecks, HealthStatus,
+		},
+		limits::{body_limit_guard, body_limits_view},
+		logging::{admin_log_level_status, admin_log_level_update, log_directives},
+		metrics::{metrics_guard, prometheus_metrics, record_rpc_error},
+		padding::{padding_guard, response_padding},
+		proxy::connectivity_selftest,
+		resources::resource_monitor,
+		runtime::{admin_config_status, admin_config_update},
+		shutdown::{register_shutdown_hook, shutdown_guard},
+		signing::{response_key, signing_guard},
+		state::{
+			get_accountid, get_blocknumber, get_identity, get_maintenance_window,
+			get_nft_availability_map_len, get_nonce, get_processed_block, get_runtime_config,
+			get_subkeys, get_task_registry, get_version, reset_nonce, set_blocknumber,
+			set_compression_threshold, set_processed_block, set_secondary_chain_api, SharedState,
+			StateConfig,
+		},
+		supervisor::{admin_task_status, RestartPolicy, Supervisor},
+		versioning::{api_versions, version_guard, ApiVersion, CURRENT_API_VERSION},
 	},
 };
 
 use crate::backup::{
 	admin_bulk::{admin_backup_fetch_bulk, admin_backup_push_bulk},
 	admin_nftid::admin_backup_fetch_id,
+	admins::{admin_roles, admin_whitelist, is_cluster_refresh_due},
+	container::BackupFormat,
+	encryption::backup_recipient_key,
+	escrow::{admin_escrow_setup, admin_escrow_status, backup_escrow, load_escrow},
+	inject::admin_backup_push_keyshares,
+	jobs::{admin_backup_status, admin_download_token, backup_artifact_download, drain_backups},
+	recovery::{recovery_key_exchange, recovery_recipient, setup_recovery_key},
+	schedule::{self, last_backup, LastBackup},
+	workspace::clean_workspaces,
 };
 
 use sentry::integrations::tower::{NewSentryLayer, SentryHttpLayer};
@@ -80,57 +170,29 @@ use sentry::integrations::tower::{NewSentryLayer, SentryHttpLayer};
 use super::server_common;
 
 /// http server app
-pub async fn http_server() -> Result<Router, Error> {
+/// # Arguments
+/// * `heartbeat_interval` - keyshare availability heartbeat interval in blocks, 0 disables it
+/// * `secondary_rpc` - optional independent rpc endpoint for dual-rpc verification
+/// * `compression_threshold` - minimum keyshare size to be compressed at rest, 0 disables it
+/// # Returns
+/// * `(Router, Supervisor, SharedState)` - the app, its background tasks and its state, which is
+///   restored or exported by an upgrade handoff
+pub async fn http_server(
+	heartbeat_interval: u32,
+	secondary_rpc: Option<String>,
+	compression_threshold: usize,
+	integrity_repair: bool,
+) -> Result<(Router, Supervisor, SharedState), Error> {
 	info!("ENCLAVE START : Generate/Import Enclave Keypair");
 
-	let enclave_keypair = if std::path::Path::new(&ENCLAVE_ACCOUNT_FILE).exists() {
-		info!(
-			"ENCLAVE START : Enclave Account Exists, Importing it! :, path: {}",
-			ENCLAVE_ACCOUNT_FILE
-		);
-
-		let phrase = match std::fs::read_to_string(ENCLAVE_ACCOUNT_FILE) {
-			Ok(phrase) => phrase,
-			Err(err) => {
-				error!("\tENCLAVE START : ERROR reading enclave account file: {err:?}");
-				return Err(anyhow!(err))
-			},
-		};
-
-		match sr25519::Pair::from_phrase(&phrase, None) {
-			Ok((keypair, _seed)) => keypair,
-			Err(err) => {
-				error!("\tENCLAVE START : ERROR creating keypair from phrase: {err:?}");
-				return Err(anyhow!(err))
-			},
-		}
-	} else {
-		info!("ENCLAVE START : Creating new Enclave Account, Remember to send 1 CAPS to it!");
-
-		let (keypair, phrase, _s_seed) = sr25519::Pair::generate_with_phrase(None);
-		let mut ekfile = match File::create(ENCLAVE_ACCOUNT_FILE) {
-			Ok(file_handle) => {
-				debug!("\tENCLAVE START : created enclave keypair file successfully");
-				file_handle
-			},
-			Err(err) => {
-				error!("\tENCLAVE START : Failed to creat enclave keypair file, error : {err:?}");
-				return Err(anyhow!(err))
-			},
-		};
-
-		match ekfile.write_all(phrase.as_bytes()) {
-			Ok(_) => {
-				debug!("\tENCLAVE START : Write enclave keypair to file successfully");
-			},
-			Err(err) => {
-				error!("\tENCLAVE START : Write enclave keypair to file failed, error : {:?}", err);
-				return Err(anyhow!(err))
-			},
-		}
-
-		keypair
+	let enclave_keypair = match load_enclave_account() {
+		Ok(keypair) => keypair,
+		Err(err) => {
+			error!("ENCLAVE START : error loading enclave account : {err:?}");
+			return Err(anyhow!(err))
+		},
 	};
+	info!("ENCLAVE START : enclave account : {}", enclave_keypair.public().to_ss58check());
 
 	let chain_api = match create_chain_api().await {
 		Ok(api) => api,
@@ -149,7 +211,53 @@ pub async fn http_server() -> Result<Router, Error> {
 	let current_block_number = current_block.block.header.number;
 	let last_processed_block = current_block_number;
 
-	let keyshare_list = helper::query_keyshare_file(SEALPATH.to_string())?;
+	// Cross-verify sealed files and view-logs before building the availability index
+	info!("ENCLAVE START : Integrity check of sealed keyshares.");
+	let keyshare_index = helper::take_keyshare_index(KEYSHARE_INDEX_FILE);
+	let keyshare_list = match integrity::check_integrity(SEALPATH, integrity_repair) {
+		Ok(report) => {
+			info!(
+				"ENCLAVE START : INTEGRITY : keyshares = {}, logs = {}, stale = {}, empty = {}, invalid = {}, orphan-logs = {}, repaired = {}",
+				report.keyshare_files,
+				report.log_files,
+				report.stale_keyshares.len(),
+				report.empty_keyshares.len(),
+				report.invalid_files.len(),
+				report.orphan_logs.len(),
+				report.repaired.len()
+			);
+
+			if report.discrepancies() > 0 {
+				warn!("ENCLAVE START : INTEGRITY : discrepancies detected : {:?}", report);
+				let audit_key = derive_subkey(&enclave_keypair, KeyPurpose::AUDITLOG);
+				if let Err(err) = integrity::write_repair_log(
+					INTEGRITY_LOG_FILE,
+					current_block_number,
+					&report,
+					&audit_key,
+				) {
+					error!("ENCLAVE START : INTEGRITY : error writing repair log : {err:?}");
+				}
+			}
+
+			// The index of the last clean shutdown is trusted if it describes the same files
+			match keyshare_index {
+				Some(index)
+					if report.discrepancies() == 0 &&
+						helper::keyshare_file_count(&index) ==
+							report.keyshare_files as usize =>
+				{
+					info!("ENCLAVE START : {} keyshares are loaded from the index", index.len());
+					index
+				},
+				_ => helper::query_keyshare_file(SEALPATH.to_string())?,
+			}
+		},
+		Err(err) => {
+			error!("ENCLAVE START : INTEGRITY : error checking sealed directory : {err:?}");
+			return Err(anyhow!(err))
+		},
+	};
 
 	// Shared-State between APIs
 	let state_config: SharedState = Arc::new(RwLock::new(StateConfig::new(
@@ -163,6 +271,90 @@ pub async fn http_server() -> Result<Router, Error> {
 
 	set_blocknumber(&state_config, current_block_number).await;
 	set_processed_block(&state_config, last_processed_block).await;
+	set_compression_threshold(&state_config, compression_threshold).await;
+
+	if let Err(err) = load_replay_journal(&state_config, current_block_number).await {
+		error!("ENCLAVE START : error loading replay journal : {err:?}");
+		return Err(anyhow!(err))
+	}
+
+	if let Err(err) = load_audit_head(&state_config).await {
+		error!("ENCLAVE START : error loading audit log : {err:?}");
+		return Err(anyhow!(err))
+	}
+
+	// Dual-RPC verification mode
+	if let Some(rpc_endpoint) = secondary_rpc {
+		match create_chain_api_from_url(&rpc_endpoint).await {
+			Ok(api) => {
+				set_secondary_chain_api(&state_config, Some(api)).await;
+				info!("ENCLAVE START : dual-rpc verification mode enabled : {}", rpc_endpoint);
+			},
+			Err(err) => {
+				error!("ENCLAVE START : get secondary chain api, error : {err:?}");
+				return Err(anyhow!(err))
+			},
+		}
+	}
+
+	if let Err(err) = load_quorum(&state_config).await {
+		error!("ENCLAVE START : error loading admin quorum file : {err:?}");
+		return Err(anyhow!(err))
+	}
+
+	if let Err(err) = load_provision_windows(&state_config).await {
+		error!("ENCLAVE START : error loading provision file : {err:?}");
+		return Err(anyhow!(err))
+	}
+
+	if let Err(err) = load_maintenance_mode(&state_config).await {
+		error!("ENCLAVE START : error loading kill-switch file : {err:?}");
+		return Err(anyhow!(err))
+	}
+
+	if let Err(err) = load_read_only(&state_config).await {
+		error!("ENCLAVE START : error loading read-only file : {err:?}");
+		return Err(anyhow!(err))
+	}
+
+	if let Err(err) = load_maintenance_window(&state_config).await {
+		error!("ENCLAVE START : error loading maintenance file : {err:?}");
+		return Err(anyhow!(err))
+	}
+
+	if let Err(err) = load_allowlist() {
+		error!("ENCLAVE START : error loading measurement allowlist file : {err:?}");
+		return Err(anyhow!(err))
+	}
+
+	if let Err(err) = load_escrow() {
+		error!("ENCLAVE START : error loading backup escrow file : {err:?}");
+		return Err(anyhow!(err))
+	}
+
+	if let Err(err) = load_collateral_cache() {
+		error!("ENCLAVE START : error loading collateral cache : {err:?}");
+		return Err(anyhow!(err))
+	}
+
+	debug!("ENCLAVE START : environment {:?}", enclave_environment());
+
+	if let Err(err) = load_attestation_history() {
+		error!("ENCLAVE START : error loading attestation history : {err:?}");
+		return Err(anyhow!(err))
+	}
+
+	// Before the keyshares are fetched, an enclave which can not prove its identity does not serve
+	if let Err(err) = run_self_test(&state_config).await {
+		error!("ENCLAVE START : attestation self-test failed : {err:?}");
+		return Err(anyhow!(err))
+	}
+
+	// Archives and staging directories left by a crash
+	let stale_workspaces = clean_workspaces(std::path::Path::new(WORKSPACE_PATH));
+	if stale_workspaces > 0 {
+		warn!("ENCLAVE START : removed {stale_workspaces} stale backup workspaces");
+	}
 
 	// Get all cluster and registered enclaves from the chain
 	// Also checks if this enclave has been registered.
@@ -170,11 +362,16 @@ pub async fn http_server() -> Result<Router, Error> {
 	while let Err(err) = cluster_discovery(&state_config.clone()).await {
 		error!("ENCLAVE START : cluster discovery error : {err:?}");
 		debug!("ENCLAVE START : Retry Cluster Discovery after a delay...");
-		std::thread::sleep(std::time::Duration::from_secs(RETRY_DELAY.into()));
+		tokio::time::sleep(std::time::Duration::from_secs(RETRY_DELAY.into())).await;
 	}
 
 	info!("ENCLAVE START : Cluster Discovery successfull.");
 
+	// Disaster-recovery exports are refused until the key is available, the enclave still serves
+	if let Err(err) = setup_recovery_key(&state_config).await {
+		error!("ENCLAVE START : disaster-recovery key is not available : {err:?}");
+	}
+
 	// Check the previous Sync-State
 	info!("ENCLAVE START : check for sync.state file from previous run ...");
 	if std::path::Path::new(&SYNC_STATE_FILE).exists() {
@@ -219,7 +416,8 @@ pub async fn http_server() -> Result<Router, Error> {
 							// For the primary cluster it should work fine.
 							error!("ENCLAVE START : SETUP-MODE : Error during setup-mode fetch-keyshares : {err:?}");
 							debug!("ENCLAVE START : SETUP-MODE : waiting before retry");
-							std::thread::sleep(std::time::Duration::from_secs(RETRY_DELAY.into()));
+							tokio::time::sleep(std::time::Duration::from_secs(RETRY_DELAY.into()))
+								.await;
 						},
 					} // FETCH
 				} // RETRY FETCH
@@ -291,9 +489,10 @@ pub async fn http_server() -> Result<Router, Error> {
 											debug!(
 												"ENCLAVE START : CRAWL : FETCH : wait before retry"
 											);
-											std::thread::sleep(std::time::Duration::from_secs(
+											tokio::time::sleep(std::time::Duration::from_secs(
 												RETRY_DELAY.into(),
-											));
+											))
+											.await;
 										},
 									}; // FETCH
 								} // FETCH RETRY
@@ -309,7 +508,8 @@ pub async fn http_server() -> Result<Router, Error> {
 							);
 							// Wait 7 seconds, then retry
 							debug!("ENCLAVE START : CRAWL : wait before retry");
-							std::thread::sleep(std::time::Duration::from_secs(RETRY_DELAY.into()));
+							tokio::time::sleep(std::time::Duration::from_secs(RETRY_DELAY.into()))
+								.await;
 							//return Err(anyhow!(crawl_err));
 						},
 					} // CRAWL
@@ -335,15 +535,6 @@ pub async fn http_server() -> Result<Router, Error> {
 		};
 	};
 
-	info!("ENCLAVE START : define the CORS layer.");
-	let _ = CorsLayer::new()
-		// allow `GET` and `POST` when accessing the resource
-		.allow_methods([Method::GET, Method::POST])
-		// allow requests from any origin
-		.allow_origin(Any)
-		.allow_headers(Any)
-		.allow_credentials(true);
-
 	info!("ENCLAVE START : define the monitor layer : Sentry.");
 	let monitor_layer = ServiceBuilder::new()
 		.layer(NewSentryLayer::new_from_top())
@@ -352,288 +543,508 @@ pub async fn http_server() -> Result<Router, Error> {
 	info!("ENCLAVE START : define the end-points");
 	let http_app = Router::new()
 		.fallback(fallback)
+		// VERSION DISCOVERY
+		.route("/api/versions", get(api_versions))
+		// VERSIONED API, the unversioned paths are aliases of v1
+		.nest("/api/v1", api_routes(&state_config, ApiVersion::V1))
+		.nest("/api/v2", api_routes(&state_config, ApiVersion::V2))
+		.nest("/api", api_routes(&state_config, ApiVersion::V1))
+		// PROMETHEUS METRICS
+		.route("/metrics", get(prometheus_metrics))
+		.layer(
+			ServiceBuilder::new()
+				.layer(HandleErrorLayer::new(handle_timeout_error))
+				.timeout(Duration::from_secs(30)),
+		)
+		// REQUEST CORRELATION ID
+		.layer(middleware::from_fn(correlation_guard))
+		.layer(monitor_layer);
+
+	info!("ENCLAVE START : define the compression layer : {:?}", compression_config());
+	let http_app = match compression_layer() {
+		Some(compression) => http_app.layer(compression),
+		None => http_app,
+	};
+
+	info!("ENCLAVE START : define the CORS layer : {:?}", cors_config());
+	let http_app = match cors_layer() {
+		Some(cors) => http_app.layer(cors),
+		None => http_app,
+	}
+	.with_state(Arc::clone(&state_config.clone()));
+
+	let app_state = state_config.clone();
+
+	info!("ENCLAVE START : Start supervised background tasks.");
+	let mut supervisor = Supervisor::new(get_task_registry(&state_config).await);
+
+	// Open files and task usage, alerts before exhaustion
+	let monitor_state = state_config.clone();
+	supervisor.register(
+		"resource-monitor",
+		&[],
+		RestartPolicy::ALWAYS,
+		Box::new(move || Box::pin(resource_monitor(monitor_state.clone()))),
+	);
+
+	// Periodic quote refresh, TCB status of the platform and announcement of a new quote
+	if quote_refresh_interval() > 0 {
+		let watchdog_state = state_config.clone();
+		supervisor.register(
+			"quote-watchdog",
+			&[],
+			RestartPolicy::ALWAYS,
+			Box::new(move || Box::pin(quote_watchdog(watchdog_state.clone()))),
+		);
+	}
+
+	// Creation events of the best blocks invalidate the cached negative nft lookups
+	let best_block_api = chain_api.clone();
+	supervisor.register(
+		"best-block-subscription",
+		&[],
+		RestartPolicy::ALWAYS,
+		Box::new(move || Box::pin(negative_cache::best_block_subscription(best_block_api.clone()))),
+	);
+
+	// Track latest block, sync and governance events
+	supervisor.register(
+		"block-subscription",
+		&[],
+		RestartPolicy::ALWAYS,
+		Box::new(move || {
+			Box::pin(block_subscription(
+				state_config.clone(),
+				chain_api.clone(),
+				heartbeat_interval,
+			))
+		}),
+	);
+
+	supervisor.start().await?;
+
+	// Subsystems stopped on SIGTERM once the in-flight requests are drained, in reverse order
+	register_shutdown_hook("sync", Box::new(|| Box::pin(async { flush_sync_state() })))?;
+	register_shutdown_hook(
+		"backups",
+		Box::new(|| Box::pin(drain_backups(Duration::from_secs(SHUTDOWN_BACKUP_WAIT)))),
+	)?;
+
+	// debug!("ENCLAVE START : wait 6 seconds to get new block.");
+	// tokio::time::sleep(tokio::time::Duration::from_secs(6)).await;
+
+	Ok((http_app, supervisor, app_state))
+}
+
+/// Api routes of a version, nested under its prefix with the shared state and middlewares
+/// # Arguments
+/// * `state` - SharedState of the middlewares
+/// * `version` - api version of the routes
+fn api_routes(state: &SharedState, version: ApiVersion) -> Router<SharedState> {
+	Router::new()
 		// STATE API
-		.route("/api/health", get(get_health_status))
-		.route("/api/quote", get(ra_get_quote))
+		.route("/health", get(get_health_status))
+		.route("/live", get(liveness_probe))
+		.route("/ready", get(readiness_probe))
+		.route("/quote", get(ra_get_quote))
+		.route("/identity", get(enclave_identity))
+		.route("/attest", post(attest))
+		.route("/attest/history", get(attestation_history))
+		.route("/attest/inspect", post(attest_inspect))
+		.route("/attest/self-test", get(attest_self_test))
+		.route("/capabilities", get(get_capabilities))
+		.route("/environment", get(get_environment))
+		.route("/connectivity", get(connectivity_selftest))
+		.route("/storage-proof/:nft_id", get(storage_proof))
+		.route("/access-check", post(access_check))
+		.route("/response-key", get(response_key))
 		// CENTRALIZED BACKUP API
-		.route("/api/backup/fetch-id", post(admin_backup_fetch_id))
-		.route("/api/backup/push-id", post(admin_backup_push_id))
-		.route("/api/backup/fetch-bulk", post(admin_backup_fetch_bulk))
-		.route("/api/backup/push-bulk", post(admin_backup_push_bulk))
-		.layer(DefaultBodyLimit::max(CONTENT_LENGTH_LIMIT))
+		.route("/backup/recipient", get(backup_recipient_key))
+		.route("/backup/fetch-id", post(admin_backup_fetch_id))
+		.route("/backup/status/:job_id", get(admin_backup_status))
+		.route("/backup/download-token", post(admin_download_token))
+		.route("/backup/artifact/:token", get(backup_artifact_download))
+		.route("/backup/push-id", post(admin_backup_push_id))
+		.route("/backup/push-keyshares", post(admin_backup_push_keyshares))
+		.route("/backup/fetch-bulk", post(admin_backup_fetch_bulk))
+		.route("/backup/push-bulk", post(admin_backup_push_bulk))
+		.route("/backup/escrow", get(admin_escrow_status).post(admin_escrow_setup))
+		.route("/backup/recovery-key", post(recovery_key_exchange))
+		.route("/backup/quorum", get(admin_quorum_status))
+		.route("/backup/tasks", get(admin_task_status))
+		.route("/backup/rotate-quorum", post(admin_quorum_rotate))
+		.route("/backup/rotate-quorum/cancel", post(admin_quorum_cancel))
+		.route("/backup/rotate-account", get(admin_account_rotations).post(admin_account_rotate))
+		.route("/backup/rotate-account/complete", post(admin_account_rotate_complete))
+		.route("/backup/read-only", get(admin_readonly_status).post(admin_readonly_switch))
+		.route("/backup/maintenance", get(admin_maintenance_status).post(admin_maintenance_switch))
+		.route("/backup/log-level", get(admin_log_level_status).post(admin_log_level_update))
+		.route("/backup/allowlist", get(admin_allowlist_status).post(admin_allowlist_update))
+		.route("/backup/provision", post(admin_provision_register))
+		.route("/backup/provision-report", post(admin_provision_report))
+		.route("/backup/compare-peer", post(admin_compare_peer))
+		.route("/backup/consistency-check", post(admin_consistency_check))
+		.route("/backup/audit-log", post(admin_audit_export))
+		.route("/backup/upgrade-arm", post(admin_upgrade_arm))
+		.route("/backup/quote-reregister", post(admin_quote_reregister))
+		.route("/backup/upload", post(admin_upload_init))
+		.route("/backup/upload/:upload_id", get(admin_upload_status))
+		.route("/backup/upload/:upload_id/part/:index", put(admin_upload_part))
+		.route("/backup/upload/:upload_id/finalize", post(admin_upload_finalize))
+		// ADMIN RUNTIME CONFIGURATION
+		.route("/admin/config", get(admin_config_status).post(admin_config_update))
 		// NFT SECRET-SHARING API
-		.route("/api/secret-nft/get-views-log/:nft_id", get(nft_get_views))
-		.route("/api/secret-nft/is-keyshare-available/:nft_id", get(is_nft_available))
-		.route("/api/secret-nft/store-keyshare", post(nft_store_keyshare))
-		.route("/api/secret-nft/retrieve-keyshare", post(nft_retrieve_keyshare))
-		.route("/api/secret-nft/remove-keyshare", post(nft_remove_keyshare))
+		.route("/secret-nft/get-views-log/:nft_id", get(nft_get_views))
+		.route("/secret-nft/is-keyshare-available/:nft_id", get(is_nft_available))
+		.route("/secret-nft/store-keyshare", post(nft_store_keyshare))
+		.route("/secret-nft/retrieve-keyshare", post(nft_retrieve_keyshare))
+		.route("/secret-nft/remove-keyshare", post(nft_remove_keyshare))
+		.route("/secret-nft/batch-store-keyshare", post(nft_batch_store_keyshare))
+		.route("/secret-nft/batch-retrieve-keyshare", post(nft_batch_retrieve_keyshare))
 		// CAPSULE SECRET-SHARING API
-		.route("/api/capsule-nft/get-views-log/:nft_id", get(capsule_get_views))
-		.route("/api/capsule-nft/is-keyshare-available/:nft_id", get(is_capsule_available))
-		.route("/api/capsule-nft/set-keyshare", post(capsule_set_keyshare))
-		.route("/api/capsule-nft/retrieve-keyshare", post(capsule_retrieve_keyshare))
-		.route("/api/capsule-nft/remove-keyshare", post(capsule_remove_keyshare))
+		.route("/capsule-nft/get-views-log/:nft_id", get(capsule_get_views))
+		.route("/capsule-nft/is-keyshare-available/:nft_id", get(is_capsule_available))
+		.route("/capsule-nft/set-keyshare", post(capsule_set_keyshare))
+		.route("/capsule-nft/update-keyshare", post(capsule_update_keyshare))
+		.route("/capsule-nft/retrieve-keyshare", post(capsule_retrieve_keyshare))
+		.route("/capsule-nft/remove-keyshare", post(capsule_remove_keyshare))
 		// SYNCHRONIZATION
-		.route("/api/backup/sync-keyshare", post(sync_keyshares))
+		.route("/backup/sync-keyshare", post(sync_keyshares))
+		.route("/backup/sync-inventory", post(sync_inventory))
+		.route("/backup/sync-root", post(sync_storage_root))
+		// KEYSHARE EVENTS
+		.route("/events/keyshares", get(keyshare_events_socket))
 		// METRIC SERVER
-		.route("/api/metric/interval-nft-list", post(metric_reconcilliation))
-		.route("/api/metric/set-crawl-block", post(set_crawl_block))
-		.layer(
-			ServiceBuilder::new()
-				.layer(HandleErrorLayer::new(handle_timeout_error))
-				.timeout(Duration::from_secs(30)),
-		)
-		.layer(monitor_layer)
-		.layer(CorsLayer::permissive())
-		.with_state(Arc::clone(&state_config.clone()));
-
-	info!("ENCLAVE START : New Thread for run-time block subscription.");
-	// New thread to track latest block
-	tokio::spawn(async move {
-		// Subscribe to all finalized blocks:
-		let mut blocks_sub = match chain_api.blocks().subscribe_finalized().await {
-			Ok(sub) => sub,
+		.route("/metric/interval-nft-list", post(metric_reconcilliation))
+		.route("/metric/interval-nft-list/:job_id", get(metric_reconcilliation_status))
+		.route("/metric/set-crawl-block", post(set_crawl_block))
+		.route("/metric/compression", get(metric_compression))
+		.route("/metric/negative-cache", get(metric_negative_cache))
+		.route("/metric/quota", get(metric_quota))
+		.route("/metric/resources", get(metric_resources))
+		.route("/metric/resource-consumers", get(metric_resource_consumers))
+		// Body sizes are limited by the body class of each route
+		.layer(DefaultBodyLimit::disable())
+		// KEYSHARE INTENTS DURING MAINTENANCE OPERATIONS
+		.route_layer(middleware::from_fn_with_state(state.clone(), coordination_guard))
+		// ADMIN REQUEST AUTHENTICATION
+		.route_layer(middleware::from_fn_with_state(state.clone(), auth_guard))
+		// REQUESTER RATE LIMIT
+		.route_layer(middleware::from_fn_with_state(state.clone(), quota_guard))
+		// GOVERNANCE KILL-SWITCH
+		.route_layer(middleware::from_fn_with_state(state.clone(), killswitch_guard))
+		// OPERATOR MAINTENANCE WINDOW
+		.route_layer(middleware::from_fn_with_state(state.clone(), maintenance_guard))
+		// KEYSHARES OF A DEBUG ENCLAVE ON MAINNET
+		.route_layer(middleware::from_fn(environment_guard))
+		// KEYSHARE RESPONSE SIGNING
+		.route_layer(middleware::from_fn_with_state(state.clone(), signing_guard))
+		// RETRIEVE RESPONSE PADDING
+		.route_layer(middleware::from_fn(padding_guard))
+		// ENTITY TAGS OF THE QUOTE AND HEALTH METADATA
+		.route_layer(middleware::from_fn(etag_guard))
+		// UNCOMPRESSED KEYSHARE RESPONSES
+		.route_layer(middleware::from_fn(compression_guard))
+		// REQUEST BODY LIMITS AND CONTENT TYPES
+		.route_layer(middleware::from_fn(body_limit_guard))
+		// GRACEFUL SHUTDOWN
+		.route_layer(middleware::from_fn(shutdown_guard))
+		// IP RATE LIMIT AND BAN LIST
+		.route_layer(middleware::from_fn(ip_guard))
+		// PROMETHEUS REQUEST METRICS
+		.route_layer(middleware::from_fn(metrics_guard))
+		// API VERSION AND DEPRECATION HEADERS
+		.route_layer(middleware::from_fn_with_state(version, version_guard))
+}
+
+/// Runtime block subscription : block number, quorum, heartbeat, kill-switch and synchronization
+/// # Arguments
+/// * `state_config` - SharedState
+/// * `chain_api` - chain api of the primary rpc
+/// * `heartbeat_interval` - blocks between heartbeats
+async fn block_subscription(
+	state_config: SharedState,
+	chain_api: DefaultApi,
+	heartbeat_interval: u32,
+) -> Result<(), Error> {
+	// Subscribe to all finalized blocks:
+	let mut blocks_sub = match chain_api.blocks().subscribe_finalized().await {
+		Ok(sub) => sub,
+		Err(err) => {
+			error!(" > Unable to subscribe to finalized blocks {err:?}");
+			return Err(anyhow!(err))
+		},
+	};
+
+	// For each new finalized block, get block number
+	while let Some(block) = blocks_sub.next().await {
+		let block = match block {
+			Ok(blk) => blk,
 			Err(err) => {
-				error!(" > Unable to subscribe to finalized blocks {err:?}");
-				return
+				error!(" > Unable to get finalized block {err:?}");
+				record_rpc_error("subscribe_finalized");
+				continue
 			},
 		};
 
-		// For each new finalized block, get block number
-		while let Some(block) = blocks_sub.next().await {
-			let block = match block {
-				Ok(blk) => blk,
-				Err(err) => {
-					error!(" > Unable to get finalized block {err:?}");
-					continue
-				},
-			};
+		let block_number = block.header().number;
 
-			let block_number = block.header().number;
+		// Write to ShareState block, necessary to prevent Read SharedState
+		set_blocknumber(&state_config, block_number).await;
+		trace!("New Block : {}", block_number);
+		trace!(" > Block Number Thread : block_number state is set to {}", block_number);
 
-			// Write to ShareState block, necessary to prevent Read SharedState
-			set_blocknumber(&state_config, block_number).await;
-			trace!("New Block : {}", block_number);
-			trace!(" > Block Number Thread : block_number state is set to {}", block_number);
+		// For block number update, we should reset the nonce as well
+		// It is used as a batch of extrinsics for every block
+		trace!(" > Block Number Thread : nonce before reset is {}", get_nonce(&state_config).await);
+		reset_nonce(&state_config).await;
+		trace!(
+			" > Block Number Thread : nonce has been reset to {}",
+			get_nonce(&state_config).await
+		);
 
-			// For block number update, we should reset the nonce as well
-			// It is used as a batch of extrinsics for every block
-			trace!(
-				" > Block Number Thread : nonce before reset is {}",
-				get_nonce(&state_config).await
-			);
-			reset_nonce(&state_config).await;
-			trace!(
-				" > Block Number Thread : nonce has been reset to {}",
-				get_nonce(&state_config).await
-			);
+		// Time-locked admin quorum rotation
+		activate_pending_quorum(&state_config, block_number).await;
 
-			// Extract block body
-			let body = match block.body().await {
-				Ok(body) => {
-					trace!(" > Block Number Thread : got block body.");
-					body
+		// Governance kill-switch remarks
+		if let Err(err) = killswitch::watch_block(&state_config, &block).await {
+			error!(" > Block Number Thread : Unable to check kill-switch remarks : {err:?}");
+		}
+
+		// Governance allowlist remarks
+		if let Err(err) = allowlist::watch_block(&state_config, &block).await {
+			error!(" > Block Number Thread : Unable to check allowlist remarks : {err:?}");
+		}
+
+		// Creation events invalidate cached negative nft lookups
+		if let Err(err) = negative_cache::watch_block(&block).await {
+			error!(" > Block Number Thread : Unable to check nft creation events : {err:?}");
+		}
+
+		// Periodic keyshare availability heartbeat, does not block the subscription
+		if heartbeat::is_heartbeat_due(block_number, heartbeat_interval) {
+			let heartbeat_state = state_config.clone();
+			tokio::spawn(async move {
+				heartbeat::send_heartbeat(&heartbeat_state).await;
+			});
+		}
+
+		// Scheduled encrypted snapshots, does not block the subscription
+		if schedule::is_backup_due(block_number) {
+			let backup_state = state_config.clone();
+			tokio::spawn(async move {
+				schedule::run_scheduled_backup(&backup_state, block_number).await;
+			});
+		}
+
+		// Extract block body
+		let body = match block.body().await {
+			Ok(body) => {
+				trace!(" > Block Number Thread : got block body.");
+				body
+			},
+			Err(err) => {
+				error!(" > Block Number Thread : Unable to get block body : {err:?}");
+				continue
+			},
+		};
+
+		let storage_api = block.storage();
+
+		let (new_nft, is_tee_events) =
+			match parse_block_body(block_number, body, &storage_api).await {
+				Ok(tuple) => {
+					trace!(" > Block Number Thread : parsed the block body.");
+					tuple
 				},
 				Err(err) => {
-					error!(" > Block Number Thread : Unable to get block body : {err:?}");
+					error!(" > Block Number Thread : Unable to parse the block body : {err:?}");
 					continue
 				},
 			};
 
-			let storage_api = block.storage();
+		// A change in clusters/enclaves data is detected, or the periodic refresh of the
+		// admin whitelist is due.
+		if is_tee_events || is_cluster_refresh_due(block_number) {
+			debug!(" > TEE Event processing");
+			match cluster_discovery(&state_config.clone()).await {
+				Ok(_) => {
+					info!("\t > Cluster discovery complete.");
+					// New self-identity is found?
+					let sync_state = match get_sync_state() {
+						Ok(st) => st,
+						Err(err) => {
+							error!(" > Block Number Thread : TEE Event : Cluster Discovery : Can not get sync state : {err:?}");
+							continue
+						},
+					};
+
+					if sync_state == "setup" {
+						// Here is Identity discovery, thus the first synchronization of all
+						// files. An empty HashMap is the wildcard signal to fetch all keyshares
+						// from nearby enclave
+						for _retry in 0..RETRY_COUNT {
+							match fetch_keyshares(
+								&state_config.clone(),
+								&std::collections::HashMap::<u32, SyncedNFT>::new(),
+							)
+							.await
+							{
+								Ok(_) => {
+									// [discussion] : should not Blindly put current
+									// block_number as the last updated keyshare's block_number
+									let _ = set_sync_state(block_number.to_string());
+									info!("\t\t > SETUP Synchronization of Keyshares complete to the block number: {} .",block_number);
+									break // BREAK THE RETRY
+								},
+
+								Err(err) => {
+									error!(
+										"\t\t > Error during setup-mode fetching keyshares : {:?}",
+										err
+									);
+									debug!("\t > Setup after Runtime > Fetch Keyshares : wait before retry");
+									tokio::time::sleep(std::time::Duration::from_secs(
+										RETRY_DELAY.into(),
+									))
+									.await;
+								},
+							} // FETCH
+						} // RETRY FETCH
+					}
+				},
 
-			let (new_nft, is_tee_events) =
-				match parse_block_body(block_number, body, &storage_api).await {
-					Ok(tuple) => {
-						trace!(" > Block Number Thread : parsed the block body.");
-						tuple
+				// Cluster discovery Error
+				Err(err) => {
+					error!("\t > Error during running-mode cluster discovery {err:?}");
+					// TODO [decision] : Integrity of clusters is corrupted. what to do? Going
+					// to maintenace mode and stop serving to API calls? Wipe?
+					continue
+				},
+			}
+		} // TEE EVENT
+
+		// New Capsule/Secret are found
+		if !new_nft.is_empty() {
+			debug!(
+				" > Runtime mode : NEW-NFT : New nft/capsule event detected, block number = {}",
+				block_number
+			);
+
+			for _retry in 0..RETRY_COUNT {
+				match fetch_keyshares(&state_config.clone(), &new_nft).await {
+					Ok(_) => {
+						let _ = set_sync_state(block_number.to_string());
+						debug!(
+							"\t > Runtime mode : NEW-NFT : Synchronization of Keyshares complete."
+						);
+						break
 					},
 					Err(err) => {
-						error!(" > Block Number Thread : Unable to parse the block body : {err:?}");
-						continue
+						error!("\t > Runtime mode : NEW-NFT : Error during running-mode nft-based syncing : {err:?}");
+						debug!("\t > Runtime mode : NEW-NFT : wait before retry");
+						tokio::time::sleep(std::time::Duration::from_secs(RETRY_DELAY.into()))
+							.await;
 					},
-				};
+				} // FETCH
+			} // RETRY FETCH
+		}
+		// TODO : Regular check to use Indexer/Dictionary for missing NFTs?! (with any reason)
+		// Maybe in another thread
 
-			// A change in clusters/enclaves data is detected.
-			if is_tee_events {
-				debug!(" > TEE Event processing");
-				match cluster_discovery(&state_config.clone()).await {
-					Ok(_) => {
-						info!("\t > Cluster discovery complete.");
-						// New self-identity is found?
-						let sync_state = match get_sync_state() {
-							Ok(st) => st,
-							Err(err) => {
-								error!(" > Block Number Thread : TEE Event : Cluster Discovery : Can not get sync state : {err:?}");
-								continue
-							},
-						};
+		// Regular CRAWL Check
+		let sync_state = match get_sync_state() {
+			Ok(st) => st,
+			Err(err) => {
+				error!(" > Block Number Thread : Can not get sync state : {err:?}");
+				continue
+			},
+		};
+
+		// IMPORTANT : Check for Runtime mode : if integrity of clusters fails, we'll wait and
+		// go back to setup-mode
+		if let Ok(last_sync_block) = sync_state.parse::<u32>() {
+			trace!(" > Runtime mode : SyncStat = {}", sync_state);
+			// If no event has detected in 10 blocks, network disconnections happened, ...
+
+			let last_processed_block = get_processed_block(&state_config).await;
+
+			if (block_number - last_processed_block) > 1 {
+				debug!(" > Runtime mode : Crawl check : Lagging last processed block : block number = {} > last processed = {}, last synced = {}", block_number, last_processed_block, last_sync_block);
+				match crawl_sync_events(&state_config, last_processed_block, block_number).await {
+					Ok(cluster_nft_map) => {
+						info!(
+							"\t > Runtime mode : Crawl check : Success crawling from {} to {} .",
+							last_processed_block, block_number
+						);
 
-						if sync_state == "setup" {
-							// Here is Identity discovery, thus the first synchronization of all
-							// files. An empty HashMap is the wildcard signal to fetch all keyshares
-							// from nearby enclave
+						if !cluster_nft_map.is_empty() {
 							for _retry in 0..RETRY_COUNT {
-								match fetch_keyshares(
-									&state_config.clone(),
-									&std::collections::HashMap::<u32, SyncedNFT>::new(),
-								)
-								.await
+								match fetch_keyshares(&state_config.clone(), &cluster_nft_map).await
 								{
 									Ok(_) => {
-										// [discussion] : should not Blindly put current
-										// block_number as the last updated keyshare's block_number
+										info!("\t > Runtime mode : Crawl check : Success runtime-mode fetching crawled blocks from {} to {} .", last_processed_block, block_number);
 										let _ = set_sync_state(block_number.to_string());
-										info!("\t\t > SETUP Synchronization of Keyshares complete to the block number: {} .",block_number);
-										break // BREAK THE RETRY
+										break
 									},
 
 									Err(err) => {
 										error!(
-										"\t\t > Error during setup-mode fetching keyshares : {:?}",
-										err);
-										debug!("\t > Setup after Runtime > Fetch Keyshares : wait before retry");
-										std::thread::sleep(std::time::Duration::from_secs(
+											"\t > Runtime mode : Crawl check : Error during running-mode nft-based syncing : {:?}",
+											err
+										);
+										// We can not proceed to next nft-based sync.
+										// Because it'll update the syncing state
+										// A retry id needed in next block
+										debug!("\t > Runtime mode : Crawl check : Fetch Keyshares : wait before retry");
+										tokio::time::sleep(std::time::Duration::from_secs(
 											RETRY_DELAY.into(),
-										));
+										))
+										.await;
 									},
-								} // FETCH
-							} // RETRY FETCH
+								} //Fetch
+							} //Retry Fetch
+						} else {
+							debug!("\t > Runtime mode : Crawl check : no new event detected in past blocks");
+							let _ = set_sync_state(last_processed_block.to_string());
 						}
 					},
 
-					// Cluster discovery Error
 					Err(err) => {
-						error!("\t > Error during running-mode cluster discovery {err:?}");
-						// TODO [decision] : Integrity of clusters is corrupted. what to do? Going
-						// to maintenace mode and stop serving to API calls? Wipe?
+						error!(
+							"\t > Runtime mode : Crawl check : Error runtime-mode crawling from {} to {} .",
+							last_processed_block, block_number
+						);
+						// We can not proceed to next nft-based sync.
+						// Because it'll update the syncing state
+						// A retry id needed in next block
+						debug!("\t > Runtime mode : Crawl check : wait before retry");
+						tokio::time::sleep(std::time::Duration::from_secs(RETRY_DELAY.into()))
+							.await;
 						continue
 					},
+				} // EVENTS CRAWLER
+			} // BLOCK LAG DETECTED
+		} else {
+			// Non Numeric SyncState file content:
+			if block_number % 10 == 0 {
+				if get_identity(&state_config).await.is_none() {
+					debug!("\t <<< Enclave has is not registered >>>");
+				} else {
+					debug!("\t <<< Enclave has never Synced >>>");
 				}
-			} // TEE EVENT
-
-			// New Capsule/Secret are found
-			if !new_nft.is_empty() {
-				debug!(
-					" > Runtime mode : NEW-NFT : New nft/capsule event detected, block number = {}",
-					block_number
-				);
-
-				for _retry in 0..RETRY_COUNT {
-					match fetch_keyshares(&state_config.clone(), &new_nft).await {
-						Ok(_) => {
-							let _ = set_sync_state(block_number.to_string());
-							debug!("\t > Runtime mode : NEW-NFT : Synchronization of Keyshares complete.");
-							break
-						},
-						Err(err) => {
-							error!("\t > Runtime mode : NEW-NFT : Error during running-mode nft-based syncing : {err:?}");
-							debug!("\t > Runtime mode : NEW-NFT : wait before retry");
-							std::thread::sleep(std::time::Duration::from_secs(RETRY_DELAY.into()));
-						},
-					} // FETCH
-				} // RETRY FETCH
-			}
-			// TODO : Regular check to use Indexer/Dictionary for missing NFTs?! (with any reason)
-			// Maybe in another thread
-
-			// Regular CRAWL Check
-			let sync_state = match get_sync_state() {
-				Ok(st) => st,
-				Err(err) => {
-					error!(" > Block Number Thread : Can not get sync state : {err:?}");
-					continue
-				},
-			};
-
-			// IMPORTANT : Check for Runtime mode : if integrity of clusters fails, we'll wait and
-			// go back to setup-mode
-			if let Ok(last_sync_block) = sync_state.parse::<u32>() {
-				trace!(" > Runtime mode : SyncStat = {}", sync_state);
-				// If no event has detected in 10 blocks, network disconnections happened, ...
-
-				let last_processed_block = get_processed_block(&state_config).await;
-
-				if (block_number - last_processed_block) > 1 {
-					debug!(" > Runtime mode : Crawl check : Lagging last processed block : block number = {} > last processed = {}, last synced = {}", block_number, last_processed_block, last_sync_block);
-					match crawl_sync_events(&state_config, last_processed_block, block_number).await
-					{
-						Ok(cluster_nft_map) => {
-							info!(
-								"\t > Runtime mode : Crawl check : Success crawling from {} to {} .",
-								last_processed_block, block_number
-							);
-
-							if !cluster_nft_map.is_empty() {
-								for _retry in 0..RETRY_COUNT {
-									match fetch_keyshares(&state_config.clone(), &cluster_nft_map)
-										.await
-									{
-										Ok(_) => {
-											info!("\t > Runtime mode : Crawl check : Success runtime-mode fetching crawled blocks from {} to {} .", last_processed_block, block_number);
-											let _ = set_sync_state(block_number.to_string());
-											break
-										},
-
-										Err(err) => {
-											error!(
-												"\t > Runtime mode : Crawl check : Error during running-mode nft-based syncing : {:?}",
-												err
-											);
-											// We can not proceed to next nft-based sync.
-											// Because it'll update the syncing state
-											// A retry id needed in next block
-											debug!("\t > Runtime mode : Crawl check : Fetch Keyshares : wait before retry");
-											std::thread::sleep(std::time::Duration::from_secs(
-												RETRY_DELAY.into(),
-											));
-										},
-									} //Fetch
-								} //Retry Fetch
-							} else {
-								debug!("\t > Runtime mode : Crawl check : no new event detected in past blocks");
-								let _ = set_sync_state(last_processed_block.to_string());
-							}
-						},
-
-						Err(err) => {
-							error!(
-								"\t > Runtime mode : Crawl check : Error runtime-mode crawling from {} to {} .",
-								last_processed_block, block_number
-							);
-							// We can not proceed to next nft-based sync.
-							// Because it'll update the syncing state
-							// A retry id needed in next block
-							debug!("\t > Runtime mode : Crawl check : wait before retry");
-							std::thread::sleep(std::time::Duration::from_secs(RETRY_DELAY.into()));
-							continue
-						},
-					} // EVENTS CRAWLER
-				} // BLOCK LAG DETECTED
-			} else {
-				// Non Numeric SyncState file content:
-				if block_number % 10 == 0 {
-					if get_identity(&state_config).await.is_none() {
-						debug!("\t <<< Enclave has is not registered >>>");
-					} else {
-						debug!("\t <<< Enclave has never Synced >>>");
-					}
-				}
-				// Prevent Crawling after first registration
-				set_processed_block(&state_config, block_number).await;
-				continue
 			}
-
-			// Update runtime block tracking variable
-			trace!("\t > Runtime mode : update last processed block");
+			// Prevent Crawling after first registration
 			set_processed_block(&state_config, block_number).await;
-		} // While blocks
-	});
+			continue
+		}
 
-	// debug!("ENCLAVE START : wait 6 seconds to get new block.");
-	// tokio::time::sleep(tokio::time::Duration::from_secs(6)).await;
+		// Update runtime block tracking variable
+		trace!("\t > Runtime mode : update last processed block");
+		set_processed_block(&state_config, block_number).await;
+	} // While blocks
 
-	Ok(http_app)
+	Err(anyhow!("finalized block subscription is closed"))
 }
 
 /* ------------------------------
@@ -686,6 +1097,129 @@ async fn fallback(uri: axum::http::Uri) -> impl IntoResponse {
 		.into_response()
 }
 
+/* ------------------------------
+	CAPABILITIES
+------------------------------ */
+
+/// Capabilities endpoint : request formats, signature schemes and public subkeys
+async fn get_capabilities(State(state): State<SharedState>) -> impl IntoResponse {
+	let subkeys = get_subkeys(&state).await;
+	let (admins, admin_source) = admin_whitelist(&state).await;
+
+	// One json! per subsystem, a single object of all the keys exceeds the macro recursion limit
+	let identity = json!({
+		"enclave_address": get_accountid(&state).await,
+		"version": get_version(&state).await,
+		// Api versions, unversioned /api paths are aliases of the deprecated v1
+		"api_versions": json!({
+			"current": CURRENT_API_VERSION,
+			"deprecated": [ApiVersion::V1],
+		}),
+		"request_versions": [REQUEST_VERSION_LEGACY, REQUEST_VERSION_JWS, REQUEST_VERSION_BINARY],
+		"signature_schemes": [SignatureScheme::SR25519, SignatureScheme::ED25519, SignatureScheme::ECDSA],
+		"requester_types": requester_registry().roles(),
+		"keyshare_policy": keyshare_policy(),
+		// Size buckets of retrieve responses, empty if they are not padded
+		"response_padding": response_padding(),
+		"subkeys": subkeys.public_keys(),
+		// Signature of "PURPOSE=address;..." by the enclave account
+		"subkeys_certificate": subkeys.certificate(),
+		// Keyshare responses carry "enclave_signature" by the RESPONSE subkey, see /api/response-key
+		"response_signature": "enclave-response_STATUS_NFTID_BLOCKNUMBER_SHA256(REQUEST)_SHA256(DATA)",
+		// Signed identity bundle of /api/identity, signed by the enclave account
+		"identity_signature": "enclave-identity_ACCOUNT_PUBLICKEY_MRENCLAVE_MRSIGNER_CLUSTERID_SLOTID_BLOCKNUMBER_SHA256(QUOTE)",
+	});
+
+	let limits = json!({
+		// Token buckets of each requester account and ip address
+		"rate_limits": get_runtime_config(&state).await.rate_limits,
+		// Token bucket of every ip address, null if disabled
+		"ip_rate_limit": ip_rate_limit(),
+		// Ban of the ip addresses which send invalid signatures, null if disabled
+		"signature_ban": ban_policy(),
+		// Allowed origins and headers of browser requests, null if CORS is disabled
+		"cors": cors_config(),
+		// Encodings of the compressed responses, null if compression is disabled
+		"compression": compression_config(),
+		// HTTP/2 and keep-alive settings of the connections
+		"connection": connection_config(),
+		// Size limit and content types of the request bodies of each class
+		"body_limits": body_limits_view(),
+		// Runtime filter directives of the logs, changed by the admin quorum
+		"log_directives": log_directives(),
+		// Mutations are rejected until this block, null if writable
+		"read_only_until": read_only_until(&state).await,
+		// Message and expected end of the operator maintenance window, null outside of maintenance
+		"maintenance": get_maintenance_window(&state).await,
+	});
+
+	let backup = json!({
+		// Co-signers of high-value nfts and collections
+		"cosign_policy": cosign_policy().view(),
+		// Whitelisted admins, from the Admin cluster onchain or the bootstrap fallback
+		"admins": admins,
+		"admin_source": admin_source,
+		// Configured admin roles, the other admins have the FULL role
+		"admin_roles": admin_roles(),
+		// Formats of fetched backup archives, restores detect the format
+		"backup_formats": [BackupFormat::ZIP, BackupFormat::V2],
+		// Shares needed to restore an escrowed bulk backup, null if bulk backups are not encrypted
+		"backup_escrow_threshold": backup_escrow().map(|escrow| escrow.threshold),
+		// Recipient of the fetch-bulk "disaster_recovery" archives, shared by the enclaves of the MRSIGNER
+		"disaster_recovery": json!({
+			"mrsigner": local_mrsigner(),
+			"recipient": recovery_recipient().map(|recipient| recipient.to_string()),
+		}),
+		// Websocket of keyshare availability events, its first message is signed by the subscriber
+		"keyshare_events": json!({
+			"path": "/api/events/keyshares",
+			"subscription_message": "keyshare-subscription_NFTID,NFTID,..._BLOCKNUMBER",
+			"max_nft_ids": MAX_SUBSCRIBED_NFTS,
+		}),
+		// Signature of the origin enclave of each keyshare of /api/backup/push-keyshares
+		"keyshare_injection": "keyshare-export_PREFIX_NFTID_BLOCKNUMBER_SHA256(KEYSHARE)",
+		// Blocks between scheduled snapshots and snapshots kept, null if disabled
+		"scheduled_backups": schedule::backup_schedule().map(|schedule| json!({
+			"interval": schedule.interval,
+			"retention": schedule.retention,
+		})),
+	});
+
+	let attestation = json!({
+		// Quote extension and key hash of the RA-TLS certificate, null behind a TLS termination
+		"ra_tls": ra_tls_certificate(),
+		// Fresh quote of /api/attest, the nonce is the second half of its report data
+		"attest_signature": "enclave-attest_NONCE_BLOCKNUMBER_SHA256(QUOTE)",
+		// Report data of the enclave quotes, followed by 32 zero bytes, and the bound api url
+		"report_data": "SHA256(PUBLICKEY || API_URL || BLOCKNUMBER)",
+		"api_url": api_url(),
+		// SGX, DIRECT (gramine-direct) or NATIVE, quotes are only available in SGX
+		"gramine_mode": gramine_mode(),
+		// Verification of the peer quotes, its collateral url and the trusted measurements
+		"quote_verification": verifier_config(),
+		// DCAP or EPID, backend of the quote verifications of this platform
+		"attestation_backend": attestation_backend().attestation_type(),
+		// Seconds before the cached collateral is fetched again, and used while the PCCS is offline
+		"collateral_cache": collateral_cache_config(),
+		// Trusted measurements of the peers, with the changes of the admin quorum
+		"measurement_allowlist": measurement_allowlist().effective(verifier_config()),
+		// Seconds between the quote refreshes and TCB checks of the platform, 0 if disabled
+		"quote_refresh_interval": quote_refresh_interval(),
+		// OFF, MAINTENANCE or ABORT, reaction to a failed attestation self-test at startup
+		"attestation_self_test": self_test_policy(),
+	});
+
+	// The subsystems are merged, the capabilities stay a flat object
+	let mut capabilities = serde_json::Map::new();
+	for section in [identity, limits, backup, attestation] {
+		if let serde_json::Value::Object(section) = section {
+			capabilities.extend(section);
+		}
+	}
+
+	(StatusCode::OK, Json(serde_json::Value::Object(capabilities)))
+}
+
 /* ------------------------------
 	HEALTH CHECK
 ------------------------------ */
@@ -699,6 +1233,19 @@ pub struct HealthResponse {
 	pub version: String,
 	pub description: String,
 	pub enclave_address: String,
+	pub read_only_until: Option<u32>,
+	// Last scheduled backup, None if there was none since the start
+	#[serde(default)]
+	pub last_backup: Option<LastBackup>,
+	// Overall status for load balancers, UNKNOWN in the reports of previous versions
+	#[serde(default)]
+	pub status: HealthStatus,
+	// Maintenance message or governance kill-switch, None in normal mode
+	#[serde(default)]
+	pub maintenance: Option<String>,
+	// Chain connectivity, synchronization, storage and attestation freshness
+	#[serde(default)]
+	pub checks: Option<HealthChecks>,
 }
 
 /// Health check endpoint
@@ -755,6 +1302,11 @@ async fn get_health_status(State(state): State<SharedState>) -> impl IntoRespons
 					block_number,
 					version: binary_version,
 					enclave_address,
+					read_only_until: read_only_until(&state).await,
+					last_backup: last_backup(),
+					status: HealthStatus::UNHEALTHY,
+					maintenance: None,
+					checks: Some(health_checks(&state).await),
 				}),
 			)
 				.into_response()
@@ -773,6 +1325,7 @@ async fn evalueate_health_status(
 	let block_number = get_blocknumber(state).await;
 	let binary_version = get_version(state).await;
 	let enclave_address = get_accountid(state).await;
+	let read_only = read_only_until(state).await;
 
 	trace!("Healthcheck : get public key.");
 	// TODO [error handling] : ADD RPC PROBLEM/TIMEOUT
@@ -788,7 +1341,7 @@ async fn evalueate_health_status(
 	let secrets_number = Some(get_nft_availability_map_len(state).await);
 
 	trace!("Healthcheck handler : get maintenance");
-	let maintenance = get_maintenance(state).await;
+	let maintenance = maintenance_message(state).await;
 
 	let chain = if cfg!(feature = "mainnet") {
 		"mainnet".to_string()
@@ -802,6 +1355,11 @@ async fn evalueate_health_status(
 		"localchain".to_string()
 	};
 
+	trace!("Healthcheck handler : get chain, sync, storage and attestation checks");
+	let checks = health_checks(state).await;
+	let last_backup = last_backup();
+	let overall = overall_status(&checks, &maintenance, &sync_state, last_backup.as_ref());
+
 	if !maintenance.is_empty() {
 		trace!("Healthcheck handler : maintenance mode");
 		return Some((
@@ -812,8 +1370,13 @@ async fn evalueate_health_status(
 				secrets_number,
 				block_number,
 				version: binary_version,
-				description: maintenance,
+				description: maintenance.clone(),
 				enclave_address,
+				read_only_until: read_only,
+				last_backup,
+				status: overall,
+				maintenance: Some(maintenance),
+				checks: Some(checks),
 			}),
 		))
 	}
@@ -840,8 +1403,17 @@ async fn evalueate_health_status(
 			secrets_number,
 			block_number,
 			version: binary_version,
-			description: "SGX server is running!".to_string(),
+			description: match read_only {
+				Some(expiry_block) =>
+					format!("SGX server is running in read-only mode until block {expiry_block}!"),
+				None => "SGX server is running!".to_string(),
+			},
 			enclave_address,
+			read_only_until: read_only,
+			last_backup,
+			status: overall,
+			maintenance: None,
+			checks: Some(checks),
 		}),
 	))
 }
@@ -0,0 +1,313 @@
+use std::{collections::BTreeMap, io, sync::OnceLock};
+
+use axum::{
+	body::{Body, HttpBody},
+	http::{
+		header::{CONTENT_LENGTH, CONTENT_TYPE},
+		Method, Request, StatusCode,
+	},
+	middleware::Next,
+	response::{IntoResponse, Response},
+	Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{debug, warn};
+
+use crate::{
+	chain::constants::{
+		CONTENT_LENGTH_LIMIT, MAX_ADMIN_BODY_SIZE, MAX_BATCH_BODY_SIZE, MAX_KEYSHARE_BODY_SIZE,
+		MAX_UPLOAD_PART_SIZE,
+	},
+	servers::versioning::endpoint_path,
+};
+
+/* ---------------------------------------
+	REQUEST BODY LIMITS
+--------------------------------------- */
+
+// Every route belongs to a body class with its own size limit and content types, instead of one
+// limit for the whole api. Json bodies are buffered up to the limit before the handler, so a huge
+// body is rejected without being held in memory. Backup archives and upload parts are streamed to
+// the handler, a body without Content-Length is aborted once it exceeds the limit. The checks
+// depend on the method and not on the framing headers, an HTTP/2 body has no Content-Length nor
+// Transfer-Encoding.
+
+static BODY_LIMITS: OnceLock<BTreeMap<BodyClass, usize>> = OnceLock::new();
+
+/// Class of a request body
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BodyClass {
+	// Store, retrieve and remove of one keyshare, access check
+	KEYSHARE,
+	// Batch store and retrieve
+	BATCH,
+	// Admin packets, synchronization and metric server requests
+	ADMIN,
+	// Parts of a resumable upload
+	UPLOAD,
+	// Multipart backup archive of push-bulk
+	BULK,
+}
+
+impl BodyClass {
+	/// Accepted media types, without parameters
+	pub fn content_types(&self) -> &'static [&'static str] {
+		match self {
+			BodyClass::UPLOAD => &["application/octet-stream"],
+			BodyClass::BULK => &["multipart/form-data"],
+			_ => &["application/json"],
+		}
+	}
+
+	/// Streamed bodies are not buffered by the middleware
+	pub fn is_streamed(&self) -> bool {
+		matches!(self, BodyClass::UPLOAD | BodyClass::BULK)
+	}
+
+	fn default_limit(&self) -> usize {
+		match self {
+			BodyClass::KEYSHARE => MAX_KEYSHARE_BODY_SIZE,
+			BodyClass::BATCH => MAX_BATCH_BODY_SIZE,
+			BodyClass::ADMIN => MAX_ADMIN_BODY_SIZE,
+			BodyClass::UPLOAD => MAX_UPLOAD_PART_SIZE as usize,
+			BodyClass::BULK => CONTENT_LENGTH_LIMIT,
+		}
+	}
+}
+
+/// Body class of an endpoint
+/// # Arguments
+/// * `path` - unversioned route pattern of the request
+pub fn body_class(path: &str) -> BodyClass {
+	match path {
+		"/api/secret-nft/batch-store-keyshare" | "/api/secret-nft/batch-retrieve-keyshare" =>
+			BodyClass::BATCH,
+		"/api/access-check" => BodyClass::KEYSHARE,
+		path if path.starts_with("/api/secret-nft/") || path.starts_with("/api/capsule-nft/") =>
+			BodyClass::KEYSHARE,
+		"/api/backup/upload/:upload_id/part/:index" => BodyClass::UPLOAD,
+		"/api/backup/push-bulk" => BodyClass::BULK,
+		_ => BodyClass::ADMIN,
+	}
+}
+
+fn default_body_limits() -> BTreeMap<BodyClass, usize> {
+	[BodyClass::KEYSHARE, BodyClass::BATCH, BodyClass::ADMIN, BodyClass::UPLOAD, BodyClass::BULK]
+		.into_iter()
+		.map(|class| (class, class.default_limit()))
+		.collect()
+}
+
+/// Parse the body limits from the command line, the other classes keep their default limit
+/// # Arguments
+/// * `overrides` - "CLASS=BYTES" items, i.e. "KEYSHARE=32768"
+pub fn parse_body_limits(
+	overrides: &[String],
+) -> Result<BTreeMap<BodyClass, usize>, anyhow::Error> {
+	let mut limits = default_body_limits();
+
+	for item in overrides {
+		let (class, limit) = item
+			.split_once('=')
+			.ok_or_else(|| anyhow::anyhow!("BODY LIMIT : expected CLASS=BYTES : {item}"))?;
+
+		let class = match class.trim().to_uppercase().as_str() {
+			"KEYSHARE" => BodyClass::KEYSHARE,
+			"BATCH" => BodyClass::BATCH,
+			"ADMIN" => BodyClass::ADMIN,
+			"UPLOAD" => BodyClass::UPLOAD,
+			"BULK" => BodyClass::BULK,
+			_ => return Err(anyhow::anyhow!("BODY LIMIT : unsupported body class '{class}'")),
+		};
+
+		let limit: usize = limit.trim().parse().map_err(|err| {
+			anyhow::anyhow!("BODY LIMIT : invalid {class:?} limit '{limit}' : {err}")
+		})?;
+		if limit == 0 {
+			return Err(anyhow::anyhow!("BODY LIMIT : {class:?} limit must be positive"))
+		}
+
+		limits.insert(class, limit);
+	}
+
+	Ok(limits)
+}
+
+/// Set the body limits, only once at startup
+pub fn set_body_limits(limits: BTreeMap<BodyClass, usize>) -> Result<(), anyhow::Error> {
+	BODY_LIMITS
+		.set(limits)
+		.map_err(|_| anyhow::anyhow!("BODY LIMIT : limits are already set"))
+}
+
+/// Effective body limits, default limits if they are not configured
+pub fn body_limits() -> &'static BTreeMap<BodyClass, usize> {
+	BODY_LIMITS.get_or_init(default_body_limits)
+}
+
+/// Effective body limit of a class
+pub fn body_limit(class: BodyClass) -> usize {
+	body_limits().get(&class).copied().unwrap_or_else(|| class.default_limit())
+}
+
+/// Body limits and content types of each class, for the capabilities
+pub fn body_limits_view() -> BTreeMap<BodyClass, serde_json::Value> {
+	body_limits()
+		.iter()
+		.map(|(class, limit)| {
+			(*class, json!({ "limit": limit, "content_types": class.content_types() }))
+		})
+		.collect()
+}
+
+/// Media type of the Content-Type header, lowercase and without its parameters
+fn media_type<B>(request: &Request<B>) -> Option<String> {
+	request
+		.headers()
+		.get(CONTENT_TYPE)
+		.and_then(|value| value.to_str().ok())
+		.map(|value| value.split(';').next().unwrap_or_default().trim().to_lowercase())
+}
+
+/// Whether the content type of a request is accepted by its class, an upload part may be sent
+/// without content type
+pub fn is_content_type_allowed(class: BodyClass, media_type: Option<&str>) -> bool {
+	match media_type {
+		Some(media_type) => class.content_types().contains(&media_type),
+		None => class == BodyClass::UPLOAD,
+	}
+}
+
+/// Whether requests of the method carry a body, their content type is always checked
+pub fn method_has_body(method: &Method) -> bool {
+	matches!(*method, Method::POST | Method::PUT | Method::PATCH)
+}
+
+/// Body aborted with an error once it exceeds the limit
+fn limited_body(body: Body, limit: usize) -> Body {
+	let stream = futures::stream::unfold((body, 0usize), move |(mut body, read)| async move {
+		match body.data().await? {
+			Ok(chunk) if read + chunk.len() > limit => Some((
+				Err(io::Error::new(
+					io::ErrorKind::InvalidData,
+					format!("request body is larger than {limit} bytes"),
+				)),
+				(Body::empty(), read),
+			)),
+			Ok(chunk) => {
+				let read = read + chunk.len();
+				Some((Ok(chunk), (body, read)))
+			},
+			Err(err) =>
+				Some((Err(io::Error::new(io::ErrorKind::Other, err)), (Body::empty(), read))),
+		}
+	});
+
+	Body::wrap_stream(stream)
+}
+
+fn too_large(class: BodyClass, limit: usize) -> Response {
+	(
+		StatusCode::PAYLOAD_TOO_LARGE,
+		Json(json!({
+			"description": format!("Request body is larger than the {class:?} limit of {limit} bytes"),
+		})),
+	)
+		.into_response()
+}
+
+/// Middleware enforcing the size limit and content types of the request body class.
+/// The body of every request is limited, the content type is checked for the methods carrying a
+/// body, i.e. not for GET requests.
+pub async fn body_limit_guard(request: Request<Body>, next: Next<Body>) -> Response {
+	let content_length = request
+		.headers()
+		.get(CONTENT_LENGTH)
+		.and_then(|value| value.to_str().ok())
+		.and_then(|value| value.parse::<usize>().ok());
+
+	let class = body_class(&endpoint_path(&request));
+	let limit = body_limit(class);
+
+	if content_length.map_or(false, |length| length > limit) {
+		debug!("BODY LIMIT : {class:?} body of {content_length:?} bytes is rejected");
+		return too_large(class, limit)
+	}
+
+	if !method_has_body(request.method()) {
+		let (parts, body) = request.into_parts();
+		return next.run(Request::from_parts(parts, limited_body(body, limit))).await
+	}
+
+	let media_type = media_type(&request);
+	if !is_content_type_allowed(class, media_type.as_deref()) {
+		warn!("BODY LIMIT : unsupported content type {media_type:?} for {class:?}");
+		return (
+			StatusCode::UNSUPPORTED_MEDIA_TYPE,
+			Json(json!({
+				"description": format!("Content-Type must be one of {:?}", class.content_types()),
+			})),
+		)
+			.into_response()
+	}
+
+	let (parts, body) = request.into_parts();
+
+	if class.is_streamed() {
+		return next.run(Request::from_parts(parts, limited_body(body, limit))).await
+	}
+
+	// Json bodies are buffered anyway by the handlers, the Content-Length may be missing
+	let bytes = match hyper::body::to_bytes(limited_body(body, limit)).await {
+		Ok(bytes) => bytes,
+		Err(err) => {
+			debug!("BODY LIMIT : {class:?} body is rejected : {err:?}");
+			return too_large(class, limit)
+		},
+	};
+
+	next.run(Request::from_parts(parts, Body::from(bytes))).await
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn body_class_test() {
+		assert_eq!(body_class("/api/secret-nft/store-keyshare"), BodyClass::KEYSHARE);
+		assert_eq!(body_class("/api/capsule-nft/retrieve-keyshare"), BodyClass::KEYSHARE);
+		assert_eq!(body_class("/api/secret-nft/batch-store-keyshare"), BodyClass::BATCH);
+		assert_eq!(body_class("/api/backup/push-bulk"), BodyClass::BULK);
+		assert_eq!(body_class("/api/backup/upload/:upload_id/part/:index"), BodyClass::UPLOAD);
+		assert_eq!(body_class("/api/backup/fetch-id"), BodyClass::ADMIN);
+
+		assert!(is_content_type_allowed(BodyClass::KEYSHARE, Some("application/json")));
+		assert!(!is_content_type_allowed(BodyClass::KEYSHARE, Some("text/plain")));
+		assert!(!is_content_type_allowed(BodyClass::KEYSHARE, None));
+		assert!(is_content_type_allowed(BodyClass::UPLOAD, None));
+		assert!(!is_content_type_allowed(BodyClass::BULK, Some("application/json")));
+
+		let limits = parse_body_limits(&["keyshare=1024".to_string()]).unwrap();
+		assert_eq!(limits[&BodyClass::KEYSHARE], 1024);
+		assert_eq!(limits[&BodyClass::BULK], CONTENT_LENGTH_LIMIT);
+		assert!(parse_body_limits(&["ADMIN=0".to_string()]).is_err());
+		assert!(parse_body_limits(&["UNKNOWN=10".to_string()]).is_err());
+
+		assert!(method_has_body(&Method::POST));
+		assert!(method_has_body(&Method::PUT));
+		assert!(!method_has_body(&Method::GET));
+	}
+
+	#[tokio::test]
+	async fn limited_body_test() {
+		let bytes = hyper::body::to_bytes(limited_body(Body::from("0123456789"), 10)).await;
+		assert_eq!(bytes.unwrap().len(), 10);
+		assert!(hyper::body::to_bytes(limited_body(Body::from("0123456789"), 9)).await.is_err());
+	}
+}
@@ -0,0 +1,318 @@
+use std::{
+	net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+	path::PathBuf,
+	sync::{
+		atomic::{AtomicU32, Ordering},
+		OnceLock,
+	},
+};
+
+use axum::{
+	body::Body,
+	extract::{connect_info::Connected, ConnectInfo, State},
+	http::{Request, StatusCode},
+	middleware::{self, Next},
+	response::{IntoResponse, Response},
+	Json, Router,
+};
+use axum_server::{tls_rustls::RustlsConfig, Handle};
+use serde::Serialize;
+use serde_json::json;
+use tokio::net::UnixStream;
+use tokio_stream::wrappers::UnixListenerStream;
+use tracing::{debug, info, warn};
+
+use crate::servers::{
+	connection::connection_config, server_common::bind_reuseport_addr, versioning::endpoint_path,
+};
+
+/* ---------------------------------------
+	ADMIN LISTENER
+--------------------------------------- */
+
+// The admin, backup and metric endpoints can be served on a second listener, so that firewalls
+// only expose the keyshare api to the public :
+// - the admin listener is a TCP address, i.e. on a private interface, or a Unix socket
+// - it only serves the admin, backup, synchronization and metric endpoints
+// - the public listener no longer serves the admin, backup and metric endpoints, the
+//   synchronization endpoints stay public since peer enclaves call the registered url
+// - a TCP admin listener uses the certificate of the public listener, a Unix socket is plain HTTP
+// - Unix clients have no ip address, each one is rate limited and banned by its peer credentials
+// Without admin listener, the public listener serves every endpoint.
+
+static ADMIN_LISTENER: OnceLock<Option<AdminListener>> = OnceLock::new();
+
+// Unix connections without peer credentials, each one is its own client
+static UNIX_CONNECTIONS: AtomicU32 = AtomicU32::new(0);
+
+/// Second listener of the admin endpoints
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub enum AdminListener {
+	TCP(SocketAddr),
+	UNIX(PathBuf),
+}
+
+/// Listener which received a request
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ListenerRole {
+	PUBLIC,
+	ADMIN,
+}
+
+/// Parse the admin listener from the command line
+/// # Arguments
+/// * `value` - "PORT", "ADDRESS:PORT" or "unix:PATH", None without admin listener
+/// * `public_port` - port of the public listener
+pub fn parse_admin_listener(
+	value: Option<&str>,
+	public_port: u16,
+) -> Result<Option<AdminListener>, anyhow::Error> {
+	let value = match value {
+		Some(value) => value.trim(),
+		None => return Ok(None),
+	};
+
+	if let Some(path) = value.strip_prefix("unix:") {
+		let path = PathBuf::from(path);
+		if !path.is_absolute() {
+			return Err(anyhow::anyhow!("ADMIN LISTENER : unix socket path must be absolute"))
+		}
+		return Ok(Some(AdminListener::UNIX(path)))
+	}
+
+	let address = match value.parse::<u16>() {
+		Ok(port) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)),
+		Err(_) => value.parse::<SocketAddr>().map_err(|_| {
+			anyhow::anyhow!(
+				"ADMIN LISTENER : invalid listener '{value}', expected PORT, ADDRESS:PORT or unix:PATH"
+			)
+		})?,
+	};
+
+	if address.port() == public_port {
+		return Err(anyhow::anyhow!(
+			"ADMIN LISTENER : port {public_port} is already the public port"
+		))
+	}
+
+	Ok(Some(AdminListener::TCP(address)))
+}
+
+/// Set the admin listener, only once at startup
+pub fn set_admin_listener(listener: Option<AdminListener>) -> Result<(), anyhow::Error> {
+	ADMIN_LISTENER
+		.set(listener)
+		.map_err(|_| anyhow::anyhow!("ADMIN LISTENER : configuration is already set"))
+}
+
+/// Admin listener, None if the public listener serves every endpoint
+pub fn admin_listener() -> Option<&'static AdminListener> {
+	ADMIN_LISTENER.get_or_init(|| None).as_ref()
+}
+
+/// Whether an endpoint belongs to the admin surface
+/// # Arguments
+/// * `path` - unversioned path of the request
+pub fn is_admin_endpoint(path: &str) -> bool {
+	path.starts_with("/api/backup/") ||
+		path.starts_with("/api/admin/") ||
+		path.starts_with("/api/metric/") ||
+		path == "/metrics"
+}
+
+/// Whether an endpoint is called by the peer enclaves
+pub fn is_sync_endpoint(path: &str) -> bool {
+	matches!(
+		path,
+		"/api/backup/sync-keyshare" | "/api/backup/sync-inventory" | "/api/backup/sync-root"
+	)
+}
+
+/// Whether a listener serves an endpoint
+/// # Arguments
+/// * `role` - listener of the request
+/// * `separated` - an admin listener is configured
+/// * `path` - unversioned path of the request
+pub fn is_served(role: ListenerRole, separated: bool, path: &str) -> bool {
+	match role {
+		ListenerRole::PUBLIC => !separated || !is_admin_endpoint(path) || is_sync_endpoint(path),
+		ListenerRole::ADMIN => is_admin_endpoint(path),
+	}
+}
+
+/// Middleware rejecting the endpoints of the other listener, as unknown routes
+pub async fn listener_guard(
+	State(role): State<ListenerRole>,
+	request: Request<Body>,
+	next: Next<Body>,
+) -> Response {
+	let path = endpoint_path(&request);
+	if is_served(role, admin_listener().is_some(), &path) {
+		return next.run(request).await
+	}
+
+	debug!("ADMIN LISTENER : {path} is not served on the {role:?} listener");
+
+	(
+		StatusCode::NOT_FOUND,
+		Json(json!({ "description": format!("No route to URL : {}", request.uri()) })),
+	)
+		.into_response()
+}
+
+/// Address of a Unix socket client, in the discard-only prefix 100::/64 which no ip client has
+/// # Arguments
+/// * `uid` - user id of the client process
+/// * `pid` - process id of the client
+pub fn unix_peer_address(uid: u32, pid: u32) -> SocketAddr {
+	let address = Ipv6Addr::new(
+		0x0100,
+		0,
+		0,
+		0,
+		(uid >> 16) as u16,
+		uid as u16,
+		(pid >> 16) as u16,
+		pid as u16,
+	);
+
+	SocketAddr::from((address, 0))
+}
+
+/// Client of the Unix admin socket, keyed by the SO_PEERCRED credentials of its connection
+#[derive(Clone, Copy, Debug)]
+struct UnixPeer(SocketAddr);
+
+impl Connected<&UnixStream> for UnixPeer {
+	fn connect_info(stream: &UnixStream) -> Self {
+		match stream.peer_cred() {
+			Ok(cred) =>
+				UnixPeer(unix_peer_address(cred.uid(), cred.pid().map_or(0, |pid| pid as u32))),
+			Err(err) => {
+				warn!("ADMIN LISTENER : unix client without peer credentials : {err:?}");
+				UnixPeer(unix_peer_address(
+					u32::MAX,
+					UNIX_CONNECTIONS.fetch_add(1, Ordering::Relaxed),
+				))
+			},
+		}
+	}
+}
+
+/// The rate limits and ip bans key the Unix clients by their peer address
+async fn unix_peer_guard(mut request: Request<Body>, next: Next<Body>) -> Response {
+	if let Some(ConnectInfo(UnixPeer(address))) =
+		request.extensions().get::<ConnectInfo<UnixPeer>>().copied()
+	{
+		request.extensions_mut().insert(ConnectInfo(address));
+	}
+
+	next.run(request).await
+}
+
+/// App of a listener, restricted to its endpoints
+pub fn listener_app(app: Router, role: ListenerRole) -> Router {
+	app.layer(middleware::from_fn_with_state(role, listener_guard))
+}
+
+/// Serve the admin endpoints on the admin listener
+/// # Arguments
+/// * `app` - The app to serve
+/// * `listener` - The admin listener
+/// * `config` - The rust-TLS config of the public listener
+/// * `handle` - The handle of the public listener, both listeners are drained together
+pub async fn serve_admin(
+	app: Router,
+	listener: &AdminListener,
+	config: RustlsConfig,
+	handle: Handle,
+) -> Result<(), anyhow::Error> {
+	let app = listener_app(app, ListenerRole::ADMIN);
+	let connection = connection_config();
+
+	match listener {
+		AdminListener::TCP(address) => {
+			info!("ADMIN LISTENER : admin endpoints are listening on {address}");
+			axum_server::from_tcp_rustls(bind_reuseport_addr(*address)?, config)
+				.handle(handle)
+				.http_config(connection.http_config())
+				.addr_incoming_config(connection.incoming_config())
+				.serve(app.into_make_service_with_connect_info::<SocketAddr>())
+				.await?
+		},
+
+		AdminListener::UNIX(path) => {
+			// The socket of a previous instance is replaced, its accepted connections are kept
+			if path.exists() {
+				std::fs::remove_file(path)?;
+			}
+			let listener = tokio::net::UnixListener::bind(path)?;
+			info!("ADMIN LISTENER : admin endpoints are listening on unix socket {path:?}");
+
+			// Unix clients have no address, they are rate limited by their peer credentials
+			let app = app.layer(middleware::from_fn(unix_peer_guard));
+
+			hyper::Server::builder(hyper::server::accept::from_stream(UnixListenerStream::new(
+				listener,
+			)))
+			.http1_header_read_timeout(std::time::Duration::from_secs(
+				connection.http1_header_timeout,
+			))
+			.serve(app.into_make_service_with_connect_info::<UnixPeer>())
+			.await?
+		},
+	}
+
+	Ok(())
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn admin_listener_test() {
+		assert_eq!(parse_admin_listener(None, 8000).unwrap(), None);
+		assert_eq!(
+			parse_admin_listener(Some("9443"), 8000).unwrap(),
+			Some(AdminListener::TCP("0.0.0.0:9443".parse().unwrap()))
+		);
+		assert_eq!(
+			parse_admin_listener(Some("10.0.0.5:9443"), 8000).unwrap(),
+			Some(AdminListener::TCP("10.0.0.5:9443".parse().unwrap()))
+		);
+		assert_eq!(
+			parse_admin_listener(Some("unix:/admin/enclave.sock"), 8000).unwrap(),
+			Some(AdminListener::UNIX(PathBuf::from("/admin/enclave.sock")))
+		);
+		assert!(parse_admin_listener(Some("8000"), 8000).is_err());
+		assert!(parse_admin_listener(Some("unix:enclave.sock"), 8000).is_err());
+		assert!(parse_admin_listener(Some("localhost"), 8000).is_err());
+
+		let store = "/api/secret-nft/store-keyshare";
+		let push = "/api/backup/push-bulk";
+		let sync = "/api/backup/sync-inventory";
+
+		assert!(is_served(ListenerRole::PUBLIC, false, push));
+		assert!(is_served(ListenerRole::PUBLIC, true, store));
+		assert!(!is_served(ListenerRole::PUBLIC, true, push));
+		assert!(!is_served(ListenerRole::PUBLIC, true, "/metrics"));
+		assert!(is_served(ListenerRole::PUBLIC, true, sync));
+
+		assert!(!is_served(ListenerRole::ADMIN, true, store));
+		assert!(is_served(ListenerRole::ADMIN, true, push));
+		assert!(is_served(ListenerRole::ADMIN, true, sync));
+		assert!(is_served(ListenerRole::ADMIN, true, "/api/admin/config"));
+
+		// Unix clients of different users or processes have their own address
+		let operator = unix_peer_address(1000, 4242);
+		assert_ne!(operator, unix_peer_address(1001, 4242));
+		assert_ne!(operator, unix_peer_address(1000, 4243));
+		assert_eq!(operator, unix_peer_address(1000, 4242));
+		assert!(!operator.ip().is_loopback());
+	}
+}
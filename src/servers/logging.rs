@@ -0,0 +1,349 @@
+use std::{
+	fs::{self, File, OpenOptions},
+	io::{self, Write},
+	path::{Path, PathBuf},
+	sync::{Arc, Mutex, OnceLock},
+};
+
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{debug, info, warn};
+use tracing_subscriber::{fmt, layer::Layered, prelude::*, reload, EnvFilter, Layer, Registry};
+
+use crate::{
+	chain::constants::{LOG_FILE_NAME, MAX_LOG_DIRECTIVES_LENGTH},
+	servers::auth::VerifiedCaller,
+};
+
+/* ---------------------------------------
+	STRUCTURED LOGGING
+--------------------------------------- */
+
+// Logs are written to stdout, as text or json lines, and optionally to size-rotated json files of
+// an unsealed directory, so they survive a restart of the enclave :
+// - sgx_server.log is the current file, it is renamed sgx_server.log.1 once it reaches its size
+// - older files are shifted up to sgx_server.log.N, the oldest one is removed
+// The filter directives ("sgx_server::backup=debug,hyper=error") can be changed at runtime by the
+// admin quorum, without restarting the enclave.
+
+type FilteredRegistry = Layered<reload::Layer<EnvFilter, Registry>, Registry>;
+type BoxedLayer = Box<dyn Layer<FilteredRegistry> + Send + Sync>;
+
+static LOG_FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+static LOG_DIRECTIVES: Mutex<String> = Mutex::new(String::new());
+static LOG_CONFIG: OnceLock<LogConfig> = OnceLock::new();
+
+/// Format of the stdout logs, files are always json
+#[derive(clap::ValueEnum, Serialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+	// Human readable lines, without target and level
+	#[default]
+	Text,
+	// One json object per event, with its level, target and request span
+	Json,
+}
+
+/// Logging configuration, from the command line
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct LogConfig {
+	pub format: LogFormat,
+	// Unsealed directory of the log files, no log file if not set
+	pub directory: Option<PathBuf>,
+	// Bytes of a log file before its rotation
+	pub max_file_size: u64,
+	// Rotated files kept beside the current one
+	pub max_files: usize,
+}
+
+/* ---------------------------------------
+	LOG ROTATION
+--------------------------------------- */
+
+struct LogFileState {
+	file: File,
+	size: u64,
+}
+
+/// Log file rotated once it reaches its maximum size
+pub struct RotatingFile {
+	path: PathBuf,
+	max_size: u64,
+	max_files: usize,
+	state: Mutex<LogFileState>,
+}
+
+impl RotatingFile {
+	/// Open the current log file of the directory, new events are appended
+	/// # Arguments
+	/// * `directory` - directory of the log files, created if needed
+	/// * `max_size` - bytes of a file before its rotation
+	/// * `max_files` - rotated files kept beside the current one
+	pub fn open(directory: &Path, max_size: u64, max_files: usize) -> io::Result<Self> {
+		fs::create_dir_all(directory)?;
+
+		let path = directory.join(LOG_FILE_NAME);
+		let file = OpenOptions::new().create(true).append(true).open(&path)?;
+		let size = file.metadata()?.len();
+
+		Ok(RotatingFile {
+			path,
+			max_size,
+			max_files,
+			state: Mutex::new(LogFileState { file, size }),
+		})
+	}
+
+	fn rotated_path(&self, index: usize) -> PathBuf {
+		let mut path = self.path.clone().into_os_string();
+		path.push(format!(".{index}"));
+		PathBuf::from(path)
+	}
+
+	fn rotate(&self, state: &mut LogFileState) -> io::Result<()> {
+		state.file.flush()?;
+
+		let oldest = self.rotated_path(self.max_files);
+		if oldest.exists() {
+			fs::remove_file(oldest)?;
+		}
+
+		for index in (1..self.max_files).rev() {
+			let rotated = self.rotated_path(index);
+			if rotated.exists() {
+				fs::rename(rotated, self.rotated_path(index + 1))?;
+			}
+		}
+
+		if self.max_files > 0 {
+			fs::rename(&self.path, self.rotated_path(1))?;
+		}
+
+		state.file = OpenOptions::new().create(true).write(true).truncate(true).open(&self.path)?;
+		state.size = 0;
+
+		Ok(())
+	}
+}
+
+// Errors can not be logged from the writer of the logs, they are printed to stderr
+impl Write for &RotatingFile {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let mut state = self
+			.state
+			.lock()
+			.map_err(|_| io::Error::new(io::ErrorKind::Other, "log file lock is poisoned"))?;
+
+		if state.size > 0 && state.size + buf.len() as u64 > self.max_size {
+			if let Err(err) = self.rotate(&mut state) {
+				eprintln!("LOGGING : log rotation failed : {err:?}");
+			}
+		}
+
+		let written = state.file.write(buf)?;
+		state.size += written as u64;
+
+		Ok(written)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		match self.state.lock() {
+			Ok(mut state) => state.file.flush(),
+			Err(_) => Err(io::Error::new(io::ErrorKind::Other, "log file lock is poisoned")),
+		}
+	}
+}
+
+/* ---------------------------------------
+	SUBSCRIBER
+--------------------------------------- */
+
+/// Install the global subscriber, only once at startup
+/// # Arguments
+/// * `config` - format and log files
+/// * `default_directives` - filter directives if RUST_LOG is not set, i.e. the verbosity level
+pub fn init_logging(config: LogConfig, default_directives: &str) -> Result<(), anyhow::Error> {
+	let directives = std::env::var(EnvFilter::DEFAULT_ENV)
+		.ok()
+		.filter(|directives| EnvFilter::try_new(directives).is_ok())
+		.unwrap_or_else(|| default_directives.to_string());
+
+	let (filter_layer, filter_handle) = reload::Layer::new(EnvFilter::try_new(&directives)?);
+
+	let stdout_layer: BoxedLayer = match config.format {
+		LogFormat::Text => fmt::layer()
+			.with_target(false)
+			.with_level(false)
+			.with_thread_ids(false)
+			.with_thread_names(false)
+			.boxed(),
+		LogFormat::Json =>
+			fmt::layer().json().with_current_span(true).with_span_list(false).boxed(),
+	};
+
+	let mut layers = vec![stdout_layer];
+	if let Some(directory) = &config.directory {
+		let file = RotatingFile::open(directory, config.max_file_size, config.max_files)?;
+		layers.push(
+			fmt::layer()
+				.json()
+				.with_current_span(true)
+				.with_span_list(false)
+				.with_ansi(false)
+				.with_writer(Arc::new(file))
+				.boxed(),
+		);
+	}
+
+	tracing_subscriber::registry().with(filter_layer).with(layers).try_init()?;
+
+	LOG_FILTER
+		.set(filter_handle)
+		.map_err(|_| anyhow::anyhow!("LOGGING : subscriber is already installed"))?;
+	if let Ok(mut current) = LOG_DIRECTIVES.lock() {
+		*current = directives;
+	}
+	LOG_CONFIG
+		.set(config)
+		.map_err(|_| anyhow::anyhow!("LOGGING : configuration is already set"))?;
+
+	Ok(())
+}
+
+/// Current filter directives
+pub fn log_directives() -> String {
+	LOG_DIRECTIVES.lock().map(|directives| directives.clone()).unwrap_or_default()
+}
+
+/// Parse filter directives, without applying them
+/// # Arguments
+/// * `directives` - comma separated "target=level" directives, as in RUST_LOG
+pub fn parse_log_directives(directives: &str) -> Result<EnvFilter, anyhow::Error> {
+	if directives.len() > MAX_LOG_DIRECTIVES_LENGTH {
+		return Err(anyhow::anyhow!(
+			"LOGGING : directives are longer than {MAX_LOG_DIRECTIVES_LENGTH} characters"
+		))
+	}
+
+	EnvFilter::try_new(directives)
+		.map_err(|err| anyhow::anyhow!("LOGGING : invalid directives '{directives}' : {err}"))
+}
+
+/// Replace the filter directives of the running subscriber
+/// # Arguments
+/// * `directives` - comma separated "target=level" directives, as in RUST_LOG
+pub fn set_log_directives(directives: &str) -> Result<(), anyhow::Error> {
+	let filter = parse_log_directives(directives)?;
+
+	LOG_FILTER
+		.get()
+		.ok_or_else(|| anyhow::anyhow!("LOGGING : subscriber is not installed"))?
+		.reload(filter)
+		.map_err(|err| anyhow::anyhow!("LOGGING : unable to reload the filter : {err}"))?;
+
+	match LOG_DIRECTIVES.lock() {
+		Ok(mut current) => *current = directives.to_string(),
+		Err(err) => warn!("LOGGING : lock error : {err:?}"),
+	}
+
+	Ok(())
+}
+
+/* ---------------------------------------
+	ADMIN LOG LEVEL API
+--------------------------------------- */
+
+/// Log level request, signed by the threshold of admin quorum
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LogLevelPacket {
+	directives: String,
+}
+
+/// Canonical hash of the filter directives, signed inside the authentication token
+/// # Arguments
+/// * `directives` - new filter directives
+pub fn log_level_data_hash(directives: &str) -> String {
+	sha256::digest(format!("log-level_{directives}").as_bytes())
+}
+
+/// Current filter directives and log files
+pub async fn admin_log_level_status() -> impl IntoResponse {
+	(
+		StatusCode::OK,
+		Json(json!({
+			"directives": log_directives(),
+			"config": LOG_CONFIG.get(),
+		})),
+	)
+}
+
+/// Change the filter directives at runtime, the request must be signed by the threshold of admin
+/// quorum. Directives are not persisted, the enclave restarts with RUST_LOG or its verbosity.
+/// # Arguments
+/// * `caller` - admin quorum which signed the request
+/// * `request` - LogLevelPacket
+pub async fn admin_log_level_update(
+	caller: VerifiedCaller,
+	Json(request): Json<LogLevelPacket>,
+) -> impl IntoResponse {
+	debug!("ADMIN LOG LEVEL : start");
+
+	if let Err((status, message)) =
+		caller.verify_data_hash(&log_level_data_hash(&request.directives))
+	{
+		let message = format!("ADMIN LOG LEVEL : {message}");
+		warn!(message);
+		return (status, Json(json!({ "error": message })))
+	}
+
+	let approvals = caller.approvals();
+	let previous = log_directives();
+	if let Err(err) = set_log_directives(&request.directives) {
+		let message = format!("ADMIN LOG LEVEL : {err}");
+		warn!(message);
+		return (StatusCode::BAD_REQUEST, Json(json!({ "error": message })))
+	}
+
+	info!(
+		"ADMIN LOG LEVEL : directives '{}' are replaced by '{}' with {} approvals",
+		previous, request.directives, approvals
+	);
+
+	(
+		StatusCode::OK,
+		Json(
+			json!({ "directives": request.directives, "previous": previous, "approvals": approvals }),
+		),
+	)
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn rotating_file_test() {
+		let directory = std::env::temp_dir().join(format!("ternoa-logging-{}", std::process::id()));
+		let file = RotatingFile::open(&directory, 10, 2).unwrap();
+
+		for line in ["event-1\n", "event-2\n", "event-3\n", "event-4\n"] {
+			(&file).write_all(line.as_bytes()).unwrap();
+		}
+
+		// One event per file, only the two previous files are kept
+		let read = |path: PathBuf| fs::read_to_string(path).unwrap();
+		assert_eq!(read(directory.join(LOG_FILE_NAME)), "event-4\n");
+		assert_eq!(read(file.rotated_path(1)), "event-3\n");
+		assert_eq!(read(file.rotated_path(2)), "event-2\n");
+		assert!(!file.rotated_path(3).exists());
+
+		fs::remove_dir_all(directory).unwrap();
+
+		assert_ne!(log_level_data_hash("info"), log_level_data_hash("debug"));
+	}
+}
@@ -0,0 +1,339 @@
+use std::{
+	collections::BTreeMap,
+	fmt::Write,
+	sync::Mutex,
+	time::{Duration, Instant},
+};
+
+use axum::{
+	body::Body,
+	extract::{MatchedPath, State},
+	http::{header, Request},
+	middleware::Next,
+	response::{IntoResponse, Response},
+};
+use tracing::error;
+
+use crate::{
+	attestation::collateral::collateral_cache_len,
+	chain::{
+		constants::{BACKUP_DURATION_BUCKETS, REQUEST_LATENCY_BUCKETS},
+		quota::ip_guard_stats,
+	},
+	servers::state::{
+		get_blocknumber, get_nft_availability_map_len, get_processed_block, SharedState,
+	},
+};
+
+/* ---------------------------------------
+	PROMETHEUS METRICS
+--------------------------------------- */
+
+// Metrics are kept in memory and rendered in the Prometheus text format by /metrics, they are
+// reset when the enclave restarts. Requests are labeled with their route pattern, never with the
+// raw url, so nft ids and job ids do not create new series.
+
+/// Cumulative histogram, each bucket counts the observations lower or equal to its bound
+#[derive(Clone, Debug, PartialEq)]
+struct Histogram {
+	bounds: &'static [f64],
+	buckets: Vec<u64>,
+	sum: f64,
+	count: u64,
+}
+
+impl Histogram {
+	fn new(bounds: &'static [f64]) -> Histogram {
+		Histogram { bounds, buckets: vec![0; bounds.len()], sum: 0.0, count: 0 }
+	}
+
+	fn observe(&mut self, value: f64) {
+		for (bound, bucket) in self.bounds.iter().zip(self.buckets.iter_mut()) {
+			if value <= *bound {
+				*bucket += 1;
+			}
+		}
+		self.sum += value;
+		self.count += 1;
+	}
+}
+
+struct Metrics {
+	// (route, method, status) -> requests
+	requests: BTreeMap<(String, String, u16), u64>,
+	// route -> latency in seconds
+	request_latency: BTreeMap<String, Histogram>,
+	// chain operation -> failed rpc calls
+	rpc_errors: BTreeMap<String, u64>,
+	// (kind, result) -> backups
+	backups: BTreeMap<(String, &'static str), u64>,
+	// kind -> duration in seconds
+	backup_duration: BTreeMap<String, Histogram>,
+	// reason -> rejected requests
+	rejected_requests: BTreeMap<&'static str, u64>,
+	// result -> collateral lookups
+	collateral_fetches: BTreeMap<&'static str, u64>,
+}
+
+static METRICS: Mutex<Metrics> = Mutex::new(Metrics {
+	requests: BTreeMap::new(),
+	request_latency: BTreeMap::new(),
+	rpc_errors: BTreeMap::new(),
+	backups: BTreeMap::new(),
+	backup_duration: BTreeMap::new(),
+	rejected_requests: BTreeMap::new(),
+	collateral_fetches: BTreeMap::new(),
+});
+
+fn update_metrics(update: impl FnOnce(&mut Metrics)) {
+	match METRICS.lock() {
+		Ok(mut metrics) => update(&mut metrics),
+		Err(err) => error!("METRICS : lock error : {err:?}"),
+	}
+}
+
+/// Count an api request and its latency
+pub fn record_request(route: &str, method: &str, status: u16, elapsed: Duration) {
+	update_metrics(|metrics| {
+		*metrics
+			.requests
+			.entry((route.to_string(), method.to_string(), status))
+			.or_default() += 1;
+		metrics
+			.request_latency
+			.entry(route.to_string())
+			.or_insert_with(|| Histogram::new(REQUEST_LATENCY_BUCKETS))
+			.observe(elapsed.as_secs_f64());
+	});
+}
+
+/// Count a failed rpc call of the chain module
+/// # Arguments
+/// * `operation` - chain operation, i.e. the name of the chain function
+pub fn record_rpc_error(operation: &str) {
+	update_metrics(|metrics| *metrics.rpc_errors.entry(operation.to_string()).or_default() += 1);
+}
+
+/// Count a backup or restore and its duration
+/// # Arguments
+/// * `kind` - "fetch-bulk", "fetch-id", "scheduled" or "restore"
+/// * `elapsed` - duration of the backup
+/// * `success` - false if the backup failed
+pub fn record_backup(kind: &str, elapsed: Duration, success: bool) {
+	let result = if success { "success" } else { "failure" };
+	update_metrics(|metrics| {
+		*metrics.backups.entry((kind.to_string(), result)).or_default() += 1;
+		metrics
+			.backup_duration
+			.entry(kind.to_string())
+			.or_insert_with(|| Histogram::new(BACKUP_DURATION_BUCKETS))
+			.observe(elapsed.as_secs_f64());
+	});
+}
+
+/// Count a request rejected before its handler
+/// # Arguments
+/// * `reason` - "ip_rate_limit", "ip_ban" or "quota"
+pub fn record_rejected_request(reason: &'static str) {
+	update_metrics(|metrics| *metrics.rejected_requests.entry(reason).or_default() += 1);
+}
+
+/// Count a collateral lookup of the quote verifier
+/// # Arguments
+/// * `result` - "cache_hit", "success", "failure" or "stale" when a failed fetch used the cache
+pub fn record_collateral_fetch(result: &'static str) {
+	update_metrics(|metrics| *metrics.collateral_fetches.entry(result).or_default() += 1);
+}
+
+/// Middleware counting the api requests by route, method and status, with their latency.
+/// It is a route layer, so the route pattern is known and unmatched urls are not counted.
+pub async fn metrics_guard(request: Request<Body>, next: Next<Body>) -> Response {
+	let route = request
+		.extensions()
+		.get::<MatchedPath>()
+		.map(|path| path.as_str().to_string())
+		.unwrap_or_else(|| "unknown".to_string());
+	let method = request.method().to_string();
+	let started = Instant::now();
+
+	let response = next.run(request).await;
+
+	record_request(&route, &method, response.status().as_u16(), started.elapsed());
+	response
+}
+
+/* ---------------------------------------
+	TEXT FORMAT
+--------------------------------------- */
+
+fn escape_label(value: &str) -> String {
+	value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn write_header(output: &mut String, name: &str, kind: &str, help: &str) {
+	let _ = writeln!(output, "# HELP {name} {help}");
+	let _ = writeln!(output, "# TYPE {name} {kind}");
+}
+
+fn write_histogram(output: &mut String, name: &str, labels: &str, histogram: &Histogram) {
+	for (bound, bucket) in histogram.bounds.iter().zip(histogram.buckets.iter()) {
+		let _ = writeln!(output, "{name}_bucket{{{labels},le=\"{bound}\"}} {bucket}");
+	}
+	let _ = writeln!(output, "{name}_bucket{{{labels},le=\"+Inf\"}} {}", histogram.count);
+	let _ = writeln!(output, "{name}_sum{{{labels}}} {}", histogram.sum);
+	let _ = writeln!(output, "{name}_count{{{labels}}} {}", histogram.count);
+}
+
+/// Render the metrics in the Prometheus text format
+/// # Arguments
+/// * `gauges` - (name, help, value) of the gauges read from the state
+pub fn render_metrics(gauges: &[(&str, &str, f64)]) -> String {
+	let mut output = String::new();
+
+	for (name, help, value) in gauges {
+		write_header(&mut output, name, "gauge", help);
+		let _ = writeln!(output, "{name} {value}");
+	}
+
+	let metrics = match METRICS.lock() {
+		Ok(metrics) => metrics,
+		Err(err) => {
+			error!("METRICS : lock error : {err:?}");
+			return output
+		},
+	};
+
+	let name = "enclave_http_requests_total";
+	write_header(&mut output, name, "counter", "Api requests by route, method and status.");
+	for ((route, method, status), count) in metrics.requests.iter() {
+		let _ = writeln!(
+			output,
+			"{name}{{route=\"{}\",method=\"{method}\",status=\"{status}\"}} {count}",
+			escape_label(route)
+		);
+	}
+
+	let name = "enclave_http_request_duration_seconds";
+	write_header(&mut output, name, "histogram", "Latency of the api requests by route.");
+	for (route, histogram) in metrics.request_latency.iter() {
+		write_histogram(
+			&mut output,
+			name,
+			&format!("route=\"{}\"", escape_label(route)),
+			histogram,
+		);
+	}
+
+	let name = "enclave_rpc_errors_total";
+	write_header(&mut output, name, "counter", "Failed chain rpc calls by operation.");
+	for (operation, count) in metrics.rpc_errors.iter() {
+		let _ = writeln!(output, "{name}{{operation=\"{}\"}} {count}", escape_label(operation));
+	}
+
+	let name = "enclave_rejected_requests_total";
+	write_header(&mut output, name, "counter", "Requests rejected by the rate limits, by reason.");
+	for (reason, count) in metrics.rejected_requests.iter() {
+		let _ = writeln!(output, "{name}{{reason=\"{reason}\"}} {count}");
+	}
+
+	let name = "enclave_collateral_fetches_total";
+	write_header(
+		&mut output,
+		name,
+		"counter",
+		"Collateral lookups of the quote verifier by result.",
+	);
+	for (result, count) in metrics.collateral_fetches.iter() {
+		let _ = writeln!(output, "{name}{{result=\"{result}\"}} {count}");
+	}
+
+	let name = "enclave_backups_total";
+	write_header(&mut output, name, "counter", "Backups and restores by kind and result.");
+	for ((kind, result), count) in metrics.backups.iter() {
+		let _ = writeln!(output, "{name}{{kind=\"{kind}\",result=\"{result}\"}} {count}");
+	}
+
+	let name = "enclave_backup_duration_seconds";
+	write_header(&mut output, name, "histogram", "Duration of the backups and restores by kind.");
+	for (kind, histogram) in metrics.backup_duration.iter() {
+		write_histogram(&mut output, name, &format!("kind=\"{kind}\""), histogram);
+	}
+
+	output
+}
+
+/// Metrics endpoint, scraped by Prometheus
+pub async fn prometheus_metrics(State(state): State<SharedState>) -> impl IntoResponse {
+	let gauges = [
+		(
+			"enclave_keyshares",
+			"Keyshares stored in the sealed directory.",
+			get_nft_availability_map_len(&state).await as f64,
+		),
+		(
+			"enclave_block_number",
+			"Last finalized block of the chain.",
+			get_blocknumber(&state).await as f64,
+		),
+		(
+			"enclave_processed_block",
+			"Last block processed by the synchronization.",
+			get_processed_block(&state).await as f64,
+		),
+		(
+			"enclave_banned_ips",
+			"Ip addresses banned after repeated signature failures.",
+			ip_guard_stats().banned as f64,
+		),
+		(
+			"enclave_collateral_cache_entries",
+			"Platforms of which the quote collateral is cached.",
+			collateral_cache_len() as f64,
+		),
+	];
+
+	([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], render_metrics(&gauges))
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn render_metrics_test() {
+		record_request("/metrics-test/:id", "GET", 200, Duration::from_millis(30));
+		record_request("/metrics-test/:id", "GET", 200, Duration::from_secs(60));
+		record_rpc_error("metrics_test");
+		record_backup("metrics-test", Duration::from_secs(2), false);
+		record_rejected_request("ip_ban");
+		record_collateral_fetch("failure");
+
+		let output = render_metrics(&[("enclave_keyshares", "Keyshares.", 12.0)]);
+		assert!(output.contains("# TYPE enclave_keyshares gauge\nenclave_keyshares 12\n"));
+		assert!(output.contains(
+			"enclave_http_requests_total{route=\"/metrics-test/:id\",method=\"GET\",status=\"200\"} 2"
+		));
+		// Buckets are cumulative, the slow request is only in +Inf
+		assert!(output.contains(
+			"enclave_http_request_duration_seconds_bucket{route=\"/metrics-test/:id\",le=\"0.05\"} 1"
+		));
+		assert!(output.contains(
+			"enclave_http_request_duration_seconds_bucket{route=\"/metrics-test/:id\",le=\"30\"} 1"
+		));
+		assert!(output.contains(
+			"enclave_http_request_duration_seconds_bucket{route=\"/metrics-test/:id\",le=\"+Inf\"} 2"
+		));
+		assert!(output.contains("enclave_rpc_errors_total{operation=\"metrics_test\"} 1"));
+		assert!(output.contains("enclave_rejected_requests_total{reason=\"ip_ban\"}"));
+		assert!(output.contains("enclave_collateral_fetches_total{result=\"failure\"}"));
+		assert!(
+			output.contains("enclave_backups_total{kind=\"metrics-test\",result=\"failure\"} 1")
+		);
+
+		assert_eq!(escape_label("a\"b\\c"), "a\\\"b\\\\c");
+	}
+}
@@ -1,3 +1,24 @@
+pub mod auth;
+pub mod compression;
+pub mod connection;
+pub mod coordination;
+pub mod correlation;
+pub mod cors;
+pub mod etag;
+pub mod events;
+pub mod health;
 pub mod http_server;
+pub mod limits;
+pub mod listener;
+pub mod logging;
+pub mod metrics;
+pub mod padding;
+pub mod proxy;
+pub mod resources;
+pub mod runtime;
 pub mod server_common;
+pub mod shutdown;
+pub mod signing;
 pub mod state;
+pub mod supervisor;
+pub mod versioning;
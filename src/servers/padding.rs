@@ -0,0 +1,169 @@
+use std::sync::OnceLock;
+
+use axum::{
+	body::{boxed, Body, Full},
+	http::{
+		header::{CONTENT_LENGTH, CONTENT_TYPE},
+		HeaderValue, Request,
+	},
+	middleware::Next,
+	response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use tracing::{debug, error};
+
+use crate::{
+	chain::constants::{MAX_PADDED_BODY_SIZE, MAX_PADDING_BUCKET},
+	servers::versioning::endpoint_path,
+};
+
+/* ---------------------------------------
+	RESPONSE PADDING
+--------------------------------------- */
+
+static RESPONSE_PADDING: OnceLock<ResponsePadding> = OnceLock::new();
+
+// Endpoints of which the response size depends on the retrieved keyshare
+const PADDED_ENDPOINTS: [&str; 3] = [
+	"/api/secret-nft/retrieve-keyshare",
+	"/api/secret-nft/batch-retrieve-keyshare",
+	"/api/capsule-nft/retrieve-keyshare",
+];
+
+/// Size buckets of retrieve responses, a network observer only learns the bucket of a response.
+/// The json body is followed by spaces up to the smallest bucket it fits in, bodies larger than
+/// the largest bucket are padded to a multiple of it. Trailing whitespace is valid json, clients
+/// need no change.
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct ResponsePadding {
+	pub scheme: &'static str,
+	// Ascending sizes in bytes, empty if padding is disabled
+	pub buckets: Vec<usize>,
+	pub endpoints: Vec<&'static str>,
+}
+
+impl ResponsePadding {
+	/// Parse the buckets from the command line
+	/// # Arguments
+	/// * `buckets` - "off" or comma separated sizes in bytes, i.e. "1024,4096,16384"
+	pub fn parse(buckets: &str) -> Result<ResponsePadding, anyhow::Error> {
+		if buckets.trim().eq_ignore_ascii_case("off") {
+			return Ok(ResponsePadding::default())
+		}
+
+		let mut sizes = Vec::new();
+		for size in buckets.split(',') {
+			let size: usize = size.trim().parse().map_err(|err| {
+				anyhow::anyhow!("RESPONSE PADDING : invalid bucket '{size}' : {err}")
+			})?;
+
+			if size == 0 || size > MAX_PADDING_BUCKET {
+				return Err(anyhow::anyhow!(
+					"RESPONSE PADDING : bucket sizes must be between 1 and {MAX_PADDING_BUCKET} bytes"
+				))
+			}
+			sizes.push(size);
+		}
+
+		sizes.sort_unstable();
+		sizes.dedup();
+
+		Ok(ResponsePadding {
+			scheme: "json-trailing-whitespace",
+			buckets: sizes,
+			endpoints: PADDED_ENDPOINTS.to_vec(),
+		})
+	}
+
+	pub fn is_enabled(&self) -> bool {
+		!self.buckets.is_empty()
+	}
+
+	/// Size of a padded body
+	/// # Arguments
+	/// * `size` - size of the json body
+	pub fn padded_size(&self, size: usize) -> usize {
+		match self.buckets.iter().find(|bucket| **bucket >= size) {
+			Some(bucket) => *bucket,
+			None => match self.buckets.last() {
+				Some(largest) => size.div_ceil(*largest) * largest,
+				None => size,
+			},
+		}
+	}
+}
+
+/// Set the padding buckets, only once at startup
+pub fn set_response_padding(padding: ResponsePadding) -> Result<(), anyhow::Error> {
+	RESPONSE_PADDING
+		.set(padding)
+		.map_err(|_| anyhow::anyhow!("RESPONSE PADDING : padding is already set"))
+}
+
+/// Effective response padding, disabled if it is not configured
+pub fn response_padding() -> &'static ResponsePadding {
+	RESPONSE_PADDING.get_or_init(ResponsePadding::default)
+}
+
+/// Middleware padding the json responses of retrieve endpoints, including errors
+pub async fn padding_guard(request: Request<Body>, next: Next<Body>) -> Response {
+	let padding = response_padding();
+	if !padding.is_enabled() || !PADDED_ENDPOINTS.contains(&endpoint_path(&request).as_str()) {
+		return next.run(request).await
+	}
+
+	let response = next.run(request).await;
+
+	let is_json = response
+		.headers()
+		.get(CONTENT_TYPE)
+		.and_then(|value| value.to_str().ok())
+		.map(|value| value.starts_with("application/json"))
+		.unwrap_or(false);
+	if !is_json {
+		return response
+	}
+
+	let (mut parts, body) = response.into_parts();
+	let mut bytes = match hyper::body::to_bytes(body).await {
+		Ok(bytes) => bytes.to_vec(),
+		Err(err) => {
+			error!("RESPONSE PADDING : unable to read the response body : {err:?}");
+			return axum::http::StatusCode::INTERNAL_SERVER_ERROR.into_response()
+		},
+	};
+
+	if bytes.len() <= MAX_PADDED_BODY_SIZE {
+		let padded_size = padding.padded_size(bytes.len());
+		debug!("RESPONSE PADDING : {} bytes padded to {}", bytes.len(), padded_size);
+		bytes.resize(padded_size, b' ');
+	}
+
+	parts.headers.insert(CONTENT_LENGTH, HeaderValue::from(bytes.len()));
+	Response::from_parts(parts, boxed(Full::from(bytes)))
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn padding_buckets_test() {
+		assert!(!ResponsePadding::parse("off").unwrap().is_enabled());
+		assert!(ResponsePadding::parse("1024,abc").is_err());
+		assert!(ResponsePadding::parse("0").is_err());
+		assert!(ResponsePadding::parse(&format!("{}", MAX_PADDING_BUCKET + 1)).is_err());
+
+		let padding = ResponsePadding::parse("4096, 1024,1024").unwrap();
+		assert_eq!(padding.buckets, vec![1024, 4096]);
+
+		assert_eq!(padding.padded_size(10), 1024);
+		assert_eq!(padding.padded_size(1024), 1024);
+		assert_eq!(padding.padded_size(1025), 4096);
+		// Beyond the largest bucket, multiples of it
+		assert_eq!(padding.padded_size(4097), 8192);
+		assert_eq!(padding.padded_size(12288), 12288);
+
+		assert_eq!(ResponsePadding::default().padded_size(10), 10);
+	}
+}
@@ -0,0 +1,415 @@
+use std::{
+	collections::{BTreeMap, HashMap},
+	sync::OnceLock,
+	time::{Duration, Instant},
+};
+
+use axum::http::header;
+use serde::Serialize;
+use serde_json::json;
+use tracing::{debug, error, info, warn};
+
+use crate::{
+	chain::constants::{
+		FD_ALERT_PERCENT, MAX_RESOURCE_CONSUMERS, RESOURCE_ALERT_COOLDOWN,
+		RESOURCE_MONITOR_INTERVAL,
+	},
+	servers::{
+		proxy::with_http_proxy,
+		state::{get_accountid, get_task_registry, SharedState},
+	},
+};
+
+/* ---------------------------------------
+	RESOURCE MONITORING
+--------------------------------------- */
+
+static RESOURCE_ALERT: OnceLock<ResourceAlertConfig> = OnceLock::new();
+
+/// Pre-exhaustion alerts of the resource monitor
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ResourceAlertConfig {
+	// Alerts are posted as json to this url, only logged and reported to sentry if not set
+	pub webhook: Option<String>,
+	// Percentage of the open files limit which triggers an alert
+	pub fd_threshold_percent: f64,
+}
+
+impl Default for ResourceAlertConfig {
+	fn default() -> Self {
+		ResourceAlertConfig { webhook: None, fd_threshold_percent: FD_ALERT_PERCENT }
+	}
+}
+
+/// Category of an open file descriptor
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum FdKind {
+	File,
+	Socket,
+	Pipe,
+	Other,
+}
+
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct FdUsage {
+	pub total: usize,
+	pub files: usize,
+	pub sockets: usize,
+	pub pipes: usize,
+	pub others: usize,
+}
+
+/// Resource usage of the enclave process.
+/// Tokio task metrics need the unstable runtime, supervised tasks and os threads are counted.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ResourceSnapshot {
+	pub open_fds: FdUsage,
+	// Soft limit of open files, None if unlimited or unknown
+	pub fd_limit: Option<u64>,
+	pub fd_usage_percent: Option<f64>,
+	pub threads: Option<u64>,
+	// Supervised background tasks by status
+	pub tasks: BTreeMap<String, usize>,
+}
+
+/// Group of open file descriptors, i.e. a directory or a tcp state
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ResourceConsumer {
+	pub kind: FdKind,
+	pub name: String,
+	pub count: usize,
+}
+
+/// Set the alert configuration, only once at startup
+pub fn set_resource_alert_config(config: ResourceAlertConfig) -> Result<(), anyhow::Error> {
+	if !(0.0..=100.0).contains(&config.fd_threshold_percent) {
+		return Err(anyhow::anyhow!(
+			"RESOURCE MONITOR : invalid fd alert percent {}",
+			config.fd_threshold_percent
+		))
+	}
+
+	RESOURCE_ALERT
+		.set(config)
+		.map_err(|_| anyhow::anyhow!("RESOURCE MONITOR : alert config is already set"))
+}
+
+/// Effective alert configuration, default threshold without webhook if it is not configured
+pub fn resource_alert_config() -> &'static ResourceAlertConfig {
+	RESOURCE_ALERT.get_or_init(ResourceAlertConfig::default)
+}
+
+/* ---------------------------------------
+	PROCFS PARSERS
+--------------------------------------- */
+
+/// Category of a /proc/self/fd link target
+pub fn classify_fd(target: &str) -> FdKind {
+	if target.starts_with("socket:") {
+		FdKind::Socket
+	} else if target.starts_with("pipe:") {
+		FdKind::Pipe
+	} else if target.starts_with('/') {
+		FdKind::File
+	} else {
+		FdKind::Other
+	}
+}
+
+/// Soft limit of "Max open files" in /proc/self/limits
+pub fn parse_fd_limit(limits: &str) -> Option<u64> {
+	limits
+		.lines()
+		.find_map(|line| line.strip_prefix("Max open files"))
+		.and_then(|values| values.split_whitespace().next())
+		.and_then(|soft| soft.parse::<u64>().ok())
+}
+
+/// "Threads" of /proc/self/status
+pub fn parse_threads(status: &str) -> Option<u64> {
+	status
+		.lines()
+		.find_map(|line| line.strip_prefix("Threads:"))
+		.and_then(|threads| threads.trim().parse::<u64>().ok())
+}
+
+fn tcp_state_name(state: &str) -> &'static str {
+	match state {
+		"01" => "ESTABLISHED",
+		"02" => "SYN_SENT",
+		"03" => "SYN_RECV",
+		"04" => "FIN_WAIT1",
+		"05" => "FIN_WAIT2",
+		"06" => "TIME_WAIT",
+		"07" => "CLOSE",
+		"08" => "CLOSE_WAIT",
+		"09" => "LAST_ACK",
+		"0A" => "LISTEN",
+		"0B" => "CLOSING",
+		_ => "UNKNOWN",
+	}
+}
+
+/// Socket inode -> name, from a /proc/self/net table
+/// # Arguments
+/// * `table` - content of tcp, tcp6, udp, udp6 or unix table
+/// * `protocol` - protocol name of the table
+/// * `sockets` - inode -> "protocol [state]"
+pub fn parse_socket_table(table: &str, protocol: &str, sockets: &mut HashMap<u64, String>) {
+	// unix table has a different layout
+	let (state_column, inode_column) = if protocol == "unix" { (None, 6) } else { (Some(3), 9) };
+
+	for line in table.lines().skip(1) {
+		let fields: Vec<&str> = line.split_whitespace().collect();
+		let inode = match fields.get(inode_column).and_then(|inode| inode.parse::<u64>().ok()) {
+			Some(inode) => inode,
+			None => continue,
+		};
+
+		let name = match state_column.and_then(|column| fields.get(column)) {
+			Some(state) if protocol.starts_with("tcp") => format!("tcp {}", tcp_state_name(state)),
+			_ => protocol.trim_end_matches('6').to_string(),
+		};
+
+		sockets.insert(inode, name);
+	}
+}
+
+/// Group name of an open file descriptor
+fn consumer_name(kind: FdKind, target: &str, sockets: &HashMap<u64, String>) -> String {
+	match kind {
+		FdKind::File => match std::path::Path::new(target).parent() {
+			Some(parent) => parent.display().to_string(),
+			None => target.to_string(),
+		},
+		FdKind::Socket => target
+			.trim_start_matches("socket:[")
+			.trim_end_matches(']')
+			.parse::<u64>()
+			.ok()
+			.and_then(|inode| sockets.get(&inode).cloned())
+			.unwrap_or_else(|| "unknown".to_string()),
+		FdKind::Pipe => "pipe".to_string(),
+		FdKind::Other => target.to_string(),
+	}
+}
+
+/// Link targets of the open file descriptors of the process
+fn open_fd_targets() -> Vec<String> {
+	match std::fs::read_dir("/proc/self/fd") {
+		Ok(entries) => entries
+			.filter_map(|entry| entry.ok())
+			.filter_map(|entry| std::fs::read_link(entry.path()).ok())
+			.map(|target| target.display().to_string())
+			.collect(),
+		Err(err) => {
+			debug!("RESOURCE MONITOR : unable to read open file descriptors : {err:?}");
+			Vec::new()
+		},
+	}
+}
+
+/* ---------------------------------------
+	SNAPSHOTS
+--------------------------------------- */
+
+/// Count file descriptors by category
+pub fn fd_usage(targets: &[String]) -> FdUsage {
+	let mut usage = FdUsage { total: targets.len(), ..Default::default() };
+
+	for target in targets {
+		match classify_fd(target) {
+			FdKind::File => usage.files += 1,
+			FdKind::Socket => usage.sockets += 1,
+			FdKind::Pipe => usage.pipes += 1,
+			FdKind::Other => usage.others += 1,
+		}
+	}
+
+	usage
+}
+
+/// Current resource usage of the enclave process
+pub async fn resource_snapshot(state: &SharedState) -> ResourceSnapshot {
+	let open_fds = fd_usage(&open_fd_targets());
+
+	let fd_limit = std::fs::read_to_string("/proc/self/limits")
+		.ok()
+		.and_then(|limits| parse_fd_limit(&limits));
+	let fd_usage_percent = fd_limit
+		.filter(|limit| *limit > 0)
+		.map(|limit| open_fds.total as f64 * 100.0 / limit as f64);
+
+	let threads = std::fs::read_to_string("/proc/self/status")
+		.ok()
+		.and_then(|status| parse_threads(&status));
+
+	let mut tasks = BTreeMap::new();
+	for report in get_task_registry(state).await.read().await.values() {
+		*tasks.entry(format!("{:?}", report.status)).or_insert(0) += 1;
+	}
+
+	ResourceSnapshot { open_fds, fd_limit, fd_usage_percent, threads, tasks }
+}
+
+/// Largest groups of open file descriptors
+/// # Arguments
+/// * `limit` - maximum number of groups
+pub fn largest_consumers(limit: usize) -> Vec<ResourceConsumer> {
+	let mut sockets = HashMap::new();
+	for protocol in ["tcp", "tcp6", "udp", "udp6", "unix"] {
+		if let Ok(table) = std::fs::read_to_string(format!("/proc/self/net/{protocol}")) {
+			parse_socket_table(&table, protocol, &mut sockets);
+		}
+	}
+
+	let mut groups: BTreeMap<(FdKind, String), usize> = BTreeMap::new();
+	for target in open_fd_targets() {
+		let kind = classify_fd(&target);
+		*groups.entry((kind, consumer_name(kind, &target, &sockets))).or_insert(0) += 1;
+	}
+
+	let mut consumers: Vec<ResourceConsumer> = groups
+		.into_iter()
+		.map(|((kind, name), count)| ResourceConsumer { kind, name, count })
+		.collect();
+
+	consumers.sort_by(|a, b| b.count.cmp(&a.count));
+	consumers.truncate(limit);
+	consumers
+}
+
+/* ---------------------------------------
+	PRE-EXHAUSTION ALERTS
+--------------------------------------- */
+
+async fn send_alert(webhook: &str, body: String) -> Result<(), anyhow::Error> {
+	let client = with_http_proxy(reqwest::Client::builder())
+		.timeout(Duration::from_secs(RESOURCE_MONITOR_INTERVAL))
+		.build()?;
+
+	let response = client
+		.post(webhook)
+		.body(body)
+		.header(header::CONTENT_TYPE, "application/json")
+		.send()
+		.await?;
+
+	if !response.status().is_success() {
+		return Err(anyhow::anyhow!("webhook responded with {}", response.status()))
+	}
+
+	Ok(())
+}
+
+/// Supervised task : sample resource usage and alert before the open files limit is reached
+/// # Arguments
+/// * `state` - SharedState
+pub async fn resource_monitor(state: SharedState) -> Result<(), anyhow::Error> {
+	let config = resource_alert_config();
+	let mut interval = tokio::time::interval(Duration::from_secs(RESOURCE_MONITOR_INTERVAL));
+	let mut last_alert: Option<Instant> = None;
+
+	loop {
+		interval.tick().await;
+
+		let snapshot = resource_snapshot(&state).await;
+		debug!("RESOURCE MONITOR : {:?}", snapshot);
+
+		let usage = match snapshot.fd_usage_percent {
+			Some(usage) if usage >= config.fd_threshold_percent => usage,
+			_ => continue,
+		};
+
+		if let Some(last) = last_alert {
+			if last.elapsed().as_secs() < RESOURCE_ALERT_COOLDOWN {
+				continue
+			}
+		}
+		last_alert = Some(Instant::now());
+
+		let consumers = largest_consumers(MAX_RESOURCE_CONSUMERS);
+		let message = format!(
+			"RESOURCE MONITOR : {} of {} open files limit is used ({:.1}%)",
+			snapshot.open_fds.total,
+			snapshot.fd_limit.unwrap_or_default(),
+			usage
+		);
+		warn!(message);
+		sentry::with_scope(
+			|scope| {
+				scope.set_tag("resource-monitor", "fd");
+			},
+			|| sentry::capture_message(&message, sentry::Level::Warning),
+		);
+
+		if let Some(webhook) = &config.webhook {
+			let body = json!({
+				"enclave_address": get_accountid(&state).await,
+				"alert": message,
+				"snapshot": snapshot,
+				"consumers": consumers,
+			})
+			.to_string();
+
+			match send_alert(webhook, body).await {
+				Ok(_) => info!("RESOURCE MONITOR : alert is sent to webhook"),
+				Err(err) => error!("RESOURCE MONITOR : unable to send alert to webhook : {err:?}"),
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn procfs_parser_test() {
+		let limits =
+			"Limit                     Soft Limit           Hard Limit           Units     \n\
+			Max cpu time              unlimited            unlimited            seconds   \n\
+			Max open files            1024                 1048576              files     \n";
+		assert_eq!(parse_fd_limit(limits), Some(1024));
+		assert_eq!(
+			parse_fd_limit("Max open files            unlimited    unlimited    files"),
+			None
+		);
+
+		assert_eq!(parse_threads("Name:\tsgx_server\nThreads:\t17\n"), Some(17));
+
+		assert_eq!(classify_fd("/opt/sgx_enclave/nft/nft_13_100.keyshare"), FdKind::File);
+		assert_eq!(classify_fd("socket:[12345]"), FdKind::Socket);
+		assert_eq!(classify_fd("pipe:[67890]"), FdKind::Pipe);
+		assert_eq!(classify_fd("anon_inode:[eventpoll]"), FdKind::Other);
+
+		let tcp = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n   \
+			0: 00000000:1F90 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 111 1 0000000000000000 100 0 0 10 0\n   \
+			1: 0100007F:1F90 0100007F:D2F0 08 00000000:00000000 00:00000000 00000000     0        0 222 1 0000000000000000 20 4 30 10 -1\n";
+		let unix = "Num       RefCount Protocol Flags    Type St Inode Path\n\
+			0000000000000000: 00000002 00000000 00010000 0001 01 333 /run/aesmd.sock\n";
+
+		let mut sockets = HashMap::new();
+		parse_socket_table(tcp, "tcp", &mut sockets);
+		parse_socket_table(unix, "unix", &mut sockets);
+		assert_eq!(sockets.get(&111).unwrap(), "tcp LISTEN");
+		assert_eq!(sockets.get(&222).unwrap(), "tcp CLOSE_WAIT");
+		assert_eq!(sockets.get(&333).unwrap(), "unix");
+
+		assert_eq!(consumer_name(FdKind::Socket, "socket:[222]", &sockets), "tcp CLOSE_WAIT");
+		assert_eq!(consumer_name(FdKind::Socket, "socket:[999]", &sockets), "unknown");
+		assert_eq!(
+			consumer_name(FdKind::File, "/opt/sgx_enclave/nft/a.log", &sockets),
+			"/opt/sgx_enclave/nft"
+		);
+
+		let usage = fd_usage(&[
+			"socket:[1]".to_string(),
+			"socket:[2]".to_string(),
+			"/dev/null".to_string(),
+			"pipe:[3]".to_string(),
+		]);
+		assert_eq!(usage, FdUsage { total: 4, files: 1, sockets: 2, pipes: 1, others: 0 });
+	}
+}
@@ -0,0 +1,269 @@
+use std::collections::BTreeMap;
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{debug, info, warn};
+
+use crate::{
+	chain::{
+		constants::{MAINTENANCE_QUEUE_TIMEOUT, MAX_QUEUE_TIMEOUT, OPERATION_QUEUE_TIMEOUT},
+		quota::{apply_rate_limits, rate_limits, RateLimit},
+		verify::APICALL,
+	},
+	servers::{
+		auth::VerifiedCaller,
+		logging::{log_directives, parse_log_directives, set_log_directives},
+		state::{get_runtime_config, SharedState},
+	},
+};
+
+/* ---------------------------------------
+	RUNTIME CONFIGURATION
+--------------------------------------- */
+
+// Quotas, queuing timeouts and log directives can be changed by the admin quorum without a
+// restart of the enclave :
+// - an update only carries the changed settings, the other settings are kept
+// - every setting is validated before any of them is applied, an invalid update changes nothing
+// - the update is applied under the write lock of the SharedState, requests see the previous or the
+//   new configuration, never a mix of both
+// The runtime configuration is not persisted, a restart goes back to the command line.
+
+/// Settings which can be changed at runtime
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct RuntimeConfig {
+	// Token buckets of each requester account and ip address, per api call
+	pub rate_limits: BTreeMap<APICALL, RateLimit>,
+	// Seconds a keyshare request waits for a maintenance operation
+	pub operation_queue_timeout: u64,
+	// Seconds a backup, restore or synchronization waits for its turn
+	pub maintenance_queue_timeout: u64,
+}
+
+impl Default for RuntimeConfig {
+	/// Configuration of the command line
+	fn default() -> Self {
+		RuntimeConfig {
+			rate_limits: rate_limits().clone(),
+			operation_queue_timeout: OPERATION_QUEUE_TIMEOUT,
+			maintenance_queue_timeout: MAINTENANCE_QUEUE_TIMEOUT,
+		}
+	}
+}
+
+/// Changed settings, the missing ones are kept
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ConfigUpdate {
+	// "CALL=BURST/PER_MINUTE" or "CALL=off" items, as --rate-limit
+	#[serde(default)]
+	pub rate_limits: Vec<String>,
+	#[serde(default)]
+	pub operation_queue_timeout: Option<u64>,
+	#[serde(default)]
+	pub maintenance_queue_timeout: Option<u64>,
+	// Filter directives of the logs, as in RUST_LOG
+	#[serde(default)]
+	pub log_directives: Option<String>,
+}
+
+fn queue_timeout(name: &str, value: Option<u64>, current: u64) -> Result<u64, anyhow::Error> {
+	match value {
+		Some(timeout) if timeout == 0 || timeout > MAX_QUEUE_TIMEOUT => Err(anyhow::anyhow!(
+			"RUNTIME CONFIG : {name} must be between 1 and {MAX_QUEUE_TIMEOUT} seconds"
+		)),
+		Some(timeout) => Ok(timeout),
+		None => Ok(current),
+	}
+}
+
+impl ConfigUpdate {
+	fn is_empty(&self) -> bool {
+		self.rate_limits.is_empty() &&
+			self.operation_queue_timeout.is_none() &&
+			self.maintenance_queue_timeout.is_none() &&
+			self.log_directives.is_none()
+	}
+
+	/// Validate the update against the current configuration, nothing is applied
+	/// # Arguments
+	/// * `current` - current runtime configuration
+	/// # Returns
+	/// * `RuntimeConfig` - configuration once the update is applied
+	pub fn validate(&self, current: &RuntimeConfig) -> Result<RuntimeConfig, anyhow::Error> {
+		if self.is_empty() {
+			return Err(anyhow::anyhow!("RUNTIME CONFIG : update is empty"))
+		}
+
+		if let Some(directives) = &self.log_directives {
+			parse_log_directives(directives)?;
+		}
+
+		Ok(RuntimeConfig {
+			rate_limits: apply_rate_limits(current.rate_limits.clone(), &self.rate_limits)?,
+			operation_queue_timeout: queue_timeout(
+				"operation_queue_timeout",
+				self.operation_queue_timeout,
+				current.operation_queue_timeout,
+			)?,
+			maintenance_queue_timeout: queue_timeout(
+				"maintenance_queue_timeout",
+				self.maintenance_queue_timeout,
+				current.maintenance_queue_timeout,
+			)?,
+		})
+	}
+}
+
+/* ---------------------------------------
+	ADMIN RUNTIME CONFIGURATION API
+--------------------------------------- */
+
+/// Runtime configuration request, signed by the threshold of admin quorum
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RuntimeConfigPacket {
+	update: ConfigUpdate,
+}
+
+/// Canonical hash of the update, signed inside the authentication token
+/// # Arguments
+/// * `update` - changed settings
+pub fn runtime_config_data_hash(update: &ConfigUpdate) -> String {
+	let timeout = |value: Option<u64>| value.map(|timeout| timeout.to_string()).unwrap_or_default();
+	sha256::digest(
+		format!(
+			"runtime-config_{}_{}_{}_{}",
+			update.rate_limits.join(","),
+			timeout(update.operation_queue_timeout),
+			timeout(update.maintenance_queue_timeout),
+			update.log_directives.as_deref().unwrap_or_default()
+		)
+		.as_bytes(),
+	)
+}
+
+/// Effective runtime configuration
+pub async fn admin_config_status(State(state): State<SharedState>) -> impl IntoResponse {
+	(
+		StatusCode::OK,
+		Json(json!({
+			"config": get_runtime_config(&state).await,
+			"log_directives": log_directives(),
+		})),
+	)
+}
+
+/// Change the runtime configuration, the request must be signed by the threshold of admin quorum
+/// # Arguments
+/// * `state` - SharedState
+/// * `request` - RuntimeConfigPacket
+pub async fn admin_config_update(
+	State(state): State<SharedState>,
+	caller: VerifiedCaller,
+	Json(request): Json<RuntimeConfigPacket>,
+) -> impl IntoResponse {
+	debug!("ADMIN CONFIG : start");
+
+	if let Err((status, message)) =
+		caller.verify_data_hash(&runtime_config_data_hash(&request.update))
+	{
+		let message = format!("ADMIN CONFIG : {message}");
+		warn!(message);
+		return (status, Json(json!({ "error": message })))
+	}
+
+	let approvals = caller.approvals();
+
+	// Validated and applied under the write lock, concurrent updates are serialized
+	let (previous, config) = {
+		let shared_state_write = &mut state.write().await;
+		let previous = shared_state_write.get_runtime_config();
+
+		let config = match request.update.validate(&previous) {
+			Ok(config) => config,
+			Err(err) => {
+				let message = format!("ADMIN CONFIG : {err}");
+				warn!(message);
+				return (StatusCode::BAD_REQUEST, Json(json!({ "error": message })))
+			},
+		};
+
+		if let Some(directives) = &request.update.log_directives {
+			if let Err(err) = set_log_directives(directives) {
+				let message = format!("ADMIN CONFIG : {err}");
+				warn!(message);
+				return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })))
+			}
+		}
+
+		shared_state_write.set_runtime_config(config.clone());
+		(previous, config)
+	};
+
+	info!(
+		"ADMIN CONFIG : configuration {:?} is replaced by {:?} with {} approvals",
+		previous, config, approvals
+	);
+
+	(
+		StatusCode::OK,
+		Json(json!({
+			"config": config,
+			"log_directives": log_directives(),
+			"previous": previous,
+			"approvals": approvals,
+		})),
+	)
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn config_update_test() {
+		let current = RuntimeConfig {
+			rate_limits: BTreeMap::from([(
+				APICALL::NFTSTORE,
+				RateLimit { burst: 10, per_minute: 30 },
+			)]),
+			operation_queue_timeout: 10,
+			maintenance_queue_timeout: 20,
+		};
+
+		assert!(ConfigUpdate::default().validate(&current).is_err());
+
+		let update = ConfigUpdate {
+			rate_limits: vec!["NFTRETRIEVE=5/10".to_string(), "NFTSTORE=off".to_string()],
+			operation_queue_timeout: Some(30),
+			..Default::default()
+		};
+		let config = update.validate(&current).unwrap();
+		assert_eq!(
+			config.rate_limits,
+			BTreeMap::from([(APICALL::NFTRETRIEVE, RateLimit { burst: 5, per_minute: 10 })])
+		);
+		assert_eq!(config.operation_queue_timeout, 30);
+		assert_eq!(config.maintenance_queue_timeout, 20);
+
+		// One invalid setting rejects the whole update
+		let invalid = ConfigUpdate {
+			operation_queue_timeout: Some(30),
+			maintenance_queue_timeout: Some(MAX_QUEUE_TIMEOUT + 1),
+			..Default::default()
+		};
+		assert!(invalid.validate(&current).is_err());
+		let invalid = ConfigUpdate {
+			rate_limits: vec!["NFTSTORE=0/1".to_string()],
+			log_directives: Some("info".to_string()),
+			..Default::default()
+		};
+		assert!(invalid.validate(&current).is_err());
+
+		assert_ne!(runtime_config_data_hash(&update), runtime_config_data_hash(&invalid));
+	}
+}
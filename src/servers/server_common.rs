@@ -1,5 +1,5 @@
 use rustls::ServerConfig;
-use rustls_acme::{caches::DirCache, AcmeConfig};
+use rustls_acme::{axum::AxumAcceptor, caches::DirCache, AcmeConfig};
 use std::{
 	net::{Ipv4Addr, SocketAddr},
 	path::PathBuf,
@@ -13,18 +13,141 @@ use tokio_stream::StreamExt;
 use axum::Router;
 use axum_server::{tls_rustls::RustlsConfig, Handle};
 
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+	attestation::ratls::ra_tls_config,
+	servers::{
+		connection::connection_config,
+		listener::{admin_listener, listener_app, serve_admin, ListenerRole},
+	},
+};
+
+/// Bind the server port with SO_REUSEPORT, an upgraded instance binds the same port before the
+/// running instance stops accepting
+/// # Arguments
+/// * `port` - The port to bind
+pub fn bind_reuseport(port: u16) -> Result<std::net::TcpListener, anyhow::Error> {
+	bind_reuseport_addr(SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)))
+}
+
+/// Bind an address with SO_REUSEPORT, see bind_reuseport
+/// # Arguments
+/// * `address` - The address to bind
+pub fn bind_reuseport_addr(address: SocketAddr) -> Result<std::net::TcpListener, anyhow::Error> {
+	let socket = match address {
+		SocketAddr::V4(_) => tokio::net::TcpSocket::new_v4()?,
+		SocketAddr::V6(_) => tokio::net::TcpSocket::new_v6()?,
+	};
+	socket.set_reuseaddr(true)?;
+	if let Err(err) = socket.set_reuseport(true) {
+		warn!("SERVER INITIALIZATION : SO_REUSEPORT is not supported, upgrade handoff will fail to bind : {err:?}");
+	}
+	socket.bind(address)?;
+
+	Ok(socket.listen(1024)?.into_std()?)
+}
 
 /// Servers the server
 /// # Arguments
 /// * `app` - The app to serve
 /// * `domain` - The domain to serve
 /// * `port` - The port to serve
+/// * `handle` - The handle to drain the server on upgrade handoff
+/// * `listener` - Listener bound during an upgrade handoff, the cached certificate is used without
+///   certificate server
+/// * `ra_tls` - Serve the RA-TLS certificate bound to the quote, without certificate server
 /// # Returns
 /// * `Result<(), anyhow::Error>` - The result of the server
-pub async fn serve(app: Router, domain: &str, port: &u16) -> Result<(), anyhow::Error> {
+pub async fn serve(
+	app: Router,
+	domain: &str,
+	port: &u16,
+	handle: Handle,
+	listener: Option<std::net::TcpListener>,
+	ra_tls: bool,
+) -> Result<(), anyhow::Error> {
 	info!("SERVER INITIALIZATION : Startng server with app, domain, port.");
 
+	let (config, listener) = if ra_tls {
+		info!("SERVER INITIALIZATION : generate RA-TLS certificate.");
+		let config = ra_tls_config(domain).await?;
+		let listener = match listener {
+			Some(listener) => listener,
+			None => bind_reuseport(*port)?,
+		};
+		(config, listener)
+	} else {
+		acme_config(domain, port, listener).await?
+	};
+
+	// HTTP/2 is negotiated in the TLS handshake
+	let connection = connection_config();
+	let mut server_config = (*config.get_inner()).clone();
+	server_config.alpn_protocols = connection.alpn_protocols();
+	config.reload_from_config(Arc::new(server_config));
+	info!("SERVER INITIALIZATION : connection settings : {:?}", connection);
+
+	let socket_addr = SocketAddr::from((Ipv4Addr::UNSPECIFIED, *port));
+	info!("SERVER INITIALIZATION : SGX Server is listening {}'\n", socket_addr);
+
+	let public_app = listener_app(app.clone(), ListenerRole::PUBLIC);
+	let sgx_server_handle = axum_server::from_tcp_rustls(listener, config.clone())
+		//.acceptor(acceptor)
+		.handle(handle.clone())
+		.http_config(connection.http_config())
+		.addr_incoming_config(connection.incoming_config())
+		.serve(public_app.into_make_service_with_connect_info::<SocketAddr>());
+
+	// DOES IT MAKE SENSE? SINCE AXUM IS INSIDE TOKIO THREAD IN MAIN FUNCTION!
+	//let sgx_server = tokio::spawn(sgx_server_handle);
+
+	// The admin listener stops with the public listener, a failed admin listener stops both
+	let served = match admin_listener() {
+		Some(admin) => {
+			tokio::pin!(sgx_server_handle);
+			tokio::select! {
+				served = &mut sgx_server_handle => served,
+				admin_served = serve_admin(app, admin, config, handle) => match admin_served {
+					Ok(_) => sgx_server_handle.await,
+					Err(err) => {
+						error!("SERVER INITIALIZATION : Error in admin listener : {err:?}");
+						return Err(err)
+					},
+				},
+			}
+		},
+		None => sgx_server_handle.await,
+	};
+
+	debug!("SERVER INITIALIZATION : server exit\n");
+	//match tokio::try_join!(sgx_server) {
+	match served {
+		Ok(_) => {
+			info!("SERVER INITIALIZATION : SGX Server finished successfully");
+			Ok(())
+		},
+
+		Err(err) => {
+			error!("SERVER INITIALIZATION : Error in SGX server : {}", err);
+			Err(anyhow::anyhow!(format!("SERVER INITIALIZATION : Error in sgx server : {err}")))
+		},
+	}
+}
+
+/// Let's Encrypt certificate of the domain, the certificate server runs before the listener is
+/// bound
+/// # Arguments
+/// * `domain` - The domain of the certificate
+/// * `port` - The port to bind
+/// * `listener` - Listener bound during an upgrade handoff
+/// # Returns
+/// * `(RustlsConfig, std::net::TcpListener)` - The rust-TLS config and the listener of the server
+async fn acme_config(
+	domain: &str,
+	port: &u16,
+	listener: Option<std::net::TcpListener>,
+) -> Result<(RustlsConfig, std::net::TcpListener), anyhow::Error> {
 	let socket_addr = SocketAddr::from((Ipv4Addr::UNSPECIFIED, 443));
 
 	info!("SERVER INITIALIZATION : starting certificate server on {}", socket_addr);
@@ -64,6 +187,27 @@ pub async fn serve(app: Router, domain: &str, port: &u16) -> Result<(), anyhow::
 
 	let config = RustlsConfig::from_config(Arc::new(rustls_config.clone()));
 
+	let listener = match listener {
+		Some(listener) => listener,
+		None => {
+			cert_server(socket_addr, config.clone(), acceptor).await?;
+			bind_reuseport(*port)?
+		},
+	};
+
+	Ok((config, listener))
+}
+
+/// Serve the ACME challenges on port 443 for 20 seconds
+/// # Arguments
+/// * `socket_addr` - The address of the certificate server
+/// * `config` - The rust-TLS config
+/// * `acceptor` - The ACME acceptor
+async fn cert_server(
+	socket_addr: SocketAddr,
+	config: RustlsConfig,
+	acceptor: AxumAcceptor,
+) -> Result<(), anyhow::Error> {
 	let dummy_app =
 		Router::new().route("/", axum::routing::get(|| async { "Server is updating!" }));
 
@@ -72,8 +216,8 @@ pub async fn serve(app: Router, domain: &str, port: &u16) -> Result<(), anyhow::
 	tokio::spawn(cert_shutdown(handle.clone()));
 
 	info!("SERVER INITIALIZATION : start cert server");
-	let cert_server = axum_server::bind_rustls(socket_addr, config.clone())
-		.acceptor(acceptor.clone())
+	let cert_server = axum_server::bind_rustls(socket_addr, config)
+		.acceptor(acceptor)
 		.handle(handle)
 		.serve(dummy_app.into_make_service())
 		.await;
@@ -98,29 +242,7 @@ pub async fn serve(app: Router, domain: &str, port: &u16) -> Result<(), anyhow::
 		},
 	}
 
-	let socket_addr = SocketAddr::from((Ipv4Addr::UNSPECIFIED, *port));
-	info!("SERVER INITIALIZATION : SGX Server is listening {}'\n", socket_addr);
-
-	let sgx_server_handle = axum_server::bind_rustls(socket_addr, config)
-		//.acceptor(acceptor)
-		.serve(app.into_make_service_with_connect_info::<SocketAddr>());
-
-	// DOES IT MAKE SENSE? SINCE AXUM IS INSIDE TOKIO THREAD IN MAIN FUNCTION!
-	//let sgx_server = tokio::spawn(sgx_server_handle);
-
-	debug!("SERVER INITIALIZATION : server exit\n");
-	//match tokio::try_join!(sgx_server) {
-	match sgx_server_handle.await {
-		Ok(_) => {
-			info!("SERVER INITIALIZATION : SGX Server finished successfully");
-			Ok(())
-		},
-
-		Err(err) => {
-			error!("SERVER INITIALIZATION : Error in SGX server : {}", err);
-			Err(anyhow::anyhow!(format!("SERVER INITIALIZATION : Error in sgx server : {err}")))
-		},
-	}
+	Ok(())
 }
 
 /// Shutdown the server
@@ -0,0 +1,150 @@
+use std::{
+	fs::File,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Mutex,
+	},
+	time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use axum::{
+	body::Body,
+	http::{Request, StatusCode},
+	middleware::Next,
+	response::{IntoResponse, Response},
+	Json,
+};
+use axum_server::Handle;
+use futures::future::BoxFuture;
+use serde_json::json;
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+	chain::{
+		constants::{KEYSHARE_INDEX_FILE, SEALPATH, SHUTDOWN_DRAIN_TIMEOUT, SHUTDOWN_HOOK_TIMEOUT},
+		helper::save_keyshare_index,
+		replay::compact_replay_journal,
+	},
+	servers::{
+		state::{get_nft_availability_map, SharedState},
+		versioning::endpoint_path,
+	},
+};
+
+/* ---------------------------------------
+	GRACEFUL SHUTDOWN
+--------------------------------------- */
+
+// SIGTERM (injected by Gramine with sys.enable_sigterm_injection) and SIGINT stop the enclave
+// without cutting a store or a backup in the middle :
+// 1. The health check reports the maintenance status and new requests are rejected
+// 2. The server stops accepting connections and drains the in-flight requests, with a timeout
+// 3. The shutdown hooks of the subsystems run in reverse registration order
+// 4. Pending writes of the sealed directory are flushed, then the background tasks are stopped
+
+/// Shutdown hook of a subsystem, i.e. waiting for a backup or flushing the sync state
+pub type ShutdownHook = Box<dyn FnOnce() -> BoxFuture<'static, Result<(), anyhow::Error>> + Send>;
+
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+static SHUTDOWN_HOOKS: Mutex<Vec<(String, ShutdownHook)>> = Mutex::new(Vec::new());
+
+/// Whether the enclave is shutting down
+pub fn is_shutting_down() -> bool {
+	SHUTTING_DOWN.load(Ordering::SeqCst)
+}
+
+/// Register a hook run once the in-flight requests are drained
+/// # Arguments
+/// * `name` - subsystem name, for the logs
+/// * `hook` - hook, it is stopped after the hook timeout
+pub fn register_shutdown_hook(name: &str, hook: ShutdownHook) -> Result<(), anyhow::Error> {
+	SHUTDOWN_HOOKS
+		.lock()
+		.map_err(|err| anyhow!("SHUTDOWN : lock error : {err:?}"))?
+		.push((name.to_string(), hook));
+	Ok(())
+}
+
+/// Wait for a termination signal
+/// # Returns
+/// * `&str` - name of the received signal
+pub async fn shutdown_signal() -> &'static str {
+	let mut terminate = match signal(SignalKind::terminate()) {
+		Ok(terminate) => terminate,
+		Err(err) => {
+			error!("SHUTDOWN : unable to listen to SIGTERM : {err:?}");
+			let _ = tokio::signal::ctrl_c().await;
+			return "SIGINT"
+		},
+	};
+
+	tokio::select! {
+		_ = terminate.recv() => "SIGTERM",
+		_ = tokio::signal::ctrl_c() => "SIGINT",
+	}
+}
+
+/// Stop accepting requests and start draining the in-flight requests, the server future returns
+/// once they are done or after the drain timeout
+/// # Arguments
+/// * `state` - SharedState, its maintenance message is set
+/// * `handle` - handle of the api server
+pub async fn begin_shutdown(state: &SharedState, handle: &Handle) {
+	SHUTTING_DOWN.store(true, Ordering::SeqCst);
+	state.write().await.set_maintenance("Enclave is shutting down".to_string());
+
+	info!("SHUTDOWN : stop accepting, drain {} connections", handle.connection_count());
+	handle.graceful_shutdown(Some(Duration::from_secs(SHUTDOWN_DRAIN_TIMEOUT)));
+}
+
+/// Run the shutdown hooks and flush the pending writes, once the server is drained
+pub async fn finish_shutdown(state: &SharedState) {
+	let hooks = match SHUTDOWN_HOOKS.lock() {
+		Ok(mut hooks) => std::mem::take(&mut *hooks),
+		Err(err) => {
+			error!("SHUTDOWN : lock error : {err:?}");
+			Vec::new()
+		},
+	};
+
+	for (name, hook) in hooks.into_iter().rev() {
+		let started = Instant::now();
+		match tokio::time::timeout(Duration::from_secs(SHUTDOWN_HOOK_TIMEOUT), hook()).await {
+			Ok(Ok(_)) => info!("SHUTDOWN : {name} is stopped in {:?}", started.elapsed()),
+			Ok(Err(err)) => error!("SHUTDOWN : {name} hook failed : {err:?}"),
+			Err(_) => warn!("SHUTDOWN : {name} hook timed out"),
+		}
+	}
+
+	if let Err(err) = flush_pending_writes(state).await {
+		error!("SHUTDOWN : error flushing the sealed directory : {err:?}");
+	}
+}
+
+/// Persist the replay journal and the availability index, then sync the sealed directory to the
+/// disk
+async fn flush_pending_writes(state: &SharedState) -> Result<(), anyhow::Error> {
+	compact_replay_journal(state).await?;
+	save_keyshare_index(KEYSHARE_INDEX_FILE, &get_nft_availability_map(state).await)?;
+	File::open(SEALPATH)?.sync_all()?;
+	debug!("SHUTDOWN : sealed directory is flushed");
+	Ok(())
+}
+
+/// Middleware rejecting the requests which arrive while the server is draining, the health check
+/// and the probes still report the maintenance status
+pub async fn shutdown_guard(request: Request<Body>, next: Next<Body>) -> Response {
+	if !is_shutting_down() ||
+		matches!(endpoint_path(&request).as_str(), "/api/health" | "/api/live" | "/api/ready")
+	{
+		return next.run(request).await
+	}
+
+	(
+		StatusCode::SERVICE_UNAVAILABLE,
+		Json(json!({ "description": "Enclave is shutting down, try another enclave." })),
+	)
+		.into_response()
+}
@@ -0,0 +1,348 @@
+use std::sync::OnceLock;
+
+use axum::{
+	body::{boxed, Body, Bytes, Full, HttpBody},
+	extract::State,
+	http::{
+		header::{CONTENT_LENGTH, CONTENT_TYPE},
+		HeaderValue, Request, StatusCode,
+	},
+	middleware::Next,
+	response::{IntoResponse, Response},
+	Json,
+};
+use serde_json::{json, Value};
+use subxt::ext::sp_core::{crypto::Ss58Codec, Pair};
+use tokio::sync::Mutex;
+use tracing::{debug, error, warn};
+
+use crate::{
+	attestation::{
+		keys::KeyPurpose,
+		ra::{create_quote, ReportDataPreimage},
+	},
+	chain::{
+		constants::{MAX_SIGNED_REQUEST_SIZE, MAX_SIGNED_RESPONSE_SIZE},
+		quota::check_quote_rate,
+	},
+	servers::{
+		state::{get_accountid, get_blocknumber, get_subkey, get_subkeys, SharedState},
+		versioning::endpoint_path,
+	},
+};
+
+/* ---------------------------------------
+	RESPONSE SIGNING
+--------------------------------------- */
+
+// Endpoints of which the responses are signed, including verification errors
+const SIGNED_ENDPOINTS: [&str; 9] = [
+	"/api/secret-nft/store-keyshare",
+	"/api/secret-nft/retrieve-keyshare",
+	"/api/secret-nft/remove-keyshare",
+	"/api/secret-nft/batch-store-keyshare",
+	"/api/secret-nft/batch-retrieve-keyshare",
+	"/api/capsule-nft/set-keyshare",
+	"/api/capsule-nft/update-keyshare",
+	"/api/capsule-nft/retrieve-keyshare",
+	"/api/capsule-nft/remove-keyshare",
+];
+
+// Status of batch responses, their items are signed in the data
+const BATCH_STATUS: &str = "BATCH";
+
+/// Data of a response covered by its signature : the keyshare of a retrieve, or one
+/// "NFTID:STATUS[:KEYSHARE]" line per item of a batch, empty otherwise
+pub fn response_data(body: &Value) -> String {
+	if let Some(keyshare) = body["keyshare_data"].as_str() {
+		return keyshare.to_string()
+	}
+
+	match body["results"].as_array() {
+		Some(results) => results
+			.iter()
+			.map(|item| {
+				let nft_id = item["nft_id"].as_u64().unwrap_or_default();
+				let status = item["status"].as_str().unwrap_or_default();
+				match body["keyshares"][nft_id.to_string()].as_str() {
+					Some(keyshare) => format!("{nft_id}:{status}:{keyshare}"),
+					None => format!("{nft_id}:{status}"),
+				}
+			})
+			.collect::<Vec<String>>()
+			.join("\n"),
+		None => String::new(),
+	}
+}
+
+/// Message signed by the response subkey
+/// # Arguments
+/// * `status` - status of the response, "BATCH" for batch responses
+/// * `nft_id` - nft_id of the response, 0 for batch responses
+/// * `block_number` - block number of the enclave when the response is signed
+/// * `request_digest` - sha256 of the request body
+/// * `data_digest` - sha256 of the response data
+pub fn response_message(
+	status: &str,
+	nft_id: u64,
+	block_number: u32,
+	request_digest: &str,
+	data_digest: &str,
+) -> String {
+	format!("enclave-response_{status}_{nft_id}_{block_number}_{request_digest}_{data_digest}")
+}
+
+async fn read_body<B: HttpBody<Data = Bytes> + Unpin>(
+	mut body: B,
+	limit: usize,
+) -> Option<Vec<u8>> {
+	let mut bytes = Vec::new();
+	while let Some(chunk) = body.data().await {
+		let chunk = chunk.ok()?;
+		if bytes.len() + chunk.len() > limit {
+			return None
+		}
+		bytes.extend_from_slice(&chunk);
+	}
+	Some(bytes)
+}
+
+/// Middleware signing the json responses of keyshare endpoints with the response subkey.
+/// The signature binds the status, nft_id and data of the response to the request body, so
+/// a client can prove which answer the enclave gave to its request.
+pub async fn signing_guard(
+	State(state): State<SharedState>,
+	request: Request<Body>,
+	next: Next<Body>,
+) -> Response {
+	if !SIGNED_ENDPOINTS.contains(&endpoint_path(&request).as_str()) {
+		return next.run(request).await
+	}
+
+	let (parts, body) = request.into_parts();
+	let request_bytes = match read_body(body, MAX_SIGNED_REQUEST_SIZE).await {
+		Some(bytes) => bytes,
+		None =>
+			return (
+				StatusCode::PAYLOAD_TOO_LARGE,
+				Json(json!({ "description": "Request body is too large" })),
+			)
+				.into_response(),
+	};
+	let request_digest = sha256::digest(request_bytes.as_slice());
+
+	let response = next.run(Request::from_parts(parts, Body::from(request_bytes))).await;
+
+	let is_json = response
+		.headers()
+		.get(CONTENT_TYPE)
+		.and_then(|value| value.to_str().ok())
+		.map(|value| value.starts_with("application/json"))
+		.unwrap_or(false);
+	if !is_json {
+		return response
+	}
+
+	let (mut parts, body) = response.into_parts();
+	let mut json_body: Value = match read_body(body, MAX_SIGNED_RESPONSE_SIZE)
+		.await
+		.and_then(|bytes| serde_json::from_slice(&bytes).ok())
+	{
+		Some(json_body) => json_body,
+		None => {
+			error!("RESPONSE SIGNING : unable to read the response body");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response()
+		},
+	};
+
+	if !json_body.is_object() {
+		return (parts.status, Json(json_body)).into_response()
+	}
+
+	let status = json_body["status"].as_str().unwrap_or(BATCH_STATUS).to_string();
+	let nft_id = json_body["nft_id"].as_u64().unwrap_or_default();
+	let block_number = get_blocknumber(&state).await;
+	let data_digest = sha256::digest(response_data(&json_body));
+
+	let response_key = get_subkey(&state, KeyPurpose::RESPONSE).await;
+	let message = response_message(&status, nft_id, block_number, &request_digest, &data_digest);
+	let signature = response_key.sign(message.as_bytes());
+
+	debug!("RESPONSE SIGNING : {} of nft_id {} at block {}", status, nft_id, block_number);
+
+	json_body["enclave_signature"] = json!({
+		"signer": response_key.public().to_ss58check(),
+		"block_number": block_number,
+		"request_digest": request_digest,
+		"data_digest": data_digest,
+		"signature": format!("{}{:?}", "0x", signature),
+	});
+
+	let bytes = match serde_json::to_vec(&json_body) {
+		Ok(bytes) => bytes,
+		Err(err) => {
+			error!("RESPONSE SIGNING : unable to serialize the response body : {err:?}");
+			return StatusCode::INTERNAL_SERVER_ERROR.into_response()
+		},
+	};
+
+	parts.headers.insert(CONTENT_LENGTH, HeaderValue::from(bytes.len()));
+	Response::from_parts(parts, boxed(Full::from(bytes)))
+}
+
+// The quote of the response key is generated once per key, it is served again to every caller
+// until the enclave account or the response subkey rotates.
+static RESPONSE_KEY_QUOTE: OnceLock<Mutex<Option<ResponseKeyQuote>>> = OnceLock::new();
+
+/// Quote served with a response key
+#[derive(Clone, Debug, PartialEq)]
+struct ResponseKeyQuote {
+	enclave_account: String,
+	response_key: String,
+	preimage: ReportDataPreimage,
+	quote: String,
+}
+
+impl ResponseKeyQuote {
+	/// Whether the quote was generated for these keys
+	fn is_bound_to(&self, enclave_account: &str, response_key: &str) -> bool {
+		self.enclave_account == enclave_account && self.response_key == response_key
+	}
+}
+
+/// New quote of the response key, charged to the quotes of all the callers
+/// # Arguments
+/// * `state` - SharedState
+/// * `enclave_account` - current enclave account
+/// * `response_key` - current response subkey
+async fn response_key_quote(
+	state: &SharedState,
+	enclave_account: &str,
+	response_key: &str,
+) -> Result<ResponseKeyQuote, String> {
+	if let Err(rejection) = check_quote_rate(None) {
+		warn!("RESPONSE KEY : quote is rejected : {rejection:?}");
+		return Err(format!("{rejection:?}"))
+	}
+
+	let (preimage, quote) = create_quote(state).await;
+	match quote {
+		Ok(quote) => Ok(ResponseKeyQuote {
+			enclave_account: enclave_account.to_string(),
+			response_key: response_key.to_string(),
+			preimage,
+			quote: hex::encode(quote),
+		}),
+		Err(err) => {
+			error!("RESPONSE KEY : unable to create the quote : {err}");
+			Err(err.to_string())
+		},
+	}
+}
+
+/// Key of signed responses and its binding to the attested enclave : the response subkey is
+/// certified by the enclave account, the enclave account signs the report data of the quote
+/// # Returns
+/// * `Json` - response subkey, subkeys certificate and the quote of the current keys
+pub async fn response_key(State(state): State<SharedState>) -> impl IntoResponse {
+	let subkeys = get_subkeys(&state).await;
+	let enclave_account = get_accountid(&state).await;
+	let response_key = subkeys.get(KeyPurpose::RESPONSE).public().to_ss58check();
+
+	let mut cache = RESPONSE_KEY_QUOTE.get_or_init(|| Mutex::new(None)).lock().await;
+	let cached = match cache.as_ref() {
+		Some(cached) if cached.is_bound_to(&enclave_account, &response_key) => Ok(cached.clone()),
+		// A new quote is only generated after a key rotation, or a failed generation
+		_ => response_key_quote(&state, &enclave_account, &response_key).await,
+	};
+	if let Ok(cached) = &cached {
+		*cache = Some(cached.clone());
+	}
+	drop(cache);
+
+	let (preimage, quote, quote_error) = match cached {
+		Ok(cached) => (Some(cached.preimage), Some(cached.quote), None),
+		Err(err) => (None, None, Some(err)),
+	};
+
+	(
+		StatusCode::OK,
+		Json(json!({
+			"enclave_account": enclave_account,
+			"response_key": response_key,
+			"subkeys": subkeys.public_keys(),
+			// Signature of "PURPOSE=address;..." by the enclave account
+			"subkeys_certificate": subkeys.certificate(),
+			// Report data is SHA256(PUBLICKEY || API_URL || BLOCKNUMBER) of this preimage
+			"quote_block_number": preimage.as_ref().map(|preimage| preimage.block_number),
+			"quote": quote,
+			"report_data_preimage": preimage,
+			"quote_error": quote_error,
+			"message": "enclave-response_STATUS_NFTID_BLOCKNUMBER_SHA256(REQUEST)_SHA256(DATA)",
+		})),
+	)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use subxt::ext::sp_core::sr25519;
+
+	#[test]
+	fn response_data_test() {
+		let retrieve = json!({
+			"status": "RETRIEVESUCCESS",
+			"nft_id": 10,
+			"keyshare_data": "secret_100_15",
+		});
+		assert_eq!(response_data(&retrieve), "secret_100_15");
+
+		let batch = json!({
+			"results": [
+				{ "nft_id": 10, "status": "RETRIEVESUCCESS" },
+				{ "nft_id": 11, "status": "NOTSYNCED" },
+			],
+			"keyshares": { "10": "secret_100_15" },
+		});
+		assert_eq!(response_data(&batch), "10:RETRIEVESUCCESS:secret_100_15\n11:NOTSYNCED");
+
+		let store = json!({ "status": "STORESUCCESS", "nft_id": 10 });
+		assert_eq!(response_data(&store), "");
+
+		let key = sr25519::Pair::from_seed(&[3u8; 32]);
+		let message = response_message(
+			"STORESUCCESS",
+			10,
+			100,
+			&sha256::digest("request"),
+			&sha256::digest(""),
+		);
+		let signature = key.sign(message.as_bytes());
+		assert!(sr25519::Pair::verify(&signature, message.as_bytes(), &key.public()));
+		assert!(!sr25519::Pair::verify(
+			&signature,
+			response_message("STORESUCCESS", 11, 100, &sha256::digest("request"), "").as_bytes(),
+			&key.public()
+		));
+	}
+
+	#[test]
+	fn response_key_quote_test() {
+		let enclave_key = sr25519::Pair::from_seed(&[7u8; 32]);
+		let cached = ResponseKeyQuote {
+			enclave_account: enclave_key.public().to_ss58check(),
+			response_key: key_address(3),
+			preimage: ReportDataPreimage::new(&enclave_key.public(), "https://enclave", 100),
+			quote: hex::encode([1u8; 64]),
+		};
+
+		// The cached quote is served until a key rotates
+		assert!(cached.is_bound_to(&enclave_key.public().to_ss58check(), &key_address(3)));
+		assert!(!cached.is_bound_to(&enclave_key.public().to_ss58check(), &key_address(4)));
+		assert!(!cached.is_bound_to(&key_address(5), &key_address(3)));
+	}
+
+	fn key_address(seed: u8) -> String {
+		sr25519::Pair::from_seed(&[seed; 32]).public().to_ss58check()
+	}
+}
@@ -1,11 +1,32 @@
-use std::{collections::BTreeMap, sync::Arc};
+use std::{collections::BTreeMap, sync::Arc, time::Instant};
 use subxt::{ext::sp_core::sr25519, tx::PairSigner};
 
 use tokio::sync::RwLock;
 
 use crate::{
-	backup::sync::Cluster,
-	chain::{core::DefaultApi, helper},
+	attestation::keys::{EnclaveSubkeys, KeyPurpose},
+	backup::{
+		maintenance::MaintenanceWindow,
+		provision::{provisioned_rate_limits, ProvisionWindow},
+		quorum::QuorumConfig,
+		readonly::ReadOnlySwitch,
+		sync::Cluster,
+		upgrade::UpgradeArm,
+	},
+	chain::{
+		audit::AuditHead,
+		commitment::{keyshare_hash, StorageProof, StorageTree, TreeHash},
+		core::DefaultApi,
+		helper,
+		killswitch::MaintenanceMode,
+		quota::{QuotaKey, QuotaLimiter, QuotaStats},
+		replay::ReplayJournal,
+		verify::APICALL,
+	},
+	servers::{
+		coordination::OperationCoordinator, health::Readiness, runtime::RuntimeConfig,
+		supervisor::TaskRegistry,
+	},
 };
 
 pub type SharedState = Arc<RwLock<StateConfig>>;
@@ -15,9 +36,15 @@ pub struct StateConfig {
 	enclave_key: sr25519::Pair,
 	enclave_account: String,
 	enclave_signer: PairSigner<subxt::PolkadotConfig, sr25519::Pair>,
+	// Purpose-specific keys derived from enclave_key
+	enclave_subkeys: EnclaveSubkeys,
 	maintenance: String,
 	rpc_client: DefaultApi,
+	// Dual-rpc verification mode : independent endpoint for cross-checking onchain data
+	secondary_rpc_client: Option<DefaultApi>,
 	current_block: u32,
+	// Arrival of the current block, None until the first finalized block
+	block_updated: Option<Instant>,
 	nonce: u64,
 	clusters: Vec<Cluster>,
 	// Identity is (ClusterID, SlotID)
@@ -26,6 +53,37 @@ pub struct StateConfig {
 	// only for dev
 	last_processed_block: u32,
 	nft_block_map: BTreeMap<u32, helper::Availability>,
+	// Sparse Merkle commitment of the keyshares in nft_block_map
+	storage_tree: StorageTree,
+	// Admin quorum, None means bootstrap quorum of admin cluster
+	quorum: Option<QuorumConfig>,
+	pending_quorum: Option<QuorumConfig>,
+	// Recently-seen retrieve requests, persisted in sealed directory
+	replay_journal: ReplayJournal,
+	// Last record of the verification audit trail, persisted in sealed directory
+	audit_head: AuditHead,
+	// Token buckets of requester accounts and ip addresses
+	quota: QuotaLimiter,
+	// Minimum keyshare size to be compressed at rest, 0 is disabled
+	compression_threshold: usize,
+	// Expected mint events of launch partners
+	provision_windows: Vec<ProvisionWindow>,
+	// Governance kill-switch, set by onchain remark
+	maintenance_mode: MaintenanceMode,
+	// Admin read-only switch, mutations are rejected until its expiry block
+	read_only: Option<ReadOnlySwitch>,
+	// Admin maintenance window, keyshare requests get the maintenance notice
+	maintenance_window: Option<MaintenanceWindow>,
+	// Admin approval of an upgrade handoff to a new binary
+	upgrade_arm: Option<UpgradeArm>,
+	// Status of supervised background tasks
+	task_registry: TaskRegistry,
+	// Read and write intents of keyshare requests, exclusive maintenance operations
+	coordinator: OperationCoordinator,
+	// Rate limits and queuing timeouts, changed at runtime by the admin quorum
+	runtime_config: RuntimeConfig,
+	// Readiness holds of the sync and backup subsystems
+	readiness: Readiness,
 }
 
 impl StateConfig {
@@ -46,18 +104,37 @@ impl StateConfig {
 		};
 
 		StateConfig {
+			enclave_subkeys: EnclaveSubkeys::derive(&enclave_key),
 			enclave_key: enclave_key.clone(),
 			enclave_account: public_key,
 			enclave_signer: PairSigner::new(enclave_key),
 			maintenance,
 			rpc_client,
+			secondary_rpc_client: None,
 			current_block: 0,
+			block_updated: None,
 			last_processed_block,
 			nonce: 0,
 			clusters: Vec::<Cluster>::new(),
 			identity: None,
 			binary_version,
+			storage_tree: StorageTree::from_availability(&nft_block_map),
 			nft_block_map,
+			quorum: None,
+			pending_quorum: None,
+			replay_journal: ReplayJournal::default(),
+			audit_head: AuditHead::default(),
+			quota: QuotaLimiter::default(),
+			compression_threshold: 0,
+			provision_windows: Vec::new(),
+			maintenance_mode: MaintenanceMode::NORMAL,
+			read_only: None,
+			maintenance_window: None,
+			upgrade_arm: None,
+			task_registry: TaskRegistry::default(),
+			coordinator: OperationCoordinator::default(),
+			runtime_config: RuntimeConfig::default(),
+			readiness: Readiness::default(),
 		}
 	}
 
@@ -85,9 +162,18 @@ impl StateConfig {
 		};
 
 		self.enclave_account = public_key;
+		self.enclave_subkeys = EnclaveSubkeys::derive(&keypair);
 		self.enclave_signer = PairSigner::new(keypair);
 	}
 
+	pub fn get_subkeys(&self) -> EnclaveSubkeys {
+		self.enclave_subkeys.clone()
+	}
+
+	pub fn get_subkey(&self, purpose: KeyPurpose) -> sr25519::Pair {
+		self.enclave_subkeys.get(purpose)
+	}
+
 	pub fn get_maintenance(&self) -> String {
 		self.maintenance.clone()
 	}
@@ -104,14 +190,27 @@ impl StateConfig {
 		self.rpc_client = new_client;
 	}
 
+	pub fn get_secondary_rpc_client(&self) -> Option<DefaultApi> {
+		self.secondary_rpc_client.clone()
+	}
+
+	pub fn set_secondary_rpc_client(&mut self, client: Option<DefaultApi>) {
+		self.secondary_rpc_client = client;
+	}
+
 	pub fn set_current_block(&mut self, block_number: u32) {
 		self.current_block = block_number;
+		self.block_updated = Some(Instant::now());
 	}
 
 	pub fn get_current_block(&self) -> u32 {
 		self.current_block
 	}
 
+	pub fn get_block_updated(&self) -> Option<Instant> {
+		self.block_updated
+	}
+
 	pub fn set_processed_block(&mut self, last_processed_block: u32) {
 		self.last_processed_block = last_processed_block;
 	}
@@ -171,17 +270,187 @@ impl StateConfig {
 		self.nft_block_map.len() as u32
 	}
 
-	pub fn set_nft_availability(&mut self, nftid_block: (u32, helper::Availability)) {
+	pub fn set_nft_availability(
+		&mut self,
+		nftid_block: (u32, helper::Availability),
+		keyshare_hash: TreeHash,
+	) {
 		// Identity is (ClusterID, SlotID)
 		self.nft_block_map.insert(nftid_block.0, nftid_block.1);
+		self.storage_tree.update(nftid_block.0, Some(keyshare_hash));
 		tracing::trace!("\nAVAILABILITY : LOW LEVEL : SET : MAP : {:#?}", self.nft_block_map);
 	}
 
 	pub fn remove_nft_availability(&mut self, nftid: u32) {
 		// Identity is (ClusterID, SlotID)
 		self.nft_block_map.remove(&nftid);
+		self.storage_tree.update(nftid, None);
 		tracing::trace!("\nAVAILABILITY : LOW LEVEL : REMOVE : MAP : {:#?}", self.nft_block_map);
 	}
+
+	pub fn get_storage_tree(&self) -> &StorageTree {
+		&self.storage_tree
+	}
+
+	pub fn get_quorum(&self) -> Option<QuorumConfig> {
+		self.quorum.clone()
+	}
+
+	pub fn set_quorum(&mut self, quorum: Option<QuorumConfig>) {
+		self.quorum = quorum;
+	}
+
+	pub fn get_pending_quorum(&self) -> Option<QuorumConfig> {
+		self.pending_quorum.clone()
+	}
+
+	pub fn set_pending_quorum(&mut self, quorum: Option<QuorumConfig>) {
+		self.pending_quorum = quorum;
+	}
+
+	pub fn get_replay_journal(&self) -> &ReplayJournal {
+		&self.replay_journal
+	}
+
+	pub fn set_replay_journal(&mut self, journal: ReplayJournal) {
+		self.replay_journal = journal;
+	}
+
+	pub fn get_audit_head(&self) -> &AuditHead {
+		&self.audit_head
+	}
+
+	pub fn set_audit_head(&mut self, head: AuditHead) {
+		self.audit_head = head;
+	}
+
+	pub fn record_request_digest(
+		&mut self,
+		digest: String,
+		expiry_block: u32,
+		current_block: u32,
+	) -> bool {
+		self.replay_journal.record(digest, expiry_block, current_block)
+	}
+
+	pub fn check_quota(
+		&mut self,
+		call: APICALL,
+		keys: &[QuotaKey],
+		cost: u32,
+		now: std::time::Instant,
+	) -> Result<(), u64> {
+		self.quota.check(&self.runtime_config.rate_limits, call, keys, cost, now)
+	}
+
+	/// Quota of a provisioned owner, with the raised store limits of its window
+	pub fn check_provisioned_quota(
+		&mut self,
+		call: APICALL,
+		keys: &[QuotaKey],
+		cost: u32,
+		now: std::time::Instant,
+	) -> Result<(), u64> {
+		let limits = provisioned_rate_limits(&self.runtime_config.rate_limits);
+		self.quota.check(&limits, call, keys, cost, now)
+	}
+
+	pub fn peek_quota(
+		&self,
+		call: APICALL,
+		keys: &[QuotaKey],
+		cost: u32,
+		now: std::time::Instant,
+	) -> Result<(), u64> {
+		self.quota.peek(&self.runtime_config.rate_limits, call, keys, cost, now)
+	}
+
+	pub fn get_quota_stats(&self) -> QuotaStats {
+		self.quota.stats()
+	}
+
+	pub fn get_quota(&self) -> &QuotaLimiter {
+		&self.quota
+	}
+
+	pub fn set_quota(&mut self, quota: QuotaLimiter) {
+		self.quota = quota;
+	}
+
+	pub fn get_runtime_config(&self) -> RuntimeConfig {
+		self.runtime_config.clone()
+	}
+
+	pub fn set_runtime_config(&mut self, config: RuntimeConfig) {
+		self.runtime_config = config;
+	}
+
+	pub fn get_upgrade_arm(&self) -> Option<UpgradeArm> {
+		self.upgrade_arm.clone()
+	}
+
+	pub fn set_upgrade_arm(&mut self, arm: Option<UpgradeArm>) {
+		self.upgrade_arm = arm;
+	}
+
+	pub fn get_compression_threshold(&self) -> usize {
+		self.compression_threshold
+	}
+
+	pub fn set_compression_threshold(&mut self, threshold: usize) {
+		self.compression_threshold = threshold;
+	}
+
+	pub fn get_nft_availability_range(&self, first_nftid: u32, last_nftid: u32) -> Vec<u32> {
+		self.nft_block_map
+			.range(first_nftid..=last_nftid)
+			.map(|(nftid, _)| *nftid)
+			.collect()
+	}
+
+	pub fn get_provision_windows(&self) -> Vec<ProvisionWindow> {
+		self.provision_windows.clone()
+	}
+
+	pub fn set_provision_windows(&mut self, windows: Vec<ProvisionWindow>) {
+		self.provision_windows = windows;
+	}
+
+	pub fn get_maintenance_mode(&self) -> MaintenanceMode {
+		self.maintenance_mode
+	}
+
+	pub fn set_maintenance_mode(&mut self, mode: MaintenanceMode) {
+		self.maintenance_mode = mode;
+	}
+
+	pub fn get_read_only(&self) -> Option<ReadOnlySwitch> {
+		self.read_only.clone()
+	}
+
+	pub fn set_read_only(&mut self, switch: Option<ReadOnlySwitch>) {
+		self.read_only = switch;
+	}
+
+	pub fn get_maintenance_window(&self) -> Option<MaintenanceWindow> {
+		self.maintenance_window.clone()
+	}
+
+	pub fn set_maintenance_window(&mut self, window: Option<MaintenanceWindow>) {
+		self.maintenance_window = window;
+	}
+
+	pub fn get_task_registry(&self) -> TaskRegistry {
+		self.task_registry.clone()
+	}
+
+	pub fn get_coordinator(&self) -> OperationCoordinator {
+		self.coordinator.clone()
+	}
+
+	pub fn get_readiness(&self) -> Readiness {
+		self.readiness.clone()
+	}
 }
 
 fn keypair_to_public(keypair: sr25519::Pair) -> Option<sr25519::Public> {
@@ -210,11 +479,26 @@ pub async fn get_chain_api(state: &SharedState) -> DefaultApi {
 	shared_state_read.get_rpc_client()
 }
 
+pub async fn get_secondary_chain_api(state: &SharedState) -> Option<DefaultApi> {
+	let shared_state_read = state.read().await;
+	shared_state_read.get_secondary_rpc_client()
+}
+
 pub async fn get_keypair(state: &SharedState) -> sr25519::Pair {
 	let shared_state_read = state.read().await;
 	shared_state_read.get_key()
 }
 
+pub async fn get_subkeys(state: &SharedState) -> EnclaveSubkeys {
+	let shared_state_read = state.read().await;
+	shared_state_read.get_subkeys()
+}
+
+pub async fn get_subkey(state: &SharedState, purpose: KeyPurpose) -> sr25519::Pair {
+	let shared_state_read = state.read().await;
+	shared_state_read.get_subkey(purpose)
+}
+
 pub async fn get_accountid(state: &SharedState) -> String {
 	let shared_state_read = state.read().await;
 	shared_state_read.get_accountid()
@@ -245,6 +529,16 @@ pub async fn get_blocknumber(state: &SharedState) -> u32 {
 	shared_state_read.get_current_block()
 }
 
+pub async fn get_audit_head(state: &SharedState) -> AuditHead {
+	let shared_state_read = state.read().await;
+	shared_state_read.get_audit_head().clone()
+}
+
+pub async fn get_block_updated(state: &SharedState) -> Option<Instant> {
+	let shared_state_read = state.read().await;
+	shared_state_read.get_block_updated()
+}
+
 pub async fn get_processed_block(state: &SharedState) -> u32 {
 	let shared_state_read = state.read().await;
 	shared_state_read.get_processed_block()
@@ -260,11 +554,92 @@ pub async fn get_nft_availability(state: &SharedState, nftid: u32) -> Option<hel
 	shared_state_read.get_nft_availability(nftid).copied()
 }
 
+pub async fn get_nft_availability_map(state: &SharedState) -> BTreeMap<u32, helper::Availability> {
+	let shared_state_read = state.read().await;
+	shared_state_read.get_nft_availability_map()
+}
+
 pub async fn get_nft_availability_map_len(state: &SharedState) -> u32 {
 	let shared_state_read = state.read().await;
 	shared_state_read.get_nft_availability_map_len()
 }
 
+/// Current storage root and the proof of an nft_id in it
+pub async fn get_storage_proof(state: &SharedState, nftid: u32) -> (String, StorageProof) {
+	let shared_state_read = state.read().await;
+	let tree = shared_state_read.get_storage_tree();
+	(tree.root(), tree.proof(nftid))
+}
+
+pub async fn get_quorum(state: &SharedState) -> Option<QuorumConfig> {
+	let shared_state_read = state.read().await;
+	shared_state_read.get_quorum()
+}
+
+pub async fn get_pending_quorum(state: &SharedState) -> Option<QuorumConfig> {
+	let shared_state_read = state.read().await;
+	shared_state_read.get_pending_quorum()
+}
+
+pub async fn get_compression_threshold(state: &SharedState) -> usize {
+	let shared_state_read = state.read().await;
+	shared_state_read.get_compression_threshold()
+}
+
+pub async fn get_nft_availability_range(
+	state: &SharedState,
+	first_nftid: u32,
+	last_nftid: u32,
+) -> Vec<u32> {
+	let shared_state_read = state.read().await;
+	shared_state_read.get_nft_availability_range(first_nftid, last_nftid)
+}
+
+pub async fn get_provision_windows(state: &SharedState) -> Vec<ProvisionWindow> {
+	let shared_state_read = state.read().await;
+	shared_state_read.get_provision_windows()
+}
+
+pub async fn get_maintenance_mode(state: &SharedState) -> MaintenanceMode {
+	let shared_state_read = state.read().await;
+	shared_state_read.get_maintenance_mode()
+}
+
+pub async fn get_quota_stats(state: &SharedState) -> QuotaStats {
+	let shared_state_read = state.read().await;
+	shared_state_read.get_quota_stats()
+}
+
+pub async fn get_read_only(state: &SharedState) -> Option<ReadOnlySwitch> {
+	let shared_state_read = state.read().await;
+	shared_state_read.get_read_only()
+}
+
+pub async fn get_maintenance_window(state: &SharedState) -> Option<MaintenanceWindow> {
+	let shared_state_read = state.read().await;
+	shared_state_read.get_maintenance_window()
+}
+
+pub async fn get_task_registry(state: &SharedState) -> TaskRegistry {
+	let shared_state_read = state.read().await;
+	shared_state_read.get_task_registry()
+}
+
+pub async fn get_coordinator(state: &SharedState) -> OperationCoordinator {
+	let shared_state_read = state.read().await;
+	shared_state_read.get_coordinator()
+}
+
+pub async fn get_readiness(state: &SharedState) -> Readiness {
+	let shared_state_read = state.read().await;
+	shared_state_read.get_readiness()
+}
+
+pub async fn get_runtime_config(state: &SharedState) -> RuntimeConfig {
+	let shared_state_read = state.read().await;
+	shared_state_read.get_runtime_config()
+}
+
 /* ---------------
  WRITE HELPERS
 ----------------*/
@@ -309,20 +684,64 @@ pub async fn _set_chain_api(state: &SharedState, api: DefaultApi) {
 	shared_state_write._set_rpc_client(api);
 }
 
+pub async fn set_secondary_chain_api(state: &SharedState, api: Option<DefaultApi>) {
+	let shared_state_write = &mut state.write().await;
+	shared_state_write.set_secondary_rpc_client(api);
+}
+
 pub async fn set_nft_availability(state: &SharedState, nftid_block: (u32, helper::Availability)) {
+	// Sealed keyshare is hashed before locking the state
+	let keyshare_hash = keyshare_hash(nftid_block.0, &nftid_block.1);
 	let shared_state_write = &mut state.write().await;
-	shared_state_write.set_nft_availability(nftid_block);
+	shared_state_write.set_nft_availability(nftid_block, keyshare_hash);
 }
 
 pub async fn reset_nft_availability(
 	state: &SharedState,
 	availability_map: BTreeMap<u32, helper::Availability>,
 ) {
+	let storage_tree = StorageTree::from_availability(&availability_map);
 	let shared_state_write = &mut state.write().await;
 	shared_state_write.nft_block_map = availability_map;
+	shared_state_write.storage_tree = storage_tree;
 }
 
 pub async fn remove_nft_availability(state: &SharedState, nftid: u32) {
 	let shared_state_write = &mut state.write().await;
 	shared_state_write.remove_nft_availability(nftid);
 }
+
+pub async fn set_quorum(state: &SharedState, quorum: Option<QuorumConfig>) {
+	let shared_state_write = &mut state.write().await;
+	shared_state_write.set_quorum(quorum);
+}
+
+pub async fn set_pending_quorum(state: &SharedState, quorum: Option<QuorumConfig>) {
+	let shared_state_write = &mut state.write().await;
+	shared_state_write.set_pending_quorum(quorum);
+}
+
+pub async fn set_compression_threshold(state: &SharedState, threshold: usize) {
+	let shared_state_write = &mut state.write().await;
+	shared_state_write.set_compression_threshold(threshold);
+}
+
+pub async fn set_provision_windows(state: &SharedState, windows: Vec<ProvisionWindow>) {
+	let shared_state_write = &mut state.write().await;
+	shared_state_write.set_provision_windows(windows);
+}
+
+pub async fn set_maintenance_mode(state: &SharedState, mode: MaintenanceMode) {
+	let shared_state_write = &mut state.write().await;
+	shared_state_write.set_maintenance_mode(mode);
+}
+
+pub async fn set_read_only(state: &SharedState, switch: Option<ReadOnlySwitch>) {
+	let shared_state_write = &mut state.write().await;
+	shared_state_write.set_read_only(switch);
+}
+
+pub async fn set_maintenance_window(state: &SharedState, window: Option<MaintenanceWindow>) {
+	let shared_state_write = &mut state.write().await;
+	shared_state_write.set_maintenance_window(window);
+}
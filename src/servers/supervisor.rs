@@ -0,0 +1,370 @@
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use anyhow::anyhow;
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use futures::future::BoxFuture;
+use serde::Serialize;
+use serde_json::json;
+use tokio::{sync::RwLock, task::JoinHandle};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
+
+use crate::{
+	chain::constants::{RETRY_DELAY, TASK_SHUTDOWN_TIMEOUT},
+	servers::state::{get_task_registry, SharedState},
+};
+
+/* ---------------------------------------
+	BACKGROUND TASK SUPERVISOR
+--------------------------------------- */
+
+/// Status of supervised tasks, shared with the admin endpoint
+pub type TaskRegistry = Arc<RwLock<BTreeMap<String, TaskReport>>>;
+
+/// A task run : the tasks are services, an error or an exit before shutdown triggers restart policy
+pub type TaskFactory = Box<dyn Fn() -> BoxFuture<'static, Result<(), anyhow::Error>> + Send + Sync>;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum RestartPolicy {
+	NEVER,
+	// Maximum number of restarts
+	LIMITED(u32),
+	ALWAYS,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum TaskStatus {
+	PENDING,
+	RUNNING,
+	RESTARTING,
+	// Shutdown, or exited without restart policy
+	STOPPED,
+	// Exhausted its restart policy
+	FAILED,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct TaskReport {
+	pub name: String,
+	pub depends_on: Vec<String>,
+	pub policy: RestartPolicy,
+	pub status: TaskStatus,
+	pub restarts: u32,
+	pub last_error: Option<String>,
+}
+
+struct TaskSpec {
+	name: String,
+	depends_on: Vec<String>,
+	policy: RestartPolicy,
+	factory: Arc<TaskFactory>,
+}
+
+/// Starts background tasks in dependency order, restarts them when they return an error or exit,
+/// and shuts them down in reverse order. A panic is not a failure of the task : the release profile
+/// aborts the enclave on panic, tasks report their failures as errors.
+pub struct Supervisor {
+	registry: TaskRegistry,
+	specs: Vec<TaskSpec>,
+	// Started tasks, in start order
+	running: Vec<(String, CancellationToken, JoinHandle<()>)>,
+	restart_delay: Duration,
+}
+
+impl Supervisor {
+	pub fn new(registry: TaskRegistry) -> Supervisor {
+		Supervisor {
+			registry,
+			specs: Vec::new(),
+			running: Vec::new(),
+			restart_delay: Duration::from_secs(RETRY_DELAY.into()),
+		}
+	}
+
+	/// Register a background task
+	/// # Arguments
+	/// * `name` - unique task name
+	/// * `depends_on` - tasks which must be started before this one
+	/// * `policy` - restart policy on error or exit
+	/// * `factory` - creates a new run of the task
+	pub fn register(
+		&mut self,
+		name: &str,
+		depends_on: &[&str],
+		policy: RestartPolicy,
+		factory: TaskFactory,
+	) -> &mut Self {
+		self.specs.push(TaskSpec {
+			name: name.to_string(),
+			depends_on: depends_on.iter().map(|dep| dep.to_string()).collect(),
+			policy,
+			factory: Arc::new(factory),
+		});
+		self
+	}
+
+	/// Dependency order of registered tasks
+	pub fn start_order(&self) -> Result<Vec<String>, anyhow::Error> {
+		let mut order: Vec<String> = Vec::new();
+
+		while order.len() < self.specs.len() {
+			let ready = self.specs.iter().find(|spec| {
+				!order.contains(&spec.name) && spec.depends_on.iter().all(|dep| order.contains(dep))
+			});
+
+			match ready {
+				Some(spec) => order.push(spec.name.clone()),
+				None => {
+					let blocked: Vec<&str> = self
+						.specs
+						.iter()
+						.filter(|spec| !order.contains(&spec.name))
+						.map(|spec| spec.name.as_str())
+						.collect();
+					return Err(anyhow!(
+						"SUPERVISOR : unknown or cyclic dependencies of tasks {:?}",
+						blocked
+					))
+				},
+			}
+		}
+
+		Ok(order)
+	}
+
+	/// Start all registered tasks in dependency order
+	pub async fn start(&mut self) -> Result<(), anyhow::Error> {
+		let order = self.start_order()?;
+
+		for spec in &self.specs {
+			self.registry.write().await.insert(
+				spec.name.clone(),
+				TaskReport {
+					name: spec.name.clone(),
+					depends_on: spec.depends_on.clone(),
+					policy: spec.policy,
+					status: TaskStatus::PENDING,
+					restarts: 0,
+					last_error: None,
+				},
+			);
+		}
+
+		for name in order {
+			let spec = match self.specs.iter().find(|spec| spec.name == name) {
+				Some(spec) => spec,
+				None => continue,
+			};
+
+			info!("SUPERVISOR : starting task {}", name);
+			let token = CancellationToken::new();
+			let handle = tokio::spawn(supervise(
+				self.registry.clone(),
+				name.clone(),
+				spec.policy,
+				spec.factory.clone(),
+				self.restart_delay,
+				token.clone(),
+			));
+
+			self.running.push((name, token, handle));
+		}
+
+		Ok(())
+	}
+
+	/// Stop all started tasks in reverse start order
+	pub async fn shutdown(mut self) {
+		while let Some((name, token, mut handle)) = self.running.pop() {
+			info!("SUPERVISOR : stopping task {}", name);
+			token.cancel();
+
+			let timeout = Duration::from_secs(TASK_SHUTDOWN_TIMEOUT);
+			if tokio::time::timeout(timeout, &mut handle).await.is_err() {
+				warn!("SUPERVISOR : task {} did not stop in {:?}, aborting", name, timeout);
+				handle.abort();
+			}
+
+			update_report(&self.registry, &name, |report| {
+				if report.status != TaskStatus::FAILED {
+					report.status = TaskStatus::STOPPED
+				}
+			})
+			.await;
+		}
+	}
+}
+
+async fn update_report(registry: &TaskRegistry, name: &str, update: impl FnOnce(&mut TaskReport)) {
+	if let Some(report) = registry.write().await.get_mut(name) {
+		update(report);
+	}
+}
+
+/// Run a task until it exits, is cancelled or exhausts its restart policy
+async fn supervise(
+	registry: TaskRegistry,
+	name: String,
+	policy: RestartPolicy,
+	factory: Arc<TaskFactory>,
+	restart_delay: Duration,
+	token: CancellationToken,
+) {
+	let mut restarts: u32 = 0;
+
+	loop {
+		update_report(&registry, &name, |report| report.status = TaskStatus::RUNNING).await;
+
+		// Separate tokio task, aborted on cancellation, tasks only await between their steps
+		let mut run = tokio::spawn(factory());
+
+		let outcome = tokio::select! {
+			joined = &mut run => joined,
+			_ = token.cancelled() => {
+				run.abort();
+				debug!("SUPERVISOR : task {} is cancelled", name);
+				return
+			},
+		};
+
+		let failure = match outcome {
+			// A service which exits, i.e. a closed subscription, is restarted
+			Ok(Ok(())) if policy == RestartPolicy::NEVER => {
+				info!("SUPERVISOR : task {} exited", name);
+				update_report(&registry, &name, |report| report.status = TaskStatus::STOPPED).await;
+				return
+			},
+			Ok(Ok(())) => "exited before shutdown".to_string(),
+			Ok(Err(err)) => format!("error : {err:?}"),
+			Err(err) => format!("aborted : {err:?}"),
+		};
+
+		let message = format!("SUPERVISOR : task {} failed, {}", name, failure);
+		error!(message);
+		sentry::capture_message(&message, sentry::Level::Error);
+
+		let can_restart = match policy {
+			RestartPolicy::NEVER => false,
+			RestartPolicy::LIMITED(max_restarts) => restarts < max_restarts,
+			RestartPolicy::ALWAYS => true,
+		};
+
+		if !can_restart {
+			update_report(&registry, &name, |report| {
+				report.status = TaskStatus::FAILED;
+				report.last_error = Some(failure);
+			})
+			.await;
+			return
+		}
+
+		restarts += 1;
+		update_report(&registry, &name, |report| {
+			report.status = TaskStatus::RESTARTING;
+			report.restarts = restarts;
+			report.last_error = Some(failure);
+		})
+		.await;
+
+		tokio::select! {
+			_ = tokio::time::sleep(restart_delay) => {},
+			_ = token.cancelled() => return,
+		}
+	}
+}
+
+/* ---------------------------------------
+	ADMIN ENDPOINT
+--------------------------------------- */
+
+/// Status of supervised background tasks
+pub async fn admin_task_status(State(state): State<SharedState>) -> impl IntoResponse {
+	let registry = get_task_registry(&state).await;
+	let tasks: Vec<TaskReport> = registry.read().await.values().cloned().collect();
+
+	(StatusCode::OK, Json(json!({ "tasks": tasks })))
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use std::sync::atomic::{AtomicU32, Ordering};
+
+	fn idle_task() -> TaskFactory {
+		Box::new(|| {
+			Box::pin(async { futures::future::pending::<Result<(), anyhow::Error>>().await })
+		})
+	}
+
+	#[test]
+	fn start_order_test() {
+		let mut supervisor = Supervisor::new(TaskRegistry::default());
+		supervisor
+			.register("sync", &["subscription"], RestartPolicy::NEVER, idle_task())
+			.register("subscription", &[], RestartPolicy::NEVER, idle_task())
+			.register("janitor", &["sync", "subscription"], RestartPolicy::NEVER, idle_task());
+
+		assert_eq!(supervisor.start_order().unwrap(), vec!["subscription", "sync", "janitor"]);
+
+		supervisor.register("orphan", &["unknown"], RestartPolicy::NEVER, idle_task());
+		assert!(supervisor.start_order().is_err());
+	}
+
+	#[tokio::test]
+	async fn supervisor_restart_test() {
+		let registry = TaskRegistry::default();
+		let runs = Arc::new(AtomicU32::new(0));
+		let counter = runs.clone();
+
+		let mut supervisor = Supervisor::new(registry.clone());
+		supervisor.restart_delay = Duration::from_millis(10);
+		supervisor.register(
+			"flaky",
+			&[],
+			RestartPolicy::ALWAYS,
+			Box::new(move || {
+				let counter = counter.clone();
+				Box::pin(async move {
+					match counter.fetch_add(1, Ordering::SeqCst) {
+						0 => Err(anyhow!("first run fails")),
+						// i.e. a closed subscription
+						1 => Ok(()),
+						_ => futures::future::pending::<Result<(), anyhow::Error>>().await,
+					}
+				})
+			}),
+		);
+		supervisor.register(
+			"broken",
+			&["flaky"],
+			RestartPolicy::NEVER,
+			Box::new(|| Box::pin(async { Err(anyhow!("broken")) })),
+		);
+		supervisor.register(
+			"oneshot",
+			&[],
+			RestartPolicy::NEVER,
+			Box::new(|| Box::pin(async { Ok(()) })),
+		);
+
+		supervisor.start().await.unwrap();
+		tokio::time::sleep(Duration::from_millis(200)).await;
+
+		let flaky = registry.read().await.get("flaky").cloned().unwrap();
+		assert_eq!(flaky.status, TaskStatus::RUNNING);
+		assert_eq!(flaky.restarts, 2);
+		assert!(flaky.last_error.unwrap().starts_with("exited"));
+		assert_eq!(runs.load(Ordering::SeqCst), 3);
+
+		let broken = registry.read().await.get("broken").cloned().unwrap();
+		assert_eq!(broken.status, TaskStatus::FAILED);
+		assert!(broken.last_error.unwrap().starts_with("error"));
+
+		let oneshot = registry.read().await.get("oneshot").cloned().unwrap();
+		assert_eq!(oneshot.status, TaskStatus::STOPPED);
+
+		supervisor.shutdown().await;
+		let flaky = registry.read().await.get("flaky").cloned().unwrap();
+		assert_eq!(flaky.status, TaskStatus::STOPPED);
+	}
+}
@@ -0,0 +1,151 @@
+use axum::{
+	body::Body,
+	extract::{MatchedPath, OriginalUri, State},
+	http::{HeaderValue, Request},
+	middleware::Next,
+	response::{IntoResponse, Response},
+	Json,
+};
+use serde::Serialize;
+use serde_json::json;
+
+/* ---------------------------------------
+	API VERSIONING
+--------------------------------------- */
+
+// The api is served under /api/v1 and /api/v2, by the same routes with the same state and
+// middlewares. The unversioned /api paths are kept as aliases of v1 for the deployed clients.
+// Breaking changes, i.e. JWS-only payloads or new response schemas, only land in v2, and v1
+// responses carry the deprecation headers pointing to their v2 successor.
+
+pub const DEPRECATION_HEADER: &str = "deprecation";
+pub const LINK_HEADER: &str = "link";
+
+/// Version of the api routes
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiVersion {
+	V1,
+	V2,
+}
+
+pub const CURRENT_API_VERSION: ApiVersion = ApiVersion::V2;
+const API_VERSIONS: [ApiVersion; 2] = [ApiVersion::V1, ApiVersion::V2];
+
+impl ApiVersion {
+	pub fn prefix(&self) -> &'static str {
+		match self {
+			ApiVersion::V1 => "/api/v1",
+			ApiVersion::V2 => "/api/v2",
+		}
+	}
+
+	pub fn is_deprecated(&self) -> bool {
+		*self != CURRENT_API_VERSION
+	}
+}
+
+/// Path without its version prefix, "/api/v2/health" and "/api/health" are "/api/health"
+pub fn unversioned_path(path: &str) -> String {
+	for version in API_VERSIONS {
+		if let Some(rest) = path.strip_prefix(version.prefix()) {
+			if rest.is_empty() || rest.starts_with('/') {
+				return format!("/api{rest}")
+			}
+		}
+	}
+
+	path.to_string()
+}
+
+/// Endpoint of a request for the middlewares, the same in every version.
+/// Nested routes see their path without the prefix, the route pattern is used instead.
+pub fn endpoint_path<B>(request: &Request<B>) -> String {
+	match request.extensions().get::<MatchedPath>() {
+		Some(matched) => unversioned_path(matched.as_str()),
+		None => unversioned_path(request.uri().path()),
+	}
+}
+
+/// Path of the same request in the current version
+fn successor_path<B>(request: &Request<B>) -> String {
+	let path = match request.extensions().get::<OriginalUri>() {
+		Some(OriginalUri(uri)) => unversioned_path(uri.path()),
+		None => endpoint_path(request),
+	};
+
+	format!("{}{}", CURRENT_API_VERSION.prefix(), path.trim_start_matches("/api"))
+}
+
+/// Middleware of the routes of a version : the version is available to the handlers as a request
+/// extension, and deprecated versions add the Deprecation and Link headers to the response
+pub async fn version_guard(
+	State(version): State<ApiVersion>,
+	mut request: Request<Body>,
+	next: Next<Body>,
+) -> Response {
+	let successor = successor_path(&request);
+	request.extensions_mut().insert(version);
+
+	let mut response = next.run(request).await;
+
+	if version.is_deprecated() {
+		let headers = response.headers_mut();
+		headers.insert(DEPRECATION_HEADER, HeaderValue::from_static("true"));
+		if let Ok(link) =
+			HeaderValue::from_str(&format!("<{successor}>; rel=\"successor-version\""))
+		{
+			headers.insert(LINK_HEADER, link);
+		}
+	}
+
+	response
+}
+
+/// Version discovery endpoint
+pub async fn api_versions() -> impl IntoResponse {
+	let versions: Vec<_> = API_VERSIONS
+		.iter()
+		.map(|version| {
+			json!({
+				"version": version,
+				"prefix": version.prefix(),
+				"deprecated": version.is_deprecated(),
+			})
+		})
+		.collect();
+
+	Json(json!({
+		"current": CURRENT_API_VERSION,
+		// Unversioned /api paths
+		"default": ApiVersion::V1,
+		"versions": versions,
+	}))
+}
+
+/* **********************
+		 TEST
+********************** */
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn unversioned_path_test() {
+		assert_eq!(unversioned_path("/api/v1/health"), "/api/health");
+		assert_eq!(
+			unversioned_path("/api/v2/secret-nft/store-keyshare"),
+			"/api/secret-nft/store-keyshare"
+		);
+		assert_eq!(unversioned_path("/api/health"), "/api/health");
+		assert_eq!(unversioned_path("/api/v2"), "/api");
+		// Only whole segments are versions
+		assert_eq!(unversioned_path("/api/v10/health"), "/api/v10/health");
+
+		let request = Request::builder().uri("/api/v1/quote").body(Body::empty()).unwrap();
+		assert_eq!(successor_path(&request), "/api/v2/quote");
+		assert!(ApiVersion::V1.is_deprecated());
+		assert!(!ApiVersion::V2.is_deprecated());
+	}
+}
@@ -2,7 +2,8 @@
 #![allow(unused_imports)]
 #![allow(unused_variables)]
 
-use clap::Parser;
+use base64::Engine as _;
+use clap::{Parser, Subcommand};
 use hex::{FromHex, FromHexError};
 use serde_json::{json, Value};
 use subxt::{
@@ -22,6 +23,8 @@ use tracing::{debug, error, info, warn};
 
 use serde::{Deserialize, Serialize};
 
+mod scenario;
+
 #[cfg_attr(
 	feature = "mainnet",
 	subxt::subxt(runtime_metadata_path = "../artifacts/ternoa_mainnet.scale")
@@ -106,12 +109,11 @@ pub struct FetchAuthenticationToken {
 	pub block_validation: u32,
 }
 
-/// Fetch Bulk Data
+/// Fetch Bulk Data, signed by the admin quorum
 #[derive(Serialize, Deserialize)]
 pub struct FetchBulkPacket {
-	admin_account: String,
-	auth_token: String, //FetchAuthenticationToken,
-	signature: String,
+	auth_token: String, //StoreAuthenticationToken,
+	signatures: BTreeMap<String, String>,
 }
 
 /// Fetch Bulk Response
@@ -196,6 +198,9 @@ pub struct ReconPacket {
 #[derive(Parser, Debug, Clone)]
 #[command(author, version, about, long_about = None)]
 struct Args {
+	#[command(subcommand)]
+	command: Option<Command>,
+
 	/// Request type : [retrieve, store] for secrets
 	/// Request type : [fetch-bulk, push-bulk, fetch-id, push-id] for backup
 	/// Request type : [reconcilliation] for metrics
@@ -230,6 +235,10 @@ struct Args {
 	#[arg(long, default_value_t = String::new())]
 	secret_share: String,
 
+	/// Path to a binary keyshare file, it is sent base64url encoded (request version 3)
+	#[arg(long, default_value_t = String::new())]
+	binary_share: String,
+
 	/// BlockNumber (Optional)
 	#[arg(short, long, default_value_t = 0)]
 	block_number: u32,
@@ -243,6 +252,21 @@ struct Args {
 	custom_data: String,
 }
 
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+	/// Replayable integration scenarios against an enclave and chain
+	Scenario {
+		#[command(subcommand)]
+		action: ScenarioAction,
+	},
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum ScenarioAction {
+	/// Execute a yaml scenario file, exits with code 1 if a step fails
+	Run { file: String },
+}
+
 /* *************************************
 				MAIN
 **************************************** */
@@ -250,6 +274,13 @@ struct Args {
 async fn main() {
 	let args = Args::parse();
 
+	if let Some(Command::Scenario { action: ScenarioAction::Run { file } }) = &args.command {
+		if !scenario::run(file).await {
+			std::process::exit(1);
+		}
+		return;
+	}
+
 	if args.seed.is_empty() {
 		println!("\n Seed-phrase can not be empty! \n");
 		return;
@@ -306,16 +337,19 @@ async fn generate_fetch_bulk(seed_phrase: String) {
 	let current_block_number = get_current_block_number().await.unwrap();
 
 	let admin_account = admin.public().to_ss58check();
-	let auth =
-		FetchAuthenticationToken { block_number: current_block_number, block_validation: 10 };
+	// Full ZIP backup : empty since_block, ZIP format, no disaster recovery
+	let auth = StoreAuthenticationToken {
+		block_number: current_block_number,
+		block_validation: 10,
+		data_hash: sha256::digest("fetch-bulk__ZIP_false".as_bytes()),
+	};
 	let auth_str = serde_json::to_string(&auth).unwrap();
 	let signature = admin.sign(auth_str.as_bytes());
 
-	let packet = FetchBulkPacket {
-		admin_account,
-		auth_token: auth_str,
-		signature: format!("{}{:?}", "0x", signature),
-	};
+	let mut signatures = BTreeMap::new();
+	signatures.insert(admin_account, format!("{}{:?}", "0x", signature));
+
+	let packet = FetchBulkPacket { auth_token: auth_str, signatures };
 
 	println!(
 		"================================== Backup Fetch Bulk Packet = \n{}\n",
@@ -475,6 +509,9 @@ pub struct StoreKeysharePacket {
 	// Signed by signer
 	pub data: String,
 	pub signature: String,
+
+	// 1 : legacy, 3 : legacy with base64url keyshare
+	pub version: u8,
 }
 
 async fn generate_store_request(args: Args) {
@@ -491,10 +528,13 @@ async fn generate_store_request(args: Args) {
 		format!("{}_{}_{}", signer.public().to_ss58check(), current_block_number, args.expire);
 	let signersig = owner.sign(signer_address.as_bytes());
 
-	let secret_share = if !args.secret_share.is_empty() {
-		args.secret_share
+	let (secret_share, version) = if !args.binary_share.is_empty() {
+		let binary_share = std::fs::read(&args.binary_share).unwrap();
+		(base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(binary_share), 3)
+	} else if !args.secret_share.is_empty() {
+		(args.secret_share, 1)
 	} else {
-		"This-is-a-Sample-Secret!@#$%^&*()1234567890".to_string()
+		("This-is-a-Sample-Secret!@#$%^&*()1234567890".to_string(), 1)
 	};
 
 	let data = if !args.custom_data.is_empty() {
@@ -511,6 +551,7 @@ async fn generate_store_request(args: Args) {
 		signersig: format!("{}{:?}", "0x", signersig),
 		data,
 		signature: format!("{}{:?}", "0x", signature),
+		version,
 	};
 
 	println!(
@@ -519,7 +560,7 @@ async fn generate_store_request(args: Args) {
 	);
 }
 
-#[derive(Serialize, Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 pub enum RequesterType {
 	OWNER,
 	DELEGATEE,
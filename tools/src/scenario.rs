@@ -0,0 +1,318 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use subxt::ext::sp_core::{crypto::Ss58Codec, sr25519, Pair};
+
+use crate::{get_current_block_number, RequesterType, RetrieveKeysharePacket, StoreKeysharePacket};
+
+/* *************************************
+		SCENARIO FILE
+**************************************** */
+
+/// Scripted sequence of requests against an enclave, with the expected statuses
+#[derive(Deserialize, Debug)]
+pub struct Scenario {
+	pub name: String,
+	// Base url of the target enclave, i.e. "https://dev-c1n1.ternoa.network:8100"
+	pub enclave: String,
+	// Account name -> seed phrase or dev uri, i.e. "//Alice"
+	pub accounts: BTreeMap<String, String>,
+	// Validity period of the requests in blocks
+	#[serde(default = "default_expire")]
+	pub expire: u32,
+	// Development enclaves use self-signed certificates
+	#[serde(default)]
+	pub accept_invalid_certs: bool,
+	pub steps: Vec<Step>,
+}
+
+fn default_expire() -> u32 {
+	15
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+	Store {
+		nft_id: u32,
+		account: String,
+		#[serde(default)]
+		secret: Option<String>,
+		#[serde(default)]
+		capsule: bool,
+	},
+	Retrieve {
+		nft_id: u32,
+		account: String,
+		#[serde(default = "default_requester_type")]
+		requester_type: RequesterType,
+		#[serde(default)]
+		capsule: bool,
+	},
+	Remove {
+		nft_id: u32,
+		account: String,
+		#[serde(default)]
+		capsule: bool,
+	},
+	WaitBlocks(u32),
+}
+
+fn default_requester_type() -> RequesterType {
+	RequesterType::OWNER
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Step {
+	#[serde(flatten)]
+	pub action: Action,
+	// Expected "status" of the response, i.e. STORESUCCESS
+	#[serde(default)]
+	pub expect: Option<String>,
+	// Expected http status code
+	#[serde(default)]
+	pub expect_http: Option<u16>,
+	// Retrieved keyshare must contain it
+	#[serde(default)]
+	pub expect_keyshare: Option<String>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct RemoveKeysharePacket {
+	pub requester_address: sr25519::Public,
+	pub data: String,
+	pub signature: String,
+}
+
+/* *************************************
+		SCENARIO RUNNER
+**************************************** */
+
+struct Runner {
+	scenario: Scenario,
+	client: reqwest::Client,
+	keys: BTreeMap<String, sr25519::Pair>,
+}
+
+impl Runner {
+	fn key(&self, account: &str) -> Result<&sr25519::Pair, String> {
+		self.keys.get(account).ok_or(format!("unknown account '{account}'"))
+	}
+
+	fn url(&self, capsule: bool, endpoint: &str) -> String {
+		let api = if capsule { "capsule-nft" } else { "secret-nft" };
+		format!("{}/api/{}/{}", self.scenario.enclave.trim_end_matches('/'), api, endpoint)
+	}
+
+	async fn post<T: Serialize>(&self, url: String, packet: &T) -> Result<(u16, Value), String> {
+		let body = serde_json::to_string(packet).map_err(|err| err.to_string())?;
+
+		let response = self
+			.client
+			.post(&url)
+			.header(reqwest::header::CONTENT_TYPE, "application/json")
+			.body(body)
+			.send()
+			.await
+			.map_err(|err| format!("{url} : {err}"))?;
+
+		let code = response.status().as_u16();
+		let text = response.text().await.map_err(|err| err.to_string())?;
+		let value = serde_json::from_str(&text).unwrap_or(Value::String(text));
+
+		Ok((code, value))
+	}
+
+	async fn store(
+		&self,
+		nft_id: u32,
+		account: &str,
+		secret: &Option<String>,
+		capsule: bool,
+	) -> Result<(u16, Value), String> {
+		let owner = self.key(account)?;
+		let signer = sr25519::Pair::generate().0;
+		let block_number = get_current_block_number().await.map_err(|err| err.to_string())?;
+		let expire = self.scenario.expire;
+
+		let signer_address =
+			format!("{}_{}_{}", signer.public().to_ss58check(), block_number, expire);
+		let signersig = owner.sign(signer_address.as_bytes());
+
+		// Unique default secret, so that a retrieve step can tell runs apart
+		let secret = secret.clone().unwrap_or(format!("Scenario-Secret-{nft_id}-{block_number}"));
+		let data = format!("{}_{}_{}_{}", nft_id, secret, block_number, expire);
+		let signature = signer.sign(data.as_bytes());
+
+		let packet = StoreKeysharePacket {
+			owner_address: owner.public(),
+			signer_address,
+			signersig: format!("{}{:?}", "0x", signersig),
+			data,
+			signature: format!("{}{:?}", "0x", signature),
+			version: 1,
+		};
+
+		let endpoint = if capsule { "set-keyshare" } else { "store-keyshare" };
+		self.post(self.url(capsule, endpoint), &packet).await
+	}
+
+	async fn retrieve(
+		&self,
+		nft_id: u32,
+		account: &str,
+		requester_type: RequesterType,
+		capsule: bool,
+	) -> Result<(u16, Value), String> {
+		let requester = self.key(account)?;
+		let block_number = get_current_block_number().await.map_err(|err| err.to_string())?;
+
+		let data = format!("{}_{}_{}", nft_id, block_number, self.scenario.expire);
+		let signature = requester.sign(data.as_bytes());
+
+		let packet = RetrieveKeysharePacket {
+			requester_address: requester.public(),
+			requester_type,
+			data,
+			signature: format!("{}{:?}", "0x", signature),
+		};
+
+		self.post(self.url(capsule, "retrieve-keyshare"), &packet).await
+	}
+
+	async fn remove(
+		&self,
+		nft_id: u32,
+		account: &str,
+		capsule: bool,
+	) -> Result<(u16, Value), String> {
+		let requester = self.key(account)?;
+		let block_number = get_current_block_number().await.map_err(|err| err.to_string())?;
+
+		let data = format!("{}_{}_{}", nft_id, block_number, self.scenario.expire);
+		let signature = requester.sign(data.as_bytes());
+
+		let packet = RemoveKeysharePacket {
+			requester_address: requester.public(),
+			data,
+			signature: format!("{}{:?}", "0x", signature),
+		};
+
+		self.post(self.url(capsule, "remove-keyshare"), &packet).await
+	}
+
+	async fn wait_blocks(&self, blocks: u32) -> Result<(), String> {
+		let start = get_current_block_number().await.map_err(|err| err.to_string())?;
+
+		loop {
+			tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+			let current = get_current_block_number().await.map_err(|err| err.to_string())?;
+			if current >= start + blocks {
+				return Ok(());
+			}
+		}
+	}
+
+	/// Execute a step and check its expectations
+	async fn run_step(&self, step: &Step) -> Result<String, String> {
+		let (code, response) = match &step.action {
+			Action::WaitBlocks(blocks) => {
+				self.wait_blocks(*blocks).await?;
+				return Ok(format!("waited {blocks} blocks"));
+			},
+			Action::Store { nft_id, account, secret, capsule } =>
+				self.store(*nft_id, account, secret, *capsule).await?,
+			Action::Retrieve { nft_id, account, requester_type, capsule } =>
+				self.retrieve(*nft_id, account, *requester_type, *capsule).await?,
+			Action::Remove { nft_id, account, capsule } =>
+				self.remove(*nft_id, account, *capsule).await?,
+		};
+
+		let status = response["status"].as_str().unwrap_or_default().to_string();
+
+		if let Some(expected) = &step.expect {
+			if !status.eq_ignore_ascii_case(expected) {
+				return Err(format!(
+					"expected status {expected}, got {status} ({code}) : {response}"
+				));
+			}
+		}
+
+		if let Some(expected) = step.expect_http {
+			if code != expected {
+				return Err(format!("expected http {expected}, got {code} : {response}"));
+			}
+		}
+
+		if let Some(expected) = &step.expect_keyshare {
+			let keyshare = response["keyshare_data"].as_str().unwrap_or_default();
+			if !keyshare.contains(expected.as_str()) {
+				return Err(format!("retrieved keyshare does not contain '{expected}'"));
+			}
+		}
+
+		Ok(format!("{status} ({code})"))
+	}
+}
+
+/// Run a scenario file, stops at the first failed step
+/// # Arguments
+/// * `path` - yaml scenario file
+/// # Returns
+/// * `bool` - true if every step has passed
+pub async fn run(path: &str) -> bool {
+	let scenario: Scenario = match std::fs::read_to_string(path)
+		.map_err(|err| err.to_string())
+		.and_then(|text| serde_yaml::from_str(&text).map_err(|err| err.to_string()))
+	{
+		Ok(scenario) => scenario,
+		Err(err) => {
+			println!("\n Invalid scenario file {path} : {err} \n");
+			return false;
+		},
+	};
+
+	let mut keys = BTreeMap::new();
+	for (name, seed) in &scenario.accounts {
+		match sr25519::Pair::from_string(seed, None) {
+			Ok(pair) => {
+				keys.insert(name.clone(), pair);
+			},
+			Err(err) => {
+				println!("\n Invalid seed of account '{name}' : {err:?} \n");
+				return false;
+			},
+		}
+	}
+
+	let client = match reqwest::Client::builder()
+		.danger_accept_invalid_certs(scenario.accept_invalid_certs)
+		.build()
+	{
+		Ok(client) => client,
+		Err(err) => {
+			println!("\n Unable to build http client : {err} \n");
+			return false;
+		},
+	};
+
+	println!("\n================================== Scenario : {} \n", scenario.name);
+
+	let runner = Runner { scenario, client, keys };
+	let total = runner.scenario.steps.len();
+
+	for (index, step) in runner.scenario.steps.iter().enumerate() {
+		match runner.run_step(step).await {
+			Ok(result) =>
+				println!(" [{}/{}] PASS {:?} : {}", index + 1, total, step.action, result),
+			Err(err) => {
+				println!(" [{}/{}] FAIL {:?} : {}", index + 1, total, step.action, err);
+				println!("\n================================== Scenario FAILED \n");
+				return false;
+			},
+		}
+	}
+
+	println!("\n================================== Scenario PASSED : {total} steps \n");
+	true
+}